@@ -0,0 +1,732 @@
+//! Users, groups and sudo privileges.
+//!
+//! Accounts come from /etc/passwd and /etc/group, password aging from /etc/shadow, and
+//! sudo rules from /etc/sudoers and the files it includes, parsed with the sudoers
+//! grammar so aliases, negation and tags resolve the way sudo resolves them. Together
+//! they give each user's effective privileges. Password hashes are never kept, only
+//! whether a password is set or locked.
+//!
+//! Account changes are typed: each is planned as commands for the executor with the
+//! command that undoes it. Sudo rules are written as files in /etc/sudoers.d that
+//! `visudo -c` checks before and after they are installed.
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{Duration, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::remote::transport::{shell_quote, Transport};
+
+pub mod sudoers;
+
+pub use sudoers::{Identity, Policy, Privilege};
+
+pub const PASSWD: &str = "/etc/passwd";
+pub const GROUP: &str = "/etc/group";
+pub const SHADOW: &str = "/etc/shadow";
+pub const SUDOERS: &str = "/etc/sudoers";
+pub const SUDOERS_D: &str = "/etc/sudoers.d";
+
+/// Rules tuxpilot writes go in sudoers.d files named after the user or group
+const MANAGED_PREFIX: &str = "tuxpilot-";
+/// sudo skips files in an include directory whose names contain a dot, so a revoked
+/// rule keeps its content but stops applying
+const REVOKED_SUFFIX: &str = ".revoked";
+/// sudo's own limit on nested includes
+const MAX_INCLUDE_DEPTH: usize = 128;
+/// First UID handed out to people rather than services on most distributions
+const FIRST_HUMAN_UID: u32 = 1000;
+/// UID of "nobody", which sits above the human range but is not a person
+const NOBODY_UID: u32 = 65534;
+/// Shadow's "never expires" for password ages
+const NO_MAXIMUM: u32 = 99999;
+
+/// Groups whose members get root or root-like access without sudo
+const PRIVILEGED_GROUPS: [(&str, &str); 8] = [
+    ("docker", "root through the Docker daemon"),
+    ("lxd", "root through LXD containers"),
+    ("incus-admin", "root through Incus containers"),
+    ("libvirt", "root through virtual machines"),
+    ("disk", "raw access to block devices"),
+    ("shadow", "read access to password hashes"),
+    ("adm", "read access to system logs"),
+    ("systemd-journal", "read access to the whole journal"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PasswordState {
+    /// A password can be used to log in
+    Usable,
+    /// A password is set but locked with "!"
+    Locked,
+    /// No password was ever set ("*", "!" or "!!")
+    Disabled,
+    /// Empty field: logging in needs no password at all
+    Empty,
+}
+
+impl fmt::Display for PasswordState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PasswordState::Usable => write!(f, "password set"),
+            PasswordState::Locked => write!(f, "password locked"),
+            PasswordState::Disabled => write!(f, "no password"),
+            PasswordState::Empty => write!(f, "empty password"),
+        }
+    }
+}
+
+/// The aging fields of a shadow entry
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Aging {
+    pub last_change: Option<NaiveDate>,
+    /// The password has to be changed at the next login
+    pub must_change: bool,
+    pub min_days: Option<u32>,
+    pub max_days: Option<u32>,
+    pub warn_days: Option<u32>,
+    pub inactive_days: Option<u32>,
+    /// The whole account stops working on this day
+    pub expires: Option<NaiveDate>,
+}
+
+impl Aging {
+    pub fn password_expires(&self) -> Option<NaiveDate> {
+        let max = self.max_days.filter(|max| *max < NO_MAXIMUM)?;
+        Some(self.last_change? + Duration::days(max.into()))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Account {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub comment: String,
+    pub home: String,
+    pub shell: String,
+    /// Primary group first
+    pub groups: Vec<String>,
+    /// None when /etc/shadow could not be read
+    pub password: Option<PasswordState>,
+    pub aging: Option<Aging>,
+    /// Can run anything as root, by UID 0 or a sudo rule
+    pub root: bool,
+    pub sudo: Vec<Privilege>,
+    /// sudo Defaults settings that apply to this user
+    pub sudo_defaults: Vec<String>,
+    /// Groups that give root-like access without sudo, with what they allow
+    pub privileged_groups: Vec<String>,
+}
+
+impl Account {
+    pub fn can_log_in(&self) -> bool {
+        !["nologin", "false"].contains(&self.shell.rsplit('/').next().unwrap_or_default()) && !self.shell.is_empty()
+    }
+
+    /// People rather than services, including root
+    pub fn is_human(&self) -> bool {
+        self.uid == 0 || (self.uid >= FIRST_HUMAN_UID && self.uid != NOBODY_UID && self.can_log_in())
+    }
+
+    /// Locked with `tuxpilot users lock`, `usermod -L` or an expiry in the past
+    pub fn is_locked(&self, today: NaiveDate) -> bool {
+        self.password == Some(PasswordState::Locked)
+            || self.aging.as_ref().and_then(|aging| aging.expires).is_some_and(|expires| expires <= today)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Group {
+    pub name: String,
+    pub gid: u32,
+    /// Supplementary members; users whose primary group this is are not listed
+    pub members: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountsReport {
+    /// The name sudo rules are matched against
+    pub hostname: String,
+    pub accounts: Vec<Account>,
+    pub groups: Vec<Group>,
+    /// sudoers files in the order sudo reads them
+    pub sudoers_files: Vec<String>,
+    /// sudoers lines that could not be parsed, as "file:line: message"
+    pub sudoers_errors: Vec<String>,
+    pub unreadable: Vec<String>,
+    #[serde(skip)]
+    policy: Policy,
+}
+
+impl AccountsReport {
+    pub fn account(&self, name: &str) -> Option<&Account> {
+        self.accounts.iter().find(|account| account.name == name)
+    }
+
+    pub fn group(&self, name: &str) -> Option<&Group> {
+        self.groups.iter().find(|group| group.name == name)
+    }
+
+    /// Who `account` would be with `groups` instead of its own
+    fn identity(&self, account: &Account, groups: &[String]) -> Identity {
+        Identity {
+            name: account.name.clone(),
+            uid: account.uid,
+            groups: groups.iter()
+                .filter_map(|name| Some((name.clone(), self.group(name)?.gid)))
+                .collect(),
+        }
+    }
+}
+
+/// Parse /etc/passwd. Groups, password state and privileges are filled in by the report.
+pub fn parse_passwd(text: &str) -> Vec<Account> {
+    text.lines()
+        .filter(|line| !line.starts_with(['#', '+', '-']))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            let [name, _, uid, gid, comment, home, shell] = fields.as_slice() else {
+                return None;
+            };
+            Some(Account {
+                name: name.to_string(),
+                uid: uid.parse().ok()?,
+                gid: gid.parse().ok()?,
+                comment: comment.to_string(),
+                home: home.to_string(),
+                shell: shell.to_string(),
+                groups: Vec::new(),
+                password: None,
+                aging: None,
+                root: false,
+                sudo: Vec::new(),
+                sudo_defaults: Vec::new(),
+                privileged_groups: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+pub fn parse_group(text: &str) -> Vec<Group> {
+    text.lines()
+        .filter(|line| !line.starts_with(['#', '+', '-']))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            let [name, _, gid, members] = fields.as_slice() else {
+                return None;
+            };
+            Some(Group {
+                name: name.to_string(),
+                gid: gid.parse().ok()?,
+                members: members.split(',').filter(|member| !member.is_empty()).map(String::from).collect(),
+            })
+        })
+        .collect()
+}
+
+/// Parse /etc/shadow into the password state and aging of each user. The hash field
+/// is only looked at to tell whether a password is set; it is not kept.
+pub fn parse_shadow(text: &str) -> HashMap<String, (PasswordState, Aging)> {
+    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).expect("valid date");
+    let day = |field: &str| field.parse::<i64>().ok().map(|days| epoch + Duration::days(days));
+    let number = |field: &str| field.parse::<u32>().ok();
+
+    text.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            if fields.len() < 8 {
+                return None;
+            }
+            let hash = fields[1];
+            let unlocked = hash.trim_start_matches('!');
+            let state = if hash.is_empty() {
+                PasswordState::Empty
+            } else if unlocked.is_empty() || unlocked.starts_with('*') {
+                PasswordState::Disabled
+            } else if unlocked.len() < hash.len() {
+                PasswordState::Locked
+            } else {
+                PasswordState::Usable
+            };
+            let aging = Aging {
+                last_change: day(fields[2]).filter(|_| fields[2] != "0"),
+                must_change: fields[2] == "0",
+                min_days: number(fields[3]).filter(|min| *min > 0),
+                max_days: number(fields[4]),
+                warn_days: number(fields[5]),
+                inactive_days: number(fields[6]),
+                expires: day(fields[7]),
+            };
+            Some((fields[0].to_string(), (state, aging)))
+        })
+        .collect()
+}
+
+/// A change to accounts, groups or sudo rules
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AccountChange {
+    CreateUser { name: String, groups: Vec<String>, shell: Option<String>, comment: Option<String>, system: bool },
+    CreateGroup { name: String, system: bool },
+    /// Lock the password and expire the account, which also stops key-based logins
+    Lock { name: String },
+    Unlock { name: String },
+    SetShell { name: String, shell: String },
+    AddToGroup { name: String, group: String },
+    RemoveFromGroup { name: String, group: String },
+    /// Let a user, or a group given as "%group", run commands with sudo
+    GrantSudo { principal: String, commands: Vec<String>, runas: String, nopasswd: bool },
+    /// Disable a rule written by GrantSudo
+    RevokeSudo { principal: String },
+}
+
+impl fmt::Display for AccountChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountChange::CreateUser { name, .. } => write!(f, "Create user {}", name),
+            AccountChange::CreateGroup { name, .. } => write!(f, "Create group {}", name),
+            AccountChange::Lock { name } => write!(f, "Lock user {}", name),
+            AccountChange::Unlock { name } => write!(f, "Unlock user {}", name),
+            AccountChange::SetShell { name, shell } => write!(f, "Set the shell of {} to {}", name, shell),
+            AccountChange::AddToGroup { name, group } => write!(f, "Add {} to group {}", name, group),
+            AccountChange::RemoveFromGroup { name, group } => write!(f, "Remove {} from group {}", name, group),
+            AccountChange::GrantSudo { principal, commands, runas, nopasswd } => {
+                write!(f, "Let {} run {} as {} with sudo", principal, commands.join(", "), runas)?;
+                if *nopasswd {
+                    write!(f, " without a password")?;
+                }
+                Ok(())
+            }
+            AccountChange::RevokeSudo { principal } => write!(f, "Revoke the sudo rule tuxpilot wrote for {}", principal),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SudoersFile {
+    pub path: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AccountStep {
+    Run(Vec<String>),
+    /// Installed with mode 0440 once `visudo -c -f` accepts it, then removed again if
+    /// the full configuration no longer passes `visudo -c`
+    WriteSudoers(SudoersFile),
+}
+
+impl fmt::Display for AccountStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountStep::Run(command) => write!(f, "{}", command.iter().map(|word| shell_quote(word)).collect::<Vec<_>>().join(" ")),
+            AccountStep::WriteSudoers(file) => write!(f, "write {}", file.path),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountPlan {
+    pub change: AccountChange,
+    pub steps: Vec<AccountStep>,
+    /// Command that restores the previous state
+    pub undo: String,
+    /// Privileges gained or lost and other consequences worth knowing before applying
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AccountInspector {
+    transport: Arc<dyn Transport>,
+}
+
+impl AccountInspector {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    /// Every account and group with its effective privileges
+    pub async fn report(&self) -> Result<AccountsReport> {
+        let mut unreadable = Vec::new();
+        let passwd = self.transport.read_file(PASSWD).await.map_err(|error| anyhow!("Failed to read {}: {}", PASSWD, error))?;
+        let group = self.transport.read_file(GROUP).await.map_err(|error| anyhow!("Failed to read {}: {}", GROUP, error))?;
+        let shadow = match self.read(SHADOW, &mut unreadable).await {
+            Some(text) => parse_shadow(&text),
+            None => HashMap::new(),
+        };
+        let hostname = match self.transport.run("hostname", &[]).await {
+            Ok(output) if output.success() && !output.stdout.trim().is_empty() => output.stdout.trim().to_string(),
+            _ => self.transport.host_name().to_string(),
+        };
+
+        let mut report = AccountsReport {
+            hostname,
+            accounts: parse_passwd(&passwd),
+            groups: parse_group(&group),
+            sudoers_files: Vec::new(),
+            sudoers_errors: Vec::new(),
+            unreadable,
+            policy: Policy::default(),
+        };
+        report.policy = self.sudo_policy(&mut report).await;
+
+        let mut accounts = std::mem::take(&mut report.accounts);
+        for account in &mut accounts {
+            let primary = report.groups.iter().find(|group| group.gid == account.gid).map(|group| group.name.clone());
+            account.groups = primary.clone().into_iter()
+                .chain(report.groups.iter()
+                    .filter(|group| group.members.contains(&account.name) && Some(&group.name) != primary.as_ref())
+                    .map(|group| group.name.clone()))
+                .collect();
+            if let Some((password, aging)) = shadow.get(&account.name) {
+                account.password = Some(*password);
+                account.aging = Some(aging.clone());
+            }
+
+            let identity = report.identity(account, &account.groups);
+            account.sudo = report.policy.privileges(&identity, &report.hostname);
+            account.sudo_defaults = report.policy.defaults(&identity, &report.hostname);
+            account.root = account.uid == 0 || account.sudo.iter().any(|privilege| privilege.root);
+            account.privileged_groups = PRIVILEGED_GROUPS.iter()
+                .filter(|(group, _)| account.groups.iter().any(|name| name == group))
+                .map(|(group, access)| format!("{} ({})", group, access))
+                .collect();
+        }
+        report.accounts = accounts;
+        Ok(report)
+    }
+
+    /// Plan `change` against the current accounts
+    pub async fn plan(&self, change: AccountChange) -> Result<AccountPlan> {
+        let report = self.report().await?;
+        let today = Local::now().date_naive();
+        let account = |name: &str| report.account(name)
+            .ok_or_else(|| anyhow!("No user called {} on {}", name, report.hostname));
+        let group = |name: &str| report.group(name)
+            .ok_or_else(|| anyhow!("No group called {} on {}", name, report.hostname));
+        let run = |words: &[&str]| AccountStep::Run(words.iter().map(|word| word.to_string()).collect());
+        let mut notes = Vec::new();
+
+        let (steps, undo) = match &change {
+            AccountChange::CreateUser { name, groups, shell, comment, system } => {
+                check_name(name)?;
+                if report.account(name).is_some() {
+                    bail!("User {} already exists", name);
+                }
+                for name in groups {
+                    group(name)?;
+                }
+                if let Some(shell) = shell {
+                    self.check_shell(shell, &mut notes).await?;
+                }
+                let mut command = vec!["useradd", "-m"];
+                if *system {
+                    command.push("-r");
+                }
+                if let Some(shell) = shell {
+                    command.extend(["-s", shell]);
+                }
+                if let Some(comment) = comment {
+                    command.extend(["-c", comment]);
+                }
+                let groups = groups.join(",");
+                if !groups.is_empty() {
+                    command.extend(["-G", &groups]);
+                }
+                command.push(name);
+                notes.push(format!("{} has no password until one is set with `passwd {}`", name, name));
+                (vec![run(&command)], format!("userdel -r {}", name))
+            }
+            AccountChange::CreateGroup { name, system } => {
+                check_name(name)?;
+                if report.group(name).is_some() {
+                    bail!("Group {} already exists", name);
+                }
+                let command = if *system { run(&["groupadd", "-r", name]) } else { run(&["groupadd", name]) };
+                (vec![command], format!("groupdel {}", name))
+            }
+            AccountChange::Lock { name } => {
+                let account = account(name)?;
+                if account.is_locked(today) {
+                    bail!("{} is already locked", name);
+                }
+                if account.uid == 0 {
+                    bail!("Locking root can leave the machine without a way to log in as administrator");
+                }
+                let expires = expiry_argument(account);
+                let undo = match account.password {
+                    Some(PasswordState::Usable) => format!("usermod -U -e {} {}", expires, name),
+                    _ => format!("usermod -e {} {}", expires, name),
+                };
+                if !account.sudo.is_empty() || account.root {
+                    notes.push(format!("{} can no longer use sudo while locked", name));
+                }
+                (vec![run(&["usermod", "-L", "-e", "1", name])], undo)
+            }
+            AccountChange::Unlock { name } => {
+                let account = account(name)?;
+                if !account.is_locked(today) {
+                    bail!("{} is not locked", name);
+                }
+                let mut command = vec!["usermod"];
+                if account.password == Some(PasswordState::Locked) {
+                    command.push("-U");
+                }
+                let expired = account.aging.as_ref().and_then(|aging| aging.expires).is_some_and(|expires| expires <= today);
+                if expired {
+                    command.extend(["-e", "-1"]);
+                }
+                command.push(name);
+                if account.password == Some(PasswordState::Disabled) {
+                    notes.push(format!("{} has no password; set one with `passwd {}` or log in with a key", name, name));
+                }
+                let undo = if expired {
+                    format!("usermod -L -e {} {}", expiry_argument(account), name)
+                } else {
+                    format!("usermod -L {}", name)
+                };
+                (vec![run(&command)], undo)
+            }
+            AccountChange::SetShell { name, shell } => {
+                let account = account(name)?;
+                if account.shell == *shell {
+                    bail!("{} already uses {}", name, shell);
+                }
+                self.check_shell(shell, &mut notes).await?;
+                (vec![run(&["usermod", "-s", shell, name])], format!("usermod -s {} {}", shell_quote(&account.shell), name))
+            }
+            AccountChange::AddToGroup { name, group: group_name } => {
+                let account = account(name)?;
+                group(group_name)?;
+                if account.groups.contains(group_name) {
+                    bail!("{} is already in group {}", name, group_name);
+                }
+                let mut groups = account.groups.clone();
+                groups.push(group_name.clone());
+                notes.extend(privilege_changes(&report, account, &groups));
+                (vec![run(&["gpasswd", "-a", name, group_name])], format!("gpasswd -d {} {}", name, group_name))
+            }
+            AccountChange::RemoveFromGroup { name, group: group_name } => {
+                let account = account(name)?;
+                let group = group(group_name)?;
+                if group.gid == account.gid {
+                    bail!("{} is the primary group of {}; change it with `usermod -g` instead", group_name, name);
+                }
+                if !group.members.contains(name) {
+                    bail!("{} is not in group {}", name, group_name);
+                }
+                let groups: Vec<String> = account.groups.iter().filter(|group| *group != group_name).cloned().collect();
+                notes.extend(privilege_changes(&report, account, &groups));
+                (vec![run(&["gpasswd", "-d", name, group_name])], format!("gpasswd -a {} {}", name, group_name))
+            }
+            AccountChange::GrantSudo { principal, commands, runas, nopasswd } => {
+                match principal.strip_prefix('%') {
+                    Some(name) => {
+                        group(name)?;
+                    }
+                    None => {
+                        account(principal)?;
+                    }
+                }
+                if commands.is_empty() {
+                    bail!("Name at least one command, or ALL");
+                }
+                for command in commands {
+                    let program = command.split_whitespace().next().unwrap_or_default();
+                    if program != "ALL" && program != "sudoedit" && !program.starts_with('/') {
+                        bail!("sudo needs the full path of {}", program);
+                    }
+                }
+                let path = managed_path(principal);
+                if self.transport.path_exists(&path).await {
+                    bail!("{} already exists; revoke it first with `tuxpilot users revoke {}`", path, principal);
+                }
+
+                let commands: Vec<String> = commands.iter().map(|command| sudoers::escape(command)).collect();
+                let tag = if *nopasswd { "NOPASSWD: " } else { "" };
+                let content = format!("# Written by tuxpilot\n{} ALL=({}) {}{}\n", principal, runas, tag, commands.join(", "));
+                let (_, errors) = sudoers::parse(&path, &content);
+                if let Some(error) = errors.first() {
+                    bail!("The rule would not parse: {}", error);
+                }
+
+                if let Some(account) = report.account(principal) {
+                    let mut policy_entries = report.policy.entries.clone();
+                    policy_entries.extend(sudoers::parse(&path, &content).0);
+                    let identity = report.identity(account, &account.groups);
+                    let privileges = Policy::new(policy_entries).privileges(&identity, &report.hostname);
+                    if privileges.iter().any(|privilege| privilege.root) && !account.root {
+                        notes.push(format!("{} becomes able to run anything as root", principal));
+                    }
+                    for privilege in account.sudo.iter().filter(|privilege| privilege.root) {
+                        notes.push(format!("{} can already run anything as root through {} ({})", principal, privilege.via, privilege.source));
+                    }
+                }
+                (vec![AccountStep::WriteSudoers(SudoersFile { path: path.clone(), content })], format!("rm {}", path))
+            }
+            AccountChange::RevokeSudo { principal } => {
+                let path = managed_path(principal);
+                if !self.transport.path_exists(&path).await {
+                    bail!("tuxpilot has not granted {} anything; there is no {}", principal, path);
+                }
+                let revoked = format!("{}{}", path, REVOKED_SUFFIX);
+                if let Some(account) = report.account(principal) {
+                    for privilege in account.sudo.iter().filter(|privilege| !privilege.source.starts_with(&format!("{}:", path))) {
+                        notes.push(format!("{} keeps `{}` as {} through {} ({})", principal, privilege.command, privilege.runas, privilege.via, privilege.source));
+                    }
+                }
+                (vec![run(&["mv", &path, &revoked])], format!("mv {} {}", revoked, path))
+            }
+        };
+        Ok(AccountPlan { change, steps, undo, notes })
+    }
+
+    /// The shell has to exist; shells missing from /etc/shells are allowed with a note
+    async fn check_shell(&self, shell: &str, notes: &mut Vec<String>) -> Result<()> {
+        if !shell.starts_with('/') || !self.transport.path_exists(shell).await {
+            bail!("{} is not a shell on {}", shell, self.transport.host_name());
+        }
+        let shells = self.transport.read_file("/etc/shells").await.unwrap_or_default();
+        if !shells.lines().any(|line| line.trim() == shell) && !shell.ends_with("/nologin") && !shell.ends_with("/false") {
+            notes.push(format!("{} is not listed in /etc/shells; some services refuse users with it", shell));
+        }
+        Ok(())
+    }
+
+    /// The entries of /etc/sudoers and everything it includes, in the order sudo reads
+    /// them. Files read, parse errors and unreadable files are recorded in `report`.
+    async fn sudo_policy(&self, report: &mut AccountsReport) -> Policy {
+        let mut entries = Vec::new();
+        let mut stack = Vec::new();
+        if let Some(text) = self.read(SUDOERS, &mut report.unreadable).await {
+            let (parsed, errors) = sudoers::parse(SUDOERS, &text);
+            report.sudoers_files.push(SUDOERS.to_string());
+            report.sudoers_errors.extend(errors);
+            stack.push(parsed.into_iter());
+        }
+
+        while let Some(current) = stack.last_mut() {
+            let Some(entry) = current.next() else {
+                stack.pop();
+                continue;
+            };
+            let sudoers::Directive::Include { path, dir } = &entry.directive else {
+                entries.push(entry);
+                continue;
+            };
+            if stack.len() >= MAX_INCLUDE_DEPTH {
+                report.sudoers_errors.push(format!("{}: includes nested too deeply", entry.source()));
+                continue;
+            }
+
+            let path = include_path(&entry.file, path, &report.hostname);
+            let files = if *dir { self.include_dir(&path, &mut report.unreadable).await } else { vec![path] };
+            let mut included = Vec::new();
+            for file in files {
+                if let Some(text) = self.read(&file, &mut report.unreadable).await {
+                    let (parsed, errors) = sudoers::parse(&file, &text);
+                    report.sudoers_files.push(file);
+                    report.sudoers_errors.extend(errors);
+                    included.extend(parsed);
+                }
+            }
+            stack.push(included.into_iter());
+        }
+        Policy::new(entries)
+    }
+
+    /// Files sudo reads from an include directory, in the order it reads them
+    async fn include_dir(&self, dir: &str, unreadable: &mut Vec<String>) -> Vec<String> {
+        match self.transport.run("ls", &["-1A", dir]).await {
+            Ok(output) if output.success() => {
+                let mut names: Vec<&str> = output.stdout.lines()
+                    .filter(|name| !name.ends_with('~') && !name.contains('.'))
+                    .collect();
+                names.sort_unstable();
+                names.into_iter().map(|name| format!("{}/{}", dir.trim_end_matches('/'), name)).collect()
+            }
+            _ => {
+                if self.transport.path_exists(dir).await {
+                    unreadable.push(dir.to_string());
+                }
+                Vec::new()
+            }
+        }
+    }
+
+    async fn read(&self, path: &str, unreadable: &mut Vec<String>) -> Option<String> {
+        match self.transport.read_file(path).await {
+            Ok(text) => Some(text),
+            Err(_) => {
+                if self.transport.path_exists(path).await {
+                    unreadable.push(path.to_string());
+                }
+                None
+            }
+        }
+    }
+}
+
+/// Include paths may use %h for the short host name and are relative to the
+/// including file's directory
+fn include_path(including: &str, path: &str, hostname: &str) -> String {
+    let path = path.replace("%h", hostname.split('.').next().unwrap_or(hostname));
+    if path.starts_with('/') {
+        return path;
+    }
+    let dir = including.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("/etc");
+    format!("{}/{}", dir, path)
+}
+
+/// The sudoers.d file tuxpilot keeps a user's or "%group"'s rule in
+fn managed_path(principal: &str) -> String {
+    let name = match principal.strip_prefix('%') {
+        Some(group) => format!("group-{}", group),
+        None => principal.to_string(),
+    };
+    format!("{}/{}{}", SUDOERS_D, MANAGED_PREFIX, name.replace('.', "_"))
+}
+
+/// The current account expiry as a usermod -e argument, -1 when there is none
+fn expiry_argument(account: &Account) -> String {
+    account.aging.as_ref()
+        .and_then(|aging| aging.expires)
+        .map(|expires| expires.format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|| "-1".to_string())
+}
+
+/// Names useradd and groupadd accept everywhere
+fn check_name(name: &str) -> Result<()> {
+    let valid = name.len() <= 32
+        && name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '-'));
+    if !valid {
+        bail!("Invalid name '{}'; use up to 32 lower case letters, digits, '_' and '-', starting with a letter", name);
+    }
+    Ok(())
+}
+
+/// What `account` would gain or lose with `groups` as its groups
+fn privilege_changes(report: &AccountsReport, account: &Account, groups: &[String]) -> Vec<String> {
+    let after = report.policy.privileges(&report.identity(account, groups), &report.hostname);
+    let describe = |privilege: &Privilege| format!("`{}` as {} ({})", privilege.command, privilege.runas, privilege.source);
+    let same = |a: &Privilege, b: &Privilege| a.source == b.source && a.command == b.command && a.runas == b.runas;
+
+    let mut notes: Vec<String> = after.iter()
+        .filter(|privilege| !account.sudo.iter().any(|before| same(before, privilege)))
+        .map(|privilege| format!("{} gains sudo {}", account.name, describe(privilege)))
+        .collect();
+    notes.extend(account.sudo.iter()
+        .filter(|privilege| !after.iter().any(|after| same(after, privilege)))
+        .map(|privilege| format!("{} loses sudo {}", account.name, describe(privilege))));
+    for (group, access) in PRIVILEGED_GROUPS {
+        match (account.groups.iter().any(|name| name == group), groups.iter().any(|name| name == group)) {
+            (false, true) => notes.push(format!("Members of {} get {}", group, access)),
+            (true, false) => notes.push(format!("{} no longer gets {} through {}", account.name, access, group)),
+            _ => {}
+        }
+    }
+    notes
+}
@@ -0,0 +1,694 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Tags that change how a command runs, in pairs of opposites. A tag applies to the
+/// commands after it in the list until its opposite is given.
+const TAGS: [[&str; 2]; 8] = [
+    ["NOPASSWD", "PASSWD"],
+    ["NOEXEC", "EXEC"],
+    ["SETENV", "NOSETENV"],
+    ["LOG_INPUT", "NOLOG_INPUT"],
+    ["LOG_OUTPUT", "NOLOG_OUTPUT"],
+    ["MAIL", "NOMAIL"],
+    ["FOLLOW", "NOFOLLOW"],
+    ["INTERCEPT", "NOINTERCEPT"],
+];
+
+/// Options written as NAME=value before a command
+const OPTIONS: [&str; 8] = ["ROLE", "TYPE", "CWD", "CHROOT", "TIMEOUT", "NOTBEFORE", "NOTAFTER", "APPARMOR_PROFILE"];
+
+const DIGESTS: [&str; 4] = ["sha224", "sha256", "sha384", "sha512"];
+
+/// How deeply aliases may refer to other aliases before the chain is taken as a loop
+const MAX_ALIAS_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AliasKind {
+    User,
+    Runas,
+    Host,
+    Cmnd,
+}
+
+/// A member of a user, host, runas or command list, such as "%wheel", "!/usr/bin/su"
+/// or an alias name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub negated: bool,
+    pub value: String,
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", if self.negated { "!" } else { "" }, self.value)
+    }
+}
+
+/// The users and groups in parentheses before a command, as in "(root:wheel)"
+#[derive(Debug, Clone, PartialEq)]
+pub struct Runas {
+    pub users: Vec<Item>,
+    pub groups: Vec<Item>,
+}
+
+impl fmt::Display for Runas {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", join(&self.users))?;
+        if !self.groups.is_empty() {
+            write!(f, ":{}", join(&self.groups))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandSpec {
+    pub command: Item,
+    /// Runas and tags carry over from the previous command in the list
+    pub runas: Option<Runas>,
+    pub tags: Vec<String>,
+    pub options: Vec<String>,
+    /// Checksum the command must match, as "sha256:..."
+    pub digest: Option<String>,
+}
+
+/// One `host_list = command_list` part of a rule
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostCommands {
+    pub hosts: Vec<Item>,
+    pub commands: Vec<CommandSpec>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DefaultsScope {
+    All,
+    Host(Vec<Item>),
+    User(Vec<Item>),
+    Runas(Vec<Item>),
+    Command(Vec<Item>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Directive {
+    Alias { kind: AliasKind, name: String, members: Vec<Item> },
+    Defaults { scope: DefaultsScope, settings: Vec<String> },
+    Rule { users: Vec<Item>, grants: Vec<HostCommands> },
+    /// `@include`/`#include`, or `@includedir`/`#includedir` when `dir` is set
+    Include { path: String, dir: bool },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub file: String,
+    /// First line of the entry, which may continue over several lines
+    pub line: usize,
+    pub directive: Directive,
+}
+
+impl Entry {
+    pub fn source(&self) -> String {
+        format!("{}:{}", self.file, self.line)
+    }
+}
+
+/// Parse a sudoers file into its entries and the lines that could not be parsed, as
+/// "file:line: message"
+pub fn parse(file: &str, text: &str) -> (Vec<Entry>, Vec<String>) {
+    let mut entries = Vec::new();
+    let mut errors = Vec::new();
+    for (line, text) in logical_lines(text) {
+        match parse_line(&text) {
+            Ok(directives) => entries.extend(directives.into_iter().map(|directive| Entry { file: file.to_string(), line, directive })),
+            Err(error) => errors.push(format!("{}:{}: {}", file, line, error)),
+        }
+    }
+    (entries, errors)
+}
+
+/// Escape the characters that end a word in a command or its arguments
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if matches!(c, ',' | ':' | '=' | '\\' | '(' | ')' | '#' | '"') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Lines with comments removed and backslash continuations joined, with the line
+/// number each starts on
+fn logical_lines(text: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    let mut start = 0;
+    for (index, raw) in text.lines().enumerate() {
+        if current.is_empty() {
+            start = index + 1;
+        }
+        let line = strip_comment(raw);
+        match line.strip_suffix('\\') {
+            Some(rest) if !rest.ends_with('\\') => {
+                current.push_str(rest);
+                current.push(' ');
+            }
+            _ => {
+                current.push_str(line);
+                if !current.trim().is_empty() {
+                    lines.push((start, current.trim().to_string()));
+                }
+                current.clear();
+            }
+        }
+    }
+    if !current.trim().is_empty() {
+        lines.push((start, current.trim().to_string()));
+    }
+    lines
+}
+
+/// A "#" starts a comment unless it begins `#include`/`#includedir` or a numeric ID
+/// such as "#1000"
+fn strip_comment(line: &str) -> &str {
+    let trimmed = line.trim_start();
+    if include_directive(trimmed).is_some() {
+        return line;
+    }
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut previous = ' ';
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            '#' if !quoted && (previous.is_whitespace() || ",:=(!".contains(previous))
+                && !line[index + 1..].starts_with(|next: char| next.is_ascii_digit()) => {
+                return &line[..index];
+            }
+            _ => {}
+        }
+        previous = c;
+    }
+    line
+}
+
+/// The path and whether it names a directory, for include lines
+fn include_directive(line: &str) -> Option<(String, bool)> {
+    for (prefix, dir) in [("@includedir", true), ("#includedir", true), ("@include", false), ("#include", false)] {
+        if let Some(rest) = line.strip_prefix(prefix) {
+            if rest.starts_with(char::is_whitespace) {
+                return Some((rest.trim().trim_matches('"').to_string(), dir));
+            }
+        }
+    }
+    None
+}
+
+fn parse_line(line: &str) -> Result<Vec<Directive>> {
+    if let Some((path, dir)) = include_directive(line) {
+        if path.is_empty() {
+            bail!("Include without a path");
+        }
+        return Ok(vec![Directive::Include { path, dir }]);
+    }
+    if let Some(rest) = line.strip_prefix("Defaults") {
+        if rest.is_empty() || rest.starts_with(|c: char| c.is_whitespace() || "@:!>".contains(c)) {
+            return Ok(vec![parse_defaults(rest)?]);
+        }
+    }
+
+    let mut parser = Parser { tokens: tokenize(line)?, position: 0 };
+    let alias_kind = match parser.peek() {
+        Some(Token::Word(word)) => match word.as_str() {
+            "User_Alias" => Some(AliasKind::User),
+            "Runas_Alias" => Some(AliasKind::Runas),
+            "Host_Alias" => Some(AliasKind::Host),
+            "Cmnd_Alias" | "Cmd_Alias" => Some(AliasKind::Cmnd),
+            _ => None,
+        },
+        _ => None,
+    };
+    let directives = match alias_kind {
+        Some(kind) => {
+            parser.position += 1;
+            let mut aliases = Vec::new();
+            loop {
+                let name = parser.word("alias name")?;
+                if !name.starts_with(|c: char| c.is_ascii_uppercase())
+                    || !name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_') {
+                    bail!("Alias names are upper case letters, digits and '_': {}", name);
+                }
+                parser.expect(Token::Equals)?;
+                let members = parser.items(kind == AliasKind::Cmnd)?;
+                aliases.push(Directive::Alias { kind, name, members });
+                if !parser.eat(&Token::Colon) {
+                    break;
+                }
+            }
+            aliases
+        }
+        None => {
+            let users = parser.items(false)?;
+            let mut grants = Vec::new();
+            loop {
+                let hosts = parser.items(false)?;
+                parser.expect(Token::Equals)?;
+                grants.push(HostCommands { hosts, commands: parser.commands()? });
+                if !parser.eat(&Token::Colon) {
+                    break;
+                }
+            }
+            vec![Directive::Rule { users, grants }]
+        }
+    };
+    if let Some(token) = parser.peek() {
+        bail!("Unexpected {}", token);
+    }
+    Ok(directives)
+}
+
+/// `Defaults` lines have their own syntax: an optional scope straight after the
+/// keyword, then comma separated settings whose values may be quoted
+fn parse_defaults(rest: &str) -> Result<Directive> {
+    let (scope, settings) = match rest.chars().next() {
+        Some(marker) if "@:!>".contains(marker) => {
+            let rest = &rest[1..];
+            let (list, settings) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            let items: Vec<Item> = split_outside_quotes(list).into_iter().map(|value| {
+                let value = value.trim();
+                let stripped = value.trim_start_matches('!');
+                Item { negated: (value.len() - stripped.len()) % 2 == 1, value: stripped.to_string() }
+            }).collect();
+            if items.iter().any(|item| item.value.is_empty()) {
+                bail!("Defaults{} without a list", marker);
+            }
+            let scope = match marker {
+                '@' => DefaultsScope::Host(items),
+                ':' => DefaultsScope::User(items),
+                '!' => DefaultsScope::Command(items),
+                _ => DefaultsScope::Runas(items),
+            };
+            (scope, settings)
+        }
+        _ => (DefaultsScope::All, rest),
+    };
+    let settings: Vec<String> = split_outside_quotes(settings).into_iter()
+        .map(|setting| setting.trim().to_string())
+        .filter(|setting| !setting.is_empty())
+        .collect();
+    if settings.is_empty() {
+        bail!("Defaults without settings");
+    }
+    Ok(Directive::Defaults { scope, settings })
+}
+
+fn split_outside_quotes(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (index, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                parts.push(&text[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Comma,
+    Colon,
+    Equals,
+    Open,
+    Close,
+    Bang,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "'{}'", word),
+            Token::Comma => write!(f, "','"),
+            Token::Colon => write!(f, "':'"),
+            Token::Equals => write!(f, "'='"),
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+            Token::Bang => write!(f, "'!'"),
+        }
+    }
+}
+
+/// Split a line into words and the punctuation of the grammar. Backslashes escape
+/// punctuation inside words and double quotes keep it, as in `"%domain users"`.
+fn tokenize(line: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        let token = match c {
+            _ if c.is_whitespace() => None,
+            ',' => Some(Token::Comma),
+            ':' => Some(Token::Colon),
+            '=' => Some(Token::Equals),
+            '(' => Some(Token::Open),
+            ')' => Some(Token::Close),
+            '!' => Some(Token::Bang),
+            _ => {
+                let mut word = String::new();
+                let mut quoted = false;
+                while let Some(&c) = chars.peek() {
+                    if !quoted && (c.is_whitespace() || ",:=()".contains(c)) {
+                        break;
+                    }
+                    chars.next();
+                    match c {
+                        '\\' => match chars.next() {
+                            Some(next) if ",:=()\\\"# \t!".contains(next) => word.push(next),
+                            Some(next) => {
+                                word.push('\\');
+                                word.push(next);
+                            }
+                            None => bail!("Line ends in a backslash"),
+                        },
+                        '"' => quoted = !quoted,
+                        _ => word.push(c),
+                    }
+                }
+                if quoted {
+                    bail!("Unterminated quote in {}", word);
+                }
+                tokens.push(Token::Word(word));
+                continue;
+            }
+        };
+        chars.next();
+        tokens.extend(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.position + offset)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let matched = self.peek() == Some(token);
+        if matched {
+            self.position += 1;
+        }
+        matched
+    }
+
+    fn expect(&mut self, token: Token) -> Result<()> {
+        match self.peek() {
+            Some(next) if *next == token => {
+                self.position += 1;
+                Ok(())
+            }
+            Some(next) => Err(anyhow!("Expected {} but found {}", token, next)),
+            None => Err(anyhow!("Expected {} at the end of the line", token)),
+        }
+    }
+
+    fn word(&mut self, what: &str) -> Result<String> {
+        match self.peek().cloned() {
+            Some(Token::Word(word)) => {
+                self.position += 1;
+                Ok(word)
+            }
+            Some(token) => Err(anyhow!("Expected {} but found {}", what, token)),
+            None => Err(anyhow!("Expected {} at the end of the line", what)),
+        }
+    }
+
+    /// An item with any number of "!" before it. Commands run over several words,
+    /// up to the next "," or ":".
+    fn item(&mut self, command: bool) -> Result<Item> {
+        let mut negated = false;
+        while self.eat(&Token::Bang) {
+            negated = !negated;
+        }
+        let mut value = self.word(if command { "a command" } else { "a name" })?;
+        if command {
+            while let Some(Token::Word(word)) = self.peek().cloned() {
+                self.position += 1;
+                value.push(' ');
+                value.push_str(&word);
+            }
+        }
+        Ok(Item { negated, value })
+    }
+
+    fn items(&mut self, command: bool) -> Result<Vec<Item>> {
+        let mut items = vec![self.item(command)?];
+        while self.eat(&Token::Comma) {
+            items.push(self.item(command)?);
+        }
+        Ok(items)
+    }
+
+    /// `(runas) OPTION=value TAG: command, ...`, where each part before the command is
+    /// optional and carries over to the commands that follow
+    fn commands(&mut self) -> Result<Vec<CommandSpec>> {
+        let mut specs = Vec::new();
+        let mut runas = None;
+        let mut tags: Vec<String> = Vec::new();
+        let mut options: Vec<String> = Vec::new();
+        loop {
+            if self.eat(&Token::Open) {
+                runas = Some(self.runas()?);
+            }
+            while let Some(Token::Word(word)) = self.peek().cloned() {
+                match self.peek_at(1) {
+                    Some(Token::Equals) if OPTIONS.contains(&word.as_str()) => {
+                        self.position += 2;
+                        let value = self.word("an option value")?;
+                        options.retain(|option| !option.starts_with(&format!("{}=", word)));
+                        options.push(format!("{}={}", word, value));
+                    }
+                    Some(Token::Colon) if TAGS.iter().any(|pair| pair.contains(&word.as_str())) => {
+                        self.position += 2;
+                        let pair = TAGS.iter().find(|pair| pair.contains(&word.as_str())).expect("tag was found above");
+                        tags.retain(|tag| !pair.contains(&tag.as_str()));
+                        tags.push(word);
+                    }
+                    _ => break,
+                }
+            }
+            let digest = match (self.peek().cloned(), self.peek_at(1)) {
+                (Some(Token::Word(algorithm)), Some(Token::Colon)) if DIGESTS.contains(&algorithm.as_str()) => {
+                    self.position += 2;
+                    Some(format!("{}:{}", algorithm, self.word("a digest")?))
+                }
+                _ => None,
+            };
+            specs.push(CommandSpec {
+                command: self.item(true)?,
+                runas: runas.clone(),
+                tags: tags.clone(),
+                options: options.clone(),
+                digest,
+            });
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        Ok(specs)
+    }
+
+    fn runas(&mut self) -> Result<Runas> {
+        let users = match self.peek() {
+            Some(Token::Colon) | Some(Token::Close) => Vec::new(),
+            _ => self.items(false)?,
+        };
+        let groups = if self.eat(&Token::Colon) && self.peek() != Some(&Token::Close) {
+            self.items(false)?
+        } else {
+            Vec::new()
+        };
+        self.expect(Token::Close)?;
+        Ok(Runas { users, groups })
+    }
+}
+
+fn join(items: &[Item]) -> String {
+    items.iter().map(Item::to_string).collect::<Vec<_>>().join(",")
+}
+
+/// Who a rule is evaluated for
+#[derive(Debug, Clone)]
+pub struct Identity {
+    pub name: String,
+    pub uid: u32,
+    /// Primary and supplementary groups with their GIDs
+    pub groups: Vec<(String, u32)>,
+}
+
+/// A command a user may run with sudo
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Privilege {
+    /// Where the rule is, as "file:line"
+    pub source: String,
+    /// The user list member that matched: the user, a "%group", an alias or "ALL"
+    pub via: String,
+    /// Target users and groups, "root" when the rule does not say
+    pub runas: String,
+    /// Command with aliases expanded, "!" in front when it is excluded
+    pub command: String,
+    pub tags: Vec<String>,
+    /// Any command as root
+    pub root: bool,
+}
+
+/// Every sudoers entry in the order sudo reads them, includes expanded
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    pub entries: Vec<Entry>,
+    aliases: HashMap<(AliasKind, String), Vec<Item>>,
+}
+
+impl Policy {
+    pub fn new(entries: Vec<Entry>) -> Self {
+        let mut aliases = HashMap::new();
+        for entry in &entries {
+            if let Directive::Alias { kind, name, members } = &entry.directive {
+                aliases.insert((*kind, name.clone()), members.clone());
+            }
+        }
+        Self { entries, aliases }
+    }
+
+    /// What `identity` may run with sudo on `host`, in the order the rules appear.
+    /// Host items this cannot evaluate, such as netgroups and addresses, count as
+    /// matching.
+    pub fn privileges(&self, identity: &Identity, host: &str) -> Vec<Privilege> {
+        let mut privileges = Vec::new();
+        for entry in &self.entries {
+            let Directive::Rule { users, grants } = &entry.directive else {
+                continue;
+            };
+            let Some((true, via)) = self.matches(users, AliasKind::User, &|value| user_matches(identity, value), 0) else {
+                continue;
+            };
+            for grant in grants {
+                if self.matches(&grant.hosts, AliasKind::Host, &|value| host_matches(host, value), 0).is_none_or(|(allowed, _)| !allowed) {
+                    continue;
+                }
+                for spec in &grant.commands {
+                    let as_root = match &spec.runas {
+                        None => true,
+                        Some(runas) if runas.users.is_empty() => false,
+                        Some(runas) => self.matches(&runas.users, AliasKind::Runas, &|value| value == "root" || value == "#0", 0)
+                            .is_some_and(|(allowed, _)| allowed),
+                    };
+                    let runas = spec.runas.as_ref().map(Runas::to_string).unwrap_or_else(|| "root".to_string());
+                    for command in self.expand_commands(&spec.command, 0) {
+                        privileges.push(Privilege {
+                            source: entry.source(),
+                            via: via.clone(),
+                            runas: runas.clone(),
+                            root: as_root && !command.negated && command.value == "ALL",
+                            command: command.to_string(),
+                            tags: spec.tags.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        privileges
+    }
+
+    /// Defaults settings that apply to `identity` on `host`
+    pub fn defaults(&self, identity: &Identity, host: &str) -> Vec<String> {
+        let mut settings = Vec::new();
+        for entry in &self.entries {
+            let Directive::Defaults { scope, settings: entry_settings } = &entry.directive else {
+                continue;
+            };
+            let applies = match scope {
+                DefaultsScope::All => true,
+                DefaultsScope::User(users) => self.matches(users, AliasKind::User, &|value| user_matches(identity, value), 0)
+                    .is_some_and(|(allowed, _)| allowed),
+                DefaultsScope::Host(hosts) => self.matches(hosts, AliasKind::Host, &|value| host_matches(host, value), 0)
+                    .is_some_and(|(allowed, _)| allowed),
+                DefaultsScope::Runas(_) | DefaultsScope::Command(_) => false,
+            };
+            if applies {
+                settings.extend(entry_settings.iter().cloned());
+            }
+        }
+        settings
+    }
+
+    /// Whether a list allows, denies or does not mention a value, and the member that
+    /// decided it. As in sudo, the last member that matches wins.
+    fn matches(&self, items: &[Item], kind: AliasKind, test: &dyn Fn(&str) -> bool, depth: usize) -> Option<(bool, String)> {
+        for item in items.iter().rev() {
+            let matched = match self.aliases.get(&(kind, item.value.clone())) {
+                Some(members) if depth < MAX_ALIAS_DEPTH => self.matches(members, kind, test, depth + 1).map(|(allowed, _)| allowed),
+                Some(_) => None,
+                None if item.value == "ALL" || test(&item.value) => Some(true),
+                None => None,
+            };
+            if let Some(allowed) = matched {
+                return Some((allowed != item.negated, item.value.clone()));
+            }
+        }
+        None
+    }
+
+    fn expand_commands(&self, command: &Item, depth: usize) -> Vec<Item> {
+        match self.aliases.get(&(AliasKind::Cmnd, command.value.clone())) {
+            Some(members) if depth < MAX_ALIAS_DEPTH => members.iter()
+                .flat_map(|member| self.expand_commands(member, depth + 1))
+                .map(|item| Item { negated: item.negated != command.negated, value: item.value })
+                .collect(),
+            _ => vec![command.clone()],
+        }
+    }
+}
+
+fn user_matches(identity: &Identity, value: &str) -> bool {
+    if let Some(group) = value.strip_prefix('%') {
+        if let Some(gid) = group.strip_prefix('#') {
+            return gid.parse::<u32>().is_ok_and(|gid| identity.groups.iter().any(|(_, id)| *id == gid));
+        }
+        // Non-Unix groups ("%:name") need a group plugin to resolve
+        return identity.groups.iter().any(|(name, _)| name == group);
+    }
+    if let Some(uid) = value.strip_prefix('#') {
+        return uid.parse::<u32>().is_ok_and(|uid| uid == identity.uid);
+    }
+    value == identity.name
+}
+
+fn host_matches(host: &str, value: &str) -> bool {
+    if value.starts_with('+') || value.contains('/') || value.parse::<std::net::IpAddr>().is_ok() {
+        return true;
+    }
+    let short = |name: &str| name.split('.').next().unwrap_or_default().to_lowercase();
+    value.eq_ignore_ascii_case(host) || (!value.contains('.') && short(value) == short(host))
+}
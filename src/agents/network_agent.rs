@@ -28,11 +28,23 @@ impl NetworkAgent {
         })
     }
 
-    async fn diagnose_network(&self, _context: &AgentContext) -> Result<Vec<String>> {
+    /// Connectivity problems found comparing the network configuration with the
+    /// kernel's addresses and routes, or what was checked when there are none
+    async fn diagnose_network(&self, context: &AgentContext) -> Result<Vec<String>> {
+        let report = context.linux_integration.network().report().await;
+        if !report.problems.is_empty() {
+            return Ok(report.problems.iter().map(|problem| problem.to_string()).collect());
+        }
+
         let mut diagnostics = Vec::new();
-        diagnostics.push("Checking network interfaces...".to_string());
-        diagnostics.push("Testing DNS resolution...".to_string());
-        diagnostics.push("Analyzing network connectivity...".to_string());
+        for interface in report.interfaces.iter().filter(|interface| interface.owner.is_some()) {
+            let owner = interface.owner.map(|owner| owner.to_string()).unwrap_or_default();
+            diagnostics.push(format!("{} is managed by {} and matches its configuration", interface.name, owner));
+        }
+        if let Some(route) = report.default_routes.first() {
+            diagnostics.push(format!("Default route: {}", route));
+        }
+        diagnostics.push(format!("DNS servers: {}", report.nameservers.join(", ")));
         Ok(diagnostics)
     }
 }
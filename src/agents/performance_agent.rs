@@ -28,12 +28,15 @@ impl PerformanceAgent {
         })
     }
 
-    async fn optimize_performance(&self, _context: &AgentContext) -> Result<Vec<String>> {
+    async fn optimize_performance(&self, context: &AgentContext) -> Result<Vec<String>> {
         let mut optimizations = Vec::new();
         optimizations.push("Analyzing CPU usage patterns...".to_string());
         optimizations.push("Checking memory allocation...".to_string());
         optimizations.push("Optimizing disk I/O...".to_string());
-        optimizations.push("Tuning system parameters...".to_string());
+        // Kernel parameters of a container or WSL instance belong to the host
+        if context.linux_integration.environment().can_change_kernel() {
+            optimizations.push("Tuning system parameters...".to_string());
+        }
         Ok(optimizations)
     }
 }
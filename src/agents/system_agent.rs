@@ -1,8 +1,11 @@
 use anyhow::Result;
+use std::sync::Arc;
 use std::time::Instant;
 
 use super::{Agent, AgentTask, AgentContext, AgentResult, AgentStatus, AgentCapability, SpecializationLevel, TaskType};
 use crate::config::Config;
+use crate::packages::{self, PackageOperation};
+use crate::remote::transport::LocalTransport;
 
 /// Specialized agent for system management tasks
 pub struct SystemAgent {
@@ -48,8 +51,12 @@ impl SystemAgent {
             recommendations.push("High disk usage detected. Consider cleaning up unnecessary files.".to_string());
         }
 
-        // Service analysis
-        let critical_services = vec!["sshd", "systemd", "networkd"];
+        // Service analysis; containers without an init system run no services
+        let critical_services = if context.linux_integration.environment().service_manager_running {
+            vec!["sshd", "systemd", "networkd"]
+        } else {
+            Vec::new()
+        };
         for service in critical_services {
             if !context.system_state.services_running.contains(&service.to_string()) {
                 recommendations.push(format!("Critical service '{}' is not running.", service));
@@ -113,21 +120,25 @@ impl SystemAgent {
     async fn optimize_system(&self, _context: &AgentContext) -> Result<Vec<String>> {
         let mut actions = Vec::new();
 
+        let manager = &self.config.system.package_manager;
+
         // Clear package cache (safe operation)
-        if self.config.system.package_manager.to_string().contains("Pacman") {
-            actions.push("Suggested: sudo pacman -Sc (clear package cache)".to_string());
-        } else if self.config.system.package_manager.to_string().contains("Apt") {
-            actions.push("Suggested: sudo apt autoremove && sudo apt autoclean".to_string());
-        }
+        let clean = packages::command_line(manager, &PackageOperation::Clean).join(" ");
+        actions.push(format!("Suggested: sudo {} (clear package cache)", clean));
 
         // Clear temporary files
         actions.push("Suggested: sudo find /tmp -type f -atime +7 -delete".to_string());
 
-        // Update package database
-        actions.push("Suggested: Update package database".to_string());
-
         // Check for system updates
-        actions.push("Suggested: Check for available system updates".to_string());
+        let backend = packages::backend(manager, Arc::new(LocalTransport));
+        match backend.list_upgradable().await {
+            Ok(upgradable) if upgradable.is_empty() => actions.push("All packages are up to date".to_string()),
+            Ok(upgradable) => {
+                let upgrade = packages::command_line(manager, &PackageOperation::Upgrade(Vec::new())).join(" ");
+                actions.push(format!("Suggested: sudo {} ({} package updates available)", upgrade, upgradable.len()));
+            }
+            Err(_) => actions.push("Suggested: Check for available system updates".to_string()),
+        }
 
         Ok(actions)
     }
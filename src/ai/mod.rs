@@ -4,6 +4,9 @@ use serde_json::{json, Value};
 
 use crate::config::{Config, AiProvider};
 use crate::error_diagnosis::ErrorDiagnostic;
+use crate::session;
+use crate::environment::EnvironmentKind;
+use crate::system_image::SystemImage;
 
 #[derive(Clone)]
 pub struct AiClient {
@@ -41,7 +44,7 @@ impl AiClient {
 
     pub async fn analyze_diagnostic(&self, diagnostic: &ErrorDiagnostic) -> Result<String> {
         let system_prompt = self.get_diagnostic_system_prompt();
-        let user_prompt = format!(
+        let mut user_prompt = format!(
             "Error Analysis Request:\n\
              Error: {}\n\
              Context: {}\n\
@@ -52,6 +55,27 @@ impl AiClient {
             diagnostic.system_info,
             diagnostic.relevant_logs.join("\n")
         );
+        if !diagnostic.crashes.is_empty() {
+            user_prompt.push_str("\nCrashes:");
+            for crash in &diagnostic.crashes {
+                user_prompt.push_str(&format!("\n{}", crash));
+                for (index, frame) in crash.backtrace.iter().enumerate() {
+                    user_prompt.push_str(&format!("\n  #{} {}", index, frame));
+                }
+            }
+        }
+        if !diagnostic.kernel.is_empty() {
+            user_prompt.push_str("\nKernel log:");
+            for issue in &diagnostic.kernel {
+                user_prompt.push_str(&format!("\n{}", issue));
+                if let Some(taint) = issue.taint.as_ref().filter(|taint| taint.is_tainted()) {
+                    user_prompt.push_str(&format!("\n  tainted: {}", taint));
+                }
+                for frame in &issue.call_trace {
+                    user_prompt.push_str(&format!("\n  {}", frame));
+                }
+            }
+        }
         
         self.send_request(&system_prompt, &user_prompt).await
     }
@@ -64,43 +88,64 @@ impl AiClient {
     }
 
     pub async fn analyze_system_status(&self, status: &str) -> Result<String> {
-        let system_prompt = "You are a Linux system administrator. Analyze system status and provide insights.";
+        let system_prompt = format!(
+            "You are a Linux system administrator. Analyze system status and provide insights.{}",
+            self.environment_note()
+        );
         let user_prompt = format!("Analyze this system status and provide recommendations:\n{}", status);
         
-        self.send_request(system_prompt, &user_prompt).await
+        self.send_request(&system_prompt, &user_prompt).await
     }
 
     pub async fn get_package_advice(&self, operation: &str, package: Option<&str>, suggestion: &str) -> Result<String> {
-        let system_prompt = "You are a Linux package management expert. Provide safe, accurate package management advice.";
+        let mut system_prompt = "You are a Linux package management expert. Provide safe, accurate package management advice.".to_string();
+        if self.config.system.system_image.is_immutable() {
+            system_prompt.push_str(&format!(
+                " The system is {}: /usr is read-only, so never suggest installing with the base package manager \
+                 or editing files under /usr. Prefer the suggested command, flatpak, toolbox/distrobox containers and /etc overrides.",
+                self.config.system.system_image
+            ));
+        }
         let user_prompt = format!(
             "Package operation: {}\nPackage: {:?}\nSuggested command: {}\nProvide advice and explanation.",
             operation, package, suggestion
         );
         
-        self.send_request(system_prompt, &user_prompt).await
+        self.send_request(&system_prompt, &user_prompt).await
     }
 
     pub async fn get_service_advice(&self, service: &str, action: Option<&str>, info: &str) -> Result<String> {
-        let system_prompt = "You are a Linux service management expert. Provide clear guidance for service operations.";
+        let system_prompt = format!(
+            "You are a Linux service management expert. Provide clear guidance for service operations.{}",
+            self.environment_note()
+        );
         let user_prompt = format!(
             "Service: {}\nAction: {:?}\nService Info: {}\nProvide guidance and next steps.",
             service, action, info
         );
         
-        self.send_request(system_prompt, &user_prompt).await
+        self.send_request(&system_prompt, &user_prompt).await
     }
 
     async fn send_request(&self, system_prompt: &str, user_prompt: &str) -> Result<String> {
-        if self.use_local {
-            self.send_local_request(system_prompt, user_prompt).await
+        let provider = if self.use_local {
+            "Local".to_string()
         } else {
-            match self.config.ai.provider {
-                AiProvider::OpenAI => self.send_openai_request(system_prompt, user_prompt).await,
-                AiProvider::Anthropic => self.send_anthropic_request(system_prompt, user_prompt).await,
-                AiProvider::Local => self.send_local_request(system_prompt, user_prompt).await,
-                AiProvider::Ollama => self.send_ollama_request(system_prompt, user_prompt).await,
+            format!("{:?}", self.config.ai.provider)
+        };
+
+        session::ai_exchange(&provider, system_prompt, user_prompt, async {
+            if self.use_local {
+                self.send_local_request(system_prompt, user_prompt).await
+            } else {
+                match self.config.ai.provider {
+                    AiProvider::OpenAI => self.send_openai_request(system_prompt, user_prompt).await,
+                    AiProvider::Anthropic => self.send_anthropic_request(system_prompt, user_prompt).await,
+                    AiProvider::Local => self.send_local_request(system_prompt, user_prompt).await,
+                    AiProvider::Ollama => self.send_ollama_request(system_prompt, user_prompt).await,
+                }
             }
-        }
+        }).await
     }
 
     async fn send_openai_request(&self, system_prompt: &str, user_prompt: &str) -> Result<String> {
@@ -237,20 +282,68 @@ impl AiClient {
             _ => "Du läufst mit Cloud-AI",
         };
 
+        let image_info = match self.config.system.system_image {
+            SystemImage::Mutable => String::new(),
+            ref image => format!(
+                " Das System ist unveränderlich ({}): /usr ist schreibgeschützt, schlage keine Paketinstallation mit dem \
+                 Basis-Paketmanager und keine Änderungen unter /usr vor, sondern rpm-ostree/transactional-update, \
+                 flatpak, toolbox/distrobox oder Overrides in /etc.",
+                image
+            ),
+        };
+
+        let environment = &self.config.system.environment;
+        let mut environment_info = match environment.kind {
+            EnvironmentKind::BareMetal => String::new(),
+            EnvironmentKind::Container => format!(
+                " Das System läuft in einem Container ({}): Kernel, Bootloader und Hardware gehören dem Host, schlage \
+                 kein modprobe, keine sysctl-Änderungen, keine Bootloader- oder Initramfs-Änderungen und keine \
+                 Festplatten- oder Sensor-Tools vor, sondern verweise auf den Host.",
+                environment
+            ),
+            EnvironmentKind::Wsl => format!(
+                " Das System läuft unter {}: Der Kernel kommt von Windows, Kernel-Optionen gehören in \
+                 %UserProfile%\\.wslconfig, und es gibt weder Bootloader noch physische Hardware.",
+                environment
+            ),
+            EnvironmentKind::VirtualMachine => format!(
+                " Das System ist eine virtuelle Maschine ({}): Festplatten und Sensoren sind virtuell, \
+                 Hardwareprobleme müssen auf dem Host geprüft werden.",
+                environment
+            ),
+        };
+        if !environment.service_manager_running {
+            environment_info.push_str(
+                " Es läuft kein Service-Manager als PID 1: Schlage kein systemctl, service oder rc-service vor, \
+                 sondern starte Programme direkt.");
+        }
+
         format!(
             "Du bist TuxPilot, ein AI-Assistent spezialisiert auf Linux-Systemadministration und Fehlerbehebung. \
-             {} Du läufst auf einem Linux-System mit {} Paketmanager und {} Service-Manager. \
+             {} Du läufst auf einem Linux-System mit {:?} Paketmanager und {:?} Service-Manager.{}{} \
              Gib praktische, genaue und sichere Ratschläge. Erkläre Befehle immer bevor du sie vorschlägst. \
              Fokussiere auf Arch Linux Best Practices wenn anwendbar. Antworte auf Deutsch.",
             ai_info,
-            format!("{:?}", self.config.system.package_manager),
-            format!("{:?}", self.config.system.service_manager)
+            self.config.system.package_manager,
+            self.config.system.service_manager,
+            image_info,
+            environment_info
         )
     }
 
+    /// Sentence for English system prompts about what cannot work in this environment
+    fn environment_note(&self) -> String {
+        self.config.system.environment.prompt_note()
+            .map(|note| format!(" {}", note))
+            .unwrap_or_default()
+    }
+
     fn get_diagnostic_system_prompt(&self) -> String {
-        "You are a Linux system diagnostic expert. Analyze errors, logs, and system information to provide \
-         clear explanations of problems and step-by-step solutions. Always prioritize system safety and \
-         provide multiple solution options when possible.".to_string()
+        format!(
+            "You are a Linux system diagnostic expert. Analyze errors, logs, and system information to provide \
+             clear explanations of problems and step-by-step solutions. Always prioritize system safety and \
+             provide multiple solution options when possible.{}",
+            self.environment_note()
+        )
     }
 }
@@ -2,14 +2,45 @@ use anyhow::Result;
 use console::{style, Term};
 use dialoguer::{Input, Confirm};
 use indicatif::{ProgressBar, ProgressStyle};
+use std::sync::Arc;
 
 
+use crate::accounts::{Account, AccountChange, AccountPlan, AccountStep, PasswordState};
 use crate::ai::AiClient;
-use crate::config::Config;
+use crate::config::{Config, PackageManager};
 use crate::error_diagnosis::ErrorDiagnostic;
+use crate::firewall::{self, ChangeAction, FirewallChange, FirewallPlan};
+use crate::journal::{parse_priority, JournalQuery, JournalReader, LogRecord};
+use crate::linux_integration::boot::{self, BootHistory};
+use crate::linux_integration::kernel::{KernelChange, KernelStep, ModuleAction};
 use crate::linux_integration::LinuxIntegration;
+use crate::network::{NetworkChange, NetworkPlan, NetworkStep};
+use crate::packages::{self, PackageOperation, Transaction};
+use crate::services::ServiceAction;
+use crate::remote::inventory::Inventory;
+use crate::remote::transport::{SshTransport, Transport};
+use crate::schedule::{Job, NewTimer, SchedulePlan, ScheduleStep};
+use crate::session::SessionArchive;
+use crate::system_image::{self, PendingActivation};
 use crate::system_monitor::SystemMonitor;
-use crate::Commands;
+use crate::{ApprovalAction, AuditAction, AuditFilterArgs, Commands, FirewallAction, FirewallChangeArgs, KernelAction, NetworkAction, ScheduleAction, SessionAction, TargetArgs, UsersAction};
+
+/// Result of running against one host: a short detail line, or the error
+type HostOutcome = (String, std::result::Result<String, String>);
+
+/// Log lines read for diagnosis; older lines rarely matter and on remote hosts they
+/// would all go over SSH
+const MAX_LOG_LINES: usize = 5000;
+
+/// What `diagnose` looks at
+enum DiagnosisInput {
+    /// An error message, or the path of a log file on the diagnosed host
+    Text(String),
+    /// A log piped in on stdin
+    Stdin(String),
+    /// The system's own logs
+    SystemLogs,
+}
 
 pub struct TuxPilotCli {
     config: Config,
@@ -22,10 +53,12 @@ pub struct TuxPilotCli {
 impl TuxPilotCli {
     pub async fn new(mut config: Config, use_local: bool) -> Result<Self> {
         // Auto-detect system configuration
-        config.detect_system()?;
-        
-        let ai_client = AiClient::new(&config, use_local).await?;
+        config.detect_system().await?;
         let linux_integration = LinuxIntegration::new(&config).await?;
+        config.system.system_image = linux_integration.system_image();
+        config.system.environment = linux_integration.environment();
+
+        let ai_client = AiClient::new(&config, use_local).await?;
         let system_monitor = SystemMonitor::new(&config)?;
         let term = Term::stdout();
 
@@ -40,32 +73,92 @@ impl TuxPilotCli {
 
     pub async fn handle_command(&mut self, command: Commands) -> Result<()> {
         match command {
-            Commands::Diagnose { input, auto } => {
-                self.handle_diagnose(input, auto).await?;
+            Commands::Diagnose { input, auto, targets } => {
+                self.handle_diagnose(input, auto, &targets).await?;
             }
             Commands::Explain { command } => {
                 self.handle_help(command).await?;
             }
-            Commands::Monitor { continuous } => {
-                self.handle_monitor(continuous).await?;
+            Commands::Monitor { continuous, targets } => {
+                self.handle_monitor(continuous, &targets).await?;
+            }
+            Commands::Logs { units, priority, boot, pid, since, until, lines, follow, json } => {
+                let query = JournalQuery {
+                    units,
+                    identifiers: Vec::new(),
+                    transports: Vec::new(),
+                    priority: priority.as_deref().map(parse_priority).transpose()?,
+                    boot,
+                    pid,
+                    since,
+                    until,
+                    lines: Some(lines),
+                    after_cursor: None,
+                };
+                self.show_logs(query, follow, json).await?;
             }
-            Commands::Package { operation, package } => {
-                self.handle_package(operation, package).await?;
+            Commands::Package { operation, package, source, targets } => {
+                self.handle_package(operation, package, source.as_deref(), &targets).await?;
             }
             Commands::Service { name, action } => {
                 self.handle_service(name, action).await?;
             }
+            Commands::Firewall { action, json, host } => {
+                self.handle_firewall(action, json, host.as_deref()).await?;
+            }
+            Commands::Kernel { action } => {
+                self.handle_kernel(action).await?;
+            }
+            Commands::Boot { apply, history, json, mode } => {
+                self.handle_boot(apply, history, json, &mode).await?;
+            }
+            Commands::Storage { json, host } => {
+                self.handle_storage(json, host.as_deref()).await?;
+            }
+            Commands::Network { action, json, host } => {
+                self.handle_network(action, json, host.as_deref()).await?;
+            }
+            Commands::Schedule { action, at, hours, json, host } => {
+                self.handle_schedule(action, at.as_deref(), hours, json, host.as_deref()).await?;
+            }
+            Commands::Users { action, user, all, json, host } => {
+                self.handle_users(action, user.as_deref(), all, json, host.as_deref()).await?;
+            }
+            Commands::Crashes { hours, json, host } => {
+                self.handle_crashes(hours, json, host.as_deref()).await?;
+            }
+            Commands::Mac { hours, apply, json, mode, host } => {
+                self.handle_mac(hours, apply, json, &mode, host.as_deref()).await?;
+            }
             Commands::Chat { execute_mode } => {
                 self.interactive_mode_with_execution(&execute_mode).await?;
             }
-            Commands::Execute { description, mode } => {
-                self.handle_execute_command(&description, &mode).await?;
+            Commands::Execute { description, mode, command, targets } => {
+                self.handle_execute_command(&description, &mode, command.as_deref(), &targets).await?;
+            }
+            Commands::Permissions { detailed, grant, minutes, revoke } => {
+                if let Some(scope) = grant {
+                    self.grant_approval(&scope, minutes).await?;
+                } else if let Some(scope) = revoke {
+                    self.revoke_approval(&scope).await?;
+                } else {
+                    self.show_permissions(detailed).await?;
+                }
+            }
+            Commands::Audit { limit, page, export, filters, action } => {
+                match action {
+                    Some(AuditAction::Verify) => self.verify_audit_log().await?,
+                    Some(AuditAction::TestSinks) => self.test_audit_sinks().await?,
+                    None => self.show_audit_log(limit, page, &filters, export.as_deref()).await?,
+                }
             }
-            Commands::Permissions { detailed } => {
-                self.show_permissions(detailed).await?;
+            Commands::Approvals { status, action } => {
+                self.handle_approvals(&status, action).await?;
             }
-            Commands::Audit { limit, export } => {
-                self.show_audit_log(limit, export.as_deref()).await?;
+            Commands::Session { action } => {
+                match action {
+                    SessionAction::Show { archive } => self.show_session(&archive)?,
+                }
             }
             Commands::Config { show, set } => {
                 self.handle_config(show, set).await?;
@@ -73,6 +166,12 @@ impl TuxPilotCli {
             Commands::Web { port, bind, ssl, ssl_cert, ssl_key } => {
                 self.handle_web_server(port, bind, ssl, ssl_cert, ssl_key).await?;
             }
+            Commands::PrivilegedHelper => {
+                crate::execution::elevation::run_helper(false).await?;
+            }
+            Commands::PrivilegedGrant => {
+                crate::execution::elevation::run_helper(true).await?;
+            }
         }
         Ok(())
     }
@@ -129,94 +228,1499 @@ impl TuxPilotCli {
         Ok(())
     }
 
-    async fn handle_diagnose(&mut self, input: Option<String>, auto: bool) -> Result<()> {
-        if auto {
-            self.term.write_line(&format!("{}", style("🔍 Analyzing system logs...").yellow()))?;
-            let diagnostic = ErrorDiagnostic::analyze_system_logs(&self.linux_integration).await?;
-            let analysis = self.ai_client.analyze_diagnostic(&diagnostic).await?;
-            self.display_analysis(&analysis)?;
-        } else if let Some(error_input) = input {
-            self.term.write_line(&format!("{}", style("🔍 Analyzing error...").yellow()))?;
-            let diagnostic = ErrorDiagnostic::analyze_error(&error_input, &self.linux_integration).await?;
-            let analysis = self.ai_client.analyze_diagnostic(&diagnostic).await?;
-            self.display_analysis(&analysis)?;
-        } else {
-            let error_input: String = Input::new()
-                .with_prompt("Enter the error message or describe the problem")
-                .interact_text()?;
-            
-            let diagnostic = ErrorDiagnostic::analyze_error(&error_input, &self.linux_integration).await?;
-            let analysis = self.ai_client.analyze_diagnostic(&diagnostic).await?;
-            self.display_analysis(&analysis)?;
-        }
+    async fn handle_diagnose(&mut self, input: Option<String>, auto: bool, targets: &TargetArgs) -> Result<()> {
+        use std::io::{IsTerminal, Read};
+
+        let input = match (auto, input) {
+            (true, _) => DiagnosisInput::SystemLogs,
+            (false, Some(input)) if input != "-" => DiagnosisInput::Text(input),
+            (false, None) if std::io::stdin().is_terminal() => DiagnosisInput::Text(Input::new()
+                .with_prompt("Enter the error message or describe the problem")
+                .interact_text()?),
+            // "-", or a log piped in
+            (false, _) => {
+                let mut content = String::new();
+                std::io::stdin().read_to_string(&mut content)?;
+                let skip = content.lines().count().saturating_sub(MAX_LOG_LINES);
+                DiagnosisInput::Stdin(content.lines().skip(skip).collect::<Vec<_>>().join("\n"))
+            }
+        };
+
+        if targets.is_local() {
+            let integration = self.linux_integration.clone();
+            return self.diagnose_on(&integration, &input).await;
+        }
+
+        let mut outcomes = Vec::new();
+        for (host, connection) in self.connect_targets(targets).await? {
+            self.write_host_header(&host)?;
+            let result = match connection {
+                Ok(integration) => self.diagnose_on(&integration, &input).await,
+                Err(e) => Err(e),
+            };
+            outcomes.push(self.host_outcome(host, result.map(|_| "diagnosed".to_string()))?);
+        }
+
+        self.display_host_summary(&outcomes)
+    }
+
+    async fn diagnose_on(&mut self, integration: &LinuxIntegration, input: &DiagnosisInput) -> Result<()> {
+        let diagnostic = match input {
+            DiagnosisInput::Stdin(content) => self.diagnose_log(integration, "stdin", content).await?,
+            // A path to a log file on the host being diagnosed
+            DiagnosisInput::Text(text) if !text.contains('\n') && integration.transport().path_exists(text).await => {
+                let content = integration.execute_command("tail", &["-n", &MAX_LOG_LINES.to_string(), text]).await?;
+                self.diagnose_log(integration, text, &content).await?
+            }
+            DiagnosisInput::Text(error_input) => {
+                self.term.write_line(&format!("{}", style("🔍 Analyzing error...").yellow()))?;
+                ErrorDiagnostic::analyze_error(error_input, integration).await?
+            }
+            DiagnosisInput::SystemLogs => {
+                self.term.write_line(&format!("{}", style("🔍 Analyzing system logs...").yellow()))?;
+                ErrorDiagnostic::analyze_system_logs(integration).await?
+            }
+        };
+
+        let analysis = self.ai_client.analyze_diagnostic(&diagnostic).await?;
+        self.display_analysis(&analysis)
+    }
+
+    /// Parse a log, show the error clusters found in it and build a diagnostic from them
+    async fn diagnose_log(&self, integration: &LinuxIntegration, origin: &str, content: &str) -> Result<ErrorDiagnostic> {
+        use crate::error_diagnosis::log_formats;
+
+        let log = log_formats::parse(content);
+        let clusters = log_formats::error_clusters(&log);
+        self.term.write_line(&format!("{} {}: {}, {} lines, {} error clusters",
+            style("📄").yellow(), origin, log.format, log.lines.len(), clusters.len()))?;
+        for cluster in &clusters {
+            if let Some(first) = cluster.errors.first() {
+                self.term.write_line(&format!("  {} {}: {}", style("⚠️").red(), cluster.describe(), first.message))?;
+            }
+        }
+
+        self.term.write_line(&format!("{}", style("🔍 Analyzing log...").yellow()))?;
+        ErrorDiagnostic::analyze_log(origin, &log, &clusters, integration).await
+    }
+
+    async fn handle_help(&mut self, command: Option<String>) -> Result<()> {
+        if let Some(cmd) = command {
+            let help_text = self.ai_client.get_command_help(&cmd).await?;
+            self.term.write_line(&format!("{} {}", style("📖 Help for").blue(), style(&cmd).bold()))?;
+            self.term.write_line(&help_text)?;
+        } else {
+            let _ = self.print_help();
+        }
+        Ok(())
+    }
+
+    async fn handle_monitor(&mut self, continuous: bool, targets: &TargetArgs) -> Result<()> {
+        if !targets.is_local() {
+            if continuous {
+                return Err(anyhow::anyhow!("Continuous monitoring only runs locally; drop --host/--group"));
+            }
+
+            let mut outcomes = Vec::new();
+            for (host, connection) in self.connect_targets(targets).await? {
+                self.write_host_header(&host)?;
+                let result = match connection {
+                    Ok(integration) => self.monitor_remote(&integration).await,
+                    Err(e) => Err(e),
+                };
+                outcomes.push(self.host_outcome(host, result)?);
+            }
+            return self.display_host_summary(&outcomes);
+        }
+
+        if continuous {
+            self.term.write_line(&format!("{}", style("📊 Starting continuous monitoring... (Press Ctrl+C to stop)").green()))?;
+            self.system_monitor.start_continuous_monitoring().await?;
+        } else {
+            let status = self.system_monitor.get_system_status().await?;
+            let analysis = self.ai_client.analyze_system_status(&status).await?;
+            self.display_system_status(&status, &analysis)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the status summary line used in the per-host results
+    async fn monitor_remote(&mut self, integration: &LinuxIntegration) -> Result<String> {
+        let status = SystemMonitor::get_remote_status(integration).await?;
+        let analysis = self.ai_client.analyze_system_status(&status).await?;
+        self.display_system_status(&status, &analysis)?;
+
+        Ok(status.lines()
+            .filter(|line| line.starts_with("CPU") || line.starts_with("Memory") || line.starts_with("Load"))
+            .collect::<Vec<_>>()
+            .join(", "))
+    }
+
+    async fn handle_package(&mut self, operation: String, package: Option<String>, source: Option<&str>, targets: &TargetArgs) -> Result<()> {
+        let source = source.map(str::parse::<PackageManager>).transpose()?;
+        if let Some(package) = &package {
+            packages::check_operands(package)?;
+        }
+        if !targets.is_local() {
+            return self.handle_remote_package(&operation, package.as_deref(), targets).await;
+        }
+
+        // Queries change nothing, so show their results straight away
+        if let Ok(query) = PackageOperation::parse(&operation, package.as_deref()) {
+            if query.is_query() {
+                self.term.write_line(&format!("{}", style("📦 Package Management").blue().bold()))?;
+                return self.run_package_operation(&query, source.as_ref()).await;
+            }
+        }
+
+        let suggestion = self.linux_integration.get_package_suggestion(&operation, package.as_deref()).await?;
+
+        // Check if we're in test mode
+        let is_test_mode = std::env::var("TUXPILOT_TEST_MODE").is_ok();
+
+        // Try to get AI advice with timeout handling (skip in test mode)
+        let ai_advice = if is_test_mode {
+            format!("Test mode: {}", suggestion)
+        } else {
+            match self.ai_client.get_package_advice(&operation, package.as_deref(), &suggestion).await {
+                Ok(advice) => advice,
+                Err(e) => {
+                    eprintln!("AI advice failed: {}", e);
+                    format!("Basic suggestion: {}", suggestion)
+                }
+            }
+        };
+
+        self.term.write_line(&format!("{}", style("📦 Package Management").blue().bold()))?;
+        self.term.write_line(&ai_advice)?;
+
+        // Show what a change would do before asking to run it
+        let transaction = match PackageOperation::parse(&operation, package.as_deref()) {
+            Ok(parsed) => self.plan_transaction(&parsed).await?,
+            Err(_) => None,
+        };
+        if let Some(transaction) = &transaction {
+            self.write_transaction(transaction)?;
+        }
+
+        // Skip interactive prompt in test mode
+        if !is_test_mode {
+            if Confirm::new()
+                .with_prompt("Would you like to execute the suggested command?")
+                .interact()?
+            {
+                match &transaction {
+                    Some(transaction) => self.run_transaction(transaction).await?,
+                    None => {
+                        let operation = PackageOperation::parse(&operation, package.as_deref())?;
+                        self.run_package_operation(&operation, source.as_ref()).await?;
+                    }
+                }
+            }
+        }
+        
+        Ok(())
+    }
+
+    /// Plan an install, removal or upgrade with the system package manager; `None` for
+    /// other operations and for installs or removals that name no package
+    async fn plan_transaction(&self, operation: &PackageOperation) -> Result<Option<Transaction>> {
+        let backend = self.linux_integration.package_backend();
+        Ok(Some(match operation {
+            PackageOperation::Install(packages) if !packages.is_empty() => backend.install(packages).await?,
+            PackageOperation::Remove(packages) if !packages.is_empty() => backend.remove(packages).await?,
+            PackageOperation::Upgrade(packages) => backend.upgrade(packages).await?,
+            _ => return Ok(None),
+        }))
+    }
+
+    fn write_transaction(&self, transaction: &Transaction) -> Result<()> {
+        for change in &transaction.changes {
+            self.term.write_line(&format!("  {}", change))?;
+        }
+        self.term.write_line(&format!("Command: {}", style(transaction.command_line()).cyan()))?;
+        Ok(())
+    }
+
+    async fn run_transaction(&mut self, transaction: &Transaction) -> Result<()> {
+        let output = self.linux_integration.execute_transaction(transaction).await?;
+        self.term.write_line(&output)?;
+        if let Some((program, args)) = transaction.command.split_first() {
+            if let Some(pending) = system_image::pending_activation(program, args) {
+                self.write_pending_activation(&pending)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Show query results from every package source, or from `source` alone, or
+    /// preview and run a change with the system package manager
+    async fn run_package_operation(&mut self, operation: &PackageOperation, source: Option<&PackageManager>) -> Result<()> {
+        if !operation.is_query() && source.is_some() {
+            return Err(anyhow::anyhow!("--source only applies to search, info, list and upgradable"));
+        }
+        let sources = self.linux_integration.package_sources(source);
+
+        let packages = match operation {
+            PackageOperation::Search(term) => sources.search(term).await?,
+            PackageOperation::Info(name) => match sources.info(name).await? {
+                Some(package) => vec![package],
+                None => return Err(anyhow::anyhow!("Package '{}' not found", name)),
+            },
+            PackageOperation::ListInstalled => sources.list_installed().await?,
+            PackageOperation::ListUpgradable => sources.list_upgradable().await?,
+            PackageOperation::Install(_) | PackageOperation::Remove(_) | PackageOperation::Upgrade(_) => {
+                let transaction = self.plan_transaction(operation).await?
+                    .ok_or_else(|| anyhow::anyhow!("Name the packages to install or remove"))?;
+                self.write_transaction(&transaction)?;
+                return self.run_transaction(&transaction).await;
+            }
+            PackageOperation::Refresh | PackageOperation::Clean => {
+                let output = self.linux_integration.execute_package_command(operation).await?;
+                self.term.write_line(&output)?;
+                return Ok(());
+            }
+        };
+
+        if packages.is_empty() {
+            self.term.write_line("No matching packages")?;
+        }
+        for package in &packages {
+            self.term.write_line(&format!("  {}", package))?;
+        }
+        Ok(())
+    }
+
+    /// Show each host's package command and, once confirmed, run it through the
+    /// executor so safety checks and auditing happen here on the controller
+    async fn handle_remote_package(&mut self, operation: &str, package: Option<&str>, targets: &TargetArgs) -> Result<()> {
+        self.term.write_line(&format!("{}", style("📦 Package Management").blue().bold()))?;
+
+        let mut planned = Vec::new();
+        let mut outcomes = Vec::new();
+        for (host, connection) in self.connect_targets(targets).await? {
+            match connection {
+                Ok(integration) => {
+                    let suggestion = integration.get_package_suggestion(operation, package).await?;
+                    self.term.write_line(&format!("🖥️  {} ({}): {}",
+                        style(&host).bold(), integration.config.system.package_manager, suggestion))?;
+                    planned.push((host, suggestion));
+                }
+                Err(e) => outcomes.push(self.host_outcome(host, Err(e))?),
+            }
+        }
+
+        let is_test_mode = std::env::var("TUXPILOT_TEST_MODE").is_ok();
+        if !planned.is_empty() && !is_test_mode && Confirm::new()
+            .with_prompt(format!("Run the suggested command on {} host(s)?", planned.len()))
+            .interact()?
+        {
+            let mut executor = crate::execution::CommandExecutor::new(
+                self.config.clone(),
+                crate::execution::ExecutionMode::Autonomous,
+            ).await?;
+
+            let description = format!("Package {} {}", operation, package.unwrap_or_default());
+            for (host, suggestion) in planned {
+                self.write_host_header(&host)?;
+                let result = self.run_on_host(&mut executor, description.trim(), &suggestion, Some(&host)).await;
+                outcomes.push(self.host_outcome(host, result)?);
+            }
+        }
+
+        if !outcomes.is_empty() {
+            self.display_host_summary(&outcomes)?;
+        }
+        Ok(())
+    }
+
+    async fn handle_service(&mut self, name: String, action: Option<String>) -> Result<()> {
+        let backend = self.linux_integration.service_backend();
+        let status = backend.status(&name).await?;
+
+        self.term.write_line(&format!("{}", style("⚙️  Service Management").blue().bold()))?;
+        match &status {
+            Some(status) => self.term.write_line(&status.to_string())?,
+            None => self.term.write_line(&format!("Service '{}' not found ({})", name, backend.manager()))?,
+        }
+        if let Some(service_action) = action.as_deref().and_then(|a| a.parse::<ServiceAction>().ok()) {
+            let mut command = backend.command(&name, &service_action);
+            if service_action.needs_root() {
+                command.insert(0, "sudo".to_string());
+            }
+            self.term.write_line(&format!("Command: {}", style(command.join(" ")).cyan()))?;
+        }
+        self.term.write_line("")?;
+
+        let service_info = self.linux_integration.get_service_info(&name).await?;
+        let ai_advice = self.ai_client.get_service_advice(&name, action.as_deref(), &service_info).await?;
+        self.term.write_line(&ai_advice)?;
+        
+        Ok(())
+    }
+
+    async fn handle_firewall(&mut self, action: Option<FirewallAction>, json: bool, host: Option<&str>) -> Result<()> {
+        let (action, change, args) = match action {
+            None => (None, None, None),
+            Some(FirewallAction::Keep { id }) => return self.keep_firewall_change(&id, host).await,
+            Some(FirewallAction::Open { port, from, comment, change }) => (Some(ChangeAction::Open), Some((port, from, comment)), Some(change)),
+            Some(FirewallAction::Close { port, from, change }) => (Some(ChangeAction::Close), Some((port, from, None)), Some(change)),
+        };
+
+        let remote;
+        let integration = match host {
+            Some(host) => {
+                remote = self.connect_host(host).await?;
+                &remote
+            }
+            None => &self.linux_integration,
+        };
+
+        let Some(backend) = integration.firewall().await else {
+            if action.is_some() {
+                return Err(anyhow::anyhow!("No active firewall found (checked firewalld, ufw, nftables and iptables)"));
+            }
+            self.term.write_line(&format!("{}", style("🛡️  Firewall").blue().bold()))?;
+            self.term.write_line("No active firewall found (checked firewalld, ufw, nftables and iptables); every listening port is reachable")?;
+            return Ok(());
+        };
+        let ruleset = backend.ruleset().await?;
+
+        let (Some(action), Some((port, source, comment)), Some(args)) = (action, change, args) else {
+            if json {
+                self.term.write_line(&serde_json::to_string_pretty(&ruleset)?)?;
+                return Ok(());
+            }
+            self.term.write_line(&format!("{}", style(format!("🛡️  Firewall: {}", ruleset.frontend)).blue().bold()))?;
+            let open = ruleset.open_ports();
+            self.term.write_line("Open ports:")?;
+            if open.is_empty() {
+                self.term.write_line("  none")?;
+            }
+            for port in &open {
+                self.term.write_line(&format!("  {}", port))?;
+            }
+            self.term.write_line("")?;
+            for line in ruleset.explain() {
+                self.term.write_line(&format!("• {}", line))?;
+            }
+            return Ok(());
+        };
+
+        let change = FirewallChange { action, port: port.parse()?, source, comment };
+        let plan = backend.plan(&ruleset, &change)?;
+        self.write_firewall_preview(&ruleset, &plan)?;
+        if args.dry_run {
+            return Ok(());
+        }
+        self.apply_firewall_plan(&plan, &args, host).await
+    }
+
+    fn write_firewall_preview(&self, before: &firewall::Ruleset, plan: &FirewallPlan) -> Result<()> {
+        self.term.write_line(&format!("{}", style(format!("🛡️  {} with {}", plan.change, before.frontend)).blue().bold()))?;
+
+        // Changed lines, each under the zone or chain it belongs to
+        self.term.write_line("Preview:")?;
+        let mut header: Option<String> = None;
+        for line in firewall::diff_lines(&before.lines(), &plan.after.lines()) {
+            let (marker, text) = line.split_at(2);
+            if marker == "  " {
+                if !text.starts_with(' ') {
+                    header = Some(line.clone());
+                }
+                continue;
+            }
+            if let Some(header) = header.take() {
+                self.term.write_line(&format!("  {}", header))?;
+            }
+            let line = if marker == "+ " { style(line.clone()).green() } else { style(line.clone()).red() };
+            self.term.write_line(&format!("  {}", line))?;
+        }
+
+        self.term.write_line("Commands:")?;
+        for command in &plan.commands {
+            self.term.write_line(&format!("  {}", style(command.join(" ")).cyan()))?;
+        }
+        self.term.write_line("Revert with:")?;
+        for command in &plan.revert {
+            self.term.write_line(&format!("  {}", command))?;
+        }
+        if !plan.persistent {
+            self.term.write_line(&format!("Note: {} changes only last until the next reboot unless the ruleset is saved", before.frontend))?;
+        }
+        self.term.write_line("")?;
+        Ok(())
+    }
+
+    /// Run a plan through the executor. Over SSH a timer on the target reverts the
+    /// change unless it is kept, so a rule that locks the session out undoes itself.
+    async fn apply_firewall_plan(&self, plan: &FirewallPlan, args: &FirewallChangeArgs, host: Option<&str>) -> Result<()> {
+        use crate::execution::{Permission, RiskLevel};
+        use std::io::IsTerminal;
+
+        let mut executor = crate::execution::CommandExecutor::new(self.config.clone(), parse_execution_mode(&args.mode)?).await?;
+        let over_ssh = host.is_some() || std::env::var_os("SSH_CONNECTION").is_some();
+        let timer = (over_ssh && args.revert_after > 0)
+            .then(|| uuid::Uuid::new_v4().simple().to_string()[..8].to_string());
+
+        // Armed first, so it also fires when applying the change cuts the connection
+        if let Some(id) = &timer {
+            let keep = keep_file(id);
+            // The marker directory is made by root here, so only root can keep the change
+            let script = format!("exec >/dev/null 2>&1; mkdir -p -m 0755 {}; sleep {}; [ -e {} ] || {{ {}; }}; rm -f {}",
+                KEEP_DIR, args.revert_after, keep, plan.revert.join("; "), keep);
+            let command = vec!["-f".to_string(), "sh".to_string(), "-c".to_string(), script];
+            let mut request = executor.plan_args(&format!("Revert \"{}\" in {}s unless kept", plan.change, args.revert_after),
+                                                 "setsid".to_string(), command, host).await?;
+            // This only asks for root: the helper runs a shell solely under an approval
+            // for this one request, whatever permissions the request lists
+            request.required_permissions = vec![Permission::WriteSystem];
+            if request.risk_level < RiskLevel::Medium {
+                request.risk_level = RiskLevel::Medium;
+            }
+            self.run_request(&mut executor, request).await?;
+        }
+
+        for command in &plan.commands {
+            let (program, args) = command.split_first().ok_or_else(|| anyhow::anyhow!("Empty command"))?;
+            let request = executor.plan_args(&plan.change.to_string(), program.clone(), args.to_vec(), host).await?;
+            self.run_request(&mut executor, request).await?;
+        }
+        self.term.write_line(&format!("✅ {}", plan.change))?;
+
+        let Some(id) = timer else {
+            return Ok(());
+        };
+        let keep_command = match host {
+            Some(host) => format!("tuxpilot firewall keep {} --host {}", id, host),
+            None => format!("tuxpilot firewall keep {}", id),
+        };
+        let confirmed = if std::io::stdin().is_terminal() || crate::session::is_replaying() {
+            let prompt = "Can you still open a new SSH connection? Keep the change?";
+            crate::session::decision(prompt, async {
+                Ok(Confirm::new().with_prompt(prompt).default(false).interact()? as usize)
+            }).await? == 1
+        } else {
+            false
+        };
+
+        if confirmed {
+            self.keep_firewall_change(&id, host).await
+        } else {
+            self.term.write_line(&format!("⏳ The change is reverted in {} seconds unless you run `{}`",
+                args.revert_after, style(keep_command).bold()))?;
+            Ok(())
+        }
+    }
+
+    /// Stop the revert timer of change `id`
+    async fn keep_firewall_change(&self, id: &str, host: Option<&str>) -> Result<()> {
+        use crate::execution::ExecutionMode;
+
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(anyhow::anyhow!("Invalid firewall change ID '{}'", id));
+        }
+        let mut executor = crate::execution::CommandExecutor::new(self.config.clone(), ExecutionMode::Supervised).await?;
+        let request = executor.plan_args(&format!("Keep firewall change {}", id), "touch".to_string(),
+                                         vec![keep_file(id)], host).await?;
+        self.run_request(&mut executor, request).await?;
+        self.term.write_line(&format!("✅ Firewall change {} kept", id))?;
+        Ok(())
+    }
+
+    async fn handle_kernel(&mut self, action: KernelAction) -> Result<()> {
+        let kernel = self.linux_integration.kernel();
+
+        match action {
+            KernelAction::Sysctl { key, set, runtime_only, reset, json, mode } => {
+                let change = match (&key, set) {
+                    (Some(key), Some(value)) => Some(kernel.plan_sysctl(key, &value, runtime_only).await?),
+                    (Some(key), None) if reset => Some(kernel.plan_sysctl_reset(key).await?),
+                    _ => None,
+                };
+                if let Some(change) = change {
+                    return self.apply_kernel_change(&change, &mode).await;
+                }
+
+                let settings = match &key {
+                    Some(key) => vec![kernel.sysctl(key).await?],
+                    None => kernel.sysctl_settings().await?,
+                };
+                if json {
+                    self.term.write_line(&serde_json::to_string_pretty(&settings)?)?;
+                    return Ok(());
+                }
+
+                self.term.write_line(&format!("{}", style("🧬 Kernel Parameters").blue().bold()))?;
+                if settings.is_empty() {
+                    self.term.write_line("No kernel parameters are set in sysctl.d or /etc/sysctl.conf")?;
+                }
+                for setting in &settings {
+                    if setting.in_sync() {
+                        self.term.write_line(&format!("  {}", setting))?;
+                    } else {
+                        self.term.write_line(&format!("  {} {}", style("⚠️").yellow(), setting))?;
+                    }
+                    if key.is_some() {
+                        for assignment in &setting.overridden {
+                            self.term.write_line(&format!("    overrides {} from {}:{}",
+                                assignment.value, assignment.file, assignment.line))?;
+                        }
+                    }
+                }
+                let drifted = settings.iter().filter(|setting| !setting.in_sync()).count();
+                if drifted > 0 {
+                    self.term.write_line(&format!("\n{} parameter(s) differ from the value applied at boot", drifted))?;
+                }
+            }
+            KernelAction::Modules { load, unload, blacklist, unblacklist, autoload, no_autoload, json, mode } => {
+                let requested = [
+                    (ModuleAction::Load, load),
+                    (ModuleAction::Unload, unload),
+                    (ModuleAction::Blacklist, blacklist),
+                    (ModuleAction::Unblacklist, unblacklist),
+                    (ModuleAction::Autoload, autoload),
+                    (ModuleAction::NoAutoload, no_autoload),
+                ].into_iter().find_map(|(action, name)| Some((action, name?)));
+                if let Some((action, name)) = requested {
+                    let change = kernel.plan_module(action, &name).await?;
+                    return self.apply_kernel_change(&change, &mode).await;
+                }
+
+                let modules = kernel.modules().await?;
+                if json {
+                    self.term.write_line(&serde_json::to_string_pretty(&modules)?)?;
+                    return Ok(());
+                }
+
+                self.term.write_line(&format!("{}", style("🧬 Kernel Modules").blue().bold()))?;
+                self.term.write_line(&format!("Loaded: {}", modules.loaded.len()))?;
+                self.term.write_line("Blacklisted:")?;
+                if modules.blacklisted.is_empty() {
+                    self.term.write_line("  none")?;
+                }
+                for (name, file) in &modules.blacklisted {
+                    let note = if modules.loaded.contains(name) { " (loaded anyway)" } else { "" };
+                    self.term.write_line(&format!("  {}{} - {}", name, note, file))?;
+                }
+                self.term.write_line("Loaded at boot:")?;
+                if modules.autoloaded.is_empty() {
+                    self.term.write_line("  none")?;
+                }
+                for (name, file) in &modules.autoloaded {
+                    self.term.write_line(&format!("  {} - {}", name, file))?;
+                }
+            }
+            KernelAction::Log { boot, json, host } => {
+                return self.handle_kernel_log(&boot, json, host.as_deref()).await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_kernel_log(&mut self, boot: &str, json: bool, host: Option<&str>) -> Result<()> {
+        let remote;
+        let integration = match host {
+            Some(host) => {
+                remote = self.connect_host(host).await?;
+                &remote
+            }
+            None => &self.linux_integration,
+        };
+        let report = integration.kernel_log().report(boot).await;
+
+        if json {
+            self.term.write_line(&serde_json::to_string_pretty(&report)?)?;
+            return Ok(());
+        }
+
+        self.term.write_line(&format!("{}", style(format!("🧬 Kernel log on {}", report.hostname)).blue().bold()))?;
+        if let Some(taint) = &report.taint {
+            self.term.write_line(&format!("Running kernel: {}", taint))?;
+        }
+        if !report.tainting_modules.is_empty() {
+            let modules: Vec<String> = report.tainting_modules.iter()
+                .map(|(name, flags)| format!("{} ({})", name, flags))
+                .collect();
+            self.term.write_line(&format!("Tainted by: {}", modules.join(", ")))?;
+        }
+        for error in &report.errors {
+            self.term.write_line(&format!("{} {}", style("⚠️").yellow(), error))?;
+        }
+
+        let source = report.source.as_deref().unwrap_or("no kernel log");
+        if report.issues.is_empty() {
+            self.term.write_line(&format!("No oopses, warnings or hardware errors in boot {} ({})", boot, source))?;
+            return Ok(());
+        }
+        self.term.write_line(&format!("{} problems in boot {} ({}):", report.issues.len(), boot, source))?;
+        for issue in &report.issues {
+            let icon = if issue.serious { style("❌").red() } else { style("⚠️").yellow() };
+            self.term.write_line(&format!("  {} {}", icon, issue))?;
+            if !issue.processes.is_empty() {
+                self.term.write_line(&format!("     process: {}", issue.processes.join(", ")))?;
+            }
+            if let Some(location) = &issue.location {
+                self.term.write_line(&format!("     at: {}", location))?;
+            }
+            if let Some(file) = &issue.module_file {
+                self.term.write_line(&format!("     module file: {}", file))?;
+            }
+            if let Some(taint) = issue.taint.as_ref().filter(|taint| taint.is_tainted()) {
+                self.term.write_line(&format!("     tainted: {}", taint))?;
+            }
+            for (index, frame) in issue.call_trace.iter().take(10).enumerate() {
+                self.term.write_line(&format!("     #{:<2} {}", index, frame))?;
+            }
+            if issue.call_trace.len() > 10 {
+                self.term.write_line(&format!("     ... {} more frames", issue.call_trace.len() - 10))?;
+            }
+            for advice in issue.advice() {
+                self.term.write_line(&format!("     {} {}", style("→").cyan(), advice))?;
+            }
+        }
+
+        // Faults already happened; hardware errors also say what is about to
+        let alerts = report.predictive_alerts();
+        for alert in alerts.iter().filter(|alert| matches!(alert.alert_type, crate::monitoring::AlertType::MaintenanceRequired)) {
+            self.term.write_line(&format!("🔮 {} ({:.0}% confidence): {}", alert.title, alert.confidence * 100.0, alert.description))?;
+        }
+        Ok(())
+    }
+
+    /// Run the steps of a kernel change through the executor, stopping at the first
+    /// that fails. Drop-in files are staged under the data directory and installed
+    /// with `install`, so every write is approved and audited like any other command.
+    async fn apply_kernel_change(&self, change: &KernelChange, mode: &str) -> Result<()> {
+        let mut executor = crate::execution::CommandExecutor::new(self.config.clone(), parse_execution_mode(mode)?).await?;
+        let staging = staging_dir()?;
+
+        self.term.write_line(&format!("{}", style(format!("🧬 {}", change.description)).blue().bold()))?;
+        for step in &change.steps {
+            self.term.write_line(&format!("  {}", step))?;
+        }
+        self.term.write_line("")?;
+
+        for step in &change.steps {
+            let (staged, program, args) = match step {
+                KernelStep::Write(drop_in) => {
+                    std::fs::create_dir_all(&staging)?;
+                    let staged = staging.join(format!("{}.conf", uuid::Uuid::new_v4()));
+                    std::fs::write(&staged, &drop_in.content)?;
+                    let args = ["-m", "0644", "-D", &staged.display().to_string(), &drop_in.path]
+                        .into_iter().map(String::from).collect();
+                    (Some(staged), "install".to_string(), args)
+                }
+                KernelStep::Run(command) => {
+                    let (program, args) = command.split_first()
+                        .ok_or_else(|| anyhow::anyhow!("Empty command"))?;
+                    (None, program.clone(), args.to_vec())
+                }
+            };
+
+            let result = async {
+                let request = executor.plan_args(&format!("{}: {}", change.description, step), program, args, None).await?;
+                self.run_request(&mut executor, request).await
+            }.await;
+            if let Some(staged) = staged {
+                let _ = std::fs::remove_file(staged);
+            }
+            result?;
+        }
+
+        self.term.write_line(&format!("✅ {}", change.description))?;
+        if let Some(undo) = &change.undo {
+            self.term.write_line(&format!("To undo: {}", style(undo).cyan()))?;
+        }
+        Ok(())
+    }
+
+    async fn handle_boot(&mut self, apply: Option<usize>, history: bool, json: bool, mode: &str) -> Result<()> {
+        let path = BootHistory::default_path()?;
+        let mut boots = BootHistory::load(&path)?;
+
+        if history {
+            if json {
+                self.term.write_line(&serde_json::to_string_pretty(&boots)?)?;
+                return Ok(());
+            }
+            self.term.write_line(&format!("{}", style("🚀 Recorded Boots").blue().bold()))?;
+            if boots.boots.is_empty() {
+                self.term.write_line("No boots recorded yet; `tuxpilot boot` records the current one")?;
+            }
+            for boot in boots.boots.iter().rev() {
+                let booted_at = boot.booted_at
+                    .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_else(|| "unknown time".to_string());
+                self.term.write_line(&format!("  {} {} {}  {}", booted_at, boot.host, &boot.boot_id, boot::format_ms(boot.times.total)))?;
+            }
+            return Ok(());
+        }
+
+        let analyzer = self.linux_integration.boot();
+        let current = analyzer.current().await?;
+        boots.record(current.clone());
+        boots.save(&path)?;
+        let regressions = boots.regressions(&current);
+        let suggestions = analyzer.suggestions(&current).await;
+
+        if let Some(number) = apply {
+            let suggestion = number.checked_sub(1).and_then(|index| suggestions.get(index))
+                .ok_or_else(|| anyhow::anyhow!("There is no suggestion {}; `tuxpilot boot` lists them", number))?;
+            let plan = suggestion.plan.as_ref()
+                .ok_or_else(|| anyhow::anyhow!("\"{}\" has to be done by hand: {}", suggestion.title, suggestion.reason))?;
+            let mut executor = crate::execution::CommandExecutor::new(self.config.clone(), parse_execution_mode(mode)?).await?;
+            for request in executor.plan_steps(plan, None).await? {
+                self.run_request(&mut executor, request).await?;
+            }
+            self.term.write_line(&format!("✅ {}", plan.description))?;
+            if let Some(rollback) = &plan.rollback {
+                self.term.write_line(&format!("To undo: {}", style(rollback).cyan()))?;
+            }
+            return Ok(());
+        }
+
+        if json {
+            let analysis = serde_json::json!({
+                "boot": current,
+                "regressions": regressions,
+                "suggestions": suggestions,
+            });
+            self.term.write_line(&serde_json::to_string_pretty(&analysis)?)?;
+            return Ok(());
+        }
+
+        self.term.write_line(&format!("{}", style(format!("🚀 Boot {}", current.boot_id)).blue().bold()))?;
+        self.term.write_line(&format!("Startup: {}", current.times))?;
+        self.term.write_line("Slowest units:")?;
+        for timing in current.blame.iter().take(10) {
+            self.term.write_line(&format!("  {:>12} {}", boot::format_ms(timing.ms), timing.unit))?;
+        }
+        if !current.critical_chain.is_empty() {
+            self.term.write_line("Critical chain:")?;
+            for link in &current.critical_chain {
+                self.term.write_line(&format!("  {}", link))?;
+            }
+        }
+
+        let earlier = match boots.earlier(&current).len() {
+            1 => "the previous boot".to_string(),
+            count => format!("the {} earlier boots", count),
+        };
+        if boots.earlier(&current).is_empty() {
+            self.term.write_line("No earlier boots recorded to compare with")?;
+        } else if regressions.is_empty() {
+            self.term.write_line(&format!("No unit got slower than in {}", earlier))?;
+        } else {
+            self.term.write_line(&format!("Regressions compared with {}:", earlier))?;
+            for regression in &regressions {
+                self.term.write_line(&format!("  {} {}", style("⚠️").yellow(), regression))?;
+            }
+            self.term.write_line(&format!("See what they did with: tuxpilot logs --boot {} --unit <unit>", current.boot_id))?;
+        }
+
+        if !suggestions.is_empty() {
+            self.term.write_line("")?;
+            self.term.write_line("Suggestions:")?;
+            for (index, suggestion) in suggestions.iter().enumerate() {
+                self.term.write_line(&format!("  {}. {}: {}", index + 1, suggestion.title, suggestion.reason))?;
+                if let Some(plan) = &suggestion.plan {
+                    for step in &plan.steps {
+                        self.term.write_line(&format!("     {}", style(step.join(" ")).cyan()))?;
+                    }
+                }
+            }
+            if suggestions.iter().any(|suggestion| suggestion.plan.is_some()) {
+                self.term.write_line("Apply one with `tuxpilot boot --apply <number>`")?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_storage(&mut self, json: bool, host: Option<&str>) -> Result<()> {
+        let remote;
+        let integration = match host {
+            Some(host) => {
+                remote = self.connect_host(host).await?;
+                &remote
+            }
+            None => &self.linux_integration,
+        };
+        let report = integration.storage().inspect().await;
+        if json {
+            self.term.write_line(&serde_json::to_string_pretty(&report)?)?;
+            return Ok(());
+        }
+
+        self.term.write_line(&format!("{}", style(format!("💽 Storage on {}", integration.host_name())).blue().bold()))?;
+        self.term.write_line("Drives:")?;
+        if report.disks.is_empty() {
+            self.term.write_line("  none found (smartctl or nvme-cli is needed to read drive health)")?;
+        }
+        for disk in &report.disks {
+            self.term.write_line(&format!("  {}", disk.summary()))?;
+        }
+        if !report.volumes.is_empty() {
+            self.term.write_line("Volumes:")?;
+            for volume in &report.volumes {
+                self.term.write_line(&format!("  {}", volume))?;
+            }
+        }
+        self.term.write_line(&format!("fstab: {} entries", report.fstab.len()))?;
+
+        self.term.write_line("")?;
+        let findings = report.sorted_findings();
+        if findings.is_empty() {
+            self.term.write_line(&format!("{}", style("✅ No storage problems found").green()))?;
+        }
+        for finding in findings {
+            let icon = match finding.severity {
+                crate::storage::Severity::Critical => style("❌").red(),
+                crate::storage::Severity::Warning => style("⚠️").yellow(),
+                crate::storage::Severity::Info => style("ℹ️").cyan(),
+            };
+            self.term.write_line(&format!("{} {}", icon, finding))?;
+        }
+
+        for alert in report.predictive_alerts() {
+            self.term.write_line(&format!("🔮 {} ({:.0}% confidence): {}", alert.title, alert.confidence * 100.0, alert.description))?;
+        }
+        Ok(())
+    }
+
+    async fn handle_network(&mut self, action: Option<NetworkAction>, json: bool, host: Option<&str>) -> Result<()> {
+        let remote;
+        let integration = match host {
+            Some(host) => {
+                remote = self.connect_host(host).await?;
+                &remote
+            }
+            None => &self.linux_integration,
+        };
+        let inspector = integration.network();
+
+        let Some(NetworkAction::Set { interface, dhcp, addresses, gateway, dns, dry_run, mode }) = action else {
+            let report = inspector.report().await;
+            if json {
+                self.term.write_line(&serde_json::to_string_pretty(&report)?)?;
+                return Ok(());
+            }
+
+            self.term.write_line(&format!("{}", style(format!("🌐 Network on {}", integration.host_name())).blue().bold()))?;
+            for interface in &report.interfaces {
+                let owner = interface.owner.map(|owner| owner.to_string()).unwrap_or_else(|| "not configured".to_string());
+                let state = match &interface.link {
+                    Some(link) => format!("{}{}", link.state, if link.addresses.is_empty() { String::new() } else { format!(", {}", link.addresses.join(" ")) }),
+                    None => "missing".to_string(),
+                };
+                self.term.write_line(&format!("{} [{}] {}", style(&interface.name).bold(), owner, state))?;
+                if let Some(config) = interface.config() {
+                    for line in config.lines().iter().skip(1) {
+                        self.term.write_line(&format!("  {}", line))?;
+                    }
+                }
+            }
+            self.term.write_line("Default routes:")?;
+            if report.default_routes.is_empty() {
+                self.term.write_line("  none")?;
+            }
+            for route in &report.default_routes {
+                self.term.write_line(&format!("  {}", route))?;
+            }
+            self.term.write_line(&format!("Nameservers: {}", if report.nameservers.is_empty() { "none".to_string() } else { report.nameservers.join(" ") }))?;
+
+            self.term.write_line("")?;
+            if report.problems.is_empty() {
+                self.term.write_line(&format!("{}", style("✅ No network problems found").green()))?;
+            }
+            for problem in &report.problems {
+                self.term.write_line(&format!("{} {}", style("⚠️").yellow(), problem))?;
+            }
+            return Ok(());
+        };
+
+        let change = NetworkChange { interface, dhcp, addresses, gateway, dns };
+        let plan = inspector.plan(&change).await?;
+        self.write_network_preview(&plan)?;
+        if dry_run {
+            return Ok(());
+        }
+        if host.is_some() && plan.steps.iter().any(|step| matches!(step, NetworkStep::Write(_))) {
+            return Err(anyhow::anyhow!("{} changes write configuration files, which is only supported on this machine", plan.stack));
+        }
+        self.apply_network_plan(&plan, &mode, host).await
+    }
+
+    fn write_network_preview(&self, plan: &NetworkPlan) -> Result<()> {
+        self.term.write_line(&format!("{}", style(format!("🌐 {} with {}", plan.change, plan.stack)).blue().bold()))?;
+        self.term.write_line("Preview:")?;
+        for line in firewall::diff_lines(&plan.before.lines(), &plan.after.lines()) {
+            let line = match &line[..2] {
+                "+ " => style(line.clone()).green(),
+                "- " => style(line.clone()).red(),
+                _ => style(line.clone()),
+            };
+            self.term.write_line(&format!("  {}", line))?;
+        }
+
+        for step in &plan.steps {
+            match step {
+                NetworkStep::Write(file) => {
+                    self.term.write_line(&format!("Write {} (mode {:o}):", file.path, file.mode))?;
+                    let before: Vec<String> = file.before.lines().map(String::from).collect();
+                    let after: Vec<String> = file.content.lines().map(String::from).collect();
+                    for line in firewall::diff_lines(&before, &after).into_iter().filter(|line| !line.starts_with("  ")) {
+                        let line = if line.starts_with('+') { style(line).green() } else { style(line).red() };
+                        self.term.write_line(&format!("  {}", line))?;
+                    }
+                }
+                NetworkStep::Run(_) => self.term.write_line(&format!("Run: {}", style(step).cyan()))?,
+            }
+        }
+        self.term.write_line(&format!("Undo with: {}", plan.undo))?;
+        if let Some(note) = &plan.note {
+            self.term.write_line(&format!("Note: {}", note))?;
+        }
+        self.term.write_line("")?;
+        Ok(())
+    }
+
+    /// Run the steps of a network plan through the executor, stopping at the first that
+    /// fails. Files are staged and installed like kernel drop-ins.
+    async fn apply_network_plan(&self, plan: &NetworkPlan, mode: &str, host: Option<&str>) -> Result<()> {
+        let mut executor = crate::execution::CommandExecutor::new(self.config.clone(), parse_execution_mode(mode)?).await?;
+        let staging = staging_dir()?;
+        if host.is_some() || std::env::var_os("SSH_CONNECTION").is_some() {
+            self.term.write_line(&format!("{}", style("⚠️  This may end the SSH session if the new configuration does not work").yellow()))?;
+        }
+
+        for step in &plan.steps {
+            let (staged, program, args) = match step {
+                NetworkStep::Write(file) => {
+                    std::fs::create_dir_all(&staging)?;
+                    let staged = staging.join(uuid::Uuid::new_v4().to_string());
+                    std::fs::write(&staged, &file.content)?;
+                    let args = ["-m", &format!("{:04o}", file.mode), "-D", &staged.display().to_string(), &file.path]
+                        .into_iter().map(String::from).collect();
+                    (Some(staged), "install".to_string(), args)
+                }
+                NetworkStep::Run(command) => {
+                    let (program, args) = command.split_first()
+                        .ok_or_else(|| anyhow::anyhow!("Empty command"))?;
+                    (None, program.clone(), args.to_vec())
+                }
+            };
+
+            let result = async {
+                let request = executor.plan_args(&format!("{}: {}", plan.change, step), program, args, host).await?;
+                self.run_request(&mut executor, request).await
+            }.await;
+            if let Some(staged) = staged {
+                let _ = std::fs::remove_file(staged);
+            }
+            result?;
+        }
+
+        self.term.write_line(&format!("✅ {}", plan.change))?;
+        self.term.write_line(&format!("To undo: {}", style(&plan.undo).cyan()))?;
+        Ok(())
+    }
+
+    async fn handle_crashes(&mut self, hours: u32, json: bool, host: Option<&str>) -> Result<()> {
+        let remote;
+        let integration = match host {
+            Some(host) => {
+                remote = self.connect_host(host).await?;
+                &remote
+            }
+            None => &self.linux_integration,
+        };
+        let since = chrono::Utc::now() - chrono::Duration::hours(hours as i64);
+        let report = integration.crashes().report(since).await;
+
+        if json {
+            self.term.write_line(&serde_json::to_string_pretty(&report)?)?;
+            return Ok(());
+        }
+
+        self.term.write_line(&format!("{}", style(format!("💥 Crashes on {}", report.hostname)).blue().bold()))?;
+        for error in &report.errors {
+            self.term.write_line(&format!("{} {}", style("⚠️").yellow(), error))?;
+        }
+        let source = report.source.as_deref().unwrap_or("no core dumps recorded");
+        if report.groups.is_empty() {
+            self.term.write_line(&format!("No crashes in the last {} hours ({})", hours, source))?;
+            return Ok(());
+        }
+        self.term.write_line(&format!("{} crashes in the last {} hours ({}), {} different:",
+            report.total, hours, source, report.groups.len()))?;
+        for group in &report.groups {
+            self.term.write_line(&format!("  {} {}", style("❌").red(), group))?;
+            if group.backtrace.is_empty() {
+                self.term.write_line("     no backtrace; install eu-stack (elfutils) or gdb to read the core")?;
+            }
+            for (index, frame) in group.backtrace.iter().take(10).enumerate() {
+                self.term.write_line(&format!("     #{:<2} {}", index, frame))?;
+            }
+            if group.backtrace.len() > 10 {
+                self.term.write_line(&format!("     ... {} more frames", group.backtrace.len() - 10))?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_mac(&mut self, hours: u32, apply: Option<usize>, json: bool, mode: &str, host: Option<&str>) -> Result<()> {
+        let remote;
+        let integration = match host {
+            Some(host) => {
+                remote = self.connect_host(host).await?;
+                &remote
+            }
+            None => &self.linux_integration,
+        };
+        let since = chrono::Utc::now() - chrono::Duration::hours(hours as i64);
+        let report = integration.mac().report(since).await;
+        let fixes = report.fixes();
+
+        if let Some(number) = apply {
+            let fix = number.checked_sub(1).and_then(|index| fixes.get(index))
+                .ok_or_else(|| anyhow::anyhow!("There is no fix {}; `tuxpilot mac` lists them", number))?;
+            let plan = fix.plan.as_ref()
+                .ok_or_else(|| anyhow::anyhow!("\"{}\" has to be done by hand: {}", fix.title, fix.reason))?;
+            let mut executor = crate::execution::CommandExecutor::new(self.config.clone(), parse_execution_mode(mode)?).await?;
+            for request in executor.plan_steps(plan, host).await? {
+                self.run_request(&mut executor, request).await?;
+            }
+            self.term.write_line(&format!("✅ {}", plan.description))?;
+            if let Some(rollback) = &plan.rollback {
+                self.term.write_line(&format!("To undo: {}", style(rollback).cyan()))?;
+            }
+            return Ok(());
+        }
+
+        if json {
+            self.term.write_line(&serde_json::to_string_pretty(&report)?)?;
+            return Ok(());
+        }
+
+        self.term.write_line(&format!("{}", style(format!("🛡️  Access control on {}", report.hostname)).blue().bold()))?;
+        self.term.write_line(&format!("Status: {}", report.status))?;
+        if !report.status.lsms.is_empty() {
+            self.term.write_line(&format!("Security modules: {}", report.status.lsms.join(", ")))?;
+        }
+        for error in &report.errors {
+            self.term.write_line(&format!("{} {}", style("⚠️").yellow(), error))?;
+        }
+
+        let source = report.source.as_deref().unwrap_or("no log");
+        if report.groups.is_empty() {
+            self.term.write_line(&format!("No denials in the last {} hours ({})", hours, source))?;
+            return Ok(());
+        }
+        self.term.write_line(&format!("Denials in the last {} hours ({}):", hours, source))?;
+        let mut number = 0;
+        for group in &report.groups {
+            let icon = if group.enforced { style("❌").red() } else { style("📝").yellow() };
+            let service = match (&group.service, group.service_failed) {
+                (Some(service), true) => format!("{} (failed)", style(service).red()),
+                (Some(service), false) => service.clone(),
+                (None, _) => "no service".to_string(),
+            };
+            self.term.write_line(&format!("  {} {} [{}]: {}", icon, group.system, service, group.explanation()))?;
+            if let Some(last) = group.last {
+                self.term.write_line(&format!("     last at {}", last.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S")))?;
+            }
+            for fix in &group.fixes {
+                number += 1;
+                self.term.write_line(&format!("     {}. {}: {}", number, fix.title, fix.reason))?;
+                if let Some(plan) = &fix.plan {
+                    for step in &plan.steps {
+                        self.term.write_line(&format!("        {}", style(step.join(" ")).cyan()))?;
+                    }
+                }
+            }
+        }
+        if fixes.iter().any(|fix| fix.plan.is_some()) {
+            self.term.write_line("Apply one with `tuxpilot mac --apply <number>`")?;
+        }
+        Ok(())
+    }
+
+    async fn handle_schedule(&mut self, action: Option<ScheduleAction>, at: Option<&str>, hours: u32, json: bool, host: Option<&str>) -> Result<()> {
+        let remote;
+        let integration = match host {
+            Some(host) => {
+                remote = self.connect_host(host).await?;
+                &remote
+            }
+            None => &self.linux_integration,
+        };
+        let scheduler = integration.scheduler();
+
+        let (plan, dry_run, mode) = match action {
+            Some(ScheduleAction::Add { name, on_calendar, command, user, description, dry_run, mode }) => {
+                (scheduler.plan_add(&NewTimer { name, on_calendar, command, user, description }).await?, dry_run, mode)
+            }
+            Some(ScheduleAction::Edit { timer, on_calendar, dry_run, mode }) => {
+                (scheduler.plan_edit(&timer, &on_calendar).await?, dry_run, mode)
+            }
+            None => {
+                let report = scheduler.report().await;
+                if json {
+                    self.term.write_line(&serde_json::to_string_pretty(&report)?)?;
+                    return Ok(());
+                }
+
+                let now = report.now;
+                self.term.write_line(&format!("{}", style(format!("⏰ Scheduled jobs on {} (now {})", integration.host_name(), now.format("%a %Y-%m-%d %H:%M %:z"))).blue().bold()))?;
+                if let Some(at) = at {
+                    let hour = parse_hour(at)?;
+                    let mut from = now.date_naive().and_hms_opt(hour, 0, 0)
+                        .and_then(|start| start.and_local_timezone(*now.offset()).single())
+                        .ok_or_else(|| anyhow::anyhow!("Invalid hour {}", at))?;
+                    if from + chrono::Duration::hours(1) <= now {
+                        from += chrono::Duration::days(1);
+                    }
+                    let to = from + chrono::Duration::hours(1);
+                    let runs = report.timeline(from.max(now), to);
+                    let window = format!("{} and {} on {}", from.format("%H:%M"), to.format("%H:%M"), from.format("%a %Y-%m-%d"));
+                    if runs.is_empty() {
+                        self.term.write_line(&format!("Nothing runs between {}", window))?;
+                    } else {
+                        self.term.write_line(&format!("Runs between {}:", window))?;
+                    }
+                    for (run, job) in runs {
+                        self.write_scheduled_job(&run.format("%H:%M").to_string(), job)?;
+                    }
+                    return Ok(());
+                }
+
+                let to = now + chrono::Duration::hours(hours.into());
+                let runs = report.timeline(now, to);
+                self.term.write_line(&format!("Next {} hours:", hours))?;
+                if runs.is_empty() {
+                    self.term.write_line("  nothing")?;
+                }
+                for (run, job) in &runs {
+                    self.write_scheduled_job(&run.format("%a %H:%M").to_string(), job)?;
+                }
+                let later: Vec<&Job> = report.jobs.iter().filter(|job| job.active && job.next.is_some_and(|next| next >= to)).collect();
+                if !later.is_empty() {
+                    self.term.write_line("Later:")?;
+                }
+                for job in later {
+                    let next = job.next.map(|next| next.format("%a %Y-%m-%d %H:%M").to_string()).unwrap_or_default();
+                    self.term.write_line(&format!("  {}  {} {} ({})", next, job.kind, style(&job.name).bold(), job.schedule))?;
+                }
+                let unscheduled: Vec<&Job> = report.jobs.iter().filter(|job| !job.active || job.next.is_none()).collect();
+                if !unscheduled.is_empty() {
+                    self.term.write_line("Not scheduled:")?;
+                }
+                for job in unscheduled {
+                    let state = if job.active { "" } else { ", inactive" };
+                    self.term.write_line(&format!("  {} {} ({}{})", job.kind, job.name, job.schedule, state))?;
+                }
+                for path in &report.unreadable {
+                    self.term.write_line(&format!("{} Could not read {}; run as root to include it", style("⚠️").yellow(), path))?;
+                }
+                let failed: Vec<&Job> = report.jobs.iter().filter(|job| job.last.as_ref().is_some_and(|last| last.success == Some(false))).collect();
+                for job in failed {
+                    self.term.write_line(&format!("{} {} failed on its last run", style("❌").red(), job.name))?;
+                }
+                return Ok(());
+            }
+        };
+
+        self.write_schedule_preview(&plan)?;
+        if dry_run {
+            return Ok(());
+        }
+        if host.is_some() {
+            return Err(anyhow::anyhow!("Timers are written as unit files, which is only supported on this machine"));
+        }
+        self.apply_schedule_plan(&plan, &mode).await
+    }
+
+    /// One job of the timeline: when it runs, what it is, and how it last went
+    fn write_scheduled_job(&self, when: &str, job: &Job) -> Result<()> {
+        self.term.write_line(&format!("  {}  {} {} ({})", when, job.kind, style(&job.name).bold(), job.schedule))?;
+        let user = job.user.as_deref().map(|user| format!("{}: ", user)).unwrap_or_default();
+        let last = match &job.last {
+            Some(last) if last.success == Some(false) => format!("; last run {}", style(last).red()),
+            Some(last) => format!("; last run {}", last),
+            None => String::new(),
+        };
+        self.term.write_line(&format!("      {}{}{}", user, job.command, last))?;
         Ok(())
     }
 
-    async fn handle_help(&mut self, command: Option<String>) -> Result<()> {
-        if let Some(cmd) = command {
-            let help_text = self.ai_client.get_command_help(&cmd).await?;
-            self.term.write_line(&format!("{} {}", style("📖 Help for").blue(), style(&cmd).bold()))?;
-            self.term.write_line(&help_text)?;
-        } else {
-            let _ = self.print_help();
+    fn write_schedule_preview(&self, plan: &SchedulePlan) -> Result<()> {
+        self.term.write_line(&format!("{}", style(format!("⏰ {}", plan.description)).blue().bold()))?;
+        if let Some(before) = &plan.before {
+            self.term.write_line(&format!("Schedule: {} -> {}", style(before).red(), style(&plan.schedule).green()))?;
+        }
+        let runs: Vec<String> = plan.runs.iter().map(|run| run.format("%a %Y-%m-%d %H:%M").to_string()).collect();
+        self.term.write_line(&format!("Next runs: {}", runs.join(", ")))?;
+        for step in &plan.steps {
+            match step {
+                ScheduleStep::Write(file) => {
+                    self.term.write_line(&format!("Write {}:", file.path))?;
+                    let before: Vec<String> = file.before.lines().map(String::from).collect();
+                    let after: Vec<String> = file.content.lines().map(String::from).collect();
+                    for line in firewall::diff_lines(&before, &after).into_iter().filter(|line| !line.starts_with("  ")) {
+                        let line = if line.starts_with('+') { style(line).green() } else { style(line).red() };
+                        self.term.write_line(&format!("  {}", line))?;
+                    }
+                }
+                ScheduleStep::Run(_) => self.term.write_line(&format!("Run: {}", style(step).cyan()))?,
+            }
         }
+        self.term.write_line(&format!("Undo with: {}", plan.undo))?;
+        self.term.write_line("")?;
         Ok(())
     }
 
-    async fn handle_monitor(&mut self, continuous: bool) -> Result<()> {
-        if continuous {
-            self.term.write_line(&format!("{}", style("📊 Starting continuous monitoring... (Press Ctrl+C to stop)").green()))?;
-            self.system_monitor.start_continuous_monitoring().await?;
-        } else {
-            let status = self.system_monitor.get_system_status().await?;
-            let analysis = self.ai_client.analyze_system_status(&status).await?;
-            self.display_system_status(&status, &analysis)?;
+    /// Run the steps of a schedule plan through the executor, stopping at the first
+    /// that fails. Unit files are staged and installed like kernel drop-ins.
+    async fn apply_schedule_plan(&self, plan: &SchedulePlan, mode: &str) -> Result<()> {
+        let mut executor = crate::execution::CommandExecutor::new(self.config.clone(), parse_execution_mode(mode)?).await?;
+        let staging = staging_dir()?;
+
+        for step in &plan.steps {
+            let (staged, program, args) = match step {
+                ScheduleStep::Write(file) => {
+                    std::fs::create_dir_all(&staging)?;
+                    let staged = staging.join(uuid::Uuid::new_v4().to_string());
+                    std::fs::write(&staged, &file.content)?;
+                    let args = ["-m", "0644", "-D", &staged.display().to_string(), &file.path]
+                        .into_iter().map(String::from).collect();
+                    (Some(staged), "install".to_string(), args)
+                }
+                ScheduleStep::Run(command) => {
+                    let (program, args) = command.split_first()
+                        .ok_or_else(|| anyhow::anyhow!("Empty command"))?;
+                    (None, program.clone(), args.to_vec())
+                }
+            };
+
+            let result = async {
+                let request = executor.plan_args(&format!("{}: {}", plan.description, step), program, args, None).await?;
+                self.run_request(&mut executor, request).await
+            }.await;
+            if let Some(staged) = staged {
+                let _ = std::fs::remove_file(staged);
+            }
+            result?;
         }
+
+        self.term.write_line(&format!("✅ {}", plan.description))?;
+        self.term.write_line(&format!("To undo: {}", style(&plan.undo).cyan()))?;
         Ok(())
     }
 
-    async fn handle_package(&mut self, operation: String, package: Option<String>) -> Result<()> {
-        let suggestion = self.linux_integration.get_package_suggestion(&operation, package.as_deref()).await?;
+    async fn handle_users(&mut self, action: Option<UsersAction>, user: Option<&str>, all: bool, json: bool, host: Option<&str>) -> Result<()> {
+        let remote;
+        let integration = match host {
+            Some(host) => {
+                remote = self.connect_host(host).await?;
+                &remote
+            }
+            None => &self.linux_integration,
+        };
+        let accounts = integration.accounts();
 
-        // Check if we're in test mode
-        let is_test_mode = std::env::var("TUXPILOT_TEST_MODE").is_ok();
+        let (change, dry_run, mode) = match action {
+            Some(UsersAction::Add { name, groups, shell, comment, system, dry_run, mode }) => {
+                (AccountChange::CreateUser { name, groups, shell, comment, system }, dry_run, mode)
+            }
+            Some(UsersAction::AddGroup { name, system, dry_run, mode }) => (AccountChange::CreateGroup { name, system }, dry_run, mode),
+            Some(UsersAction::Lock { name, dry_run, mode }) => (AccountChange::Lock { name }, dry_run, mode),
+            Some(UsersAction::Unlock { name, dry_run, mode }) => (AccountChange::Unlock { name }, dry_run, mode),
+            Some(UsersAction::Shell { name, shell, dry_run, mode }) => (AccountChange::SetShell { name, shell }, dry_run, mode),
+            Some(UsersAction::Join { name, group, dry_run, mode }) => (AccountChange::AddToGroup { name, group }, dry_run, mode),
+            Some(UsersAction::Leave { name, group, dry_run, mode }) => (AccountChange::RemoveFromGroup { name, group }, dry_run, mode),
+            Some(UsersAction::Grant { principal, mut commands, runas, nopasswd, dry_run, mode }) => {
+                if commands.is_empty() {
+                    commands.push("ALL".to_string());
+                }
+                (AccountChange::GrantSudo { principal, commands, runas, nopasswd }, dry_run, mode)
+            }
+            Some(UsersAction::Revoke { principal, dry_run, mode }) => (AccountChange::RevokeSudo { principal }, dry_run, mode),
+            None => {
+                let report = accounts.report().await?;
+                if json {
+                    match user {
+                        Some(name) => {
+                            let account = report.account(name).ok_or_else(|| anyhow::anyhow!("No user called {}", name))?;
+                            self.term.write_line(&serde_json::to_string_pretty(account)?)?;
+                        }
+                        None => self.term.write_line(&serde_json::to_string_pretty(&report)?)?,
+                    }
+                    return Ok(());
+                }
 
-        // Try to get AI advice with timeout handling (skip in test mode)
-        let ai_advice = if is_test_mode {
-            format!("Test mode: {}", suggestion)
-        } else {
-            match self.ai_client.get_package_advice(&operation, package.as_deref(), &suggestion).await {
-                Ok(advice) => advice,
-                Err(e) => {
-                    eprintln!("AI advice failed: {}", e);
-                    format!("Basic suggestion: {}", suggestion)
+                let today = chrono::Local::now().date_naive();
+                self.term.write_line(&format!("{}", style(format!("👥 Accounts on {}", report.hostname)).blue().bold()))?;
+                if let Some(name) = user {
+                    let account = report.account(name).ok_or_else(|| anyhow::anyhow!("No user called {}", name))?;
+                    self.write_account(account, today, true)?;
+                } else {
+                    for account in report.accounts.iter().filter(|account| all || account.is_human() || !account.sudo.is_empty()) {
+                        self.write_account(account, today, false)?;
+                    }
+                    let root: Vec<&str> = report.accounts.iter().filter(|account| account.root).map(|account| account.name.as_str()).collect();
+                    self.term.write_line(&format!("Can run anything as root: {}", root.join(", ")))?;
+                    if !all {
+                        self.term.write_line("Service accounts without sudo rules are hidden; use --all to list them")?;
+                    }
                 }
+                for error in &report.sudoers_errors {
+                    self.term.write_line(&format!("{} {}", style("⚠️").yellow(), error))?;
+                }
+                for path in &report.unreadable {
+                    self.term.write_line(&format!("{} Could not read {}; run as root to include it", style("⚠️").yellow(), path))?;
+                }
+                return Ok(());
             }
         };
 
-        self.term.write_line(&format!("{}", style("📦 Package Management").blue().bold()))?;
-        self.term.write_line(&ai_advice)?;
+        let plan = accounts.plan(change).await?;
+        self.write_account_preview(&plan)?;
+        if dry_run {
+            return Ok(());
+        }
+        if host.is_some() && plan.steps.iter().any(|step| matches!(step, AccountStep::WriteSudoers(_))) {
+            return Err(anyhow::anyhow!("Sudo rules are written as files, which is only supported on this machine"));
+        }
+        self.apply_account_plan(&plan, &mode, host).await
+    }
 
-        // Skip interactive prompt in test mode
-        if !is_test_mode {
-            if Confirm::new()
-                .with_prompt("Would you like to execute the suggested command?")
-                .interact()?
-            {
-                self.linux_integration.execute_package_command(&suggestion).await?;
+    /// One account: who it is, how it logs in, and what it may do as root
+    fn write_account(&self, account: &Account, today: chrono::NaiveDate, detail: bool) -> Result<()> {
+        let marker = if account.root { format!(" {}", style("[root]").red()) } else { String::new() };
+        self.term.write_line(&format!("  {} (uid {}){}  {}", style(&account.name).bold(), account.uid, marker, account.shell))?;
+        self.term.write_line(&format!("      groups: {}", account.groups.join(", ")))?;
+
+        let mut status = Vec::new();
+        if let Some(password) = account.password {
+            status.push(match password {
+                PasswordState::Empty => style(password.to_string()).red().to_string(),
+                _ => password.to_string(),
+            });
+        }
+        if let Some(aging) = &account.aging {
+            if aging.must_change {
+                status.push("must change password at next login".to_string());
+            }
+            if let Some(expires) = aging.password_expires() {
+                let text = format!("password expires {}", expires);
+                status.push(if expires <= today { style(text).yellow().to_string() } else { text });
+            }
+            if let Some(expires) = aging.expires {
+                let text = format!("account expires {}", expires);
+                status.push(if expires <= today { style(text).yellow().to_string() } else { text });
             }
         }
-        
+        if !status.is_empty() {
+            self.term.write_line(&format!("      {}", status.join(", ")))?;
+        }
+        if detail {
+            self.term.write_line(&format!("      home {}, comment \"{}\"", account.home, account.comment))?;
+            if let Some(aging) = &account.aging {
+                let mut fields: Vec<String> = aging.last_change.map(|day| format!("changed {}", day)).into_iter().collect();
+                for (name, days) in [("min", aging.min_days), ("max", aging.max_days), ("warn", aging.warn_days), ("inactive", aging.inactive_days)] {
+                    fields.extend(days.map(|days| format!("{} {} days", name, days)));
+                }
+                self.term.write_line(&format!("      aging: {}", fields.join(", ")))?;
+            }
+        }
+
+        for privilege in &account.sudo {
+            let tags = if privilege.tags.is_empty() { String::new() } else { format!(" {}", privilege.tags.join(" ")) };
+            let command = if privilege.root { style(&privilege.command).red().to_string() } else { privilege.command.clone() };
+            self.term.write_line(&format!("      sudo: {} as {}{} via {} ({})", command, privilege.runas, tags, privilege.via, privilege.source))?;
+        }
+        if detail && !account.sudo_defaults.is_empty() {
+            self.term.write_line(&format!("      sudo defaults: {}", account.sudo_defaults.join(", ")))?;
+        }
+        for group in &account.privileged_groups {
+            self.term.write_line(&format!("      {} {}", style("⚠️").yellow(), group))?;
+        }
         Ok(())
     }
 
-    async fn handle_service(&mut self, name: String, action: Option<String>) -> Result<()> {
-        let service_info = self.linux_integration.get_service_info(&name).await?;
-        let ai_advice = self.ai_client.get_service_advice(&name, action.as_deref(), &service_info).await?;
-        
-        self.term.write_line(&format!("{}", style("⚙️  Service Management").blue().bold()))?;
-        self.term.write_line(&ai_advice)?;
-        
+    fn write_account_preview(&self, plan: &AccountPlan) -> Result<()> {
+        self.term.write_line(&format!("{}", style(format!("👥 {}", plan.change)).blue().bold()))?;
+        for step in &plan.steps {
+            match step {
+                AccountStep::WriteSudoers(file) => {
+                    self.term.write_line(&format!("Write {} (checked with visudo):", file.path))?;
+                    for line in file.content.lines() {
+                        self.term.write_line(&format!("  {}", style(format!("+{}", line)).green()))?;
+                    }
+                }
+                AccountStep::Run(_) => self.term.write_line(&format!("Run: {}", style(step).cyan()))?,
+            }
+        }
+        for note in &plan.notes {
+            self.term.write_line(&format!("{} {}", style("ℹ️").cyan(), note))?;
+        }
+        self.term.write_line(&format!("Undo with: {}", plan.undo))?;
+        self.term.write_line("")?;
+        Ok(())
+    }
+
+    /// Run the steps of an account plan through the executor, stopping at the first
+    /// that fails. Sudoers files are staged and only installed once `visudo -c -f`
+    /// accepts them; if the whole configuration then fails `visudo -c`, the file is
+    /// removed again so sudo keeps working.
+    async fn apply_account_plan(&self, plan: &AccountPlan, mode: &str, host: Option<&str>) -> Result<()> {
+        let mut executor = crate::execution::CommandExecutor::new(self.config.clone(), parse_execution_mode(mode)?).await?;
+        let description = plan.change.to_string();
+
+        for step in &plan.steps {
+            match step {
+                AccountStep::Run(command) => {
+                    let (program, args) = command.split_first()
+                        .ok_or_else(|| anyhow::anyhow!("Empty command"))?;
+                    let mut request = executor.plan_args(&format!("{}: {}", description, step), program.clone(), args.to_vec(), host).await?;
+                    request.context.rollback_plan = Some(plan.undo.clone());
+                    self.run_request(&mut executor, request).await?;
+                }
+                AccountStep::WriteSudoers(file) => {
+                    let staging = staging_dir()?;
+                    std::fs::create_dir_all(&staging)?;
+                    let staged = staging.join(uuid::Uuid::new_v4().to_string());
+                    std::fs::write(&staged, &file.content)?;
+                    let staged_path = staged.display().to_string();
+
+                    let result = async {
+                        let check = ["-c", "-f", &staged_path].map(String::from).to_vec();
+                        let request = executor.plan_args(&format!("{}: check {}", description, file.path), "visudo".to_string(), check, None).await?;
+                        self.run_request(&mut executor, request).await?;
+
+                        let install = ["-m", "0440", "-D", &staged_path, &file.path].map(String::from).to_vec();
+                        let mut request = executor.plan_args(&format!("{}: {}", description, step), "install".to_string(), install, None).await?;
+                        request.context.rollback_plan = Some(plan.undo.clone());
+                        self.run_request(&mut executor, request).await
+                    }.await;
+                    let _ = std::fs::remove_file(&staged);
+                    result?;
+
+                    let check = vec!["-c".to_string()];
+                    let request = executor.plan_args(&format!("{}: check the sudo configuration", description), "visudo".to_string(), check, None).await?;
+                    if let Err(error) = self.run_request(&mut executor, request).await {
+                        let remove = vec!["-f".to_string(), file.path.clone()];
+                        let request = executor.plan_args(&format!("Remove {} after visudo rejected the configuration", file.path), "rm".to_string(), remove, None).await?;
+                        self.run_request(&mut executor, request).await?;
+                        return Err(error.context(format!("visudo rejected the configuration with {}; it was removed again", file.path)));
+                    }
+                }
+            }
+        }
+
+        self.term.write_line(&format!("✅ {}", description))?;
+        self.term.write_line(&format!("To undo: {}", style(&plan.undo).cyan()))?;
         Ok(())
     }
 
@@ -286,6 +1790,16 @@ impl TuxPilotCli {
             self.term.write_line(&format!("Version: {}", distro_info.version))?;
             self.term.write_line(&format!("ID: {}", distro_info.id))?;
             self.term.write_line(&format!("Architecture: {}", distro_info.architecture))?;
+            if distro_info.system_image.is_immutable() {
+                self.term.write_line(&format!("System Image: {}", distro_info.system_image))?;
+            }
+            if !distro_info.environment.is_bare_metal() {
+                self.term.write_line(&format!("Environment: {}", distro_info.environment))?;
+            }
+            if !distro_info.additional_package_managers.is_empty() {
+                let managers: Vec<String> = distro_info.additional_package_managers.iter().map(|m| m.to_string()).collect();
+                self.term.write_line(&format!("Additional Package Managers: {}", managers.join(", ")))?;
+            }
             self.term.write_line(&format!("Init System: {}", distro_info.init_system))?;
             self.term.write_line(&format!("Shell: {}", distro_info.shell))?;
         }
@@ -305,19 +1819,151 @@ impl TuxPilotCli {
         self.interactive_mode().await
     }
 
-    async fn handle_execute_command(&mut self, description: &str, mode: &str) -> Result<()> {
+    async fn handle_execute_command(&mut self, description: &str, mode: &str, command: Option<&str>, targets: &TargetArgs) -> Result<()> {
         self.term.write_line(&format!("{}", style("🔧 Command Execution").blue().bold()))?;
         self.term.write_line(&format!("Description: {}", description))?;
         self.term.write_line(&format!("Mode: {}", mode))?;
         self.term.write_line("")?;
 
-        // TODO: Implement actual command execution
+        if let Some(command_line) = command {
+            let mut executor = crate::execution::CommandExecutor::new(
+                self.config.clone(),
+                parse_execution_mode(mode)?,
+            ).await?;
+
+            if targets.is_local() {
+                let result = self.run_on_host(&mut executor, description, command_line, None).await?;
+                self.term.write_line(&format!("✅ {}", result))?;
+                return Ok(());
+            }
+
+            let hosts = Inventory::load(&self.config)?.resolve(&targets.hosts, &targets.groups)?;
+            let mut outcomes = Vec::new();
+            for host in hosts {
+                self.write_host_header(&host.name)?;
+                let result = self.run_on_host(&mut executor, description, command_line, Some(&host.name)).await;
+                outcomes.push(self.host_outcome(host.name, result)?);
+            }
+            return self.display_host_summary(&outcomes);
+        }
+
+        // TODO: Implement AI-planned command execution
         self.term.write_line("⚠️  Command execution system not yet fully implemented.")?;
         self.term.write_line("This feature will allow TuxPilot to execute commands based on natural language descriptions.")?;
 
         Ok(())
     }
 
+    /// Connect to the hosts selected with `--host`/`--group`. Unreachable hosts come
+    /// back as errors so the remaining hosts can still be handled.
+    async fn connect_targets(&self, targets: &TargetArgs) -> Result<Vec<(String, Result<LinuxIntegration>)>> {
+        let hosts = Inventory::load(&self.config)?.resolve(&targets.hosts, &targets.groups)?;
+
+        let connections = hosts.into_iter().map(|host| async move {
+            let name = host.name.clone();
+            let transport = Arc::new(SshTransport::new(host, &self.config));
+            let connection = async {
+                transport.run("true", &[]).await?;
+                LinuxIntegration::with_transport(&self.config, transport).await
+            }.await;
+            (name, connection)
+        });
+
+        Ok(futures::future::join_all(connections).await)
+    }
+
+    /// Run one command line through the executor and print its output. Returns the
+    /// summary line for the per-host results.
+    async fn run_on_host(
+        &self,
+        executor: &mut crate::execution::CommandExecutor,
+        description: &str,
+        command_line: &str,
+        host: Option<&str>,
+    ) -> Result<String> {
+        let request = executor.plan_command(description, command_line, host).await?;
+        self.run_request(executor, request).await
+    }
+
+    /// Connect to a single inventory host
+    async fn connect_host(&self, host: &str) -> Result<LinuxIntegration> {
+        let targets = TargetArgs { hosts: vec![host.to_string()], groups: Vec::new() };
+        let (_, connection) = self.connect_targets(&targets).await?.into_iter().next()
+            .ok_or_else(|| anyhow::anyhow!("Host '{}' not found in the inventory", host))?;
+        connection
+    }
+
+    /// Execute a planned request and print its output
+    async fn run_request(
+        &self,
+        executor: &mut crate::execution::CommandExecutor,
+        request: crate::execution::ExecutionRequest,
+    ) -> Result<String> {
+        let result = executor.execute_request(request).await?;
+
+        if !result.stdout.trim().is_empty() {
+            self.term.write_line(result.stdout.trim_end())?;
+        }
+        if !result.stderr.trim().is_empty() {
+            self.term.write_line(&format!("{}", style(result.stderr.trim_end()).red()))?;
+        }
+        if let Some(pending) = &result.pending_activation {
+            self.write_pending_activation(pending)?;
+        }
+
+        let detail = format!("exit {} in {:.1}s",
+            result.exit_code.map(|c| c.to_string()).unwrap_or_else(|| "-".to_string()),
+            result.execution_time.as_secs_f64());
+
+        if result.success {
+            Ok(detail)
+        } else if result.exit_code.is_none() {
+            Err(anyhow::anyhow!("{}", result.stdout.trim()))
+        } else {
+            Err(anyhow::anyhow!("{}", detail))
+        }
+    }
+
+    fn write_pending_activation(&self, pending: &PendingActivation) -> Result<()> {
+        self.term.write_line(&format!("{} {}; run `{}` to activate it",
+            style("⏳").yellow(), pending.reason, style(&pending.apply_command).bold()))?;
+        Ok(())
+    }
+
+    fn write_host_header(&self, host: &str) -> Result<()> {
+        self.term.write_line("")?;
+        self.term.write_line(&format!("{}", style(format!("🖥️  {}", host)).cyan().bold()))?;
+        Ok(())
+    }
+
+    fn host_outcome(&self, host: String, result: Result<String>) -> Result<HostOutcome> {
+        if let Err(e) = &result {
+            self.term.write_line(&format!("❌ {}: {:#}", host, e))?;
+        }
+        Ok((host, result.map_err(|e| format!("{:#}", e))))
+    }
+
+    fn display_host_summary(&self, outcomes: &[HostOutcome]) -> Result<()> {
+        let failed = outcomes.iter().filter(|(_, result)| result.is_err()).count();
+
+        self.term.write_line("")?;
+        self.term.write_line(&format!("{}", style(format!("📋 Results: {} succeeded, {} failed",
+            outcomes.len() - failed, failed)).bold()))?;
+
+        let width = outcomes.iter().map(|(host, _)| host.len()).max().unwrap_or(0);
+        for (host, result) in outcomes {
+            match result {
+                Ok(detail) => self.term.write_line(&format!("  ✅ {:width$}  {}", host, detail, width = width))?,
+                Err(e) => self.term.write_line(&format!("  ❌ {:width$}  {}", host, e, width = width))?,
+            }
+        }
+
+        if failed > 0 {
+            return Err(anyhow::anyhow!("{} of {} hosts failed", failed, outcomes.len()));
+        }
+        Ok(())
+    }
+
     async fn show_permissions(&mut self, detailed: bool) -> Result<()> {
         self.term.write_line(&format!("{}", style("🔐 TuxPilot Permissions").blue().bold()))?;
         self.term.write_line("")?;
@@ -338,27 +1984,287 @@ impl TuxPilotCli {
             self.term.write_line("  - Service Management: Requires systemctl permissions")?;
         }
 
+        self.show_elevation_status()?;
+
         Ok(())
     }
 
-    async fn show_audit_log(&mut self, limit: usize, export_format: Option<&str>) -> Result<()> {
-        self.term.write_line(&format!("{}", style("📊 TuxPilot Audit Log").blue().bold()))?;
-        self.term.write_line(&format!("Showing last {} entries", limit))?;
+    fn show_elevation_status(&mut self) -> Result<()> {
+        use crate::execution::elevation::PrivilegeElevator;
+
         self.term.write_line("")?;
+        let elevator = match PrivilegeElevator::new(&self.config)? {
+            Some(elevator) => elevator,
+            None if unsafe { libc::geteuid() } == 0 => {
+                self.term.write_line("🔑 Running as root: no elevation needed")?;
+                return Ok(());
+            }
+            None => {
+                self.term.write_line("🔑 Elevation: unavailable (install sudo or pkexec)")?;
+                return Ok(());
+            }
+        };
+
+        self.term.write_line(&format!("🔑 Elevation: privileged helper via {:?}", elevator.method()))?;
+        let approvals = elevator.active_approvals();
+        if approvals.is_empty() {
+            self.term.write_line("⏱️  Active approvals: none")?;
+        } else {
+            self.term.write_line("⏱️  Active approvals:")?;
+            for approval in approvals {
+                let remaining = (approval.expires_at - chrono::Utc::now()).num_minutes().max(0);
+                self.term.write_line(&format!("  - {:?} ({} min left)", approval.scope, remaining))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn grant_approval(&mut self, scope: &str, minutes: Option<u32>) -> Result<()> {
+        use crate::execution::audit::AuditLogger;
+        use crate::execution::elevation::{ApprovalGrant, PrivilegeElevator};
+        use crate::execution::permissions::parse_permission;
+
+        let scope = parse_permission(scope)?;
+        let minutes = minutes.unwrap_or(self.config.elevation.approval_minutes);
+
+        let elevator = match PrivilegeElevator::new(&self.config)? {
+            Some(elevator) => elevator,
+            None => {
+                self.term.write_line("No approval needed: running as root or no elevation method available")?;
+                return Ok(());
+            }
+        };
+
+        elevator.grant(ApprovalGrant { scope: scope.clone(), minutes }).await?;
+        AuditLogger::new(&self.config).await?
+            .log_permission_request(&format!("{:?} via {:?} for {} minutes", scope, elevator.method(), minutes), true)
+            .await?;
+
+        self.term.write_line(&format!("✅ Approved {:?} for {} minutes", scope, minutes))?;
+        Ok(())
+    }
+
+    async fn revoke_approval(&mut self, scope: &str) -> Result<()> {
+        use crate::execution::elevation::PrivilegeElevator;
+        use crate::execution::permissions::parse_permission;
+
+        let scope = match scope {
+            "all" => None,
+            other => Some(parse_permission(other)?),
+        };
+
+        let elevator = match PrivilegeElevator::new(&self.config)? {
+            Some(elevator) => elevator,
+            None => {
+                self.term.write_line("No approvals to revoke: running as root or no elevation method available")?;
+                return Ok(());
+            }
+        };
+
+        elevator.revoke(scope).await?;
+        self.term.write_line("✅ Approval revoked")?;
+        Ok(())
+    }
+
+    /// Print journal entries, and with `follow` keep printing new ones until interrupted
+    async fn show_logs(&self, query: JournalQuery, follow: bool, json: bool) -> Result<()> {
+        use futures::StreamExt;
+
+        let reader = JournalReader::new(self.linux_integration.transport().clone());
+        let print = |record: &LogRecord| -> Result<()> {
+            if json {
+                self.term.write_line(&serde_json::to_string(record)?)?;
+                return Ok(());
+            }
+            let line = record.to_string();
+            let line = match record.priority {
+                Some(0..=3) => style(line).red().to_string(),
+                Some(4) => style(line).yellow().to_string(),
+                _ => line,
+            };
+            self.term.write_line(&line)?;
+            Ok(())
+        };
+
+        if !follow {
+            let records = reader.read(&query).await?;
+            if records.is_empty() && !json {
+                self.term.write_line("No matching journal entries")?;
+            }
+            return records.iter().try_for_each(print);
+        }
+
+        let records = reader.follow(query, std::time::Duration::from_secs(1));
+        futures::pin_mut!(records);
+        while let Some(record) = records.next().await {
+            print(&record?)?;
+        }
+        Ok(())
+    }
+
+    async fn show_audit_log(&mut self, limit: usize, page: usize, filters: &AuditFilterArgs, export_format: Option<&str>) -> Result<()> {
+        use crate::execution::audit::{query, AuditLogger, ExportFormat};
+
+        let mut audit_query = query::AuditQuery::new();
+        audit_query.since = filters.since.as_deref().map(query::parse_time_bound).transpose()?;
+        audit_query.until = filters.until.as_deref().map(query::parse_time_bound).transpose()?;
+        audit_query.user = filters.user.clone();
+        audit_query.session_id = filters.session.clone();
+        audit_query.min_risk = filters.risk.as_deref().map(query::parse_risk_level).transpose()?;
+        audit_query.entry_types = filters.entry_types.iter()
+            .map(|t| query::parse_entry_type(t))
+            .collect::<Result<_>>()?;
+        audit_query.command_contains = filters.grep.clone();
+        audit_query.success = match (filters.succeeded, filters.failed) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        };
+        audit_query.page = page;
+        audit_query.per_page = limit;
 
-        // TODO: Implement audit log reading
-        self.term.write_line("📋 Audit log features:")?;
-        self.term.write_line("  ✅ Complete command execution history")?;
-        self.term.write_line("  ✅ Permission requests and grants")?;
-        self.term.write_line("  ✅ Safety violations and warnings")?;
-        self.term.write_line("  ✅ System changes and rollback information")?;
+        let audit_logger = AuditLogger::new(&self.config).await?;
+        let result = audit_logger.query(audit_query).await?;
 
         if let Some(format) = export_format {
-            self.term.write_line("")?;
-            self.term.write_line(&format!("📤 Export format: {}", format))?;
-            self.term.write_line("Export functionality will be available soon.")?;
+            let format: ExportFormat = format.parse()?;
+            let entries: Vec<_> = result.hits.into_iter().map(|hit| hit.entry).collect();
+            self.term.write_line(&audit_logger.export_entries(&entries, format)?)?;
+            return Ok(());
+        }
+
+        self.term.write_line(&format!("{}", style("📊 TuxPilot Audit Log").blue().bold()))?;
+        self.term.write_line(&format!(
+            "Showing {} of {} matching entries (page {}/{})",
+            result.hits.len(),
+            result.total,
+            result.page,
+            result.total_pages.max(1)
+        ))?;
+        self.term.write_line("")?;
+
+        for hit in &result.hits {
+            let summary = &hit.summary;
+            let status = match summary.success {
+                Some(true) => "✅",
+                Some(false) => "❌",
+                None => "•",
+            };
+            let risk = summary.risk_level.as_ref()
+                .map(|r| format!(" [{:?}]", r))
+                .unwrap_or_default();
+
+            self.term.write_line(&format!(
+                "{} {} {:?}{} {} {}",
+                status,
+                summary.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                summary.entry_type,
+                risk,
+                style(&summary.user).dim(),
+                summary.command.as_deref().unwrap_or(""),
+            ))?;
+        }
+
+        Ok(())
+    }
+
+    async fn verify_audit_log(&mut self) -> Result<()> {
+        use crate::execution::audit::AuditLogger;
+
+        let audit_logger = AuditLogger::new(&self.config).await?;
+        let report = audit_logger.verify_integrity().await?;
+
+        self.term.write_line(&report.to_string())?;
+
+        if !report.is_intact() {
+            return Err(anyhow::anyhow!("Audit log verification failed"));
+        }
+        Ok(())
+    }
+
+    async fn handle_approvals(&mut self, status: &str, action: Option<ApprovalAction>) -> Result<()> {
+        use crate::execution::approval::{self, ApprovalBroker, ApprovalStatus};
+        use crate::execution::audit::AuditLogger;
+
+        let broker = ApprovalBroker::new(&self.config, AuditLogger::new(&self.config).await?)?;
+        let approver = approval::login_name();
+
+        let (id, approved, comment) = match action {
+            Some(ApprovalAction::Approve { id, comment }) => (id, true, comment),
+            Some(ApprovalAction::Deny { id, comment }) => (id, false, comment),
+            None => {
+                let status = match status {
+                    "all" => None,
+                    other => Some(other.parse::<ApprovalStatus>()?),
+                };
+                let approvals = broker.list(status).await?;
+
+                self.term.write_line(&format!("{}", style("🗳️  TuxPilot Approval Queue").blue().bold()))?;
+                self.term.write_line("━━━━━━━━━━━━━━━━━━━━━━━━━━")?;
+                if approvals.is_empty() {
+                    self.term.write_line("No matching approval requests")?;
+                }
+                for approval in approvals {
+                    self.term.write_line(&approval.to_string())?;
+                }
+                return Ok(());
+            }
+        };
+
+        let id = uuid::Uuid::parse_str(&id)
+            .map_err(|_| anyhow::anyhow!("Invalid approval request ID: {}", id))?;
+        let approval = broker.decide(id, &approver, approved, comment).await?;
+        self.term.write_line(&approval.to_string())?;
+        Ok(())
+    }
+
+    fn show_session(&mut self, archive: &std::path::Path) -> Result<()> {
+        let archive = SessionArchive::load(archive)?;
+        let header = &archive.header;
+
+        self.term.write_line(&format!("{}", style("🎬 TuxPilot Session").blue().bold()))?;
+        self.term.write_line("━━━━━━━━━━━━━━━━━━━━━━━━━━")?;
+        self.term.write_line(&format!("🕐 Recorded: {}", header.recorded_at.format("%Y-%m-%d %H:%M:%S UTC")))?;
+        self.term.write_line(&format!("🖥️  Host: {} (TuxPilot {})", header.hostname, header.tuxpilot_version))?;
+        self.term.write_line(&format!("⌨️  Command: {}", header.arguments.join(" ")))?;
+        self.term.write_line(&format!("📼 Events: {}", archive.events.len()))?;
+        self.term.write_line("")?;
+
+        for event in &archive.events {
+            self.term.write_line(&event.to_string())?;
+        }
+        Ok(())
+    }
+
+    async fn test_audit_sinks(&mut self) -> Result<()> {
+        use crate::execution::audit::AuditLogger;
+
+        let audit_logger = AuditLogger::new(&self.config).await?;
+        let results = audit_logger.test_sinks().await?;
+
+        self.term.write_line(&format!("{}", style("📡 TuxPilot Audit Sinks").blue().bold()))?;
+        self.term.write_line("━━━━━━━━━━━━━━━━━━━━━━━━━━")?;
+
+        if results.is_empty() {
+            self.term.write_line("No audit sinks configured. Add [[audit.sinks]] entries to the config file.")?;
+            return Ok(());
+        }
+
+        let mut failed = 0;
+        for (name, result) in &results {
+            match result {
+                Ok(()) => self.term.write_line(&format!("✅ {}: delivered", name))?,
+                Err(e) => {
+                    failed += 1;
+                    self.term.write_line(&format!("❌ {}: {:#}", name, e))?;
+                }
+            }
         }
 
+        if failed > 0 {
+            return Err(anyhow::anyhow!("{} of {} audit sinks failed", failed, results.len()));
+        }
         Ok(())
     }
 
@@ -435,6 +2341,7 @@ impl TuxPilotCli {
         self.term.write_line("   • GET  /api/system/status  - System status")?;
         self.term.write_line("   • POST /api/commands/execute - Execute commands")?;
         self.term.write_line("   • GET  /api/logs           - System logs")?;
+        self.term.write_line("   • GET  /api/audit          - Audit log search")?;
         self.term.write_line("   • WS   /ws                 - WebSocket connection")?;
         self.term.write_line("")?;
         self.term.write_line("🛡️  Security Features:")?;
@@ -467,3 +2374,52 @@ impl TuxPilotCli {
         Ok(())
     }
 }
+
+/// Root-owned directory of the keep markers, shared with the privileged helper's approvals
+const KEEP_DIR: &str = "/run/tuxpilot";
+
+/// Marker that tells the revert timer of firewall change `id` to do nothing
+fn keep_file(id: &str) -> String {
+    format!("{}/firewall-{}.keep", KEEP_DIR, id)
+}
+
+/// Where files are written before `install` puts them in place
+fn staging_dir() -> Result<std::path::PathBuf> {
+    Ok(dirs::data_dir()
+        .ok_or_else(|| anyhow::anyhow!("Failed to get data directory"))?
+        .join("tuxpilot")
+        .join("staging"))
+}
+
+/// Hour of the day from "3", "03:00", "15:30" or "3am"
+fn parse_hour(at: &str) -> Result<u32> {
+    let text = at.trim().to_lowercase();
+    let (text, add) = match (text.strip_suffix("am"), text.strip_suffix("pm")) {
+        (Some(hour), _) => (hour.trim().to_string(), Some(0)),
+        (_, Some(hour)) => (hour.trim().to_string(), Some(12)),
+        _ => (text.clone(), None),
+    };
+    let hour: u32 = text.split(':').next().unwrap_or_default().parse()
+        .map_err(|_| anyhow::anyhow!("Invalid time '{}'; use an hour such as 3 or 03:00", at))?;
+    let hour = match add {
+        Some(add) if (1..=12).contains(&hour) => hour % 12 + add,
+        Some(_) => return Err(anyhow::anyhow!("Invalid time '{}'", at)),
+        None => hour,
+    };
+    if hour > 23 {
+        return Err(anyhow::anyhow!("Invalid time '{}'", at));
+    }
+    Ok(hour)
+}
+
+fn parse_execution_mode(mode: &str) -> Result<crate::execution::ExecutionMode> {
+    use crate::execution::ExecutionMode;
+
+    match mode.to_lowercase().replace('_', "-").as_str() {
+        "supervised" => Ok(ExecutionMode::Supervised),
+        "semi-auto" | "semiauto" => Ok(ExecutionMode::SemiAuto),
+        "autonomous" => Ok(ExecutionMode::Autonomous),
+        "read-only" | "readonly" => Ok(ExecutionMode::ReadOnly),
+        _ => Err(anyhow::anyhow!("Unknown execution mode '{}'. Use supervised, semi-auto, autonomous or read-only", mode)),
+    }
+}
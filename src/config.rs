@@ -3,11 +3,25 @@ use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use std::fs;
 
+use crate::environment::EnvironmentInfo;
+use crate::remote::transport::{LocalTransport, Transport};
+use crate::system_image::SystemImage;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub ai: AiConfig,
     pub system: SystemConfig,
     pub ui: UiConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub elevation: ElevationConfig,
+    #[serde(default)]
+    pub approvals: ApprovalConfig,
+    #[serde(default)]
+    pub state_probes: StateProbeConfig,
+    #[serde(default)]
+    pub remote: RemoteConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -69,6 +83,12 @@ pub struct SystemConfig {
     pub execution_mode: ExecutionMode,
     pub require_confirmation: bool,
     pub command_timeout_seconds: u64,
+    /// Detected at startup, never written to the config file
+    #[serde(skip)]
+    pub system_image: SystemImage,
+    /// Container, WSL or virtual machine, also detected at startup
+    #[serde(skip)]
+    pub environment: EnvironmentInfo,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -79,13 +99,21 @@ pub enum ExecutionMode {
     ReadOnly,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum PackageManager {
     Pacman,
     Apt,
     Dnf,
     Zypper,
     Portage,
+    Apk,
+    Xbps,
+    /// Per-user profile managed with `nix-env`
+    Nix,
+    /// Per-user profile managed with `nix profile`
+    NixProfile,
+    Flatpak,
+    Snap,
 }
 
 impl std::fmt::Display for PackageManager {
@@ -96,6 +124,36 @@ impl std::fmt::Display for PackageManager {
             PackageManager::Dnf => write!(f, "Dnf"),
             PackageManager::Zypper => write!(f, "Zypper"),
             PackageManager::Portage => write!(f, "Portage"),
+            PackageManager::Apk => write!(f, "Apk"),
+            PackageManager::Xbps => write!(f, "Xbps"),
+            PackageManager::Nix => write!(f, "Nix"),
+            PackageManager::NixProfile => write!(f, "Nix profile"),
+            PackageManager::Flatpak => write!(f, "Flatpak"),
+            PackageManager::Snap => write!(f, "Snap"),
+        }
+    }
+}
+
+impl std::str::FromStr for PackageManager {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().replace(['-', '_', ' '], "").as_str() {
+            "pacman" => Ok(PackageManager::Pacman),
+            "apt" => Ok(PackageManager::Apt),
+            "dnf" => Ok(PackageManager::Dnf),
+            "zypper" => Ok(PackageManager::Zypper),
+            "portage" | "emerge" => Ok(PackageManager::Portage),
+            "apk" => Ok(PackageManager::Apk),
+            "xbps" => Ok(PackageManager::Xbps),
+            "nix" | "nixenv" => Ok(PackageManager::Nix),
+            "nixprofile" => Ok(PackageManager::NixProfile),
+            "flatpak" => Ok(PackageManager::Flatpak),
+            "snap" => Ok(PackageManager::Snap),
+            _ => Err(anyhow::anyhow!(
+                "Unknown package manager '{}'. Use pacman, apt, dnf, zypper, portage, apk, xbps, nix, nix-profile, flatpak or snap",
+                s
+            )),
         }
     }
 }
@@ -127,6 +185,214 @@ pub struct UiConfig {
     pub ssl_enabled: bool,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    /// Directory holding the audit log; defaults to the user data directory
+    pub directory: Option<PathBuf>,
+    /// Rotate the active log once it grows beyond this size
+    pub max_file_size_bytes: u64,
+    /// Rotate the active log once its oldest entry is older than this
+    pub max_file_age_days: u32,
+    /// Number of rotated segments to keep
+    pub keep_rotated: usize,
+    /// Delete rotated segments older than this
+    pub retention_days: u32,
+    pub compress_rotated: bool,
+    /// Sign every entry with the ed25519 host key
+    pub sign_entries: bool,
+    /// Private signing key; defaults to `audit.key` in the config directory
+    pub signing_key_path: Option<PathBuf>,
+    /// Public key signatures are verified against. Must lie outside the audit directory;
+    /// without it only a root-owned `/etc/tuxpilot/audit.pub` is trusted.
+    pub verifying_key_path: Option<PathBuf>,
+    /// Live forwarding of audit entries to syslog, journald or SIEM files
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sinks: Vec<AuditSinkConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditSinkConfig {
+    pub name: String,
+    pub kind: AuditSinkKind,
+    /// Payload format for syslog and file sinks; journald always receives structured fields
+    pub format: AuditSinkFormat,
+    pub transport: SyslogTransport,
+    /// `host:port` for UDP/TCP, a socket path for unix transport and journald, a path for file sinks
+    pub address: Option<String>,
+    /// Syslog facility name, e.g. `authpriv` or `local0`
+    pub facility: String,
+    pub app_name: String,
+    /// Entries spooled in the audit directory while the target is unavailable; the oldest
+    /// are dropped first
+    pub buffer_size: usize,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum AuditSinkKind {
+    Journald,
+    Syslog,
+    File,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum AuditSinkFormat {
+    Rfc5424,
+    Cef,
+    Leef,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum SyslogTransport {
+    Udp,
+    Tcp,
+    Unix,
+}
+
+impl Default for AuditSinkConfig {
+    fn default() -> Self {
+        Self {
+            name: "syslog".to_string(),
+            kind: AuditSinkKind::Syslog,
+            format: AuditSinkFormat::Rfc5424,
+            transport: SyslogTransport::Udp,
+            address: None,
+            facility: "authpriv".to_string(),
+            app_name: "tuxpilot".to_string(),
+            buffer_size: 1000,
+            enabled: true,
+        }
+    }
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            directory: None,
+            max_file_size_bytes: 10 * 1024 * 1024,
+            max_file_age_days: 30,
+            keep_rotated: 12,
+            retention_days: 365,
+            compress_rotated: true,
+            sign_entries: false,
+            signing_key_path: None,
+            verifying_key_path: None,
+            sinks: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ElevationConfig {
+    /// Tool used to start the privileged helper; detected from PATH when unset
+    pub method: Option<ElevationMethod>,
+    /// Binary run as the helper; defaults to the running tuxpilot executable
+    pub helper_path: Option<PathBuf>,
+    /// Duration offered when approving a scope for repeated use
+    pub approval_minutes: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ElevationMethod {
+    Sudo,
+    Pkexec,
+}
+
+impl Default for ElevationConfig {
+    fn default() -> Self {
+        Self {
+            method: None,
+            helper_path: None,
+            approval_minutes: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApprovalConfig {
+    /// Directory holding the approval queue; defaults to the user data directory
+    pub directory: Option<PathBuf>,
+    /// Pending requests are denied once they have waited this long
+    pub timeout_minutes: u32,
+    /// Require two distinct approvers for critical-risk commands
+    pub two_person_critical: bool,
+}
+
+impl Default for ApprovalConfig {
+    fn default() -> Self {
+        Self {
+            directory: None,
+            timeout_minutes: 30,
+            two_person_critical: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StateProbeConfig {
+    /// Compare system state before and after every command that is not read-only
+    pub enabled: bool,
+    /// Config files and directories that are checksummed and backed up before execution
+    pub watched_paths: Vec<PathBuf>,
+    /// Backups of changed files are kept this long so the change can be rolled back
+    pub backup_retention_days: u32,
+}
+
+impl Default for StateProbeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            watched_paths: [
+                "/etc/fstab",
+                "/etc/hosts",
+                "/etc/hostname",
+                "/etc/resolv.conf",
+                "/etc/environment",
+                "/etc/sudoers",
+                "/etc/sudoers.d",
+                "/etc/ssh/sshd_config",
+                "/etc/sysctl.conf",
+                "/etc/sysctl.d",
+                "/etc/modprobe.d",
+                "/etc/modules-load.d",
+                "/etc/modules",
+                "/etc/default/grub",
+                "/etc/pacman.conf",
+                "/etc/apt/sources.list",
+                "/etc/apt/sources.list.d",
+                "/etc/yum.repos.d",
+            ].iter().map(PathBuf::from).collect(),
+            backup_retention_days: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteConfig {
+    /// Host inventory; defaults to ~/.config/tuxpilot/inventory.toml
+    pub inventory: Option<PathBuf>,
+    /// Seconds to wait for an SSH connection before giving up on a host
+    pub connect_timeout_seconds: u64,
+    /// Seconds an idle shared SSH connection is kept open (0 disables multiplexing)
+    pub control_persist_seconds: u64,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self {
+            inventory: None,
+            connect_timeout_seconds: 10,
+            control_persist_seconds: 60,
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -168,6 +434,8 @@ impl Default for Config {
                 execution_mode: ExecutionMode::Supervised,
                 require_confirmation: true,
                 command_timeout_seconds: 30,
+                system_image: SystemImage::default(),
+                environment: EnvironmentInfo::default(),
             },
             ui: UiConfig {
                 theme: "default".to_string(),
@@ -177,6 +445,11 @@ impl Default for Config {
                 bind_address: "127.0.0.1".to_string(),
                 ssl_enabled: false,
             },
+            audit: AuditConfig::default(),
+            elevation: ElevationConfig::default(),
+            approvals: ApprovalConfig::default(),
+            state_probes: StateProbeConfig::default(),
+            remote: RemoteConfig::default(),
         }
     }
 }
@@ -227,7 +500,7 @@ impl Config {
         Ok(config_dir.join("config.toml"))
     }
 
-    pub fn detect_system(&mut self) -> Result<()> {
+    pub async fn detect_system(&mut self) -> Result<()> {
         // Auto-detect package manager
         if Path::new("/usr/bin/pacman").exists() {
             self.system.package_manager = PackageManager::Pacman;
@@ -239,6 +512,19 @@ impl Config {
             self.system.package_manager = PackageManager::Zypper;
         } else if Path::new("/usr/bin/emerge").exists() {
             self.system.package_manager = PackageManager::Portage;
+        } else if Path::new("/sbin/apk").exists() {
+            self.system.package_manager = PackageManager::Apk;
+        } else if Path::new("/usr/bin/xbps-install").exists() {
+            self.system.package_manager = PackageManager::Xbps;
+        } else if Path::new("/run/current-system/sw/bin/nix-env").exists() {
+            let profile_manifest = dirs::home_dir()
+                .map(|home| home.join(".nix-profile/manifest.json").exists())
+                .unwrap_or(false);
+            self.system.package_manager = if profile_manifest {
+                PackageManager::NixProfile
+            } else {
+                PackageManager::Nix
+            };
         }
 
         // Auto-detect service manager
@@ -251,7 +537,7 @@ impl Config {
         }
 
         // Auto-detect Ollama installation
-        if self.is_ollama_available() {
+        if Self::is_ollama_available().await {
             self.ai.provider = AiProvider::Ollama;
             log::info!("Ollama detected, switching to local AI provider");
         }
@@ -259,23 +545,16 @@ impl Config {
         Ok(())
     }
 
-    fn is_ollama_available(&self) -> bool {
-        // Check if ollama command exists
-        if let Ok(output) = std::process::Command::new("which")
-            .arg("ollama")
-            .output()
-        {
-            if output.status.success() {
-                // Check if ollama service is running
-                if let Ok(response) = std::process::Command::new("curl")
-                    .arg("-s")
-                    .arg("http://localhost:11434/api/tags")
-                    .output()
-                {
-                    return response.status.success();
-                }
-            }
-        }
-        false
+    async fn is_ollama_available() -> bool {
+        // Check if ollama command exists and its service is running. Both probes go
+        // through the transport so recorded sessions replay the same detection.
+        let transport = LocalTransport;
+        let installed = transport.run("which", &["ollama"]).await
+            .map(|output| output.success())
+            .unwrap_or(false);
+
+        installed && transport.run("curl", &["-s", "http://localhost:11434/api/tags"]).await
+            .map(|output| output.success())
+            .unwrap_or(false)
     }
 }
@@ -0,0 +1,136 @@
+//! Stack frames from the three places a backtrace can come from: the trace
+//! systemd-coredump stores with the crash, eu-stack and gdb.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::LazyLock;
+
+/// `#0  0x00007f3a5d6a8e2c __pthread_kill_implementation (libc.so.6 + 0x8ee2c)`, with
+/// "n/a" for frames without a symbol
+static COREDUMP_FRAME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^#\d+\s+(0x[0-9a-f]+)\s+(\S+)\s+\((\S+)(?: \+ 0x[0-9a-f]+)?\)").expect("valid regex")
+});
+/// `#0  0x00007f3a5d6a8e2c __pthread_kill_implementation - /usr/lib64/libc.so.6`, the
+/// source location following on its own indented line
+static EU_STACK_FRAME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^#\d+\s+(0x[0-9a-f]+)(?:\s+([^\s-]\S*))?(?:\s+-\s+(\S+))?").expect("valid regex")
+});
+/// `#1  0x0000555555555139 in main () at crash.c:5`, `#0  raise (sig=6) at raise.c:50` or
+/// `#2  0x00007ffff7c2a1ca in ?? () from /lib64/libc.so.6`
+static GDB_FRAME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^#\d+\s+(?:(0x[0-9a-f]+) in )?(\S+) \(.*?\)(?: (at|from) (\S+))?").expect("valid regex")
+});
+
+/// Frames signal delivery and abort() add on top of the code that crashed
+const SIGNAL_FRAMES: &[&str] = &[
+    "raise", "abort", "gsignal", "pthread_kill", "__GI_raise", "__GI_abort", "__pthread_kill_implementation",
+    "__pthread_kill_internal", "__assert_fail_base", "__assert_fail", "__libc_message", "__fortify_fail",
+    "__stack_chk_fail", "malloc_printerr",
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    pub address: Option<String>,
+    /// Symbol name, when the binary or its debug info has one
+    pub function: Option<String>,
+    /// Executable or shared library the frame is in
+    pub module: Option<String>,
+    /// "file.c:42" when debug info is installed
+    pub source: Option<String>,
+}
+
+impl Frame {
+    /// Whether the frame only delivers the signal, such as raise() under abort()
+    pub fn is_signal_frame(&self) -> bool {
+        self.function.as_deref().is_some_and(|function| SIGNAL_FRAMES.contains(&function))
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.function.as_deref().unwrap_or("??"))?;
+        if let Some(module) = &self.module {
+            write!(f, " ({})", module)?;
+        }
+        if let Some(source) = &self.source {
+            write!(f, " at {}", source)?;
+        }
+        Ok(())
+    }
+}
+
+fn symbol(name: &str) -> Option<String> {
+    Some(name.to_string()).filter(|name| !matches!(name.as_str(), "n/a" | "??"))
+}
+
+/// Frames of the first thread in `coredumpctl info`, which is the one that crashed
+pub fn parse_coredumpctl(info: &str) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    let mut in_trace = false;
+    for line in info.lines().map(str::trim) {
+        if line.starts_with("Stack trace of thread") {
+            if in_trace {
+                break;
+            }
+            in_trace = true;
+            continue;
+        }
+        if !in_trace {
+            continue;
+        }
+        let Some(captures) = COREDUMP_FRAME.captures(line) else {
+            if frames.is_empty() {
+                continue;
+            }
+            break;
+        };
+        frames.push(Frame {
+            address: Some(captures[1].to_string()),
+            function: symbol(&captures[2]),
+            module: Some(captures[3].to_string()),
+            source: None,
+        });
+    }
+    frames
+}
+
+/// Frames from `eu-stack -1 -m -s`
+pub fn parse_eu_stack(output: &str) -> Vec<Frame> {
+    let mut frames: Vec<Frame> = Vec::new();
+    for line in output.lines() {
+        if let Some(captures) = EU_STACK_FRAME.captures(line) {
+            frames.push(Frame {
+                address: Some(captures[1].to_string()),
+                function: captures.get(2).and_then(|function| symbol(function.as_str())),
+                module: captures.get(3).map(|module| module.as_str().to_string()),
+                source: None,
+            });
+        } else if line.starts_with(char::is_whitespace) && line.contains(':') {
+            if let Some(frame) = frames.last_mut() {
+                frame.source = Some(line.trim().to_string());
+            }
+        }
+    }
+    frames
+}
+
+/// Frames from gdb's `bt`
+pub fn parse_gdb(output: &str) -> Vec<Frame> {
+    output.lines()
+        .filter_map(|line| GDB_FRAME.captures(line.trim()))
+        .map(|captures| {
+            let location = captures.get(4).map(|location| location.as_str().to_string());
+            let (module, source) = match captures.get(3).map(|kind| kind.as_str()) {
+                Some("from") => (location, None),
+                _ => (None, location),
+            };
+            Frame {
+                address: captures.get(1).map(|address| address.as_str().to_string()),
+                function: symbol(&captures[2]),
+                module,
+                source,
+            }
+        })
+        .collect()
+}
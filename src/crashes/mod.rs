@@ -0,0 +1,456 @@
+//! Crashes and core dumps.
+//!
+//! systemd-coredump records every crash with the executable, the signal and, when
+//! elfutils is available to it, a symbolised backtrace of the crashing thread. Crashes
+//! are listed with `coredumpctl --json`, or from the core files in
+//! /var/lib/systemd/coredump where coredumpctl is missing or too old for JSON. When the
+//! stored trace has no symbols, the core is read again with eu-stack or gdb. Crashes
+//! with the same program, signal and innermost frames are grouped, so a crash that
+//! keeps coming back shows up once with its history.
+
+use chrono::{DateTime, Local, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::packages::PackageBackend;
+use crate::remote::transport::Transport;
+
+pub mod backtrace;
+
+pub use backtrace::Frame;
+
+pub const COREDUMP_DIR: &str = "/var/lib/systemd/coredump";
+/// Newest crashes looked at in detail; older ones are only counted
+const MAX_CRASHES: usize = 50;
+/// Crashes symbolised with eu-stack or gdb per report, since each reads a whole core
+const MAX_SYMBOLISED: usize = 5;
+/// Named frames below the signal delivery that identify a crash
+const SIGNATURE_FRAMES: usize = 3;
+const MAX_FRAMES: usize = 32;
+/// Compression systemd-coredump may use for core files
+const COMPRESSION_SUFFIXES: &[&str] = &[".zst", ".xz", ".lz4"];
+
+const SIGNALS: &[(i32, &str)] = &[
+    (3, "SIGQUIT"),
+    (4, "SIGILL"),
+    (5, "SIGTRAP"),
+    (6, "SIGABRT"),
+    (7, "SIGBUS"),
+    (8, "SIGFPE"),
+    (11, "SIGSEGV"),
+    (24, "SIGXCPU"),
+    (25, "SIGXFSZ"),
+    (31, "SIGSYS"),
+];
+
+/// Copies the core to a temporary file, decompressing it or asking coredumpctl for it,
+/// and prints its backtrace with eu-stack, or with gdb where eu-stack is missing. The
+/// first line names the tool. $1 is "coredumpctl" or the core file, $2 the PID and $3
+/// the executable, when known.
+const SYMBOLISE: &str = r#"core=$(mktemp) || exit 1
+trap 'rm -f "$core"' EXIT
+case "$1" in
+    coredumpctl) coredumpctl -q dump "$2" -o "$core" >/dev/null || exit 1 ;;
+    *.zst) zstd -dcq -- "$1" > "$core" || exit 1 ;;
+    *.xz) xz -dc -- "$1" > "$core" || exit 1 ;;
+    *.lz4) lz4 -dcq -- "$1" > "$core" || exit 1 ;;
+    *) cat -- "$1" > "$core" || exit 1 ;;
+esac
+if command -v eu-stack >/dev/null 2>&1; then
+    echo eu-stack
+    eu-stack -1 -m -s -n 32 --core="$core" ${3:+--executable="$3"}
+elif command -v gdb >/dev/null 2>&1 && [ -n "$3" ]; then
+    echo gdb
+    gdb -batch -nx -ex bt "$3" "$core" 2>/dev/null
+else
+    exit 2
+fi"#;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Crash {
+    pub at: DateTime<Utc>,
+    pub pid: u32,
+    pub uid: Option<u32>,
+    pub signal: Option<i32>,
+    /// Path of the executable, or only the command name when read from a core file's name
+    pub executable: String,
+    pub command_line: Option<String>,
+    pub unit: Option<String>,
+    /// Package that owns the executable
+    pub package: Option<String>,
+    /// "present", "missing", "truncated" or "none", as coredumpctl reports the core file
+    pub core: Option<String>,
+    /// The core file in /var/lib/systemd/coredump, when crashes were listed from there
+    pub core_file: Option<String>,
+    /// The crashing thread, innermost frame first
+    pub backtrace: Vec<Frame>,
+    /// "systemd-coredump", "eu-stack" or "gdb"
+    pub backtrace_source: Option<String>,
+}
+
+impl Crash {
+    pub fn program(&self) -> &str {
+        self.executable.rsplit('/').next().unwrap_or(&self.executable)
+    }
+
+    /// "SIGSEGV", or "signal 42" for ones without a name here
+    pub fn signal_name(&self) -> Option<String> {
+        let signal = self.signal?;
+        Some(SIGNALS.iter()
+            .find(|(number, _)| *number == signal)
+            .map(|(_, name)| name.to_string())
+            .unwrap_or_else(|| format!("signal {}", signal)))
+    }
+
+    fn symbolised(&self) -> bool {
+        self.backtrace.iter().any(|frame| frame.function.is_some())
+    }
+
+    /// What stays the same when the same bug crashes the program again: the program,
+    /// the signal and the innermost named frames below the signal delivery
+    pub fn signature(&self) -> String {
+        let frames: Vec<&str> = self.backtrace.iter()
+            .skip_while(|frame| frame.is_signal_frame())
+            .filter_map(|frame| frame.function.as_deref())
+            .take(SIGNATURE_FRAMES)
+            .collect();
+        let mut signature = format!("{} {}", self.program(), self.signal_name().unwrap_or_else(|| "crash".to_string()));
+        if !frames.is_empty() {
+            signature.push_str(&format!(" in {}", frames.join(" < ")));
+        }
+        signature
+    }
+}
+
+/// Crashes with the same signature
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashGroup {
+    pub signature: String,
+    pub executable: String,
+    pub signal: Option<String>,
+    pub package: Option<String>,
+    pub units: Vec<String>,
+    pub count: usize,
+    pub first: DateTime<Utc>,
+    pub last: DateTime<Utc>,
+    /// PIDs of the crashed processes
+    pub pids: Vec<u32>,
+    /// Backtrace of the newest crash with symbols, or of the newest crash when none has them
+    pub backtrace: Vec<Frame>,
+    pub backtrace_source: Option<String>,
+}
+
+impl fmt::Display for CrashGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.executable)?;
+        if let Some(package) = &self.package {
+            write!(f, " ({})", package)?;
+        }
+        write!(f, " killed by {}", self.signal.as_deref().unwrap_or("an unknown signal"))?;
+        if self.count > 1 {
+            write!(f, ", {} times since {}", self.count, self.first.with_timezone(&Local).format("%Y-%m-%d %H:%M"))?;
+        }
+        write!(f, ", last at {}", self.last.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"))?;
+        if !self.units.is_empty() {
+            write!(f, " in {}", self.units.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Crashes grouped by signature, the most recent first
+pub fn group(crashes: &[Crash]) -> Vec<CrashGroup> {
+    let mut groups: Vec<CrashGroup> = Vec::new();
+    // Symbolised crashes first, so the ones without a backtrace can join their groups
+    let mut ordered: Vec<&Crash> = crashes.iter().collect();
+    ordered.sort_by_key(|crash| Reverse((crash.symbolised(), crash.at)));
+
+    for crash in ordered {
+        // Crashes that could not be symbolised join the newest group of the same program
+        // and signal rather than starting one of their own
+        let signature = crash.signature();
+        let signal = crash.signal_name();
+        let existing = groups.iter_mut().find(|group| group.signature == signature
+            || (!crash.symbolised() && group.executable == crash.executable && group.signal == signal));
+        let Some(group) = existing else {
+            groups.push(CrashGroup {
+                signature,
+                executable: crash.executable.clone(),
+                signal,
+                package: crash.package.clone(),
+                units: crash.unit.iter().cloned().collect(),
+                count: 1,
+                first: crash.at,
+                last: crash.at,
+                pids: vec![crash.pid],
+                backtrace: crash.backtrace.clone(),
+                backtrace_source: crash.backtrace_source.clone(),
+            });
+            continue;
+        };
+        group.count += 1;
+        group.first = group.first.min(crash.at);
+        group.last = group.last.max(crash.at);
+        group.pids.push(crash.pid);
+        if let Some(unit) = &crash.unit {
+            if !group.units.contains(unit) {
+                group.units.push(unit.clone());
+            }
+        }
+        if group.package.is_none() {
+            group.package = crash.package.clone();
+        }
+        if group.backtrace.is_empty() {
+            group.backtrace = crash.backtrace.clone();
+            group.backtrace_source = crash.backtrace_source.clone();
+        }
+    }
+    groups.sort_by_key(|group| Reverse(group.last));
+    groups
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashReport {
+    pub hostname: String,
+    /// "coredumpctl" or the core dump directory
+    pub source: Option<String>,
+    /// Crashes found, including the older ones beyond those looked at in detail
+    pub total: usize,
+    pub groups: Vec<CrashGroup>,
+    /// Sources that could not be read
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct CrashInspector {
+    transport: Arc<dyn Transport>,
+    packages: Box<dyn PackageBackend>,
+}
+
+impl CrashInspector {
+    pub fn new(transport: Arc<dyn Transport>, packages: Box<dyn PackageBackend>) -> Self {
+        Self { transport, packages }
+    }
+
+    /// Crashes since `since`, newest first, with where they were found and what could not
+    /// be read
+    pub async fn list(&self, since: DateTime<Utc>) -> (Vec<Crash>, Option<String>, Vec<String>) {
+        let mut errors = Vec::new();
+        let since_arg = format!("--since=@{}", since.timestamp());
+        let unavailable = match self.transport.run("coredumpctl", &["--json=short", "--no-pager", &since_arg, "list"]).await {
+            Ok(output) if output.success() => match parse_list(&output.stdout) {
+                Some(mut crashes) => {
+                    crashes.sort_by_key(|crash| Reverse(crash.at));
+                    return (crashes, Some("coredumpctl".to_string()), errors);
+                }
+                None => "coredumpctl printed no JSON".to_string(),
+            },
+            // coredumpctl exits with 1 when nothing matched
+            Ok(output) if output.stderr.contains("No coredumps found") => {
+                return (Vec::new(), Some("coredumpctl".to_string()), errors);
+            }
+            Ok(output) => format!("coredumpctl: {}", output.stderr.trim()),
+            Err(e) => format!("coredumpctl: {}", e),
+        };
+
+        // Without coredumpctl, or with one that predates --json, the core files still
+        // carry the command, PID and time in their names
+        if !self.transport.path_exists(COREDUMP_DIR).await {
+            errors.push(unavailable);
+            return (Vec::new(), None, errors);
+        }
+        match self.transport.run("ls", &["-1", COREDUMP_DIR]).await {
+            Ok(output) if output.success() => {
+                let mut crashes: Vec<Crash> = output.stdout.lines()
+                    .filter_map(parse_core_name)
+                    .filter(|crash| crash.at >= since)
+                    .collect();
+                crashes.sort_by_key(|crash| Reverse(crash.at));
+                (crashes, Some(COREDUMP_DIR.to_string()), errors)
+            }
+            Ok(output) => {
+                errors.push(format!("{}: {}", COREDUMP_DIR, output.stderr.trim()));
+                (Vec::new(), None, errors)
+            }
+            Err(e) => {
+                errors.push(format!("{}: {}", COREDUMP_DIR, e));
+                (Vec::new(), None, errors)
+            }
+        }
+    }
+
+    /// Crashes since `since`, with details and backtraces, grouped by signature
+    pub async fn report(&self, since: DateTime<Utc>) -> CrashReport {
+        let (crashes, source, errors) = self.list(since).await;
+        CrashReport {
+            hostname: self.transport.host_name().to_string(),
+            source,
+            total: crashes.len(),
+            groups: self.explain(crashes).await,
+            errors,
+        }
+    }
+
+    /// Details, package owners and backtraces of the newest crashes, grouped by signature
+    pub async fn explain(&self, mut crashes: Vec<Crash>) -> Vec<CrashGroup> {
+        crashes.truncate(MAX_CRASHES);
+        let mut symbolised = 0;
+        let mut owners: HashMap<String, Option<String>> = HashMap::new();
+        for crash in &mut crashes {
+            if self.inspect(crash, symbolised < MAX_SYMBOLISED).await {
+                symbolised += 1;
+            }
+            if crash.package.is_none() && crash.executable.starts_with('/') {
+                if !owners.contains_key(&crash.executable) {
+                    let owner = self.packages.owns_file(&crash.executable).await.ok().flatten();
+                    owners.insert(crash.executable.clone(), owner);
+                }
+                crash.package = owners[&crash.executable].clone();
+            }
+        }
+        group(&crashes)
+    }
+
+    /// Fill in what `coredumpctl info` knows about the crash, and symbolise the core
+    /// with eu-stack or gdb when the stored trace has no names and `symbolise` allows
+    /// it. Returns whether the core was read.
+    async fn inspect(&self, crash: &mut Crash, symbolise: bool) -> bool {
+        if crash.core_file.is_none() {
+            let pid = crash.pid.to_string();
+            if let Ok(output) = self.transport.run("coredumpctl", &["info", "-1", "--no-pager", &pid]).await {
+                if output.success() {
+                    apply_info(crash, &output.stdout);
+                }
+            }
+        }
+
+        let core_available = crash.core_file.is_some() || crash.core.as_deref() == Some("present");
+        if crash.symbolised() || !symbolise || !core_available {
+            return false;
+        }
+        let source = crash.core_file.clone().unwrap_or_else(|| "coredumpctl".to_string());
+        let pid = crash.pid.to_string();
+        let executable = if crash.executable.starts_with('/') { crash.executable.as_str() } else { "" };
+        let Ok(output) = self.transport.run("sh", &["-c", SYMBOLISE, "sh", &source, &pid, executable]).await else {
+            return true;
+        };
+        if !output.success() {
+            return true;
+        }
+        let (tool, trace) = output.stdout.split_once('\n').unwrap_or((output.stdout.as_str(), ""));
+        let frames = match tool.trim() {
+            "eu-stack" => backtrace::parse_eu_stack(trace),
+            "gdb" => backtrace::parse_gdb(trace),
+            _ => Vec::new(),
+        };
+        if frames.iter().any(|frame| frame.function.is_some()) || crash.backtrace.is_empty() {
+            crash.backtrace = frames.into_iter().take(MAX_FRAMES).collect();
+            crash.backtrace_source = Some(tool.trim().to_string());
+        }
+        true
+    }
+}
+
+/// Crashes from `coredumpctl --json=short list`, `None` when the output is not JSON
+fn parse_list(output: &str) -> Option<Vec<Crash>> {
+    let Value::Array(entries) = serde_json::from_str(output.trim()).ok()? else {
+        return None;
+    };
+    Some(entries.iter()
+        .filter_map(|entry| {
+            let micros = entry.get("time")?.as_i64()?;
+            Some(Crash {
+                at: Utc.timestamp_micros(micros).single()?,
+                pid: entry.get("pid")?.as_u64()? as u32,
+                uid: entry.get("uid").and_then(Value::as_u64).map(|uid| uid as u32),
+                signal: entry.get("sig").and_then(Value::as_i64).map(|signal| signal as i32),
+                executable: entry.get("exe").and_then(Value::as_str).unwrap_or("unknown").to_string(),
+                command_line: None,
+                unit: None,
+                package: None,
+                core: entry.get("corefile").and_then(Value::as_str).map(String::from),
+                core_file: None,
+                backtrace: Vec::new(),
+                backtrace_source: None,
+            })
+        })
+        .collect())
+}
+
+/// A core file named `core.<command>.<uid>.<boot id>.<pid>.<microseconds>`, usually
+/// compressed. Dots and slashes in the command are escaped as \x2e and \x2f.
+fn parse_core_name(name: &str) -> Option<Crash> {
+    let rest = name.strip_prefix("core.")?;
+    let rest = COMPRESSION_SUFFIXES.iter().find_map(|suffix| rest.strip_suffix(suffix)).unwrap_or(rest);
+    let mut parts = rest.rsplitn(5, '.');
+    let micros = parts.next()?.parse::<i64>().ok()?;
+    let pid = parts.next()?.parse().ok()?;
+    let _boot_id = parts.next()?;
+    let uid = parts.next()?.parse().ok();
+    let command = unescape(parts.next()?);
+
+    Some(Crash {
+        at: Utc.timestamp_micros(micros).single()?,
+        pid,
+        uid,
+        signal: None,
+        executable: command,
+        command_line: None,
+        unit: None,
+        package: None,
+        core: Some("present".to_string()),
+        core_file: Some(format!("{}/{}", COREDUMP_DIR, name)),
+        backtrace: Vec::new(),
+        backtrace_source: None,
+    })
+}
+
+fn unescape(text: &str) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("\\x") {
+        result.push_str(&rest[..start]);
+        let byte = rest.get(start + 2..start + 4).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match byte {
+            Some(byte) => {
+                result.push(byte as char);
+                rest = &rest[start + 4..];
+            }
+            None => {
+                result.push_str("\\x");
+                rest = &rest[start + 2..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// The "Key: value" lines and stack trace of `coredumpctl info`
+fn apply_info(crash: &mut Crash, info: &str) {
+    for line in info.lines() {
+        let Some((key, value)) = line.trim().split_once(": ") else {
+            continue;
+        };
+        let value = value.trim();
+        match key {
+            // "11 (SEGV)"
+            "Signal" => crash.signal = value.split_whitespace().next().and_then(|signal| signal.parse().ok()).or(crash.signal),
+            "Executable" => crash.executable = value.to_string(),
+            "Command Line" => crash.command_line = Some(value.to_string()),
+            "Unit" | "User Unit" => crash.unit = crash.unit.clone().or_else(|| Some(value.to_string())),
+            // Recorded from the binary's package metadata by newer systemd, as "name/version"
+            "Package" => crash.package = Some(value.replace('/', " ")),
+            _ => {}
+        }
+    }
+    let frames = backtrace::parse_coredumpctl(info);
+    if !frames.is_empty() {
+        crash.backtrace = frames.into_iter().take(MAX_FRAMES).collect();
+        crash.backtrace_source = Some("systemd-coredump".to_string());
+    }
+}
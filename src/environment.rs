@@ -0,0 +1,302 @@
+//! Runtime environment detection.
+//!
+//! Inside a container the kernel, boot loader and hardware belong to the host, and
+//! often no service manager runs as PID 1. WSL boots Microsoft's kernel without a boot
+//! loader and only runs systemd when it is enabled in `/etc/wsl.conf`, and virtual
+//! machines have no physical disks or sensors. The checks mirror what
+//! `systemd-detect-virt` looks at (container marker files, PID 1's cgroups, DMI
+//! vendor strings and the CPU's hypervisor flag) but read the files directly, so they
+//! also work where systemd is missing and over SSH.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::remote::transport::Transport;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum EnvironmentKind {
+    #[default]
+    BareMetal,
+    VirtualMachine,
+    Container,
+    /// Windows Subsystem for Linux
+    Wsl,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvironmentInfo {
+    pub kind: EnvironmentKind,
+    /// Container engine or hypervisor, named like `systemd-detect-virt` does, such as
+    /// "docker", "lxc", "kvm" or "wsl2"
+    pub technology: Option<String>,
+    /// Command name of PID 1
+    pub init_process: Option<String>,
+    /// Whether a service manager runs as PID 1, so services can be started and enabled
+    pub service_manager_running: bool,
+}
+
+impl Default for EnvironmentInfo {
+    fn default() -> Self {
+        Self {
+            kind: EnvironmentKind::BareMetal,
+            technology: None,
+            init_process: None,
+            service_manager_running: true,
+        }
+    }
+}
+
+impl fmt::Display for EnvironmentInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            EnvironmentKind::BareMetal => write!(f, "Bare metal")?,
+            EnvironmentKind::VirtualMachine => write!(f, "Virtual machine")?,
+            EnvironmentKind::Container => write!(f, "Container")?,
+            EnvironmentKind::Wsl => write!(f, "Windows Subsystem for Linux")?,
+        }
+        if let Some(technology) = &self.technology {
+            write!(f, " ({})", technology)?;
+        }
+        Ok(())
+    }
+}
+
+/// PID 1 names of init systems that manage services
+const INIT_PROCESSES: &[&str] = &["systemd", "init", "openrc-init", "runit", "runit-init", "s6-svscan", "dinit"];
+/// Commands that talk to the service manager
+const SERVICE_COMMANDS: &[&str] = &["systemctl", "service", "rc-service", "rc-update", "update-rc.d", "chkconfig"];
+/// Commands that change the running kernel
+const KERNEL_COMMANDS: &[&str] = &["modprobe", "insmod", "rmmod", "sysctl"];
+/// Commands that change how the kernel is booted
+const BOOT_COMMANDS: &[&str] = &[
+    "update-grub", "grub-mkconfig", "grub2-mkconfig", "grub-install", "grubby", "bootctl", "efibootmgr",
+    "mkinitcpio", "dracut", "update-initramfs", "kexec",
+];
+/// Commands that only make sense with physical hardware
+const HARDWARE_COMMANDS: &[&str] = &["smartctl", "hdparm", "sensors", "sensors-detect", "fwupdmgr", "ipmitool"];
+
+/// DMI vendor and product strings of hypervisors, and the names `systemd-detect-virt` uses for them
+const DMI_VENDORS: &[(&str, &str)] = &[
+    ("KVM", "kvm"),
+    ("Amazon EC2", "amazon"),
+    ("QEMU", "qemu"),
+    ("VMware", "vmware"),
+    ("VMW", "vmware"),
+    ("innotek GmbH", "oracle"),
+    ("VirtualBox", "oracle"),
+    ("Xen", "xen"),
+    ("Bochs", "bochs"),
+    ("Parallels", "parallels"),
+    ("BHYVE", "bhyve"),
+    ("Google Compute Engine", "google"),
+    ("OpenStack", "openstack"),
+    ("Apple Virtualization", "apple"),
+];
+const DMI_FILES: &[&str] = &[
+    "/sys/class/dmi/id/product_name",
+    "/sys/class/dmi/id/sys_vendor",
+    "/sys/class/dmi/id/board_vendor",
+    "/sys/class/dmi/id/bios_vendor",
+];
+
+/// Detect the environment of the machine behind `transport`
+pub async fn detect(transport: &dyn Transport) -> EnvironmentInfo {
+    let init_process = transport.read_file("/proc/1/comm").await.ok()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty());
+    let systemd_running = transport.path_exists("/run/systemd/system").await;
+
+    let (kind, technology) = if let Some(version) = wsl_version(transport).await {
+        (EnvironmentKind::Wsl, Some(format!("wsl{}", version)))
+    } else if let Some(engine) = container_engine(transport).await {
+        (EnvironmentKind::Container, Some(engine))
+    } else if let Some(hypervisor) = hypervisor(transport).await {
+        (EnvironmentKind::VirtualMachine, Some(hypervisor).filter(|name| name != "unknown"))
+    } else {
+        (EnvironmentKind::BareMetal, None)
+    };
+
+    // WSL's own PID 1 is also called "init" but only starts the distribution
+    let service_manager_running = systemd_running || (kind != EnvironmentKind::Wsl
+        && init_process.as_deref().is_some_and(|name| INIT_PROCESSES.contains(&name)));
+
+    EnvironmentInfo { kind, technology, init_process, service_manager_running }
+}
+
+async fn wsl_version(transport: &dyn Transport) -> Option<u8> {
+    // "5.15.153.1-microsoft-standard-WSL2" on WSL2, "4.4.0-19041-Microsoft" on WSL1
+    let release = transport.read_file("/proc/sys/kernel/osrelease").await.unwrap_or_default();
+    if release.contains("WSL2") || release.contains("microsoft-standard") {
+        return Some(2);
+    }
+    if release.contains("Microsoft") {
+        return Some(1);
+    }
+    transport.path_exists("/proc/sys/fs/binfmt_misc/WSLInterop").await.then_some(2)
+}
+
+async fn container_engine(transport: &dyn Transport) -> Option<String> {
+    // Written by systemd and most container managers, e.g. "docker", "lxc" or "systemd-nspawn"
+    if let Ok(engine) = transport.read_file("/run/systemd/container").await {
+        if !engine.trim().is_empty() {
+            return Some(engine.trim().to_string());
+        }
+    }
+    if transport.path_exists("/.dockerenv").await {
+        return Some("docker".to_string());
+    }
+    if transport.path_exists("/run/.containerenv").await {
+        return Some("podman".to_string());
+    }
+
+    // Only readable as root; the variable container managers set for PID 1
+    if let Ok(environ) = transport.read_file("/proc/1/environ").await {
+        if let Some(engine) = environ.split('\0').find_map(|variable| variable.strip_prefix("container=")) {
+            return Some(engine.to_string());
+        }
+    }
+
+    // cgroup v1 paths name the engine; with cgroup v2 a container only sees "0::/"
+    let cgroups = transport.read_file("/proc/1/cgroup").await.unwrap_or_default();
+    for (marker, engine) in [("kubepods", "kubernetes"), ("docker", "docker"), ("libpod", "podman"), ("lxc", "lxc")] {
+        if cgroups.contains(marker) {
+            return Some(engine.to_string());
+        }
+    }
+    if transport.path_exists("/var/run/secrets/kubernetes.io/serviceaccount").await {
+        return Some("kubernetes".to_string());
+    }
+    if transport.path_exists("/proc/vz").await && !transport.path_exists("/proc/bc").await {
+        return Some("openvz".to_string());
+    }
+    None
+}
+
+/// Hypervisor name, "unknown" when only the CPU says there is one
+async fn hypervisor(transport: &dyn Transport) -> Option<String> {
+    let mut dmi = Vec::new();
+    for path in DMI_FILES {
+        if let Ok(value) = transport.read_file(path).await {
+            dmi.push(value.trim().to_string());
+        }
+    }
+    for (vendor, name) in DMI_VENDORS {
+        if dmi.iter().any(|value| value.starts_with(vendor)) {
+            return Some(name.to_string());
+        }
+    }
+    // Hyper-V; Microsoft's own hardware uses the same vendor string
+    if dmi.iter().any(|value| value == "Virtual Machine") && dmi.iter().any(|value| value.starts_with("Microsoft")) {
+        return Some("microsoft".to_string());
+    }
+
+    if let Ok(kind) = transport.read_file("/sys/hypervisor/type").await {
+        // dom0 runs on Xen too but owns the hardware
+        let capabilities = transport.read_file("/proc/xen/capabilities").await.unwrap_or_default();
+        if kind.trim() == "xen" && !capabilities.contains("control_d") {
+            return Some("xen".to_string());
+        }
+    }
+
+    // The CPUID hypervisor bit, which every x86 hypervisor sets
+    let cpuinfo = transport.read_file("/proc/cpuinfo").await.unwrap_or_default();
+    cpuinfo.lines()
+        .filter(|line| line.starts_with("flags"))
+        .any(|line| line.split_whitespace().any(|flag| flag == "hypervisor"))
+        .then(|| "unknown".to_string())
+}
+
+impl EnvironmentInfo {
+    pub fn is_bare_metal(&self) -> bool {
+        self.kind == EnvironmentKind::BareMetal
+    }
+
+    /// Kernel modules and parameters can be changed from here
+    pub fn can_change_kernel(&self) -> bool {
+        matches!(self.kind, EnvironmentKind::BareMetal | EnvironmentKind::VirtualMachine)
+    }
+
+    /// The boot loader and initramfs are part of this system
+    pub fn can_change_boot(&self) -> bool {
+        self.can_change_kernel()
+    }
+
+    /// Why `program args` cannot work in this environment. `None` when it can.
+    pub fn restriction(&self, program: &str, args: &[String]) -> Option<String> {
+        if program == "sudo" {
+            let (program, args) = args.split_first()?;
+            return self.restriction(program, args);
+        }
+
+        let user_manager = args.iter().any(|arg| arg == "--user");
+        if SERVICE_COMMANDS.contains(&program) && !self.service_manager_running && !user_manager {
+            return Some(match self.kind {
+                EnvironmentKind::Wsl => format!(
+                    "systemd is not enabled in this WSL distribution, so `{}` cannot work. Add \"[boot]\" with \
+                     \"systemd=true\" to /etc/wsl.conf and run `wsl --shutdown` from Windows, or start the program directly",
+                    program
+                ),
+                _ => format!(
+                    "No service manager runs as PID 1 in this {} (PID 1 is {}), so `{}` cannot work. Start the program \
+                     in the foreground or from the container's entrypoint instead",
+                    self, self.init_process.as_deref().unwrap_or("unknown"), program
+                ),
+            });
+        }
+
+        let writes_sysctl = program == "sysctl" && args.iter().any(|arg| {
+            arg == "-w" || arg == "-p" || arg == "--system" || arg.starts_with("--load") || arg.contains('=')
+        });
+        let changes_kernel = KERNEL_COMMANDS.contains(&program) && (program != "sysctl" || writes_sysctl);
+        if changes_kernel && self.kind == EnvironmentKind::Container {
+            return Some(format!(
+                "The kernel belongs to the container host, so `{}` has to run on the host rather than inside the container",
+                program
+            ));
+        }
+
+        if BOOT_COMMANDS.contains(&program) && !self.can_change_boot() {
+            return Some(match self.kind {
+                EnvironmentKind::Wsl => format!(
+                    "WSL boots Microsoft's kernel without a boot loader or initramfs, so `{}` has no effect. Set kernel \
+                     options with kernelCommandLine in %UserProfile%\\.wslconfig and restart with `wsl --shutdown`",
+                    program
+                ),
+                _ => format!("The container host boots the kernel, so `{}` has to run on the host", program),
+            });
+        }
+
+        if HARDWARE_COMMANDS.contains(&program) && !self.is_bare_metal() {
+            return Some(format!(
+                "This is a {}, which only has virtual devices, so `{}` has nothing to inspect. Check the physical host instead",
+                self, program
+            ));
+        }
+
+        None
+    }
+
+    /// What the AI should not suggest here, `None` on bare metal with a service manager
+    pub fn prompt_note(&self) -> Option<String> {
+        let mut limits = Vec::new();
+        if !self.service_manager_running {
+            limits.push("no service manager runs as PID 1, so never suggest systemctl, service or rc-service; run programs directly");
+        }
+        match self.kind {
+            EnvironmentKind::Container => limits.push(
+                "the kernel, boot loader and hardware belong to the host, so never suggest modprobe, sysctl changes, \
+                 boot loader or initramfs changes, or disk and sensor tools; say they have to be done on the host"),
+            EnvironmentKind::Wsl => limits.push(
+                "the kernel comes from Windows, so kernel options go into %UserProfile%\\.wslconfig instead of a boot \
+                 loader, and there are no physical disks or sensors"),
+            EnvironmentKind::VirtualMachine => limits.push(
+                "disks and sensors are virtual, so hardware problems have to be checked on the hypervisor host"),
+            EnvironmentKind::BareMetal => {}
+        }
+
+        if limits.is_empty() {
+            return None;
+        }
+        Some(format!("The system runs as {}: {}.", self, limits.join("; ")))
+    }
+}
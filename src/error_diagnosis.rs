@@ -1,12 +1,29 @@
 use anyhow::{Context, Result};
 use regex::Regex;
-use std::fs;
 use std::path::Path;
 use chrono::{DateTime, Utc};
 
+use crate::crashes::{Crash, CrashGroup};
+use crate::journal::{JournalQuery, JournalReader, LogRecord};
+use crate::kernel_log::{self, EventKind, KernelEvent, KernelIssue};
 use crate::linux_integration::LinuxIntegration;
+use crate::mac::{self, DenialGroup};
 
 pub mod advanced;
+pub mod log_formats;
+
+use log_formats::{ErrorCluster, ParsedLog};
+
+/// How far back crashes are looked for
+const CRASH_HOURS: i64 = 24;
+/// Crash groups attached to a diagnostic, the most recent first
+const MAX_DIAGNOSED_CRASHES: usize = 3;
+/// Words that say a program crashed rather than reported an error
+const CRASH_WORDS: &[&str] = &["segfault", "segmentation fault", "core dumped", "dumped core", "sigsegv", "sigabrt", "aborted", "crash"];
+/// Kernel faults and hardware errors attached to a diagnostic, the most recent first
+const MAX_DIAGNOSED_KERNEL_ISSUES: usize = 5;
+/// Words that point at the kernel, a driver or a device rather than at a program
+const KERNEL_WORDS: &[&str] = &["kernel", "driver", "oops", "call trace", "tainted", "machine check", "hardware error", "edac", "pcie", "nvme"];
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ErrorDiagnostic {
@@ -17,6 +34,12 @@ pub struct ErrorDiagnostic {
     pub timestamp: DateTime<Utc>,
     pub severity: ErrorSeverity,
     pub category: ErrorCategory,
+    /// Recent crashes of the programs involved, with their backtraces
+    #[serde(default)]
+    pub crashes: Vec<CrashGroup>,
+    /// Kernel faults and hardware errors involved, with their drivers and packages
+    #[serde(default)]
+    pub kernel: Vec<KernelIssue>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -35,17 +58,44 @@ pub enum ErrorCategory {
     Service,
     Hardware,
     Permission,
+    /// Denied by SELinux or AppArmor, which looks like a permission error
+    AccessControl,
     Unknown,
 }
 
 impl ErrorDiagnostic {
     pub async fn analyze_error(error_input: &str, linux_integration: &LinuxIntegration) -> Result<Self> {
-        let category = Self::categorize_error(error_input);
-        let severity = Self::assess_severity(error_input, &category);
+        let mut category = Self::categorize_error(error_input);
+        let mut severity = Self::assess_severity(error_input, &category);
         
         let system_info = linux_integration.get_system_info().await?;
-        let relevant_logs = Self::extract_relevant_logs(error_input, linux_integration).await?;
+        let mut relevant_logs = Self::extract_relevant_logs(error_input, linux_integration).await?;
+
+        // Permission errors are often SELinux or AppArmor denials in disguise
+        if matches!(category, ErrorCategory::Permission | ErrorCategory::AccessControl) {
+            let denials = Self::access_denials(error_input, linux_integration).await;
+            if !denials.is_empty() {
+                category = ErrorCategory::AccessControl;
+                let mut lines = Self::describe_denials(&denials, true);
+                lines.append(&mut relevant_logs);
+                relevant_logs = lines;
+            }
+        }
         
+        // A program that crashed is worse than one that reported an error
+        let crashes = Self::related_crashes(error_input, linux_integration).await;
+        if !crashes.is_empty() && !matches!(severity, ErrorSeverity::Critical) {
+            severity = ErrorSeverity::High;
+        }
+
+        // So is a fault in the kernel, and the kernel going down is as bad as it gets
+        let kernel = Self::kernel_issues(error_input, linux_integration).await;
+        if kernel.iter().any(|issue| issue.kind == EventKind::Panic) {
+            severity = ErrorSeverity::Critical;
+        } else if kernel.iter().any(|issue| issue.serious) && !matches!(severity, ErrorSeverity::Critical) {
+            severity = ErrorSeverity::High;
+        }
+
         Ok(Self {
             error_message: error_input.to_string(),
             context: Self::build_context(error_input, &system_info),
@@ -54,12 +104,23 @@ impl ErrorDiagnostic {
             timestamp: Utc::now(),
             severity,
             category,
+            crashes,
+            kernel,
         })
     }
 
     pub async fn analyze_system_logs(linux_integration: &LinuxIntegration) -> Result<Self> {
-        let recent_errors = Self::scan_recent_errors(linux_integration).await?;
+        let mut recent_errors = Self::scan_recent_errors(linux_integration).await?;
         let system_info = linux_integration.get_system_info().await?;
+        let inspector = linux_integration.crashes();
+        let (recent_crashes, _, _) = inspector.list(Utc::now() - chrono::Duration::hours(CRASH_HOURS)).await;
+        let mut crashes = inspector.explain(recent_crashes).await;
+        crashes.truncate(MAX_DIAGNOSED_CRASHES);
+
+        // Oopses, warnings and hardware errors of this boot
+        let (events, _, _) = linux_integration.kernel_log().events("0").await;
+        let kernel = Self::explain_kernel_events(&events, linux_integration).await;
+        recent_errors.extend(kernel.iter().map(|issue| issue.to_string()));
         
         let error_message = if recent_errors.is_empty() {
             "No recent errors found in system logs".to_string()
@@ -75,13 +136,90 @@ impl ErrorDiagnostic {
             timestamp: Utc::now(),
             severity: ErrorSeverity::Medium,
             category: ErrorCategory::System,
+            crashes,
+            kernel,
+        })
+    }
+
+    /// Diagnose a parsed log file. The most severe error cluster becomes the error and
+    /// every cluster's surrounding lines become the relevant logs.
+    pub async fn analyze_log(origin: &str, log: &ParsedLog, clusters: &[ErrorCluster], linux_integration: &LinuxIntegration) -> Result<Self> {
+        let system_info = linux_integration.get_system_info().await?;
+        let all_errors: Vec<&str> = clusters.iter()
+            .flat_map(|cluster| cluster.errors.iter().map(|line| line.message.as_str()))
+            .collect();
+
+        let error_message = match clusters.first() {
+            None => format!("No errors or warnings found in {}", origin),
+            Some(cluster) => {
+                let mut message = cluster.errors.iter()
+                    .take(3)
+                    .map(|line| line.message.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                if clusters.len() > 1 {
+                    message.push_str(&format!("\n(and {} more error clusters)", clusters.len() - 1));
+                }
+                message
+            }
+        };
+
+        let category = Self::categorize_error(&all_errors.join("\n"));
+        let severity = Self::assess_severity(&all_errors.join("\n"), &category);
+
+        let mut relevant_logs = Vec::new();
+        for cluster in clusters {
+            relevant_logs.push(format!("--- {} {}", origin, cluster.describe()));
+            relevant_logs.extend(cluster.context.iter().cloned());
+        }
+        if !clusters.is_empty() {
+            relevant_logs.extend(Self::extract_relevant_logs(&error_message, linux_integration).await?);
+        }
+
+        let crashes = Self::related_crashes(&all_errors.join("\n"), linux_integration).await;
+
+        // Oopses and hardware errors in the log itself, whose call traces are not errors
+        let logged = kernel_log::parse(log.lines.iter().map(|line| (None, line.raw.as_str())));
+        let kernel = if logged.is_empty() {
+            Self::kernel_issues(&all_errors.join("\n"), linux_integration).await
+        } else {
+            Self::explain_kernel_events(&logged, linux_integration).await
+        };
+
+        let timestamps: Vec<_> = log.lines.iter().filter_map(|line| line.timestamp).collect();
+        let period = match (timestamps.iter().min(), timestamps.iter().max()) {
+            (Some(first), Some(last)) => format!(", {} to {}", first, last),
+            _ => String::new(),
+        };
+
+        Ok(Self {
+            context: format!(
+                "{}\nLog file {} ({}, {} lines{}), {} error clusters",
+                Self::build_context(&error_message, &system_info),
+                origin, log.format, log.lines.len(), period, clusters.len()
+            ),
+            error_message,
+            system_info,
+            relevant_logs,
+            timestamp: Utc::now(),
+            severity,
+            category,
+            crashes,
+            kernel,
         })
     }
 
     fn categorize_error(error_message: &str) -> ErrorCategory {
         let error_lower = error_message.to_lowercase();
+        let kernel_events = kernel_log::parse_text(error_message);
         
-        if error_lower.contains("network") || error_lower.contains("connection") || 
+        if error_message.lines().any(|line| mac::parse_denial(line).is_some()) {
+            ErrorCategory::AccessControl
+        } else if kernel_events.iter().any(|event| event.kind.is_hardware()) {
+            ErrorCategory::Hardware
+        } else if !kernel_events.is_empty() {
+            ErrorCategory::System
+        } else if error_lower.contains("network") || error_lower.contains("connection") || 
            error_lower.contains("dns") || error_lower.contains("timeout") {
             ErrorCategory::Network
         } else if error_lower.contains("package") || error_lower.contains("pacman") || 
@@ -138,9 +276,23 @@ impl ErrorDiagnostic {
         // Extract keywords from error message for log searching
         let keywords = Self::extract_keywords(error_input);
         
+        // Units named in the error have their own journal entries for this boot
+        let reader = JournalReader::new(linux_integration.transport().clone());
+        for unit in keywords.iter().filter(|keyword| [".service", ".timer", ".socket"].iter().any(|suffix| keyword.ends_with(suffix))) {
+            let query = JournalQuery {
+                units: vec![unit.clone()],
+                boot: Some("0".to_string()),
+                lines: Some(5),
+                ..Default::default()
+            };
+            if let Ok(records) = reader.read(&query).await {
+                relevant_logs.extend(records.iter().rev().map(|record| format!("journal: {}", record)));
+            }
+        }
+
         // Search through system logs
         for log_path in &linux_integration.config.system.log_paths {
-            if let Ok(logs) = Self::search_logs(log_path, &keywords).await {
+            if let Ok(logs) = Self::search_logs(linux_integration, log_path, &keywords).await {
                 relevant_logs.extend(logs);
             }
         }
@@ -153,27 +305,143 @@ impl ErrorDiagnostic {
     async fn scan_recent_errors(linux_integration: &LinuxIntegration) -> Result<Vec<String>> {
         let mut errors = Vec::new();
         
-        // Scan journalctl for recent errors
-        if let Ok(output) = linux_integration.execute_command("journalctl", &["-p", "err", "-n", "20", "--no-pager"]).await {
-            let lines: Vec<String> = output.lines()
-                .filter(|line| !line.trim().is_empty())
-                .map(|s| s.to_string())
-                .collect();
-            errors.extend(lines);
+        // Scan the journal for errors in this boot
+        let reader = JournalReader::new(linux_integration.transport().clone());
+        let query = JournalQuery {
+            priority: Some(3),
+            boot: Some("0".to_string()),
+            lines: Some(20),
+            ..Default::default()
+        };
+        if let Ok(records) = reader.read(&query).await {
+            errors.extend(records.iter().map(|record| record.to_string()));
+            errors.extend(Self::unit_warnings(&reader, &records).await);
         }
 
-        // Scan dmesg for kernel errors
-        if let Ok(output) = linux_integration.execute_command("dmesg", &["-l", "err", "-T"]).await {
-            let lines: Vec<String> = output.lines()
-                .take(10)
-                .map(|s| s.to_string())
-                .collect();
-            errors.extend(lines);
+        // SELinux and AppArmor denials don't show up as errors
+        let inspector = linux_integration.mac();
+        if inspector.status().await.enforcing() {
+            let (denials, _, _) = inspector.denials(Utc::now() - chrono::Duration::hours(1)).await;
+            let groups: Vec<DenialGroup> = mac::group(&denials).into_iter().filter(|group| group.enforced).collect();
+            errors.extend(Self::describe_denials(&groups, false));
         }
 
         Ok(errors)
     }
 
+    /// Denials behind a permission error: the ones quoted in the error itself, or else
+    /// the ones enforced in the last hour
+    async fn access_denials(error_input: &str, linux_integration: &LinuxIntegration) -> Vec<DenialGroup> {
+        let inspector = linux_integration.mac();
+        let quoted: Vec<_> = error_input.lines().filter_map(mac::parse_denial).collect();
+        if !quoted.is_empty() {
+            return inspector.explain(&quoted).await;
+        }
+        if !inspector.status().await.enforcing() {
+            return Vec::new();
+        }
+        let (denials, _, _) = inspector.denials(Utc::now() - chrono::Duration::hours(1)).await;
+        let enforced: Vec<_> = denials.into_iter().filter(|denial| !denial.permissive).collect();
+        let mut groups = inspector.explain(&enforced).await;
+        groups.truncate(5);
+        groups
+    }
+
+    /// Recent crashes of programs named in `text`, or every recent crash when the text
+    /// says something crashed without naming what
+    async fn related_crashes(text: &str, linux_integration: &LinuxIntegration) -> Vec<CrashGroup> {
+        let inspector = linux_integration.crashes();
+        let (recent, _, _) = inspector.list(Utc::now() - chrono::Duration::hours(CRASH_HOURS)).await;
+        if recent.is_empty() {
+            return Vec::new();
+        }
+
+        let text_lower = text.to_lowercase();
+        let names_program = |crash: &Crash| {
+            let program = regex::escape(&crash.program().to_lowercase());
+            Regex::new(&format!(r"\b{}\b", program)).is_ok_and(|pattern| pattern.is_match(&text_lower))
+        };
+        let named: Vec<Crash> = recent.iter().filter(|crash| names_program(crash)).cloned().collect();
+        let crashes = if !named.is_empty() {
+            named
+        } else if CRASH_WORDS.iter().any(|word| text_lower.contains(word)) {
+            recent
+        } else {
+            return Vec::new();
+        };
+
+        let mut groups = inspector.explain(crashes).await;
+        groups.truncate(MAX_DIAGNOSED_CRASHES);
+        groups
+    }
+
+    /// Kernel faults and hardware errors behind `text`: the ones quoted in it, or else
+    /// this boot's when the text is about the kernel, a driver or a device
+    async fn kernel_issues(text: &str, linux_integration: &LinuxIntegration) -> Vec<KernelIssue> {
+        let quoted = kernel_log::parse_text(text);
+        if !quoted.is_empty() {
+            return Self::explain_kernel_events(&quoted, linux_integration).await;
+        }
+        let text_lower = text.to_lowercase();
+        if !KERNEL_WORDS.iter().any(|word| text_lower.contains(word)) {
+            return Vec::new();
+        }
+        let (events, _, _) = linux_integration.kernel_log().events("0").await;
+        Self::explain_kernel_events(&events, linux_integration).await
+    }
+
+    async fn explain_kernel_events(events: &[KernelEvent], linux_integration: &LinuxIntegration) -> Vec<KernelIssue> {
+        if events.is_empty() {
+            return Vec::new();
+        }
+        let mut issues = linux_integration.kernel_log().explain(events).await;
+        issues.truncate(MAX_DIAGNOSED_KERNEL_ISSUES);
+        issues
+    }
+
+    fn describe_denials(groups: &[DenialGroup], with_fixes: bool) -> Vec<String> {
+        let mut lines = Vec::new();
+        for group in groups {
+            let service = match (&group.service, group.service_failed) {
+                (Some(service), true) => format!(" [{} failed]", service),
+                (Some(service), false) => format!(" [{}]", service),
+                (None, _) => String::new(),
+            };
+            lines.push(format!("{} denial{}: {}", group.system, service, group.explanation()));
+            if with_fixes {
+                lines.extend(group.fixes.iter().map(|fix| format!("  possible fix - {}: {}", fix.title, fix.reason)));
+            }
+        }
+        lines
+    }
+
+    /// Warnings the units behind `errors` logged earlier in the same boot, which often
+    /// explain the error itself
+    async fn unit_warnings(reader: &JournalReader, errors: &[LogRecord]) -> Vec<String> {
+        let mut units: Vec<(&str, &str)> = errors.iter()
+            .filter_map(|record| Some((record.unit.as_deref()?, record.boot_id.as_deref()?)))
+            .collect();
+        units.sort();
+        units.dedup();
+
+        let mut warnings = Vec::new();
+        for (unit, boot_id) in units.into_iter().take(3) {
+            let query = JournalQuery {
+                units: vec![unit.to_string()],
+                priority: Some(4),
+                boot: Some(boot_id.to_string()),
+                lines: Some(5),
+                ..Default::default()
+            };
+            if let Ok(records) = reader.read(&query).await {
+                warnings.extend(records.iter()
+                    .filter(|record| record.priority == Some(4))
+                    .map(|record| format!("{} warning: {}", unit, record)));
+            }
+        }
+        warnings
+    }
+
     fn extract_keywords(error_message: &str) -> Vec<String> {
         let mut keywords = Vec::new();
         
@@ -212,17 +480,15 @@ impl ErrorDiagnostic {
         keywords
     }
 
-    async fn search_logs(log_path: &Path, keywords: &[String]) -> Result<Vec<String>> {
-        if !log_path.exists() {
-            return Ok(Vec::new());
-        }
-
-        let content = fs::read_to_string(log_path)
+    async fn search_logs(linux_integration: &LinuxIntegration, log_path: &Path, keywords: &[String]) -> Result<Vec<String>> {
+        // Only the tail is needed, and on remote hosts it avoids copying whole logs over SSH
+        let path = log_path.to_string_lossy();
+        let content = linux_integration.execute_command("tail", &["-n", "1000", &path]).await
             .context(format!("Failed to read log file: {:?}", log_path))?;
 
         let mut matching_lines = Vec::new();
         
-        for line in content.lines().rev().take(1000) { // Check last 1000 lines
+        for line in content.lines().rev() { // Check last 1000 lines
             for keyword in keywords {
                 if line.to_lowercase().contains(&keyword.to_lowercase()) {
                     matching_lines.push(format!("{}: {}", log_path.display(), line));
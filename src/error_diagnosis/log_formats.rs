@@ -0,0 +1,370 @@
+//! Parsing log files handed to `diagnose --input`.
+//!
+//! The format is detected from a sample of lines, every line becomes a [`LogLine`]
+//! with whatever timestamp, priority and source the format records, and errors that
+//! occur close together are grouped into clusters with the lines around them.
+
+use chrono::{DateTime, Datelike, Local, NaiveDateTime};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::journal::{parse_priority, LogRecord};
+
+/// Lines looked at to detect the format
+const SAMPLE_LINES: usize = 50;
+/// Lines shown before the first and after the last error of a cluster
+const CONTEXT_LINES: usize = 3;
+/// Clusters handed on for diagnosis, most severe first
+const MAX_CLUSTERS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LogFormat {
+    Rfc5424,
+    Rfc3164,
+    JournalJson,
+    AccessLog,
+    NginxError,
+    ApacheError,
+    Xorg,
+    Pacman,
+    Dpkg,
+    Dmesg,
+    Plain,
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LogFormat::Rfc5424 => "syslog (RFC 5424)",
+            LogFormat::Rfc3164 => "syslog (RFC 3164)",
+            LogFormat::JournalJson => "journal JSON export",
+            LogFormat::AccessLog => "nginx/apache access log",
+            LogFormat::NginxError => "nginx error log",
+            LogFormat::ApacheError => "apache error log",
+            LogFormat::Xorg => "Xorg log",
+            LogFormat::Pacman => "pacman log",
+            LogFormat::Dpkg => "dpkg log",
+            LogFormat::Dmesg => "kernel log (dmesg)",
+            LogFormat::Plain => "plain text",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// One line of a log file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogLine {
+    /// 1-based line number in the input
+    pub line_number: usize,
+    /// As written in the log, in the time zone of the machine that wrote it
+    pub timestamp: Option<NaiveDateTime>,
+    /// syslog priority, 3 for errors and 4 for warnings
+    pub priority: Option<u8>,
+    /// Program, unit, module or client that the line is about
+    pub source: Option<String>,
+    pub message: String,
+    pub raw: String,
+}
+
+impl LogLine {
+    fn is_error(&self) -> bool {
+        self.priority.is_some_and(|p| p <= 3)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParsedLog {
+    pub format: LogFormat,
+    pub lines: Vec<LogLine>,
+}
+
+/// Errors that occurred close together in the log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorCluster {
+    pub errors: Vec<LogLine>,
+    /// Raw lines from shortly before the first error to shortly after the last one,
+    /// prefixed with their line numbers
+    pub context: Vec<String>,
+    /// Later clusters with the same messages, which are not listed separately
+    pub repeats: usize,
+}
+
+impl ErrorCluster {
+    fn first_line(&self) -> usize {
+        self.errors.first().map(|e| e.line_number).unwrap_or(0)
+    }
+
+    fn last_line(&self) -> usize {
+        self.errors.last().map(|e| e.line_number).unwrap_or(0)
+    }
+
+    fn most_severe(&self) -> u8 {
+        self.errors.iter().filter_map(|e| e.priority).min().unwrap_or(7)
+    }
+
+    /// "lines 12-15 (2 more times)" for headings
+    pub fn describe(&self) -> String {
+        let lines = if self.first_line() == self.last_line() {
+            format!("line {}", self.first_line())
+        } else {
+            format!("lines {}-{}", self.first_line(), self.last_line())
+        };
+        match self.repeats {
+            0 => lines,
+            1 => format!("{} (once more later)", lines),
+            n => format!("{} ({} more times later)", lines, n),
+        }
+    }
+
+    /// The messages with numbers masked, so the same failure at other times or for
+    /// other PIDs compares equal
+    fn signature(&self) -> String {
+        self.errors.iter()
+            .map(|e| e.message.chars().map(|c| if c.is_ascii_digit() { '#' } else { c }).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Line patterns of the formats that can be told apart by a regex
+struct Patterns {
+    rfc5424: Regex,
+    rfc3164: Regex,
+    access: Regex,
+    nginx_error: Regex,
+    apache_error: Regex,
+    kernel_stamp: Regex,
+    xorg_marker: Regex,
+    pacman: Regex,
+    dpkg: Regex,
+}
+
+impl Patterns {
+    fn new() -> Self {
+        let regex = |pattern: &str| Regex::new(pattern).expect("valid log pattern");
+        Self {
+            // <PRI>1 TIMESTAMP HOST APP PROCID MSGID [SD] MSG
+            rfc5424: regex(r"^<(\d{1,3})>1 (\S+) (\S+) (\S+) (\S+) \S+ (?:-|(?:\[.*?\])+) ?(.*)$"),
+            // "May  1 10:00:00 host prog[pid]: msg", or rsyslog's ISO timestamps
+            rfc3164: regex(r"^(?:<(\d{1,3})>)?([A-Z][a-z]{2} +\d{1,2} \d{2}:\d{2}:\d{2}|\d{4}-\d{2}-\d{2}T\S+) (\S+) ([^\s:\[]+)(?:\[(\d+)\])?: ?(.*)$"),
+            // Common and combined log format
+            access: regex(r#"^(\S+) \S+ \S+ \[([^\]]+)\] "([^"]*)" (\d{3}) (\S+)"#),
+            nginx_error: regex(r"^(\d{4}/\d{2}/\d{2} \d{2}:\d{2}:\d{2}) \[(\w+)\] (\d+)#\d+: (?:\*\d+ )?(.*)$"),
+            apache_error: regex(r"^\[([A-Z][a-z]{2} [A-Z][a-z]{2} +\d{1,2} [\d:.]+ \d{4})\] \[(?:([\w-]+):)?(\w+)\](?: \[pid (\d+)(?::tid \d+)?\])?(?: \[client [^\]]+\])? ?(.*)$"),
+            kernel_stamp: regex(r"^(?:<(\d)>)?\[\s*(\d+\.\d+|[A-Z][a-z]{2} [A-Z][a-z]{2} +\d{1,2} \d{2}:\d{2}:\d{2} \d{4})\] ?(.*)$"),
+            xorg_marker: regex(r"^\[\s*\d+\.\d+\] \((EE|WW|II|==|--|\*\*|\+\+|!!|NI|\?\?)\) (.*)$"),
+            pacman: regex(r"^\[(\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}(?::\d{2})?(?:[+-]\d{4})?)\] \[([\w-]+)\] (.*)$"),
+            dpkg: regex(r"^(\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}) (status|install|upgrade|remove|purge|configure|trigproc|startup|conffile) (.*)$"),
+        }
+    }
+}
+
+/// Detect the format of `content` and parse every line
+pub fn parse(content: &str) -> ParsedLog {
+    let patterns = Patterns::new();
+    let format = detect(content, &patterns);
+    let lines = content.lines()
+        .enumerate()
+        .filter(|(_, raw)| !raw.trim().is_empty())
+        .map(|(index, raw)| {
+            let mut line = parse_line(format, raw, &patterns).unwrap_or_else(|| LogLine {
+                line_number: 0,
+                timestamp: None,
+                priority: None,
+                source: None,
+                message: raw.trim().to_string(),
+                raw: String::new(),
+            });
+            line.line_number = index + 1;
+            line.raw = raw.to_string();
+            line.priority = line.priority.or_else(|| keyword_priority(&line.message));
+            line
+        })
+        .collect();
+
+    ParsedLog { format, lines }
+}
+
+fn detect(content: &str, patterns: &Patterns) -> LogFormat {
+    let sample: Vec<&str> = content.lines().filter(|line| !line.trim().is_empty()).take(SAMPLE_LINES).collect();
+    let count = |matches: &dyn Fn(&str) -> bool| sample.iter().filter(|line| matches(line)).count();
+    let most = |n: usize| !sample.is_empty() && n * 2 >= sample.len();
+
+    if most(count(&|line| line.starts_with('{') && line.contains("\"__REALTIME_TIMESTAMP\""))) {
+        return LogFormat::JournalJson;
+    }
+    // Xorg and the kernel both stamp lines with seconds since boot
+    if most(count(&|line| patterns.kernel_stamp.is_match(line))) {
+        return if sample.iter().any(|line| patterns.xorg_marker.is_match(line)) { LogFormat::Xorg } else { LogFormat::Dmesg };
+    }
+
+    let candidates = [
+        (LogFormat::Rfc5424, &patterns.rfc5424),
+        (LogFormat::Rfc3164, &patterns.rfc3164),
+        (LogFormat::AccessLog, &patterns.access),
+        (LogFormat::NginxError, &patterns.nginx_error),
+        (LogFormat::ApacheError, &patterns.apache_error),
+        (LogFormat::Pacman, &patterns.pacman),
+        (LogFormat::Dpkg, &patterns.dpkg),
+    ];
+    candidates.iter()
+        .map(|(format, regex)| (*format, count(&|line| regex.is_match(line))))
+        .filter(|(_, matched)| most(*matched))
+        .max_by_key(|(_, matched)| *matched)
+        .map(|(format, _)| format)
+        .unwrap_or(LogFormat::Plain)
+}
+
+/// Fields of one line; `None` for lines that do not follow the format, such as
+/// continuation lines of a multi-line message
+fn parse_line(format: LogFormat, raw: &str, patterns: &Patterns) -> Option<LogLine> {
+    let line = |timestamp, priority, source: Option<&str>, message: &str| LogLine {
+        line_number: 0,
+        timestamp,
+        priority,
+        source: source.map(String::from),
+        message: message.trim().to_string(),
+        raw: String::new(),
+    };
+
+    match format {
+        LogFormat::Rfc5424 => {
+            let caps = patterns.rfc5424.captures(raw)?;
+            let priority = caps[1].parse::<u8>().ok().map(|pri| pri % 8);
+            Some(line(iso_time(&caps[2]), priority, Some(&caps[4]).filter(|app| *app != "-"), &caps[6]))
+        }
+        LogFormat::Rfc3164 => {
+            let caps = patterns.rfc3164.captures(raw)?;
+            let priority = caps.get(1).and_then(|pri| pri.as_str().parse::<u8>().ok()).map(|pri| pri % 8);
+            let timestamp = iso_time(&caps[2]).or_else(|| syslog_time(&caps[2]));
+            Some(line(timestamp, priority, Some(&caps[4]), &caps[6]))
+        }
+        LogFormat::JournalJson => {
+            let record = LogRecord::from_json(raw)?;
+            let source = record.unit.clone().or(record.identifier.clone());
+            let timestamp = Some(record.timestamp.with_timezone(&Local).naive_local());
+            Some(line(timestamp, record.priority, source.as_deref(), &record.message))
+        }
+        LogFormat::AccessLog => {
+            let caps = patterns.access.captures(raw)?;
+            let priority = match caps[4].as_bytes()[0] {
+                b'5' => 3,
+                b'4' => 4,
+                _ => 6,
+            };
+            let timestamp = DateTime::parse_from_str(&caps[2], "%d/%b/%Y:%H:%M:%S %z").ok().map(|t| t.naive_local());
+            Some(line(timestamp, Some(priority), Some(&caps[1]), &format!("\"{}\" {}", &caps[3], &caps[4])))
+        }
+        LogFormat::NginxError => {
+            let caps = patterns.nginx_error.captures(raw)?;
+            let timestamp = NaiveDateTime::parse_from_str(&caps[1], "%Y/%m/%d %H:%M:%S").ok();
+            Some(line(timestamp, parse_priority(&caps[2]).ok(), Some("nginx"), &caps[4]))
+        }
+        LogFormat::ApacheError => {
+            let caps = patterns.apache_error.captures(raw)?;
+            let time = caps[1].split_whitespace().collect::<Vec<_>>().join(" ");
+            let timestamp = NaiveDateTime::parse_from_str(&time, "%a %b %d %H:%M:%S%.f %Y").ok();
+            // Apache 2.4 has trace1-8 below debug
+            let priority = parse_priority(&caps[3]).ok().or_else(|| caps[3].starts_with("trace").then_some(7));
+            Some(line(timestamp, priority, caps.get(2).map(|m| m.as_str()), &caps[5]))
+        }
+        LogFormat::Xorg => {
+            let caps = patterns.xorg_marker.captures(raw)?;
+            let priority = match &caps[1] {
+                "EE" => 3,
+                "WW" => 4,
+                "NI" | "??" => 5,
+                _ => 6,
+            };
+            Some(line(None, Some(priority), None, &caps[2]))
+        }
+        LogFormat::Pacman => {
+            let caps = patterns.pacman.captures(raw)?;
+            let timestamp = DateTime::parse_from_str(&caps[1], "%Y-%m-%dT%H:%M:%S%z").ok().map(|t| t.naive_local())
+                .or_else(|| NaiveDateTime::parse_from_str(&caps[1], "%Y-%m-%d %H:%M").ok());
+            Some(line(timestamp, None, Some(&caps[2]), &caps[3]))
+        }
+        LogFormat::Dpkg => {
+            let caps = patterns.dpkg.captures(raw)?;
+            let timestamp = NaiveDateTime::parse_from_str(&caps[1], "%Y-%m-%d %H:%M:%S").ok();
+            // A package left half-installed or half-configured did not finish
+            let priority = (&caps[2] == "status" && caps[3].starts_with("half-")).then_some(4);
+            Some(line(timestamp, priority, Some(&caps[2]), &caps[3]))
+        }
+        LogFormat::Dmesg => {
+            let caps = patterns.kernel_stamp.captures(raw)?;
+            let priority = caps.get(1).and_then(|level| level.as_str().parse().ok());
+            // Seconds since boot cannot be placed in time; `dmesg -T` stamps can
+            let timestamp = NaiveDateTime::parse_from_str(&caps[2], "%a %b %e %H:%M:%S %Y").ok();
+            Some(line(timestamp, priority, Some("kernel"), &caps[3]))
+        }
+        LogFormat::Plain => None,
+    }
+}
+
+fn iso_time(text: &str) -> Option<NaiveDateTime> {
+    DateTime::parse_from_rfc3339(text).ok().map(|t| t.naive_local())
+}
+
+/// RFC 3164 timestamps leave out the year; assume the current one
+fn syslog_time(text: &str) -> Option<NaiveDateTime> {
+    let text = format!("{} {}", Local::now().year(), text.split_whitespace().collect::<Vec<_>>().join(" "));
+    NaiveDateTime::parse_from_str(&text, "%Y %b %e %H:%M:%S").ok()
+}
+
+/// Priority guessed from the message for formats that do not record one
+fn keyword_priority(message: &str) -> Option<u8> {
+    let lower = message.to_lowercase();
+    if ["error", "failed", "failure", "fatal", "panic", "segfault", "oops", "call trace", "cannot", "denied"]
+        .iter().any(|keyword| lower.contains(keyword))
+    {
+        Some(3)
+    } else if lower.contains("warning") || lower.starts_with("warn") {
+        Some(4)
+    } else {
+        None
+    }
+}
+
+/// Errors grouped by how close together they are, with warnings standing in when
+/// the log has no errors. Clusters that repeat an earlier one are counted on it.
+pub fn error_clusters(log: &ParsedLog) -> Vec<ErrorCluster> {
+    let threshold = if log.lines.iter().any(LogLine::is_error) { 3 } else { 4 };
+    let mut clusters: Vec<ErrorCluster> = Vec::new();
+    let mut current: Vec<LogLine> = Vec::new();
+
+    let mut close = |errors: &mut Vec<LogLine>| {
+        if errors.is_empty() {
+            return;
+        }
+        let first = errors[0].line_number.saturating_sub(CONTEXT_LINES);
+        let last = errors[errors.len() - 1].line_number + CONTEXT_LINES;
+        let cluster = ErrorCluster {
+            errors: std::mem::take(errors),
+            context: log.lines.iter()
+                .filter(|line| line.line_number >= first && line.line_number <= last)
+                .map(|line| format!("{}: {}", line.line_number, line.raw))
+                .collect(),
+            repeats: 0,
+        };
+        let signature = cluster.signature();
+        match clusters.iter_mut().find(|earlier| earlier.signature() == signature) {
+            Some(earlier) => earlier.repeats += 1,
+            None => clusters.push(cluster),
+        }
+    };
+
+    for line in log.lines.iter().filter(|line| line.priority.is_some_and(|p| p <= threshold)) {
+        let adjacent = current.last().is_some_and(|last| line.line_number - last.line_number <= CONTEXT_LINES * 2);
+        if !adjacent {
+            close(&mut current);
+        }
+        current.push(line.clone());
+    }
+    close(&mut current);
+
+    clusters.sort_by_key(|cluster| (cluster.most_severe(), std::cmp::Reverse(cluster.repeats), cluster.first_line()));
+    clusters.truncate(MAX_CLUSTERS);
+    clusters
+}
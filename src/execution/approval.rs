@@ -0,0 +1,345 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::config::Config;
+use super::audit::AuditLogger;
+use super::{ExecutionRequest, RiskLevel};
+
+const QUEUE_FILE: &str = "queue.json";
+const LOCK_FILE: &str = "queue.lock";
+/// How often a waiting request re-reads the queue for decisions made by other processes
+const POLL_INTERVAL_MS: u64 = 1000;
+/// Decided requests are kept this long for review before being dropped from the queue
+const KEEP_DECIDED_DAYS: i64 = 7;
+
+/// Queue of execution requests waiting for approval from the web API, WebSocket or CLI.
+/// The queue file is the source of truth so several tuxpilot processes can share it.
+#[derive(Debug, Clone)]
+pub struct ApprovalBroker {
+    dir: PathBuf,
+    timeout_minutes: u32,
+    two_person_critical: bool,
+    audit_logger: AuditLogger,
+    events: broadcast::Sender<PendingApproval>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingApproval {
+    pub id: Uuid,
+    pub request: ExecutionRequest,
+    pub requested_by: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub required_approvals: usize,
+    pub decisions: Vec<ApproverDecision>,
+    pub status: ApprovalStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApproverDecision {
+    pub approver: String,
+    pub approved: bool,
+    pub comment: Option<String>,
+    pub decided_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ApprovalStatus {
+    Pending,
+    Approved,
+    Denied,
+    Expired,
+}
+
+/// Exclusive lock on the queue, released when dropped
+struct QueueLock {
+    _file: File,
+}
+
+impl PendingApproval {
+    pub fn approvals(&self) -> usize {
+        self.decisions.iter().filter(|d| d.approved).count()
+    }
+
+    fn expire_if_stale(&mut self, now: DateTime<Utc>) -> bool {
+        if self.status == ApprovalStatus::Pending && self.expires_at <= now {
+            self.status = ApprovalStatus::Expired;
+            return true;
+        }
+        false
+    }
+}
+
+impl ApprovalBroker {
+    pub fn new(config: &Config, audit_logger: AuditLogger) -> Result<Self> {
+        let dir = match &config.approvals.directory {
+            Some(dir) => dir.clone(),
+            None => dirs::data_dir()
+                .context("Failed to get data directory")?
+                .join("tuxpilot")
+                .join("approvals"),
+        };
+        let (events, _) = broadcast::channel(64);
+
+        Ok(Self {
+            dir,
+            timeout_minutes: config.approvals.timeout_minutes,
+            two_person_critical: config.approvals.two_person_critical,
+            audit_logger,
+            events,
+        })
+    }
+
+    /// Whether a request at this risk level must go through the queue even when a
+    /// terminal is available
+    pub fn requires_queue(&self, risk_level: &RiskLevel) -> bool {
+        self.two_person_critical && *risk_level == RiskLevel::Critical
+    }
+
+    /// Updates for every request submitted or decided in this process
+    pub fn subscribe(&self) -> broadcast::Receiver<PendingApproval> {
+        self.events.subscribe()
+    }
+
+    pub async fn submit(&self, request: &ExecutionRequest, requested_by: &str) -> Result<PendingApproval> {
+        let now = Utc::now();
+        let required_approvals = if self.requires_queue(&request.risk_level) { 2 } else { 1 };
+
+        let approval = PendingApproval {
+            id: Uuid::new_v4(),
+            request: request.clone(),
+            requested_by: requested_by.to_string(),
+            created_at: now,
+            expires_at: now + chrono::Duration::minutes(self.timeout_minutes as i64),
+            required_approvals,
+            decisions: Vec::new(),
+            status: ApprovalStatus::Pending,
+        };
+
+        let queued = approval.clone();
+        self.update(move |queue| {
+            queue.push(queued);
+            Ok(())
+        }).await?;
+
+        self.audit_logger.log_approval_requested(&approval).await?;
+        let _ = self.events.send(approval.clone());
+        Ok(approval)
+    }
+
+    /// Record one approver's decision. A denial settles the request immediately; an
+    /// approval settles it once enough distinct approvers agreed.
+    pub async fn decide(&self, id: Uuid, approver: &str, approved: bool, comment: Option<String>) -> Result<PendingApproval> {
+        let decision = ApproverDecision {
+            approver: approver.to_string(),
+            approved,
+            comment,
+            decided_at: Utc::now(),
+        };
+
+        let recorded = decision.clone();
+        let approval = self.update(move |queue| {
+            let approval = queue.iter_mut()
+                .find(|a| a.id == id)
+                .ok_or_else(|| anyhow::anyhow!("Approval request not found: {}", id))?;
+
+            if approval.status != ApprovalStatus::Pending {
+                return Err(anyhow::anyhow!("Approval request {} is already {:?}", id, approval.status));
+            }
+            if approval.decisions.iter().any(|d| d.approver == recorded.approver) {
+                return Err(anyhow::anyhow!("{} has already decided on request {}", recorded.approver, id));
+            }
+            if approved && approval.requested_by == recorded.approver {
+                return Err(anyhow::anyhow!("{} cannot approve their own request {}", recorded.approver, id));
+            }
+
+            approval.decisions.push(recorded);
+            if !approved {
+                approval.status = ApprovalStatus::Denied;
+            } else if approval.approvals() >= approval.required_approvals {
+                approval.status = ApprovalStatus::Approved;
+            }
+            Ok(approval.clone())
+        }).await?;
+
+        self.audit_logger.log_approval_decision(&approval, &decision).await?;
+        let _ = self.events.send(approval.clone());
+        Ok(approval)
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<Option<PendingApproval>> {
+        let queue = self.update(|queue| Ok(queue.clone())).await?;
+        Ok(queue.into_iter().find(|a| a.id == id))
+    }
+
+    /// Requests in the queue, newest first, optionally limited to one status
+    pub async fn list(&self, status: Option<ApprovalStatus>) -> Result<Vec<PendingApproval>> {
+        let mut queue = self.update(|queue| Ok(queue.clone())).await?;
+        if let Some(status) = status {
+            queue.retain(|a| a.status == status);
+        }
+        queue.sort_by_key(|a| std::cmp::Reverse(a.created_at));
+        Ok(queue)
+    }
+
+    /// Block until the request is approved, denied or expires. Polls the queue so
+    /// decisions made by other processes are picked up too.
+    pub async fn wait_for_decision(&self, id: Uuid) -> Result<PendingApproval> {
+        let mut events = self.subscribe();
+
+        loop {
+            let approval = self.get(id).await?
+                .ok_or_else(|| anyhow::anyhow!("Approval request disappeared: {}", id))?;
+
+            if approval.status != ApprovalStatus::Pending {
+                if approval.status == ApprovalStatus::Expired {
+                    self.audit_logger.log_approval_decision(&approval, &ApproverDecision {
+                        approver: "system".to_string(),
+                        approved: false,
+                        comment: Some("Approval request expired".to_string()),
+                        decided_at: Utc::now(),
+                    }).await?;
+                    let _ = self.events.send(approval.clone());
+                }
+                return Ok(approval);
+            }
+
+            let poll = tokio::time::sleep(std::time::Duration::from_millis(POLL_INTERVAL_MS));
+            tokio::select! {
+                _ = poll => {}
+                _ = events.recv() => {}
+            }
+        }
+    }
+
+    /// Load the queue under the lock, expire stale requests, apply `change` and save
+    async fn update<T, F>(&self, change: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Vec<PendingApproval>) -> Result<T> + Send + 'static,
+    {
+        let dir = self.dir.clone();
+
+        tokio::task::spawn_blocking(move || {
+            fs::create_dir_all(&dir).context("Failed to create approval queue directory")?;
+            let _lock = QueueLock::acquire(&dir)?;
+
+            let queue_file = dir.join(QUEUE_FILE);
+            let mut queue: Vec<PendingApproval> = match fs::read_to_string(&queue_file) {
+                Ok(content) => serde_json::from_str(&content).context("Failed to parse approval queue")?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+                Err(e) => return Err(e).context("Failed to read approval queue"),
+            };
+
+            let now = Utc::now();
+            let mut changed = false;
+            for approval in queue.iter_mut() {
+                changed |= approval.expire_if_stale(now);
+            }
+            let before = queue.len();
+            queue.retain(|a| a.status == ApprovalStatus::Pending
+                || a.created_at > now - chrono::Duration::days(KEEP_DECIDED_DAYS));
+            changed |= queue.len() != before;
+
+            let snapshot = serde_json::to_vec(&queue)?;
+            let result = change(&mut queue)?;
+
+            if changed || serde_json::to_vec(&queue)? != snapshot {
+                let tmp = dir.join(format!("{}.tmp", QUEUE_FILE));
+                fs::write(&tmp, serde_json::to_vec_pretty(&queue)?)
+                    .context("Failed to write approval queue")?;
+                fs::rename(&tmp, &queue_file).context("Failed to replace approval queue")?;
+            }
+
+            Ok(result)
+        })
+        .await
+        .context("Approval queue task failed")?
+    }
+}
+
+/// Login name of the user running this process, from the password database rather than
+/// $USER, which the caller controls. Under sudo this is the user who invoked sudo, so a
+/// request made with sudo cannot be approved by the same person without it.
+pub fn login_name() -> String {
+    let mut uid = unsafe { libc::getuid() };
+    if uid == 0 {
+        if let Some(sudo_uid) = std::env::var("SUDO_UID").ok().and_then(|value| value.parse().ok()) {
+            uid = sudo_uid;
+        }
+    }
+
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    let mut result = std::ptr::null_mut();
+    let status = unsafe {
+        libc::getpwuid_r(uid, &mut passwd, buffer.as_mut_ptr(), buffer.len(), &mut result)
+    };
+    if status == 0 && !result.is_null() {
+        return unsafe { std::ffi::CStr::from_ptr(passwd.pw_name) }.to_string_lossy().into_owned();
+    }
+    format!("uid:{}", uid)
+}
+
+impl QueueLock {
+    fn acquire(dir: &std::path::Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(LOCK_FILE))
+            .context("Failed to open approval queue lock")?;
+
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to lock approval queue");
+        }
+
+        Ok(Self { _file: file })
+    }
+}
+
+impl std::str::FromStr for ApprovalStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "pending" => Ok(ApprovalStatus::Pending),
+            "approved" => Ok(ApprovalStatus::Approved),
+            "denied" => Ok(ApprovalStatus::Denied),
+            "expired" => Ok(ApprovalStatus::Expired),
+            _ => Err(anyhow::anyhow!("Unknown approval status '{}'. Use pending, approved, denied or expired", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for PendingApproval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let icon = match self.status {
+            ApprovalStatus::Pending => "⏳",
+            ApprovalStatus::Approved => "✅",
+            ApprovalStatus::Denied => "❌",
+            ApprovalStatus::Expired => "⌛",
+        };
+
+        writeln!(f, "{} {} [{:?}] {:?}", icon, self.id, self.request.risk_level, self.status)?;
+        writeln!(f, "   🔧 {} {}", self.request.command, self.request.args.join(" "))?;
+        writeln!(f, "   📝 {}", self.request.description)?;
+        writeln!(f, "   👤 Requested by {} at {}, expires {}",
+                 self.requested_by,
+                 self.created_at.format("%Y-%m-%d %H:%M:%S"),
+                 self.expires_at.format("%Y-%m-%d %H:%M:%S"))?;
+        writeln!(f, "   👥 Approvals: {}/{}", self.approvals(), self.required_approvals)?;
+        for decision in &self.decisions {
+            writeln!(f, "      {} {}{}",
+                     if decision.approved { "✅" } else { "❌" },
+                     decision.approver,
+                     decision.comment.as_ref().map(|c| format!(": {}", c)).unwrap_or_default())?;
+        }
+        Ok(())
+    }
+}
@@ -2,16 +2,30 @@ use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
-use tokio::fs::OpenOptions;
-use tokio::io::AsyncWriteExt;
+use std::sync::Arc;
 use uuid::Uuid;
 
 use crate::config::Config;
+use super::approval::{ApproverDecision, PendingApproval};
 use super::{ExecutionRequest, ExecutionResult};
 
+pub mod integrity;
+pub mod query;
+pub mod rotation;
+pub mod sinks;
+
+use integrity::{ChainHead, HostKey, VerificationReport};
+use query::{AuditPage, AuditQuery};
+use rotation::{AuditFiles, AuditLock};
+use sinks::AuditSinks;
+
 #[derive(Debug, Clone)]
 pub struct AuditLogger {
-    log_file: PathBuf,
+    files: AuditFiles,
+    host_key: Option<HostKey>,
+    sinks: Arc<std::sync::Mutex<AuditSinks>>,
+    write_lock: Arc<tokio::sync::Mutex<()>>,
+    session_id: String,
     config: Config,
 }
 
@@ -23,9 +37,18 @@ pub struct AuditEntry {
     pub user: String,
     pub session_id: String,
     pub data: serde_json::Value,
+    /// Position in the hash chain, starting at 1
+    #[serde(default)]
+    pub sequence: u64,
+    #[serde(default)]
+    pub prev_hash: String,
+    #[serde(default)]
+    pub hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum AuditEntryType {
     ExecutionRequest,
     ExecutionResult,
@@ -48,46 +71,76 @@ pub struct ExecutionRecord {
 
 impl AuditLogger {
     pub async fn new(config: &Config) -> Result<Self> {
-        let log_dir = dirs::data_dir()
-            .context("Failed to get data directory")?
-            .join("tuxpilot")
-            .join("audit");
+        let log_dir = Self::log_directory(config)?;
         
         tokio::fs::create_dir_all(&log_dir).await
             .context("Failed to create audit log directory")?;
         
-        let log_file = log_dir.join("audit.jsonl");
+        let files = AuditFiles::new(log_dir);
+
+        let host_key = if config.audit.sign_entries {
+            Some(HostKey::load_or_create(&Self::signing_key_path(config)?)?)
+        } else {
+            None
+        };
+
+        let sinks = AuditSinks::new(&config.audit.sinks, &files.dir().join("spool"))
+            .context("Failed to configure audit sinks")?;
         
         Ok(Self {
-            log_file,
+            files,
+            host_key,
+            sinks: Arc::new(std::sync::Mutex::new(sinks)),
+            write_lock: Arc::new(tokio::sync::Mutex::new(())),
+            // Simple session ID based on process ID and start time
+            session_id: format!("{}_{}", std::process::id(), Utc::now().timestamp()),
             config: config.clone(),
         })
     }
 
-    pub async fn log_request(&self, request: &ExecutionRequest) -> Result<()> {
-        let entry = AuditEntry {
+    fn log_directory(config: &Config) -> Result<PathBuf> {
+        match &config.audit.directory {
+            Some(dir) => Ok(dir.clone()),
+            None => Ok(dirs::data_dir()
+                .context("Failed to get data directory")?
+                .join("tuxpilot")
+                .join("audit")),
+        }
+    }
+
+    /// Kept out of the audit directory, next to the config
+    fn signing_key_path(config: &Config) -> Result<PathBuf> {
+        match &config.audit.signing_key_path {
+            Some(path) => Ok(path.clone()),
+            None => Ok(Config::default_config_path()?.with_file_name("audit.key")),
+        }
+    }
+
+    fn new_entry(&self, entry_type: AuditEntryType, data: serde_json::Value) -> AuditEntry {
+        AuditEntry {
             id: Uuid::new_v4(),
             timestamp: Utc::now(),
-            entry_type: AuditEntryType::ExecutionRequest,
+            entry_type,
             user: self.get_current_user(),
             session_id: self.get_session_id(),
-            data: serde_json::to_value(request)?,
-        };
+            data,
+            sequence: 0,
+            prev_hash: String::new(),
+            hash: String::new(),
+            signature: None,
+        }
+    }
 
-        self.write_audit_entry(&entry).await
+    pub async fn log_request(&self, request: &ExecutionRequest) -> Result<()> {
+        let entry = self.new_entry(AuditEntryType::ExecutionRequest, serde_json::to_value(request)?);
+
+        self.write_audit_entry(entry).await
     }
 
     pub async fn log_result(&self, result: &ExecutionResult) -> Result<()> {
-        let entry = AuditEntry {
-            id: Uuid::new_v4(),
-            timestamp: Utc::now(),
-            entry_type: AuditEntryType::ExecutionResult,
-            user: self.get_current_user(),
-            session_id: self.get_session_id(),
-            data: serde_json::to_value(result)?,
-        };
+        let entry = self.new_entry(AuditEntryType::ExecutionResult, serde_json::to_value(result)?);
 
-        self.write_audit_entry(&entry).await
+        self.write_audit_entry(entry).await
     }
 
     pub async fn log_permission_request(&self, permission: &str, granted: bool) -> Result<()> {
@@ -97,209 +150,219 @@ impl AuditLogger {
             AuditEntryType::PermissionDenied
         };
 
-        let entry = AuditEntry {
-            id: Uuid::new_v4(),
-            timestamp: Utc::now(),
-            entry_type,
-            user: self.get_current_user(),
-            session_id: self.get_session_id(),
-            data: serde_json::json!({
-                "permission": permission,
-                "granted": granted
-            }),
+        let entry = self.new_entry(entry_type, serde_json::json!({
+            "permission": permission,
+            "granted": granted
+        }));
+
+        self.write_audit_entry(entry).await
+    }
+
+    pub async fn log_approval_requested(&self, approval: &PendingApproval) -> Result<()> {
+        let entry = self.new_entry(AuditEntryType::PermissionRequest, serde_json::json!({
+            "approval_id": approval.id,
+            "execution_id": approval.request.id,
+            "command": format!("{} {}", approval.request.command, approval.request.args.join(" ")).trim(),
+            "risk_level": approval.request.risk_level,
+            "requested_by": approval.requested_by,
+            "required_approvals": approval.required_approvals,
+            "expires_at": approval.expires_at
+        }));
+
+        self.write_audit_entry(entry).await
+    }
+
+    pub async fn log_approval_decision(&self, approval: &PendingApproval, decision: &ApproverDecision) -> Result<()> {
+        let entry_type = if decision.approved {
+            AuditEntryType::PermissionGranted
+        } else {
+            AuditEntryType::PermissionDenied
         };
 
-        self.write_audit_entry(&entry).await
+        let entry = self.new_entry(entry_type, serde_json::json!({
+            "approval_id": approval.id,
+            "execution_id": approval.request.id,
+            "command": format!("{} {}", approval.request.command, approval.request.args.join(" ")).trim(),
+            "risk_level": approval.request.risk_level,
+            "approver": decision.approver,
+            "granted": decision.approved,
+            "comment": decision.comment,
+            "status": approval.status,
+            "approvals": approval.approvals(),
+            "required_approvals": approval.required_approvals
+        }));
+
+        self.write_audit_entry(entry).await
     }
 
     pub async fn log_safety_violation(&self, command: &str, reason: &str) -> Result<()> {
-        let entry = AuditEntry {
-            id: Uuid::new_v4(),
-            timestamp: Utc::now(),
-            entry_type: AuditEntryType::SafetyViolation,
-            user: self.get_current_user(),
-            session_id: self.get_session_id(),
-            data: serde_json::json!({
-                "command": command,
-                "reason": reason,
-                "severity": "high"
-            }),
-        };
+        let entry = self.new_entry(AuditEntryType::SafetyViolation, serde_json::json!({
+            "command": command,
+            "reason": reason,
+            "severity": "high"
+        }));
 
-        self.write_audit_entry(&entry).await
+        self.write_audit_entry(entry).await
     }
 
     pub async fn log_system_change(&self, change_type: &str, description: &str, reversible: bool) -> Result<()> {
-        let entry = AuditEntry {
-            id: Uuid::new_v4(),
-            timestamp: Utc::now(),
-            entry_type: AuditEntryType::SystemChange,
-            user: self.get_current_user(),
-            session_id: self.get_session_id(),
-            data: serde_json::json!({
-                "change_type": change_type,
-                "description": description,
-                "reversible": reversible,
-                "timestamp": Utc::now()
-            }),
-        };
-
-        self.write_audit_entry(&entry).await
+        let entry = self.new_entry(AuditEntryType::SystemChange, serde_json::json!({
+            "change_type": change_type,
+            "description": description,
+            "reversible": reversible,
+            "timestamp": Utc::now()
+        }));
+
+        self.write_audit_entry(entry).await
     }
 
     pub async fn log_error(&self, error: &str, context: Option<&str>) -> Result<()> {
-        let entry = AuditEntry {
-            id: Uuid::new_v4(),
-            timestamp: Utc::now(),
-            entry_type: AuditEntryType::Error,
-            user: self.get_current_user(),
-            session_id: self.get_session_id(),
-            data: serde_json::json!({
-                "error": error,
-                "context": context,
-                "severity": "error"
-            }),
-        };
+        let entry = self.new_entry(AuditEntryType::Error, serde_json::json!({
+            "error": error,
+            "context": context,
+            "severity": "error"
+        }));
 
-        self.write_audit_entry(&entry).await
+        self.write_audit_entry(entry).await
     }
 
-    async fn write_audit_entry(&self, entry: &AuditEntry) -> Result<()> {
-        let mut file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&self.log_file)
-            .await
-            .context("Failed to open audit log file")?;
+    /// Link the entry to the chain head, sign it and append it, rotating the log first
+    /// when the retention policy asks for it. The stored entry is then forwarded to the
+    /// configured sinks.
+    async fn write_audit_entry(&self, mut entry: AuditEntry) -> Result<()> {
+        let _guard = self.write_lock.lock().await;
+        let files = self.files.clone();
+        let host_key = self.host_key.clone();
+        let sinks = self.sinks.clone();
+        let audit_config = self.config.audit.clone();
+
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let lock = AuditLock::acquire(&files, true)?;
+            let mut head = files.read_head()?.unwrap_or_default();
+
+            rotation::rotate_if_needed(&files, &audit_config, &mut head)?;
+
+            entry.sequence = head.sequence + 1;
+            entry.prev_hash = head.hash.clone();
+            entry.hash = integrity::compute_entry_hash(&entry)?;
+            entry.signature = host_key.as_ref().map(|key| key.sign(&entry.hash));
+
+            rotation::append_line(&files, &serde_json::to_string(&entry)?)?;
+
+            head = ChainHead {
+                sequence: entry.sequence,
+                hash: entry.hash.clone(),
+                pruned_through: head.pruned_through,
+            };
+            files.write_head(&head)?;
+            drop(lock);
+
+            // Sinks may block on the network, so they run outside the file lock; the
+            // write guard still keeps deliveries in chain order
+            let mut sinks = sinks.lock().unwrap_or_else(|e| e.into_inner());
+            if !sinks.is_empty() {
+                sinks.dispatch(&entry);
+            }
+            Ok(())
+        })
+        .await
+        .context("Audit writer task failed")?
+    }
 
-        let json_line = serde_json::to_string(entry)?;
-        file.write_all(format!("{}\n", json_line).as_bytes()).await
-            .context("Failed to write audit entry")?;
+    /// Send a synthetic entry to every configured sink, bypassing the audit log itself
+    pub async fn test_sinks(&self) -> Result<Vec<(String, Result<()>)>> {
+        let sinks = self.sinks.clone();
+        let entry = self.new_entry(AuditEntryType::SystemChange, serde_json::json!({
+            "change_type": "audit_sink_test",
+            "description": "TuxPilot audit sink test",
+            "reversible": true,
+            "timestamp": Utc::now()
+        }));
+
+        tokio::task::spawn_blocking(move || {
+            sinks.lock().unwrap_or_else(|e| e.into_inner()).test(&entry)
+        })
+        .await
+        .context("Audit sink test task failed")
+    }
 
-        file.flush().await
-            .context("Failed to flush audit log")?;
+    /// Check the hash chain and signatures across all retained segments
+    pub async fn verify_integrity(&self) -> Result<VerificationReport> {
+        let files = self.files.clone();
+        let pinned = self.config.audit.verifying_key_path.clone();
+        let sign_entries = self.config.audit.sign_entries;
+        if pinned.as_ref().is_some_and(|path| path.starts_with(files.dir())) {
+            return Err(anyhow::anyhow!("audit.verifying_key_path must be outside the audit directory"));
+        }
 
-        Ok(())
+        tokio::task::spawn_blocking(move || {
+            let verifying_key = integrity::load_verifying_key(pinned.as_deref())?;
+            let require_signatures = sign_entries || verifying_key.is_some();
+            integrity::verify_chain(&files, verifying_key.as_ref(), require_signatures)
+        })
+        .await
+        .context("Audit verification task failed")?
     }
 
     pub async fn get_execution(&self, execution_id: Uuid) -> Result<ExecutionRecord> {
-        let content = tokio::fs::read_to_string(&self.log_file).await
-            .context("Failed to read audit log")?;
+        let files = self.files.clone();
+        let (request_entry, result_entry) = tokio::task::spawn_blocking(move || {
+            query::find_execution(&files, execution_id)
+        })
+        .await
+        .context("Audit query task failed")??;
 
-        let mut request: Option<ExecutionRequest> = None;
-        let mut result: Option<ExecutionResult> = None;
+        let request = request_entry.as_ref()
+            .and_then(|e| serde_json::from_value::<ExecutionRequest>(e.data.clone()).ok());
+        let result = result_entry
+            .and_then(|e| serde_json::from_value::<ExecutionResult>(e.data).ok());
 
-        for line in content.lines() {
-            if let Ok(entry) = serde_json::from_str::<AuditEntry>(line) {
-                match entry.entry_type {
-                    AuditEntryType::ExecutionRequest => {
-                        if let Ok(req) = serde_json::from_value::<ExecutionRequest>(entry.data) {
-                            if req.id == execution_id {
-                                request = Some(req);
-                            }
-                        }
-                    }
-                    AuditEntryType::ExecutionResult => {
-                        if let Ok(res) = serde_json::from_value::<ExecutionResult>(entry.data) {
-                            if res.id == execution_id {
-                                result = Some(res);
-                            }
-                        }
-                    }
-                    _ => {}
-                }
-            }
-        }
-
-        match (request, result) {
-            (Some(req), Some(res)) => Ok(ExecutionRecord {
+        match (request_entry, request, result) {
+            (Some(entry), Some(req), Some(res)) => Ok(ExecutionRecord {
                 request: req,
                 result: res,
-                timestamp: Utc::now(),
-                user: self.get_current_user(),
-                session_id: self.get_session_id(),
+                timestamp: entry.timestamp,
+                user: entry.user,
+                session_id: entry.session_id,
             }),
             _ => Err(anyhow::anyhow!("Execution record not found: {}", execution_id)),
         }
     }
 
     pub async fn get_recent_executions(&self, limit: usize) -> Result<Vec<ExecutionRecord>> {
-        let content = tokio::fs::read_to_string(&self.log_file).await
-            .context("Failed to read audit log")?;
+        let mut audit_query = AuditQuery::new();
+        audit_query.entry_types = vec![AuditEntryType::ExecutionRequest];
+        audit_query.per_page = limit;
 
-        let mut executions = std::collections::HashMap::new();
-        let mut requests = std::collections::HashMap::new();
-        let mut results = std::collections::HashMap::new();
+        let page = self.query(audit_query).await?;
 
-        // Parse all entries
-        for line in content.lines() {
-            if let Ok(entry) = serde_json::from_str::<AuditEntry>(line) {
-                match entry.entry_type {
-                    AuditEntryType::ExecutionRequest => {
-                        if let Ok(req) = serde_json::from_value::<ExecutionRequest>(entry.data) {
-                            requests.insert(req.id, (req, entry.timestamp, entry.user, entry.session_id));
-                        }
-                    }
-                    AuditEntryType::ExecutionResult => {
-                        if let Ok(res) = serde_json::from_value::<ExecutionResult>(entry.data) {
-                            results.insert(res.id, res);
-                        }
-                    }
-                    _ => {}
+        let mut executions = Vec::new();
+        for hit in page.hits {
+            if let Some(execution_id) = hit.summary.execution_id {
+                if let Ok(record) = self.get_execution(execution_id).await {
+                    executions.push(record);
                 }
             }
         }
 
-        // Combine requests and results
-        for (id, (request, timestamp, user, session_id)) in requests {
-            if let Some(result) = results.get(&id) {
-                executions.insert(id, ExecutionRecord {
-                    request,
-                    result: result.clone(),
-                    timestamp,
-                    user,
-                    session_id,
-                });
-            }
-        }
-
-        // Sort by timestamp and take the most recent
-        let mut execution_list: Vec<_> = executions.into_values().collect();
-        execution_list.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
-        execution_list.truncate(limit);
-
-        Ok(execution_list)
+        Ok(executions)
     }
 
-    pub async fn get_audit_summary(&self) -> Result<AuditSummary> {
-        let content = tokio::fs::read_to_string(&self.log_file).await
-            .context("Failed to read audit log")?;
-
-        let mut summary = AuditSummary::default();
-
-        for line in content.lines() {
-            if let Ok(entry) = serde_json::from_str::<AuditEntry>(line) {
-                match entry.entry_type {
-                    AuditEntryType::ExecutionRequest => summary.total_executions += 1,
-                    AuditEntryType::PermissionDenied => summary.permission_denials += 1,
-                    AuditEntryType::SafetyViolation => summary.safety_violations += 1,
-                    AuditEntryType::Error => summary.errors += 1,
-                    _ => {}
-                }
-
-                // Track recent activity (last 24 hours)
-                if entry.timestamp > Utc::now() - chrono::Duration::hours(24) {
-                    summary.recent_activity += 1;
-                }
-            }
-        }
-
-        Ok(summary)
+    /// Filter the audit log through its index; results are newest first
+    pub async fn query(&self, audit_query: AuditQuery) -> Result<AuditPage> {
+        let files = self.files.clone();
+        tokio::task::spawn_blocking(move || query::run_query(&files, &audit_query))
+            .await
+            .context("Audit query task failed")?
     }
 
-    pub async fn export_audit_log(&self, format: ExportFormat) -> Result<String> {
-        let content = tokio::fs::read_to_string(&self.log_file).await
-            .context("Failed to read audit log")?;
+    /// Export a query result as JSON lines, CSV or HTML
+    pub fn export_entries(&self, entries: &[AuditEntry], format: ExportFormat) -> Result<String> {
+        let mut content = String::new();
+        for entry in entries {
+            content.push_str(&serde_json::to_string(entry)?);
+            content.push('\n');
+        }
 
         match format {
             ExportFormat::Json => Ok(content),
@@ -394,20 +457,10 @@ impl AuditLogger {
     }
 
     fn get_session_id(&self) -> String {
-        // Simple session ID based on process ID and start time
-        format!("{}_{}", std::process::id(), Utc::now().timestamp())
+        self.session_id.clone()
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
-pub struct AuditSummary {
-    pub total_executions: u64,
-    pub permission_denials: u64,
-    pub safety_violations: u64,
-    pub errors: u64,
-    pub recent_activity: u64,
-}
-
 #[derive(Debug, Clone)]
 pub enum ExportFormat {
     Json,
@@ -415,15 +468,15 @@ pub enum ExportFormat {
     Html,
 }
 
-impl std::fmt::Display for AuditSummary {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "📊 TuxPilot Audit Summary")?;
-        writeln!(f, "━━━━━━━━━━━━━━━━━━━━━━━━━━")?;
-        writeln!(f, "🔧 Total Executions: {}", self.total_executions)?;
-        writeln!(f, "🚫 Permission Denials: {}", self.permission_denials)?;
-        writeln!(f, "⚠️  Safety Violations: {}", self.safety_violations)?;
-        writeln!(f, "❌ Errors: {}", self.errors)?;
-        writeln!(f, "📈 Recent Activity (24h): {}", self.recent_activity)?;
-        Ok(())
+impl std::str::FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(ExportFormat::Json),
+            "csv" => Ok(ExportFormat::Csv),
+            "html" => Ok(ExportFormat::Html),
+            _ => Err(anyhow::anyhow!("Unknown export format '{}'. Use json, csv or html", s)),
+        }
     }
 }
@@ -0,0 +1,416 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use super::rotation::{self, AuditFiles, AuditLock};
+use super::{AuditEntry, AuditEntryType};
+
+/// `prev_hash` of the very first entry in a chain
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Position of the newest entry in the chain, persisted next to the active log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainHead {
+    pub sequence: u64,
+    pub hash: String,
+    /// Last entry removed by the retention policy, if any
+    pub pruned_through: Option<ChainLink>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainLink {
+    pub sequence: u64,
+    pub hash: String,
+}
+
+impl Default for ChainHead {
+    fn default() -> Self {
+        Self {
+            sequence: 0,
+            hash: GENESIS_HASH.to_string(),
+            pruned_through: None,
+        }
+    }
+}
+
+/// The fields covered by an entry's hash, in a fixed order
+#[derive(Serialize)]
+struct HashedFields<'a> {
+    sequence: u64,
+    prev_hash: &'a str,
+    id: &'a Uuid,
+    timestamp: &'a DateTime<Utc>,
+    entry_type: &'a AuditEntryType,
+    user: &'a str,
+    session_id: &'a str,
+    data: &'a serde_json::Value,
+}
+
+pub fn compute_entry_hash(entry: &AuditEntry) -> Result<String> {
+    let fields = HashedFields {
+        sequence: entry.sequence,
+        prev_hash: &entry.prev_hash,
+        id: &entry.id,
+        timestamp: &entry.timestamp,
+        entry_type: &entry.entry_type,
+        user: &entry.user,
+        session_id: &entry.session_id,
+        data: &entry.data,
+    };
+
+    let bytes = serde_json::to_vec(&fields).context("Failed to serialize audit entry for hashing")?;
+    Ok(hex::encode(Sha256::digest(&bytes)))
+}
+
+/// Where an administrator installs the host public key, root-owned, when no key is
+/// pinned in the config
+pub const SYSTEM_VERIFYING_KEY: &str = "/etc/tuxpilot/audit.pub";
+
+/// ed25519 key used to sign audit entries on this host
+#[derive(Debug, Clone)]
+pub struct HostKey {
+    signing_key: SigningKey,
+}
+
+impl HostKey {
+    /// Load the host key, generating it (mode 0600) together with its `.pub` file on first use
+    pub fn load_or_create(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create key directory: {:?}", parent))?;
+        }
+
+        // create_new with the final mode, so the secret is never readable by others
+        let mut file = match OpenOptions::new().write(true).create_new(true).mode(0o600).open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Self::load(path),
+            Err(e) => return Err(e).with_context(|| format!("Failed to create audit signing key: {:?}", path)),
+        };
+
+        let mut secret = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut secret);
+        let signing_key = SigningKey::from_bytes(&secret);
+
+        file.write_all(hex::encode(secret).as_bytes())
+            .with_context(|| format!("Failed to write audit signing key: {:?}", path))?;
+        std::fs::write(public_key_path(path), hex::encode(signing_key.verifying_key().to_bytes()))
+            .context("Failed to write audit public key")?;
+
+        log::info!("Generated audit signing key at {:?}; install {:?} as {} to verify against it",
+                   path, public_key_path(path), SYSTEM_VERIFYING_KEY);
+        Ok(Self { signing_key })
+    }
+
+    fn load(path: &Path) -> Result<Self> {
+        let encoded = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read audit signing key: {:?}", path))?;
+        let bytes: [u8; 32] = hex::decode(encoded.trim())
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .context("Audit signing key is not a 32-byte hex string")?;
+
+        Ok(Self { signing_key: SigningKey::from_bytes(&bytes) })
+    }
+
+    pub fn sign(&self, hash: &str) -> String {
+        hex::encode(self.signing_key.sign(hash.as_bytes()).to_bytes())
+    }
+}
+
+pub fn public_key_path(key_path: &Path) -> PathBuf {
+    key_path.with_extension("pub")
+}
+
+/// The public key signatures are checked against: the one pinned in the config, or
+/// the system key when root owns it and nobody else can write it. Never the signing
+/// key or anything else the user who writes the log controls.
+pub fn load_verifying_key(pinned: Option<&Path>) -> Result<Option<VerifyingKey>> {
+    let path = match pinned {
+        Some(path) => path,
+        None => {
+            let path = Path::new(SYSTEM_VERIFYING_KEY);
+            let trusted = std::fs::metadata(path)
+                .map(|metadata| metadata.uid() == 0 && metadata.mode() & 0o022 == 0)
+                .unwrap_or(false);
+            if !trusted {
+                return Ok(None);
+            }
+            path
+        }
+    };
+
+    let encoded = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read audit public key: {:?}", path))?;
+    let key = hex::decode(encoded.trim())
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+        .with_context(|| format!("Audit public key is not a valid ed25519 key: {:?}", path))?;
+    Ok(Some(key))
+}
+
+fn record(
+    report: &mut VerificationReport,
+    segment: &Path,
+    line: usize,
+    kind: IntegrityIssueKind,
+    sequence: Option<u64>,
+    detail: String,
+) {
+    report.issues.push(IntegrityIssue {
+        kind,
+        segment: segment.to_path_buf(),
+        line,
+        sequence,
+        detail,
+    });
+}
+
+fn verify_signature(key: &VerifyingKey, hash: &str, signature: &str) -> bool {
+    hex::decode(signature)
+        .ok()
+        .and_then(|bytes| Signature::from_slice(&bytes).ok())
+        .map(|sig| key.verify(hash.as_bytes(), &sig).is_ok())
+        .unwrap_or(false)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum IntegrityIssueKind {
+    MalformedEntry,
+    SequenceGap,
+    SequenceOutOfOrder,
+    BrokenLink,
+    ModifiedEntry,
+    InvalidSignature,
+    MissingSignature,
+    UnchainedEntry,
+    TruncatedLog,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntegrityIssue {
+    pub kind: IntegrityIssueKind,
+    pub segment: PathBuf,
+    pub line: usize,
+    pub sequence: Option<u64>,
+    pub detail: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct VerificationReport {
+    pub segments_checked: usize,
+    pub entries_checked: u64,
+    pub signed_entries: u64,
+    pub legacy_entries: u64,
+    pub first_sequence: Option<u64>,
+    pub last_sequence: Option<u64>,
+    pub issues: Vec<IntegrityIssue>,
+}
+
+impl VerificationReport {
+    pub fn is_intact(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Walk every segment in chain order and check sequence continuity, hash links,
+/// entry hashes and signatures against the persisted chain head. With
+/// `require_signatures` every chained entry has to be signed, so stripping the
+/// signatures and rewriting the chain does not pass as an unsigned log.
+pub fn verify_chain(files: &AuditFiles, verifying_key: Option<&VerifyingKey>, require_signatures: bool) -> Result<VerificationReport> {
+    let _lock = AuditLock::acquire(files, false)?;
+
+    let head = files.read_head()?;
+    let mut report = VerificationReport::default();
+
+    let (mut expected_sequence, mut expected_hash) = match head.as_ref().and_then(|h| h.pruned_through.as_ref()) {
+        Some(link) => (link.sequence, link.hash.clone()),
+        None => (0, GENESIS_HASH.to_string()),
+    };
+    let mut chain_started = false;
+
+    for segment in files.all_segments()? {
+        report.segments_checked += 1;
+        let content = rotation::read_segment(&segment)?;
+
+        for (index, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let entry: AuditEntry = match serde_json::from_str(line) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    record(&mut report, &segment, index + 1, IntegrityIssueKind::MalformedEntry, None, format!("Unparseable entry: {}", e));
+                    continue;
+                }
+            };
+
+            // Entries written before hash chaining was introduced carry no hash
+            if entry.hash.is_empty() {
+                if chain_started {
+                    record(
+                        &mut report,
+                        &segment,
+                        index + 1,
+                        IntegrityIssueKind::UnchainedEntry,
+                        None,
+                        "Entry without hash inside the chain".to_string(),
+                    );
+                } else {
+                    report.legacy_entries += 1;
+                }
+                continue;
+            }
+
+            chain_started = true;
+            report.entries_checked += 1;
+            report.first_sequence.get_or_insert(entry.sequence);
+            report.last_sequence = Some(entry.sequence);
+
+            if entry.sequence > expected_sequence + 1 {
+                record(
+                    &mut report,
+                    &segment,
+                    index + 1,
+                    IntegrityIssueKind::SequenceGap,
+                    Some(entry.sequence),
+                    format!("Entries {}..{} are missing", expected_sequence + 1, entry.sequence - 1),
+                );
+            } else if entry.sequence <= expected_sequence {
+                record(
+                    &mut report,
+                    &segment,
+                    index + 1,
+                    IntegrityIssueKind::SequenceOutOfOrder,
+                    Some(entry.sequence),
+                    format!("Expected sequence {}", expected_sequence + 1),
+                );
+            }
+
+            if entry.prev_hash != expected_hash {
+                record(
+                    &mut report,
+                    &segment,
+                    index + 1,
+                    IntegrityIssueKind::BrokenLink,
+                    Some(entry.sequence),
+                    "Previous hash does not match the preceding entry".to_string(),
+                );
+            }
+
+            if compute_entry_hash(&entry)? != entry.hash {
+                record(
+                    &mut report,
+                    &segment,
+                    index + 1,
+                    IntegrityIssueKind::ModifiedEntry,
+                    Some(entry.sequence),
+                    "Entry content does not match its hash".to_string(),
+                );
+            }
+
+            match (&entry.signature, verifying_key) {
+                (Some(signature), Some(key)) => {
+                    report.signed_entries += 1;
+                    if !verify_signature(key, &entry.hash, signature) {
+                        record(
+                            &mut report,
+                            &segment,
+                            index + 1,
+                            IntegrityIssueKind::InvalidSignature,
+                            Some(entry.sequence),
+                            "Signature does not match the host key".to_string(),
+                        );
+                    }
+                }
+                (Some(_), None) => {
+                    record(
+                        &mut report,
+                        &segment,
+                        index + 1,
+                        IntegrityIssueKind::InvalidSignature,
+                        Some(entry.sequence),
+                        format!("Entry is signed but no trusted host public key is available; pin one with \
+                                 audit.verifying_key_path or install it as {}", SYSTEM_VERIFYING_KEY),
+                    );
+                }
+                (None, _) if require_signatures => {
+                    record(
+                        &mut report,
+                        &segment,
+                        index + 1,
+                        IntegrityIssueKind::MissingSignature,
+                        Some(entry.sequence),
+                        "Entry is not signed".to_string(),
+                    );
+                }
+                (None, _) => {}
+            }
+
+            expected_sequence = entry.sequence;
+            expected_hash = entry.hash;
+        }
+    }
+
+    if let Some(head) = head {
+        if head.sequence != expected_sequence || head.hash != expected_hash {
+            report.issues.push(IntegrityIssue {
+                kind: IntegrityIssueKind::TruncatedLog,
+                segment: files.active_log(),
+                line: 0,
+                sequence: Some(head.sequence),
+                detail: format!(
+                    "Chain head records sequence {} but the log ends at {}",
+                    head.sequence, expected_sequence
+                ),
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+impl std::fmt::Display for VerificationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "🔏 TuxPilot Audit Verification")?;
+        writeln!(f, "━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━")?;
+        writeln!(f, "📁 Segments Checked: {}", self.segments_checked)?;
+        writeln!(f, "🔗 Chained Entries: {}", self.entries_checked)?;
+        if let (Some(first), Some(last)) = (self.first_sequence, self.last_sequence) {
+            writeln!(f, "🔢 Sequence Range: {} - {}", first, last)?;
+        }
+        writeln!(f, "✍️  Signed Entries: {}", self.signed_entries)?;
+        if self.legacy_entries > 0 {
+            writeln!(f, "📜 Legacy Entries (unchained): {}", self.legacy_entries)?;
+        }
+        writeln!(f)?;
+
+        if self.is_intact() {
+            writeln!(f, "✅ Audit log is intact")?;
+        } else {
+            writeln!(f, "❌ {} integrity issue(s) found:", self.issues.len())?;
+            for issue in &self.issues {
+                writeln!(
+                    f,
+                    "  • {:?} in {}:{}{} - {}",
+                    issue.kind,
+                    issue.segment.display(),
+                    issue.line,
+                    issue.sequence.map(|s| format!(" (seq {})", s)).unwrap_or_default(),
+                    issue.detail
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
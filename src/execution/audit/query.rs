@@ -0,0 +1,426 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use crate::execution::RiskLevel;
+use super::rotation::{self, AuditFiles, AuditLock};
+use super::{AuditEntry, AuditEntryType};
+
+const INDEX_FILE: &str = "audit.idx";
+
+/// Compact, filterable summary of one audit entry. The index is a JSONL file kept
+/// next to the log and extended incrementally; full entries are only read back
+/// for the page being returned.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditIndexRecord {
+    pub sequence: u64,
+    /// Sequence of the first entry in the segment holding this entry
+    pub segment_start: u64,
+    /// Zero-based line within that segment
+    pub line: usize,
+    pub timestamp: DateTime<Utc>,
+    pub entry_type: AuditEntryType,
+    pub user: String,
+    pub session_id: String,
+    pub execution_id: Option<Uuid>,
+    pub command: Option<String>,
+    pub risk_level: Option<RiskLevel>,
+    pub success: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditQuery {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub user: Option<String>,
+    pub session_id: Option<String>,
+    /// Minimum risk level; entries without a risk level never match
+    pub min_risk: Option<RiskLevel>,
+    pub entry_types: Vec<AuditEntryType>,
+    /// Case-insensitive substring of the command line
+    pub command_contains: Option<String>,
+    pub success: Option<bool>,
+    /// One-based page number
+    pub page: usize,
+    pub per_page: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditHit {
+    pub summary: AuditIndexRecord,
+    pub entry: AuditEntry,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditPage {
+    pub hits: Vec<AuditHit>,
+    pub total: usize,
+    pub page: usize,
+    pub per_page: usize,
+    pub total_pages: usize,
+}
+
+impl AuditQuery {
+    pub fn new() -> Self {
+        Self {
+            page: 1,
+            per_page: 50,
+            ..Default::default()
+        }
+    }
+
+    pub fn matches(&self, record: &AuditIndexRecord) -> bool {
+        if self.since.map(|since| record.timestamp < since).unwrap_or(false) {
+            return false;
+        }
+        if self.until.map(|until| record.timestamp > until).unwrap_or(false) {
+            return false;
+        }
+        if self.user.as_ref().map(|u| &record.user != u).unwrap_or(false) {
+            return false;
+        }
+        if self.session_id.as_ref().map(|s| &record.session_id != s).unwrap_or(false) {
+            return false;
+        }
+        if !self.entry_types.is_empty() && !self.entry_types.contains(&record.entry_type) {
+            return false;
+        }
+        if let Some(min_risk) = &self.min_risk {
+            match &record.risk_level {
+                Some(risk) if risk >= min_risk => {}
+                _ => return false,
+            }
+        }
+        if let Some(needle) = &self.command_contains {
+            let needle = needle.to_lowercase();
+            match &record.command {
+                Some(command) if command.to_lowercase().contains(&needle) => {}
+                _ => return false,
+            }
+        }
+        if let Some(success) = self.success {
+            if record.success != Some(success) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Parse `--since`/`--until` values: RFC 3339 timestamps, plain dates
+/// (`2025-01-31`) or durations relative to now (`30m`, `24h`, `7d`, `2w`)
+pub fn parse_time_bound(value: &str) -> Result<DateTime<Utc>> {
+    let value = value.trim();
+
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc());
+    }
+
+    // Split before the last character, which need not be ASCII in user input
+    let unit_start = value.char_indices().last().map_or(0, |(index, _)| index);
+    let (amount, unit) = value.split_at(unit_start);
+    let amount: i64 = amount.parse()
+        .with_context(|| format!("Invalid time value '{}'. Use RFC 3339, YYYY-MM-DD or e.g. 24h", value))?;
+    let duration = match unit {
+        "s" => chrono::Duration::seconds(amount),
+        "m" => chrono::Duration::minutes(amount),
+        "h" => chrono::Duration::hours(amount),
+        "d" => chrono::Duration::days(amount),
+        "w" => chrono::Duration::weeks(amount),
+        _ => return Err(anyhow::anyhow!("Unknown time unit in '{}'. Use s, m, h, d or w", value)),
+    };
+
+    Ok(Utc::now() - duration)
+}
+
+pub fn parse_risk_level(value: &str) -> Result<RiskLevel> {
+    match value.to_lowercase().as_str() {
+        "safe" => Ok(RiskLevel::Safe),
+        "low" => Ok(RiskLevel::Low),
+        "medium" => Ok(RiskLevel::Medium),
+        "high" => Ok(RiskLevel::High),
+        "critical" => Ok(RiskLevel::Critical),
+        _ => Err(anyhow::anyhow!("Unknown risk level '{}'. Use safe, low, medium, high or critical", value)),
+    }
+}
+
+/// Accepts variant names in any case, with or without separators (`safety-violation`)
+pub fn parse_entry_type(value: &str) -> Result<AuditEntryType> {
+    let normalized: String = value.chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+
+    match normalized.as_str() {
+        "executionrequest" => Ok(AuditEntryType::ExecutionRequest),
+        "executionresult" => Ok(AuditEntryType::ExecutionResult),
+        "permissionrequest" => Ok(AuditEntryType::PermissionRequest),
+        "permissiongranted" => Ok(AuditEntryType::PermissionGranted),
+        "permissiondenied" => Ok(AuditEntryType::PermissionDenied),
+        "safetyviolation" => Ok(AuditEntryType::SafetyViolation),
+        "systemchange" => Ok(AuditEntryType::SystemChange),
+        "error" => Ok(AuditEntryType::Error),
+        _ => Err(anyhow::anyhow!("Unknown audit entry type '{}'", value)),
+    }
+}
+
+fn index_path(files: &AuditFiles) -> PathBuf {
+    files.dir().join(INDEX_FILE)
+}
+
+pub fn summarize(entry: &AuditEntry, segment_start: u64, line: usize) -> AuditIndexRecord {
+    let data = &entry.data;
+    let mut record = AuditIndexRecord {
+        sequence: entry.sequence,
+        segment_start,
+        line,
+        timestamp: entry.timestamp,
+        entry_type: entry.entry_type.clone(),
+        user: entry.user.clone(),
+        session_id: entry.session_id.clone(),
+        execution_id: None,
+        command: None,
+        risk_level: None,
+        success: None,
+    };
+
+    match entry.entry_type {
+        AuditEntryType::ExecutionRequest => {
+            record.execution_id = data.get("id").and_then(|v| serde_json::from_value(v.clone()).ok());
+            record.risk_level = data.get("risk_level").and_then(|v| serde_json::from_value(v.clone()).ok());
+
+            let command = data.get("command").and_then(|v| v.as_str()).unwrap_or_default();
+            let args: Vec<&str> = data.get("args")
+                .and_then(|v| v.as_array())
+                .map(|args| args.iter().filter_map(|a| a.as_str()).collect())
+                .unwrap_or_default();
+            let command_line = std::iter::once(command).chain(args).collect::<Vec<_>>().join(" ");
+            record.command = Some(match data.get("host").and_then(|v| v.as_str()) {
+                Some(host) => format!("{}: {}", host, command_line),
+                None => command_line,
+            });
+        }
+        AuditEntryType::ExecutionResult => {
+            record.execution_id = data.get("id").and_then(|v| serde_json::from_value(v.clone()).ok());
+            record.success = data.get("success").and_then(|v| v.as_bool());
+        }
+        AuditEntryType::PermissionRequest | AuditEntryType::PermissionGranted | AuditEntryType::PermissionDenied => {
+            // Permission prompts name the permission; queued approvals name the command
+            record.command = data.get("permission")
+                .or_else(|| data.get("command"))
+                .and_then(|v| v.as_str())
+                .map(str::to_string);
+            record.execution_id = data.get("execution_id").and_then(|v| serde_json::from_value(v.clone()).ok());
+            record.risk_level = data.get("risk_level").and_then(|v| serde_json::from_value(v.clone()).ok());
+            record.success = data.get("granted").and_then(|v| v.as_bool());
+        }
+        AuditEntryType::SafetyViolation => {
+            record.command = data.get("command").and_then(|v| v.as_str()).map(str::to_string);
+            record.risk_level = Some(RiskLevel::High);
+            record.success = Some(false);
+        }
+        AuditEntryType::SystemChange => {
+            record.command = data.get("description").and_then(|v| v.as_str()).map(str::to_string);
+        }
+        AuditEntryType::Error => {
+            record.success = Some(false);
+        }
+    }
+
+    record
+}
+
+/// Map each segment's first sequence to its path, oldest first
+fn segment_map(files: &AuditFiles) -> Result<BTreeMap<u64, PathBuf>> {
+    let mut segments = BTreeMap::new();
+
+    for path in files.rotated_segments()? {
+        if let Some(start) = rotation::segment_start(&path) {
+            segments.insert(start, path);
+        }
+    }
+
+    let active = files.active_log();
+    if let Some(first) = rotation::first_entry(&active) {
+        segments.insert(first.sequence, active);
+    }
+
+    Ok(segments)
+}
+
+fn load_index(files: &AuditFiles) -> Result<Vec<AuditIndexRecord>> {
+    let content = match fs::read_to_string(index_path(files)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context("Failed to read audit index"),
+    };
+
+    Ok(content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+}
+
+/// Bring the index up to date with the log and drop records for pruned segments.
+/// Must be called with the audit lock held.
+fn refresh_index(files: &AuditFiles) -> Result<Vec<AuditIndexRecord>> {
+    let mut records = load_index(files)?;
+    let segments = segment_map(files)?;
+
+    // Records whose segment was removed by retention can no longer be resolved
+    let before = records.len();
+    records.retain(|r| segments.contains_key(&r.segment_start));
+    if records.len() != before {
+        let mut content = String::new();
+        for record in &records {
+            content.push_str(&serde_json::to_string(record)?);
+            content.push('\n');
+        }
+        fs::write(index_path(files), content).context("Failed to rewrite audit index")?;
+    }
+
+    let fresh_index = records.is_empty();
+    let last_sequence = records.iter().map(|r| r.sequence).max().unwrap_or(0);
+
+    let mut writer = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(index_path(files))
+        .context("Failed to open audit index")?;
+
+    let starts: Vec<u64> = segments.keys().copied().collect();
+    for (position, (start, path)) in segments.iter().enumerate() {
+        // Every entry of this segment precedes the next segment's start
+        if let Some(next_start) = starts.get(position + 1) {
+            if *next_start <= last_sequence && !fresh_index {
+                continue;
+            }
+        }
+
+        let content = rotation::read_segment(path)?;
+        for (line, raw) in content.lines().enumerate() {
+            let entry: AuditEntry = match serde_json::from_str(raw) {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+
+            // Unchained legacy entries are only picked up when building from scratch
+            let is_new = entry.sequence > last_sequence || (fresh_index && entry.hash.is_empty());
+            if is_new {
+                let record = summarize(&entry, *start, line);
+                writeln!(writer, "{}", serde_json::to_string(&record)?)
+                    .context("Failed to update audit index")?;
+                records.push(record);
+            }
+        }
+    }
+
+    Ok(records)
+}
+
+/// Copy command and risk level from each execution request onto its result
+fn join_results(records: &mut [AuditIndexRecord]) {
+    let requests: HashMap<Uuid, (Option<String>, Option<RiskLevel>)> = records.iter()
+        .filter(|r| matches!(r.entry_type, AuditEntryType::ExecutionRequest))
+        .filter_map(|r| r.execution_id.map(|id| (id, (r.command.clone(), r.risk_level.clone()))))
+        .collect();
+
+    for record in records.iter_mut() {
+        if matches!(record.entry_type, AuditEntryType::ExecutionResult) {
+            if let Some((command, risk)) = record.execution_id.and_then(|id| requests.get(&id)) {
+                record.command = command.clone();
+                record.risk_level = risk.clone();
+            }
+        }
+    }
+}
+
+/// Read back the full entries for the given records, touching each segment once
+fn fetch_entries(files: &AuditFiles, records: &[AuditIndexRecord]) -> Result<Vec<Option<AuditEntry>>> {
+    let segments = segment_map(files)?;
+    let mut contents: HashMap<u64, Vec<String>> = HashMap::new();
+
+    let mut entries = Vec::with_capacity(records.len());
+    for record in records {
+        let lines = match contents.entry(record.segment_start) {
+            std::collections::hash_map::Entry::Occupied(slot) => slot.into_mut(),
+            std::collections::hash_map::Entry::Vacant(slot) => {
+                let lines = match segments.get(&record.segment_start) {
+                    Some(path) => rotation::read_segment(path)?.lines().map(str::to_string).collect(),
+                    None => Vec::new(),
+                };
+                slot.insert(lines)
+            }
+        };
+
+        let entry = lines
+            .get(record.line)
+            .and_then(|line| serde_json::from_str(line).ok());
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+/// Filter the indexed log, newest first, and return the requested page
+pub fn run_query(files: &AuditFiles, query: &AuditQuery) -> Result<AuditPage> {
+    let _lock = AuditLock::acquire(files, true)?;
+
+    let mut records = refresh_index(files)?;
+    join_results(&mut records);
+
+    let mut matched: Vec<AuditIndexRecord> = records.into_iter().filter(|r| query.matches(r)).collect();
+    matched.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then(b.sequence.cmp(&a.sequence)));
+
+    let per_page = query.per_page.max(1);
+    let page = query.page.max(1);
+    let total = matched.len();
+    let total_pages = total.div_ceil(per_page);
+
+    let page_records: Vec<AuditIndexRecord> = matched.into_iter()
+        .skip((page - 1) * per_page)
+        .take(per_page)
+        .collect();
+
+    let hits = fetch_entries(files, &page_records)?
+        .into_iter()
+        .zip(page_records)
+        .filter_map(|(entry, summary)| entry.map(|entry| AuditHit { summary, entry }))
+        .collect();
+
+    Ok(AuditPage {
+        hits,
+        total,
+        page,
+        per_page,
+        total_pages,
+    })
+}
+
+/// Look up the request and result entries of one execution through the index
+pub fn find_execution(files: &AuditFiles, execution_id: Uuid) -> Result<(Option<AuditEntry>, Option<AuditEntry>)> {
+    let _lock = AuditLock::acquire(files, true)?;
+
+    let records: Vec<AuditIndexRecord> = refresh_index(files)?
+        .into_iter()
+        .filter(|r| r.execution_id == Some(execution_id))
+        .collect();
+
+    let mut request = None;
+    let mut result = None;
+    for (entry, record) in fetch_entries(files, &records)?.into_iter().zip(&records) {
+        match record.entry_type {
+            AuditEntryType::ExecutionRequest => request = entry,
+            AuditEntryType::ExecutionResult => result = entry,
+            _ => {}
+        }
+    }
+
+    Ok((request, result))
+}
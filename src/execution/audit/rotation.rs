@@ -0,0 +1,264 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use crate::config::AuditConfig;
+use super::integrity::{ChainHead, ChainLink};
+use super::AuditEntry;
+
+const ACTIVE_LOG: &str = "audit.jsonl";
+const HEAD_FILE: &str = "audit.head";
+const LOCK_FILE: &str = "audit.lock";
+const SEGMENT_PREFIX: &str = "audit-";
+
+/// Layout of the audit directory: the active log, rotated segments and the chain head
+#[derive(Debug, Clone)]
+pub struct AuditFiles {
+    dir: PathBuf,
+}
+
+/// Advisory lock on the audit directory, shared between tuxpilot processes.
+/// The lock is released when the underlying file is closed.
+pub struct AuditLock {
+    _file: File,
+}
+
+impl AuditLock {
+    pub fn acquire(files: &AuditFiles, exclusive: bool) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(files.lock_file())
+            .context("Failed to open audit lock file")?;
+
+        let operation = if exclusive { libc::LOCK_EX } else { libc::LOCK_SH };
+        if unsafe { libc::flock(file.as_raw_fd(), operation) } != 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to lock audit log");
+        }
+
+        Ok(Self { _file: file })
+    }
+}
+
+impl AuditFiles {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn active_log(&self) -> PathBuf {
+        self.dir.join(ACTIVE_LOG)
+    }
+
+    pub fn head_file(&self) -> PathBuf {
+        self.dir.join(HEAD_FILE)
+    }
+
+    fn lock_file(&self) -> PathBuf {
+        self.dir.join(LOCK_FILE)
+    }
+
+    /// Rotated segments, oldest first
+    pub fn rotated_segments(&self) -> Result<Vec<PathBuf>> {
+        let mut segments = Vec::new();
+
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(segments),
+            Err(e) => return Err(e).context("Failed to list audit directory"),
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+            let is_segment = path.file_name()
+                .and_then(|n| n.to_str())
+                .map(|n| n.starts_with(SEGMENT_PREFIX) && (n.ends_with(".jsonl") || n.ends_with(".jsonl.gz")))
+                .unwrap_or(false);
+
+            if is_segment {
+                segments.push(path);
+            }
+        }
+
+        // Segment names start with the zero-padded sequence of their first entry
+        segments.sort();
+        Ok(segments)
+    }
+
+    /// All segments in chain order, ending with the active log
+    pub fn all_segments(&self) -> Result<Vec<PathBuf>> {
+        let mut segments = self.rotated_segments()?;
+        let active = self.active_log();
+        if active.exists() {
+            segments.push(active);
+        }
+        Ok(segments)
+    }
+
+    pub fn read_head(&self) -> Result<Option<ChainHead>> {
+        match fs::read_to_string(self.head_file()) {
+            Ok(content) => Ok(Some(serde_json::from_str(&content).context("Failed to parse audit chain head")?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("Failed to read audit chain head"),
+        }
+    }
+
+    pub fn write_head(&self, head: &ChainHead) -> Result<()> {
+        let tmp = self.dir.join(format!("{}.tmp", HEAD_FILE));
+        fs::write(&tmp, serde_json::to_vec(head)?).context("Failed to write audit chain head")?;
+        fs::rename(&tmp, self.head_file()).context("Failed to replace audit chain head")?;
+        Ok(())
+    }
+}
+
+/// Read a segment, transparently decompressing rotated `.gz` files
+pub fn read_segment(path: &Path) -> Result<String> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open audit segment: {:?}", path))?;
+
+    let mut content = String::new();
+    if path.extension().map(|e| e == "gz").unwrap_or(false) {
+        GzDecoder::new(file).read_to_string(&mut content)
+    } else {
+        BufReader::new(file).read_to_string(&mut content)
+    }
+    .with_context(|| format!("Failed to read audit segment: {:?}", path))?;
+
+    Ok(content)
+}
+
+/// Sequence of the first entry in a rotated segment, taken from its file name
+pub fn segment_start(path: &Path) -> Option<u64> {
+    path.file_name()?
+        .to_str()?
+        .strip_prefix(SEGMENT_PREFIX)?
+        .split('-')
+        .next()?
+        .parse()
+        .ok()
+}
+
+pub fn first_entry(path: &Path) -> Option<AuditEntry> {
+    let file = File::open(path).ok()?;
+    let mut line = String::new();
+    BufReader::new(file).read_line(&mut line).ok()?;
+    serde_json::from_str(&line).ok()
+}
+
+fn last_entry(path: &Path) -> Option<AuditEntry> {
+    let content = read_segment(path).ok()?;
+    content.lines().rev().find_map(|line| serde_json::from_str(line).ok())
+}
+
+/// Rotate the active log if it exceeds the configured size or age, then apply retention.
+/// Must be called with the exclusive audit lock held.
+pub fn rotate_if_needed(files: &AuditFiles, config: &AuditConfig, head: &mut ChainHead) -> Result<()> {
+    let active = files.active_log();
+    let size = match fs::metadata(&active) {
+        Ok(metadata) => metadata.len(),
+        Err(_) => return Ok(()),
+    };
+    if size == 0 {
+        return Ok(());
+    }
+
+    let first = first_entry(&active);
+    let too_large = size >= config.max_file_size_bytes;
+    let too_old = first.as_ref()
+        .map(|e| e.timestamp < Utc::now() - chrono::Duration::days(config.max_file_age_days as i64))
+        .unwrap_or(false);
+
+    if !too_large && !too_old {
+        return Ok(());
+    }
+
+    let first_sequence = first.as_ref().map(|e| e.sequence).unwrap_or(0);
+    rotate(files, config, first_sequence, Utc::now())?;
+    apply_retention(files, config, head)
+}
+
+fn rotate(files: &AuditFiles, config: &AuditConfig, first_sequence: u64, now: DateTime<Utc>) -> Result<()> {
+    let active = files.active_log();
+    let base = format!("{}{:012}-{}.jsonl", SEGMENT_PREFIX, first_sequence, now.format("%Y%m%dT%H%M%S"));
+
+    if config.compress_rotated {
+        let target = files.dir().join(format!("{}.gz", base));
+        let mut input = File::open(&active).context("Failed to open audit log for rotation")?;
+        let output = File::create(&target)
+            .with_context(|| format!("Failed to create audit segment: {:?}", target))?;
+
+        let mut encoder = GzEncoder::new(output, Compression::default());
+        std::io::copy(&mut input, &mut encoder).context("Failed to compress audit segment")?;
+        encoder.finish()?.sync_all()?;
+        fs::remove_file(&active).context("Failed to remove rotated audit log")?;
+    } else {
+        fs::rename(&active, files.dir().join(base)).context("Failed to rotate audit log")?;
+    }
+
+    log::info!("Rotated audit log at sequence {}", first_sequence);
+    Ok(())
+}
+
+/// Delete the oldest rotated segments beyond the retention policy. Only a contiguous
+/// prefix is removed so the remaining chain stays verifiable; the last pruned link is
+/// recorded in the chain head.
+fn apply_retention(files: &AuditFiles, config: &AuditConfig, head: &mut ChainHead) -> Result<()> {
+    let segments = files.rotated_segments()?;
+    let cutoff = std::time::SystemTime::now()
+        - std::time::Duration::from_secs(config.retention_days as u64 * 24 * 60 * 60);
+
+    let excess = segments.len().saturating_sub(config.keep_rotated);
+    let expired = segments.iter()
+        .take_while(|path| {
+            fs::metadata(path)
+                .and_then(|m| m.modified())
+                .map(|modified| modified < cutoff)
+                .unwrap_or(false)
+        })
+        .count();
+
+    let prune_count = excess.max(expired);
+    if prune_count == 0 {
+        return Ok(());
+    }
+
+    for path in &segments[..prune_count] {
+        if let Some(last) = last_entry(path) {
+            if !last.hash.is_empty() {
+                head.pruned_through = Some(ChainLink {
+                    sequence: last.sequence,
+                    hash: last.hash,
+                });
+            }
+        }
+        fs::remove_file(path)
+            .with_context(|| format!("Failed to remove expired audit segment: {:?}", path))?;
+        log::info!("Removed expired audit segment {:?}", path);
+    }
+
+    Ok(())
+}
+
+/// Append one serialized entry to the active log
+pub fn append_line(files: &AuditFiles, line: &str) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(files.active_log())
+        .context("Failed to open audit log file")?;
+
+    file.write_all(format!("{}\n", line).as_bytes())
+        .context("Failed to write audit entry")?;
+    file.sync_data().context("Failed to flush audit log")?;
+
+    Ok(())
+}
@@ -0,0 +1,548 @@
+use anyhow::{Context, Result};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixDatagram, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::config::{AuditSinkConfig, AuditSinkFormat, AuditSinkKind, SyslogTransport};
+use crate::execution::RiskLevel;
+use super::query::{self, AuditIndexRecord};
+use super::{AuditEntry, AuditEntryType};
+
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+const SYSLOG_SOCKET: &str = "/dev/log";
+const DEFAULT_SYSLOG_ADDRESS: &str = "127.0.0.1:514";
+/// Private enterprise number reserved for documentation (RFC 5612)
+const SD_ID: &str = "tuxpilot@32473";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_BACKOFF_EXPONENT: u32 = 6;
+
+/// All configured sinks. Delivery failures never fail the audit write itself;
+/// undelivered entries are spooled per sink in the audit directory until the target
+/// comes back, and whichever process starts next drains the spool.
+#[derive(Debug, Default)]
+pub struct AuditSinks {
+    sinks: Vec<AuditSink>,
+}
+
+#[derive(Debug)]
+pub struct AuditSink {
+    config: AuditSinkConfig,
+    facility: u8,
+    hostname: String,
+    connection: Option<Connection>,
+    /// One hex-encoded message per line, shared by every process writing this audit log
+    spool_path: PathBuf,
+    pending: usize,
+    dropped: u64,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+#[derive(Debug)]
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    UnixDatagram(UnixDatagram),
+    UnixStream(UnixStream),
+    File(File),
+}
+
+/// Locked handle on a sink's spool file
+struct Spool {
+    file: File,
+}
+
+impl AuditSinks {
+    pub fn new(configs: &[AuditSinkConfig], spool_dir: &Path) -> Result<Self> {
+        let mut sinks: Vec<AuditSink> = configs.iter()
+            .filter(|c| c.enabled)
+            .map(|config| AuditSink::new(config, spool_dir))
+            .collect::<Result<_>>()?;
+
+        // Entries left over from an earlier run go out before anything new
+        for sink in &mut sinks {
+            let spooled = std::fs::metadata(&sink.spool_path).map(|m| m.len() > 0).unwrap_or(false);
+            if spooled {
+                if let Err(e) = sink.drain() {
+                    log::warn!("Audit sink '{}' still unavailable ({} entries spooled): {:#}",
+                        sink.config.name, sink.pending, e);
+                }
+            }
+        }
+
+        Ok(Self { sinks })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Forward an entry to every sink, logging (not returning) delivery failures
+    pub fn dispatch(&mut self, entry: &AuditEntry) {
+        for sink in &mut self.sinks {
+            if let Err(e) = sink.deliver(entry) {
+                log::warn!("Audit sink '{}' unavailable ({} entries spooled): {:#}",
+                    sink.config.name, sink.pending, e);
+            }
+        }
+    }
+
+    /// Deliver an entry to every sink immediately, ignoring any retry backoff
+    pub fn test(&mut self, entry: &AuditEntry) -> Vec<(String, Result<()>)> {
+        self.sinks.iter_mut()
+            .map(|sink| {
+                sink.retry_at = None;
+                (sink.config.name.clone(), sink.deliver(entry))
+            })
+            .collect()
+    }
+}
+
+impl AuditSink {
+    pub fn new(config: &AuditSinkConfig, spool_dir: &Path) -> Result<Self> {
+        let facility = parse_facility(&config.facility)
+            .with_context(|| format!("Invalid audit sink '{}'", config.name))?;
+
+        if config.kind == AuditSinkKind::File && config.address.is_none() {
+            return Err(anyhow::anyhow!("Audit sink '{}' needs an address (file path)", config.name));
+        }
+
+        let file_name: String = config.name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+            .collect();
+
+        Ok(Self {
+            config: config.clone(),
+            facility,
+            hostname: gethostname::gethostname().to_string_lossy().to_string(),
+            connection: None,
+            spool_path: spool_dir.join(format!("{}.spool", file_name)),
+            pending: 0,
+            dropped: 0,
+            failures: 0,
+            retry_at: None,
+        })
+    }
+
+    /// Queue the entry behind anything still spooled and try to flush the queue
+    pub fn deliver(&mut self, entry: &AuditEntry) -> Result<()> {
+        let message = self.render(entry);
+        self.with_spool(|sink, pending| {
+            pending.push_back(message);
+            while pending.len() > sink.config.buffer_size.max(1) {
+                pending.pop_front();
+                sink.dropped += 1;
+            }
+            sink.flush(pending)
+        })
+    }
+
+    /// Send whatever is spooled
+    fn drain(&mut self) -> Result<()> {
+        self.with_spool(|sink, pending| sink.flush(pending))
+    }
+
+    /// Run `f` on the spooled messages under the spool lock and write back what is left
+    fn with_spool(&mut self, f: impl FnOnce(&mut Self, &mut VecDeque<Vec<u8>>) -> Result<()>) -> Result<()> {
+        let mut spool = Spool::lock(&self.spool_path)
+            .with_context(|| format!("Failed to open spool of audit sink '{}'", self.config.name))?;
+        let mut pending = spool.read()?;
+
+        let result = f(self, &mut pending);
+
+        spool.write(&pending)?;
+        self.pending = pending.len();
+        result
+    }
+
+    fn flush(&mut self, pending: &mut VecDeque<Vec<u8>>) -> Result<()> {
+        if let Some(retry_at) = self.retry_at {
+            if Instant::now() < retry_at {
+                return Err(anyhow::anyhow!("Waiting before reconnecting"));
+            }
+        }
+
+        while let Some(message) = pending.front().cloned() {
+            if let Err(e) = self.send(&message) {
+                // Reconnect on the next attempt, backing off exponentially
+                self.connection = None;
+                self.failures += 1;
+                let backoff = Duration::from_secs(1 << self.failures.min(MAX_BACKOFF_EXPONENT));
+                self.retry_at = Some(Instant::now() + backoff);
+                return Err(e);
+            }
+            pending.pop_front();
+        }
+
+        if self.dropped > 0 {
+            log::warn!("Audit sink '{}' dropped {} entries while unavailable", self.config.name, self.dropped);
+            self.dropped = 0;
+        }
+        self.failures = 0;
+        self.retry_at = None;
+        Ok(())
+    }
+
+    fn send(&mut self, message: &[u8]) -> Result<()> {
+        if self.connection.is_none() {
+            self.connection = Some(self.connect()?);
+        }
+
+        let result = match self.connection.as_mut() {
+            Some(Connection::Udp(socket)) => socket.send(message).map(|_| ()),
+            Some(Connection::UnixDatagram(socket)) => socket.send(message).map(|_| ()),
+            // Stream transports use RFC 6587 octet-counting framing
+            Some(Connection::Tcp(stream)) => stream.write_all(&frame(message)),
+            Some(Connection::UnixStream(stream)) => stream.write_all(&frame(message)),
+            Some(Connection::File(file)) => file.write_all(message).and_then(|_| file.write_all(b"\n")),
+            None => Ok(()),
+        };
+
+        result.with_context(|| format!("Failed to send to audit sink '{}'", self.config.name))
+    }
+
+    fn connect(&self) -> Result<Connection> {
+        let address = self.config.address.as_deref();
+
+        let connection = match (&self.config.kind, &self.config.transport) {
+            (AuditSinkKind::Journald, _) => {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(address.unwrap_or(JOURNALD_SOCKET))?;
+                Connection::UnixDatagram(socket)
+            }
+            (AuditSinkKind::File, _) => {
+                let path = address.unwrap_or_default();
+                Connection::File(OpenOptions::new().create(true).append(true).open(path)?)
+            }
+            (AuditSinkKind::Syslog, SyslogTransport::Udp) => {
+                let socket = UdpSocket::bind("0.0.0.0:0")?;
+                socket.connect(address.unwrap_or(DEFAULT_SYSLOG_ADDRESS))?;
+                Connection::Udp(socket)
+            }
+            (AuditSinkKind::Syslog, SyslogTransport::Tcp) => {
+                let target = address.unwrap_or(DEFAULT_SYSLOG_ADDRESS)
+                    .to_socket_addrs()?
+                    .next()
+                    .context("Syslog address did not resolve")?;
+                let stream = TcpStream::connect_timeout(&target, CONNECT_TIMEOUT)?;
+                stream.set_write_timeout(Some(CONNECT_TIMEOUT))?;
+                Connection::Tcp(stream)
+            }
+            (AuditSinkKind::Syslog, SyslogTransport::Unix) => {
+                // /dev/log is usually a datagram socket, but some daemons listen on a stream
+                let path = address.unwrap_or(SYSLOG_SOCKET);
+                let socket = UnixDatagram::unbound()?;
+                match socket.connect(path) {
+                    Ok(()) => Connection::UnixDatagram(socket),
+                    Err(_) => Connection::UnixStream(UnixStream::connect(path)?),
+                }
+            }
+        };
+
+        Ok(connection)
+    }
+
+    fn render(&self, entry: &AuditEntry) -> Vec<u8> {
+        let summary = query::summarize(entry, 0, 0);
+
+        match (&self.config.kind, &self.config.format) {
+            (AuditSinkKind::Journald, _) => self.render_journald(entry, &summary),
+            (_, AuditSinkFormat::Rfc5424) => {
+                self.render_rfc5424(entry, &summary, &describe(entry, &summary)).into_bytes()
+            }
+            (kind, format) => {
+                let line = match format {
+                    AuditSinkFormat::Cef => render_cef(entry, &summary, &self.hostname),
+                    _ => render_leef(entry, &summary, &self.hostname),
+                };
+
+                // SIEM lines travel inside a syslog envelope; files get the bare line
+                match kind {
+                    AuditSinkKind::File => line.into_bytes(),
+                    _ => self.render_rfc5424(entry, &summary, &line).into_bytes(),
+                }
+            }
+        }
+    }
+
+    fn render_rfc5424(&self, entry: &AuditEntry, summary: &AuditIndexRecord, message: &str) -> String {
+        let priority = self.facility as u32 * 8 + severity(entry, summary) as u32;
+
+        let mut params = vec![
+            ("sequence", entry.sequence.to_string()),
+            ("user", entry.user.clone()),
+            ("session", entry.session_id.clone()),
+            ("hash", entry.hash.clone()),
+        ];
+        if let Some(risk) = &summary.risk_level {
+            params.push(("risk", format!("{:?}", risk)));
+        }
+        if let Some(execution_id) = summary.execution_id {
+            params.push(("execution_id", execution_id.to_string()));
+        }
+
+        let structured_data = params.iter()
+            .map(|(name, value)| format!(" {}=\"{}\"", name, escape_sd_value(value)))
+            .collect::<String>();
+
+        format!(
+            "<{}>1 {} {} {} {} {:?} [{}{}] {}",
+            priority,
+            entry.timestamp.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            self.hostname,
+            self.config.app_name,
+            std::process::id(),
+            entry.entry_type,
+            SD_ID,
+            structured_data,
+            message
+        )
+    }
+
+    fn render_journald(&self, entry: &AuditEntry, summary: &AuditIndexRecord) -> Vec<u8> {
+        let mut fields = vec![
+            ("MESSAGE", describe(entry, summary)),
+            ("PRIORITY", severity(entry, summary).to_string()),
+            ("SYSLOG_FACILITY", self.facility.to_string()),
+            ("SYSLOG_IDENTIFIER", self.config.app_name.clone()),
+            ("TUXPILOT_ENTRY_ID", entry.id.to_string()),
+            ("TUXPILOT_ENTRY_TYPE", format!("{:?}", entry.entry_type)),
+            ("TUXPILOT_SEQUENCE", entry.sequence.to_string()),
+            ("TUXPILOT_USER", entry.user.clone()),
+            ("TUXPILOT_SESSION_ID", entry.session_id.clone()),
+            ("TUXPILOT_HASH", entry.hash.clone()),
+        ];
+        if let Some(command) = &summary.command {
+            fields.push(("TUXPILOT_COMMAND", command.clone()));
+        }
+        if let Some(risk) = &summary.risk_level {
+            fields.push(("TUXPILOT_RISK_LEVEL", format!("{:?}", risk)));
+        }
+        if let Some(execution_id) = summary.execution_id {
+            fields.push(("TUXPILOT_EXECUTION_ID", execution_id.to_string()));
+        }
+        if let Some(success) = summary.success {
+            fields.push(("TUXPILOT_SUCCESS", success.to_string()));
+        }
+
+        // Native journal protocol: KEY=value lines, or a length-prefixed value when it
+        // contains a newline
+        let mut message = Vec::new();
+        for (key, value) in fields {
+            message.extend_from_slice(key.as_bytes());
+            if value.contains('\n') {
+                message.push(b'\n');
+                message.extend_from_slice(&(value.len() as u64).to_le_bytes());
+            } else {
+                message.push(b'=');
+            }
+            message.extend_from_slice(value.as_bytes());
+            message.push(b'\n');
+        }
+        message
+    }
+}
+
+fn render_cef(entry: &AuditEntry, summary: &AuditIndexRecord, hostname: &str) -> String {
+    let mut extension = vec![
+        ("rt", entry.timestamp.timestamp_millis().to_string()),
+        ("dvchost", hostname.to_string()),
+        ("suser", entry.user.clone()),
+        ("externalId", entry.sequence.to_string()),
+        ("cs1Label", "sessionId".to_string()),
+        ("cs1", entry.session_id.clone()),
+        ("cs2Label", "entryHash".to_string()),
+        ("cs2", entry.hash.clone()),
+    ];
+    if let Some(risk) = &summary.risk_level {
+        extension.push(("cs3Label", "riskLevel".to_string()));
+        extension.push(("cs3", format!("{:?}", risk)));
+    }
+    if let Some(command) = &summary.command {
+        extension.push(("msg", command.clone()));
+    }
+    if let Some(success) = summary.success {
+        extension.push(("outcome", if success { "success" } else { "failure" }.to_string()));
+    }
+
+    format!(
+        "CEF:0|TuxPilot|TuxPilot|{}|{:?}|{}|{}|{}",
+        env!("CARGO_PKG_VERSION"),
+        entry.entry_type,
+        escape_cef_header(&describe(entry, summary)),
+        severity_score(entry, summary),
+        extension.iter()
+            .map(|(key, value)| format!("{}={}", key, escape_cef_extension(value)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    )
+}
+
+fn render_leef(entry: &AuditEntry, summary: &AuditIndexRecord, hostname: &str) -> String {
+    let mut attributes = vec![
+        ("devTime", entry.timestamp.timestamp_millis().to_string()),
+        ("sev", severity_score(entry, summary).to_string()),
+        ("cat", format!("{:?}", entry.entry_type)),
+        ("identHostName", hostname.to_string()),
+        ("usrName", entry.user.clone()),
+        ("sequence", entry.sequence.to_string()),
+        ("sessionId", entry.session_id.clone()),
+        ("entryHash", entry.hash.clone()),
+    ];
+    if let Some(risk) = &summary.risk_level {
+        attributes.push(("riskLevel", format!("{:?}", risk)));
+    }
+    if let Some(command) = &summary.command {
+        attributes.push(("command", command.clone()));
+    }
+    if let Some(success) = summary.success {
+        attributes.push(("outcome", if success { "success" } else { "failure" }.to_string()));
+    }
+
+    format!(
+        "LEEF:1.0|TuxPilot|TuxPilot|{}|{:?}|{}",
+        env!("CARGO_PKG_VERSION"),
+        entry.entry_type,
+        attributes.iter()
+            .map(|(key, value)| format!("{}={}", key, value.replace(['\t', '\r', '\n'], " ")))
+            .collect::<Vec<_>>()
+            .join("\t")
+    )
+}
+
+/// One-line human description used as the syslog/journald message
+fn describe(entry: &AuditEntry, summary: &AuditIndexRecord) -> String {
+    let mut message = format!("{:?}", entry.entry_type);
+
+    let detail = summary.command.clone()
+        .or_else(|| entry.data.get("error").and_then(|v| v.as_str()).map(str::to_string));
+    if let Some(detail) = detail {
+        message.push_str(": ");
+        message.push_str(&detail);
+    }
+    if let Some(risk) = &summary.risk_level {
+        message.push_str(&format!(" (risk {:?})", risk));
+    }
+    match summary.success {
+        Some(true) => message.push_str(" succeeded"),
+        Some(false) => message.push_str(" failed"),
+        None => {}
+    }
+
+    message.replace(['\r', '\n'], " ")
+}
+
+/// Syslog severity (0 = emergency .. 7 = debug)
+fn severity(entry: &AuditEntry, summary: &AuditIndexRecord) -> u8 {
+    match entry.entry_type {
+        AuditEntryType::Error => 3,
+        AuditEntryType::SafetyViolation | AuditEntryType::PermissionDenied => 4,
+        AuditEntryType::SystemChange => 5,
+        AuditEntryType::ExecutionResult if summary.success == Some(false) => 4,
+        _ if summary.risk_level.as_ref().map(|r| *r >= RiskLevel::High).unwrap_or(false) => 5,
+        _ => 6,
+    }
+}
+
+/// CEF/LEEF severity on their 0-10 scale
+fn severity_score(entry: &AuditEntry, summary: &AuditIndexRecord) -> u8 {
+    match severity(entry, summary) {
+        0..=3 => 8,
+        4 => 6,
+        5 => 4,
+        _ => 2,
+    }
+}
+
+impl Spool {
+    fn lock(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(path)?;
+
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(Self { file })
+    }
+
+    fn read(&mut self) -> Result<VecDeque<Vec<u8>>> {
+        let mut content = String::new();
+        self.file.read_to_string(&mut content)?;
+        // A line cut short by a crash is dropped rather than sent garbled
+        Ok(content.lines().filter_map(|line| hex::decode(line).ok()).collect())
+    }
+
+    fn write(&mut self, pending: &VecDeque<Vec<u8>>) -> Result<()> {
+        let mut content = String::new();
+        for message in pending {
+            content.push_str(&hex::encode(message));
+            content.push('\n');
+        }
+        self.file.set_len(0)?;
+        self.file.rewind()?;
+        self.file.write_all(content.as_bytes())?;
+        Ok(())
+    }
+}
+
+fn parse_facility(name: &str) -> Result<u8> {
+    let facility = match name.to_lowercase().as_str() {
+        "kern" => 0,
+        "user" => 1,
+        "mail" => 2,
+        "daemon" => 3,
+        "auth" => 4,
+        "syslog" => 5,
+        "lpr" => 6,
+        "news" => 7,
+        "uucp" => 8,
+        "cron" => 9,
+        "authpriv" => 10,
+        "ftp" => 11,
+        "local0" => 16,
+        "local1" => 17,
+        "local2" => 18,
+        "local3" => 19,
+        "local4" => 20,
+        "local5" => 21,
+        "local6" => 22,
+        "local7" => 23,
+        _ => return Err(anyhow::anyhow!("Unknown syslog facility '{}'", name)),
+    };
+    Ok(facility)
+}
+
+fn frame(message: &[u8]) -> Vec<u8> {
+    let mut framed = format!("{} ", message.len()).into_bytes();
+    framed.extend_from_slice(message);
+    framed
+}
+
+fn escape_sd_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace(']', "\\]")
+}
+
+fn escape_cef_header(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+fn escape_cef_extension(value: &str) -> String {
+    value.replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\r', "\\r")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,464 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+
+use crate::config::{Config, ElevationMethod};
+use crate::remote::transport::CommandOutput;
+use crate::session;
+use super::permissions::{self, PermissionManager};
+use super::safety::SafetyChecker;
+use super::{ExecutionRequest, ExecutionResult, Permission, RiskLevel, LOCAL_HOST};
+
+/// Root-owned and world-readable, so the unprivileged process can see active approvals
+/// but only the helper can change them. Lives on tmpfs, so approvals end at reboot.
+const APPROVAL_STORE: &str = "/run/tuxpilot/approvals.json";
+const SECURE_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+const MAX_APPROVAL_MINUTES: u32 = 240;
+const MAX_REQUEST_BYTES: u64 = 1024 * 1024;
+const HELPER_TIMEOUT_SECS: u64 = 300;
+
+/// Privileged permissions from most to least specific. The first one a request needs
+/// is the scope its approval has to cover.
+const SCOPES: [Permission; 6] = [
+    Permission::UserManagement,
+    Permission::PackageManagement,
+    Permission::ServiceManagement,
+    Permission::SystemConfiguration,
+    Permission::FileSystemWrite,
+    Permission::WriteSystem,
+];
+
+/// Time-boxed permission for one user to run requests of a given scope as root
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Approval {
+    pub uid: u32,
+    pub scope: Permission,
+    pub granted_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ApprovalStore {
+    approvals: Vec<Approval>,
+}
+
+/// Approval sent along with a helper invocation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovalGrant {
+    pub scope: Permission,
+    /// Zero approves only the request the grant accompanies
+    pub minutes: u32,
+}
+
+/// What the unprivileged process asks the helper to do
+#[derive(Debug, Serialize, Deserialize)]
+pub enum HelperAction {
+    Execute {
+        request: Box<ExecutionRequest>,
+        grant: Option<ApprovalGrant>,
+    },
+    Grant(ApprovalGrant),
+    Revoke {
+        scope: Option<Permission>,
+    },
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HelperResponse {
+    pub result: Option<ExecutionResult>,
+    pub error: Option<String>,
+}
+
+/// Runs approved requests through `tuxpilot privileged-helper` started by sudo or pkexec,
+/// so the main process never needs to run as root
+#[derive(Debug, Clone)]
+pub struct PrivilegeElevator {
+    method: ElevationMethod,
+    helper_path: PathBuf,
+    uid: u32,
+}
+
+pub fn approval_scope(permissions: &[Permission]) -> Option<Permission> {
+    SCOPES.iter().find(|scope| permissions.contains(scope)).cloned()
+}
+
+impl ApprovalStore {
+    pub fn load() -> Self {
+        std::fs::read_to_string(APPROVAL_STORE)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = Path::new(APPROVAL_STORE);
+        let dir = path.parent().context("Invalid approval store path")?;
+        std::fs::create_dir_all(dir).context("Failed to create approval store directory")?;
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o755))?;
+
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)
+            .context("Failed to write approval store")?;
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o644))?;
+        std::fs::rename(&tmp, path).context("Failed to replace approval store")?;
+        Ok(())
+    }
+
+    fn prune(&mut self) {
+        let now = Utc::now();
+        self.approvals.retain(|a| a.expires_at > now);
+    }
+
+    fn grant(&mut self, uid: u32, grant: &ApprovalGrant) {
+        let now = Utc::now();
+        let minutes = grant.minutes.min(MAX_APPROVAL_MINUTES);
+
+        self.approvals.retain(|a| !(a.uid == uid && a.scope == grant.scope));
+        self.approvals.push(Approval {
+            uid,
+            scope: grant.scope.clone(),
+            granted_at: now,
+            expires_at: now + chrono::Duration::minutes(minutes as i64),
+        });
+    }
+
+    pub fn active(&self, uid: u32, scope: &Permission) -> Option<&Approval> {
+        let now = Utc::now();
+        self.approvals.iter().find(|a| a.uid == uid && &a.scope == scope && a.expires_at > now)
+    }
+
+    pub fn active_for(&self, uid: u32) -> Vec<&Approval> {
+        let now = Utc::now();
+        self.approvals.iter().filter(|a| a.uid == uid && a.expires_at > now).collect()
+    }
+}
+
+impl PrivilegeElevator {
+    /// Returns `None` when already running as root or when neither sudo nor pkexec exists
+    pub fn new(config: &Config) -> Result<Option<Self>> {
+        if unsafe { libc::geteuid() } == 0 {
+            return Ok(None);
+        }
+
+        let method = match &config.elevation.method {
+            Some(method) => method.clone(),
+            None if find_in_path("sudo").is_some() => ElevationMethod::Sudo,
+            None if find_in_path("pkexec").is_some() => ElevationMethod::Pkexec,
+            None => return Ok(None),
+        };
+
+        let helper_path = match &config.elevation.helper_path {
+            Some(path) => path.clone(),
+            None => std::env::current_exe().context("Failed to locate the tuxpilot executable")?,
+        };
+
+        Ok(Some(Self {
+            method,
+            helper_path,
+            uid: unsafe { libc::getuid() },
+        }))
+    }
+
+    pub fn method(&self) -> &ElevationMethod {
+        &self.method
+    }
+
+    pub fn active_approval(&self, scope: &Permission) -> Option<Approval> {
+        ApprovalStore::load().active(self.uid, scope).cloned()
+    }
+
+    pub fn active_approvals(&self) -> Vec<Approval> {
+        ApprovalStore::load().active_for(self.uid).into_iter().cloned().collect()
+    }
+
+    /// Run one request as root. Without a grant the helper only accepts it under a
+    /// still-active approval for the request's scope.
+    pub async fn execute(&self, request: &ExecutionRequest, grant: Option<ApprovalGrant>) -> Result<ExecutionResult> {
+        let args: Vec<&str> = request.args.iter().map(String::as_str).collect();
+
+        let output = session::command(LOCAL_HOST, &request.command, &args, async {
+            let response = self.invoke(&HelperAction::Execute {
+                request: Box::new(request.clone()),
+                grant,
+            }).await?;
+
+            let result = response.result.context("Privileged helper returned no result")?;
+            Ok(CommandOutput {
+                exit_code: result.exit_code,
+                stdout: result.stdout,
+                stderr: result.stderr,
+                duration: result.execution_time,
+            })
+        }).await?;
+
+        Ok(ExecutionResult {
+            id: request.id,
+            success: output.success(),
+            exit_code: output.exit_code,
+            stdout: output.stdout,
+            stderr: output.stderr,
+            execution_time: output.duration,
+            side_effects: vec![],
+            pending_activation: None,
+        })
+    }
+
+    pub async fn grant(&self, grant: ApprovalGrant) -> Result<()> {
+        self.invoke(&HelperAction::Grant(grant)).await.map(|_| ())
+    }
+
+    pub async fn revoke(&self, scope: Option<Permission>) -> Result<()> {
+        self.invoke(&HelperAction::Revoke { scope }).await.map(|_| ())
+    }
+
+    async fn invoke(&self, action: &HelperAction) -> Result<HelperResponse> {
+        // Grants go to a separate subcommand that sudo starts with -k, so cached credentials
+        // never create an approval. For pkexec the polkit action for it must be auth_admin.
+        let granting = action.grants();
+        let mut cmd = match self.method {
+            ElevationMethod::Sudo => {
+                let mut cmd = Command::new("sudo");
+                if granting {
+                    cmd.arg("-k");
+                }
+                cmd.arg("--");
+                cmd
+            }
+            ElevationMethod::Pkexec => Command::new("pkexec"),
+        };
+
+        // sudo and pkexec prompt for credentials on the terminal, not on these pipes
+        let mut child = cmd.arg(&self.helper_path)
+            .arg(if granting { "privileged-grant" } else { "privileged-helper" })
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to start privileged helper via {:?}", self.method))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&serde_json::to_vec(action)?).await
+                .context("Failed to send request to privileged helper")?;
+        }
+
+        let output = child.wait_with_output().await
+            .context("Privileged helper did not finish")?;
+
+        let response: HelperResponse = match serde_json::from_slice(&output.stdout) {
+            Ok(response) => response,
+            Err(_) => {
+                return Err(anyhow::anyhow!(
+                    "Privileged helper failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+        };
+
+        match response.error {
+            Some(error) => Err(anyhow::anyhow!("Privileged helper refused the request: {}", error)),
+            None => Ok(response),
+        }
+    }
+}
+
+impl HelperAction {
+    /// Whether the action creates an approval instead of using a standing one
+    fn grants(&self) -> bool {
+        matches!(self, HelperAction::Grant(_) | HelperAction::Execute { grant: Some(_), .. })
+    }
+}
+
+/// Entry point of `tuxpilot privileged-helper` and `tuxpilot privileged-grant`. Reads one
+/// `HelperAction` from stdin, re-validates it and answers with a `HelperResponse` on stdout.
+/// Only `privileged-grant`, which the user authenticated for afresh, accepts grants.
+pub async fn run_helper(granting: bool) -> Result<()> {
+    let mut input = String::new();
+    tokio::io::stdin().take(MAX_REQUEST_BYTES).read_to_string(&mut input).await
+        .context("Failed to read helper request")?;
+
+    let outcome = match serde_json::from_str::<HelperAction>(&input) {
+        Ok(action) => handle_action(action, granting).await,
+        Err(e) => Err(anyhow::Error::new(e).context("Invalid helper request")),
+    };
+
+    let response = match outcome {
+        Ok(result) => HelperResponse { result, error: None },
+        Err(e) => HelperResponse { result: None, error: Some(format!("{:#}", e)) },
+    };
+    println!("{}", serde_json::to_string(&response)?);
+
+    match response.error {
+        Some(error) => Err(anyhow::anyhow!(error)),
+        None => Ok(()),
+    }
+}
+
+async fn handle_action(action: HelperAction, granting: bool) -> Result<Option<ExecutionResult>> {
+    if unsafe { libc::geteuid() } != 0 {
+        return Err(anyhow::anyhow!("The privileged helper must be started through sudo or pkexec"));
+    }
+    if action.grants() && !granting {
+        return Err(anyhow::anyhow!("Approval grants need fresh authentication through privileged-grant"));
+    }
+
+    let uid = invoking_uid();
+    let mut store = ApprovalStore::load();
+    store.prune();
+
+    match action {
+        HelperAction::Grant(grant) => {
+            store.grant(uid, &grant);
+            store.save()?;
+            Ok(None)
+        }
+        HelperAction::Revoke { scope } => {
+            store.approvals.retain(|a| a.uid != uid || scope.as_ref().map(|s| s != &a.scope).unwrap_or(false));
+            store.save()?;
+            Ok(None)
+        }
+        HelperAction::Execute { request, grant } => {
+            let checked = validate_request(&request).await?;
+
+            let fresh = grant.as_ref().map(|g| g.scope == checked.scope).unwrap_or(false);
+            if let Some(grant) = grant.as_ref().filter(|g| g.minutes > 0) {
+                store.grant(uid, grant);
+                store.save()?;
+            }
+
+            if checked.risk_level == RiskLevel::Critical && !fresh {
+                return Err(anyhow::anyhow!("Critical commands need a fresh approval"));
+            }
+            if checked.runs_any_command && !fresh {
+                return Err(anyhow::anyhow!("{} runs any command it is given and needs a fresh approval", request.command));
+            }
+            if !fresh && store.active(uid, &checked.scope).is_none() {
+                return Err(anyhow::anyhow!("No active approval for {:?}", checked.scope));
+            }
+
+            execute_privileged(&request).await.map(Some)
+        }
+    }
+}
+
+/// What the helper makes of a request, whatever the request itself declares
+struct CheckedRequest {
+    scope: Permission,
+    risk_level: RiskLevel,
+    /// Shells, interpreters and wrappers are only run under an approval for this request
+    runs_any_command: bool,
+}
+
+/// Re-derive scope and risk from policy instead of trusting what the request declares
+async fn validate_request(request: &ExecutionRequest) -> Result<CheckedRequest> {
+    if request.command.is_empty() || (request.command.contains('/') && !request.command.starts_with('/')) {
+        return Err(anyhow::anyhow!("Invalid command '{}'", request.command));
+    }
+    let program = resolve_program(&request.command)
+        .with_context(|| format!("Command not found or not root-owned: {}", request.command))?;
+
+    let policy = Config::default();
+    let safety = SafetyChecker::new(&policy).await?.analyze_command(request).await?;
+    if !safety.is_safe {
+        return Err(anyhow::anyhow!("Command failed safety check: {}", safety.reason));
+    }
+
+    // The scope comes from the program and its arguments alone; a symlink such as
+    // /bin/sh -> dash is a runner by either name
+    let name = Path::new(&request.command).file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(&request.command);
+    let runs_any_command = permissions::runs_any_command(name)
+        || permissions::runs_any_command(&program.to_string_lossy());
+    let permissions = PermissionManager::new(&policy).await?
+        .can_execute_command(name, &request.args)?;
+    let scope = if runs_any_command {
+        Permission::WriteSystem
+    } else {
+        approval_scope(&permissions).unwrap_or(Permission::ReadSystem)
+    };
+
+    let risk_level = if safety.risk_level > request.risk_level {
+        safety.risk_level
+    } else {
+        request.risk_level.clone()
+    };
+
+    Ok(CheckedRequest { scope, risk_level, runs_any_command })
+}
+
+async fn execute_privileged(request: &ExecutionRequest) -> Result<ExecutionResult> {
+    let start_time = std::time::Instant::now();
+    let program = resolve_program(&request.command)
+        .with_context(|| format!("Command not found or not root-owned: {}", request.command))?;
+
+    let mut cmd = Command::new(program);
+    cmd.args(&request.args)
+        .env_clear()
+        .env("PATH", SECURE_PATH)
+        .env("DEBIAN_FRONTEND", "noninteractive")
+        .env("NEEDRESTART_MODE", "a")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .stdin(Stdio::null());
+
+    let output = tokio::time::timeout(
+        std::time::Duration::from_secs(HELPER_TIMEOUT_SECS),
+        cmd.output()
+    ).await
+    .context("Command execution timed out")?
+    .context("Failed to execute command")?;
+
+    Ok(ExecutionResult {
+        id: request.id,
+        success: output.status.success(),
+        exit_code: output.status.code(),
+        stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        execution_time: start_time.elapsed(),
+        side_effects: vec![],
+        pending_activation: None,
+    })
+}
+
+/// The user who ran sudo/pkexec, not root
+fn invoking_uid() -> u32 {
+    ["SUDO_UID", "PKEXEC_UID"].iter()
+        .find_map(|var| std::env::var(var).ok()?.parse().ok())
+        .unwrap_or_else(|| unsafe { libc::getuid() })
+}
+
+/// The program to run, which has to be root-owned and not writable by anyone else down
+/// to the filesystem root, so an approval cannot be used to run a user's own binary
+fn resolve_program(command: &str) -> Option<PathBuf> {
+    let candidate = if command.starts_with('/') {
+        PathBuf::from(command)
+    } else {
+        SECURE_PATH.split(':')
+            .map(|dir| Path::new(dir).join(command))
+            .find(|path| path.is_file())?
+    };
+
+    let program = candidate.canonicalize().ok()?;
+    (program.is_file() && program.ancestors().all(root_controlled)).then_some(program)
+}
+
+fn root_controlled(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    std::fs::metadata(path)
+        .map(|metadata| metadata.uid() == 0 && metadata.mode() & 0o022 == 0)
+        .unwrap_or(false)
+}
+
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
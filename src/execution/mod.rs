@@ -1,14 +1,27 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::io::IsTerminal;
 use std::process::Stdio;
 use tokio::process::Command;
 use uuid::Uuid;
 
+pub mod approval;
 pub mod permissions;
 pub mod safety;
 pub mod audit;
+pub mod elevation;
+pub mod state;
 
 use crate::config::Config;
+use crate::remote::inventory::Inventory;
+use crate::remote::transport::{shell_quote, shell_words, CommandOutput, LocalTransport, SshTransport, Transport};
+use crate::session;
+use crate::environment;
+use crate::linux_integration::kernel;
+use crate::system_image::{self, PendingActivation, SystemImage};
+
+/// Host name recorded for commands that run on this machine
+const LOCAL_HOST: &str = "localhost";
 
 #[derive(Debug)]
 pub struct CommandExecutor {
@@ -16,7 +29,21 @@ pub struct CommandExecutor {
     permission_manager: permissions::PermissionManager,
     safety_checker: safety::SafetyChecker,
     audit_logger: audit::AuditLogger,
+    approval_broker: approval::ApprovalBroker,
+    state_probe: state::StateProbe,
     execution_mode: ExecutionMode,
+    system_image: SystemImage,
+}
+
+/// Who let a request run
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ApprovedBy {
+    Nobody,
+    /// The execution mode runs requests of this risk without asking
+    Mode,
+    Terminal,
+    /// Approvers in the approval queue
+    Queue,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,9 +63,12 @@ pub struct ExecutionRequest {
     pub risk_level: RiskLevel,
     pub required_permissions: Vec<Permission>,
     pub context: ExecutionContext,
+    /// Inventory host the command runs on; `None` runs it on this machine
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd)]
 pub enum RiskLevel {
     Safe,        // Read-only operations, system info
     Low,         // Package queries, service status
@@ -68,6 +98,17 @@ pub struct ExecutionContext {
     pub rollback_plan: Option<String>,
 }
 
+/// Command lines that carry out one change, in order. `CommandExecutor::plan_steps`
+/// turns them into requests, so each step is checked, approved and audited on its own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionPlan {
+    pub description: String,
+    /// Each step as a program and its arguments
+    pub steps: Vec<Vec<String>>,
+    /// Command line that undoes the whole plan
+    pub rollback: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExecutionResult {
     pub id: Uuid,
@@ -77,6 +118,9 @@ pub struct ExecutionResult {
     pub stderr: String,
     pub execution_time: std::time::Duration,
     pub side_effects: Vec<SideEffect>,
+    /// Set when the change only takes effect after an apply step or a reboot
+    #[serde(default)]
+    pub pending_activation: Option<PendingActivation>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -99,119 +143,410 @@ pub enum SideEffectType {
     ConfigurationChanged,
     UserCreated,
     UserModified,
+    UserRemoved,
+    GroupCreated,
+    GroupRemoved,
+    ServiceEnabled,
+    ServiceDisabled,
+    SocketOpened,
+    SocketClosed,
+    ScheduledTaskChanged,
+    KernelParameterChanged,
+    KernelModuleLoaded,
+    KernelModuleUnloaded,
 }
 
 impl CommandExecutor {
     pub async fn new(config: Config, execution_mode: ExecutionMode) -> Result<Self> {
+        let audit_logger = audit::AuditLogger::new(&config).await?;
+        let approval_broker = approval::ApprovalBroker::new(&config, audit_logger.clone())?;
+
         Ok(Self {
             config: config.clone(),
             permission_manager: permissions::PermissionManager::new(&config).await?,
             safety_checker: safety::SafetyChecker::new(&config).await?,
-            audit_logger: audit::AuditLogger::new(&config).await?,
+            audit_logger,
+            approval_broker,
+            state_probe: state::StateProbe::new(&config)?,
             execution_mode,
+            system_image: system_image::detect(&LocalTransport).await,
         })
     }
 
+    pub fn audit_logger(&self) -> &audit::AuditLogger {
+        &self.audit_logger
+    }
+
+    pub fn approval_broker(&self) -> &approval::ApprovalBroker {
+        &self.approval_broker
+    }
+
     pub async fn execute_request(&mut self, request: ExecutionRequest) -> Result<ExecutionResult> {
         // 1. Log the execution request
         self.audit_logger.log_request(&request).await?;
 
-        // 2. Check permissions
-        self.permission_manager.check_permissions(&request.required_permissions)?;
+        // 2. Check permissions (remote commands run with the SSH user's rights on the host)
+        if request.host.is_none() {
+            self.permission_manager.check_permissions(&request.required_permissions)?;
+        }
+
+        // 3. Image-based systems install packages and override /usr differently, and
+        //    remote containers or WSL instances cannot run some commands at all (the
+        //    safety checker covers the local environment)
+        let image = match &request.host {
+            Some(host) => {
+                let transport = self.remote_transport(host)?;
+                if let Some(reason) = environment::detect(&transport).await.restriction(&request.command, &request.args) {
+                    return Err(anyhow::anyhow!("{}", reason));
+                }
+                system_image::detect(&transport).await
+            }
+            None => self.system_image.clone(),
+        };
+        if let Some(advice) = image.reroute(&request.command, &request.args) {
+            return Err(anyhow::anyhow!("{}", advice));
+        }
 
-        // 3. Safety checks
+        // 4. Safety checks
         let safety_result = self.safety_checker.analyze_command(&request).await?;
         if !safety_result.is_safe {
             return Err(anyhow::anyhow!("Command failed safety check: {}", safety_result.reason));
         }
 
-        // 4. Execution mode handling
-        let should_execute = match self.execution_mode {
+        // 5. Execution mode handling
+        let approved_by = match self.execution_mode {
             ExecutionMode::ReadOnly => {
                 if request.risk_level != RiskLevel::Safe {
                     return Err(anyhow::anyhow!("Read-only mode: command not allowed"));
                 }
-                true
+                ApprovedBy::Mode
             }
             ExecutionMode::Supervised => {
                 self.request_user_approval(&request).await?
             }
             ExecutionMode::SemiAuto => {
                 match request.risk_level {
-                    RiskLevel::Safe | RiskLevel::Low => true,
+                    RiskLevel::Safe | RiskLevel::Low => ApprovedBy::Mode,
                     _ => self.request_user_approval(&request).await?
                 }
             }
             ExecutionMode::Autonomous => {
                 match request.risk_level {
                     RiskLevel::Critical => self.request_user_approval(&request).await?,
-                    _ => true
+                    _ => ApprovedBy::Mode
                 }
             }
         };
 
-        if !should_execute {
-            return Ok(ExecutionResult {
-                id: request.id,
-                success: false,
-                exit_code: None,
-                stdout: "Execution cancelled by user".to_string(),
-                stderr: String::new(),
-                execution_time: std::time::Duration::from_secs(0),
-                side_effects: vec![],
-            });
+        if approved_by == ApprovedBy::Nobody {
+            return Ok(Self::cancelled_result(&request));
         }
 
-        // 5. Execute the command
-        let result = self.execute_command_safely(&request).await?;
+        // 6. Snapshot the state the command may change
+        let before = self.capture_state(&request).await;
+
+        // 7. Execute the command: over SSH for inventory hosts, otherwise locally and
+        //    through the privileged helper when it needs root
+        let mut result = if let Some(host) = &request.host {
+            self.execute_remote(&request, host).await?
+        } else if self.permission_manager.needs_elevation(&request.required_permissions) {
+            match self.execute_elevated(&request, approved_by).await? {
+                Some(result) => result,
+                None => return Ok(Self::cancelled_result(&request)),
+            }
+        } else {
+            self.execute_command_safely(&request).await?
+        };
+
+        // 8. Work out what actually changed
+        result.side_effects = match before {
+            Some(before) => self.diff_state(&request, &before).await,
+            None => self.analyze_side_effects(&request).await?,
+        };
+        if result.success {
+            result.pending_activation = system_image::pending_activation(&request.command, &request.args);
+        }
 
-        // 6. Log the result
+        // 9. Log the result
         self.audit_logger.log_result(&result).await?;
 
         Ok(result)
     }
 
-    async fn execute_command_safely(&self, request: &ExecutionRequest) -> Result<ExecutionResult> {
-        let start_time = std::time::Instant::now();
+    /// Build a request for an exact command line, split into words with shell quoting,
+    /// classifying its permissions and risk the same way whether it runs locally or on
+    /// an inventory host
+    pub async fn plan_command(&self, description: &str, command_line: &str, host: Option<&str>) -> Result<ExecutionRequest> {
+        let mut words = shell_words(command_line)?.into_iter();
+        let command = words.next().context("Empty command")?;
+        self.plan_args(description, command, words.collect(), host).await
+    }
 
-        // Create command with safety measures
-        let mut cmd = Command::new(&request.command);
-        cmd.args(&request.args)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .stdin(Stdio::null());
+    /// Like `plan_command`, for a command that is already split into words
+    pub async fn plan_args(&self, description: &str, mut command: String, mut args: Vec<String>, host: Option<&str>) -> Result<ExecutionRequest> {
+        // Elevation is handled by the executor, not by a sudo prefix
+        if command == "sudo" && !args.is_empty() {
+            command = args.remove(0);
+        }
 
-        // Set environment variables for safety
-        cmd.env("DEBIAN_FRONTEND", "noninteractive");
-        cmd.env("NEEDRESTART_MODE", "a");
+        let mut request = ExecutionRequest {
+            id: Uuid::new_v4(),
+            required_permissions: permissions::required_permissions(&command, &args),
+            command,
+            args,
+            description: description.to_string(),
+            risk_level: RiskLevel::Safe,
+            context: ExecutionContext {
+                user_request: description.to_string(),
+                ai_reasoning: String::new(),
+                expected_outcome: String::new(),
+                rollback_plan: None,
+            },
+            host: host.map(str::to_string),
+        };
 
-        // Execute with timeout
-        let output = tokio::time::timeout(
-            std::time::Duration::from_secs(300), // 5 minute timeout
-            cmd.output()
-        ).await
-        .context("Command execution timed out")?
-        .context("Failed to execute command")?;
+        request.risk_level = self.safety_checker.analyze_command(&request).await?.risk_level;
+        if request.required_permissions.contains(&Permission::WriteSystem) && request.risk_level < RiskLevel::Medium {
+            request.risk_level = RiskLevel::Medium;
+        }
+
+        Ok(request)
+    }
 
-        let execution_time = start_time.elapsed();
+    /// Requests for the steps of `plan`, in order
+    pub async fn plan_steps(&self, plan: &ExecutionPlan, host: Option<&str>) -> Result<Vec<ExecutionRequest>> {
+        let mut requests = Vec::new();
+        for step in &plan.steps {
+            let (command, args) = step.split_first().context("Empty step in execution plan")?;
+            let mut request = self.plan_args(&plan.description, command.clone(), args.to_vec(), host).await?;
+            request.context.rollback_plan = plan.rollback.clone();
+            requests.push(request);
+        }
+        Ok(requests)
+    }
 
-        // Analyze side effects
-        let side_effects = self.analyze_side_effects(request, &output).await?;
+    fn cancelled_result(request: &ExecutionRequest) -> ExecutionResult {
+        ExecutionResult {
+            id: request.id,
+            success: false,
+            exit_code: None,
+            stdout: "Execution cancelled by user".to_string(),
+            stderr: String::new(),
+            execution_time: std::time::Duration::from_secs(0),
+            side_effects: vec![],
+            pending_activation: None,
+        }
+    }
+
+    /// Run the request as root through the privileged helper. Returns `None` when the
+    /// user declines to approve the elevation.
+    async fn execute_elevated(&mut self, request: &ExecutionRequest, approved_by: ApprovedBy) -> Result<Option<ExecutionResult>> {
+        let elevator = self.permission_manager.elevator()
+            .cloned()
+            .context("Command needs root privileges, but neither sudo nor pkexec is available")?;
+
+        // The helper only runs shells and wrappers under an approval for that one request
+        let runs_any_command = permissions::runs_any_command(&request.command);
+        let scope = match elevation::approval_scope(&request.required_permissions) {
+            Some(scope) if !runs_any_command => scope,
+            _ => Permission::WriteSystem,
+        };
+        let has_standing_approval = request.risk_level != RiskLevel::Critical
+            && !runs_any_command
+            && elevator.active_approval(&scope).is_some();
+
+        // Approvers in the queue already decided on this request, so their decision is
+        // the grant for it; without a terminal the elevation goes to the queue as well
+        let grant = if has_standing_approval {
+            None
+        } else if approved_by == ApprovedBy::Queue {
+            Some(elevation::ApprovalGrant { scope: scope.clone(), minutes: 0 })
+        } else if !std::io::stdin().is_terminal() && !session::is_replaying() {
+            match self.request_queued_approval(request).await? {
+                ApprovedBy::Queue => Some(elevation::ApprovalGrant { scope: scope.clone(), minutes: 0 }),
+                _ => return Ok(None),
+            }
+        } else {
+            match self.request_elevation_approval(&scope, !runs_any_command).await? {
+                Some(grant) => Some(grant),
+                None => return Ok(None),
+            }
+        };
+
+        if let Some(grant) = &grant {
+            self.audit_logger.log_permission_request(
+                &format!("{:?} via {:?} for {} minutes", grant.scope, elevator.method(), grant.minutes),
+                true,
+            ).await?;
+        }
+
+        Ok(Some(elevator.execute(request, grant).await?))
+    }
+
+    fn remote_transport(&self, host: &str) -> Result<SshTransport> {
+        let inventory = Inventory::load(&self.config)?;
+        Ok(SshTransport::new(inventory.host(host)?, &self.config))
+    }
+
+    /// Run the request on an inventory host. Commands that need root use `sudo -n` on
+    /// the host when its inventory entry allows it.
+    async fn execute_remote(&self, request: &ExecutionRequest, host: &str) -> Result<ExecutionResult> {
+        let transport = self.remote_transport(host)?;
+        let args: Vec<&str> = request.args.iter().map(String::as_str).collect();
+
+        let run = if elevation::approval_scope(&request.required_permissions).is_some() {
+            transport.run_privileged(&request.command, &args)
+        } else {
+            transport.run(&request.command, &args)
+        };
+
+        let output = tokio::time::timeout(std::time::Duration::from_secs(300), run)
+            .await
+            .context("Command execution timed out")??;
 
         Ok(ExecutionResult {
             id: request.id,
-            success: output.status.success(),
-            exit_code: output.status.code(),
-            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
-            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
-            execution_time,
-            side_effects,
+            success: output.success(),
+            exit_code: output.exit_code,
+            stdout: output.stdout,
+            stderr: output.stderr,
+            execution_time: output.duration,
+            side_effects: vec![],
+            pending_activation: None,
         })
     }
 
-    async fn request_user_approval(&self, request: &ExecutionRequest) -> Result<bool> {
+    /// Ask how far to approve the elevation; `reusable` offers a time-boxed approval
+    async fn request_elevation_approval(&self, scope: &Permission, reusable: bool) -> Result<Option<elevation::ApprovalGrant>> {
+        use dialoguer::Select;
+
+        let minutes = self.config.elevation.approval_minutes;
+        let mut options = vec!["Run this command once".to_string()];
+        if reusable {
+            options.push(format!("Allow TuxPilot to {} for {} minutes", permissions::permission_description(scope), minutes));
+        }
+        options.push("Cancel".to_string());
+
+        let prompt = "🔑 This command needs root privileges";
+        let choice = session::decision(prompt, async {
+            Ok(Select::new()
+                .with_prompt(prompt)
+                .items(&options)
+                .default(0)
+                .interact()?)
+        }).await?;
+
+        Ok(match choice {
+            0 => Some(elevation::ApprovalGrant { scope: scope.clone(), minutes: 0 }),
+            1 if reusable => Some(elevation::ApprovalGrant { scope: scope.clone(), minutes }),
+            _ => None,
+        })
+    }
+
+    async fn execute_command_safely(&self, request: &ExecutionRequest) -> Result<ExecutionResult> {
+        let args: Vec<&str> = request.args.iter().map(String::as_str).collect();
+
+        let output = session::command(LOCAL_HOST, &request.command, &args, async {
+            let start_time = std::time::Instant::now();
+
+            // Create command with safety measures
+            let mut cmd = Command::new(&request.command);
+            cmd.args(&request.args)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .stdin(Stdio::null());
+
+            // Set environment variables for safety
+            cmd.env("DEBIAN_FRONTEND", "noninteractive");
+            cmd.env("NEEDRESTART_MODE", "a");
+
+            // Execute with timeout
+            let output = tokio::time::timeout(
+                std::time::Duration::from_secs(300), // 5 minute timeout
+                cmd.output()
+            ).await
+            .context("Command execution timed out")?
+            .context("Failed to execute command")?;
+
+            Ok(CommandOutput {
+                exit_code: output.status.code(),
+                stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+                duration: start_time.elapsed(),
+            })
+        }).await?;
+
+        Ok(ExecutionResult {
+            id: request.id,
+            success: output.success(),
+            exit_code: output.exit_code,
+            stdout: output.stdout,
+            stderr: output.stderr,
+            execution_time: output.duration,
+            side_effects: vec![],
+            pending_activation: None,
+        })
+    }
+
+    /// Snapshot system state before a command that may change it. Read-only commands
+    /// and disabled probes fall back to guessing side effects from the command name.
+    async fn capture_state(&self, request: &ExecutionRequest) -> Option<state::StateSnapshot> {
+        // Probes only see this machine, so remote commands fall back to estimates. Replays
+        // do too: the probes read the live system, which is not the one that was recorded.
+        if !self.config.state_probes.enabled || request.risk_level == RiskLevel::Safe
+            || request.host.is_some() || session::is_replaying() {
+            return None;
+        }
+
+        let parameters = kernel::assigned_keys(&request.command, &request.args);
+        match self.state_probe.capture(Some(request.id), &parameters).await {
+            Ok(snapshot) => Some(snapshot),
+            Err(e) => {
+                log::warn!("State probe failed, side effects will be estimated: {:#}", e);
+                None
+            }
+        }
+    }
+
+    async fn diff_state(&self, request: &ExecutionRequest, before: &state::StateSnapshot) -> Vec<SideEffect> {
+        let parameters = kernel::assigned_keys(&request.command, &request.args);
+        let side_effects = match self.state_probe.capture(None, &parameters).await {
+            Ok(after) => self.state_probe.diff(before, &after),
+            Err(e) => {
+                log::warn!("State probe failed after execution: {:#}", e);
+                Vec::new()
+            }
+        };
+
+        self.state_probe.discard_unused_backups(request.id, &side_effects);
+        side_effects
+    }
+
+    async fn request_user_approval(&self, request: &ExecutionRequest) -> Result<ApprovedBy> {
+        // Recorded as the answer whether it came from the terminal or the queue
+        let choice = session::decision("Do you want to execute this command?", async {
+            Ok(match self.ask_for_approval(request).await? {
+                ApprovedBy::Terminal => 1,
+                ApprovedBy::Queue => 2,
+                _ => 0,
+            })
+        }).await?;
+
+        Ok(match choice {
+            1 => ApprovedBy::Terminal,
+            2 => ApprovedBy::Queue,
+            _ => ApprovedBy::Nobody,
+        })
+    }
+
+    async fn ask_for_approval(&self, request: &ExecutionRequest) -> Result<ApprovedBy> {
         use dialoguer::Confirm;
 
+        // Without a terminal (web server, MCP, automation) nobody can answer a prompt
+        if !std::io::stdin().is_terminal() || self.approval_broker.requires_queue(&request.risk_level) {
+            return self.request_queued_approval(request).await;
+        }
+
         println!("\n🤖 TuxPilot wants to execute a command:");
         println!("📝 Description: {}", request.description);
         println!("⚠️  Risk Level: {:?}", request.risk_level);
@@ -227,10 +562,26 @@ impl CommandExecutor {
             .default(false)
             .interact()?;
 
-        Ok(approved)
+        Ok(if approved { ApprovedBy::Terminal } else { ApprovedBy::Nobody })
+    }
+
+    /// Park the request in the approval queue and wait for remote approvers
+    async fn request_queued_approval(&self, request: &ExecutionRequest) -> Result<ApprovedBy> {
+        let pending = self.approval_broker.submit(request, &approval::login_name()).await?;
+
+        eprintln!("⏳ Waiting for approval of request {} ({} approver(s) needed, expires {})",
+                  pending.id,
+                  pending.required_approvals,
+                  pending.expires_at.format("%Y-%m-%d %H:%M:%S"));
+
+        let decided = self.approval_broker.wait_for_decision(pending.id).await?;
+        eprintln!("{}", decided);
+
+        Ok(if decided.status == approval::ApprovalStatus::Approved { ApprovedBy::Queue } else { ApprovedBy::Nobody })
     }
 
-    async fn analyze_side_effects(&self, request: &ExecutionRequest, _output: &std::process::Output) -> Result<Vec<SideEffect>> {
+    /// Estimate side effects from the command name when no state snapshot was taken
+    async fn analyze_side_effects(&self, request: &ExecutionRequest) -> Result<Vec<SideEffect>> {
         let mut side_effects = Vec::new();
 
         // Analyze based on command type
@@ -251,7 +602,7 @@ impl CommandExecutor {
                         effect_type: SideEffectType::ServiceStarted,
                         description: format!("Service started: {}", request.args.join(" ")),
                         reversible: true,
-                        rollback_command: Some(format!("systemctl stop {}", request.args.last().unwrap_or(&String::new()))),
+                        rollback_command: Some(format!("systemctl stop {}", shell_quote(request.args.last().map(String::as_str).unwrap_or_default()))),
                     });
                 }
             }
@@ -287,13 +638,14 @@ impl CommandExecutor {
     pub async fn rollback_execution(&mut self, execution_id: Uuid) -> Result<()> {
         // Implementation for rolling back executed commands
         let execution_record = self.audit_logger.get_execution(execution_id).await?;
+        let host = execution_record.request.host.clone();
         
         for side_effect in execution_record.result.side_effects {
             if side_effect.reversible {
                 if let Some(rollback_cmd) = side_effect.rollback_command {
                     println!("🔄 Rolling back: {}", rollback_cmd);
-                    // Execute rollback command
-                    let parts: Vec<&str> = rollback_cmd.split_whitespace().collect();
+                    // Rollback commands are stored as shell-quoted words
+                    let parts = shell_words(&rollback_cmd)?;
                     if let Some((cmd, args)) = parts.split_first() {
                         let rollback_request = ExecutionRequest {
                             id: Uuid::new_v4(),
@@ -308,6 +660,7 @@ impl CommandExecutor {
                                 expected_outcome: "System restored to previous state".to_string(),
                                 rollback_plan: None,
                             },
+                            host: host.clone(),
                         };
                         
                         self.execute_request(rollback_request).await?;
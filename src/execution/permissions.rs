@@ -4,13 +4,27 @@ use std::collections::HashSet;
 
 
 use crate::config::Config;
-use super::Permission;
+use crate::firewall;
+use crate::network;
+use crate::remote::transport::CommandOutput;
+use crate::session;
+use super::elevation::{self, PrivilegeElevator};
+use super::{Permission, LOCAL_HOST};
+
+/// Shells, interpreters and wrappers that run whatever command line they are given
+const COMMAND_RUNNERS: [&str; 44] = [
+    "sh", "bash", "dash", "zsh", "ksh", "mksh", "fish", "csh", "tcsh", "busybox",
+    "python", "perl", "ruby", "php", "node", "nodejs", "lua", "tclsh", "expect", "awk", "gawk", "mawk",
+    "env", "setsid", "nohup", "nice", "ionice", "chrt", "taskset", "timeout", "stdbuf", "flock", "xargs",
+    "su", "sudo", "doas", "pkexec", "runuser", "chroot", "unshare", "nsenter", "systemd-run", "script", "strace",
+];
 
 #[derive(Debug, Clone)]
 pub struct PermissionManager {
     granted_permissions: HashSet<Permission>,
     user_permissions: UserPermissions,
     system_permissions: SystemPermissions,
+    elevator: Option<PrivilegeElevator>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,7 +46,7 @@ pub struct SystemPermissions {
 }
 
 impl PermissionManager {
-    pub async fn new(_config: &Config) -> Result<Self> {
+    pub async fn new(config: &Config) -> Result<Self> {
         let user_permissions = Self::detect_user_permissions().await?;
         let system_permissions = Self::detect_system_permissions(&user_permissions).await?;
         let granted_permissions = Self::calculate_granted_permissions(&user_permissions, &system_permissions);
@@ -41,6 +55,7 @@ impl PermissionManager {
             granted_permissions,
             user_permissions,
             system_permissions,
+            elevator: PrivilegeElevator::new(config)?,
         })
     }
 
@@ -53,13 +68,22 @@ impl PermissionManager {
         let is_root = uid == 0;
 
         // Check if user is in sudo group
-        let groups_output = tokio::process::Command::new("groups")
-            .output()
-            .await
-            .context("Failed to get user groups")?;
+        let groups_output = session::command(LOCAL_HOST, "groups", &[], async {
+            let start = std::time::Instant::now();
+            let output = tokio::process::Command::new("groups")
+                .output()
+                .await
+                .context("Failed to get user groups")?;
+
+            Ok(CommandOutput {
+                exit_code: output.status.code(),
+                stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+                duration: start.elapsed(),
+            })
+        }).await?;
         
-        let groups_str = String::from_utf8_lossy(&groups_output.stdout);
-        let groups: Vec<String> = groups_str
+        let groups: Vec<String> = groups_output.stdout
             .split_whitespace()
             .map(|s| s.to_string())
             .collect();
@@ -149,69 +173,17 @@ impl PermissionManager {
         &self.granted_permissions
     }
 
-    pub fn can_execute_command(&self, command: &str, args: &[String]) -> Result<Vec<Permission>> {
-        let mut required_permissions = Vec::new();
-
-        match command {
-            // Package managers
-            "pacman" | "apt" | "dnf" | "zypper" | "emerge" => {
-                required_permissions.push(Permission::ReadSystem);
-                
-                // Check if it's a write operation
-                if args.iter().any(|arg| {
-                    matches!(arg.as_str(), "-S" | "install" | "remove" | "upgrade" | "update")
-                }) {
-                    required_permissions.push(Permission::PackageManagement);
-                    required_permissions.push(Permission::WriteSystem);
-                }
-            }
-
-            // Service management
-            "systemctl" | "service" | "rc-service" => {
-                required_permissions.push(Permission::ReadSystem);
-                
-                if args.iter().any(|arg| {
-                    matches!(arg.as_str(), "start" | "stop" | "restart" | "enable" | "disable")
-                }) {
-                    required_permissions.push(Permission::ServiceManagement);
-                    required_permissions.push(Permission::WriteSystem);
-                }
-            }
-
-            // File operations
-            "cp" | "mv" | "rm" | "mkdir" | "rmdir" | "chmod" | "chown" => {
-                required_permissions.push(Permission::FileSystemWrite);
-                required_permissions.push(Permission::WriteSystem);
-            }
-
-            "cat" | "ls" | "find" | "grep" | "head" | "tail" => {
-                required_permissions.push(Permission::FileSystemRead);
-                required_permissions.push(Permission::ReadSystem);
-            }
-
-            // User management
-            "useradd" | "userdel" | "usermod" | "passwd" => {
-                required_permissions.push(Permission::UserManagement);
-                required_permissions.push(Permission::WriteSystem);
-            }
-
-            // Network operations
-            "wget" | "curl" | "ping" | "nslookup" | "dig" => {
-                required_permissions.push(Permission::NetworkAccess);
-                required_permissions.push(Permission::ReadSystem);
-            }
+    pub fn elevator(&self) -> Option<&PrivilegeElevator> {
+        self.elevator.as_ref()
+    }
 
-            // System configuration
-            "mount" | "umount" | "fdisk" | "parted" => {
-                required_permissions.push(Permission::SystemConfiguration);
-                required_permissions.push(Permission::WriteSystem);
-            }
+    /// Whether a request with these permissions has to go through the privileged helper
+    pub fn needs_elevation(&self, required: &[Permission]) -> bool {
+        !self.user_permissions.is_root && elevation::approval_scope(required).is_some()
+    }
 
-            // Default: require read access
-            _ => {
-                required_permissions.push(Permission::ReadSystem);
-            }
-        }
+    pub fn can_execute_command(&self, command: &str, args: &[String]) -> Result<Vec<Permission>> {
+        let required_permissions = required_permissions(command, args);
 
         // Check if we have the required permissions
         self.check_permissions(&required_permissions)?;
@@ -230,55 +202,177 @@ impl PermissionManager {
             total_permissions: self.granted_permissions.len(),
         }
     }
+}
 
-    pub async fn request_elevated_permissions(&mut self, permission: Permission) -> Result<bool> {
-        use dialoguer::Confirm;
+/// Permissions a command line needs, judged from the command and its arguments
+pub fn required_permissions(command: &str, args: &[String]) -> Vec<Permission> {
+    let mut required_permissions = Vec::new();
+
+    match command {
+        // Package managers
+        "pacman" | "apt" | "dnf" | "zypper" | "emerge" => {
+            required_permissions.push(Permission::ReadSystem);
+            
+            // Check if it's a write operation
+            if args.iter().any(|arg| {
+                matches!(arg.as_str(), "-S" | "install" | "remove" | "upgrade" | "update")
+            }) {
+                required_permissions.push(Permission::PackageManagement);
+                required_permissions.push(Permission::WriteSystem);
+            }
+        }
 
-        if self.has_permission(&permission) {
-            return Ok(true);
+        // Service management
+        "systemctl" | "service" | "rc-service" => {
+            required_permissions.push(Permission::ReadSystem);
+            
+            if args.iter().any(|arg| {
+                matches!(arg.as_str(), "start" | "stop" | "restart" | "enable" | "disable" | "daemon-reload")
+            }) {
+                required_permissions.push(Permission::ServiceManagement);
+                required_permissions.push(Permission::WriteSystem);
+            }
         }
 
-        // Check if elevation is possible
-        if !self.user_permissions.is_sudo_user && !self.user_permissions.is_root {
-            return Err(anyhow::anyhow!(
-                "Cannot elevate permissions: user is not in sudo group"
-            ));
+        // File operations
+        "cp" | "mv" | "rm" | "mkdir" | "rmdir" | "chmod" | "chown" | "install" => {
+            required_permissions.push(Permission::FileSystemWrite);
+            required_permissions.push(Permission::WriteSystem);
         }
 
-        let permission_desc = match permission {
-            Permission::PackageManagement => "install/remove packages",
-            Permission::ServiceManagement => "manage system services",
-            Permission::FileSystemWrite => "modify files and directories",
-            Permission::UserManagement => "manage users and groups",
-            Permission::SystemConfiguration => "modify system configuration",
-            Permission::NetworkAccess => "access network resources",
-            _ => "perform system operations",
-        };
-
-        let approved = Confirm::new()
-            .with_prompt(&format!(
-                "TuxPilot needs elevated permissions to {}. Grant permission?",
-                permission_desc
-            ))
-            .default(false)
-            .interact()?;
-
-        if approved {
-            // Test sudo access
-            let sudo_test = tokio::process::Command::new("sudo")
-                .args(&["-n", "true"])
-                .output()
-                .await?;
+        "cat" | "ls" | "find" | "grep" | "head" | "tail" => {
+            required_permissions.push(Permission::FileSystemRead);
+            required_permissions.push(Permission::ReadSystem);
+        }
+
+        // User management
+        "useradd" | "userdel" | "usermod" | "passwd" | "groupadd" | "groupdel" | "gpasswd" | "chage" => {
+            required_permissions.push(Permission::UserManagement);
+            required_permissions.push(Permission::WriteSystem);
+        }
+
+        // Checking sudoers only reads it; editing it changes who may become root
+        "visudo" => {
+            required_permissions.push(Permission::ReadSystem);
+
+            if !args.iter().any(|arg| arg == "-c" || arg == "--check") {
+                required_permissions.push(Permission::UserManagement);
+                required_permissions.push(Permission::WriteSystem);
+            }
+        }
+
+        // Network operations
+        "wget" | "curl" | "ping" | "nslookup" | "dig" => {
+            required_permissions.push(Permission::NetworkAccess);
+            required_permissions.push(Permission::ReadSystem);
+        }
+
+        // System configuration
+        "mount" | "umount" | "fdisk" | "parted" => {
+            required_permissions.push(Permission::SystemConfiguration);
+            required_permissions.push(Permission::WriteSystem);
+        }
+
+        // Kernel parameters and modules
+        "modprobe" | "insmod" | "rmmod" => {
+            required_permissions.push(Permission::SystemConfiguration);
+            required_permissions.push(Permission::WriteSystem);
+        }
+
+        // Firewall frontends
+        "iptables" | "ip6tables" | "nft" | "ufw" | "firewall-cmd" => {
+            required_permissions.push(Permission::ReadSystem);
+
+            if !firewall::is_query(command, args) {
+                required_permissions.push(Permission::SystemConfiguration);
+                required_permissions.push(Permission::WriteSystem);
+            }
+        }
 
-            if sudo_test.status.success() {
-                self.granted_permissions.insert(permission);
-                Ok(true)
-            } else {
-                Err(anyhow::anyhow!("Sudo authentication required"))
+        // Network configuration
+        "nmcli" | "networkctl" | "netplan" | "ifup" | "ifdown" => {
+            required_permissions.push(Permission::ReadSystem);
+
+            if !network::is_query(command, args) {
+                required_permissions.push(Permission::SystemConfiguration);
+                required_permissions.push(Permission::WriteSystem);
             }
-        } else {
-            Ok(false)
         }
+
+        // SELinux and AppArmor policy; listing and dry runs only read it
+        "setsebool" | "semanage" | "restorecon" | "chcon" | "setenforce" | "aa-complain" | "aa-enforce" | "aa-disable" | "apparmor_parser" => {
+            required_permissions.push(Permission::ReadSystem);
+
+            let query = match command {
+                "semanage" => args.iter().any(|arg| arg == "-l" || arg == "--list"),
+                "restorecon" => args.iter().any(|arg| arg == "-n"),
+                _ => false,
+            };
+            if !query {
+                required_permissions.push(Permission::SystemConfiguration);
+                required_permissions.push(Permission::WriteSystem);
+            }
+        }
+
+        "sysctl" => {
+            required_permissions.push(Permission::ReadSystem);
+
+            if args.iter().any(|arg| arg == "-w" || arg == "-p" || arg == "--system" || arg.contains('=')) {
+                required_permissions.push(Permission::SystemConfiguration);
+                required_permissions.push(Permission::WriteSystem);
+            }
+        }
+
+        // Default: require read access
+        _ => {
+            required_permissions.push(Permission::ReadSystem);
+        }
+    }
+
+    required_permissions
+}
+
+/// Whether `command` is a shell, interpreter or wrapper such as `setsid` or `env`. These
+/// run whatever they are given, so they have no privileged scope of their own.
+pub fn runs_any_command(command: &str) -> bool {
+    let name = std::path::Path::new(command).file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(command);
+    // python3.12, perl5.38
+    let name = name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    COMMAND_RUNNERS.contains(&name)
+}
+
+pub fn permission_description(permission: &Permission) -> &'static str {
+    match permission {
+        Permission::PackageManagement => "install/remove packages",
+        Permission::ServiceManagement => "manage system services",
+        Permission::FileSystemWrite => "modify files and directories",
+        Permission::UserManagement => "manage users and groups",
+        Permission::SystemConfiguration => "modify system configuration",
+        Permission::NetworkAccess => "access network resources",
+        _ => "perform system operations",
+    }
+}
+
+/// Parse a permission name such as `PackageManagement`, `package-management` or `packages`
+pub fn parse_permission(value: &str) -> Result<Permission> {
+    let normalized: String = value.chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+
+    match normalized.as_str() {
+        "readsystem" | "read" => Ok(Permission::ReadSystem),
+        "writesystem" | "write" => Ok(Permission::WriteSystem),
+        "packagemanagement" | "packages" | "package" => Ok(Permission::PackageManagement),
+        "servicemanagement" | "services" | "service" => Ok(Permission::ServiceManagement),
+        "filesystemread" => Ok(Permission::FileSystemRead),
+        "filesystemwrite" | "files" => Ok(Permission::FileSystemWrite),
+        "networkaccess" | "network" => Ok(Permission::NetworkAccess),
+        "usermanagement" | "users" => Ok(Permission::UserManagement),
+        "systemconfiguration" | "config" => Ok(Permission::SystemConfiguration),
+        _ => Err(anyhow::anyhow!("Unknown permission '{}'", value)),
     }
 }
 
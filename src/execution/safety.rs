@@ -4,6 +4,10 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 
 use crate::config::Config;
+use crate::environment::{self, EnvironmentInfo};
+use crate::firewall;
+use crate::network;
+use crate::remote::transport::LocalTransport;
 use super::{ExecutionRequest, RiskLevel};
 
 #[derive(Debug, Clone)]
@@ -11,6 +15,8 @@ pub struct SafetyChecker {
     dangerous_commands: HashSet<String>,
     dangerous_patterns: Vec<Regex>,
     safe_commands: HashSet<String>,
+    /// Where local commands run; remote hosts are checked by the executor
+    environment: EnvironmentInfo,
     config: Config,
 }
 
@@ -33,6 +39,7 @@ impl SafetyChecker {
             dangerous_commands,
             dangerous_patterns,
             safe_commands,
+            environment: environment::detect(&LocalTransport).await,
             config: config.clone(),
         })
     }
@@ -59,11 +66,7 @@ impl SafetyChecker {
         // User management
         commands.insert("userdel".to_string());
         commands.insert("passwd".to_string());
-        
-        // Network/Security
-        commands.insert("iptables".to_string());
-        commands.insert("ufw".to_string());
-        commands.insert("firewall-cmd".to_string());
+
         
         // Package management (potentially dangerous)
         commands.insert("dpkg".to_string());
@@ -176,6 +179,19 @@ impl SafetyChecker {
             });
         }
 
+        // Commands this container, WSL instance or VM cannot run at all
+        if request.host.is_none() {
+            if let Some(reason) = self.environment.restriction(&request.command, &request.args) {
+                return Ok(SafetyResult {
+                    is_safe: false,
+                    risk_level: RiskLevel::Low,
+                    reason,
+                    warnings: Vec::new(),
+                    suggestions: vec![format!("Running in: {}", self.environment)],
+                });
+            }
+        }
+
         // Check dangerous patterns
         for pattern in &self.dangerous_patterns {
             if pattern.is_match(&full_command) {
@@ -221,10 +237,13 @@ impl SafetyChecker {
         match request.command.as_str() {
             "rm" => self.analyze_rm_command(&request.args),
             "chmod" | "chown" => self.analyze_permission_command(&request.args),
-            "pacman" | "apt" | "dnf" | "zypper" => self.analyze_package_command(&request.args),
+            "pacman" | "apt" | "apt-get" | "dnf" | "zypper" | "emerge" | "apk" | "xbps-install" | "xbps-remove"
+            | "nix-env" | "flatpak" | "snap" => self.analyze_package_command(&request.args),
             "systemctl" | "service" => self.analyze_service_command(&request.args),
             "dd" => self.analyze_dd_command(&request.args),
             "mount" | "umount" => self.analyze_mount_command(&request.args),
+            "iptables" | "ip6tables" | "nft" | "ufw" | "firewall-cmd" => self.analyze_firewall_command(&request.command, &request.args),
+            "nmcli" | "networkctl" | "netplan" | "ifup" | "ifdown" => self.analyze_network_command(&request.command, &request.args),
             _ => {
                 if self.safe_commands.contains(&request.command) {
                     Ok((true, RiskLevel::Safe, "Command is safe".to_string()))
@@ -286,14 +305,15 @@ impl SafetyChecker {
 
     fn analyze_package_command(&self, args: &[String]) -> Result<(bool, RiskLevel, String)> {
         // Check for safe package operations
-        let safe_operations = ["search", "info", "list", "show", "-Q", "-Ss", "-Si"];
+        let safe_operations = ["search", "info", "list", "show", "find", "-Q", "-Ss", "-Si", "-q", "-qa", "-qc"];
         
         if args.iter().any(|arg| safe_operations.contains(&arg.as_str())) {
             return Ok((true, RiskLevel::Safe, "Package query operation".to_string()));
         }
 
         // Check for installation/removal operations
-        let modify_operations = ["install", "remove", "upgrade", "update", "-S", "-R", "-Syu"];
+        let modify_operations = ["install", "remove", "upgrade", "update", "add", "del", "uninstall", "refresh",
+            "-S", "-R", "-Syu", "-i", "-e", "-u", "-y"];
         
         if args.iter().any(|arg| modify_operations.contains(&arg.as_str())) {
             return Ok((true, RiskLevel::Medium, "Package modification operation".to_string()));
@@ -303,13 +323,13 @@ impl SafetyChecker {
     }
 
     fn analyze_service_command(&self, args: &[String]) -> Result<(bool, RiskLevel, String)> {
-        let safe_operations = ["status", "is-active", "is-enabled", "list-units"];
+        let safe_operations = ["status", "is-active", "is-enabled", "list-units", "list-timers", "show", "cat"];
         
         if args.iter().any(|arg| safe_operations.contains(&arg.as_str())) {
             return Ok((true, RiskLevel::Safe, "Service query operation".to_string()));
         }
 
-        let modify_operations = ["start", "stop", "restart", "enable", "disable"];
+        let modify_operations = ["start", "stop", "restart", "enable", "disable", "daemon-reload"];
         
         if args.iter().any(|arg| modify_operations.contains(&arg.as_str())) {
             return Ok((true, RiskLevel::Medium, "Service modification operation".to_string()));
@@ -335,6 +355,25 @@ impl SafetyChecker {
         Ok((true, RiskLevel::Medium, "Mount operations require caution".to_string()))
     }
 
+    fn analyze_firewall_command(&self, command: &str, args: &[String]) -> Result<(bool, RiskLevel, String)> {
+        if firewall::is_query(command, args) {
+            return Ok((true, RiskLevel::Safe, "Firewall query operation".to_string()));
+        }
+        if let Some(reason) = firewall::destructive(command, args) {
+            return Ok((false, RiskLevel::Critical, reason.to_string()));
+        }
+
+        Ok((true, RiskLevel::High, "Firewall rule change".to_string()))
+    }
+
+    fn analyze_network_command(&self, command: &str, args: &[String]) -> Result<(bool, RiskLevel, String)> {
+        if network::is_query(command, args) {
+            return Ok((true, RiskLevel::Safe, "Network query operation".to_string()));
+        }
+
+        Ok((true, RiskLevel::High, "Network configuration change; a mistake can cut this machine off the network".to_string()))
+    }
+
     fn check_common_safety_issues(&self, request: &ExecutionRequest, warnings: &mut Vec<String>, suggestions: &mut Vec<String>) {
         let full_command = format!("{} {}", request.command, request.args.join(" "));
 
@@ -0,0 +1,599 @@
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::process::Command;
+use uuid::Uuid;
+
+use crate::accounts::{self, PasswordState};
+use crate::config::{Config, PackageManager, ServiceManager};
+use crate::linux_integration::kernel;
+use crate::packages::{self, PackageOperation};
+use crate::remote::transport::{shell_quote, CommandOutput, LocalTransport};
+use crate::services::{self, ActiveState, EnabledState, ServiceAction, ServiceBackend};
+use crate::session;
+use super::{SideEffect, SideEffectType, LOCAL_HOST};
+
+/// Cron tables whose entries are compared; per-user spools are usually only readable as root
+const CRON_PATHS: &[&str] = &["/etc/crontab", "/etc/cron.d", "/var/spool/cron", "/var/spool/cron/crontabs"];
+/// Larger files are still checksummed, but not backed up
+const MAX_BACKUP_BYTES: u64 = 1024 * 1024;
+
+/// Takes lightweight snapshots of the system around an execution so the side effects
+/// that actually happened can be reported, instead of guessing from the command name.
+#[derive(Debug, Clone)]
+pub struct StateProbe {
+    package_manager: PackageManager,
+    services: Arc<dyn ServiceBackend>,
+    watched_paths: Vec<PathBuf>,
+    backup_root: PathBuf,
+    retention_days: u32,
+}
+
+/// Point-in-time view of the parts of the system commands commonly change. Probes
+/// that could not run (missing tool, no permission) are `None` and left out of the diff.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    pub packages: Option<BTreeSet<String>>,
+    pub enabled_units: Option<BTreeSet<String>>,
+    pub active_units: Option<BTreeSet<String>>,
+    pub listening_sockets: Option<BTreeSet<String>>,
+    pub users: Option<BTreeSet<String>>,
+    pub groups: Option<BTreeSet<String>>,
+    /// Shell, supplementary groups and lock state of each user
+    #[serde(default)]
+    pub accounts: Option<BTreeMap<String, AccountState>>,
+    pub cron_entries: BTreeMap<PathBuf, Vec<String>>,
+    pub files: BTreeMap<PathBuf, FileState>,
+    pub kernel_modules: Option<BTreeSet<String>>,
+    /// Runtime values of the kernel parameters the command assigns
+    #[serde(default)]
+    pub kernel_parameters: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileState {
+    pub checksum: String,
+    /// Copy taken before execution, used to restore the file on rollback
+    pub backup: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountState {
+    pub shell: String,
+    pub groups: Vec<String>,
+    /// None when /etc/shadow could not be read
+    pub locked: Option<bool>,
+    pub expires: Option<NaiveDate>,
+}
+
+impl StateProbe {
+    pub fn new(config: &Config) -> Result<Self> {
+        let backup_root = dirs::data_dir()
+            .context("Failed to get data directory")?
+            .join("tuxpilot")
+            .join("state");
+
+        Ok(Self {
+            package_manager: config.system.package_manager.clone(),
+            services: Arc::from(services::backend(&config.system.service_manager, Arc::new(LocalTransport))),
+            watched_paths: config.state_probes.watched_paths.clone(),
+            backup_root,
+            retention_days: config.state_probes.backup_retention_days,
+        })
+    }
+
+    /// Capture the current state. With a `backup_id`, watched files are also copied
+    /// aside so later changes to them can be rolled back. Kernel parameters are too many
+    /// to read every time, so only the `parameters` named are.
+    pub async fn capture(&self, backup_id: Option<Uuid>, parameters: &[String]) -> Result<StateSnapshot> {
+        let (packages, (enabled_units, active_units), listening_sockets) = tokio::join!(
+            self.probe_packages(),
+            self.probe_services(),
+            probe_sockets(),
+        );
+
+        let watched_paths = self.watched_paths.clone();
+        let parameters = parameters.to_vec();
+        let backup_root = self.backup_root.clone();
+        let retention_days = self.retention_days;
+
+        let (users, groups, accounts, cron_entries, files, kernel_modules, kernel_parameters) = tokio::task::spawn_blocking(move || -> Result<_> {
+            let backup_dir = match backup_id {
+                Some(id) => {
+                    prune_backups(&backup_root, retention_days);
+                    let dir = backup_root.join(id.to_string());
+                    fs::create_dir_all(&dir).context("Failed to create state backup directory")?;
+                    fs::set_permissions(&backup_root, fs::Permissions::from_mode(0o700))?;
+                    Some(dir)
+                }
+                None => None,
+            };
+
+            let cron_files = expand_paths(CRON_PATHS.iter().map(PathBuf::from));
+            let cron_entries = cron_files.iter()
+                .filter_map(|path| Some((path.clone(), read_cron_entries(path)?)))
+                .collect();
+
+            let mut paths = expand_paths(watched_paths);
+            paths.extend(cron_files);
+            let files = snapshot_files(&paths, backup_dir.as_deref());
+
+            let kernel_parameters = parameters.iter()
+                .filter_map(|key| Some((key.clone(), kernel::local_value(key)?)))
+                .collect();
+
+            Ok((read_names("/etc/passwd"), read_names("/etc/group"), read_accounts(), cron_entries, files,
+                read_modules(), kernel_parameters))
+        })
+        .await
+        .context("State probe task failed")??;
+
+        Ok(StateSnapshot {
+            packages,
+            enabled_units,
+            active_units,
+            listening_sockets,
+            users,
+            groups,
+            accounts,
+            cron_entries,
+            files,
+            kernel_modules,
+            kernel_parameters,
+        })
+    }
+
+    /// Turn the differences between two snapshots into side effects with rollback commands
+    pub fn diff(&self, before: &StateSnapshot, after: &StateSnapshot) -> Vec<SideEffect> {
+        let mut effects = Vec::new();
+
+        for (added, package) in set_changes(&before.packages, &after.packages) {
+            effects.push(if added {
+                effect(SideEffectType::PackageInstalled, format!("Package installed: {}", package),
+                       Some(package_command(&self.package_manager, false, package)))
+            } else {
+                effect(SideEffectType::PackageRemoved, format!("Package removed: {}", package),
+                       Some(package_command(&self.package_manager, true, package)))
+            });
+        }
+
+        for (added, unit) in set_changes(&before.enabled_units, &after.enabled_units) {
+            effects.push(if added {
+                effect(SideEffectType::ServiceEnabled, format!("Unit enabled: {}", unit),
+                       Some(self.service_command(unit, ServiceAction::Disable)))
+            } else {
+                effect(SideEffectType::ServiceDisabled, format!("Unit disabled: {}", unit),
+                       Some(self.service_command(unit, ServiceAction::Enable)))
+            });
+        }
+
+        for (added, unit) in set_changes(&before.active_units, &after.active_units) {
+            effects.push(if added {
+                effect(SideEffectType::ServiceStarted, format!("Unit started: {}", unit),
+                       Some(self.service_command(unit, ServiceAction::Stop)))
+            } else {
+                effect(SideEffectType::ServiceStopped, format!("Unit stopped: {}", unit),
+                       Some(self.service_command(unit, ServiceAction::Start)))
+            });
+        }
+
+        // Sockets follow the services that own them, so they have no rollback of their own
+        for (added, socket) in set_changes(&before.listening_sockets, &after.listening_sockets) {
+            effects.push(if added {
+                effect(SideEffectType::SocketOpened, format!("Listening socket opened: {}", socket), None)
+            } else {
+                effect(SideEffectType::SocketClosed, format!("Listening socket closed: {}", socket), None)
+            });
+        }
+
+        for (added, user) in set_changes(&before.users, &after.users) {
+            effects.push(if added {
+                effect(SideEffectType::UserCreated, format!("User created: {}", user),
+                       Some(format!("userdel {}", shell_quote(user))))
+            } else {
+                // The home directory and UID are gone, so this cannot be undone faithfully
+                effect(SideEffectType::UserRemoved, format!("User removed: {}", user), None)
+            });
+        }
+
+        for (added, group) in set_changes(&before.groups, &after.groups) {
+            effects.push(if added {
+                effect(SideEffectType::GroupCreated, format!("Group created: {}", group),
+                       Some(format!("groupdel {}", shell_quote(group))))
+            } else {
+                effect(SideEffectType::GroupRemoved, format!("Group removed: {}", group),
+                       Some(format!("groupadd {}", shell_quote(group))))
+            });
+        }
+
+        if let (Some(before), Some(after)) = (&before.accounts, &after.accounts) {
+            for (name, old) in before {
+                let Some(new) = after.get(name).filter(|new| *new != old) else {
+                    continue;
+                };
+                effects.extend(account_change(name, old, new));
+            }
+        }
+
+        for (added, module) in set_changes(&before.kernel_modules, &after.kernel_modules) {
+            effects.push(if added {
+                effect(SideEffectType::KernelModuleLoaded, format!("Kernel module loaded: {}", module),
+                       Some(format!("modprobe -r {}", shell_quote(module))))
+            } else {
+                effect(SideEffectType::KernelModuleUnloaded, format!("Kernel module unloaded: {}", module),
+                       Some(format!("modprobe {}", shell_quote(module))))
+            });
+        }
+
+        for (key, old) in &before.kernel_parameters {
+            let Some(new) = after.kernel_parameters.get(key).filter(|new| *new != old) else {
+                continue;
+            };
+            // Multi-field values such as net.ipv4.ip_local_port_range stay one quoted word
+            let rollback = Some(format!("sysctl -w {}", shell_quote(&format!("{}={}", key, old))));
+            effects.push(effect(SideEffectType::KernelParameterChanged,
+                                format!("Kernel parameter changed: {} {} -> {}", key, old, new), rollback));
+        }
+
+        let paths: BTreeSet<&PathBuf> = before.files.keys().chain(after.files.keys()).collect();
+        for path in paths {
+            let restore = before.files.get(path)
+                .and_then(|state| state.backup.as_ref())
+                .map(|backup| format!("cp -a {} {}", quote_path(backup), quote_path(path)));
+
+            let (effect_type, change, rollback) = match (before.files.get(path), after.files.get(path)) {
+                (None, Some(_)) => (SideEffectType::FileCreated, "created", Some(format!("rm -f {}", quote_path(path)))),
+                (Some(_), None) => (SideEffectType::FileDeleted, "deleted", restore),
+                (Some(old), Some(new)) if old.checksum != new.checksum => (SideEffectType::FileModified, "modified", restore),
+                _ => continue,
+            };
+
+            let is_cron = before.cron_entries.contains_key(path) || after.cron_entries.contains_key(path);
+            effects.push(if is_cron {
+                effect(SideEffectType::ScheduledTaskChanged,
+                       describe_cron_change(path, before.cron_entries.get(path), after.cron_entries.get(path)),
+                       rollback)
+            } else {
+                effect(effect_type, format!("File {}: {}", change, path.display()), rollback)
+            });
+        }
+
+        effects
+    }
+
+    /// Drop the backups taken for an execution when none of its side effects need them
+    pub fn discard_unused_backups(&self, backup_id: Uuid, effects: &[SideEffect]) {
+        let dir = self.backup_root.join(backup_id.to_string());
+        let prefix = dir.display().to_string();
+
+        let referenced = effects.iter()
+            .filter_map(|e| e.rollback_command.as_ref())
+            .any(|command| command.contains(&prefix));
+
+        if !referenced {
+            let _ = fs::remove_dir_all(&dir);
+        }
+    }
+
+    /// Enabled and active units. systemd also reports sockets and timers; other init
+    /// systems only know services.
+    async fn probe_services(&self) -> (Option<BTreeSet<String>>, Option<BTreeSet<String>>) {
+        if matches!(self.services.manager(), ServiceManager::Systemd) {
+            return tokio::join!(
+                first_column("systemctl", &["list-unit-files", "--state=enabled", "--no-legend", "--no-pager", "--plain"]),
+                first_column("systemctl", &["list-units", "--state=active", "--type=service,socket,timer", "--no-legend", "--no-pager", "--plain"]),
+            );
+        }
+
+        let Ok(services) = self.services.list().await else {
+            return (None, None);
+        };
+        let enabled = services.iter()
+            .filter(|status| status.enabled == EnabledState::Enabled)
+            .map(|status| status.name.clone())
+            .collect();
+        let active = services.iter()
+            .filter(|status| status.active == ActiveState::Active)
+            .map(|status| status.name.clone())
+            .collect();
+        (Some(enabled), Some(active))
+    }
+
+    fn service_command(&self, unit: &str, action: ServiceAction) -> String {
+        quote_words(&self.services.command(unit, &action))
+    }
+
+    async fn probe_packages(&self) -> Option<BTreeSet<String>> {
+        match self.package_manager {
+            PackageManager::Pacman => first_column("pacman", &["-Qq"]).await,
+            PackageManager::Apt => {
+                let lines = command_lines("dpkg-query", &["-W", "-f=${Status}\t${binary:Package}\n"]).await?;
+                Some(lines.iter()
+                    .filter_map(|line| line.strip_prefix("install ok installed\t"))
+                    .map(String::from)
+                    .collect())
+            }
+            PackageManager::Dnf | PackageManager::Zypper => first_column("rpm", &["-qa", "--qf", "%{NAME}\n"]).await,
+            PackageManager::Portage => first_column("qlist", &["-I"]).await,
+            PackageManager::Apk => first_column("apk", &["info"]).await,
+            PackageManager::Xbps => {
+                // "ii name-1.0_1 description"
+                let lines = command_lines("xbps-query", &["-l"]).await?;
+                Some(lines.iter()
+                    .filter_map(|line| line.split_whitespace().nth(1))
+                    .map(|pkgver| pkgver.rsplit_once('-').map(|(name, _)| name).unwrap_or(pkgver).to_string())
+                    .collect())
+            }
+            PackageManager::Nix => first_column("nix-env", &["-q"]).await,
+            PackageManager::NixProfile => {
+                let lines = command_lines("nix", &["--extra-experimental-features", "nix-command", "profile", "list", "--json"]).await?;
+                let manifest: serde_json::Value = serde_json::from_str(&lines.join("\n")).ok()?;
+                Some(manifest["elements"].as_object()?.keys().cloned().collect())
+            }
+            PackageManager::Flatpak => first_column("flatpak", &["list", "--columns=application"]).await,
+            PackageManager::Snap => first_column("snap", &["list"]).await.map(|mut names| {
+                names.remove("Name");
+                names
+            }),
+        }
+    }
+}
+
+/// Command that installs (`install = true`) or removes a single package
+fn package_command(package_manager: &PackageManager, install: bool, package: &str) -> String {
+    let packages = vec![package.to_string()];
+    let operation = if install {
+        PackageOperation::Install(packages)
+    } else {
+        PackageOperation::Remove(packages)
+    };
+    quote_words(&packages::command_line(package_manager, &operation))
+}
+
+/// A command line of shell-quoted words, as rollback commands are stored
+fn quote_words(words: &[String]) -> String {
+    words.iter().map(|word| shell_quote(word)).collect::<Vec<_>>().join(" ")
+}
+
+fn quote_path(path: &Path) -> String {
+    shell_quote(&path.to_string_lossy())
+}
+
+fn effect(effect_type: SideEffectType, description: String, rollback_command: Option<String>) -> SideEffect {
+    SideEffect {
+        effect_type,
+        description,
+        reversible: rollback_command.is_some(),
+        rollback_command,
+    }
+}
+
+/// A UserModified effect for the differences between two states of one account,
+/// undone with a single usermod. Supplementary groups cannot be cleared that way.
+fn account_change(name: &str, old: &AccountState, new: &AccountState) -> Option<SideEffect> {
+    let mut changes = Vec::new();
+    let mut undo = vec!["usermod".to_string()];
+    let mut reversible = true;
+    if old.shell != new.shell {
+        changes.push(format!("shell {} -> {}", old.shell, new.shell));
+        undo.extend(["-s".to_string(), old.shell.clone()]);
+    }
+    if old.groups != new.groups {
+        changes.push(format!("groups {} -> {}", old.groups.join(","), new.groups.join(",")));
+        reversible &= !old.groups.is_empty();
+        undo.extend(["-G".to_string(), old.groups.join(",")]);
+    }
+    if let (Some(was_locked), Some(locked)) = (old.locked, new.locked) {
+        if was_locked != locked {
+            changes.push(if locked { "locked" } else { "unlocked" }.to_string());
+            undo.push(if was_locked { "-L" } else { "-U" }.to_string());
+        }
+    }
+    if old.expires != new.expires {
+        let day = |expires: Option<NaiveDate>| expires.map(|day| day.to_string());
+        changes.push(format!("expires {} -> {}", day(old.expires).unwrap_or_else(|| "never".to_string()),
+                             day(new.expires).unwrap_or_else(|| "never".to_string())));
+        undo.extend(["-e".to_string(), day(old.expires).unwrap_or_else(|| "-1".to_string())]);
+    }
+    if changes.is_empty() {
+        return None;
+    }
+    undo.push(name.to_string());
+    Some(effect(SideEffectType::UserModified, format!("User modified: {} ({})", name, changes.join(", ")),
+                reversible.then(|| quote_words(&undo))))
+}
+
+/// Entries added (`true`) or removed (`false`) between two probe results
+fn set_changes<'a>(before: &'a Option<BTreeSet<String>>, after: &'a Option<BTreeSet<String>>) -> Vec<(bool, &'a String)> {
+    match (before, after) {
+        (Some(before), Some(after)) => after.difference(before).map(|s| (true, s))
+            .chain(before.difference(after).map(|s| (false, s)))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn describe_cron_change(path: &Path, before: Option<&Vec<String>>, after: Option<&Vec<String>>) -> String {
+    let empty = Vec::new();
+    let before = before.unwrap_or(&empty);
+    let after = after.unwrap_or(&empty);
+
+    let added: Vec<&str> = after.iter().filter(|e| !before.contains(e)).map(String::as_str).collect();
+    let removed: Vec<&str> = before.iter().filter(|e| !after.contains(e)).map(String::as_str).collect();
+
+    let mut description = format!("Cron table changed: {}", path.display());
+    if !added.is_empty() {
+        description.push_str(&format!("; added: {}", added.join(" | ")));
+    }
+    if !removed.is_empty() {
+        description.push_str(&format!("; removed: {}", removed.join(" | ")));
+    }
+    description
+}
+
+async fn command_lines(program: &str, args: &[&str]) -> Option<Vec<String>> {
+    let output = session::command(LOCAL_HOST, program, args, async {
+        let start = Instant::now();
+        let output = Command::new(program)
+            .args(args)
+            .env("LC_ALL", "C")
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .await
+            .with_context(|| format!("Failed to execute command: {} {:?}", program, args))?;
+
+        Ok(CommandOutput {
+            exit_code: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::new(),
+            duration: start.elapsed(),
+        })
+    }).await.ok()?;
+
+    if !output.success() {
+        return None;
+    }
+
+    Some(output.stdout
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+async fn first_column(program: &str, args: &[&str]) -> Option<BTreeSet<String>> {
+    let lines = command_lines(program, args).await?;
+    Some(lines.iter()
+        .filter_map(|line| line.split_whitespace().next())
+        .map(String::from)
+        .collect())
+}
+
+/// Listening TCP/UDP sockets as "proto address:port"
+async fn probe_sockets() -> Option<BTreeSet<String>> {
+    let lines = command_lines("ss", &["-H", "-ltnu"]).await?;
+    Some(lines.iter()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            Some(format!("{} {}", fields.first()?, fields.get(4)?))
+        })
+        .collect())
+}
+
+/// Loaded kernel modules, the first column of /proc/modules
+fn read_modules() -> Option<BTreeSet<String>> {
+    let content = fs::read_to_string("/proc/modules").ok()?;
+    Some(content.lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .map(String::from)
+        .collect())
+}
+
+/// Names from a colon-separated database such as /etc/passwd or /etc/group
+fn read_names(path: &str) -> Option<BTreeSet<String>> {
+    let content = fs::read_to_string(path).ok()?;
+    Some(content.lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split(':').next())
+        .map(String::from)
+        .collect())
+}
+
+/// Account state from passwd, group and shadow; lock state is left out without shadow
+fn read_accounts() -> Option<BTreeMap<String, AccountState>> {
+    let accounts = accounts::parse_passwd(&fs::read_to_string(accounts::PASSWD).ok()?);
+    let groups = accounts::parse_group(&fs::read_to_string(accounts::GROUP).ok()?);
+    let shadow = fs::read_to_string(accounts::SHADOW).ok().map(|text| accounts::parse_shadow(&text));
+    Some(accounts.into_iter().map(|account| {
+        let (locked, expires) = match shadow.as_ref().and_then(|shadow| shadow.get(&account.name)) {
+            Some((password, aging)) => (Some(*password == PasswordState::Locked), aging.expires),
+            None => (None, None),
+        };
+        let groups = groups.iter()
+            .filter(|group| group.members.contains(&account.name))
+            .map(|group| group.name.clone())
+            .collect();
+        (account.name, AccountState { shell: account.shell, groups, locked, expires })
+    }).collect())
+}
+
+fn read_cron_entries(path: &Path) -> Option<Vec<String>> {
+    let content = fs::read_to_string(path).ok()?;
+    Some(content.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+/// Files named directly plus the regular files directly inside named directories
+fn expand_paths(paths: impl IntoIterator<Item = PathBuf>) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+
+    for path in paths {
+        if path.is_file() {
+            files.push(path);
+        } else if let Ok(entries) = fs::read_dir(&path) {
+            let mut children: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|child| child.is_file())
+                .collect();
+            children.sort();
+            files.extend(children);
+        }
+    }
+
+    files
+}
+
+fn snapshot_files(paths: &[PathBuf], backup_dir: Option<&Path>) -> BTreeMap<PathBuf, FileState> {
+    let mut files = BTreeMap::new();
+
+    for path in paths {
+        // Unreadable files (e.g. /etc/sudoers as a normal user) are simply not tracked
+        let Ok(content) = fs::read(path) else { continue };
+        let checksum = hex::encode(Sha256::digest(&content));
+
+        let backup = backup_dir
+            .filter(|_| content.len() as u64 <= MAX_BACKUP_BYTES)
+            .and_then(|dir| {
+                let target = dir.join(path.strip_prefix("/").unwrap_or(path));
+                fs::create_dir_all(target.parent()?).ok()?;
+                fs::copy(path, &target).ok()?;
+                Some(target)
+            });
+
+        files.insert(path.clone(), FileState { checksum, backup });
+    }
+
+    files
+}
+
+/// Remove backup directories older than the retention period
+fn prune_backups(backup_root: &Path, retention_days: u32) {
+    let Ok(entries) = fs::read_dir(backup_root) else { return };
+    let max_age = std::time::Duration::from_secs(retention_days as u64 * 24 * 60 * 60);
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let expired = entry.metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > max_age);
+
+        if expired {
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
+}
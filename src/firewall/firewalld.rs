@@ -0,0 +1,182 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use crate::remote::transport::Transport;
+use super::{check_not_open, check_single_port, not_open, shell_line, words};
+use super::{ChangeAction, FirewallBackend, FirewallChange, FirewallPlan, Frontend, Port, Ruleset, Zone};
+
+#[derive(Debug, Clone)]
+pub struct Firewalld {
+    transport: Arc<dyn Transport>,
+}
+
+impl Firewalld {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    /// Ports of a firewalld service, from its definition
+    async fn service_ports(&self, service: &str) -> Vec<Port> {
+        let Ok(output) = self.transport.run("firewall-cmd", &[&format!("--info-service={}", service)]).await else {
+            return Vec::new();
+        };
+        output.stdout.lines()
+            .filter_map(|line| line.trim().strip_prefix("ports:"))
+            .flat_map(|ports| ports.split_whitespace().filter_map(|port| port.parse::<Port>().ok()).collect::<Vec<_>>())
+            .collect()
+    }
+}
+
+/// Parse `firewall-cmd --list-all-zones`
+pub(crate) fn parse_zones(output: &str) -> Vec<Zone> {
+    let mut zones: Vec<Zone> = Vec::new();
+    let mut in_rich_rules = false;
+
+    for line in output.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        // "public (default, active)"
+        if !line.starts_with(char::is_whitespace) {
+            let (name, flags) = line.split_once(' ').unwrap_or((line, ""));
+            zones.push(Zone {
+                name: name.to_string(),
+                default: flags.contains("default"),
+                active: flags.contains("active"),
+                ..Default::default()
+            });
+            in_rich_rules = false;
+            continue;
+        }
+        let Some(zone) = zones.last_mut() else {
+            continue;
+        };
+
+        // Rich rules follow on their own lines, indented with a tab
+        if in_rich_rules && line.starts_with('\t') {
+            zone.rich_rules.push(line.trim().to_string());
+            continue;
+        }
+        let Some((key, value)) = line.trim().split_once(':') else {
+            continue;
+        };
+        in_rich_rules = key == "rich rules";
+        let values = value.split_whitespace().map(String::from);
+        match key {
+            "target" => zone.target = value.trim().trim_matches('%').to_string(),
+            "interfaces" => zone.interfaces = values.collect(),
+            "sources" => zone.sources = values.collect(),
+            "services" => zone.services = values.collect(),
+            "ports" => zone.ports = values.filter_map(|port| port.parse().ok()).collect(),
+            _ => {}
+        }
+    }
+
+    // Interfaces no other zone claims use the default zone
+    for zone in &mut zones {
+        zone.active |= zone.default;
+    }
+    zones
+}
+
+/// The rich rule tuxpilot uses to open a port for one source
+fn rich_rule(port: &Port, source: &str) -> String {
+    let family = if source.contains(':') { "ipv6" } else { "ipv4" };
+    format!("rule family=\"{}\" source address=\"{}\" port port=\"{}\" protocol=\"{}\" accept",
+        family, source, port.range("-"), port.protocol)
+}
+
+/// The same change at runtime and in the permanent configuration
+fn both(zone: &str, option: &str) -> Vec<Vec<String>> {
+    let zone = format!("--zone={}", zone);
+    vec![
+        words(&["firewall-cmd", &zone, option]),
+        words(&["firewall-cmd", "--permanent", &zone, option]),
+    ]
+}
+
+#[async_trait::async_trait]
+impl FirewallBackend for Firewalld {
+    async fn is_active(&self) -> bool {
+        self.transport.run("firewall-cmd", &["--state"]).await
+            .is_ok_and(|output| output.stdout.trim() == "running")
+    }
+
+    async fn ruleset(&self) -> Result<Ruleset> {
+        let output = self.transport.run("firewall-cmd", &["--list-all-zones"]).await?;
+        if !output.success() {
+            return Err(anyhow::anyhow!("firewall-cmd --list-all-zones failed: {}", output.stderr.trim()));
+        }
+        let mut ruleset = Ruleset::new(Frontend::Firewalld);
+        ruleset.zones = parse_zones(&output.stdout);
+
+        let services: BTreeSet<String> = ruleset.zones.iter()
+            .filter(|zone| zone.active)
+            .flat_map(|zone| zone.services.iter().cloned())
+            .collect();
+        for service in services {
+            let ports = self.service_ports(&service).await;
+            for zone in ruleset.zones.iter_mut().filter(|zone| zone.services.contains(&service)) {
+                zone.service_ports.insert(service.clone(), ports.clone());
+            }
+        }
+        Ok(ruleset)
+    }
+
+    fn plan(&self, ruleset: &Ruleset, change: &FirewallChange) -> Result<FirewallPlan> {
+        let mut after = ruleset.clone();
+        let (commands, revert) = match change.action {
+            ChangeAction::Open => {
+                check_not_open(ruleset, change)?;
+                let index = ruleset.zones.iter().position(|zone| zone.default)
+                    .context("firewalld reports no default zone")?;
+                let zone = &ruleset.zones[index];
+
+                let (add, remove) = match &change.source {
+                    Some(source) => {
+                        let rule = rich_rule(&change.port, source);
+                        after.zones[index].rich_rules.push(rule.clone());
+                        (format!("--add-rich-rule={}", rule), format!("--remove-rich-rule={}", rule))
+                    }
+                    None => {
+                        after.zones[index].ports.push(change.port);
+                        (format!("--add-port={}", change.port), format!("--remove-port={}", change.port))
+                    }
+                };
+                (both(&zone.name, &add), both(&zone.name, &remove).iter().map(|command| shell_line(command)).collect())
+            }
+            ChangeAction::Close => {
+                let mut commands = Vec::new();
+                let mut revert = Vec::new();
+                for (index, zone) in ruleset.zones.iter().enumerate().filter(|(_, zone)| zone.active) {
+                    let (remove, add) = if let Some(source) = &change.source {
+                        let rule = rich_rule(&change.port, source);
+                        if !zone.rich_rules.contains(&rule) {
+                            continue;
+                        }
+                        after.zones[index].rich_rules.retain(|existing| *existing != rule);
+                        (format!("--remove-rich-rule={}", rule), format!("--add-rich-rule={}", rule))
+                    } else if zone.ports.contains(&change.port) {
+                        after.zones[index].ports.retain(|port| *port != change.port);
+                        (format!("--remove-port={}", change.port), format!("--add-port={}", change.port))
+                    } else if let Some(service) = zone.services.iter().find(|service| zone.ports_of(service).contains(&change.port)) {
+                        check_single_port(&zone.ports_of(service), change, &format!("service {} in zone {}", service, zone.name))?;
+                        after.zones[index].services.retain(|existing| existing != service);
+                        (format!("--remove-service={}", service), format!("--add-service={}", service))
+                    } else {
+                        continue;
+                    };
+                    commands.extend(both(&zone.name, &remove));
+                    revert.extend(both(&zone.name, &add).iter().map(|command| shell_line(command)));
+                }
+                if commands.is_empty() {
+                    return Err(not_open(ruleset, change));
+                }
+                (commands, revert)
+            }
+        };
+
+        Ok(FirewallPlan { change: change.clone(), commands, revert, after, persistent: true })
+    }
+}
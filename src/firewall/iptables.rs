@@ -0,0 +1,265 @@
+use anyhow::Result;
+use std::sync::Arc;
+
+use crate::remote::transport::Transport;
+use super::{check_not_open, check_single_port, not_open, shell_line, words};
+use super::{ChangeAction, Chain, FirewallBackend, FirewallChange, FirewallPlan, Frontend, Port, Protocol, Rule, Ruleset, Verdict};
+
+/// Address families and the tools that print their rules
+const FAMILIES: &[(&str, &str)] = &[("ip", "iptables-save"), ("ip6", "ip6tables-save")];
+
+#[derive(Debug, Clone)]
+pub struct Iptables {
+    transport: Arc<dyn Transport>,
+}
+
+impl Iptables {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+}
+
+/// Parse `iptables-save` output; `family` is "ip" or "ip6"
+pub(crate) fn parse_save(family: &str, output: &str) -> Vec<Chain> {
+    let mut chains: Vec<Chain> = Vec::new();
+    let mut table = String::new();
+
+    for line in output.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix('*') {
+            table = format!("{} {}", family, name);
+        } else if let Some(declaration) = line.strip_prefix(':') {
+            // ":INPUT DROP [0:0]", with "-" as the policy of user-defined chains
+            let mut fields = declaration.split_whitespace();
+            let name = fields.next().unwrap_or_default().to_string();
+            let policy = fields.next().filter(|policy| *policy != "-").map(Verdict::parse);
+            let hook = match name.as_str() {
+                "INPUT" | "FORWARD" | "OUTPUT" if table.ends_with(" filter") => Some(name.to_lowercase()),
+                _ => None,
+            };
+            chains.push(Chain { table: table.clone(), name, hook, policy, rules: Vec::new() });
+        } else if let Some(rule) = line.strip_prefix("-A ") {
+            let Some((name, spec)) = rule.split_once(' ') else {
+                continue;
+            };
+            if let Some(chain) = chains.iter_mut().find(|chain| chain.table == table && chain.name == name) {
+                let mut parsed = parse_rule(spec);
+                parsed.handle = Some(chain.rules.len() as u64 + 1);
+                chain.rules.push(parsed);
+            }
+        }
+    }
+
+    // Targets that name a chain of the same table continue there
+    let names: Vec<(String, String)> = chains.iter().map(|chain| (chain.table.clone(), chain.name.clone())).collect();
+    for chain in &mut chains {
+        for rule in &mut chain.rules {
+            if let Verdict::Other(target) = &rule.verdict {
+                if names.iter().any(|(table, name)| *table == chain.table && name == target) {
+                    rule.verdict = Verdict::Jump(target.clone());
+                }
+            }
+        }
+    }
+    chains
+}
+
+/// A rule specification as iptables-save prints it
+fn save_line(spec: &[String]) -> String {
+    spec.iter()
+        .map(|word| if word.contains(char::is_whitespace) { format!("\"{}\"", word) } else { word.clone() })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Words of a rule specification, keeping quoted comments together
+fn split_spec(spec: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut chars = spec.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' if quoted => current.extend(chars.next()),
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    words.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn parse_rule(spec: &str) -> Rule {
+    let mut rule = Rule::new(Verdict::Other("continue".to_string()), spec.to_string());
+    let words = split_spec(spec);
+    let mut words = words.iter().map(String::as_str).peekable();
+    let mut port_specs = Vec::new();
+
+    while let Some(word) = words.next() {
+        if word == "!" {
+            let option = words.next().unwrap_or_default();
+            let value = words.next().unwrap_or_default();
+            rule.other.push(format!("! {} {}", option, value));
+            continue;
+        }
+        match word {
+            "-p" | "--protocol" => rule.protocol = words.next().map(String::from),
+            "-s" | "--source" => rule.source = words.next().map(String::from),
+            "-i" | "--in-interface" => rule.interface = words.next().map(String::from),
+            "--dport" | "--destination-port" | "--dports" | "--destination-ports" => {
+                port_specs.extend(words.next().unwrap_or_default().split(',').map(String::from));
+            }
+            "--ctstate" | "--state" => {
+                rule.states = words.next().unwrap_or_default().split(',').map(str::to_lowercase).collect();
+            }
+            "--comment" => rule.comment = words.next().map(String::from),
+            "-j" | "--jump" | "-g" | "--goto" => {
+                let target = words.next().unwrap_or_default();
+                rule.verdict = Verdict::parse(target);
+                // Target options such as --reject-with
+                while words.peek().is_some_and(|next| next.starts_with("--")) {
+                    words.next();
+                    words.next();
+                }
+            }
+            // Match modules only load the options that follow
+            "-m" | "--match" => {
+                words.next();
+            }
+            option if option.starts_with('-') => {
+                let value = if words.peek().is_some_and(|next| !next.starts_with('-')) { words.next().unwrap_or_default() } else { "" };
+                rule.other.push(format!("{} {}", option, value).trim_end().to_string());
+            }
+            other => rule.other.push(other.to_string()),
+        }
+    }
+
+    // Ports that cannot be read stay a condition, so the rule does not look like it
+    // accepts the whole protocol
+    let protocol = rule.protocol.as_deref().and_then(|p| p.parse::<Protocol>().ok());
+    for spec in port_specs {
+        match protocol.and_then(|protocol| format!("{}/{}", spec, protocol).parse::<Port>().ok()) {
+            Some(port) => rule.ports.push(port),
+            None => rule.other.push(format!("--dport {}", spec)),
+        }
+    }
+    rule
+}
+
+/// Words after "-A INPUT" that open `change.port` for `change.source`
+fn open_spec(change: &FirewallChange) -> Vec<String> {
+    let mut spec = Vec::new();
+    if let Some(source) = &change.source {
+        spec.extend(words(&["-s", source]));
+    }
+    let protocol = change.port.protocol.to_string();
+    spec.extend(words(&["-p", &protocol, "-m", &protocol, "--dport", &change.port.range(":")]));
+    if let Some(comment) = &change.comment {
+        spec.extend(words(&["-m", "comment", "--comment", comment]));
+    }
+    spec.extend(words(&["-j", "ACCEPT"]));
+    spec
+}
+
+fn tool(table: &str) -> &'static str {
+    if table.starts_with("ip6 ") { "ip6tables" } else { "iptables" }
+}
+
+#[async_trait::async_trait]
+impl FirewallBackend for Iptables {
+    async fn is_active(&self) -> bool {
+        self.transport.run("iptables-save", &[]).await
+            .is_ok_and(|output| output.success() && output.stdout.lines().any(|line| line.starts_with("-A ")))
+    }
+
+    async fn ruleset(&self) -> Result<Ruleset> {
+        let mut ruleset = Ruleset::new(Frontend::Iptables);
+        for (family, save) in FAMILIES {
+            let output = self.transport.run(save, &[]).await;
+            match output {
+                Ok(output) if output.success() => ruleset.chains.extend(parse_save(family, &output.stdout)),
+                // Hosts without IPv6 support have no ip6tables
+                _ if *family == "ip6" => {}
+                Ok(output) => return Err(anyhow::anyhow!("{} failed: {}", save, output.stderr.trim())),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(ruleset)
+    }
+
+    fn plan(&self, ruleset: &Ruleset, change: &FirewallChange) -> Result<FirewallPlan> {
+        let mut after = ruleset.clone();
+        let mut commands = Vec::new();
+        let mut revert = Vec::new();
+
+        match change.action {
+            ChangeAction::Open => {
+                check_not_open(ruleset, change)?;
+                let v6 = change.source.as_deref().map(|source| source.contains(':'));
+                let spec = open_spec(change);
+                for (index, chain) in ruleset.chains.iter().enumerate() {
+                    let family_matches = match v6 {
+                        Some(v6) => chain.table.starts_with("ip6 ") == v6,
+                        None => true,
+                    };
+                    if chain.hook.as_deref() != Some("input") || !family_matches {
+                        continue;
+                    }
+                    let mut command = words(&[tool(&chain.table), "-I", &chain.name]);
+                    command.extend(spec.iter().cloned());
+                    let mut undo = words(&[tool(&chain.table), "-D", &chain.name]);
+                    undo.extend(spec.iter().cloned());
+                    commands.push(command);
+                    revert.push(shell_line(&undo));
+
+                    let mut rule = parse_rule(&save_line(&spec));
+                    rule.handle = Some(1);
+                    after.chains[index].rules.insert(0, rule);
+                }
+                if commands.is_empty() {
+                    return Err(anyhow::anyhow!("No INPUT chain found to add the rule to"));
+                }
+            }
+            ChangeAction::Close => {
+                // Later positions first, so earlier ones stay valid while deleting
+                let mut matches: Vec<(usize, usize)> = ruleset.chains.iter()
+                    .enumerate()
+                    .flat_map(|(index, chain)| chain.rules.iter().enumerate().map(move |(position, rule)| (index, position, rule)))
+                    .filter(|(index, _, rule)| ruleset.chains[*index].hook.as_deref() == Some("input")
+                        && rule.opens_ports() && rule.source == change.source
+                        && rule.ports.contains(&change.port))
+                    .map(|(index, position, _)| (index, position))
+                    .collect();
+                if matches.is_empty() {
+                    return Err(not_open(ruleset, change));
+                }
+                for (index, position) in &matches {
+                    let rule = &ruleset.chains[*index].rules[*position];
+                    check_single_port(&rule.ports, change, &rule.text)?;
+                }
+
+                matches.sort_by(|a, b| b.cmp(a));
+                for (index, position) in &matches {
+                    let chain = &ruleset.chains[*index];
+                    commands.push(words(&[tool(&chain.table), "-D", &chain.name, &(position + 1).to_string()]));
+                    after.chains[*index].rules.remove(*position);
+                }
+                // Reinserted in the original order, each at its old position
+                for (index, position) in matches.iter().rev() {
+                    let chain = &ruleset.chains[*index];
+                    let mut restore = words(&[tool(&chain.table), "-I", &chain.name, &(position + 1).to_string()]);
+                    restore.extend(split_spec(&chain.rules[*position].text));
+                    revert.push(shell_line(&restore));
+                }
+            }
+        }
+
+        Ok(FirewallPlan { change: change.clone(), commands, revert, after, persistent: false })
+    }
+}
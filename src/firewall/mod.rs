@@ -0,0 +1,748 @@
+//! Firewall frontends.
+//!
+//! nftables, iptables, ufw and firewalld describe the same filtering in different
+//! terms. Each backend parses the active one into a common model of zones, chains and
+//! rules, so open ports can be listed and explained the same way everywhere. Like the
+//! package and service backends, changes are only planned as command lines for the
+//! executor to run, together with the commands that revert them.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::remote::transport::{shell_quote, Transport};
+
+pub mod firewalld;
+pub mod iptables;
+pub mod nftables;
+pub mod ufw;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Frontend {
+    Nftables,
+    Iptables,
+    Ufw,
+    Firewalld,
+}
+
+impl fmt::Display for Frontend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Frontend::Nftables => write!(f, "nftables"),
+            Frontend::Iptables => write!(f, "iptables"),
+            Frontend::Ufw => write!(f, "ufw"),
+            Frontend::Firewalld => write!(f, "firewalld"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Protocol::Tcp => write!(f, "tcp"),
+            Protocol::Udp => write!(f, "udp"),
+        }
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "tcp" => Ok(Protocol::Tcp),
+            "udp" => Ok(Protocol::Udp),
+            _ => Err(anyhow::anyhow!("Unknown protocol '{}'. Use tcp or udp", s)),
+        }
+    }
+}
+
+/// A port or port range of one protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Port {
+    pub start: u16,
+    pub end: u16,
+    pub protocol: Protocol,
+}
+
+impl Port {
+    pub fn new(port: u16, protocol: Protocol) -> Self {
+        Self { start: port, end: port, protocol }
+    }
+
+    pub fn covers(&self, other: &Port) -> bool {
+        self.protocol == other.protocol && self.start <= other.start && other.end <= self.end
+    }
+
+    /// "22" or "6000-6010", as nftables and firewalld write ranges
+    pub fn range(&self, separator: &str) -> String {
+        if self.start == self.end {
+            self.start.to_string()
+        } else {
+            format!("{}{}{}", self.start, separator, self.end)
+        }
+    }
+
+    /// Service name people know the port by, such as "ssh"
+    pub fn service_name(&self) -> Option<&'static str> {
+        SERVICES.iter()
+            .find(|(_, ports)| ports.len() == 1 && ports[0] == (self.start, self.protocol) && self.start == self.end)
+            .map(|(name, _)| *name)
+    }
+}
+
+/// "443/tcp" or "6000-6010/udp"
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.range("-"), self.protocol)
+    }
+}
+
+/// "443/tcp", "443" (TCP), "6000-6010/udp", "6000:6010/udp" or a service name such as "https"
+impl FromStr for Port {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (range, protocol) = match s.split_once('/') {
+            Some((range, protocol)) => (range, protocol.parse()?),
+            None => match service_ports(s).as_slice() {
+                [port] => return Ok(*port),
+                [] => (s, Protocol::Tcp),
+                _ => return Err(anyhow::anyhow!("'{}' uses several ports; give one as PORT/PROTOCOL", s)),
+            },
+        };
+        let parse = |port: &str| port.trim().parse::<u16>()
+            .ok()
+            .filter(|port| *port > 0)
+            .ok_or_else(|| anyhow::anyhow!("Invalid port '{}'", s));
+        let (start, end) = match range.split_once(['-', ':']) {
+            Some((start, end)) => (parse(start)?, parse(end)?),
+            None => (parse(range)?, parse(range)?),
+        };
+        if start > end {
+            return Err(anyhow::anyhow!("Invalid port range '{}'", s));
+        }
+        Ok(Self { start, end, protocol })
+    }
+}
+
+/// Service names used by firewalld services, ufw application profiles and /etc/services
+const SERVICES: &[(&str, &[(u16, Protocol)])] = &[
+    ("ssh", &[(22, Protocol::Tcp)]),
+    ("openssh", &[(22, Protocol::Tcp)]),
+    ("http", &[(80, Protocol::Tcp)]),
+    ("https", &[(443, Protocol::Tcp)]),
+    ("dns", &[(53, Protocol::Tcp), (53, Protocol::Udp)]),
+    ("dhcp", &[(67, Protocol::Udp)]),
+    ("dhcpv6-client", &[(546, Protocol::Udp)]),
+    ("mdns", &[(5353, Protocol::Udp)]),
+    ("ntp", &[(123, Protocol::Udp)]),
+    ("smtp", &[(25, Protocol::Tcp)]),
+    ("imaps", &[(993, Protocol::Tcp)]),
+    ("mysql", &[(3306, Protocol::Tcp)]),
+    ("postgresql", &[(5432, Protocol::Tcp)]),
+    ("cockpit", &[(9090, Protocol::Tcp)]),
+    ("nginx http", &[(80, Protocol::Tcp)]),
+    ("nginx https", &[(443, Protocol::Tcp)]),
+    ("nginx full", &[(80, Protocol::Tcp), (443, Protocol::Tcp)]),
+    ("apache", &[(80, Protocol::Tcp)]),
+    ("apache secure", &[(443, Protocol::Tcp)]),
+    ("apache full", &[(80, Protocol::Tcp), (443, Protocol::Tcp)]),
+];
+
+/// Ports of a well-known service, empty when the name is not known
+pub fn service_ports(name: &str) -> Vec<Port> {
+    let name = name.to_lowercase();
+    SERVICES.iter()
+        .find(|(service, _)| *service == name)
+        .map(|(_, ports)| ports.iter().map(|(port, protocol)| Port::new(*port, *protocol)).collect())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Verdict {
+    Accept,
+    Drop,
+    Reject,
+    Return,
+    /// Continue in another chain
+    Jump(String),
+    /// Anything else, such as logging or NAT targets
+    Other(String),
+}
+
+impl Verdict {
+    /// Policy or target names as the frontends print them, e.g. "DROP", "deny" or "accept"
+    pub fn parse(name: &str) -> Self {
+        match name.to_lowercase().as_str() {
+            "accept" | "allow" => Verdict::Accept,
+            "drop" | "deny" => Verdict::Drop,
+            "reject" => Verdict::Reject,
+            "return" => Verdict::Return,
+            _ => Verdict::Other(name.to_string()),
+        }
+    }
+
+    fn blocks(&self) -> bool {
+        matches!(self, Verdict::Drop | Verdict::Reject)
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verdict::Accept => write!(f, "accept"),
+            Verdict::Drop => write!(f, "drop"),
+            Verdict::Reject => write!(f, "reject"),
+            Verdict::Return => write!(f, "return"),
+            Verdict::Jump(target) => write!(f, "jump {}", target),
+            Verdict::Other(other) => write!(f, "{}", other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rule {
+    pub verdict: Verdict,
+    /// Layer 4 protocol the rule matches, such as "tcp" or "icmp"
+    pub protocol: Option<String>,
+    /// Destination ports
+    pub ports: Vec<Port>,
+    pub source: Option<String>,
+    /// Incoming interface
+    pub interface: Option<String>,
+    /// Connection tracking states, such as "established"
+    pub states: Vec<String>,
+    pub comment: Option<String>,
+    /// Matches that are not part of the model, in the frontend's syntax
+    pub other: Vec<String>,
+    /// The rule in the frontend's own syntax
+    pub text: String,
+    /// nftables handle or iptables position, for deleting the rule
+    pub handle: Option<u64>,
+}
+
+impl Rule {
+    fn new(verdict: Verdict, text: String) -> Self {
+        Self {
+            verdict,
+            protocol: None,
+            ports: Vec::new(),
+            source: None,
+            interface: None,
+            states: Vec::new(),
+            comment: None,
+            other: Vec::new(),
+            text,
+            handle: None,
+        }
+    }
+
+    /// Accepts new connections to its ports from its source, and nothing narrower
+    fn opens_ports(&self) -> bool {
+        self.verdict == Verdict::Accept && !self.ports.is_empty() && self.states.is_empty()
+            && self.other.is_empty() && self.interface.as_deref() != Some("lo")
+    }
+
+    /// Matches every packet, such as a final "drop" or "accept"
+    fn matches_everything(&self) -> bool {
+        self.protocol.is_none() && self.ports.is_empty() && self.source.is_none()
+            && self.interface.is_none() && self.states.is_empty() && self.other.is_empty()
+    }
+
+    /// "Allow 443/tcp from 10.0.0.0/8 on eth0 (web)"
+    pub fn describe(&self) -> String {
+        let action = match &self.verdict {
+            Verdict::Accept => "Allow".to_string(),
+            Verdict::Drop => "Drop".to_string(),
+            Verdict::Reject => "Reject".to_string(),
+            Verdict::Return => "Return from the chain for".to_string(),
+            Verdict::Jump(target) => format!("Continue in chain {} for", target),
+            Verdict::Other(other) => format!("Apply {} to", other),
+        };
+        let what = if !self.ports.is_empty() {
+            self.ports.iter().map(Port::to_string).collect::<Vec<_>>().join(", ")
+        } else if !self.states.is_empty() {
+            format!("{} connections", self.states.join(" and "))
+        } else if let Some(protocol) = &self.protocol {
+            format!("{} traffic", protocol)
+        } else {
+            "all traffic".to_string()
+        };
+
+        let mut description = format!("{} {}", action, what);
+        if let Some(source) = &self.source {
+            description.push_str(&format!(" from {}", source));
+        }
+        if let Some(interface) = &self.interface {
+            description.push_str(&format!(" on {}", interface));
+        }
+        if !self.other.is_empty() {
+            description.push_str(&format!(" when {}", self.other.join(" ")));
+        }
+        if let Some(comment) = &self.comment {
+            description.push_str(&format!(" ({})", comment));
+        }
+        description
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chain {
+    /// Address family and table, such as "inet filter"
+    pub table: String,
+    pub name: String,
+    /// Where packets enter the chain: "input", "forward" or "output"; `None` for
+    /// chains only reached by a jump
+    pub hook: Option<String>,
+    /// What happens to packets no rule decides on
+    pub policy: Option<Verdict>,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Zone {
+    pub name: String,
+    pub default: bool,
+    pub active: bool,
+    /// firewalld target for traffic no service or port allows: "default", "ACCEPT",
+    /// "DROP" or "REJECT"
+    pub target: String,
+    pub interfaces: Vec<String>,
+    pub sources: Vec<String>,
+    pub services: Vec<String>,
+    pub ports: Vec<Port>,
+    pub rich_rules: Vec<String>,
+    /// Ports of the zone's services, from firewalld's service definitions
+    #[serde(default)]
+    pub service_ports: BTreeMap<String, Vec<Port>>,
+}
+
+impl Zone {
+    /// Ports the zone's services and port list open, with the service that opens each
+    pub fn open_ports(&self) -> Vec<(Port, Option<String>)> {
+        let mut ports: Vec<(Port, Option<String>)> = self.services.iter()
+            .flat_map(|service| self.ports_of(service).into_iter().map(move |port| (port, Some(service.clone()))))
+            .collect();
+        ports.extend(self.ports.iter().map(|port| (*port, None)));
+        ports
+    }
+
+    pub fn ports_of(&self, service: &str) -> Vec<Port> {
+        self.service_ports.get(service).cloned().unwrap_or_else(|| service_ports(service))
+    }
+
+    fn fallback(&self) -> Verdict {
+        match self.target.to_uppercase().as_str() {
+            "ACCEPT" => Verdict::Accept,
+            "DROP" => Verdict::Drop,
+            _ => Verdict::Reject,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct OpenPort {
+    pub port: Port,
+    /// Address or network allowed to connect, `None` for everyone
+    pub source: Option<String>,
+    /// What opens it: a zone, service, chain or rule comment
+    pub via: String,
+}
+
+/// "443/tcp (https) from everyone, via zone public"
+impl fmt::Display for OpenPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.port)?;
+        if let Some(name) = self.port.service_name() {
+            write!(f, " ({})", name)?;
+        }
+        write!(f, " from {}, via {}", self.source.as_deref().unwrap_or("everyone"), self.via)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ruleset {
+    pub frontend: Frontend,
+    /// firewalld zones; empty for the other frontends
+    pub zones: Vec<Zone>,
+    pub chains: Vec<Chain>,
+}
+
+impl Ruleset {
+    pub fn new(frontend: Frontend) -> Self {
+        Self { frontend, zones: Vec::new(), chains: Vec::new() }
+    }
+
+    fn chain(&self, table: &str, name: &str) -> Option<&Chain> {
+        self.chains.iter().find(|chain| chain.table == table && chain.name == name)
+    }
+
+    pub fn input_chains(&self) -> impl Iterator<Item = &Chain> {
+        self.chains.iter().filter(|chain| chain.hook.as_deref() == Some("input"))
+    }
+
+    pub fn default_zone(&self) -> Option<&Zone> {
+        self.zones.iter().find(|zone| zone.default)
+    }
+
+    /// Rules that decide on incoming packets, following jumps, with the chain each is in
+    pub fn incoming_rules(&self) -> Vec<(&Chain, &Rule)> {
+        let mut rules = Vec::new();
+        let mut visited = BTreeSet::new();
+        for chain in self.input_chains() {
+            self.collect_rules(chain, &mut visited, &mut rules);
+        }
+        rules
+    }
+
+    fn collect_rules<'a>(&'a self, chain: &'a Chain, visited: &mut BTreeSet<(String, String)>, rules: &mut Vec<(&'a Chain, &'a Rule)>) {
+        if !visited.insert((chain.table.clone(), chain.name.clone())) {
+            return;
+        }
+        for rule in &chain.rules {
+            rules.push((chain, rule));
+            if let Verdict::Jump(target) = &rule.verdict {
+                if let Some(target) = self.chain(&chain.table, target) {
+                    self.collect_rules(target, visited, rules);
+                }
+            }
+        }
+    }
+
+    pub fn open_ports(&self) -> Vec<OpenPort> {
+        let mut open = BTreeSet::new();
+        for zone in self.zones.iter().filter(|zone| zone.active) {
+            for (port, service) in zone.open_ports() {
+                let via = match service {
+                    Some(service) => format!("zone {}, service {}", zone.name, service),
+                    None => format!("zone {}", zone.name),
+                };
+                open.insert(OpenPort { port, source: None, via });
+            }
+        }
+        for (chain, rule) in self.incoming_rules() {
+            if !rule.opens_ports() {
+                continue;
+            }
+            let via = match &rule.comment {
+                Some(comment) => format!("{} {} ({})", chain.table, chain.name, comment),
+                None => format!("{} {}", chain.table, chain.name),
+            };
+            for port in &rule.ports {
+                open.insert(OpenPort { port: *port, source: rule.source.clone(), via: via.clone() });
+            }
+        }
+        open.into_iter().collect()
+    }
+
+    /// Whether everyone can connect to `port` from outside
+    pub fn reachable(&self, port: &Port) -> bool {
+        if !self.zones.is_empty() {
+            return self.zones.iter()
+                .filter(|zone| zone.active)
+                .any(|zone| zone.fallback() == Verdict::Accept
+                    || zone.open_ports().iter().any(|(open, _)| open.covers(port)));
+        }
+
+        // Every input hook sees the packet, so each of them has to let it through
+        self.input_chains().all(|chain| {
+            let mut visited = BTreeSet::new();
+            let mut rules = Vec::new();
+            self.collect_rules(chain, &mut visited, &mut rules);
+            for (_, rule) in rules {
+                if rule.opens_ports() && rule.source.is_none() && rule.ports.iter().any(|open| open.covers(port)) {
+                    return true;
+                }
+                if rule.verdict.blocks() && rule.source.is_none() && rule.interface.is_none()
+                    && (rule.matches_everything() || rule.ports.iter().any(|blocked| blocked.covers(port))) {
+                    return false;
+                }
+            }
+            !chain.policy.as_ref().is_some_and(Verdict::blocks)
+        })
+    }
+
+    /// The ruleset in plain sentences
+    pub fn explain(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        if let Some(zone) = self.default_zone() {
+            lines.push(format!("Interfaces without a zone of their own use the default zone {}.", zone.name));
+        }
+        for zone in self.zones.iter().filter(|zone| zone.active) {
+            let bound: Vec<&String> = zone.interfaces.iter().chain(&zone.sources).collect();
+            if !bound.is_empty() {
+                lines.push(format!("Zone {} applies to {}.", zone.name,
+                    bound.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(", ")));
+            }
+            let fallback = match zone.fallback() {
+                Verdict::Accept => "accepted",
+                Verdict::Drop => "silently dropped",
+                _ => "rejected",
+            };
+            lines.push(format!("In zone {}, connections that no service or port allows are {}.", zone.name, fallback));
+            for rich_rule in &zone.rich_rules {
+                lines.push(format!("Zone {} also has the rich rule: {}", zone.name, rich_rule));
+            }
+        }
+
+        let input_chains: Vec<&Chain> = self.input_chains().collect();
+        if self.zones.is_empty() && input_chains.is_empty() {
+            lines.push("No chain filters incoming traffic, so every listening port is reachable.".to_string());
+        }
+        for chain in &input_chains {
+            let fallback = match &chain.policy {
+                Some(Verdict::Drop) => "dropped",
+                Some(Verdict::Reject) => "rejected",
+                _ => "accepted",
+            };
+            lines.push(format!("Incoming packets no rule in {} {} decides on are {}.", chain.table, chain.name, fallback));
+        }
+
+        let incoming = self.incoming_rules();
+        if incoming.iter().any(|(_, rule)| rule.verdict == Verdict::Accept && rule.states.iter().any(|s| s == "established")) {
+            lines.push("Replies to connections this machine opens are let back in.".to_string());
+        }
+        for (_, rule) in &incoming {
+            if rule.verdict.blocks() || (rule.verdict == Verdict::Accept && !rule.opens_ports() && rule.states.is_empty()) {
+                lines.push(format!("{}.", rule.describe()));
+            }
+        }
+
+        for open in self.open_ports() {
+            lines.push(format!("{} is open to {} ({}).", open.port, open.source.as_deref().unwrap_or("everyone"), open.via));
+        }
+
+        let ssh = Port::new(22, Protocol::Tcp);
+        if !self.reachable(&ssh) && !self.open_ports().iter().any(|open| open.port.covers(&ssh)) {
+            lines.push("SSH (22/tcp) is not allowed in, so remote logins fail unless sshd listens on another open port.".to_string());
+        }
+        lines
+    }
+
+    /// One line per zone setting, chain and rule, for previews
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for zone in &self.zones {
+            let mut flags = Vec::new();
+            if zone.default {
+                flags.push("default");
+            }
+            if zone.active {
+                flags.push("active");
+            }
+            let flags = if flags.is_empty() { String::new() } else { format!(" ({})", flags.join(", ")) };
+            lines.push(format!("zone {}{}: target {}", zone.name, flags, zone.target));
+            lines.extend(zone.interfaces.iter().map(|interface| format!("  interface {}", interface)));
+            lines.extend(zone.sources.iter().map(|source| format!("  source {}", source)));
+            lines.extend(zone.services.iter().map(|service| format!("  service {}", service)));
+            lines.extend(zone.ports.iter().map(|port| format!("  port {}", port)));
+            lines.extend(zone.rich_rules.iter().map(|rule| format!("  rich rule {}", rule)));
+        }
+        for chain in &self.chains {
+            let mut header = format!("chain {} {}", chain.table, chain.name);
+            match (&chain.hook, &chain.policy) {
+                (Some(hook), Some(policy)) => header.push_str(&format!(" (hook {}, policy {})", hook, policy)),
+                (Some(hook), None) => header.push_str(&format!(" (hook {})", hook)),
+                _ => {}
+            }
+            lines.push(header);
+            lines.extend(chain.rules.iter().map(|rule| format!("  {}", rule.text)));
+        }
+        lines
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ChangeAction {
+    Open,
+    Close,
+}
+
+/// A minimal change: one port, optionally only for one source
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FirewallChange {
+    pub action: ChangeAction,
+    pub port: Port,
+    pub source: Option<String>,
+    /// Why the port is opened, kept as the rule's comment
+    pub comment: Option<String>,
+}
+
+/// "open 443/tcp from 10.0.0.0/8 (web)"
+impl fmt::Display for FirewallChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.action {
+            ChangeAction::Open => write!(f, "open {}", self.port)?,
+            ChangeAction::Close => write!(f, "close {}", self.port)?,
+        }
+        if let Some(source) = &self.source {
+            write!(f, " from {}", source)?;
+        }
+        if let Some(comment) = &self.comment {
+            write!(f, " ({})", comment)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FirewallPlan {
+    pub change: FirewallChange,
+    /// Command lines that make the change, without a sudo prefix
+    pub commands: Vec<Vec<String>>,
+    /// Shell command lines that undo it, for the revert timer and the undo hint
+    pub revert: Vec<String>,
+    /// The ruleset as it will look afterwards
+    pub after: Ruleset,
+    /// Whether the change survives a reboot or a reload of the firewall
+    pub persistent: bool,
+}
+
+#[async_trait::async_trait]
+pub trait FirewallBackend: std::fmt::Debug + Send + Sync {
+    /// Whether this frontend is installed and currently filtering
+    async fn is_active(&self) -> bool;
+
+    async fn ruleset(&self) -> Result<Ruleset>;
+
+    /// Commands that make `change` to `ruleset`, or why it cannot be made
+    fn plan(&self, ruleset: &Ruleset, change: &FirewallChange) -> Result<FirewallPlan>;
+}
+
+/// The frontend managing the firewall on the machine behind `transport`. The
+/// management tools come first because they program nftables or iptables themselves.
+pub async fn detect(transport: Arc<dyn Transport>) -> Option<Box<dyn FirewallBackend>> {
+    let candidates: Vec<Box<dyn FirewallBackend>> = vec![
+        Box::new(firewalld::Firewalld::new(transport.clone())),
+        Box::new(ufw::Ufw::new(transport.clone())),
+        Box::new(nftables::Nftables::new(transport.clone())),
+        Box::new(iptables::Iptables::new(transport)),
+    ];
+    for candidate in candidates {
+        if candidate.is_active().await {
+            return Some(candidate);
+        }
+    }
+    None
+}
+
+/// Whether a firewall command line only reads the configuration
+pub fn is_query(command: &str, args: &[String]) -> bool {
+    match command {
+        "iptables" | "ip6tables" => args.iter().any(|arg| matches!(arg.as_str(), "-L" | "-S" | "--list" | "--list-rules"))
+            && !args.iter().any(|arg| matches!(arg.as_str(), "-Z" | "--zero")),
+        "iptables-save" | "ip6tables-save" => true,
+        "nft" => args.iter().find(|arg| !arg.starts_with('-')).is_some_and(|arg| arg == "list"),
+        "ufw" => args.first().is_some_and(|arg| matches!(arg.as_str(), "status" | "show" | "app" | "version")),
+        "firewall-cmd" => {
+            let reads = |arg: &String| ["--get-", "--list-", "--query-", "--info-", "--state"].iter().any(|prefix| arg.starts_with(prefix));
+            args.iter().any(reads) && args.iter().all(|arg| reads(arg) || arg.starts_with("--zone=") || arg == "--permanent")
+        }
+        _ => false,
+    }
+}
+
+/// Why a firewall command line is too dangerous to run, for commands that drop
+/// every rule or every connection at once
+pub fn destructive(command: &str, args: &[String]) -> Option<&'static str> {
+    let has = |flag: &str| args.iter().any(|arg| arg == flag);
+    match command {
+        "iptables" | "ip6tables" if has("-F") || has("--flush") => Some("Flushing chains removes every rule in them at once"),
+        "nft" if args.windows(2).any(|pair| pair[0] == "flush" && pair[1] == "ruleset") => {
+            Some("Flushing the ruleset removes every nftables rule at once")
+        }
+        "ufw" if has("reset") => Some("ufw reset deletes every rule and disables the firewall"),
+        "firewall-cmd" if has("--panic-on") => Some("Panic mode drops all traffic, including this SSH session"),
+        _ => None,
+    }
+}
+
+/// Lines of `before` and `after` marked with "  ", "- " or "+ ", from their longest
+/// common subsequence
+pub fn diff_lines(before: &[String], after: &[String]) -> Vec<String> {
+    let (n, m) = (before.len(), after.len());
+    let mut common = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            common[i][j] = if before[i] == after[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && before[i] == after[j] {
+            lines.push(format!("  {}", before[i]));
+            i += 1;
+            j += 1;
+        } else if j < m && (i == n || common[i][j + 1] >= common[i + 1][j]) {
+            lines.push(format!("+ {}", after[j]));
+            j += 1;
+        } else {
+            lines.push(format!("- {}", before[i]));
+            i += 1;
+        }
+    }
+    lines
+}
+
+/// Owned words of a command line
+fn words(words: &[&str]) -> Vec<String> {
+    words.iter().map(|word| word.to_string()).collect()
+}
+
+/// A command line the shell runs as the given words
+fn shell_line(words: &[String]) -> String {
+    words.iter().map(|word| shell_quote(word)).collect::<Vec<_>>().join(" ")
+}
+
+/// Closing only touches a rule that opens nothing but `change.port`
+fn check_single_port(rule_ports: &[Port], change: &FirewallChange, text: &str) -> Result<()> {
+    if rule_ports.len() > 1 {
+        let others: Vec<String> = rule_ports.iter()
+            .filter(|port| **port != change.port)
+            .map(Port::to_string)
+            .collect();
+        return Err(anyhow::anyhow!("{} is opened together with {} by `{}`; change that rule by hand",
+            change.port, others.join(", "), text));
+    }
+    Ok(())
+}
+
+/// Why there is nothing to close for `change`
+fn not_open(ruleset: &Ruleset, change: &FirewallChange) -> anyhow::Error {
+    if ruleset.reachable(&change.port) {
+        anyhow::anyhow!("No rule opens {}, it is reachable because nothing blocks it; \
+                         tuxpilot only removes rules that open ports", change.port)
+    } else {
+        anyhow::anyhow!("{} is not open", change.port)
+    }
+}
+
+/// Opening a port nothing blocks would only add a rule that changes nothing
+fn check_not_open(ruleset: &Ruleset, change: &FirewallChange) -> Result<()> {
+    let already = match &change.source {
+        Some(source) => ruleset.open_ports().iter()
+            .any(|open| open.port.covers(&change.port) && open.source.as_deref() == Some(source.as_str())),
+        None => ruleset.reachable(&change.port),
+    };
+    if already {
+        return Err(anyhow::anyhow!("{} is already open", change.port));
+    }
+    Ok(())
+}
@@ -0,0 +1,311 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::sync::Arc;
+
+use crate::remote::transport::Transport;
+use super::{check_not_open, check_single_port, not_open, shell_line, words};
+use super::{ChangeAction, Chain, FirewallBackend, FirewallChange, FirewallPlan, Frontend, Port, Protocol, Rule, Ruleset, Verdict};
+
+#[derive(Debug, Clone)]
+pub struct Nftables {
+    transport: Arc<dyn Transport>,
+}
+
+impl Nftables {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+}
+
+/// Parse `nft -j list ruleset`
+pub(crate) fn parse_ruleset(json: &str) -> Result<Ruleset> {
+    let value: Value = serde_json::from_str(json).context("Failed to parse nft JSON output")?;
+    let items = value["nftables"].as_array().context("nft JSON output has no ruleset")?;
+
+    let mut ruleset = Ruleset::new(Frontend::Nftables);
+    for item in items {
+        if let Some(chain) = item.get("chain") {
+            // Only base chains of type filter decide whether packets get through
+            let is_filter = chain["type"].as_str().is_none_or(|kind| kind == "filter");
+            ruleset.chains.push(Chain {
+                table: table_name(chain),
+                name: chain["name"].as_str().unwrap_or_default().to_string(),
+                hook: chain["hook"].as_str().filter(|_| is_filter).map(String::from),
+                policy: chain["policy"].as_str().map(Verdict::parse),
+                rules: Vec::new(),
+            });
+        } else if let Some(rule) = item.get("rule") {
+            let (table, name) = (table_name(rule), rule["chain"].as_str().unwrap_or_default());
+            if let Some(chain) = ruleset.chains.iter_mut().find(|chain| chain.table == table && chain.name == name) {
+                chain.rules.push(parse_rule(rule));
+            }
+        }
+    }
+    Ok(ruleset)
+}
+
+/// "inet filter"
+fn table_name(object: &Value) -> String {
+    format!("{} {}", object["family"].as_str().unwrap_or_default(), object["table"].as_str().unwrap_or_default())
+}
+
+fn parse_rule(rule: &Value) -> Rule {
+    let mut parsed = Rule::new(Verdict::Other("continue".to_string()), String::new());
+    let mut text = Vec::new();
+
+    for expression in rule["expr"].as_array().into_iter().flatten() {
+        let rendered = render(expression);
+        if let Some(condition) = expression.get("match") {
+            let (left, right) = (&condition["left"], &condition["right"]);
+            let negated = condition["op"].as_str() == Some("!=");
+            match (left.get("payload"), left.get("meta"), left.get("ct")) {
+                _ if negated => parsed.other.push(rendered.clone()),
+                (Some(payload), _, _) if payload["field"] == "dport" => {
+                    let protocol = payload["protocol"].as_str().and_then(|p| p.parse::<Protocol>().ok());
+                    match protocol {
+                        Some(protocol) => {
+                            parsed.protocol = Some(protocol.to_string());
+                            parsed.ports = ports(right, protocol);
+                            // Ports that cannot be read stay a condition, so the rule
+                            // does not look like it accepts the whole protocol
+                            if parsed.ports.is_empty() {
+                                parsed.other.push(rendered.clone());
+                            }
+                        }
+                        None => parsed.other.push(rendered.clone()),
+                    }
+                }
+                (Some(payload), _, _) if payload["field"] == "saddr" => parsed.source = Some(value_text(right)),
+                (_, Some(meta), _) if meta["key"] == "l4proto" || meta["key"] == "protocol" => {
+                    parsed.protocol = Some(value_text(right));
+                }
+                (_, Some(meta), _) if meta["key"] == "iifname" || meta["key"] == "iif" => {
+                    parsed.interface = Some(value_text(right));
+                }
+                (_, _, Some(ct)) if ct["key"] == "state" => parsed.states = list(right),
+                _ => parsed.other.push(rendered.clone()),
+            }
+        } else if expression.get("accept").is_some() {
+            parsed.verdict = Verdict::Accept;
+        } else if expression.get("drop").is_some() {
+            parsed.verdict = Verdict::Drop;
+        } else if expression.get("reject").is_some() {
+            parsed.verdict = Verdict::Reject;
+        } else if expression.get("return").is_some() {
+            parsed.verdict = Verdict::Return;
+        } else if let Some(jump) = expression.get("jump").or_else(|| expression.get("goto")) {
+            parsed.verdict = Verdict::Jump(jump["target"].as_str().unwrap_or_default().to_string());
+        } else if expression.get("counter").is_none() && expression.get("log").is_none() {
+            parsed.other.push(rendered.clone());
+        }
+        text.push(rendered);
+    }
+
+    parsed.comment = rule["comment"].as_str().map(String::from);
+    if let Some(comment) = &parsed.comment {
+        text.push(format!("comment \"{}\"", comment));
+    }
+    parsed.text = text.join(" ");
+    parsed.handle = rule["handle"].as_u64();
+    parsed
+}
+
+fn ports(value: &Value, protocol: Protocol) -> Vec<Port> {
+    match value {
+        Value::Number(port) => port.as_u64()
+            .and_then(|port| u16::try_from(port).ok())
+            .map(|port| vec![Port::new(port, protocol)])
+            .unwrap_or_default(),
+        Value::String(name) => super::service_ports(name).into_iter().filter(|port| port.protocol == protocol).collect(),
+        Value::Object(object) if object.contains_key("range") => {
+            let bounds: Vec<u16> = object["range"].as_array().into_iter().flatten()
+                .filter_map(|bound| bound.as_u64().and_then(|bound| u16::try_from(bound).ok()))
+                .collect();
+            match bounds.as_slice() {
+                [start, end] if start <= end => vec![Port { start: *start, end: *end, protocol }],
+                _ => Vec::new(),
+            }
+        }
+        Value::Object(object) if object.contains_key("set") => object["set"].as_array().into_iter().flatten()
+            .flat_map(|element| ports(element, protocol))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Flags such as ct state come as a string or a list of strings
+fn list(value: &Value) -> Vec<String> {
+    match value {
+        Value::Array(values) => values.iter().map(value_text).collect(),
+        Value::Object(object) if object.contains_key("set") => list(&object["set"]),
+        _ => vec![value_text(value)],
+    }
+}
+
+/// Right-hand side of a match in nft syntax
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Number(number) => number.to_string(),
+        Value::Array(values) => values.iter().map(value_text).collect::<Vec<_>>().join(","),
+        Value::Object(object) if object.contains_key("set") => {
+            format!("{{ {} }}", object["set"].as_array().into_iter().flatten().map(value_text).collect::<Vec<_>>().join(", "))
+        }
+        Value::Object(object) if object.contains_key("range") => {
+            object["range"].as_array().into_iter().flatten().map(value_text).collect::<Vec<_>>().join("-")
+        }
+        Value::Object(object) if object.contains_key("prefix") => {
+            format!("{}/{}", value_text(&object["prefix"]["addr"]), value_text(&object["prefix"]["len"]))
+        }
+        other => other.to_string(),
+    }
+}
+
+/// One expression in nft syntax, close enough to `nft list ruleset` to recreate
+/// the rules tuxpilot changes
+fn render(expression: &Value) -> String {
+    if let Some(condition) = expression.get("match") {
+        let left = &condition["left"];
+        let key = if let Some(payload) = left.get("payload") {
+            format!("{} {}", payload["protocol"].as_str().unwrap_or_default(), payload["field"].as_str().unwrap_or_default())
+        } else if let Some(meta) = left.get("meta") {
+            let key = meta["key"].as_str().unwrap_or_default();
+            if ["iifname", "oifname", "iif", "oif"].contains(&key) { key.to_string() } else { format!("meta {}", key) }
+        } else if let Some(ct) = left.get("ct") {
+            format!("ct {}", ct["key"].as_str().unwrap_or_default())
+        } else {
+            left.to_string()
+        };
+        let right = match (&condition["right"], key.ends_with("ifname")) {
+            (Value::String(name), true) => format!("\"{}\"", name),
+            (right, _) => value_text(right),
+        };
+        return match condition["op"].as_str() {
+            Some("==") | Some("in") | None => format!("{} {}", key, right),
+            Some(op) => format!("{} {} {}", key, op, right),
+        };
+    }
+
+    let Some((name, argument)) = expression.as_object().and_then(|object| object.iter().next()) else {
+        return expression.to_string();
+    };
+    match name.as_str() {
+        "accept" | "drop" | "return" | "counter" | "log" => name.clone(),
+        "reject" => "reject".to_string(),
+        "jump" | "goto" => format!("{} {}", name, argument["target"].as_str().unwrap_or_default()),
+        _ => expression.to_string(),
+    }
+}
+
+/// "inet filter" and "input" as separate words for nft
+fn chain_words(chain: &Chain) -> Vec<&str> {
+    chain.table.split_whitespace().chain(std::iter::once(chain.name.as_str())).collect()
+}
+
+#[async_trait::async_trait]
+impl FirewallBackend for Nftables {
+    async fn is_active(&self) -> bool {
+        self.transport.run("nft", &["list", "tables"]).await
+            .is_ok_and(|output| output.success() && !output.stdout.trim().is_empty())
+    }
+
+    async fn ruleset(&self) -> Result<Ruleset> {
+        let output = self.transport.run("nft", &["-j", "list", "ruleset"]).await?;
+        if !output.success() {
+            return Err(anyhow::anyhow!("nft list ruleset failed: {}", output.stderr.trim()));
+        }
+        parse_ruleset(&output.stdout)
+    }
+
+    fn plan(&self, ruleset: &Ruleset, change: &FirewallChange) -> Result<FirewallPlan> {
+        let mut after = ruleset.clone();
+        let (commands, revert) = match change.action {
+            ChangeAction::Open => {
+                check_not_open(ruleset, change)?;
+                // The inet family covers IPv4 and IPv6 at once, so prefer it
+                let index = ruleset.chains.iter()
+                    .enumerate()
+                    .filter(|(_, chain)| chain.hook.as_deref() == Some("input"))
+                    .min_by_key(|(_, chain)| !chain.table.starts_with("inet "))
+                    .map(|(index, _)| index)
+                    .context("No nftables chain filters incoming traffic")?;
+                let chain = &ruleset.chains[index];
+
+                // Tagged so the revert can find the rule's handle, which is only known
+                // once it exists
+                let tag = format!("tuxpilot-{}", &uuid::Uuid::new_v4().simple().to_string()[..8]);
+                let comment = match &change.comment {
+                    Some(comment) => format!("{} {}", tag, comment.replace('"', "'")),
+                    None => tag.clone(),
+                };
+
+                let family = if change.source.as_deref().is_some_and(|source| source.contains(':')) { "ip6" } else { "ip" };
+                let mut rule = Vec::new();
+                if let Some(source) = &change.source {
+                    rule.extend(words(&[family, "saddr", source]));
+                }
+                rule.extend(words(&[&change.port.protocol.to_string(), "dport", &change.port.range("-"), "accept"]));
+                rule.extend(words(&["comment", &format!("\"{}\"", comment)]));
+
+                let mut command = words(&["nft", "insert", "rule"]);
+                command.extend(chain_words(chain).into_iter().map(String::from));
+                command.extend(rule.iter().cloned());
+
+                let mut list = words(&["nft", "-a", "list", "chain"]);
+                list.extend(chain_words(chain).into_iter().map(String::from));
+                let mut delete = words(&["nft", "delete", "rule"]);
+                delete.extend(chain_words(chain).into_iter().map(String::from));
+                let (list, delete) = (shell_line(&list), shell_line(&delete));
+                let revert = format!(
+                    "{list} | sed -n 's/.*\"{tag}.*# handle \\([0-9]*\\)$/\\1/p' \
+                     | while read -r handle; do {delete} handle \"$handle\"; done");
+
+                let mut added = Rule::new(Verdict::Accept, rule.join(" "));
+                added.protocol = Some(change.port.protocol.to_string());
+                added.ports = vec![change.port];
+                added.source = change.source.clone();
+                added.comment = Some(comment);
+                after.chains[index].rules.insert(0, added);
+
+                (vec![command], vec![revert])
+            }
+            ChangeAction::Close => {
+                let (index, position) = ruleset.chains.iter()
+                    .enumerate()
+                    .flat_map(|(index, chain)| chain.rules.iter().enumerate().map(move |(position, rule)| (index, position, rule)))
+                    .find(|(_, _, rule)| rule.opens_ports() && rule.source == change.source
+                        && rule.ports.contains(&change.port))
+                    .map(|(index, position, _)| (index, position))
+                    .ok_or_else(|| not_open(ruleset, change))?;
+                let chain = &ruleset.chains[index];
+                let rule = &chain.rules[position];
+                check_single_port(&rule.ports, change, &rule.text)?;
+                let handle = rule.handle.context("nft did not report the rule's handle")?;
+
+                let mut command = words(&["nft", "delete", "rule"]);
+                command.extend(chain_words(chain).into_iter().map(String::from));
+                command.extend(words(&["handle", &handle.to_string()]));
+
+                // Put the rule back where it was: before the rule that followed it
+                let mut restore = words(&["nft"]);
+                match chain.rules.get(position + 1).and_then(|next| next.handle) {
+                    Some(next) => {
+                        restore.extend(words(&["insert", "rule"]));
+                        restore.extend(chain_words(chain).into_iter().map(String::from));
+                        restore.extend(words(&["position", &next.to_string()]));
+                    }
+                    None => {
+                        restore.extend(words(&["add", "rule"]));
+                        restore.extend(chain_words(chain).into_iter().map(String::from));
+                    }
+                }
+                restore.push(rule.text.clone());
+
+                after.chains[index].rules.remove(position);
+                (vec![command], vec![shell_line(&restore)])
+            }
+        };
+
+        Ok(FirewallPlan { change: change.clone(), commands, revert, after, persistent: false })
+    }
+}
@@ -0,0 +1,222 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::sync::Arc;
+
+use crate::remote::transport::Transport;
+use super::{check_not_open, check_single_port, not_open, shell_line, words};
+use super::{ChangeAction, Chain, FirewallBackend, FirewallChange, FirewallPlan, Frontend, Port, Protocol, Rule, Ruleset, Verdict};
+
+/// ufw's own chains, by the direction its status output names them
+const DIRECTIONS: &[(&str, &str, &str)] = &[("incoming", "IN", "input"), ("outgoing", "OUT", "output"), ("routed", "FWD", "forward")];
+
+#[derive(Debug, Clone)]
+pub struct Ufw {
+    transport: Arc<dyn Transport>,
+}
+
+impl Ufw {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+}
+
+fn columns() -> Regex {
+    Regex::new(r"\s{2,}").expect("column separator is a valid regex")
+}
+
+/// Parse `ufw status verbose`
+pub(crate) fn parse_status(output: &str) -> Ruleset {
+    let mut ruleset = Ruleset::new(Frontend::Ufw);
+    ruleset.chains = DIRECTIONS.iter()
+        .map(|(name, _, hook)| Chain {
+            table: "ufw".to_string(),
+            name: name.to_string(),
+            hook: Some(hook.to_string()),
+            policy: None,
+            rules: Vec::new(),
+        })
+        .collect();
+    let columns = columns();
+    let mut in_table = false;
+
+    for line in output.lines() {
+        // "Default: deny (incoming), allow (outgoing), disabled (routed)"
+        if let Some(defaults) = line.strip_prefix("Default:") {
+            for default in defaults.split(',') {
+                let Some((policy, direction)) = default.trim().split_once(' ') else {
+                    continue;
+                };
+                let direction = direction.trim_matches(|c| c == '(' || c == ')');
+                if let Some(chain) = ruleset.chains.iter_mut().find(|chain| chain.name == direction) {
+                    chain.policy = (policy != "disabled").then(|| Verdict::parse(policy));
+                }
+            }
+            continue;
+        }
+        if line.starts_with("--") {
+            in_table = true;
+            continue;
+        }
+        if !in_table || line.trim().is_empty() {
+            continue;
+        }
+
+        let (line, comment) = match line.split_once(" # ") {
+            Some((line, comment)) => (line, Some(comment.trim().to_string())),
+            None => (line, None),
+        };
+        let fields: Vec<&str> = columns.split(line.trim()).collect();
+        let [to, action, from] = fields.as_slice() else {
+            continue;
+        };
+        let mut action_words = action.split_whitespace();
+        let verdict = match action_words.next().unwrap_or_default() {
+            // Rate limited, but still allowed
+            "LIMIT" => Verdict::Accept,
+            verdict => Verdict::parse(verdict),
+        };
+        let direction = action_words.next().unwrap_or("IN");
+        let Some(index) = DIRECTIONS.iter().position(|(_, word, _)| *word == direction) else {
+            continue;
+        };
+
+        let mut rule = Rule::new(verdict, format!("{}  {}  {}", to, action, from));
+        let to = to.trim_end_matches(" (v6)");
+        let (to, interface) = match to.split_once(" on ") {
+            Some((to, interface)) => (to, Some(interface.to_string())),
+            None => (to, None),
+        };
+        rule.interface = interface;
+        if to != "Anywhere" {
+            rule.ports = to_ports(to);
+            match rule.ports.first() {
+                Some(port) if rule.ports.iter().all(|other| other.protocol == port.protocol) => rule.protocol = Some(port.protocol.to_string()),
+                Some(_) => {}
+                None => rule.other.push(format!("to {}", to)),
+            }
+        }
+        let from = from.trim_end_matches(" (v6)");
+        if from != "Anywhere" {
+            rule.source = Some(from.to_string());
+        }
+        if let Some(comment) = comment {
+            rule.text.push_str(&format!("  # {}", comment));
+            rule.comment = Some(comment);
+        }
+        ruleset.chains[index].rules.push(rule);
+    }
+    ruleset
+}
+
+/// "22/tcp", "80,443/tcp", "6000:6010/udp", "22" (TCP and UDP) or an application
+/// profile such as "OpenSSH"
+fn to_ports(to: &str) -> Vec<Port> {
+    let (list, protocols) = match to.split_once('/') {
+        Some((list, protocol)) => (list, protocol.parse::<Protocol>().map(|p| vec![p]).unwrap_or_default()),
+        None if to.starts_with(|c: char| c.is_ascii_digit()) => (to, vec![Protocol::Tcp, Protocol::Udp]),
+        None => return super::service_ports(to),
+    };
+    list.split(',')
+        .flat_map(|range| protocols.iter().filter_map(move |protocol| format!("{}/{}", range, protocol).parse::<Port>().ok()))
+        .collect()
+}
+
+/// What follows `ufw allow` to match `to` from `source`
+fn rule_spec(to: &str, source: Option<&str>) -> Vec<String> {
+    let Some(source) = source else {
+        return words(&[to]);
+    };
+    let mut spec = words(&["from", source, "to", "any"]);
+    match to.split_once('/') {
+        Some((port, protocol)) => spec.extend(words(&["port", port, "proto", protocol])),
+        None if to.starts_with(|c: char| c.is_ascii_digit()) => spec.extend(words(&["port", to])),
+        None => spec.extend(words(&["app", to])),
+    }
+    spec
+}
+
+#[async_trait::async_trait]
+impl FirewallBackend for Ufw {
+    async fn is_active(&self) -> bool {
+        self.transport.run("ufw", &["status"]).await
+            .is_ok_and(|output| output.success() && output.stdout.contains("Status: active"))
+    }
+
+    async fn ruleset(&self) -> Result<Ruleset> {
+        let output = self.transport.run("ufw", &["status", "verbose"]).await?;
+        if !output.success() {
+            return Err(anyhow::anyhow!("ufw status failed: {}", output.stderr.trim()));
+        }
+        Ok(parse_status(&output.stdout))
+    }
+
+    fn plan(&self, ruleset: &Ruleset, change: &FirewallChange) -> Result<FirewallPlan> {
+        let mut after = ruleset.clone();
+        let incoming = ruleset.chains.iter().position(|chain| chain.name == "incoming")
+            .context("ufw status has no incoming rules")?;
+
+        let (commands, revert) = match change.action {
+            ChangeAction::Open => {
+                check_not_open(ruleset, change)?;
+                let to = format!("{}/{}", change.port.range(":"), change.port.protocol);
+                let spec = rule_spec(&to, change.source.as_deref());
+
+                let mut command = words(&["ufw", "allow"]);
+                command.extend(spec.iter().cloned());
+                if let Some(comment) = &change.comment {
+                    command.extend(words(&["comment", comment]));
+                }
+                let mut undo = words(&["ufw", "delete", "allow"]);
+                undo.extend(spec);
+
+                let mut text = format!("{}  ALLOW IN  {}", to, change.source.as_deref().unwrap_or("Anywhere"));
+                if let Some(comment) = &change.comment {
+                    text.push_str(&format!("  # {}", comment));
+                }
+                let mut rule = Rule::new(Verdict::Accept, text);
+                rule.protocol = Some(change.port.protocol.to_string());
+                rule.ports = vec![change.port];
+                rule.source = change.source.clone();
+                rule.comment = change.comment.clone();
+                after.chains[incoming].rules.push(rule);
+
+                (vec![command], vec![shell_line(&undo)])
+            }
+            ChangeAction::Close => {
+                let chain = &ruleset.chains[incoming];
+                let matching: Vec<usize> = chain.rules.iter()
+                    .enumerate()
+                    .filter(|(_, rule)| rule.opens_ports() && rule.interface.is_none() && rule.source == change.source
+                        && rule.ports.contains(&change.port))
+                    .map(|(position, _)| position)
+                    .collect();
+                let Some(first) = matching.first().map(|position| &chain.rules[*position]) else {
+                    return Err(not_open(ruleset, change));
+                };
+                check_single_port(&first.ports, change, &first.text)?;
+
+                // The "To" and "Action" columns, e.g. "OpenSSH" and "LIMIT IN"; one
+                // delete removes the IPv4 and IPv6 rule together
+                let fields: Vec<&str> = columns().split(&first.text).collect();
+                let to = fields[0].trim_end_matches(" (v6)");
+                let action = fields[1].split_whitespace().next().unwrap_or("ALLOW").to_lowercase();
+                let spec = rule_spec(to, change.source.as_deref());
+
+                let mut command = words(&["ufw", "delete", &action]);
+                command.extend(spec.iter().cloned());
+                let mut restore = words(&["ufw", &action]);
+                restore.extend(spec);
+                if let Some(comment) = &first.comment {
+                    restore.extend(words(&["comment", comment]));
+                }
+
+                for position in matching.iter().rev() {
+                    after.chains[incoming].rules.remove(*position);
+                }
+                (vec![command], vec![shell_line(&restore)])
+            }
+        };
+
+        Ok(FirewallPlan { change: change.clone(), commands, revert, after, persistent: true })
+    }
+}
@@ -0,0 +1,243 @@
+//! Structured access to the systemd journal.
+//!
+//! Entries are read with `journalctl -o json`, which prints every field of an entry as
+//! one JSON object per line and handles compressed, sealed and rotated journal files.
+//! Each entry carries a cursor, so follow mode polls for entries after the last one it
+//! saw and works the same over SSH as it does locally.
+
+use anyhow::Result;
+use chrono::{DateTime, Local, TimeZone, Utc};
+use futures::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::remote::transport::Transport;
+
+/// syslog priority names, indexed by their number
+pub const PRIORITY_NAMES: &[&str] = &["emerg", "alert", "crit", "err", "warning", "notice", "info", "debug"];
+
+/// Priority from a name such as "err" or "warning", or a number from 0 to 7
+pub fn parse_priority(value: &str) -> Result<u8> {
+    if let Ok(number) = value.parse::<u8>() {
+        if (number as usize) < PRIORITY_NAMES.len() {
+            return Ok(number);
+        }
+    }
+
+    let name = match value.to_lowercase().as_str() {
+        "emergency" => "emerg".to_string(),
+        "critical" => "crit".to_string(),
+        "error" => "err".to_string(),
+        "warn" => "warning".to_string(),
+        other => other.to_string(),
+    };
+    PRIORITY_NAMES.iter()
+        .position(|known| *known == name)
+        .map(|position| position as u8)
+        .ok_or_else(|| anyhow::anyhow!("Unknown priority '{}', expected 0-7 or one of: {}", value, PRIORITY_NAMES.join(", ")))
+}
+
+/// One journal entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogRecord {
+    /// Position in the journal; reading after it continues with the next entry
+    pub cursor: String,
+    pub timestamp: DateTime<Utc>,
+    pub priority: Option<u8>,
+    /// `_SYSTEMD_UNIT`, or `_SYSTEMD_USER_UNIT` for user services
+    pub unit: Option<String>,
+    pub pid: Option<u32>,
+    pub boot_id: Option<String>,
+    pub hostname: Option<String>,
+    /// `SYSLOG_IDENTIFIER`, falling back to the command name
+    pub identifier: Option<String>,
+    pub message: String,
+    /// Every field of the entry, including the ones above
+    pub fields: BTreeMap<String, String>,
+}
+
+impl LogRecord {
+    pub(crate) fn from_json(line: &str) -> Option<Self> {
+        let Value::Object(entry) = serde_json::from_str(line).ok()? else {
+            return None;
+        };
+        let fields: BTreeMap<String, String> = entry.into_iter()
+            .filter_map(|(name, value)| Some((name, field_value(value)?)))
+            .collect();
+
+        let micros = fields.get("__REALTIME_TIMESTAMP")?.parse::<i64>().ok()?;
+        let field = |name: &str| fields.get(name).cloned();
+        Some(Self {
+            cursor: field("__CURSOR")?,
+            timestamp: Utc.timestamp_micros(micros).single()?,
+            priority: fields.get("PRIORITY").and_then(|p| p.parse().ok()),
+            unit: field("_SYSTEMD_UNIT").or_else(|| field("_SYSTEMD_USER_UNIT")),
+            pid: fields.get("_PID").and_then(|pid| pid.parse().ok()),
+            boot_id: field("_BOOT_ID"),
+            hostname: field("_HOSTNAME"),
+            identifier: field("SYSLOG_IDENTIFIER").or_else(|| field("_COMM")),
+            message: field("MESSAGE").unwrap_or_default(),
+            fields,
+        })
+    }
+
+    /// Whether the entry was logged by `unit` or by systemd about it
+    pub fn concerns_unit(&self, unit: &str) -> bool {
+        ["_SYSTEMD_UNIT", "_SYSTEMD_USER_UNIT", "UNIT", "USER_UNIT"].iter()
+            .any(|name| self.fields.get(*name).map(String::as_str) == Some(unit))
+    }
+}
+
+/// journalctl's short format: "May 01 10:00:00 host sshd[812]: message"
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.timestamp.with_timezone(&Local).format("%b %d %H:%M:%S"))?;
+        if let Some(hostname) = &self.hostname {
+            write!(f, " {}", hostname)?;
+        }
+        write!(f, " {}", self.identifier.as_deref().unwrap_or("unknown"))?;
+        if let Some(pid) = self.pid {
+            write!(f, "[{}]", pid)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Field values are strings, byte arrays for binary data, or arrays of either when a
+/// field is set more than once
+fn field_value(value: Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Array(items) if items.iter().all(Value::is_u64) => {
+            let bytes: Vec<u8> = items.iter().filter_map(Value::as_u64).map(|byte| byte as u8).collect();
+            Some(String::from_utf8_lossy(&bytes).into_owned())
+        }
+        Value::Array(items) => Some(items.into_iter().filter_map(field_value).collect::<Vec<_>>().join("\n")),
+        _ => None,
+    }
+}
+
+/// Which entries to read
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JournalQuery {
+    /// Entries of these units, including systemd's own messages about them
+    pub units: Vec<String>,
+    /// Entries logged under these syslog identifiers, such as "CRON"
+    #[serde(default)]
+    pub identifiers: Vec<String>,
+    /// How entries reached the journal, such as "audit" or "kernel"
+    #[serde(default)]
+    pub transports: Vec<String>,
+    /// Least severe priority to include, e.g. 3 for "err" and worse
+    pub priority: Option<u8>,
+    /// Boot ID, or an offset such as "0" for the current boot and "-1" for the one before
+    pub boot: Option<String>,
+    pub pid: Option<u32>,
+    /// Anything journalctl accepts, such as "2024-05-01 10:00", "-1h" or "yesterday"
+    pub since: Option<String>,
+    pub until: Option<String>,
+    /// Only the newest entries; all matching entries when unset
+    pub lines: Option<usize>,
+    /// Only entries after this cursor
+    pub after_cursor: Option<String>,
+}
+
+impl JournalQuery {
+    fn args(&self) -> Vec<String> {
+        let mut args = vec!["--no-pager".to_string(), "-o".to_string(), "json".to_string()];
+        for unit in &self.units {
+            args.extend(["-u".to_string(), unit.clone()]);
+        }
+        for identifier in &self.identifiers {
+            args.extend(["-t".to_string(), identifier.clone()]);
+        }
+        if let Some(priority) = self.priority {
+            args.extend(["-p".to_string(), priority.to_string()]);
+        }
+        if let Some(boot) = &self.boot {
+            args.extend(["-b".to_string(), boot.clone()]);
+        }
+        if let Some(since) = &self.since {
+            args.push(format!("--since={}", since));
+        }
+        if let Some(until) = &self.until {
+            args.push(format!("--until={}", until));
+        }
+        if let Some(lines) = self.lines {
+            args.extend(["-n".to_string(), lines.to_string()]);
+        }
+        if let Some(cursor) = &self.after_cursor {
+            args.push(format!("--after-cursor={}", cursor));
+        }
+        // Field matches come last
+        if let Some(pid) = self.pid {
+            args.push(format!("_PID={}", pid));
+        }
+        for transport in &self.transports {
+            args.push(format!("_TRANSPORT={}", transport));
+        }
+        args
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JournalReader {
+    transport: Arc<dyn Transport>,
+}
+
+impl JournalReader {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    /// Matching entries, oldest first
+    pub async fn read(&self, query: &JournalQuery) -> Result<Vec<LogRecord>> {
+        let args = query.args();
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let output = self.transport.run("journalctl", &args).await?;
+
+        // journalctl exits non-zero when some journal files are unreadable but still
+        // prints the entries it could read
+        if !output.success() && output.stdout.trim().is_empty() {
+            return Err(anyhow::anyhow!("journalctl failed on {}: {}", self.transport.host_name(), output.stderr.trim()));
+        }
+        Ok(output.stdout.lines().filter_map(LogRecord::from_json).collect())
+    }
+
+    /// The newest `query.lines` entries, then new ones as they are written. Each poll
+    /// continues from the cursor of the last entry, so entries are never repeated or
+    /// skipped between polls.
+    pub fn follow(&self, query: JournalQuery, interval: Duration) -> impl Stream<Item = Result<LogRecord>> + Send + 'static {
+        let started = Utc::now();
+        let state = (self.clone(), query, VecDeque::<LogRecord>::new(), true);
+
+        stream::unfold(state, move |(reader, mut query, mut pending, mut first)| async move {
+            loop {
+                if let Some(record) = pending.pop_front() {
+                    query.after_cursor = Some(record.cursor.clone());
+                    return Some((Ok(record), (reader, query, pending, false)));
+                }
+
+                if !first {
+                    tokio::time::sleep(interval).await;
+                }
+                first = false;
+                match reader.read(&query).await {
+                    Ok(records) => pending.extend(records),
+                    Err(e) => return Some((Err(e), (reader, query, pending, false))),
+                }
+
+                query.lines = None;
+                // Nothing matched yet; later polls only want what is written from now on
+                if query.after_cursor.is_none() && pending.is_empty() {
+                    query.since = Some(format!("@{}", started.timestamp()));
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,72 @@
+//! Hardware errors the kernel reports: machine checks, EDAC memory errors, PCIe AER
+//! errors and NVMe command timeouts.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+use super::{EventKind, KernelEvent};
+
+/// `mce: [Hardware Error]: CPU 2: Machine Check: 0 Bank 5: be00000000800400`, or the
+/// summary `mce: [Hardware Error]: Machine check events logged`
+static MACHINE_CHECK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"mce: \[Hardware Error\]: (?:(Machine check events logged)|CPU (\d+): Machine Check( Exception)?: \S+ Bank (\d+): ([0-9a-f]+))")
+        .expect("valid regex")
+});
+/// `EDAC MC0: 1 CE memory read error on CPU_SrcID#0_Ha#0_Chan#1_DIMM#0 (channel:1 slot:0 page:0x12345 ...)`
+static EDAC: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"EDAC (?:(\w+) )?(MC\d+): \d+ (CE|UE) .*?(?: on (\S+))?(?: \(|$)").expect("valid regex")
+});
+/// `nvme 0000:03:00.0: PCIe Bus Error: severity=Corrected, type=Physical Layer, (Receiver ID)`,
+/// where the first word is the driver bound to the device, or "pci" without one
+static PCIE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\S+) ([0-9a-f]{4}:[0-9a-f]{2}:[0-9a-f]{2}\.[0-9a-f]): (?:AER: )?PCIe Bus Error: severity=([^,]+)")
+        .expect("valid regex")
+});
+/// `nvme nvme0: I/O 123 QID 4 timeout, aborting`, and the resets and removals that follow
+static NVME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^nvme (nvme\d+): (.*(?:timeout|controller is down|Device not ready|Removing after probe failure).*)")
+        .expect("valid regex")
+});
+
+/// Machine check status bit for errors the hardware could not correct
+const MCI_STATUS_UC: u64 = 1 << 61;
+
+/// The hardware error a kernel message reports
+pub fn parse(line: &str) -> Option<KernelEvent> {
+    if let Some(captures) = MACHINE_CHECK.captures(line) {
+        let mut event = KernelEvent::new(EventKind::MachineCheck, line);
+        if captures.get(1).is_some() {
+            event.corrected = true;
+        } else {
+            event.device = Some(format!("CPU {} bank {}", &captures[2], &captures[4]));
+            let status = u64::from_str_radix(&captures[5], 16).unwrap_or(MCI_STATUS_UC);
+            event.corrected = captures.get(3).is_none() && status & MCI_STATUS_UC == 0;
+        }
+        return Some(event);
+    }
+    if let Some(captures) = EDAC.captures(line) {
+        let mut event = KernelEvent::new(EventKind::MemoryError, line);
+        event.device = Some(match captures.get(4) {
+            Some(location) => format!("{} {}", &captures[2], location.as_str()),
+            None => captures[2].to_string(),
+        });
+        event.driver = captures.get(1).map(|driver| driver.as_str().to_string());
+        event.corrected = &captures[3] == "CE";
+        return Some(event);
+    }
+    if let Some(captures) = PCIE.captures(line) {
+        let mut event = KernelEvent::new(EventKind::PcieError, line);
+        event.device = Some(captures[2].to_string());
+        event.driver = Some(captures[1].to_string()).filter(|driver| driver != "pci");
+        // "Corrected" on older kernels, "Correctable" on newer ones
+        event.corrected = captures[3].starts_with("Correct");
+        return Some(event);
+    }
+    if let Some(captures) = NVME.captures(line) {
+        let mut event = KernelEvent::new(EventKind::NvmeTimeout, line);
+        event.device = Some(captures[1].to_string());
+        event.driver = Some("nvme".to_string());
+        return Some(event);
+    }
+    None
+}
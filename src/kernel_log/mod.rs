@@ -0,0 +1,620 @@
+//! Kernel oopses, BUGs, warnings, panics and hardware errors.
+//!
+//! The kernel reports a fault as a block of lines: what went wrong, the process and
+//! taint flags, the faulting instruction, the loaded modules and the call trace. Blocks
+//! are put back together from the kernel's journal entries, or from dmesg where there
+//! is no journal, and the module the fault is in is traced to its file and the package
+//! that ships it. Machine checks, EDAC memory errors, PCIe AER errors and NVMe timeouts
+//! are read from single lines. Events of the same kind in the same place are grouped,
+//! so a warning that fires on every resume shows up once.
+
+use chrono::{DateTime, Local, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, LazyLock};
+
+use crate::journal::{JournalQuery, JournalReader};
+use crate::monitoring::{AlertSeverity, AlertType, PredictiveAlert};
+use crate::packages::PackageBackend;
+use crate::remote::transport::Transport;
+
+pub mod hardware;
+pub mod taint;
+
+pub use taint::Taint;
+
+/// Lines kept of a block that never reaches its end marker
+const MAX_EVENT_LINES: usize = 80;
+const MAX_FRAMES: usize = 16;
+/// Corrected hardware errors from one device before they are worth an alert
+const CORRECTED_ALERT_COUNT: usize = 10;
+
+/// What dmesg, `dmesg -T` and syslog files put in front of a kernel message:
+/// `[   12.345678] `, `[Sat Oct 18 10:00:00 2026] ` or `Oct 18 10:00:00 host kernel: `
+static PREFIX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:.*?\bkernel: )?(?:<\d>)?(?:\[[^\]]*\d\]\s*)?").expect("valid regex")
+});
+/// `CPU: 3 PID: 1234 Comm: modprobe Tainted: G        W  OE      6.1.0-13-amd64 #1 Debian ...`
+static CPU_LINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^CPU: \d+ (?:UID: \d+ )?PID: (\d+) Comm: (\S+) (?:Not tainted|Tainted: ([A-Z ]+?))\s+\d").expect("valid regex")
+});
+/// The faulting instruction: `RIP: 0010:nv_foo+0x1a/0x340 [nvidia]` on x86 and
+/// `pc : nv_foo+0x1a/0x340 [nvidia]` on arm64
+static FAULT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:RIP: [0-9a-f]{4}:|pc : )([\w.]+\+0x[0-9a-f]+/0x[0-9a-f]+)(?: \[(\w+)\])?").expect("valid regex")
+});
+/// `? nv_bar+0x12/0x30 [nvidia]`, where "?" marks leftovers on the stack that may not
+/// be part of the trace
+static FRAME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\? )?([\w.]+)\+0x[0-9a-f]+/0x[0-9a-f]+(?: \[(\w+)\])?").expect("valid regex")
+});
+/// `WARNING: CPU: 2 PID: 123 at drivers/gpu/drm/i915/display/intel_display.c:1234 intel_foo+0x12/0x30 [i915]`
+static WARNING: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^WARNING: CPU: \d+ PID: (\d+) at \S+ ([\w.]+\+0x[0-9a-f]+/0x[0-9a-f]+)(?: \[(\w+)\])?").expect("valid regex")
+});
+/// Addresses, PIDs and counters that differ between occurrences of the same fault
+static NUMBER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b(?:0x)?[0-9a-f]*[0-9][0-9a-f]*\b").expect("valid regex")
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EventKind {
+    Panic,
+    Oops,
+    Bug,
+    Warning,
+    MachineCheck,
+    MemoryError,
+    PcieError,
+    NvmeTimeout,
+}
+
+impl EventKind {
+    pub fn is_hardware(self) -> bool {
+        matches!(self, Self::MachineCheck | Self::MemoryError | Self::PcieError | Self::NvmeTimeout)
+    }
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Panic => "Kernel panic",
+            Self::Oops => "Oops",
+            Self::Bug => "BUG",
+            Self::Warning => "Warning",
+            Self::MachineCheck => "Machine check",
+            Self::MemoryError => "Memory error",
+            Self::PcieError => "PCIe error",
+            Self::NvmeTimeout => "NVMe timeout",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// One oops, BUG, warning or panic block, or one hardware error
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KernelEvent {
+    pub kind: EventKind,
+    pub at: Option<DateTime<Utc>>,
+    /// The line saying what went wrong, such as "BUG: kernel NULL pointer dereference, address: 0000000000000008"
+    pub summary: String,
+    /// Module the faulting code is in, from the faulting instruction, the warning or the call trace
+    pub module: Option<String>,
+    /// Function and offset of the faulting instruction
+    pub location: Option<String>,
+    /// Command of the process that was running
+    pub process: Option<String>,
+    pub pid: Option<u32>,
+    pub taint: Option<Taint>,
+    /// Reliable frames, innermost first, as "function [module]"
+    pub call_trace: Vec<String>,
+    /// Proprietary, out-of-tree, unsigned and staging modules that were loaded, with their flags
+    pub suspect_modules: Vec<String>,
+    /// CPU bank, memory controller and DIMM, PCI address or NVMe controller of a hardware error
+    pub device: Option<String>,
+    /// Driver bound to the device
+    pub driver: Option<String>,
+    /// Hardware errors the hardware recovered from by itself
+    pub corrected: bool,
+    pub lines: Vec<String>,
+}
+
+impl KernelEvent {
+    fn new(kind: EventKind, line: &str) -> Self {
+        Self {
+            kind,
+            at: None,
+            summary: line.to_string(),
+            module: None,
+            location: None,
+            process: None,
+            pid: None,
+            taint: None,
+            call_trace: Vec::new(),
+            suspect_modules: Vec::new(),
+            device: None,
+            driver: None,
+            corrected: false,
+            lines: vec![line.to_string()],
+        }
+    }
+
+    /// What stays the same when the same fault happens again: the kind, the faulting
+    /// function or the device, and the module
+    pub fn signature(&self) -> String {
+        let place = match (&self.device, &self.location) {
+            (Some(device), _) if self.kind.is_hardware() => device.clone(),
+            (_, Some(location)) => location.split('+').next().unwrap_or(location).to_string(),
+            _ => NUMBER.replace_all(&self.summary, "#").to_string(),
+        };
+        let mut signature = format!("{} {}", self.kind, place);
+        if let Some(module) = &self.module {
+            signature.push_str(&format!(" [{}]", module));
+        }
+        if self.corrected {
+            signature.push_str(" corrected");
+        }
+        signature
+    }
+
+    /// Whether a process or the whole kernel was killed, or the hardware lost data
+    pub fn is_serious(&self) -> bool {
+        match self.kind {
+            EventKind::Warning => false,
+            kind if kind.is_hardware() => !self.corrected,
+            _ => true,
+        }
+    }
+}
+
+/// Events in kernel messages, in the order they were logged, each with when its first
+/// line was logged where that is known. Lines may still carry dmesg or syslog prefixes.
+pub fn parse<'a>(lines: impl IntoIterator<Item = (Option<DateTime<Utc>>, &'a str)>) -> Vec<KernelEvent> {
+    let mut events: Vec<KernelEvent> = Vec::new();
+    let mut current: Option<KernelEvent> = None;
+    let mut in_trace = false;
+
+    for (at, raw) in lines {
+        let line = PREFIX.replace(raw, "");
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(mut event) = hardware::parse(line) {
+            event.at = at;
+            events.push(event);
+            continue;
+        }
+
+        if let Some(kind) = start(line) {
+            // "BUG: kernel NULL pointer dereference" is followed by "Oops: 0002 [#1]" for
+            // the same fault, and a fault that takes the kernel down by "Kernel panic -
+            // not syncing: Fatal exception", after the block has already ended
+            let continued = if current.is_some() {
+                current.as_mut().filter(|event| match kind {
+                    EventKind::Oops => event.kind == EventKind::Bug && event.call_trace.is_empty(),
+                    EventKind::Panic => event.kind != EventKind::Panic,
+                    _ => false,
+                })
+            } else {
+                events.last_mut().filter(|event| kind == EventKind::Panic && line.contains("Fatal exception")
+                    && !event.kind.is_hardware() && event.kind != EventKind::Panic)
+            };
+            if let Some(event) = continued {
+                event.kind = kind;
+                event.lines.push(line.to_string());
+                continue;
+            }
+
+            events.extend(current.take().map(finish));
+            let mut event = KernelEvent::new(kind, line);
+            event.at = at;
+            if let Some(captures) = WARNING.captures(line) {
+                event.pid = captures[1].parse().ok();
+                event.location = Some(captures[2].to_string());
+                event.module = captures.get(3).map(|module| module.as_str().to_string());
+            }
+            current = Some(event);
+            in_trace = false;
+            continue;
+        }
+
+        let Some(event) = current.as_mut() else {
+            continue;
+        };
+        event.lines.push(line.to_string());
+        if line.starts_with("---[ end") || event.lines.len() >= MAX_EVENT_LINES {
+            events.extend(current.take().map(finish));
+            in_trace = false;
+            continue;
+        }
+
+        if let Some(captures) = CPU_LINE.captures(line) {
+            event.pid = captures[1].parse().ok();
+            event.process = Some(captures[2].to_string());
+            event.taint = Some(captures.get(3).map(|letters| Taint::from_letters(letters.as_str())).unwrap_or_default());
+        } else if let Some(captures) = FAULT.captures(line) {
+            event.location = Some(captures[1].to_string());
+            if let Some(module) = captures.get(2) {
+                event.module = Some(module.as_str().to_string());
+            }
+        } else if let Some(modules) = line.strip_prefix("Modules linked in:") {
+            // "nvidia(POE) nvidia_uvm(OE) snd_hda_intel"
+            event.suspect_modules = modules.split_whitespace()
+                .filter(|module| module.ends_with(')') && module.contains(['P', 'O', 'E', 'C']))
+                .map(String::from)
+                .collect();
+        } else if line.eq_ignore_ascii_case("Call Trace:") {
+            in_trace = true;
+        } else if in_trace {
+            match FRAME.captures(line) {
+                Some(captures) if captures.get(1).is_none() && event.call_trace.len() < MAX_FRAMES => {
+                    event.call_trace.push(match captures.get(3) {
+                        Some(module) => format!("{} [{}]", &captures[2], module.as_str()),
+                        None => captures[2].to_string(),
+                    });
+                }
+                Some(_) => {}
+                // <TASK>, <IRQ> and their ends
+                None if line.starts_with('<') => {}
+                None => in_trace = false,
+            }
+        }
+    }
+    events.extend(current.map(finish));
+    events
+}
+
+/// Kernel messages of a pasted error or log file, without times
+pub fn parse_text(text: &str) -> Vec<KernelEvent> {
+    parse(text.lines().map(|line| (None, line)))
+}
+
+/// The kind of block a line starts
+fn start(line: &str) -> Option<EventKind> {
+    if line.starts_with("Kernel panic - not syncing") {
+        Some(EventKind::Panic)
+    } else if line.starts_with("Oops") || line.starts_with("Internal error: Oops") || line.starts_with("general protection fault") {
+        Some(EventKind::Oops)
+    } else if line.starts_with("BUG: ") || line.starts_with("watchdog: BUG: ") || line.starts_with("kernel BUG at ")
+        || line.starts_with("Unable to handle kernel") {
+        Some(EventKind::Bug)
+    } else if line.starts_with("WARNING: ") {
+        Some(EventKind::Warning)
+    } else {
+        None
+    }
+}
+
+/// A fault outside any module shows up in the first module its call trace passes through
+fn finish(mut event: KernelEvent) -> KernelEvent {
+    if event.module.is_none() && !event.kind.is_hardware() {
+        event.module = event.call_trace.iter()
+            .find_map(|frame| frame.strip_suffix(']')?.rsplit_once(" [").map(|(_, module)| module.to_string()));
+    }
+    event
+}
+
+/// Events with the same signature
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KernelIssue {
+    pub signature: String,
+    pub kind: EventKind,
+    /// First line of the newest occurrence
+    pub summary: String,
+    pub module: Option<String>,
+    pub location: Option<String>,
+    pub device: Option<String>,
+    pub driver: Option<String>,
+    /// File the module or driver is loaded from
+    pub module_file: Option<String>,
+    /// Package that ships the module or driver
+    pub package: Option<String>,
+    pub corrected: bool,
+    pub serious: bool,
+    pub count: usize,
+    pub first: Option<DateTime<Utc>>,
+    pub last: Option<DateTime<Utc>>,
+    /// Processes that were running, as "command[pid]"
+    pub processes: Vec<String>,
+    pub taint: Option<Taint>,
+    /// Call trace of the newest occurrence that has one
+    pub call_trace: Vec<String>,
+    pub suspect_modules: Vec<String>,
+}
+
+impl KernelIssue {
+    /// What to do about it, most useful first
+    pub fn advice(&self) -> Vec<String> {
+        let device = self.device.as_deref().unwrap_or("the device");
+        let mut advice = Vec::new();
+        match self.kind {
+            EventKind::MachineCheck => advice.push("Decode the machine check with rasdaemon or mcelog; repeated ones point to \
+                overheating, outdated microcode or a failing CPU".to_string()),
+            EventKind::MemoryError => advice.push(format!("Test the memory with memtest86+, then reseat or replace {}", device)),
+            EventKind::PcieError => advice.push(format!("Check the link with `lspci -vv -s {}`; repeated errors point to the \
+                slot, a riser or the card, or to link power management (try booting with pcie_aspm=off)", device)),
+            EventKind::NvmeTimeout => advice.push(format!("Check the drive with `smartctl -a /dev/{}` and update its firmware; \
+                timeouts that stop with nvme_core.default_ps_max_latency_us=0 come from power saving", device)),
+            _ => {
+                advice.push(match (&self.module, &self.package) {
+                    (Some(module), Some(package)) => format!("Update {}, which ships the {} module, or the kernel", package, module),
+                    (Some(module), None) => format!("Update the {} module or the kernel", module),
+                    _ => "Update the kernel".to_string(),
+                });
+                if self.taint.as_ref().is_some_and(Taint::third_party) && !self.suspect_modules.is_empty() {
+                    advice.push(format!("Reproduce it without {} before reporting it upstream, since kernel developers \
+                        ignore traces from kernels tainted by them", self.suspect_modules.join(", ")));
+                } else if self.serious && !self.call_trace.is_empty() {
+                    advice.push("Report the call trace to the module's maintainers or your distribution if it happens again".to_string());
+                }
+            }
+        }
+        advice
+    }
+}
+
+impl fmt::Display for KernelIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if self.corrected {
+            write!(f, " (corrected)")?;
+        }
+        if let Some(module) = &self.module {
+            write!(f, " in {}", module)?;
+        } else if let Some(device) = &self.device {
+            write!(f, " on {}", device)?;
+            if let Some(driver) = &self.driver {
+                write!(f, " [{}]", driver)?;
+            }
+        }
+        if let Some(package) = &self.package {
+            write!(f, " ({})", package)?;
+        }
+        write!(f, ": {}", self.summary)?;
+        if self.count > 1 {
+            write!(f, ", {} times", self.count)?;
+        }
+        if let Some(last) = self.last {
+            write!(f, ", last at {}", last.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Events grouped by signature, the most recent first
+pub fn group(events: &[KernelEvent]) -> Vec<KernelIssue> {
+    let mut issues: Vec<KernelIssue> = Vec::new();
+    for event in events {
+        let signature = event.signature();
+        let process = event.process.as_ref().map(|process| match event.pid {
+            Some(pid) => format!("{}[{}]", process, pid),
+            None => process.clone(),
+        });
+        let Some(issue) = issues.iter_mut().find(|issue| issue.signature == signature) else {
+            issues.push(KernelIssue {
+                signature,
+                kind: event.kind,
+                summary: event.summary.clone(),
+                module: event.module.clone(),
+                location: event.location.clone(),
+                device: event.device.clone(),
+                driver: event.driver.clone(),
+                module_file: None,
+                package: None,
+                corrected: event.corrected,
+                serious: event.is_serious(),
+                count: 1,
+                first: event.at,
+                last: event.at,
+                processes: process.into_iter().collect(),
+                taint: event.taint.clone(),
+                call_trace: event.call_trace.clone(),
+                suspect_modules: event.suspect_modules.clone(),
+            });
+            continue;
+        };
+        // Events come oldest first, so the newest occurrence is the last one seen
+        issue.count += 1;
+        issue.first = issue.first.or(event.at);
+        issue.last = event.at.or(issue.last);
+        issue.summary = event.summary.clone();
+        issue.serious |= event.is_serious();
+        if let Some(process) = process {
+            if !issue.processes.contains(&process) {
+                issue.processes.push(process);
+            }
+        }
+        if event.taint.is_some() {
+            issue.taint = event.taint.clone();
+        }
+        if !event.call_trace.is_empty() {
+            issue.call_trace = event.call_trace.clone();
+            issue.suspect_modules = event.suspect_modules.clone();
+        }
+    }
+    issues.reverse();
+    issues.sort_by_key(|issue| Reverse(issue.last));
+    issues
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KernelLogReport {
+    pub hostname: String,
+    /// Boot offset the events are from, "0" for the running kernel
+    pub boot: String,
+    /// "journal" or "dmesg"
+    pub source: Option<String>,
+    /// Taint of the running kernel
+    pub taint: Option<Taint>,
+    /// Loaded modules that taint the running kernel, with their flags
+    pub tainting_modules: Vec<(String, String)>,
+    pub issues: Vec<KernelIssue>,
+    /// Sources that could not be read
+    pub errors: Vec<String>,
+}
+
+impl KernelLogReport {
+    /// Alerts for faults, warnings, hardware errors that lost data and corrected ones
+    /// that keep coming back
+    pub fn predictive_alerts(&self) -> Vec<PredictiveAlert> {
+        let now = Utc::now();
+        self.issues.iter()
+            .filter(|issue| issue.serious || !issue.kind.is_hardware() || issue.count >= CORRECTED_ALERT_COUNT)
+            .map(|issue| {
+                let (alert_type, severity, title) = if issue.kind.is_hardware() {
+                    let severity = if issue.serious { AlertSeverity::Critical } else { AlertSeverity::Warning };
+                    let title = format!("{} on {}", issue.kind, issue.device.as_deref().unwrap_or(&self.hostname));
+                    (AlertType::MaintenanceRequired, severity, title)
+                } else {
+                    let severity = match issue.kind {
+                        EventKind::Panic => AlertSeverity::Critical,
+                        EventKind::Warning => AlertSeverity::Warning,
+                        _ => AlertSeverity::Error,
+                    };
+                    let title = format!("{} in {}", issue.kind, issue.module.as_deref().unwrap_or("the kernel"));
+                    (AlertType::ServiceFailure, severity, title)
+                };
+                // Corrected errors only predict a failure; everything else already happened
+                let predicted_time = if issue.serious || !issue.kind.is_hardware() { now } else { now + chrono::Duration::days(30) };
+                PredictiveAlert {
+                    alert_id: uuid::Uuid::new_v4().to_string(),
+                    alert_type,
+                    severity,
+                    title,
+                    description: issue.to_string(),
+                    predicted_time,
+                    confidence: if issue.serious { 0.9 } else { 0.6 },
+                    affected_components: [&issue.module, &issue.device, &issue.driver, &issue.package].into_iter()
+                        .flatten()
+                        .cloned()
+                        .collect(),
+                    preventive_actions: issue.advice(),
+                    created_at: now,
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct KernelLogInspector {
+    transport: Arc<dyn Transport>,
+    packages: Box<dyn PackageBackend>,
+}
+
+impl KernelLogInspector {
+    pub fn new(transport: Arc<dyn Transport>, packages: Box<dyn PackageBackend>) -> Self {
+        Self { transport, packages }
+    }
+
+    /// Taint of the running kernel and the loaded modules that taint it
+    pub async fn taint(&self) -> (Option<Taint>, Vec<(String, String)>) {
+        let taint = self.transport.read_file(taint::TAINTED).await.ok()
+            .and_then(|value| value.trim().parse().ok())
+            .map(Taint::from_value);
+        let modules = match self.transport.read_file("/proc/modules").await {
+            Ok(modules) => taint::tainting_modules(&modules),
+            Err(_) => Vec::new(),
+        };
+        (taint, modules)
+    }
+
+    /// Events logged in `boot`, an offset such as "0" for this boot or "-1" for the one
+    /// before, with where they were read and what could not be
+    pub async fn events(&self, boot: &str) -> (Vec<KernelEvent>, Option<String>, Vec<String>) {
+        let mut errors = Vec::new();
+        let query = JournalQuery {
+            transports: vec!["kernel".to_string()],
+            boot: Some(boot.to_string()),
+            ..Default::default()
+        };
+        let unavailable = match JournalReader::new(self.transport.clone()).read(&query).await {
+            Ok(records) => {
+                let events = parse(records.iter().map(|record| (Some(record.timestamp), record.message.as_str())));
+                return (events, Some("journal".to_string()), errors);
+            }
+            Err(e) => e.to_string(),
+        };
+
+        // Without a journal only the running kernel's ring buffer is left, and its
+        // stamps are seconds since boot
+        if boot != "0" {
+            errors.push(unavailable);
+            return (Vec::new(), None, errors);
+        }
+        match self.transport.run("dmesg", &[]).await {
+            Ok(output) if output.success() => {
+                (parse(output.stdout.lines().map(|line| (None, line))), Some("dmesg".to_string()), errors)
+            }
+            Ok(output) => {
+                errors.extend([unavailable, format!("dmesg: {}", output.stderr.trim())]);
+                (Vec::new(), None, errors)
+            }
+            Err(e) => {
+                errors.extend([unavailable, format!("dmesg: {}", e)]);
+                (Vec::new(), None, errors)
+            }
+        }
+    }
+
+    /// Events of `boot`, grouped and traced to their drivers and packages, with the
+    /// running kernel's taint
+    pub async fn report(&self, boot: &str) -> KernelLogReport {
+        let (events, source, errors) = self.events(boot).await;
+        let (taint, tainting_modules) = self.taint().await;
+        KernelLogReport {
+            hostname: self.transport.host_name().to_string(),
+            boot: boot.to_string(),
+            source,
+            taint,
+            tainting_modules,
+            issues: self.explain(&events).await,
+            errors,
+        }
+    }
+
+    /// Group events and find the driver, module file and package behind each group
+    pub async fn explain(&self, events: &[KernelEvent]) -> Vec<KernelIssue> {
+        let mut issues = group(events);
+        let mut owners: HashMap<String, (Option<String>, Option<String>)> = HashMap::new();
+        for issue in &mut issues {
+            if issue.driver.is_none() && issue.kind == EventKind::PcieError {
+                if let Some(device) = issue.device.clone() {
+                    issue.driver = self.bound_driver(&device).await;
+                }
+            }
+            let Some(name) = issue.module.clone().or_else(|| issue.driver.clone()) else {
+                continue;
+            };
+            if !owners.contains_key(&name) {
+                let file = self.module_file(&name).await;
+                let package = match &file {
+                    Some(file) => self.packages.owns_file(file).await.ok().flatten(),
+                    None => None,
+                };
+                owners.insert(name.clone(), (file, package));
+            }
+            (issue.module_file, issue.package) = owners[&name].clone();
+        }
+        issues
+    }
+
+    /// Driver bound to a PCI device
+    async fn bound_driver(&self, device: &str) -> Option<String> {
+        let link = format!("/sys/bus/pci/devices/{}/driver", device);
+        let output = self.transport.run("readlink", &[&link]).await.ok()?;
+        let target = output.stdout.trim();
+        (output.success() && !target.is_empty()).then(|| target.rsplit('/').next().unwrap_or(target).to_string())
+    }
+
+    /// File a module is loaded from; `None` for modules built into the kernel
+    async fn module_file(&self, name: &str) -> Option<String> {
+        let output = self.transport.run("modinfo", &["-F", "filename", name]).await.ok()?;
+        let file = output.stdout.lines().next()?.trim();
+        (output.success() && file.starts_with('/')).then(|| file.to_string())
+    }
+}
@@ -0,0 +1,105 @@
+//! Taint flags: the kernel's record of what makes its state or its bug reports less
+//! trustworthy, as the number in /proc/sys/kernel/tainted and as the letters after
+//! "Tainted:" in oopses and warnings.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const TAINTED: &str = "/proc/sys/kernel/tainted";
+
+/// Bit, letter and meaning of each flag, in the order the kernel prints the letters
+const FLAGS: &[(u32, char, &str)] = &[
+    (0, 'P', "proprietary module loaded"),
+    (1, 'F', "module force loaded"),
+    (2, 'S', "running on hardware or firmware out of specification"),
+    (3, 'R', "module force unloaded"),
+    (4, 'M', "processor reported a machine check"),
+    (5, 'B', "bad page referenced or unexpected page flags"),
+    (6, 'U', "tainted on request from userspace"),
+    (7, 'D', "kernel died recently with an oops or BUG"),
+    (8, 'A', "ACPI table overridden"),
+    (9, 'W', "kernel issued a warning"),
+    (10, 'C', "staging driver loaded"),
+    (11, 'I', "working around a platform firmware bug"),
+    (12, 'O', "out-of-tree module loaded"),
+    (13, 'E', "unsigned module loaded"),
+    (14, 'L', "soft lockup occurred"),
+    (15, 'K', "kernel live patched"),
+    (16, 'X', "auxiliary taint set by the distribution"),
+    (17, 'T', "built with the struct randomisation plugin"),
+    (18, 'N', "in-kernel test run"),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaintFlag {
+    pub letter: char,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Taint {
+    pub value: u64,
+    pub flags: Vec<TaintFlag>,
+}
+
+impl Taint {
+    pub fn from_value(value: u64) -> Self {
+        let mut flags: Vec<TaintFlag> = FLAGS.iter()
+            .filter(|(bit, _, _)| value & (1 << bit) != 0)
+            .map(|(_, letter, description)| TaintFlag { letter: *letter, description: description.to_string() })
+            .collect();
+        // Flags added by kernels newer than this table
+        for bit in (FLAGS.len() as u32)..64 {
+            if value & (1 << bit) != 0 {
+                flags.push(TaintFlag { letter: '?', description: format!("taint bit {}", bit) });
+            }
+        }
+        Self { value, flags }
+    }
+
+    /// The letters after "Tainted:", such as "G        W  OE". "G" in the first place
+    /// only says that no proprietary module is loaded.
+    pub fn from_letters(letters: &str) -> Self {
+        let value = letters.chars()
+            .filter_map(|letter| FLAGS.iter().find(|(_, flag, _)| *flag == letter))
+            .fold(0, |value, (bit, _, _)| value | (1 << bit));
+        Self::from_value(value)
+    }
+
+    pub fn is_tainted(&self) -> bool {
+        self.value != 0
+    }
+
+    pub fn has(&self, letter: char) -> bool {
+        self.flags.iter().any(|flag| flag.letter == letter)
+    }
+
+    /// Whether code the kernel developers cannot debug is loaded, which makes upstream
+    /// ignore the kernel's bug reports
+    pub fn third_party(&self) -> bool {
+        self.has('P') || self.has('O')
+    }
+}
+
+impl fmt::Display for Taint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.is_tainted() {
+            return write!(f, "not tainted");
+        }
+        let letters: String = self.flags.iter().map(|flag| flag.letter).collect();
+        let descriptions: Vec<&str> = self.flags.iter().map(|flag| flag.description.as_str()).collect();
+        write!(f, "{} ({}): {}", letters, self.value, descriptions.join(", "))
+    }
+}
+
+/// Loaded modules that taint the kernel, with their flags, from /proc/modules lines
+/// such as "nvidia 56799232 1 nvidia_modeset, Live 0x0000000000000000 (POE)"
+pub fn tainting_modules(proc_modules: &str) -> Vec<(String, String)> {
+    proc_modules.lines()
+        .filter_map(|line| {
+            let name = line.split_whitespace().next()?;
+            let flags = line.trim_end().strip_suffix(')')?.rsplit_once('(')?.1;
+            flags.chars().all(|flag| flag.is_ascii_uppercase()).then(|| (name.to_string(), flags.to_string()))
+        })
+        .collect()
+}
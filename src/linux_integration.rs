@@ -1,9 +1,29 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use tokio::process::Command as AsyncCommand;
+use std::sync::Arc;
 
+use crate::accounts::AccountInspector;
 use crate::config::{Config, PackageManager, ServiceManager};
+use crate::crashes::CrashInspector;
+use crate::environment::{self, EnvironmentInfo};
+use crate::firewall::{self, FirewallBackend};
+use crate::kernel_log::KernelLogInspector;
+use crate::mac::MacInspector;
+use crate::network::NetworkInspector;
+use crate::schedule::ScheduleInspector;
+use crate::packages::{self, PackageBackend, PackageOperation, PackageSources, Transaction};
+use crate::remote::transport::{LocalTransport, Transport};
+use crate::services::{self, ServiceBackend};
+use crate::services::sysvinit::SysVinit;
+use crate::storage::StorageInspector;
+use crate::system_image::{self, SystemImage};
+
+pub mod boot;
+pub mod kernel;
+
+use boot::BootAnalyzer;
+use kernel::KernelManager;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DistributionInfo {
@@ -12,6 +32,15 @@ pub struct DistributionInfo {
     pub id: String,
     pub id_like: Vec<String>,
     pub package_manager: PackageManager,
+    /// Package managers installed next to the distribution's own, such as flatpak
+    #[serde(default)]
+    pub additional_package_managers: Vec<PackageManager>,
+    /// Whether packages and /usr can be changed in place
+    #[serde(default)]
+    pub system_image: SystemImage,
+    /// Bare metal, virtual machine, container or WSL
+    #[serde(default)]
+    pub environment: EnvironmentInfo,
     pub service_manager: ServiceManager,
     pub init_system: String,
     pub shell: String,
@@ -24,27 +53,52 @@ pub struct DistributionInfo {
 pub struct LinuxIntegration {
     pub config: Config,
     pub distribution_info: Option<DistributionInfo>,
+    transport: Arc<dyn Transport>,
 }
 
 impl LinuxIntegration {
     pub async fn new(config: &Config) -> Result<Self> {
+        Self::with_transport(config, Arc::new(LocalTransport)).await
+    }
+
+    /// Integration for the machine behind `transport`. Remote hosts use the package and
+    /// service manager detected on them rather than the ones in the local config.
+    pub async fn with_transport(config: &Config, transport: Arc<dyn Transport>) -> Result<Self> {
         let mut integration = Self {
             config: config.clone(),
             distribution_info: None,
+            transport,
         };
 
         // Detect distribution on initialization
-        integration.distribution_info = Some(integration.detect_distribution().await?);
+        let distribution_info = integration.detect_distribution().await?;
+        if integration.transport.is_remote() {
+            integration.config.system.package_manager = distribution_info.package_manager.clone();
+            integration.config.system.service_manager = distribution_info.service_manager.clone();
+        }
+        integration.distribution_info = Some(distribution_info);
 
         Ok(integration)
     }
 
+    pub fn host_name(&self) -> &str {
+        self.transport.host_name()
+    }
+
+    pub fn transport(&self) -> &Arc<dyn Transport> {
+        &self.transport
+    }
+
+    pub async fn read_file(&self, path: &str) -> Result<String> {
+        self.transport.read_file(path).await
+    }
+
     /// Comprehensive Linux distribution detection
     pub async fn detect_distribution(&self) -> Result<DistributionInfo> {
         let mut distro_info = HashMap::new();
 
         // Read /etc/os-release (standard)
-        if let Ok(content) = tokio::fs::read_to_string("/etc/os-release").await {
+        if let Ok(content) = self.read_file("/etc/os-release").await {
             for line in content.lines() {
                 if let Some((key, value)) = line.split_once('=') {
                     let value = value.trim_matches('"');
@@ -78,6 +132,9 @@ impl LinuxIntegration {
 
         // Detect package manager based on distribution
         let package_manager = self.detect_package_manager(&id, &id_like).await?;
+        let additional_package_managers = self.detect_additional_package_managers(&package_manager).await;
+        let system_image = system_image::detect(self.transport.as_ref()).await;
+        let environment = environment::detect(self.transport.as_ref()).await;
 
         // Detect service manager
         let service_manager = self.detect_service_manager().await?;
@@ -86,7 +143,15 @@ impl LinuxIntegration {
         let init_system = self.detect_init_system().await?;
 
         // Detect shell
-        let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/bash".to_string());
+        let shell = if self.transport.is_remote() {
+            self.execute_command("sh", &["-c", "echo $SHELL"]).await
+                .map(|s| s.trim().to_string())
+                .ok()
+                .filter(|s| !s.is_empty())
+        } else {
+            std::env::var("SHELL").ok()
+        }
+        .unwrap_or_else(|| "/bin/bash".to_string());
 
         // Detect architecture
         let architecture = self.execute_command("uname", &["-m"]).await
@@ -100,6 +165,9 @@ impl LinuxIntegration {
             id,
             id_like,
             package_manager,
+            additional_package_managers,
+            system_image,
+            environment,
             service_manager,
             init_system,
             shell,
@@ -123,13 +191,13 @@ impl LinuxIntegration {
         ];
 
         for (file_path, id, name) in detection_files {
-            if tokio::fs::metadata(file_path).await.is_ok() {
+            if self.transport.path_exists(file_path).await {
                 info.insert("ID".to_string(), id.to_string());
                 info.insert("NAME".to_string(), name.to_string());
                 info.insert("PRETTY_NAME".to_string(), name.to_string());
 
                 // Try to read version from file
-                if let Ok(content) = tokio::fs::read_to_string(file_path).await {
+                if let Ok(content) = self.read_file(file_path).await {
                     let version = content.lines().next().unwrap_or("Unknown").trim();
                     info.insert("VERSION".to_string(), version.to_string());
                 }
@@ -157,6 +225,8 @@ impl LinuxIntegration {
             ("dnf", PackageManager::Dnf),
             ("zypper", PackageManager::Zypper),
             ("emerge", PackageManager::Portage),
+            ("apk", PackageManager::Apk),
+            ("xbps-install", PackageManager::Xbps),
         ];
 
         // First, try distribution-based detection
@@ -166,6 +236,9 @@ impl LinuxIntegration {
             "fedora" | "centos" | "rhel" | "almalinux" | "rocky" => return Ok(PackageManager::Dnf),
             "opensuse" | "sles" => return Ok(PackageManager::Zypper),
             "gentoo" => return Ok(PackageManager::Portage),
+            "alpine" | "postmarketos" => return Ok(PackageManager::Apk),
+            "void" => return Ok(PackageManager::Xbps),
+            "nixos" => return Ok(packages::nix::detect(self.transport.as_ref()).await),
             _ => {}
         }
 
@@ -176,6 +249,7 @@ impl LinuxIntegration {
                 "debian" => return Ok(PackageManager::Apt),
                 "fedora" | "rhel" => return Ok(PackageManager::Dnf),
                 "suse" => return Ok(PackageManager::Zypper),
+                "alpine" => return Ok(PackageManager::Apk),
                 _ => {}
             }
         }
@@ -186,11 +260,30 @@ impl LinuxIntegration {
                 return Ok(pm);
             }
         }
+        if self.command_exists("nix-env").await {
+            return Ok(packages::nix::detect(self.transport.as_ref()).await);
+        }
 
         // Default fallback
         Ok(PackageManager::Apt)
     }
 
+    /// Flatpak, snap and a Nix user profile can sit next to any distribution's manager
+    async fn detect_additional_package_managers(&self, primary: &PackageManager) -> Vec<PackageManager> {
+        let mut managers = Vec::new();
+
+        if !matches!(primary, PackageManager::Nix | PackageManager::NixProfile) && self.command_exists("nix-env").await {
+            managers.push(packages::nix::detect(self.transport.as_ref()).await);
+        }
+        for (cmd, pm) in [("flatpak", PackageManager::Flatpak), ("snap", PackageManager::Snap)] {
+            if self.command_exists(cmd).await {
+                managers.push(pm);
+            }
+        }
+
+        managers
+    }
+
     async fn detect_service_manager(&self) -> Result<ServiceManager> {
         // Check for systemd first (most common)
         if self.command_exists("systemctl").await {
@@ -212,7 +305,7 @@ impl LinuxIntegration {
 
     async fn detect_init_system(&self) -> Result<String> {
         // Check if systemd is running
-        if tokio::fs::metadata("/run/systemd/system").await.is_ok() {
+        if self.transport.path_exists("/run/systemd/system").await {
             return Ok("systemd".to_string());
         }
 
@@ -248,7 +341,7 @@ impl LinuxIntegration {
         }
         
         // Distribution info
-        if let Ok(distro) = tokio::fs::read_to_string("/etc/os-release").await {
+        if let Ok(distro) = self.read_file("/etc/os-release").await {
             if let Some(name) = distro.lines().find(|line| line.starts_with("PRETTY_NAME=")) {
                 let name = name.replace("PRETTY_NAME=", "").trim_matches('"').to_string();
                 info.push(format!("Distribution: {}", name));
@@ -277,267 +370,198 @@ impl LinuxIntegration {
         Ok(info.join("\n"))
     }
 
-    pub async fn get_package_suggestion(&self, operation: &str, package: Option<&str>) -> Result<String> {
-        match self.config.system.package_manager {
-            PackageManager::Pacman => self.get_pacman_suggestion(operation, package),
-            PackageManager::Apt => self.get_apt_suggestion(operation, package),
-            PackageManager::Dnf => self.get_dnf_suggestion(operation, package),
-            PackageManager::Zypper => self.get_zypper_suggestion(operation, package),
-            PackageManager::Portage => self.get_portage_suggestion(operation, package),
+    pub fn environment(&self) -> EnvironmentInfo {
+        self.distribution_info.as_ref()
+            .map(|info| info.environment.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn system_image(&self) -> SystemImage {
+        self.distribution_info.as_ref()
+            .map(|info| info.system_image.clone())
+            .unwrap_or_default()
+    }
+
+    /// Backend for this machine's package manager; on image-based systems package
+    /// changes go through rpm-ostree or transactional-update instead
+    pub fn package_backend(&self) -> Box<dyn PackageBackend> {
+        let backend = packages::backend(&self.config.system.package_manager, self.transport.clone());
+        let image = self.system_image();
+        if image.is_immutable() {
+            Box::new(packages::layered::Layered::new(backend, image))
+        } else {
+            backend
         }
     }
 
-    pub async fn execute_package_command(&self, command: &str) -> Result<String> {
-        let parts: Vec<&str> = command.split_whitespace().collect();
-        if parts.is_empty() {
-            return Err(anyhow::anyhow!("Empty command"));
+    /// The package manager backend together with every additional one detected, for
+    /// searches and listings across all of them; `only` narrows this to one manager
+    pub fn package_sources(&self, only: Option<&PackageManager>) -> PackageSources {
+        if let Some(manager) = only {
+            return PackageSources::new(std::slice::from_ref(manager), self.transport.clone());
         }
-        
-        let (cmd, args) = parts.split_first().unwrap();
-        self.execute_command_with_sudo(cmd, args).await
+
+        let mut managers = vec![self.config.system.package_manager.clone()];
+        if let Some(info) = &self.distribution_info {
+            managers.extend(info.additional_package_managers.iter()
+                .filter(|manager| **manager != self.config.system.package_manager)
+                .cloned());
+        }
+        PackageSources::new(&managers, self.transport.clone())
     }
 
-    pub async fn get_service_info(&self, service_name: &str) -> Result<String> {
-        match self.config.system.service_manager {
-            ServiceManager::Systemd => {
-                let status = self.execute_command("systemctl", &["status", service_name]).await
-                    .unwrap_or_else(|_| "Service not found or not accessible".to_string());
-                Ok(status)
-            }
-            ServiceManager::OpenRC => {
-                let status = self.execute_command("rc-service", &[service_name, "status"]).await
-                    .unwrap_or_else(|_| "Service not found or not accessible".to_string());
-                Ok(status)
-            }
-            ServiceManager::SysVInit => {
-                let status = self.execute_command("service", &[service_name, "status"]).await
-                    .unwrap_or_else(|_| "Service not found or not accessible".to_string());
-                Ok(status)
-            }
+    /// Command line a user would run for a package operation, with `sudo` where needed
+    pub async fn get_package_suggestion(&self, operation: &str, package: Option<&str>) -> Result<String> {
+        let placeholder = match operation.to_lowercase().as_str() {
+            "search" => "<search_term>",
+            _ => "<package_name>",
+        };
+        let operation = match PackageOperation::parse(operation, Some(package.unwrap_or(placeholder))) {
+            Ok(operation) => operation,
+            Err(e) => return Ok(e.to_string()),
+        };
+
+        let image = self.system_image();
+        if let (SystemImage::ReadOnly, Some((_, packages))) = (&image, operation.transaction_kind()) {
+            return Ok(image.package_advice(&packages.join(" ")));
         }
+
+        let backend = self.package_backend();
+        let command = backend.command(&operation).join(" ");
+        Ok(if backend.needs_root(&operation) { format!("sudo {}", command) } else { command })
     }
 
-    pub async fn execute_command(&self, command: &str, args: &[&str]) -> Result<String> {
-        let output = AsyncCommand::new(command)
-            .args(args)
-            .output()
-            .await
-            .context(format!("Failed to execute command: {} {:?}", command, args))?;
-
-        if output.status.success() {
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    /// Run a planned install, removal or upgrade, through sudo when the backend needs root
+    pub async fn execute_transaction(&self, transaction: &Transaction) -> Result<String> {
+        let image = self.system_image();
+        if let SystemImage::ReadOnly = image {
+            return Err(anyhow::anyhow!("{}", image.package_advice(&transaction.packages.join(" "))));
+        }
+
+        let (cmd, args) = transaction.command.split_first().context("Empty command")?;
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+        if self.package_backend().needs_root(&transaction.operation()) {
+            self.execute_command_with_sudo(cmd, &args).await
         } else {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            Err(anyhow::anyhow!("Command failed: {}", stderr))
+            self.execute_command(cmd, &args).await
         }
     }
 
-    async fn execute_command_with_sudo(&self, command: &str, args: &[&str]) -> Result<String> {
-        let mut sudo_args = vec!["sudo", command];
-        sudo_args.extend(args);
-        
-        let output = AsyncCommand::new("sudo")
-            .args(&sudo_args[1..])
-            .output()
-            .await
-            .context(format!("Failed to execute sudo command: {} {:?}", command, args))?;
+    /// Run a package operation's command, through sudo when it changes the system
+    pub async fn execute_package_command(&self, operation: &PackageOperation) -> Result<String> {
+        let image = self.system_image();
+        if let (SystemImage::ReadOnly, Some((_, packages))) = (&image, operation.transaction_kind()) {
+            return Err(anyhow::anyhow!("{}", image.package_advice(&packages.join(" "))));
+        }
+
+        let backend = self.package_backend();
+        let command = backend.command(operation);
+        let (cmd, args) = command.split_first().context("Empty command")?;
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
 
-        if output.status.success() {
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+        if backend.needs_root(operation) {
+            self.execute_command_with_sudo(cmd, &args).await
         } else {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            Err(anyhow::anyhow!("Sudo command failed: {}", stderr))
+            self.execute_command(cmd, &args).await
         }
     }
 
-    fn get_pacman_suggestion(&self, operation: &str, package: Option<&str>) -> Result<String> {
-        let cmd = match operation.to_lowercase().as_str() {
-            "install" | "add" => {
-                if let Some(pkg) = package {
-                    format!("sudo pacman -S {}", pkg)
-                } else {
-                    "sudo pacman -S <package_name>".to_string()
-                }
-            }
-            "remove" | "uninstall" => {
-                if let Some(pkg) = package {
-                    format!("sudo pacman -R {}", pkg)
-                } else {
-                    "sudo pacman -R <package_name>".to_string()
-                }
-            }
-            "update" | "upgrade" => "sudo pacman -Syu".to_string(),
-            "search" => {
-                if let Some(pkg) = package {
-                    format!("pacman -Ss {}", pkg)
-                } else {
-                    "pacman -Ss <search_term>".to_string()
-                }
-            }
-            "info" => {
-                if let Some(pkg) = package {
-                    format!("pacman -Si {}", pkg)
-                } else {
-                    "pacman -Si <package_name>".to_string()
-                }
-            }
-            "list" => "pacman -Q".to_string(),
-            "clean" => "sudo pacman -Sc".to_string(),
-            _ => format!("Unknown operation: {}. Try: install, remove, update, search, info, list, clean", operation),
-        };
-        Ok(cmd)
+    /// Backend for the firewall frontend in use, `None` when no firewall is active
+    pub async fn firewall(&self) -> Option<Box<dyn FirewallBackend>> {
+        firewall::detect(self.transport.clone()).await
     }
 
-    fn get_apt_suggestion(&self, operation: &str, package: Option<&str>) -> Result<String> {
-        let cmd = match operation.to_lowercase().as_str() {
-            "install" | "add" => {
-                if let Some(pkg) = package {
-                    format!("sudo apt install {}", pkg)
-                } else {
-                    "sudo apt install <package_name>".to_string()
-                }
-            }
-            "remove" | "uninstall" => {
-                if let Some(pkg) = package {
-                    format!("sudo apt remove {}", pkg)
-                } else {
-                    "sudo apt remove <package_name>".to_string()
-                }
-            }
-            "update" => "sudo apt update".to_string(),
-            "upgrade" => "sudo apt update && sudo apt upgrade".to_string(),
-            "search" => {
-                if let Some(pkg) = package {
-                    format!("apt search {}", pkg)
-                } else {
-                    "apt search <search_term>".to_string()
-                }
-            }
-            "info" => {
-                if let Some(pkg) = package {
-                    format!("apt show {}", pkg)
-                } else {
-                    "apt show <package_name>".to_string()
-                }
-            }
-            "list" => "apt list --installed".to_string(),
-            "clean" => "sudo apt autoremove && sudo apt autoclean".to_string(),
-            _ => format!("Unknown operation: {}. Try: install, remove, update, upgrade, search, info, list, clean", operation),
-        };
-        Ok(cmd)
+    /// Boot timings of this machine
+    pub fn boot(&self) -> BootAnalyzer {
+        BootAnalyzer::new(self.transport.clone())
     }
 
-    fn get_dnf_suggestion(&self, operation: &str, package: Option<&str>) -> Result<String> {
-        let cmd = match operation.to_lowercase().as_str() {
-            "install" | "add" => {
-                if let Some(pkg) = package {
-                    format!("sudo dnf install {}", pkg)
-                } else {
-                    "sudo dnf install <package_name>".to_string()
-                }
-            }
-            "remove" | "uninstall" => {
-                if let Some(pkg) = package {
-                    format!("sudo dnf remove {}", pkg)
-                } else {
-                    "sudo dnf remove <package_name>".to_string()
-                }
-            }
-            "update" | "upgrade" => "sudo dnf upgrade".to_string(),
-            "search" => {
-                if let Some(pkg) = package {
-                    format!("dnf search {}", pkg)
-                } else {
-                    "dnf search <search_term>".to_string()
-                }
-            }
-            "info" => {
-                if let Some(pkg) = package {
-                    format!("dnf info {}", pkg)
-                } else {
-                    "dnf info <package_name>".to_string()
-                }
-            }
-            "list" => "dnf list installed".to_string(),
-            "clean" => "sudo dnf autoremove && sudo dnf clean all".to_string(),
-            _ => format!("Unknown operation: {}. Try: install, remove, update, search, info, list, clean", operation),
-        };
-        Ok(cmd)
+    /// Kernel parameters and modules of this machine
+    pub fn kernel(&self) -> KernelManager {
+        KernelManager::new(self.transport.clone())
     }
 
-    fn get_zypper_suggestion(&self, operation: &str, package: Option<&str>) -> Result<String> {
-        let cmd = match operation.to_lowercase().as_str() {
-            "install" | "add" => {
-                if let Some(pkg) = package {
-                    format!("sudo zypper install {}", pkg)
-                } else {
-                    "sudo zypper install <package_name>".to_string()
-                }
-            }
-            "remove" | "uninstall" => {
-                if let Some(pkg) = package {
-                    format!("sudo zypper remove {}", pkg)
-                } else {
-                    "sudo zypper remove <package_name>".to_string()
-                }
-            }
-            "update" | "upgrade" => "sudo zypper update".to_string(),
-            "search" => {
-                if let Some(pkg) = package {
-                    format!("zypper search {}", pkg)
-                } else {
-                    "zypper search <search_term>".to_string()
-                }
-            }
-            "info" => {
-                if let Some(pkg) = package {
-                    format!("zypper info {}", pkg)
-                } else {
-                    "zypper info <package_name>".to_string()
-                }
-            }
-            "list" => "zypper search --installed-only".to_string(),
-            "clean" => "sudo zypper clean".to_string(),
-            _ => format!("Unknown operation: {}. Try: install, remove, update, search, info, list, clean", operation),
-        };
-        Ok(cmd)
+    /// Drive, volume and filesystem health of this machine
+    pub fn storage(&self) -> StorageInspector {
+        StorageInspector::new(self.transport.clone())
     }
 
-    fn get_portage_suggestion(&self, operation: &str, package: Option<&str>) -> Result<String> {
-        let cmd = match operation.to_lowercase().as_str() {
-            "install" | "add" => {
-                if let Some(pkg) = package {
-                    format!("sudo emerge {}", pkg)
-                } else {
-                    "sudo emerge <package_name>".to_string()
-                }
-            }
-            "remove" | "uninstall" => {
-                if let Some(pkg) = package {
-                    format!("sudo emerge --unmerge {}", pkg)
-                } else {
-                    "sudo emerge --unmerge <package_name>".to_string()
-                }
-            }
-            "update" | "upgrade" => "sudo emerge --sync && sudo emerge -uDN @world".to_string(),
-            "search" => {
-                if let Some(pkg) = package {
-                    format!("emerge --search {}", pkg)
-                } else {
-                    "emerge --search <search_term>".to_string()
-                }
-            }
-            "info" => {
-                if let Some(pkg) = package {
-                    format!("emerge --info {}", pkg)
-                } else {
-                    "emerge --info <package_name>".to_string()
-                }
+    /// Network configuration of this machine across the stacks that manage it
+    pub fn network(&self) -> NetworkInspector {
+        NetworkInspector::new(self.transport.clone())
+    }
+
+    /// Cron jobs, anacron jobs and systemd timers of this machine
+    pub fn scheduler(&self) -> ScheduleInspector {
+        ScheduleInspector::new(self.transport.clone())
+    }
+
+    /// Users, groups and sudo privileges of this machine
+    pub fn accounts(&self) -> AccountInspector {
+        AccountInspector::new(self.transport.clone())
+    }
+
+    /// Core dumps on this machine, with package owners from its package manager
+    pub fn crashes(&self) -> CrashInspector {
+        CrashInspector::new(self.transport.clone(), self.package_backend())
+    }
+
+    /// Oopses, warnings, panics and hardware errors in this machine's kernel log
+    pub fn kernel_log(&self) -> KernelLogInspector {
+        KernelLogInspector::new(self.transport.clone(), self.package_backend())
+    }
+
+    /// SELinux and AppArmor status and denials on this machine
+    pub fn mac(&self) -> MacInspector {
+        MacInspector::new(self.transport.clone())
+    }
+
+    /// Backend for this machine's service manager
+    pub fn service_backend(&self) -> Box<dyn ServiceBackend> {
+        match self.config.system.service_manager {
+            ServiceManager::SysVInit => {
+                let chkconfig = self.distribution_info.as_ref().is_some_and(|info| {
+                    std::iter::once(&info.id).chain(&info.id_like)
+                        .any(|id| ["rhel", "fedora", "centos", "suse", "opensuse", "sles"].contains(&id.as_str()))
+                });
+                Box::new(SysVinit::new(self.transport.clone()).with_chkconfig(chkconfig))
             }
-            "list" => "qlist -I".to_string(),
-            "clean" => "sudo emerge --depclean".to_string(),
-            _ => format!("Unknown operation: {}. Try: install, remove, update, search, info, list, clean", operation),
+            ref manager => services::backend(manager, self.transport.clone()),
+        }
+    }
+
+    /// Typed status followed by the service manager's own report
+    pub async fn get_service_info(&self, service_name: &str) -> Result<String> {
+        let backend = self.service_backend();
+        let Some(status) = backend.status(service_name).await? else {
+            return Ok(format!("Service '{}' not found ({})", service_name, backend.manager()));
         };
-        Ok(cmd)
+        let report = backend.describe(service_name).await.unwrap_or_default();
+        Ok(format!("{}\n\n{}", status, report.trim_end()))
+    }
+
+    pub async fn execute_command(&self, command: &str, args: &[&str]) -> Result<String> {
+        let output = self.transport.run(command, args).await?;
+
+        if output.success() {
+            Ok(output.stdout)
+        } else {
+            Err(anyhow::anyhow!("Command failed: {}", output.stderr))
+        }
+    }
+
+    async fn execute_command_with_sudo(&self, command: &str, args: &[&str]) -> Result<String> {
+        let mut sudo_args = vec![command];
+        sudo_args.extend(args);
+
+        let output = self.transport.run("sudo", &sudo_args).await
+            .context(format!("Failed to execute sudo command: {} {:?}", command, args))?;
+
+        if output.success() {
+            Ok(output.stdout)
+        } else {
+            Err(anyhow::anyhow!("Sudo command failed: {}", output.stderr))
+        }
     }
 }
@@ -0,0 +1,412 @@
+//! Boot performance.
+//!
+//! `systemd-analyze time`, `blame` and `critical-chain` describe the current boot. Each
+//! analysed boot is recorded under its journal boot ID, so the history holds one entry
+//! per boot however often it is looked at, and `tuxpilot logs --boot <id>` shows what
+//! happened during a boot that got slower. A unit regresses when it took much longer
+//! than its median over the earlier boots it appeared in.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::execution::ExecutionPlan;
+use crate::remote::transport::Transport;
+use crate::storage::fstab;
+
+/// Boots kept in the history, per host
+const HISTORY_LIMIT: usize = 30;
+/// Earlier boots a unit is compared against
+const BASELINE_BOOTS: usize = 10;
+/// A unit regresses when it takes this many times its median...
+const REGRESSION_FACTOR: f64 = 1.5;
+/// ...and at least this much longer
+const REGRESSION_MIN_MS: u64 = 1000;
+/// Units that wait until the network is fully configured
+const WAIT_ONLINE_UNITS: &[&str] = &["NetworkManager-wait-online.service", "systemd-networkd-wait-online.service"];
+/// Waiting for the network is only worth removing when it takes this long
+const WAIT_ONLINE_MIN_MS: u64 = 2000;
+/// Filesystems that need the network before they can be mounted
+const NETWORK_FILESYSTEMS: &[&str] = &["nfs", "nfs4", "cifs", "smb3", "sshfs", "fuse.sshfs", "glusterfs", "ceph", "iscsi"];
+/// Boot loader menus that wait longer than this are worth shortening
+const LOADER_SLOW_MS: u64 = 5000;
+
+/// Startup phases from `systemd-analyze time`, in milliseconds; containers and
+/// machines without EFI only report some of them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BootTimes {
+    pub firmware: Option<u64>,
+    pub loader: Option<u64>,
+    pub kernel: Option<u64>,
+    pub initrd: Option<u64>,
+    pub userspace: Option<u64>,
+    pub total: u64,
+}
+
+impl fmt::Display for BootTimes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let phases = [
+            ("firmware", self.firmware),
+            ("loader", self.loader),
+            ("kernel", self.kernel),
+            ("initrd", self.initrd),
+            ("userspace", self.userspace),
+        ];
+        let phases: Vec<String> = phases.iter()
+            .filter_map(|(name, ms)| ms.map(|ms| format!("{} {}", format_ms(ms), name)))
+            .collect();
+        write!(f, "{} = {}", phases.join(" + "), format_ms(self.total))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnitTiming {
+    pub unit: String,
+    pub ms: u64,
+}
+
+/// One line of `systemd-analyze critical-chain`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainLink {
+    pub unit: String,
+    /// When the unit became active, after userspace started
+    pub active_at_ms: Option<u64>,
+    /// How long the unit took to start
+    pub took_ms: Option<u64>,
+    pub depth: usize,
+}
+
+impl fmt::Display for ChainLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", "  ".repeat(self.depth), self.unit)?;
+        if let Some(at) = self.active_at_ms {
+            write!(f, " @{}", format_ms(at))?;
+        }
+        if let Some(took) = self.took_ms {
+            write!(f, " +{}", format_ms(took))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BootRecord {
+    /// Journal boot ID, without dashes like `_BOOT_ID`
+    pub boot_id: String,
+    pub host: String,
+    pub booted_at: Option<DateTime<Utc>>,
+    pub times: BootTimes,
+    /// Units by the time they took to start, slowest first
+    pub blame: Vec<UnitTiming>,
+    pub critical_chain: Vec<ChainLink>,
+}
+
+impl BootRecord {
+    pub fn unit_ms(&self, unit: &str) -> Option<u64> {
+        self.blame.iter().find(|timing| timing.unit == unit).map(|timing| timing.ms)
+    }
+}
+
+/// A unit that took much longer than it used to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Regression {
+    pub unit: String,
+    pub ms: u64,
+    /// Median over the earlier boots
+    pub usual_ms: u64,
+    pub boots_compared: usize,
+}
+
+impl fmt::Display for Regression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}, usually {}", self.unit, format_ms(self.ms), format_ms(self.usual_ms))?;
+        if self.boots_compared > 1 {
+            write!(f, " (median of {} boots)", self.boots_compared)?;
+        }
+        Ok(())
+    }
+}
+
+/// Something that would make booting faster. Suggestions with a plan can be applied
+/// through the executor; the others need a manual change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BootSuggestion {
+    pub title: String,
+    pub reason: String,
+    pub plan: Option<ExecutionPlan>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BootHistory {
+    pub boots: Vec<BootRecord>,
+}
+
+impl BootHistory {
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(dirs::data_dir()
+            .context("Failed to get data directory")?
+            .join("tuxpilot")
+            .join("boot-history.json"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).context(format!("Failed to parse {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).context(format!("Failed to read {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Add or replace the record for a boot, keeping the newest boots of each host
+    pub fn record(&mut self, boot: BootRecord) {
+        let host = boot.host.clone();
+        self.boots.retain(|existing| !(existing.host == boot.host && existing.boot_id == boot.boot_id));
+        self.boots.push(boot);
+        self.boots.sort_by_key(|boot| boot.booted_at);
+
+        // Oldest first, so the oldest boots of the host go
+        let mut excess = self.boots.iter().filter(|boot| boot.host == host).count().saturating_sub(HISTORY_LIMIT);
+        self.boots.retain(|boot| {
+            if boot.host == host && excess > 0 {
+                excess -= 1;
+                return false;
+            }
+            true
+        });
+    }
+
+    /// Boots of `host` before `boot`, newest first
+    pub fn earlier(&self, boot: &BootRecord) -> Vec<&BootRecord> {
+        self.boots.iter()
+            .rev()
+            .filter(|earlier| earlier.host == boot.host && earlier.boot_id != boot.boot_id && earlier.booted_at <= boot.booted_at)
+            .collect()
+    }
+
+    /// Units of `boot` that took much longer than their median over earlier boots
+    pub fn regressions(&self, boot: &BootRecord) -> Vec<Regression> {
+        let earlier = self.earlier(boot);
+        let mut regressions: Vec<Regression> = boot.blame.iter()
+            .filter_map(|timing| {
+                let mut usual: Vec<u64> = earlier.iter()
+                    .filter_map(|earlier| earlier.unit_ms(&timing.unit))
+                    .take(BASELINE_BOOTS)
+                    .collect();
+                if usual.is_empty() {
+                    return None;
+                }
+                usual.sort_unstable();
+                let median = usual[usual.len() / 2];
+                let regressed = timing.ms as f64 > median as f64 * REGRESSION_FACTOR && timing.ms >= median + REGRESSION_MIN_MS;
+                regressed.then(|| Regression {
+                    unit: timing.unit.clone(),
+                    ms: timing.ms,
+                    usual_ms: median,
+                    boots_compared: usual.len(),
+                })
+            })
+            .collect();
+        regressions.sort_by_key(|regression| std::cmp::Reverse(regression.ms - regression.usual_ms));
+        regressions
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BootAnalyzer {
+    transport: Arc<dyn Transport>,
+}
+
+impl BootAnalyzer {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    async fn analyze_output(&self, args: &[&str]) -> Result<String> {
+        let output = self.transport.run("systemd-analyze", args).await
+            .context("Boot analysis needs systemd-analyze, which comes with systemd")?;
+        if !output.success() {
+            return Err(anyhow::anyhow!("systemd-analyze {} failed: {}", args.join(" "), output.stderr.trim()));
+        }
+        Ok(output.stdout)
+    }
+
+    /// Timings of the current boot
+    pub async fn current(&self) -> Result<BootRecord> {
+        let times = parse_time(&self.analyze_output(&["time"]).await?)
+            .context("systemd-analyze time printed no startup times; the boot may not have finished yet")?;
+        let blame = parse_blame(&self.analyze_output(&["blame", "--no-pager"]).await?);
+        // Not every unit has timing information for the chain; it is optional
+        let critical_chain = self.analyze_output(&["critical-chain", "--no-pager"]).await
+            .map(|output| parse_critical_chain(&output))
+            .unwrap_or_default();
+
+        let boot_id = self.transport.read_file("/proc/sys/kernel/random/boot_id").await?
+            .trim()
+            .replace('-', "");
+        let booted_at = self.transport.read_file("/proc/stat").await.ok()
+            .and_then(|stat| stat.lines().find_map(|line| line.strip_prefix("btime ").map(|btime| btime.trim().to_string())))
+            .and_then(|btime| btime.parse::<i64>().ok())
+            .and_then(|btime| Utc.timestamp_opt(btime, 0).single());
+
+        Ok(BootRecord {
+            boot_id,
+            host: self.transport.host_name().to_string(),
+            booted_at,
+            times,
+            blame,
+            critical_chain,
+        })
+    }
+
+    /// Safe ways to make this boot faster
+    pub async fn suggestions(&self, boot: &BootRecord) -> Vec<BootSuggestion> {
+        let mut suggestions = Vec::new();
+
+        let (entries, _) = fstab::read(self.transport.as_ref()).await;
+        let network_mounts: Vec<&str> = entries.iter()
+            .filter(|entry| NETWORK_FILESYSTEMS.contains(&entry.fs_type.as_str()) || entry.options.iter().any(|option| option == "_netdev"))
+            .map(|entry| entry.mount_point.as_str())
+            .collect();
+        for unit in WAIT_ONLINE_UNITS {
+            let on_chain = boot.critical_chain.iter().any(|link| link.unit == *unit);
+            let Some(ms) = boot.unit_ms(unit).filter(|ms| *ms >= WAIT_ONLINE_MIN_MS || on_chain) else {
+                continue;
+            };
+            if !network_mounts.is_empty() {
+                suggestions.push(BootSuggestion {
+                    title: format!("Keep {}", unit),
+                    reason: format!("it takes {}, but {} need the network before they can be mounted",
+                        format_ms(ms), network_mounts.join(", ")),
+                    plan: None,
+                });
+                continue;
+            }
+            suggestions.push(BootSuggestion {
+                title: format!("Mask {}", unit),
+                reason: format!("it waits {} for the network to be fully configured{}; no filesystem in /etc/fstab needs the network, \
+                    and services ordered after network-online.target start without the wait",
+                    format_ms(ms), if on_chain { " and holds up the boot" } else { "" }),
+                plan: Some(ExecutionPlan {
+                    description: format!("Mask {} to stop waiting for the network at boot", unit),
+                    steps: vec![vec!["systemctl".to_string(), "mask".to_string(), unit.to_string()]],
+                    rollback: Some(format!("systemctl unmask {}", unit)),
+                }),
+            });
+        }
+
+        if let Some(loader) = boot.times.loader.filter(|ms| *ms >= LOADER_SLOW_MS) {
+            suggestions.push(BootSuggestion {
+                title: "Shorten the boot loader menu timeout".to_string(),
+                reason: format!("the boot loader took {}, most of it usually spent waiting in its menu; lower GRUB_TIMEOUT \
+                    in /etc/default/grub and regenerate the configuration, or `timeout` in loader.conf for systemd-boot",
+                    format_ms(loader)),
+                plan: None,
+            });
+        }
+        suggestions
+    }
+}
+
+/// Milliseconds from a systemd time span such as "1min 2.345s", "345ms" or "1h 3min"
+pub fn parse_span(span: &str) -> Option<u64> {
+    let mut total = 0.0;
+    let mut any = false;
+    for word in span.split_whitespace() {
+        let split = word.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+        let (number, unit) = word.split_at(split);
+        let number: f64 = number.parse().ok()?;
+        let factor = match unit {
+            "d" => 86_400_000.0,
+            "h" => 3_600_000.0,
+            "min" => 60_000.0,
+            "s" => 1000.0,
+            "ms" => 1.0,
+            "us" | "µs" => 0.001,
+            _ => return None,
+        };
+        total += number * factor;
+        any = true;
+    }
+    any.then_some(total.round() as u64)
+}
+
+pub fn format_ms(ms: u64) -> String {
+    if ms >= 60_000 {
+        format!("{}min {:.3}s", ms / 60_000, (ms % 60_000) as f64 / 1000.0)
+    } else if ms >= 1000 {
+        format!("{:.3}s", ms as f64 / 1000.0)
+    } else {
+        format!("{}ms", ms)
+    }
+}
+
+/// Parse `systemd-analyze time`, e.g. "Startup finished in 4.1s (firmware) + 2s (loader)
+/// + 1.2s (kernel) + 2.3s (initrd) + 10.2s (userspace) = 19.8s"
+pub(crate) fn parse_time(output: &str) -> Option<BootTimes> {
+    let line = output.lines().find_map(|line| line.trim().strip_prefix("Startup finished in "))?;
+    let (phases, total) = line.split_once(" = ")?;
+    let mut times = BootTimes { total: parse_span(total)?, ..Default::default() };
+    for phase in phases.split(" + ") {
+        let Some((span, name)) = phase.trim().rsplit_once(" (") else {
+            continue;
+        };
+        let ms = parse_span(span);
+        match name.trim_end_matches(')') {
+            "firmware" => times.firmware = ms,
+            "loader" => times.loader = ms,
+            "kernel" => times.kernel = ms,
+            "initrd" => times.initrd = ms,
+            "userspace" => times.userspace = ms,
+            _ => {}
+        }
+    }
+    Some(times)
+}
+
+/// Parse `systemd-analyze blame`: "1min 2.003s foo.service" per line, slowest first
+pub(crate) fn parse_blame(output: &str) -> Vec<UnitTiming> {
+    output.lines()
+        .filter_map(|line| {
+            let (span, unit) = line.trim().rsplit_once(' ')?;
+            Some(UnitTiming { unit: unit.to_string(), ms: parse_span(span)? })
+        })
+        .collect()
+}
+
+/// Parse `systemd-analyze critical-chain`, e.g.
+///
+/// ```text
+/// graphical.target @10.012s
+/// └─multi-user.target @10.011s
+///   └─NetworkManager-wait-online.service @4.888s +5.123s
+/// ```
+pub(crate) fn parse_critical_chain(output: &str) -> Vec<ChainLink> {
+    output.lines()
+        .filter(|line| !line.starts_with("The time "))
+        .filter_map(|line| {
+            let start = line.find(|c: char| c.is_alphanumeric())?;
+            let depth = line[..start].chars().count() / 2;
+            let line = line[start..].trim_end();
+            let (line, took_ms) = match line.rsplit_once(" +") {
+                Some((line, took)) => (line, parse_span(took)),
+                None => (line, None),
+            };
+            let (unit, active_at_ms) = match line.split_once(" @") {
+                Some((unit, at)) => (unit, parse_span(at)),
+                None => (line, None),
+            };
+            Some(ChainLink { unit: unit.to_string(), active_at_ms, took_ms, depth })
+        })
+        .collect()
+}
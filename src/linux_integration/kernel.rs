@@ -0,0 +1,578 @@
+//! Kernel parameters and modules.
+//!
+//! Runtime values come from `/proc/sys` and `/proc/modules`. Persisted values follow
+//! the sysctl.d(5) and modules-load.d(5) rules: files are read from `/etc`, `/run` and
+//! the vendor directories, a file in an earlier directory masks one with the same name
+//! in a later directory, all files are applied in file name order, and the last
+//! assignment of a key wins. Changes tuxpilot makes live in its own drop-in files,
+//! which record the value a parameter had before so it can be reset.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::remote::transport::Transport;
+
+const SYSCTL_DIRS: &[&str] = &["/etc/sysctl.d", "/run/sysctl.d", "/usr/local/lib/sysctl.d", "/usr/lib/sysctl.d", "/lib/sysctl.d"];
+/// Read after every sysctl.d file
+const SYSCTL_CONF: &str = "/etc/sysctl.conf";
+const MODPROBE_DIRS: &[&str] = &["/etc/modprobe.d", "/run/modprobe.d", "/usr/local/lib/modprobe.d", "/usr/lib/modprobe.d", "/lib/modprobe.d"];
+const MODULES_LOAD_DIRS: &[&str] = &["/etc/modules-load.d", "/run/modules-load.d", "/usr/local/lib/modules-load.d", "/usr/lib/modules-load.d", "/lib/modules-load.d"];
+/// Debian's list of modules loaded at boot
+const ETC_MODULES: &str = "/etc/modules";
+
+pub const SYSCTL_DROP_IN: &str = "/etc/sysctl.d/99-tuxpilot.conf";
+pub const MODPROBE_DROP_IN: &str = "/etc/modprobe.d/tuxpilot.conf";
+pub const MODULES_LOAD_DROP_IN: &str = "/etc/modules-load.d/tuxpilot.conf";
+
+const DROP_IN_HEADER: &str = "# Managed by tuxpilot, use `tuxpilot kernel` to change it";
+
+/// One `key = value` line of a sysctl configuration file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Assignment {
+    /// Dotted key; may contain `*` globs such as `net.ipv4.conf.*.rp_filter`
+    pub key: String,
+    pub value: String,
+    pub file: String,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SysctlSetting {
+    pub key: String,
+    /// Current value, `None` when the parameter does not exist (yet), e.g. because the
+    /// module providing it is not loaded
+    pub runtime: Option<String>,
+    /// Assignment applied at boot
+    pub persisted: Option<Assignment>,
+    /// Earlier assignments the persisted one overrides
+    pub overridden: Vec<Assignment>,
+}
+
+impl SysctlSetting {
+    /// Whether the running kernel uses the value applied at boot
+    pub fn in_sync(&self) -> bool {
+        match (&self.runtime, &self.persisted) {
+            (Some(runtime), Some(persisted)) => same_value(runtime, &persisted.value),
+            _ => true,
+        }
+    }
+}
+
+/// "net.ipv4.ip_forward = 1 (persisted: 0 in /etc/sysctl.d/99-sysctl.conf:3)"
+impl fmt::Display for SysctlSetting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} = {}", self.key, self.runtime.as_deref().map(normalize_value).unwrap_or_else(|| "(not available)".to_string()))?;
+        match &self.persisted {
+            Some(persisted) if self.in_sync() => write!(f, " (persisted in {}:{})", persisted.file, persisted.line),
+            Some(persisted) => write!(f, " (persisted: {} in {}:{})", persisted.value, persisted.file, persisted.line),
+            None => write!(f, " (not persisted)"),
+        }
+    }
+}
+
+/// Modules loaded now, kept from loading automatically, and loaded at boot, each with
+/// the file that configures it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModuleState {
+    pub loaded: BTreeSet<String>,
+    pub blacklisted: BTreeMap<String, String>,
+    pub autoloaded: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ModuleAction {
+    Load,
+    Unload,
+    /// Keep the module from being loaded automatically, and unload it now
+    Blacklist,
+    Unblacklist,
+    /// Load the module at every boot, and now
+    Autoload,
+    NoAutoload,
+}
+
+/// A drop-in file with its complete new content
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DropIn {
+    pub path: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum KernelStep {
+    Write(DropIn),
+    /// Program and arguments
+    Run(Vec<String>),
+}
+
+impl fmt::Display for KernelStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KernelStep::Write(drop_in) => write!(f, "write {}", drop_in.path),
+            KernelStep::Run(command) => write!(f, "{}", command.join(" ")),
+        }
+    }
+}
+
+/// Steps that make a change, in order; a failing step stops the rest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KernelChange {
+    pub description: String,
+    pub steps: Vec<KernelStep>,
+    /// Command that undoes the change
+    pub undo: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct KernelManager {
+    transport: Arc<dyn Transport>,
+}
+
+impl KernelManager {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    /// Current value of `key` from `/proc/sys`
+    pub async fn runtime_value(&self, key: &str) -> Option<String> {
+        self.transport.read_file(&proc_path(key)).await.ok().map(|value| value.trim_end_matches('\n').to_string())
+    }
+
+    /// Every key set in the sysctl configuration, with its runtime value
+    pub async fn sysctl_settings(&self) -> Result<Vec<SysctlSetting>> {
+        let mut by_key: BTreeMap<String, Vec<Assignment>> = BTreeMap::new();
+        for assignment in self.sysctl_assignments().await? {
+            by_key.entry(assignment.key.clone()).or_default().push(assignment);
+        }
+
+        let mut settings = Vec::new();
+        for (key, mut assignments) in by_key {
+            let persisted = assignments.pop();
+            let runtime = if key.contains('*') { None } else { self.runtime_value(&key).await };
+            settings.push(SysctlSetting { key, runtime, persisted, overridden: assignments });
+        }
+        Ok(settings)
+    }
+
+    /// Runtime and persisted value of one key, counting globs that match it
+    pub async fn sysctl(&self, key: &str) -> Result<SysctlSetting> {
+        let key = normalize_key(key);
+        let mut assignments: Vec<Assignment> = self.sysctl_assignments().await?
+            .into_iter()
+            .filter(|assignment| glob_match(&assignment.key, &key))
+            .collect();
+        let persisted = assignments.pop();
+        Ok(SysctlSetting { runtime: self.runtime_value(&key).await, key, persisted, overridden: assignments })
+    }
+
+    /// Assignments from every sysctl configuration file, in the order they are applied
+    pub async fn sysctl_assignments(&self) -> Result<Vec<Assignment>> {
+        let mut assignments = Vec::new();
+        let mut files = self.config_files(SYSCTL_DIRS).await?;
+        if self.transport.path_exists(SYSCTL_CONF).await {
+            files.push(SYSCTL_CONF.to_string());
+        }
+        for file in files {
+            let content = self.transport.read_file(&file).await.unwrap_or_default();
+            assignments.extend(parse_sysctl_conf(&file, &content));
+        }
+        Ok(assignments)
+    }
+
+    /// Set `key` now and, unless `runtime_only`, at every boot. The runtime change goes
+    /// first so invalid values never reach the configuration.
+    pub async fn plan_sysctl(&self, key: &str, value: &str, runtime_only: bool) -> Result<KernelChange> {
+        let key = normalize_key(key);
+        validate_key(&key)?;
+        if value.contains('\n') || value.trim().is_empty() {
+            return Err(anyhow::anyhow!("Invalid value for {}", key));
+        }
+        let current = self.runtime_value(&key).await
+            .ok_or_else(|| anyhow::anyhow!("Unknown kernel parameter '{}' ({} does not exist)", key, proc_path(&key)))?;
+
+        let mut steps = vec![KernelStep::Run(vec!["sysctl".to_string(), "-w".to_string(), format!("{}={}", key, value)])];
+        if runtime_only {
+            return Ok(KernelChange {
+                description: format!("Set {} to {} until the next boot", key, value),
+                steps,
+                undo: Some(format!("sysctl -w {}={}", key, normalize_value(&current))),
+            });
+        }
+
+        let mut entries = SysctlDropIn::parse(&self.transport.read_file(SYSCTL_DROP_IN).await.unwrap_or_default());
+        entries.set(&key, value, &normalize_value(&current));
+        steps.push(KernelStep::Write(DropIn { path: SYSCTL_DROP_IN.to_string(), content: entries.render() }));
+
+        Ok(KernelChange {
+            description: format!("Set {} to {} now and at boot", key, value),
+            steps,
+            undo: Some(format!("tuxpilot kernel sysctl {} --reset", key)),
+        })
+    }
+
+    /// Remove tuxpilot's setting for `key` and restore the value it had before
+    pub async fn plan_sysctl_reset(&self, key: &str) -> Result<KernelChange> {
+        let key = normalize_key(key);
+        let mut entries = SysctlDropIn::parse(&self.transport.read_file(SYSCTL_DROP_IN).await.unwrap_or_default());
+        let entry = entries.remove(&key)
+            .ok_or_else(|| anyhow::anyhow!("{} is not set in {}", key, SYSCTL_DROP_IN))?;
+
+        // The value from before tuxpilot changed it, or else what the remaining
+        // configuration applies at boot
+        let restore = match entry.previous {
+            Some(previous) => Some(previous),
+            None => self.sysctl_assignments().await?
+                .into_iter()
+                .rfind(|assignment| assignment.file != SYSCTL_DROP_IN && glob_match(&assignment.key, &key))
+                .map(|assignment| assignment.value),
+        };
+
+        let mut steps = vec![KernelStep::Write(DropIn { path: SYSCTL_DROP_IN.to_string(), content: entries.render() })];
+        if let Some(value) = &restore {
+            steps.push(KernelStep::Run(vec!["sysctl".to_string(), "-w".to_string(), format!("{}={}", key, value)]));
+        }
+        Ok(KernelChange {
+            description: match &restore {
+                Some(value) => format!("Remove tuxpilot's setting for {} and restore {}", key, value),
+                None => format!("Remove tuxpilot's setting for {}; the running value stays until the next boot", key),
+            },
+            steps,
+            undo: Some(format!("tuxpilot kernel sysctl {} --set {}", key, entry.value)),
+        })
+    }
+
+    pub async fn modules(&self) -> Result<ModuleState> {
+        let loaded = self.transport.read_file("/proc/modules").await.unwrap_or_default()
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .map(normalize_module)
+            .collect();
+
+        let mut blacklisted = BTreeMap::new();
+        for file in self.config_files(MODPROBE_DIRS).await? {
+            let content = self.transport.read_file(&file).await.unwrap_or_default();
+            for name in parse_blacklist(&content) {
+                blacklisted.insert(name, file.clone());
+            }
+        }
+
+        let mut autoloaded = BTreeMap::new();
+        let mut files = self.config_files(MODULES_LOAD_DIRS).await?;
+        if self.transport.path_exists(ETC_MODULES).await {
+            files.push(ETC_MODULES.to_string());
+        }
+        for file in files {
+            let content = self.transport.read_file(&file).await.unwrap_or_default();
+            for name in parse_module_list(&content) {
+                autoloaded.insert(name, file.clone());
+            }
+        }
+
+        Ok(ModuleState { loaded, blacklisted, autoloaded })
+    }
+
+    pub async fn plan_module(&self, action: ModuleAction, name: &str) -> Result<KernelChange> {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err(anyhow::anyhow!("Invalid module name '{}'", name));
+        }
+        let module = normalize_module(name);
+        let state = self.modules().await?;
+        let is_loaded = state.loaded.contains(&module);
+        let modprobe = |args: &[&str]| KernelStep::Run(
+            std::iter::once("modprobe").chain(args.iter().copied()).map(String::from).collect());
+
+        let (description, steps, undo) = match action {
+            ModuleAction::Load => (
+                format!("Load {}", module),
+                vec![modprobe(&[&module])],
+                format!("modprobe -r {}", module),
+            ),
+            ModuleAction::Unload => (
+                format!("Unload {}", module),
+                vec![modprobe(&["-r", &module])],
+                format!("modprobe {}", module),
+            ),
+            ModuleAction::Blacklist => {
+                let mut lines = DropInLines::read(self.transport.as_ref(), MODPROBE_DROP_IN).await;
+                lines.insert(format!("blacklist {}", module));
+                let mut steps = vec![KernelStep::Write(lines.drop_in(MODPROBE_DROP_IN))];
+                if is_loaded {
+                    steps.push(modprobe(&["-r", &module]));
+                }
+                (format!("Keep {} from loading automatically", module), steps,
+                 format!("tuxpilot kernel modules --unblacklist {}", module))
+            }
+            ModuleAction::Unblacklist => {
+                let mut lines = DropInLines::read(self.transport.as_ref(), MODPROBE_DROP_IN).await;
+                if !lines.remove(&format!("blacklist {}", module)) {
+                    return Err(match state.blacklisted.get(&module) {
+                        Some(file) => anyhow::anyhow!("{} is blacklisted in {}, which tuxpilot does not manage", module, file),
+                        None => anyhow::anyhow!("{} is not blacklisted", module),
+                    });
+                }
+                (format!("Allow {} to load automatically again", module),
+                 vec![KernelStep::Write(lines.drop_in(MODPROBE_DROP_IN))],
+                 format!("tuxpilot kernel modules --blacklist {}", module))
+            }
+            ModuleAction::Autoload => {
+                let mut lines = DropInLines::read(self.transport.as_ref(), MODULES_LOAD_DROP_IN).await;
+                lines.insert(module.clone());
+                let mut steps = vec![KernelStep::Write(lines.drop_in(MODULES_LOAD_DROP_IN))];
+                if !is_loaded {
+                    steps.push(modprobe(&[&module]));
+                }
+                (format!("Load {} at every boot", module), steps,
+                 format!("tuxpilot kernel modules --no-autoload {}", module))
+            }
+            ModuleAction::NoAutoload => {
+                let mut lines = DropInLines::read(self.transport.as_ref(), MODULES_LOAD_DROP_IN).await;
+                if !lines.remove(&module) {
+                    return Err(match state.autoloaded.get(&module) {
+                        Some(file) => anyhow::anyhow!("{} is loaded at boot by {}, which tuxpilot does not manage", module, file),
+                        None => anyhow::anyhow!("{} is not loaded at boot", module),
+                    });
+                }
+                (format!("Stop loading {} at boot; it stays loaded until then", module),
+                 vec![KernelStep::Write(lines.drop_in(MODULES_LOAD_DROP_IN))],
+                 format!("tuxpilot kernel modules --autoload {}", module))
+            }
+        };
+
+        Ok(KernelChange { description, steps, undo: Some(undo) })
+    }
+
+    /// `*.conf` files in `dirs`, where a file masks files of the same name in later
+    /// directories, sorted by file name
+    async fn config_files(&self, dirs: &[&str]) -> Result<Vec<String>> {
+        let patterns: Vec<String> = dirs.iter().map(|dir| format!("{}/*.conf", dir)).collect();
+        let script = format!("ls -1d {} 2>/dev/null", patterns.join(" "));
+        let output = self.transport.run("sh", &["-c", &script]).await?;
+
+        let mut by_name: BTreeMap<String, String> = BTreeMap::new();
+        for path in output.stdout.lines() {
+            let Some(name) = path.rsplit('/').next() else {
+                continue;
+            };
+            let directory = &path[..path.len() - name.len() - 1];
+            let rank = |path: &str| dirs.iter().position(|dir| path.starts_with(&format!("{}/", dir)));
+            match by_name.get(name) {
+                Some(existing) if rank(existing) <= rank(path) => {}
+                _ if dirs.contains(&directory) => {
+                    by_name.insert(name.to_string(), path.to_string());
+                }
+                _ => {}
+            }
+        }
+        Ok(by_name.into_values().collect())
+    }
+}
+
+/// Keys as written use dots or slashes as separators
+fn normalize_key(key: &str) -> String {
+    key.trim().replace('/', ".")
+}
+
+fn proc_path(key: &str) -> String {
+    format!("/proc/sys/{}", key.replace('.', "/"))
+}
+
+fn validate_key(key: &str) -> Result<()> {
+    let valid = !key.is_empty()
+        && !key.split('.').any(|part| part.is_empty() || part == "..")
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || "_.-:@+".contains(c));
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Invalid kernel parameter name '{}'", key))
+    }
+}
+
+/// /proc/sys separates fields with tabs, configuration files with spaces
+fn normalize_value(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn same_value(a: &str, b: &str) -> bool {
+    normalize_value(a) == normalize_value(b)
+}
+
+/// Module names treat dashes and underscores the same
+fn normalize_module(name: &str) -> String {
+    name.replace('-', "_")
+}
+
+/// `*` matches any run of characters, as in sysctl.d keys
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let Some((head, rest)) = pattern.split_once('*') else {
+        return pattern == text;
+    };
+    let Some(text) = text.strip_prefix(head) else {
+        return false;
+    };
+    (0..=text.len())
+        .filter(|&i| text.is_char_boundary(i))
+        .any(|i| glob_match(rest, &text[i..]))
+}
+
+pub(crate) fn parse_sysctl_conf(file: &str, content: &str) -> Vec<Assignment> {
+    content.lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                return None;
+            }
+            // A leading "-" only means errors setting the key are ignored
+            let (key, value) = line.trim_start_matches('-').split_once('=')?;
+            Some(Assignment {
+                key: normalize_key(key),
+                value: value.trim().to_string(),
+                file: file.to_string(),
+                line: index + 1,
+            })
+        })
+        .collect()
+}
+
+/// Modules named in `blacklist` lines, or disabled with `install <name> /bin/false`
+fn parse_blacklist(content: &str) -> Vec<String> {
+    content.lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            match (words.next()?, words.next()?, words.next()) {
+                ("blacklist", name, _) => Some(normalize_module(name)),
+                ("install", name, Some("/bin/false" | "/bin/true" | "/usr/bin/false" | "/usr/bin/true")) => Some(normalize_module(name)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// One module name per line, with `#` and `;` comments
+fn parse_module_list(content: &str) -> Vec<String> {
+    content.lines()
+        .map(|line| line.split(['#', ';']).next().unwrap_or("").trim())
+        .filter_map(|line| line.split_whitespace().next())
+        .map(normalize_module)
+        .collect()
+}
+
+/// Keys in tuxpilot's sysctl drop-in, each with the value it had before
+#[derive(Debug, Default)]
+struct SysctlDropIn {
+    entries: Vec<DropInEntry>,
+}
+
+#[derive(Debug, Clone)]
+struct DropInEntry {
+    key: String,
+    value: String,
+    previous: Option<String>,
+}
+
+impl SysctlDropIn {
+    /// "# <key> was <value>" above an assignment records the value before tuxpilot
+    fn parse(content: &str) -> Self {
+        let mut entries = Vec::new();
+        let mut previous = None;
+        for line in content.lines().map(str::trim) {
+            if let Some(comment) = line.strip_prefix("# ") {
+                previous = comment.split_once(" was ").map(|(_, value)| value.to_string());
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=') {
+                entries.push(DropInEntry {
+                    key: normalize_key(key),
+                    value: value.trim().to_string(),
+                    previous: previous.take(),
+                });
+            }
+        }
+        Self { entries }
+    }
+
+    fn set(&mut self, key: &str, value: &str, current: &str) {
+        match self.entries.iter_mut().find(|entry| entry.key == key) {
+            Some(entry) => entry.value = value.to_string(),
+            None => self.entries.push(DropInEntry {
+                key: key.to_string(),
+                value: value.to_string(),
+                previous: Some(current.to_string()),
+            }),
+        }
+    }
+
+    fn remove(&mut self, key: &str) -> Option<DropInEntry> {
+        let position = self.entries.iter().position(|entry| entry.key == key)?;
+        Some(self.entries.remove(position))
+    }
+
+    fn render(&self) -> String {
+        let mut content = format!("{}\n", DROP_IN_HEADER);
+        for entry in &self.entries {
+            if let Some(previous) = &entry.previous {
+                content.push_str(&format!("# {} was {}\n", entry.key, previous));
+            }
+            content.push_str(&format!("{} = {}\n", entry.key, entry.value));
+        }
+        content
+    }
+}
+
+/// Lines of a module drop-in, without comments
+struct DropInLines {
+    lines: Vec<String>,
+}
+
+impl DropInLines {
+    async fn read(transport: &dyn Transport, path: &str) -> Self {
+        let content = transport.read_file(path).await.unwrap_or_default();
+        let lines = content.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from)
+            .collect();
+        Self { lines }
+    }
+
+    fn insert(&mut self, line: String) {
+        if !self.lines.contains(&line) {
+            self.lines.push(line);
+        }
+    }
+
+    fn remove(&mut self, line: &str) -> bool {
+        let before = self.lines.len();
+        self.lines.retain(|existing| existing != line);
+        self.lines.len() != before
+    }
+
+    fn drop_in(&self, path: &str) -> DropIn {
+        let mut content = format!("{}\n", DROP_IN_HEADER);
+        for line in &self.lines {
+            content.push_str(line);
+            content.push('\n');
+        }
+        DropIn { path: path.to_string(), content }
+    }
+}
+
+/// Keys a `sysctl` command line assigns, such as `vm.swappiness` in `sysctl -w vm.swappiness=10`
+pub fn assigned_keys(command: &str, args: &[String]) -> Vec<String> {
+    if command != "sysctl" {
+        return Vec::new();
+    }
+    args.iter()
+        .filter(|arg| !arg.starts_with('-'))
+        .filter_map(|arg| arg.split_once('='))
+        .map(|(key, _)| normalize_key(key))
+        .collect()
+}
+
+/// Runtime value of `key` on this machine, for the state probe
+pub fn local_value(key: &str) -> Option<String> {
+    std::fs::read_to_string(proc_path(key)).ok().map(|value| normalize_value(&value))
+}
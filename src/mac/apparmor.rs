@@ -0,0 +1,195 @@
+//! AppArmor denials and the profile changes that allow them.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::execution::ExecutionPlan;
+use crate::remote::transport::Transport;
+use super::{audit_fields, audit_time, Denial, DenialGroup, MacFix, MacSystem};
+
+const ENABLED: &str = "/sys/module/apparmor/parameters/enabled";
+/// Loaded profiles, one "name (mode)" per line; only root may read it
+const PROFILES: &str = "/sys/kernel/security/apparmor/profiles";
+const PROFILE_DIR: &str = "/etc/apparmor.d";
+/// Order of permissions in file rules
+const FILE_PERMISSIONS: &str = "rwalkm";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AppArmorStatus {
+    /// Profiles whose rules are enforced
+    pub enforcing: Vec<String>,
+    /// Profiles that only log what they would deny
+    pub complaining: Vec<String>,
+    /// Why the loaded profiles could not be listed, usually missing root
+    pub error: Option<String>,
+}
+
+impl fmt::Display for AppArmorStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.error {
+            Some(error) => write!(f, "AppArmor enabled, profiles not listed: {}", error),
+            None => write!(f, "AppArmor with {} profiles enforcing and {} in complain mode",
+                self.enforcing.len(), self.complaining.len()),
+        }
+    }
+}
+
+/// Loaded profiles and their modes; `None` when AppArmor is not enabled
+pub async fn status(transport: &dyn Transport) -> Option<AppArmorStatus> {
+    let enabled = transport.read_file(ENABLED).await.ok()?;
+    if enabled.trim() != "Y" {
+        return None;
+    }
+    let mut status = AppArmorStatus::default();
+    match transport.read_file(PROFILES).await {
+        Ok(profiles) => {
+            for line in profiles.lines() {
+                let Some((name, mode)) = line.trim().rsplit_once(" (") else {
+                    continue;
+                };
+                match mode.trim_end_matches(')') {
+                    "enforce" => status.enforcing.push(name.to_string()),
+                    "complain" => status.complaining.push(name.to_string()),
+                    _ => {}
+                }
+            }
+        }
+        Err(e) => status.error = Some(e.to_string()),
+    }
+    Some(status)
+}
+
+/// An AppArmor audit message: `apparmor="DENIED" operation="open" class="file"
+/// profile="/usr/sbin/mysqld" name="/data/mysql/ibdata1" ... requested_mask="r" denied_mask="r"`
+pub fn parse(line: &str) -> Option<Denial> {
+    let permissive = if line.contains("apparmor=\"DENIED\"") {
+        false
+    } else if line.contains("apparmor=\"ALLOWED\"") {
+        true
+    } else {
+        return None;
+    };
+    let fields = audit_fields(line);
+    let field = |name: &str| fields.get(name).cloned();
+    let subject = field("profile")?;
+    let operation = field("operation").unwrap_or_default();
+
+    // Older kernels leave out the class
+    let class = field("class").unwrap_or_else(|| {
+        if fields.contains_key("capname") || operation == "capable" {
+            "cap".to_string()
+        } else if fields.contains_key("family") {
+            "net".to_string()
+        } else {
+            "file".to_string()
+        }
+    });
+    let mask = field("denied_mask").or_else(|| field("requested_mask")).unwrap_or_default();
+    let (object, permissions) = match class.as_str() {
+        "file" => (field("name")?, mask.chars().map(String::from).collect()),
+        "cap" => {
+            let capability = field("capname")?;
+            (format!("capability {}", capability), vec![capability])
+        }
+        "net" => {
+            let family = field("family").unwrap_or_else(|| "inet".to_string());
+            let kind = field("sock_type").unwrap_or_else(|| "stream".to_string());
+            (format!("network {} {}", family, kind), vec![operation.clone()])
+        }
+        _ => {
+            let target = field("peer").or_else(|| field("name")).unwrap_or_else(|| operation.clone());
+            (format!("{} {}", class, target), if mask.is_empty() { vec![operation.clone()] } else { vec![mask] })
+        }
+    };
+    let path = Some(object.clone()).filter(|object| class == "file" && object.starts_with('/'));
+    let comm = field("comm");
+    let pid = fields.get("pid").and_then(|pid| pid.parse().ok());
+
+    Some(Denial {
+        system: MacSystem::AppArmor,
+        at: audit_time(line),
+        subject,
+        object,
+        class,
+        permissions,
+        comm,
+        pid,
+        path,
+        permissive,
+        fields,
+        record: line.to_string(),
+    })
+}
+
+/// A rule in the profile's local include that allows exactly the denied access, and
+/// complain mode for the whole profile
+pub(crate) async fn fixes(transport: &dyn Transport, group: &DenialGroup) -> Vec<MacFix> {
+    let mut fixes = Vec::new();
+    let file = profile_file(transport, &group.subject).await;
+
+    if let Some(rule) = rule(group) {
+        let reason = match &file {
+            Some(file) => {
+                let name = file.rsplit('/').next().unwrap_or(file);
+                format!("add `{}` to {}/local/{}, which the profile includes and package updates leave alone, then \
+                    reload it with `apparmor_parser -r {}`", rule, PROFILE_DIR, name, file)
+            }
+            None => format!("add `{}` to the profile {}, or to the local file it includes, then reload it with \
+                `apparmor_parser -r`", rule, group.subject),
+        };
+        fixes.push(MacFix {
+            title: format!("Allow {} in {}", group.object, group.subject),
+            reason,
+            plan: None,
+        });
+    }
+
+    if group.enforced {
+        let target = file.clone().unwrap_or_else(|| group.subject.clone());
+        fixes.push(MacFix {
+            title: format!("Put {} in complain mode", group.subject),
+            reason: "the profile stops denying anything and only logs, which confirms AppArmor is the cause; add the \
+                missing rule and switch back with aa-enforce".to_string(),
+            plan: Some(ExecutionPlan {
+                description: format!("Put the AppArmor profile {} in complain mode", group.subject),
+                steps: vec![vec!["aa-complain".to_string(), target.clone()]],
+                rollback: Some(format!("aa-enforce {}", target)),
+            }),
+        });
+    }
+    fixes
+}
+
+/// The profile rule that allows the group's access
+fn rule(group: &DenialGroup) -> Option<String> {
+    match group.class.as_str() {
+        "file" => {
+            // Creating and deleting files are covered by write in rules
+            let letters = group.permissions.concat();
+            let mut permissions: String = FILE_PERMISSIONS.chars()
+                .filter(|p| letters.contains(*p) || (*p == 'w' && letters.contains(['c', 'd'])))
+                .collect();
+            if letters.contains('x') {
+                permissions.push_str("ix");
+            }
+            if permissions.is_empty() {
+                return None;
+            }
+            let path = if group.object.contains(char::is_whitespace) {
+                format!("\"{}\"", group.object)
+            } else {
+                group.object.clone()
+            };
+            Some(format!("{} {},", path, permissions))
+        }
+        "cap" | "net" => Some(format!("{},", group.object)),
+        _ => None,
+    }
+}
+
+/// Profiles for programs are stored under the program's path with dots for slashes,
+/// such as /etc/apparmor.d/usr.sbin.mysqld
+async fn profile_file(transport: &dyn Transport, profile: &str) -> Option<String> {
+    let path = format!("{}/{}", PROFILE_DIR, profile.trim_start_matches('/').replace('/', "."));
+    transport.path_exists(&path).await.then_some(path)
+}
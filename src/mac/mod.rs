@@ -0,0 +1,491 @@
+//! Mandatory access control: SELinux and AppArmor.
+//!
+//! A program that gets "Permission denied" although the file modes allow the access
+//! was usually stopped by the security module, and nothing in the error says so. The
+//! denials themselves are in the audit log, or in the journal when auditd is not
+//! running. They are grouped by who was denied what, tied back to the service whose
+//! process was denied, and paired with the narrowest change that allows the access: a
+//! file label or a boolean before a port label, and a rule in the profile's local
+//! include before putting a whole domain or profile into permissive or complain mode.
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::execution::ExecutionPlan;
+use crate::journal::{JournalQuery, JournalReader};
+use crate::remote::transport::Transport;
+
+pub mod apparmor;
+pub mod selinux;
+
+pub use apparmor::AppArmorStatus;
+pub use selinux::{SelinuxMode, SelinuxStatus};
+
+pub const AUDIT_LOG: &str = "/var/log/audit/audit.log";
+/// Lines read from the end of the audit log; auditd rotates it at a few megabytes
+const AUDIT_TAIL: &str = "20000";
+/// Security modules the kernel runs, in the order it calls them
+const LSM_LIST: &str = "/sys/kernel/security/lsm";
+/// Paths and ports kept per group
+const MAX_SAMPLES: usize = 5;
+/// Audit fields the kernel hex encodes when their value contains spaces or quotes
+const UNTRUSTED_FIELDS: &[&str] = &["name", "path", "comm", "exe", "cwd", "proctitle"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum MacSystem {
+    SELinux,
+    AppArmor,
+}
+
+impl fmt::Display for MacSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MacSystem::SELinux => write!(f, "SELinux"),
+            MacSystem::AppArmor => write!(f, "AppArmor"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MacStatus {
+    /// Active security modules, when securityfs is mounted
+    pub lsms: Vec<String>,
+    pub selinux: Option<SelinuxStatus>,
+    pub apparmor: Option<AppArmorStatus>,
+}
+
+impl MacStatus {
+    /// Whether SELinux or AppArmor can deny anything on this machine
+    pub fn enforcing(&self) -> bool {
+        self.selinux.as_ref().is_some_and(|selinux| selinux.mode == SelinuxMode::Enforcing)
+            || self.apparmor.as_ref().is_some_and(|apparmor| !apparmor.enforcing.is_empty())
+    }
+}
+
+impl fmt::Display for MacStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(selinux) = &self.selinux {
+            parts.push(selinux.to_string());
+        }
+        if let Some(apparmor) = &self.apparmor {
+            parts.push(apparmor.to_string());
+        }
+        if parts.is_empty() {
+            write!(f, "no SELinux or AppArmor")
+        } else {
+            write!(f, "{}", parts.join("; "))
+        }
+    }
+}
+
+/// One access the security module refused, or only logged in permissive and complain mode
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Denial {
+    pub system: MacSystem,
+    pub at: Option<DateTime<Utc>>,
+    /// SELinux domain of the process, or the AppArmor profile confining it
+    pub subject: String,
+    /// SELinux type of the target, or the file, capability or network access for AppArmor
+    pub object: String,
+    /// Object class such as "file" or "tcp_socket", or AppArmor's "file", "cap" and "net"
+    pub class: String,
+    /// Denied permissions, such as "read" and "open", or AppArmor's mask letters
+    pub permissions: Vec<String>,
+    pub comm: Option<String>,
+    pub pid: Option<u32>,
+    /// Absolute path of the target, when the record names one
+    pub path: Option<String>,
+    /// Logged but allowed: SELinux in permissive mode or an AppArmor profile in complain mode
+    pub permissive: bool,
+    /// Every key=value field of the record
+    pub fields: BTreeMap<String, String>,
+    /// The record in audit.log's format, as audit2why reads it
+    pub record: String,
+}
+
+impl Denial {
+    fn key(&self) -> (MacSystem, &str, &str, &str) {
+        (self.system, &self.subject, &self.object, &self.class)
+    }
+}
+
+/// The SELinux or AppArmor denial in a log line, from the audit log, the journal or dmesg
+pub fn parse_denial(line: &str) -> Option<Denial> {
+    selinux::parse(line).or_else(|| apparmor::parse(line))
+}
+
+/// key=value fields of an audit record. Values are quoted, or hex encoded when the
+/// kernel did not trust them to be printable. The first occurrence of a key wins.
+pub fn audit_fields(text: &str) -> BTreeMap<String, String> {
+    let mut fields = BTreeMap::new();
+    let mut rest = text;
+    while let Some(equals) = rest.find('=') {
+        let key_start = rest[..equals].rfind(|c: char| c.is_whitespace() || c == '\'' || c == ':').map_or(0, |i| i + 1);
+        let key = rest[key_start..equals].to_string();
+        let after = &rest[equals + 1..];
+        let (value, next) = match after.strip_prefix('"') {
+            Some(quoted) => match quoted.find('"') {
+                Some(end) => (quoted[..end].to_string(), &quoted[end + 1..]),
+                None => (quoted.to_string(), ""),
+            },
+            None => {
+                let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                let value = after[..end].trim_end_matches('\'');
+                let value = if UNTRUSTED_FIELDS.contains(&key.as_str()) {
+                    decode_hex(value).unwrap_or_else(|| value.to_string())
+                } else {
+                    value.to_string()
+                };
+                (value, &after[end..])
+            }
+        };
+        if !key.is_empty() {
+            fields.entry(key).or_insert(value);
+        }
+        rest = next;
+    }
+    fields
+}
+
+fn decode_hex(value: &str) -> Option<String> {
+    if value.len() < 2 || !value.len().is_multiple_of(2) || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let bytes: Option<Vec<u8>> = (0..value.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).ok())
+        .collect();
+    // NUL separates the arguments in proctitle
+    Some(String::from_utf8_lossy(&bytes?).replace('\0', " "))
+}
+
+/// The "1729238400.123:457" in "audit(1729238400.123:457)"
+fn audit_stamp(line: &str) -> Option<&str> {
+    let start = line.find("audit(")? + "audit(".len();
+    let end = start + line[start..].find(')')?;
+    Some(&line[start..end])
+}
+
+fn audit_time(line: &str) -> Option<DateTime<Utc>> {
+    let (seconds, _) = audit_stamp(line)?.split_once(':')?;
+    let (whole, fraction) = seconds.split_once('.').unwrap_or((seconds, "0"));
+    let millis = format!("{:0<3}", fraction).get(..3)?.parse::<i64>().ok()?;
+    Utc.timestamp_millis_opt(whole.parse::<i64>().ok()? * 1000 + millis).single()
+}
+
+/// A change that would allow a group of denials. Fixes with a plan can be applied
+/// through the executor; the others need a manual change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MacFix {
+    pub title: String,
+    pub reason: String,
+    pub plan: Option<ExecutionPlan>,
+}
+
+/// Denials of the same access: one subject, one object, one class
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DenialGroup {
+    pub system: MacSystem,
+    pub subject: String,
+    pub object: String,
+    pub class: String,
+    pub permissions: Vec<String>,
+    pub count: usize,
+    pub first: Option<DateTime<Utc>>,
+    pub last: Option<DateTime<Utc>>,
+    /// Command names of the denied processes
+    pub commands: Vec<String>,
+    pub paths: Vec<String>,
+    /// Ports the process tried to bind or connect to
+    pub ports: Vec<u16>,
+    /// Service the denied process belongs to
+    pub service: Option<String>,
+    pub service_failed: bool,
+    /// False when every denial was only logged
+    pub enforced: bool,
+    /// Most specific fix first
+    pub fixes: Vec<MacFix>,
+    /// Newest record, for audit2why
+    #[serde(skip)]
+    record: String,
+    #[serde(skip)]
+    pids: Vec<u32>,
+}
+
+impl DenialGroup {
+    /// What was denied, in a sentence
+    pub fn explanation(&self) -> String {
+        let who = match self.commands.first() {
+            Some(command) => format!("{} ({})", command, self.subject),
+            None => self.subject.clone(),
+        };
+        let what = match self.system {
+            MacSystem::SELinux => format!("{} {} on {} labelled {}", self.verb(), self.permissions.join(", "),
+                article(&self.class), self.object),
+            MacSystem::AppArmor => match self.class.as_str() {
+                "file" => format!("{} {} access to {}", self.verb(), self.permissions.join(""), self.object),
+                _ => format!("{} {}", self.verb(), self.object),
+            },
+        };
+        let mut sentence = format!("{} {}", who, what);
+        if self.system == MacSystem::SELinux && !self.paths.is_empty() {
+            sentence.push_str(&format!(" ({})", self.paths.join(", ")));
+        }
+        if !self.ports.is_empty() {
+            sentence.push_str(&format!(" on port {}", self.ports.iter().map(u16::to_string).collect::<Vec<_>>().join(", ")));
+        }
+        if self.count > 1 {
+            sentence.push_str(&format!(", {} times", self.count));
+        }
+        if !self.enforced {
+            sentence.push_str(match self.system {
+                MacSystem::SELinux => "; only logged because the domain is permissive",
+                MacSystem::AppArmor => "; only logged because the profile is in complain mode",
+            });
+        }
+        sentence
+    }
+
+    fn verb(&self) -> &'static str {
+        if self.enforced { "was denied" } else { "would be denied" }
+    }
+
+    fn add(&mut self, denial: &Denial) {
+        self.count += 1;
+        self.enforced |= !denial.permissive;
+        for permission in &denial.permissions {
+            if !self.permissions.contains(permission) {
+                self.permissions.push(permission.clone());
+            }
+        }
+        if let Some(at) = denial.at {
+            self.first = Some(self.first.map_or(at, |first| first.min(at)));
+            if self.last.is_none_or(|last| at >= last) {
+                self.last = Some(at);
+                self.record = denial.record.clone();
+            }
+        } else if self.record.is_empty() {
+            self.record = denial.record.clone();
+        }
+        push_sample(&mut self.commands, denial.comm.clone());
+        push_sample(&mut self.paths, denial.path.clone());
+        let port = denial.fields.get("src").or_else(|| denial.fields.get("dest")).and_then(|port| port.parse().ok());
+        push_sample(&mut self.ports, port);
+        push_sample(&mut self.pids, denial.pid);
+    }
+}
+
+fn push_sample<T: PartialEq>(samples: &mut Vec<T>, value: Option<T>) {
+    if let Some(value) = value {
+        if samples.len() < MAX_SAMPLES && !samples.contains(&value) {
+            samples.push(value);
+        }
+    }
+}
+
+fn article(class: &str) -> String {
+    match class.chars().next() {
+        Some('a' | 'e' | 'i' | 'o' | 'u') => format!("an {}", class),
+        _ => format!("a {}", class),
+    }
+}
+
+/// Denials grouped by subject, object and class; enforced groups first, then the most
+/// frequent
+pub fn group(denials: &[Denial]) -> Vec<DenialGroup> {
+    let mut groups: Vec<DenialGroup> = Vec::new();
+    for denial in denials {
+        let group = match groups.iter().position(|group| (group.system, group.subject.as_str(), group.object.as_str(), group.class.as_str()) == denial.key()) {
+            Some(index) => &mut groups[index],
+            None => {
+                groups.push(DenialGroup {
+                    system: denial.system,
+                    subject: denial.subject.clone(),
+                    object: denial.object.clone(),
+                    class: denial.class.clone(),
+                    permissions: Vec::new(),
+                    count: 0,
+                    first: None,
+                    last: None,
+                    commands: Vec::new(),
+                    paths: Vec::new(),
+                    ports: Vec::new(),
+                    service: None,
+                    service_failed: false,
+                    enforced: false,
+                    fixes: Vec::new(),
+                    record: String::new(),
+                    pids: Vec::new(),
+                });
+                groups.last_mut().expect("just pushed")
+            }
+        };
+        group.add(denial);
+    }
+    groups.sort_by(|a, b| b.enforced.cmp(&a.enforced).then(b.count.cmp(&a.count)));
+    groups
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MacReport {
+    pub hostname: String,
+    pub status: MacStatus,
+    /// Where the denials were read: the audit log or the journal
+    pub source: Option<String>,
+    pub groups: Vec<DenialGroup>,
+    /// Sources that could not be read
+    pub errors: Vec<String>,
+}
+
+impl MacReport {
+    /// Every fix, in the order they are numbered for `--apply`
+    pub fn fixes(&self) -> Vec<&MacFix> {
+        self.groups.iter().flat_map(|group| group.fixes.iter()).collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MacInspector {
+    transport: Arc<dyn Transport>,
+}
+
+impl MacInspector {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    /// Which security modules are active, and in which mode
+    pub async fn status(&self) -> MacStatus {
+        let transport = self.transport.as_ref();
+        let lsms = transport.read_file(LSM_LIST).await
+            .map(|list| list.trim().split(',').filter(|lsm| !lsm.is_empty()).map(String::from).collect())
+            .unwrap_or_default();
+        MacStatus {
+            lsms,
+            selinux: selinux::status(transport).await,
+            apparmor: apparmor::status(transport).await,
+        }
+    }
+
+    /// Denials logged since `since`, from the audit log when it can be read and from
+    /// the journal otherwise, with where they were read and what could not be
+    pub async fn denials(&self, since: DateTime<Utc>) -> (Vec<Denial>, Option<String>, Vec<String>) {
+        let mut errors = Vec::new();
+        if self.transport.path_exists(AUDIT_LOG).await {
+            match self.transport.run("tail", &["-n", AUDIT_TAIL, AUDIT_LOG]).await {
+                Ok(output) if output.success() => {
+                    let denials = output.stdout.lines()
+                        .filter_map(parse_denial)
+                        .filter(|denial| denial.at.is_none_or(|at| at >= since))
+                        .collect();
+                    return (denials, Some(AUDIT_LOG.to_string()), errors);
+                }
+                Ok(output) => errors.push(format!("{}: {}", AUDIT_LOG, output.stderr.trim())),
+                Err(e) => errors.push(format!("{}: {}", AUDIT_LOG, e)),
+            }
+        }
+
+        // Without auditd the kernel's audit records go to the journal, and AppArmor
+        // denials also show up as kernel messages
+        let query = JournalQuery {
+            transports: vec!["audit".to_string(), "kernel".to_string()],
+            since: Some(format!("@{}", since.timestamp())),
+            ..Default::default()
+        };
+        match JournalReader::new(self.transport.clone()).read(&query).await {
+            Ok(records) => {
+                let mut denials: Vec<Denial> = Vec::new();
+                for record in records {
+                    let Some(mut denial) = parse_denial(&record.message) else {
+                        continue;
+                    };
+                    denial.at = denial.at.or(Some(record.timestamp));
+                    // Both transports carry the same record when auditd is not running
+                    if !denials.iter().any(|seen| seen.record == denial.record && seen.at == denial.at) {
+                        denials.push(denial);
+                    }
+                }
+                (denials, Some("journal".to_string()), errors)
+            }
+            Err(e) => {
+                errors.push(e.to_string());
+                (Vec::new(), None, errors)
+            }
+        }
+    }
+
+    /// Denials since `since`, grouped, tied to their services and with fixes
+    pub async fn report(&self, since: DateTime<Utc>) -> MacReport {
+        let (denials, source, errors) = self.denials(since).await;
+        MacReport {
+            hostname: self.transport.host_name().to_string(),
+            status: self.status().await,
+            source,
+            groups: self.explain(&denials).await,
+            errors,
+        }
+    }
+
+    /// Group denials, find the service behind each group and propose fixes
+    pub async fn explain(&self, denials: &[Denial]) -> Vec<DenialGroup> {
+        let mut groups = group(denials);
+        let failed = self.failed_services().await;
+        for group in &mut groups {
+            group.service = self.service_of(group).await;
+            group.service_failed = group.service.as_ref().is_some_and(|service| failed.contains(service));
+            group.fixes = match group.system {
+                MacSystem::SELinux => selinux::fixes(self.transport.as_ref(), group).await,
+                MacSystem::AppArmor => apparmor::fixes(self.transport.as_ref(), group).await,
+            };
+        }
+        groups
+    }
+
+    /// The unit whose cgroup a denied process was in, or a service named after the program
+    async fn service_of(&self, group: &DenialGroup) -> Option<String> {
+        for pid in &group.pids {
+            let Ok(cgroup) = self.transport.read_file(&format!("/proc/{}/cgroup", pid)).await else {
+                continue;
+            };
+            let unit = cgroup.lines()
+                .filter_map(|line| line.rsplit('/').next())
+                .find(|name| name.ends_with(".service"));
+            if let Some(unit) = unit {
+                return Some(unit.to_string());
+            }
+        }
+
+        let mut programs: Vec<&str> = group.commands.iter().map(String::as_str).collect();
+        if group.system == MacSystem::AppArmor {
+            programs.push(group.subject.rsplit('/').next().unwrap_or(&group.subject));
+        }
+        for program in programs {
+            let unit = format!("{}.service", program);
+            let loaded = self.transport.run("systemctl", &["show", "-p", "LoadState", "--value", &unit]).await
+                .is_ok_and(|output| output.success() && output.stdout.trim() == "loaded");
+            if loaded {
+                return Some(unit);
+            }
+        }
+        None
+    }
+
+    async fn failed_services(&self) -> Vec<String> {
+        let Ok(output) = self.transport.run("systemctl", &["list-units", "--failed", "--type=service", "--plain", "--no-legend"]).await else {
+            return Vec::new();
+        };
+        output.stdout.lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .map(String::from)
+            .collect()
+    }
+}
+
+/// The type in an SELinux context such as "system_u:system_r:httpd_t:s0"
+pub(crate) fn context_type(context: &str) -> Option<&str> {
+    context.split(':').nth(2)
+}
+
@@ -0,0 +1,317 @@
+//! SELinux AVC denials and the policy changes that allow them.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::LazyLock;
+
+use crate::execution::ExecutionPlan;
+use crate::remote::transport::{shell_quote, Transport};
+use super::{audit_fields, audit_stamp, audit_time, context_type, Denial, DenialGroup, MacFix, MacSystem};
+
+const ENFORCE: &str = "/sys/fs/selinux/enforce";
+
+static AVC: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"avc:\s+denied\s+\{([^}]*)\}\s+for\s+(.*)$").expect("valid regex"));
+static SETSEBOOL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"setsebool -P (\S+) (\d)").expect("valid regex"));
+
+/// Classes whose targets are files, where a wrong label is the usual cause
+const FILE_CLASSES: &[&str] = &["file", "dir", "lnk_file", "sock_file", "fifo_file", "chr_file", "blk_file"];
+/// Types the policy gives files in places no rule covers; relabelling to them does not
+/// help, the location needs its own file context
+const GENERIC_TYPES: &[&str] = &["default_t", "var_t", "usr_t", "root_t", "etc_runtime_t"];
+
+/// Booleans that allow a common access: the domain, the target type and the
+/// permission it was denied, either empty for any. More specific entries come first.
+const BOOLEANS: &[(&str, &str, &str, &str)] = &[
+    ("httpd_t", "mysqld_port_t", "name_connect", "httpd_can_network_connect_db"),
+    ("httpd_t", "postgresql_port_t", "name_connect", "httpd_can_network_connect_db"),
+    ("httpd_t", "smtp_port_t", "name_connect", "httpd_can_sendmail"),
+    ("httpd_t", "", "name_connect", "httpd_can_network_connect"),
+    ("httpd_t", "user_home_dir_t", "", "httpd_enable_homedirs"),
+    ("httpd_t", "user_home_t", "", "httpd_read_user_content"),
+    ("httpd_t", "nfs_t", "", "httpd_use_nfs"),
+    ("httpd_t", "cifs_t", "", "httpd_use_cifs"),
+    ("haproxy_t", "", "name_connect", "haproxy_connect_any"),
+    ("nginx_t", "", "name_connect", "httpd_can_network_connect"),
+    ("smbd_t", "user_home_t", "", "samba_enable_home_dirs"),
+    ("smbd_t", "user_home_dir_t", "", "samba_enable_home_dirs"),
+    ("named_t", "named_zone_t", "write", "named_write_master_zones"),
+    ("container_t", "nfs_t", "", "virt_use_nfs"),
+    ("svirt_t", "nfs_t", "", "virt_use_nfs"),
+    ("container_t", "cifs_t", "", "virt_use_samba"),
+    ("svirt_t", "cifs_t", "", "virt_use_samba"),
+];
+
+/// Port type a domain may bind, for services moved to a non-standard port
+const PORT_TYPES: &[(&str, &str)] = &[
+    ("httpd_t", "http_port_t"),
+    ("sshd_t", "ssh_port_t"),
+    ("mysqld_t", "mysqld_port_t"),
+    ("postgresql_t", "postgresql_port_t"),
+    ("named_t", "dns_port_t"),
+    ("redis_t", "redis_port_t"),
+    ("squid_t", "http_cache_port_t"),
+    ("postfix_master_t", "smtp_port_t"),
+];
+
+/// Type for content a domain serves or stores, for directories outside the places the
+/// policy knows
+const CONTENT_TYPES: &[(&str, &str)] = &[
+    ("httpd_t", "httpd_sys_content_t"),
+    ("mysqld_t", "mysqld_db_t"),
+    ("postgresql_t", "postgresql_db_t"),
+    ("named_t", "named_zone_t"),
+    ("smbd_t", "samba_share_t"),
+    ("container_t", "container_file_t"),
+    ("ftpd_t", "public_content_t"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SelinuxMode {
+    Enforcing,
+    Permissive,
+    Disabled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SelinuxStatus {
+    pub mode: SelinuxMode,
+    /// Loaded policy, such as "targeted"
+    pub policy: Option<String>,
+}
+
+impl fmt::Display for SelinuxStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = match self.mode {
+            SelinuxMode::Enforcing => "enforcing",
+            SelinuxMode::Permissive => "permissive",
+            SelinuxMode::Disabled => "disabled",
+        };
+        write!(f, "SELinux {}", mode)?;
+        if let Some(policy) = &self.policy {
+            write!(f, " ({} policy)", policy)?;
+        }
+        Ok(())
+    }
+}
+
+/// SELinux's mode from selinuxfs, or from getenforce where it is not mounted; `None`
+/// when the kernel or userspace has no SELinux at all
+pub async fn status(transport: &dyn Transport) -> Option<SelinuxStatus> {
+    let mode = match transport.read_file(ENFORCE).await {
+        Ok(enforce) if enforce.trim() == "1" => SelinuxMode::Enforcing,
+        Ok(_) => SelinuxMode::Permissive,
+        Err(_) => {
+            let output = transport.run("getenforce", &[]).await.ok().filter(|output| output.success())?;
+            match output.stdout.trim() {
+                "Enforcing" => SelinuxMode::Enforcing,
+                "Permissive" => SelinuxMode::Permissive,
+                _ => SelinuxMode::Disabled,
+            }
+        }
+    };
+    let policy = transport.run("sestatus", &[]).await.ok()
+        .filter(|output| output.success())
+        .and_then(|output| output.stdout.lines()
+            .find_map(|line| line.strip_prefix("Loaded policy name:").map(|name| name.trim().to_string())));
+    Some(SelinuxStatus { mode, policy })
+}
+
+/// An AVC denial, as auditd writes it or as the kernel logs it without auditd:
+/// `avc:  denied  { read } for  pid=812 comm="nginx" name="index.html" ... tclass=file permissive=0`
+pub fn parse(line: &str) -> Option<Denial> {
+    let captures = AVC.captures(line)?;
+    let permissions: Vec<String> = captures[1].split_whitespace().map(String::from).collect();
+    let rest = captures[2].trim_end();
+    let fields = audit_fields(rest);
+
+    let subject = context_type(fields.get("scontext")?)?.to_string();
+    let object = context_type(fields.get("tcontext")?)?.to_string();
+    let class = fields.get("tclass")?.clone();
+    let path = fields.get("path").or_else(|| fields.get("name"))
+        .filter(|path| path.starts_with('/'))
+        .cloned();
+    let record = format!("type=AVC msg=audit({}): avc:  denied  {{ {} }} for  {}",
+        audit_stamp(line).unwrap_or("0.000:0"), permissions.join(" "), rest.trim_end_matches('\''));
+
+    Some(Denial {
+        system: MacSystem::SELinux,
+        at: audit_time(line),
+        subject,
+        object,
+        class,
+        permissions,
+        comm: fields.get("comm").cloned(),
+        pid: fields.get("pid").and_then(|pid| pid.parse().ok()),
+        path,
+        permissive: fields.get("permissive").map(String::as_str) == Some("1"),
+        fields,
+        record,
+    })
+}
+
+/// Fixes for a group of denials, the most targeted first: restore a wrong label, add a
+/// file context, turn on a boolean, label a port, and as a last resort make the domain
+/// permissive
+pub(crate) async fn fixes(transport: &dyn Transport, group: &DenialGroup) -> Vec<MacFix> {
+    let mut fixes = Vec::new();
+
+    if FILE_CLASSES.contains(&group.class.as_str()) {
+        for path in &group.paths {
+            let Some(expected) = expected_type(transport, path).await else {
+                continue;
+            };
+            if GENERIC_TYPES.contains(&expected.as_str()) {
+                fixes.extend(file_context(group, path));
+            } else if expected != group.object {
+                fixes.push(relabel(group, path, &expected));
+            }
+        }
+    }
+
+    let mut booleans = audit2why(transport, &group.record).await;
+    if booleans.is_empty() {
+        for (domain, target, permission, boolean) in BOOLEANS {
+            let applies = *domain == group.subject
+                && (target.is_empty() || *target == group.object)
+                && (permission.is_empty() || group.permissions.iter().any(|p| p == permission));
+            if applies && !booleans.iter().any(|(name, _)| name == boolean) && boolean_is_off(transport, boolean).await {
+                booleans.push((boolean.to_string(), None));
+            }
+        }
+    }
+    for (boolean, description) in booleans {
+        let description = description.map(|d| format!(" ({})", d.trim_end_matches('.'))).unwrap_or_default();
+        fixes.push(MacFix {
+            title: format!("Turn on {}", boolean),
+            reason: format!("the policy already has a rule for this access, switched off by default{}; it applies to \
+                everything running as {}, not only this program", description, group.subject),
+            plan: Some(ExecutionPlan {
+                description: format!("Turn on the SELinux boolean {}", boolean),
+                steps: vec![vec!["setsebool".to_string(), "-P".to_string(), boolean.clone(), "1".to_string()]],
+                rollback: Some(format!("setsebool -P {} 0", boolean)),
+            }),
+        });
+    }
+
+    let protocol = match group.class.as_str() {
+        "tcp_socket" => Some("tcp"),
+        "udp_socket" => Some("udp"),
+        _ => None,
+    };
+    let port_type = PORT_TYPES.iter().find(|(domain, _)| *domain == group.subject).map(|(_, port_type)| *port_type);
+    if let (Some(protocol), Some(port_type), true) = (protocol, port_type, group.permissions.iter().any(|p| p == "name_bind")) {
+        for port in &group.ports {
+            fixes.push(MacFix {
+                title: format!("Label {}/{} as {}", protocol, port, port_type),
+                reason: format!("{} may only listen on ports labelled {}, and {} is not one of them", group.subject, port_type, port),
+                plan: Some(ExecutionPlan {
+                    description: format!("Allow {} to listen on {}/{}", group.subject, protocol, port),
+                    steps: vec![vec!["semanage".to_string(), "port".to_string(), "-a".to_string(), "-t".to_string(),
+                        port_type.to_string(), "-p".to_string(), protocol.to_string(), port.to_string()]],
+                    rollback: Some(format!("semanage port -d -t {} -p {} {}", port_type, protocol, port)),
+                }),
+            });
+        }
+    }
+
+    if group.enforced {
+        fixes.push(MacFix {
+            title: format!("Make {} permissive", group.subject),
+            reason: format!("last resort: SELinux stops enforcing anything for {} while the rest of the system stays \
+                enforcing, and keeps logging what it would deny so a local module can be written with audit2allow",
+                group.subject),
+            plan: Some(ExecutionPlan {
+                description: format!("Make the SELinux domain {} permissive", group.subject),
+                steps: vec![vec!["semanage".to_string(), "permissive".to_string(), "-a".to_string(), group.subject.clone()]],
+                rollback: Some(format!("semanage permissive -d {}", group.subject)),
+            }),
+        });
+    }
+    fixes
+}
+
+/// The type file context rules give `path`
+async fn expected_type(transport: &dyn Transport, path: &str) -> Option<String> {
+    let output = transport.run("matchpathcon", &["-n", path]).await.ok().filter(|output| output.success())?;
+    context_type(output.stdout.trim()).map(String::from)
+}
+
+fn relabel(group: &DenialGroup, path: &str, expected: &str) -> MacFix {
+    let mut steps = vec!["restorecon".to_string()];
+    if group.class == "dir" {
+        steps.push("-R".to_string());
+    }
+    steps.extend(["-v".to_string(), path.to_string()]);
+    MacFix {
+        title: format!("Relabel {}", path),
+        reason: format!("it is labelled {} but the policy expects {} there; files moved with `mv` keep the label of \
+            the place they were created", group.object, expected),
+        plan: Some(ExecutionPlan {
+            description: format!("Restore the SELinux label of {}", path),
+            steps: vec![steps],
+            rollback: Some(format!("chcon {}-t {} {}", if group.class == "dir" { "-R " } else { "" }, group.object, shell_quote(path))),
+        }),
+    }
+}
+
+/// A file context rule for the directory of `path`, when the domain has a usual type
+/// for its content
+fn file_context(group: &DenialGroup, path: &str) -> Option<MacFix> {
+    let (_, content_type) = CONTENT_TYPES.iter().find(|(domain, _)| *domain == group.subject)?;
+    let directory = if group.class == "dir" {
+        path
+    } else {
+        path.rsplit_once('/').map(|(parent, _)| parent).filter(|parent| !parent.is_empty())?
+    };
+    let pattern = format!("{}(/.*)?", directory);
+    Some(MacFix {
+        title: format!("Label {} as {}", directory, content_type),
+        reason: format!("no file context rule covers {}, so its files get a generic type {} may not use; a rule for the \
+            directory keeps the label across relabels", directory, group.subject),
+        plan: Some(ExecutionPlan {
+            description: format!("Add a file context for {} and relabel it", directory),
+            steps: vec![
+                vec!["semanage".to_string(), "fcontext".to_string(), "-a".to_string(), "-t".to_string(),
+                    content_type.to_string(), pattern.clone()],
+                vec!["restorecon".to_string(), "-R".to_string(), "-v".to_string(), directory.to_string()],
+            ],
+            rollback: Some(format!("semanage fcontext -d {} && restorecon -R -v {}", shell_quote(&pattern), shell_quote(directory))),
+        }),
+    })
+}
+
+/// Booleans audit2why says would allow the record, with their descriptions
+async fn audit2why(transport: &dyn Transport, record: &str) -> Vec<(String, Option<String>)> {
+    if record.is_empty() {
+        return Vec::new();
+    }
+    let script = format!("printf '%s\\n' {} | audit2why", shell_quote(record));
+    match transport.run("sh", &["-c", &script]).await {
+        Ok(output) if output.success() => parse_audit2why(&output.stdout),
+        _ => Vec::new(),
+    }
+}
+
+/// "Description:" is followed by the boolean's description and, a few lines later, the
+/// setsebool command that turns it on
+fn parse_audit2why(output: &str) -> Vec<(String, Option<String>)> {
+    let mut booleans = Vec::new();
+    let mut description = None;
+    let mut lines = output.lines().map(str::trim);
+    while let Some(line) = lines.next() {
+        if line == "Description:" {
+            description = lines.next().map(String::from).filter(|d| !d.is_empty());
+        } else if let Some(captures) = SETSEBOOL.captures(line) {
+            if &captures[2] == "1" {
+                booleans.push((captures[1].to_string(), description.take()));
+            }
+        }
+    }
+    booleans
+}
+
+async fn boolean_is_off(transport: &dyn Transport, boolean: &str) -> bool {
+    transport.run("getsebool", &[boolean]).await
+        .is_ok_and(|output| output.success() && output.stdout.trim().ends_with("off"))
+}
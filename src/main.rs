@@ -1,22 +1,37 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{Args as ClapArgs, Parser, Subcommand};
 use std::path::PathBuf;
 
+mod accounts;
 mod agents;
 mod ai;
 mod automation;
 mod cli;
 mod config;
 mod containers;
+mod crashes;
+mod environment;
 mod error_diagnosis;
 mod execution;
+mod firewall;
+mod journal;
+mod kernel_log;
 mod linux_integration;
+mod mac;
 mod mcp;
 mod monitoring;
+mod network;
 mod nlp;
+mod packages;
 mod performance;
 mod plugins;
+mod remote;
+mod schedule;
 mod security;
+mod services;
+mod session;
+mod storage;
+mod system_image;
 mod system_monitor;
 mod web;
 
@@ -43,19 +58,31 @@ struct Args {
     /// Use local AI model instead of cloud API
     #[arg(long)]
     local: bool,
+
+    /// Record commands, AI exchanges and approval answers into a session archive
+    #[arg(long, global = true, value_name = "ARCHIVE")]
+    record: Option<PathBuf>,
+
+    /// Replay a recorded session archive instead of running commands and calling the AI
+    #[arg(long, global = true, value_name = "ARCHIVE", conflicts_with = "record")]
+    replay: Option<PathBuf>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Diagnose system errors and provide solutions
     Diagnose {
-        /// Error message or log file to analyze
+        /// Error message, or a log file to analyze (syslog, journal JSON, nginx/apache,
+        /// Xorg, pacman, dpkg or dmesg); "-" or a pipe reads the log from stdin
         #[arg(short, long)]
         input: Option<String>,
         
         /// Analyze system logs automatically
         #[arg(long)]
         auto: bool,
+
+        #[command(flatten)]
+        targets: TargetArgs,
     },
     
     /// Get help with Linux commands
@@ -69,14 +96,54 @@ enum Commands {
         /// Continuous monitoring mode
         #[arg(short, long)]
         continuous: bool,
+
+        #[command(flatten)]
+        targets: TargetArgs,
     },
     
+    /// Show journal entries, filtered by unit, priority, boot, process or time
+    Logs {
+        /// Only entries of this systemd unit (repeatable)
+        #[arg(short, long = "unit")]
+        units: Vec<String>,
+        /// Least severe priority to show: emerg, alert, crit, err, warning, notice, info, debug or 0-7
+        #[arg(short, long)]
+        priority: Option<String>,
+        /// Only this boot: a boot ID, or an offset such as 0 (current) or -1 (previous)
+        #[arg(short, long, allow_hyphen_values = true)]
+        boot: Option<String>,
+        /// Only entries of this process
+        #[arg(long)]
+        pid: Option<u32>,
+        /// Only entries at or after this time, e.g. "-1h", "yesterday" or "2024-05-01 10:00"
+        #[arg(long, allow_hyphen_values = true)]
+        since: Option<String>,
+        /// Only entries at or before this time
+        #[arg(long, allow_hyphen_values = true)]
+        until: Option<String>,
+        /// Number of recent entries to show
+        #[arg(short = 'n', long, default_value = "50")]
+        lines: usize,
+        /// Keep printing new entries as they are written
+        #[arg(short, long)]
+        follow: bool,
+        /// Print each entry as JSON with all of its journal fields
+        #[arg(long)]
+        json: bool,
+    },
+
     /// Package management assistance
     Package {
-        /// Package operation (install, remove, update, search)
+        /// Package operation (install, remove, update, upgrade, search, info, list, upgradable, clean)
         operation: String,
         /// Package name
         package: Option<String>,
+        /// Only search or list this package manager, e.g. flatpak or snap
+        #[arg(long)]
+        source: Option<String>,
+
+        #[command(flatten)]
+        targets: TargetArgs,
     },
     
     /// Service management help
@@ -87,6 +154,130 @@ enum Commands {
         action: Option<String>,
     },
     
+    /// Show the active firewall and explain it, or open and close ports
+    Firewall {
+        #[command(subcommand)]
+        action: Option<FirewallAction>,
+        /// Print the parsed ruleset as JSON
+        #[arg(long)]
+        json: bool,
+        /// Inventory host to inspect or change instead of this machine
+        #[arg(long, global = true)]
+        host: Option<String>,
+    },
+
+    /// Show and change kernel parameters and modules
+    Kernel {
+        #[command(subcommand)]
+        action: KernelAction,
+    },
+
+    /// Analyse how long booting took, track it across boots and suggest speedups
+    Boot {
+        /// Apply the suggestion with this number
+        #[arg(long)]
+        apply: Option<usize>,
+        /// List the recorded boots instead
+        #[arg(long)]
+        history: bool,
+        /// Print the analysis as JSON
+        #[arg(long)]
+        json: bool,
+        /// Execution mode for --apply (supervised, semi-auto, autonomous, read-only)
+        #[arg(long, default_value = "supervised")]
+        mode: String,
+    },
+
+    /// Check drive health, RAID and volume state, /etc/fstab and filesystem errors
+    Storage {
+        /// Print the full report as JSON
+        #[arg(long)]
+        json: bool,
+        /// Inventory host to inspect instead of this machine
+        #[arg(long)]
+        host: Option<String>,
+    },
+
+    /// Show which stack configures each network interface and explain connectivity problems
+    Network {
+        #[command(subcommand)]
+        action: Option<NetworkAction>,
+        /// Print the interfaces and problems as JSON
+        #[arg(long)]
+        json: bool,
+        /// Inventory host to inspect or change instead of this machine
+        #[arg(long, global = true)]
+        host: Option<String>,
+    },
+
+    /// List cron jobs, anacron jobs and systemd timers on one timeline, or manage timers
+    Schedule {
+        #[command(subcommand)]
+        action: Option<ScheduleAction>,
+        /// Only jobs that run in this hour of the day, such as 3 or 03:00
+        #[arg(long)]
+        at: Option<String>,
+        /// How many hours ahead the timeline reaches
+        #[arg(long, default_value = "24")]
+        hours: u32,
+        /// Print the jobs as JSON
+        #[arg(long)]
+        json: bool,
+        /// Inventory host to inspect or change instead of this machine
+        #[arg(long, global = true)]
+        host: Option<String>,
+    },
+
+    /// Show users, groups and their effective sudo privileges, or change accounts
+    Users {
+        #[command(subcommand)]
+        action: Option<UsersAction>,
+        /// Show one user in detail
+        #[arg(long)]
+        user: Option<String>,
+        /// Include service accounts
+        #[arg(long)]
+        all: bool,
+        /// Print the accounts as JSON
+        #[arg(long)]
+        json: bool,
+        /// Inventory host to inspect or change instead of this machine
+        #[arg(long, global = true)]
+        host: Option<String>,
+    },
+
+    /// List crashed programs with their signal, package and backtrace, grouped by signature
+    Crashes {
+        /// How many hours back to look for crashes
+        #[arg(long, default_value = "24")]
+        hours: u32,
+        /// Print the crash groups as JSON
+        #[arg(long)]
+        json: bool,
+        /// Inventory host to inspect instead of this machine
+        #[arg(long)]
+        host: Option<String>,
+    },
+
+    /// Show SELinux and AppArmor denials, the services they break and how to allow them
+    Mac {
+        /// How many hours back to look for denials
+        #[arg(long, default_value = "24")]
+        hours: u32,
+        /// Apply the fix with this number
+        #[arg(long)]
+        apply: Option<usize>,
+        /// Print the status and denials as JSON
+        #[arg(long)]
+        json: bool,
+        /// Execution mode for --apply (supervised, semi-auto, autonomous, read-only)
+        #[arg(long, default_value = "supervised")]
+        mode: String,
+        /// Inventory host to inspect or change instead of this machine
+        #[arg(long)]
+        host: Option<String>,
+    },
+
     /// Interactive chat mode
     Chat {
         /// Execution mode for commands
@@ -101,6 +292,11 @@ enum Commands {
         /// Execution mode (supervised, semi-auto, autonomous, read-only)
         #[arg(long, default_value = "supervised")]
         mode: String,
+        /// Exact command line to run for this description
+        #[arg(long)]
+        command: Option<String>,
+        #[command(flatten)]
+        targets: TargetArgs,
     },
 
     /// Show execution permissions and capabilities
@@ -108,6 +304,15 @@ enum Commands {
         /// Show detailed permission information
         #[arg(long)]
         detailed: bool,
+        /// Approve a permission scope for running commands as root (e.g. packages, services)
+        #[arg(long)]
+        grant: Option<String>,
+        /// Minutes the approval stays valid
+        #[arg(long, requires = "grant")]
+        minutes: Option<u32>,
+        /// Withdraw an approval early (a scope, or "all")
+        #[arg(long, conflicts_with = "grant")]
+        revoke: Option<String>,
     },
 
     /// View audit log of executed commands
@@ -115,9 +320,31 @@ enum Commands {
         /// Number of recent entries to show
         #[arg(short, long, default_value = "10")]
         limit: usize,
+        /// Page of matching entries to show
+        #[arg(long, default_value = "1")]
+        page: usize,
         /// Export format (json, csv, html)
         #[arg(long)]
         export: Option<String>,
+        #[command(flatten)]
+        filters: AuditFilterArgs,
+        #[command(subcommand)]
+        action: Option<AuditAction>,
+    },
+
+    /// Review execution requests waiting for approval
+    Approvals {
+        /// Status to list: pending (default), approved, denied, expired or all
+        #[arg(long, default_value = "pending")]
+        status: String,
+        #[command(subcommand)]
+        action: Option<ApprovalAction>,
+    },
+
+    /// Inspect recorded session archives
+    Session {
+        #[command(subcommand)]
+        action: SessionAction,
     },
 
     /// Configure TuxPilot settings
@@ -148,6 +375,394 @@ enum Commands {
         #[arg(long)]
         ssl_key: Option<PathBuf>,
     },
+
+    /// Run one approved request as root (started through sudo or pkexec)
+    #[command(hide = true)]
+    PrivilegedHelper,
+
+    /// Record an approval grant as root (started through sudo -k or pkexec, so the
+    /// user authenticates again)
+    #[command(hide = true)]
+    PrivilegedGrant,
+}
+
+#[derive(ClapArgs)]
+struct TargetArgs {
+    /// Run against this inventory host instead of locally (repeatable)
+    #[arg(long = "host")]
+    hosts: Vec<String>,
+    /// Run against every host in this inventory group, or "all" (repeatable)
+    #[arg(long = "group")]
+    groups: Vec<String>,
+}
+
+impl TargetArgs {
+    fn is_local(&self) -> bool {
+        self.hosts.is_empty() && self.groups.is_empty()
+    }
+}
+
+#[derive(ClapArgs)]
+struct AuditFilterArgs {
+    /// Only entries at or after this time (RFC 3339, YYYY-MM-DD or relative like 24h, 7d)
+    #[arg(long)]
+    since: Option<String>,
+    /// Only entries at or before this time
+    #[arg(long)]
+    until: Option<String>,
+    /// Only entries recorded for this user
+    #[arg(long)]
+    user: Option<String>,
+    /// Only entries from this session
+    #[arg(long)]
+    session: Option<String>,
+    /// Minimum risk level (safe, low, medium, high, critical)
+    #[arg(long)]
+    risk: Option<String>,
+    /// Entry type such as ExecutionRequest or SafetyViolation (repeatable)
+    #[arg(long = "type")]
+    entry_types: Vec<String>,
+    /// Only entries whose command contains this text
+    #[arg(long)]
+    grep: Option<String>,
+    /// Only successful operations
+    #[arg(long, conflicts_with = "failed")]
+    succeeded: bool,
+    /// Only failed or denied operations
+    #[arg(long)]
+    failed: bool,
+}
+
+#[derive(Subcommand)]
+enum AuditAction {
+    /// Verify the hash chain and signatures of the audit log
+    Verify,
+    /// Send a test event to every configured audit sink
+    TestSinks,
+}
+
+#[derive(Subcommand)]
+enum ApprovalAction {
+    /// Approve a queued request
+    Approve {
+        /// Approval request ID
+        id: String,
+        /// Comment recorded with the decision
+        #[arg(long)]
+        comment: Option<String>,
+    },
+    /// Deny a queued request
+    Deny {
+        /// Approval request ID
+        id: String,
+        /// Comment recorded with the decision
+        #[arg(long)]
+        comment: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum FirewallAction {
+    /// Open a port, such as 443/tcp, 6000-6010/udp or https
+    Open {
+        port: String,
+        /// Only allow connections from this address or network
+        #[arg(long)]
+        from: Option<String>,
+        /// Why the port is open, kept with the rule where the frontend allows it
+        #[arg(long)]
+        comment: Option<String>,
+        #[command(flatten)]
+        change: FirewallChangeArgs,
+    },
+    /// Close a port tuxpilot or someone else opened
+    Close {
+        port: String,
+        /// Only the rule for this address or network
+        #[arg(long)]
+        from: Option<String>,
+        #[command(flatten)]
+        change: FirewallChangeArgs,
+    },
+    /// Keep a change made over SSH, so its revert timer does nothing
+    Keep {
+        /// ID printed when the change was made
+        id: String,
+    },
+}
+
+#[derive(ClapArgs)]
+struct FirewallChangeArgs {
+    /// Over SSH, seconds until the change is reverted unless it is kept; 0 turns the timer off
+    #[arg(long, default_value = "120")]
+    revert_after: u64,
+    /// Only show the preview
+    #[arg(long)]
+    dry_run: bool,
+    /// Execution mode (supervised, semi-auto, autonomous, read-only)
+    #[arg(long, default_value = "supervised")]
+    mode: String,
+}
+
+#[derive(Subcommand)]
+enum NetworkAction {
+    /// Change the addressing of an interface in the stack that manages it
+    Set {
+        interface: String,
+        /// Get the IPv4 address by DHCP
+        #[arg(long, conflicts_with_all = ["addresses", "gateway"])]
+        dhcp: bool,
+        /// Static address with prefix length, such as 192.0.2.10/24; repeat for more
+        #[arg(long = "address")]
+        addresses: Vec<String>,
+        /// IPv4 default gateway
+        #[arg(long)]
+        gateway: Option<String>,
+        /// DNS server; repeat for more
+        #[arg(long)]
+        dns: Vec<String>,
+        /// Only show the preview
+        #[arg(long)]
+        dry_run: bool,
+        /// Execution mode (supervised, semi-auto, autonomous, read-only)
+        #[arg(long, default_value = "supervised")]
+        mode: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ScheduleAction {
+    /// Create a systemd timer that runs a command
+    Add {
+        /// Name of the timer and its service
+        name: String,
+        /// When to run, as a systemd calendar expression such as "daily" or "Mon..Fri 03:00"
+        #[arg(long)]
+        on_calendar: String,
+        /// Command line to run
+        #[arg(long)]
+        command: String,
+        /// Run as this user instead of root
+        #[arg(long)]
+        user: Option<String>,
+        /// Description of the timer
+        #[arg(long)]
+        description: Option<String>,
+        /// Only show the preview
+        #[arg(long)]
+        dry_run: bool,
+        /// Execution mode (supervised, semi-auto, autonomous, read-only)
+        #[arg(long, default_value = "supervised")]
+        mode: String,
+    },
+    /// Change when an existing timer runs
+    Edit {
+        /// Timer unit, with or without ".timer"
+        timer: String,
+        /// New schedule as a systemd calendar expression
+        #[arg(long)]
+        on_calendar: String,
+        /// Only show the preview
+        #[arg(long)]
+        dry_run: bool,
+        /// Execution mode (supervised, semi-auto, autonomous, read-only)
+        #[arg(long, default_value = "supervised")]
+        mode: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum UsersAction {
+    /// Create a user with a home directory
+    Add {
+        name: String,
+        /// Supplementary group; repeat for more
+        #[arg(long = "group")]
+        groups: Vec<String>,
+        /// Login shell instead of the distribution default
+        #[arg(long)]
+        shell: Option<String>,
+        /// Full name or other description
+        #[arg(long)]
+        comment: Option<String>,
+        /// Create a system account
+        #[arg(long)]
+        system: bool,
+        /// Only show the preview
+        #[arg(long)]
+        dry_run: bool,
+        /// Execution mode (supervised, semi-auto, autonomous, read-only)
+        #[arg(long, default_value = "supervised")]
+        mode: String,
+    },
+    /// Create a group
+    AddGroup {
+        name: String,
+        /// Create a system group
+        #[arg(long)]
+        system: bool,
+        /// Only show the preview
+        #[arg(long)]
+        dry_run: bool,
+        /// Execution mode (supervised, semi-auto, autonomous, read-only)
+        #[arg(long, default_value = "supervised")]
+        mode: String,
+    },
+    /// Lock a user's password and expire the account
+    Lock {
+        name: String,
+        /// Only show the preview
+        #[arg(long)]
+        dry_run: bool,
+        /// Execution mode (supervised, semi-auto, autonomous, read-only)
+        #[arg(long, default_value = "supervised")]
+        mode: String,
+    },
+    /// Undo a lock
+    Unlock {
+        name: String,
+        /// Only show the preview
+        #[arg(long)]
+        dry_run: bool,
+        /// Execution mode (supervised, semi-auto, autonomous, read-only)
+        #[arg(long, default_value = "supervised")]
+        mode: String,
+    },
+    /// Change a user's login shell
+    Shell {
+        name: String,
+        /// Path of the new shell
+        shell: String,
+        /// Only show the preview
+        #[arg(long)]
+        dry_run: bool,
+        /// Execution mode (supervised, semi-auto, autonomous, read-only)
+        #[arg(long, default_value = "supervised")]
+        mode: String,
+    },
+    /// Add a user to a group
+    Join {
+        name: String,
+        group: String,
+        /// Only show the preview
+        #[arg(long)]
+        dry_run: bool,
+        /// Execution mode (supervised, semi-auto, autonomous, read-only)
+        #[arg(long, default_value = "supervised")]
+        mode: String,
+    },
+    /// Remove a user from a group
+    Leave {
+        name: String,
+        group: String,
+        /// Only show the preview
+        #[arg(long)]
+        dry_run: bool,
+        /// Execution mode (supervised, semi-auto, autonomous, read-only)
+        #[arg(long, default_value = "supervised")]
+        mode: String,
+    },
+    /// Let a user, or a group written as %group, run commands with sudo
+    Grant {
+        principal: String,
+        /// Full path of a command with any arguments; repeat for more, ALL when none is given
+        #[arg(long = "command")]
+        commands: Vec<String>,
+        /// User to run the commands as
+        #[arg(long, default_value = "root")]
+        runas: String,
+        /// Do not ask for a password
+        #[arg(long)]
+        nopasswd: bool,
+        /// Only show the preview
+        #[arg(long)]
+        dry_run: bool,
+        /// Execution mode (supervised, semi-auto, autonomous, read-only)
+        #[arg(long, default_value = "supervised")]
+        mode: String,
+    },
+    /// Disable a sudo rule written by `tuxpilot users grant`
+    Revoke {
+        principal: String,
+        /// Only show the preview
+        #[arg(long)]
+        dry_run: bool,
+        /// Execution mode (supervised, semi-auto, autonomous, read-only)
+        #[arg(long, default_value = "supervised")]
+        mode: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum KernelAction {
+    /// Show runtime and persisted values of kernel parameters, or change one
+    Sysctl {
+        /// Parameter such as vm.swappiness; without one, every persisted parameter is listed
+        key: Option<String>,
+        /// Set the parameter now and at every boot
+        #[arg(long, requires = "key")]
+        set: Option<String>,
+        /// Only change the running kernel
+        #[arg(long, requires = "set")]
+        runtime_only: bool,
+        /// Remove tuxpilot's setting and restore the previous value
+        #[arg(long, requires = "key", conflicts_with = "set")]
+        reset: bool,
+        /// Print the settings as JSON
+        #[arg(long)]
+        json: bool,
+        /// Execution mode for changes (supervised, semi-auto, autonomous, read-only)
+        #[arg(long, default_value = "supervised")]
+        mode: String,
+    },
+    /// Show loaded, blacklisted and boot-time modules, or change one
+    Modules {
+        /// Load a module now
+        #[arg(long, group = "module_change")]
+        load: Option<String>,
+        /// Unload a module now
+        #[arg(long, group = "module_change")]
+        unload: Option<String>,
+        /// Keep a module from loading automatically, and unload it
+        #[arg(long, group = "module_change")]
+        blacklist: Option<String>,
+        /// Remove tuxpilot's blacklist entry for a module
+        #[arg(long, group = "module_change")]
+        unblacklist: Option<String>,
+        /// Load a module at every boot, and now
+        #[arg(long, group = "module_change")]
+        autoload: Option<String>,
+        /// Stop loading a module at boot
+        #[arg(long, group = "module_change")]
+        no_autoload: Option<String>,
+        /// Print the module state as JSON
+        #[arg(long)]
+        json: bool,
+        /// Execution mode for changes (supervised, semi-auto, autonomous, read-only)
+        #[arg(long, default_value = "supervised")]
+        mode: String,
+    },
+    /// Decode oopses, warnings, panics, taint and hardware errors in the kernel log
+    Log {
+        /// Boot to read, "0" for the running kernel and "-1" for the boot before
+        #[arg(long, default_value = "0", allow_hyphen_values = true)]
+        boot: String,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+        /// Inventory host to inspect instead of this machine
+        #[arg(long)]
+        host: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum SessionAction {
+    /// Show the header and events of a session archive
+    Show {
+        /// Session archive file
+        archive: PathBuf,
+    },
 }
 
 #[tokio::main]
@@ -161,6 +776,19 @@ async fn main() -> Result<()> {
         env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
     }
 
+    // The privileged helper runs as root and must not touch the invoking user's config
+    match args.command {
+        Some(Commands::PrivilegedHelper) => return execution::elevation::run_helper(false).await,
+        Some(Commands::PrivilegedGrant) => return execution::elevation::run_helper(true).await,
+        _ => {}
+    }
+
+    if let Some(archive) = &args.record {
+        session::start_recording(archive)?;
+    } else if let Some(archive) = &args.replay {
+        session::start_replay(archive)?;
+    }
+
     // Load configuration
     let config = Config::load(args.config.as_deref())?;
     
@@ -1,5 +1,6 @@
 use anyhow::Result;
 
+use crate::journal::{JournalQuery, JournalReader};
 use super::{MCPResource, MCPContext};
 
 /// System logs resource
@@ -22,20 +23,17 @@ impl MCPResource for SystemLogsResource {
     }
 
     fn description(&self) -> &str {
-        "Recent system logs from journalctl"
+        "Recent journal entries with all their fields"
     }
 
     fn mime_type(&self) -> &str {
-        "text/plain"
+        "application/json"
     }
 
-    async fn read(&self, _context: &MCPContext) -> Result<String> {
-        let output = tokio::process::Command::new("journalctl")
-            .args(&["-n", "100", "--no-pager"])
-            .output()
-            .await?;
-
-        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    async fn read(&self, context: &MCPContext) -> Result<String> {
+        let reader = JournalReader::new(context.linux_integration.transport().clone());
+        let query = JournalQuery { lines: Some(100), ..Default::default() };
+        Ok(serde_json::to_string_pretty(&reader.read(&query).await?)?)
     }
 }
 
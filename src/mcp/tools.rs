@@ -1,6 +1,9 @@
 use anyhow::Result;
 use serde_json::json;
 
+use crate::config::PackageManager;
+use crate::journal::{parse_priority, JournalQuery, JournalReader};
+use crate::services::ServiceAction;
 use super::{MCPTool, MCPContext, MCPToolResult};
 
 /// System information tool
@@ -89,12 +92,16 @@ impl MCPTool for PackageManagerTool {
             "properties": {
                 "action": {
                     "type": "string",
-                    "enum": ["search", "install", "remove", "update", "list", "info"],
+                    "enum": ["search", "install", "remove", "update", "list", "upgradable", "info", "why", "owns"],
                     "description": "Package management action to perform"
                 },
                 "package": {
                     "type": "string",
-                    "description": "Package name (required for most actions)"
+                    "description": "Package name (required for most actions), or a file path for owns"
+                },
+                "source": {
+                    "type": "string",
+                    "description": "Limit search, info, list and upgradable to one package manager such as flatpak or snap; all detected ones are queried otherwise"
                 },
                 "options": {
                     "type": "array",
@@ -114,37 +121,64 @@ impl MCPTool for PackageManagerTool {
         let package = params.get("package")
             .and_then(|v| v.as_str());
 
-        let result = match action {
-            "search" => {
-                if let Some(pkg) = package {
-                    let suggestion = context.linux_integration.get_package_suggestion("search", Some(pkg)).await?;
-                    json!({
-                        "action": "search",
-                        "package": pkg,
-                        "suggestion": suggestion
-                    })
-                } else {
-                    return Ok(MCPToolResult {
-                        success: false,
-                        content: json!({}),
-                        error: Some("Package name required for search".to_string()),
-                    });
-                }
-            },
-            "list" => {
-                let suggestion = context.linux_integration.get_package_suggestion("list", None).await?;
-                json!({
-                    "action": "list",
-                    "suggestion": suggestion
-                })
-            },
-            _ => {
+        let source = params.get("source")
+            .and_then(|v| v.as_str())
+            .map(str::parse::<PackageManager>)
+            .transpose()?;
+
+        let backend = context.linux_integration.package_backend();
+        let sources = context.linux_integration.package_sources(source.as_ref());
+        let result = match (action, package) {
+            ("search", Some(pkg)) => json!({
+                "action": "search",
+                "package": pkg,
+                "sources": sources.managers(),
+                "packages": sources.search(pkg).await?
+            }),
+            ("info", Some(pkg)) => json!({
+                "action": "info",
+                "package": sources.info(pkg).await?
+            }),
+            ("why", Some(pkg)) => json!({
+                "action": "why",
+                "reason": backend.why_installed(pkg).await?
+            }),
+            ("owns", Some(path)) => json!({
+                "action": "owns",
+                "path": path,
+                "package": backend.owns_file(path).await?
+            }),
+            ("list", _) => json!({
+                "action": "list",
+                "sources": sources.managers(),
+                "packages": sources.list_installed().await?
+            }),
+            ("upgradable", _) => json!({
+                "action": "upgradable",
+                "sources": sources.managers(),
+                "packages": sources.list_upgradable().await?
+            }),
+            ("install" | "remove", Some(_)) | ("update", _) => {
+                let packages: Vec<String> = package.map(|p| vec![p.to_string()]).unwrap_or_default();
+                let transaction = match action {
+                    "install" => backend.install(&packages).await?,
+                    "remove" => backend.remove(&packages).await?,
+                    _ => backend.upgrade(&packages).await?,
+                };
                 json!({
                     "action": action,
-                    "package": package,
+                    "command": transaction.command_line(),
+                    "transaction": transaction,
                     "note": "This action would require execution permissions"
                 })
             }
+            _ => {
+                return Ok(MCPToolResult {
+                    success: false,
+                    content: json!({}),
+                    error: Some(format!("Action '{}' needs a package name or is not supported", action)),
+                });
+            }
         };
 
         Ok(MCPToolResult {
@@ -180,7 +214,7 @@ impl MCPTool for ServiceManagerTool {
             "properties": {
                 "action": {
                     "type": "string",
-                    "enum": ["status", "start", "stop", "restart", "enable", "disable", "list"],
+                    "enum": ["status", "start", "stop", "restart", "reload", "enable", "disable", "list"],
                     "description": "Service management action"
                 },
                 "service": {
@@ -200,37 +234,35 @@ impl MCPTool for ServiceManagerTool {
         let service = params.get("service")
             .and_then(|v| v.as_str());
 
-        let result = match action {
-            "status" => {
-                if let Some(svc) = service {
-                    let status = context.linux_integration.get_service_info(svc).await?;
-                    json!({
-                        "action": "status",
-                        "service": svc,
-                        "status": status
-                    })
-                } else {
-                    return Ok(MCPToolResult {
-                        success: false,
-                        content: json!({}),
-                        error: Some("Service name required for status".to_string()),
-                    });
-                }
-            },
-            "list" => {
-                // This would list all services
-                json!({
-                    "action": "list",
-                    "note": "Service listing would be implemented here"
-                })
-            },
-            _ => {
+        let backend = context.linux_integration.service_backend();
+        let result = match (action, service) {
+            ("list", _) => json!({
+                "action": "list",
+                "manager": backend.manager().to_string(),
+                "services": backend.list().await?
+            }),
+            ("status", Some(svc)) => json!({
+                "action": "status",
+                "service": svc,
+                "manager": backend.manager().to_string(),
+                "status": backend.status(svc).await?
+            }),
+            (_, Some(svc)) => {
+                let service_action: ServiceAction = action.parse()?;
                 json!({
                     "action": action,
-                    "service": service,
+                    "service": svc,
+                    "command": backend.command(svc, &service_action).join(" "),
                     "note": "This action would require execution permissions"
                 })
             }
+            (_, None) => {
+                return Ok(MCPToolResult {
+                    success: false,
+                    content: json!({}),
+                    error: Some(format!("Service name required for {}", action)),
+                });
+            }
         };
 
         Ok(MCPToolResult {
@@ -547,13 +579,33 @@ impl MCPTool for LogAnalyzerTool {
                     "type": "integer",
                     "description": "Number of recent lines to analyze",
                     "default": 100
+                },
+                "unit": {
+                    "type": "string",
+                    "description": "Journal only: entries of this systemd unit"
+                },
+                "priority": {
+                    "type": "string",
+                    "description": "Journal only: least severe priority to include, e.g. err or warning"
+                },
+                "boot": {
+                    "type": "string",
+                    "description": "Journal only: boot ID, or 0 for the current boot and -1 for the previous one"
+                },
+                "pid": {
+                    "type": "integer",
+                    "description": "Journal only: entries of this process"
+                },
+                "since": {
+                    "type": "string",
+                    "description": "Journal only: start time such as '-1h' or '2024-05-01 10:00'"
                 }
             },
             "required": ["log_type"]
         })
     }
 
-    async fn execute(&self, params: serde_json::Value, _context: &MCPContext) -> Result<MCPToolResult> {
+    async fn execute(&self, params: serde_json::Value, context: &MCPContext) -> Result<MCPToolResult> {
         let log_type = params.get("log_type")
             .and_then(|v| v.as_str())
             .ok_or_else(|| anyhow::anyhow!("Log type is required"))?;
@@ -564,16 +616,22 @@ impl MCPTool for LogAnalyzerTool {
 
         let result = match log_type {
             "journal" => {
-                let output = tokio::process::Command::new("journalctl")
-                    .args(&["-n", &lines.to_string(), "--no-pager"])
-                    .output()
-                    .await?;
-
-                let logs = String::from_utf8_lossy(&output.stdout);
+                let text = |name: &str| params.get(name).and_then(|v| v.as_str()).map(String::from);
+                let query = JournalQuery {
+                    units: text("unit").into_iter().collect(),
+                    priority: text("priority").map(|p| parse_priority(&p)).transpose()?,
+                    boot: text("boot"),
+                    pid: params.get("pid").and_then(|v| v.as_u64()).map(|pid| pid as u32),
+                    since: text("since"),
+                    lines: Some(lines as usize),
+                    ..Default::default()
+                };
+                let records = JournalReader::new(context.linux_integration.transport().clone())
+                    .read(&query).await?;
                 json!({
                     "log_type": "journal",
                     "lines": lines,
-                    "content": logs
+                    "records": records
                 })
             },
             _ => {
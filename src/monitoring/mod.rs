@@ -281,7 +281,17 @@ impl AdvancedMonitoringSystem {
 
     pub async fn generate_predictive_alerts(&self) -> Result<Vec<PredictiveAlert>> {
         let metrics_history = self.metrics_collector.get_historical_data(168).await?; // 1 week
-        self.predictive_engine.generate_alerts(&metrics_history).await
+        let mut alerts = self.predictive_engine.generate_alerts(&metrics_history).await?;
+
+        // Failing drives show up in their SMART data long before in the metrics
+        let storage = self.linux_integration.storage().inspect().await;
+        alerts.extend(storage.predictive_alerts());
+
+        // So do correctable hardware errors in the kernel log, and faults in drivers
+        // never show up in the metrics at all
+        let kernel = self.linux_integration.kernel_log().report("0").await;
+        alerts.extend(kernel.predictive_alerts());
+        Ok(alerts)
     }
 
     pub async fn get_optimization_recommendations(&self) -> Result<Vec<HealthRecommendation>> {
@@ -0,0 +1,301 @@
+use anyhow::Result;
+use std::sync::Arc;
+
+use crate::remote::transport::{shell_quote, Transport};
+use super::{prefix_length, ConfigFile, InterfaceConfig, Method, NetworkBackend, NetworkStep, Route, Stack, Vlan};
+
+const INTERFACES: &str = "/etc/network/interfaces";
+/// Interfaces ifup has brought up, as "name=name" lines
+const IFSTATE: &str = "/run/network/ifstate";
+/// Words that start a new stanza and so end the options of the previous one
+const STANZAS: &[&str] = &["iface", "auto", "mapping", "source", "source-directory", "rename"];
+/// Options that hold the addressing this module rewrites
+const ADDRESSING: &[&str] = &["address", "netmask", "gateway", "dns-nameservers"];
+/// `source` and `source-directory` may include each other; deeper nesting is ignored
+const MAX_INCLUDE_DEPTH: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct Ifupdown {
+    transport: Arc<dyn Transport>,
+}
+
+impl Ifupdown {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    /// The interfaces file and every file it sources, in the order ifupdown reads them
+    async fn files(&self) -> (Vec<(String, String)>, Vec<String>) {
+        let mut files = Vec::new();
+        let mut unreadable = Vec::new();
+        let mut pending = vec![(INTERFACES.to_string(), 0)];
+        while let Some((path, depth)) = pending.pop() {
+            let text = match self.transport.read_file(&path).await {
+                Ok(text) => text,
+                Err(_) => {
+                    // A missing main file only means ifupdown is not used
+                    if depth > 0 && self.transport.path_exists(&path).await {
+                        unreadable.push(path);
+                    }
+                    continue;
+                }
+            };
+            let mut included = Vec::new();
+            for (keyword, target) in includes(&text) {
+                if depth >= MAX_INCLUDE_DEPTH {
+                    break;
+                }
+                // Relative paths are relative to the directory of the interfaces file
+                let target = if target.starts_with('/') { target } else { format!("/etc/network/{}", target) };
+                let script = match keyword.as_str() {
+                    "source" => format!("ls -1d {} 2>/dev/null", target.split_whitespace().map(|glob| glob.replace('\'', "")).collect::<Vec<_>>().join(" ")),
+                    _ => format!("ls -1 {} 2>/dev/null", shell_quote(&target)),
+                };
+                let Ok(output) = self.transport.run("sh", &["-c", &script]).await else {
+                    continue;
+                };
+                for entry in output.stdout.lines() {
+                    if keyword == "source" {
+                        included.push(entry.to_string());
+                    } else if entry.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                        // source-directory only reads names run-parts would run
+                        included.push(format!("{}/{}", target.trim_end_matches('/'), entry));
+                    }
+                }
+            }
+            files.push((path, text));
+            // Popped from the end, so pushed in reverse to keep the reading order
+            pending.extend(included.into_iter().rev().map(|path| (path, depth + 1)));
+        }
+        (files, unreadable)
+    }
+}
+
+/// `source` and `source-directory` lines of an interfaces file
+fn includes(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .filter_map(|line| {
+            let (keyword, target) = line.trim().split_once(char::is_whitespace)?;
+            matches!(keyword, "source" | "source-directory").then(|| (keyword.to_string(), target.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Parse an interfaces file. The inet and inet6 stanzas of an interface are combined
+/// into one configuration; loopback stanzas are left out.
+pub(crate) fn parse_interfaces(path: &str, text: &str) -> Vec<InterfaceConfig> {
+    let mut configs: Vec<InterfaceConfig> = Vec::new();
+    // Index into `configs` of the stanza being read, and its family
+    let mut current: Option<(usize, String)> = None;
+    let mut netmask: Option<String> = None;
+
+    let finish_address = |config: &mut InterfaceConfig, netmask: &mut Option<String>| {
+        // "address 192.0.2.10" with a separate "netmask 255.255.255.0"
+        if let (Some(mask), Some(last)) = (netmask.take(), config.addresses.last_mut()) {
+            if !last.contains('/') {
+                if let Some(prefix) = prefix_length(&mask) {
+                    last.push_str(&format!("/{}", prefix));
+                }
+            }
+        }
+    };
+
+    for line in text.lines() {
+        let words: Vec<&str> = line.split('#').next().unwrap_or_default().split_whitespace().collect();
+        let Some(keyword) = words.first() else {
+            continue;
+        };
+        if STANZAS.contains(keyword) || keyword.starts_with("allow-") {
+            if let Some((index, _)) = current.take() {
+                finish_address(&mut configs[index], &mut netmask);
+            }
+            if let ["iface", name, family, method, ..] = words.as_slice() {
+                if *method == "loopback" {
+                    continue;
+                }
+                let index = match configs.iter().position(|config| config.interface == *name) {
+                    Some(index) => index,
+                    None => {
+                        let mut config = InterfaceConfig::new(name, Stack::Ifupdown, path);
+                        config.vlan = vlan_from_name(name);
+                        configs.push(config);
+                        configs.len() - 1
+                    }
+                };
+                let config = &mut configs[index];
+                match (*family, *method) {
+                    ("inet", "dhcp") => config.method = Method::Dhcp,
+                    ("inet", "static") => config.method = Method::Static,
+                    ("inet", "ipv4ll") => config.method = Method::LinkLocal,
+                    ("inet6", "dhcp" | "auto") => config.dhcp6 = true,
+                    _ => {}
+                }
+                current = Some((index, family.to_string()));
+            }
+            continue;
+        }
+
+        let Some((index, family)) = &current else {
+            continue;
+        };
+        let config = &mut configs[*index];
+        let value = words.get(1).map(|value| value.to_string());
+        match (*keyword, value) {
+            ("address", Some(address)) => {
+                finish_address(config, &mut netmask);
+                config.addresses.push(address);
+            }
+            ("netmask", Some(mask)) => netmask = Some(mask),
+            ("gateway", Some(gateway)) if family == "inet" => config.gateway = Some(gateway),
+            ("gateway", Some(gateway)) => config.routes.push(Route { destination: "::/0".to_string(), gateway: Some(gateway), metric: None }),
+            ("dns-nameservers", Some(_)) => config.dns.extend(words[1..].iter().map(|server| server.to_string())),
+            ("dns-search", Some(_)) => config.search.extend(words[1..].iter().map(|domain| domain.to_string())),
+            ("vlan-raw-device" | "vlan_raw_device", Some(parent)) => {
+                let id = config.vlan.as_ref().map(|vlan| vlan.id)
+                    .or_else(|| config.interface.trim_start_matches(|c: char| !c.is_ascii_digit()).parse().ok());
+                if let Some(id) = id {
+                    config.vlan = Some(Vlan { id, parent });
+                }
+            }
+            ("up" | "post-up", Some(_)) => config.routes.extend(route_from_command(&words[1..])),
+            _ => {}
+        }
+    }
+    if let Some((index, _)) = current {
+        finish_address(&mut configs[index], &mut netmask);
+    }
+    configs
+}
+
+/// VLAN interfaces named "eth0.100"
+fn vlan_from_name(name: &str) -> Option<Vlan> {
+    let (parent, id) = name.rsplit_once('.')?;
+    Some(Vlan { id: id.parse().ok()?, parent: parent.to_string() })
+}
+
+/// Route from an "up ip route add 10.0.0.0/8 via 192.0.2.254" hook
+fn route_from_command(words: &[&str]) -> Option<Route> {
+    let ["ip", rest @ ..] = words else {
+        return None;
+    };
+    let rest: Vec<&str> = rest.iter().copied().filter(|word| *word != "-4" && *word != "-6").collect();
+    let ["route", "add", destination, options @ ..] = rest.as_slice() else {
+        return None;
+    };
+    let option = |name: &str| options.iter().position(|word| *word == name).and_then(|index| options.get(index + 1));
+    Some(Route {
+        destination: destination.to_string(),
+        gateway: option("via").map(|gateway| gateway.to_string()),
+        metric: option("metric").and_then(|metric| metric.parse().ok()),
+    })
+}
+
+/// An interfaces file with the inet stanza of `interface` given the addressing of
+/// `config`, or with a new stanza appended when there is none
+pub(crate) fn rewrite_interfaces(text: &str, interface: &str, config: &InterfaceConfig) -> String {
+    let method = if config.method == Method::Dhcp { "dhcp" } else { "static" };
+    let mut options: Vec<String> = Vec::new();
+    if config.method != Method::Dhcp {
+        options.extend(config.addresses.iter().filter(|address| address.contains('.')).map(|address| format!("address {}", address)));
+        options.extend(config.gateway.iter().map(|gateway| format!("gateway {}", gateway)));
+    }
+    if !config.dns.is_empty() {
+        options.push(format!("dns-nameservers {}", config.dns.join(" ")));
+    }
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut in_stanza = false;
+    let mut found = false;
+    let mut indent = "    ".to_string();
+    for line in text.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let keyword = words.first().copied().unwrap_or_default();
+        if STANZAS.contains(&keyword) || keyword.starts_with("allow-") {
+            if in_stanza {
+                insert_options(&mut lines, &options, &indent);
+            }
+            in_stanza = matches!(words.as_slice(), ["iface", name, "inet", ..] if *name == interface);
+            if in_stanza {
+                found = true;
+                let mut words: Vec<String> = words.iter().map(|word| word.to_string()).collect();
+                words[3] = method.to_string();
+                lines.push(words.join(" "));
+                continue;
+            }
+        } else if in_stanza && !keyword.is_empty() && !keyword.starts_with('#') {
+            indent = line[..line.len() - line.trim_start().len()].to_string();
+            if ADDRESSING.contains(&keyword) {
+                continue;
+            }
+        }
+        lines.push(line.to_string());
+    }
+    if in_stanza {
+        insert_options(&mut lines, &options, &indent);
+    }
+    if !found {
+        if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+            lines.push(String::new());
+        }
+        lines.push(format!("auto {}", interface));
+        lines.push(format!("iface {} inet {}", interface, method));
+        lines.extend(options.iter().map(|option| format!("{}{}", indent, option)));
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Add the addressing options at the end of a stanza, before the blank lines that
+/// separate it from the next one
+fn insert_options(lines: &mut Vec<String>, options: &[String], indent: &str) {
+    let blank = lines.iter().rev().take_while(|line| line.trim().is_empty()).count();
+    let at = lines.len() - blank;
+    for (offset, option) in options.iter().enumerate() {
+        lines.insert(at + offset, format!("{}{}", indent, option));
+    }
+}
+
+#[async_trait::async_trait]
+impl NetworkBackend for Ifupdown {
+    fn stack(&self) -> Stack {
+        Stack::Ifupdown
+    }
+
+    async fn managed(&self) -> Vec<String> {
+        let Ok(state) = self.transport.read_file(IFSTATE).await else {
+            return Vec::new();
+        };
+        state.lines()
+            .filter_map(|line| line.split('=').next())
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty() && name != "lo")
+            .collect()
+    }
+
+    async fn configs(&self) -> (Vec<InterfaceConfig>, Vec<String>) {
+        let (files, unreadable) = self.files().await;
+        let mut configs: Vec<InterfaceConfig> = Vec::new();
+        for (path, text) in &files {
+            for config in parse_interfaces(path, text) {
+                configs.retain(|existing| existing.interface != config.interface);
+                configs.push(config);
+            }
+        }
+        (configs, unreadable)
+    }
+
+    /// The interface is taken down before its stanza changes, since ifdown reads the
+    /// stanza to know which addresses to remove
+    async fn plan(&self, interface: &str, before: &InterfaceConfig, after: &InterfaceConfig) -> Result<Vec<NetworkStep>> {
+        let path = if before.source.is_empty() { INTERFACES.to_string() } else { before.source.clone() };
+        let current = self.transport.read_file(&path).await.unwrap_or_default();
+        Ok(vec![
+            NetworkStep::Run(["ifdown", interface].map(String::from).to_vec()),
+            NetworkStep::Write(ConfigFile { content: rewrite_interfaces(&current, interface, after), before: current, path, mode: 0o644 }),
+            NetworkStep::Run(["ifup", interface].map(String::from).to_vec()),
+        ])
+    }
+
+    fn note(&self) -> Option<&'static str> {
+        Some("the interface is down while it is reconfigured; dns-nameservers only takes effect with resolvconf installed")
+    }
+}
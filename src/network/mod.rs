@@ -0,0 +1,941 @@
+//! Network configuration.
+//!
+//! NetworkManager, systemd-networkd, netplan and ifupdown each keep interface
+//! configuration in their own files: keyfiles, `.network` units, YAML and
+//! `/etc/network/interfaces`. Each backend parses its files into a common model of
+//! addresses, routes, DNS servers and VLANs, and the stack that owns an interface is
+//! worked out from which daemons manage it right now. Comparing the model with the
+//! addresses and routes the kernel actually has explains most connectivity problems.
+//! Changes are planned for the owning stack as file writes and commands for the
+//! executor to run, like the kernel drop-ins.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use crate::remote::transport::{shell_quote, Transport};
+
+pub mod ifupdown;
+pub mod netplan;
+pub mod networkd;
+pub mod networkmanager;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Stack {
+    NetworkManager,
+    Networkd,
+    Netplan,
+    Ifupdown,
+}
+
+impl fmt::Display for Stack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stack::NetworkManager => write!(f, "NetworkManager"),
+            Stack::Networkd => write!(f, "systemd-networkd"),
+            Stack::Netplan => write!(f, "netplan"),
+            Stack::Ifupdown => write!(f, "ifupdown"),
+        }
+    }
+}
+
+/// How an interface gets its IPv4 address
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Method {
+    Dhcp,
+    Static,
+    LinkLocal,
+    Disabled,
+    /// The stack brings the link up but assigns no address, e.g. a bridge port
+    Unconfigured,
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Method::Dhcp => write!(f, "DHCP"),
+            Method::Static => write!(f, "static"),
+            Method::LinkLocal => write!(f, "link-local"),
+            Method::Disabled => write!(f, "disabled"),
+            Method::Unconfigured => write!(f, "none"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Route {
+    /// Network in CIDR notation; "0.0.0.0/0" and "::/0" are default routes
+    pub destination: String,
+    pub gateway: Option<String>,
+    pub metric: Option<u32>,
+}
+
+impl Route {
+    pub fn is_default(&self) -> bool {
+        matches!(self.destination.as_str(), "default" | "0.0.0.0/0" | "::/0")
+    }
+}
+
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.destination)?;
+        if let Some(gateway) = &self.gateway {
+            write!(f, " via {}", gateway)?;
+        }
+        if let Some(metric) = self.metric {
+            write!(f, " metric {}", metric)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Vlan {
+    pub id: u16,
+    pub parent: String,
+}
+
+/// One interface as a stack's configuration files describe it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InterfaceConfig {
+    /// Interface name, or whitespace separated glob patterns for networkd
+    pub interface: String,
+    pub stack: Stack,
+    /// File the configuration comes from, or the NetworkManager connection when its
+    /// keyfile cannot be read
+    pub source: String,
+    /// Connection name for NetworkManager, definition ID for netplan
+    pub id: Option<String>,
+    pub method: Method,
+    pub dhcp6: bool,
+    /// Static addresses in CIDR notation
+    pub addresses: Vec<String>,
+    /// IPv4 default gateway; IPv6 default routes are kept in `routes`
+    pub gateway: Option<String>,
+    pub routes: Vec<Route>,
+    pub dns: Vec<String>,
+    pub search: Vec<String>,
+    pub vlan: Option<Vlan>,
+    /// Stack netplan hands the interface to
+    pub renderer: Option<Stack>,
+}
+
+impl InterfaceConfig {
+    pub fn new(interface: &str, stack: Stack, source: &str) -> Self {
+        Self {
+            interface: interface.to_string(),
+            stack,
+            source: source.to_string(),
+            id: None,
+            method: Method::Unconfigured,
+            dhcp6: false,
+            addresses: Vec::new(),
+            gateway: None,
+            routes: Vec::new(),
+            dns: Vec::new(),
+            search: Vec::new(),
+            vlan: None,
+            renderer: None,
+        }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.interface.split_whitespace().any(|pattern| glob_match(pattern, name))
+    }
+
+    /// Whether `interface` names a single interface rather than a pattern
+    pub fn is_concrete(&self) -> bool {
+        !self.interface.is_empty() && !self.interface.contains(['*', '?', '[', ' '])
+    }
+
+    /// The configuration with `change` made to it
+    pub fn changed(&self, change: &NetworkChange) -> Self {
+        let mut after = self.clone();
+        if change.dhcp {
+            after.method = Method::Dhcp;
+            after.addresses.clear();
+            after.gateway = None;
+            after.routes.retain(|route| !route.is_default());
+        } else if !change.addresses.is_empty() {
+            after.method = Method::Static;
+            after.addresses = change.addresses.clone();
+        }
+        if change.gateway.is_some() {
+            after.gateway = change.gateway.clone();
+        }
+        if !change.dns.is_empty() {
+            after.dns = change.dns.clone();
+        }
+        after
+    }
+
+    /// The `tuxpilot network set` command line that restores this configuration
+    pub fn set_command(&self, interface: &str) -> String {
+        let mut command = format!("tuxpilot network set {}", interface);
+        match self.method {
+            Method::Dhcp => command.push_str(" --dhcp"),
+            _ => {
+                for address in &self.addresses {
+                    command.push_str(&format!(" --address {}", address));
+                }
+                if let Some(gateway) = &self.gateway {
+                    command.push_str(&format!(" --gateway {}", gateway));
+                }
+            }
+        }
+        for server in &self.dns {
+            command.push_str(&format!(" --dns {}", server));
+        }
+        command
+    }
+
+    /// Human readable lines, used for listings and to preview changes
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("{} ({}{})", self.interface, self.stack,
+            if self.source.is_empty() { String::new() } else { format!(", {}", self.source) })];
+        if let Some(renderer) = self.renderer {
+            lines.push(format!("  rendered by: {}", renderer));
+        }
+        if let Some(id) = &self.id {
+            lines.push(format!("  id: {}", id));
+        }
+        if let Some(vlan) = &self.vlan {
+            lines.push(format!("  vlan: {} on {}", vlan.id, vlan.parent));
+        }
+        lines.push(format!("  ipv4: {}", self.method));
+        if self.dhcp6 {
+            lines.push("  ipv6: DHCP".to_string());
+        }
+        lines.extend(self.addresses.iter().map(|address| format!("  address: {}", address)));
+        lines.extend(self.gateway.iter().map(|gateway| format!("  gateway: {}", gateway)));
+        lines.extend(self.routes.iter().map(|route| format!("  route: {}", route)));
+        if !self.dns.is_empty() {
+            lines.push(format!("  dns: {}", self.dns.join(" ")));
+        }
+        if !self.search.is_empty() {
+            lines.push(format!("  search: {}", self.search.join(" ")));
+        }
+        lines
+    }
+}
+
+/// An interface as the kernel has it, from `ip -j addr show`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Link {
+    pub name: String,
+    /// Operational state, e.g. "UP", "DOWN" or "UNKNOWN"
+    pub state: String,
+    /// Administratively up
+    pub up: bool,
+    pub carrier: bool,
+    /// Assigned addresses in CIDR notation
+    pub addresses: Vec<String>,
+    /// Lower device of a VLAN or macvlan
+    pub parent: Option<String>,
+}
+
+impl Link {
+    /// IPv4 addresses outside 169.254.0.0/16
+    pub fn routable_ipv4(&self) -> Vec<&String> {
+        self.addresses.iter()
+            .filter(|address| address.contains('.') && !address.starts_with("169.254."))
+            .collect()
+    }
+}
+
+/// A route the kernel has, from `ip -j route show`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuntimeRoute {
+    pub destination: String,
+    pub gateway: Option<String>,
+    pub device: Option<String>,
+    pub metric: Option<u64>,
+    /// The gateway is reachable on the link even though no subnet contains it
+    pub onlink: bool,
+}
+
+impl fmt::Display for RuntimeRoute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.destination)?;
+        if let Some(gateway) = &self.gateway {
+            write!(f, " via {}", gateway)?;
+        }
+        if let Some(device) = &self.device {
+            write!(f, " dev {}", device)?;
+        }
+        if let Some(metric) = self.metric {
+            write!(f, " metric {}", metric)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interface {
+    pub name: String,
+    /// Stack responsible for the interface, `None` when nothing configures it
+    pub owner: Option<Stack>,
+    pub link: Option<Link>,
+    /// Every configuration that applies to the interface, from all stacks
+    pub configs: Vec<InterfaceConfig>,
+}
+
+impl Interface {
+    /// The owner's configuration of the interface
+    pub fn config(&self) -> Option<&InterfaceConfig> {
+        self.configs.iter().find(|config| Some(config.stack) == self.owner)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Problem {
+    /// Interface the problem is about, `None` for the machine as a whole
+    pub interface: Option<String>,
+    pub message: String,
+    /// What to do about it
+    pub advice: Option<String>,
+}
+
+impl Problem {
+    fn new(interface: Option<&str>, message: impl Into<String>) -> Self {
+        Self { interface: interface.map(String::from), message: message.into(), advice: None }
+    }
+
+    fn advice(mut self, advice: impl Into<String>) -> Self {
+        self.advice = Some(advice.into());
+        self
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(interface) = &self.interface {
+            write!(f, "{}: ", interface)?;
+        }
+        write!(f, "{}", self.message)?;
+        if let Some(advice) = &self.advice {
+            write!(f, " ({})", advice)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NetworkReport {
+    pub interfaces: Vec<Interface>,
+    pub default_routes: Vec<RuntimeRoute>,
+    /// Nameservers and search domains from /etc/resolv.conf
+    pub nameservers: Vec<String>,
+    pub search: Vec<String>,
+    /// Configuration files that exist but could not be read
+    pub unreadable: Vec<String>,
+    pub problems: Vec<Problem>,
+}
+
+impl NetworkReport {
+    pub fn interface(&self, name: &str) -> Option<&Interface> {
+        self.interfaces.iter().find(|interface| interface.name == name)
+    }
+
+    /// Compare the configuration with what the kernel has, and explain what keeps the
+    /// machine from reaching the network
+    fn diagnose(&mut self) {
+        let mut problems = Vec::new();
+
+        for interface in &self.interfaces {
+            let name = Some(interface.name.as_str());
+            let mut distinct: Vec<Stack> = interface.configs.iter().map(|config| config.stack).collect();
+            distinct.sort();
+            distinct.dedup();
+            // netplan writes configuration for its renderer, which is not a conflict
+            let renderers: Vec<Stack> = interface.configs.iter().filter_map(|config| config.renderer).collect();
+            distinct.retain(|stack| !renderers.contains(stack));
+            if distinct.len() > 1 {
+                let sources: Vec<String> = interface.configs.iter()
+                    .filter(|config| distinct.contains(&config.stack))
+                    .map(|config| format!("{} ({})", config.stack, config.source))
+                    .collect();
+                let mut problem = Problem::new(name, format!("configured by more than one stack: {}", sources.join(", ")));
+                if let Some(owner) = interface.owner {
+                    problem = problem.advice(format!("{} manages it; remove the other configuration so they do not fight over the addresses", owner));
+                }
+                problems.push(problem);
+            }
+
+            let Some(config) = interface.config() else {
+                continue;
+            };
+            let Some(link) = &interface.link else {
+                let message = match &config.vlan {
+                    Some(vlan) => format!("VLAN {} on {} is configured in {} but does not exist", vlan.id, vlan.parent, config.source),
+                    None => format!("configured in {} but no such interface exists", config.source),
+                };
+                problems.push(Problem::new(name, message)
+                    .advice("compare the name with `ip link`; interfaces may have been renamed, e.g. from eth0 to enp3s0"));
+                continue;
+            };
+            if !link.up {
+                problems.push(Problem::new(name, "the interface is down").advice(format!("bring it up with `{}`", apply_hint(config, &interface.name))));
+                continue;
+            }
+            if !link.carrier {
+                problems.push(Problem::new(name, "no carrier: nothing is connected to the interface")
+                    .advice("check the cable, the switch port or the Wi-Fi association"));
+                continue;
+            }
+
+            match config.method {
+                Method::Static => {
+                    for address in config.addresses.iter().filter(|address| !link.addresses.contains(address)) {
+                        problems.push(Problem::new(name, format!("address {} from {} is not assigned", address, config.source))
+                            .advice(format!("apply the configuration with `{}`", apply_hint(config, &interface.name))));
+                    }
+                }
+                Method::Dhcp if link.routable_ipv4().is_empty() => {
+                    problems.push(Problem::new(name, "uses DHCP but has no IPv4 address, so no DHCP server answered")
+                        .advice("check that the network has a DHCP server, or give the interface a static address"));
+                }
+                _ => {}
+            }
+        }
+
+        let ipv4_defaults: Vec<&RuntimeRoute> = self.default_routes.iter()
+            .filter(|route| route.gateway.as_deref().is_none_or(|gateway| gateway.contains('.')))
+            .collect();
+        let has_addresses = self.interfaces.iter().filter_map(|interface| interface.link.as_ref())
+            .any(|link| !link.routable_ipv4().is_empty());
+        if ipv4_defaults.is_empty() && has_addresses {
+            let configured = self.interfaces.iter()
+                .find_map(|interface| interface.config().and_then(|config| config.gateway.as_ref().map(|gateway| (interface, config, gateway))));
+            let problem = Problem::new(None, "there is no default IPv4 route, so only directly connected networks are reachable");
+            problems.push(match configured {
+                Some((interface, config, gateway)) => problem.advice(format!("the gateway {} configured for {} is not applied; run `{}`",
+                    gateway, interface.name, apply_hint(config, &interface.name))),
+                None => problem.advice("add a gateway to the configuration of the interface facing the router"),
+            });
+        }
+        for route in ipv4_defaults.iter().filter(|route| !route.onlink) {
+            let (Some(gateway), Some(device)) = (&route.gateway, &route.device) else {
+                continue;
+            };
+            let Some(link) = self.interface(device).and_then(|interface| interface.link.as_ref()) else {
+                continue;
+            };
+            if !link.addresses.iter().any(|address| in_subnet(address, gateway)) {
+                problems.push(Problem::new(Some(device), format!("the default gateway {} is outside every subnet on the interface, so it cannot be reached", gateway))
+                    .advice("check the gateway address and the prefix length of the interface address"));
+            }
+        }
+
+        if self.nameservers.is_empty() {
+            let problem = Problem::new(None, "/etc/resolv.conf lists no nameservers, so host names cannot be resolved");
+            problems.push(problem.advice("add DNS servers to the configuration of the interface, e.g. with `tuxpilot network set <interface> --dns 192.0.2.53`"));
+        } else if !self.nameservers.iter().any(|server| server.starts_with("127.")) {
+            // A local resolver such as systemd-resolved forwards to the configured
+            // servers itself; otherwise they should show up in resolv.conf
+            for interface in &self.interfaces {
+                let Some(config) = interface.config() else {
+                    continue;
+                };
+                let missing: Vec<&String> = config.dns.iter().filter(|server| !self.nameservers.contains(server)).collect();
+                if !missing.is_empty() && interface.link.as_ref().is_some_and(|link| link.up) {
+                    problems.push(Problem::new(Some(&interface.name), format!("DNS servers {} from {} are not in /etc/resolv.conf",
+                        missing.iter().map(|server| server.as_str()).collect::<Vec<_>>().join(", "), config.source))
+                        .advice("something else writes /etc/resolv.conf; check where it points with `ls -l /etc/resolv.conf`"));
+                }
+            }
+        }
+
+        for path in &self.unreadable {
+            problems.push(Problem::new(None, format!("{} could not be read", path)).advice("run as root to include it"));
+        }
+        self.problems = problems;
+    }
+}
+
+/// A change to the addressing of one interface
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NetworkChange {
+    pub interface: String,
+    pub dhcp: bool,
+    /// Static addresses in CIDR notation, replacing the configured ones
+    pub addresses: Vec<String>,
+    pub gateway: Option<String>,
+    /// Nameservers replacing the configured ones; empty keeps them
+    pub dns: Vec<String>,
+}
+
+impl NetworkChange {
+    pub fn validate(&self) -> Result<()> {
+        if !self.interface.chars().all(|c| c.is_ascii_alphanumeric() || "._-@".contains(c)) || self.interface.is_empty() {
+            return Err(anyhow::anyhow!("Invalid interface name '{}'", self.interface));
+        }
+        if self.dhcp && (!self.addresses.is_empty() || self.gateway.is_some()) {
+            return Err(anyhow::anyhow!("--dhcp cannot be combined with static addresses or a gateway"));
+        }
+        if !self.dhcp && self.addresses.is_empty() && self.gateway.is_none() && self.dns.is_empty() {
+            return Err(anyhow::anyhow!("Nothing to change: give --dhcp, --address, --gateway or --dns"));
+        }
+        for address in &self.addresses {
+            if parse_cidr(address).is_none() {
+                return Err(anyhow::anyhow!("Invalid address '{}'; give it with a prefix length, such as 192.0.2.10/24", address));
+            }
+        }
+        for ip in self.gateway.iter().chain(&self.dns) {
+            if ip.parse::<IpAddr>().is_err() {
+                return Err(anyhow::anyhow!("Invalid IP address '{}'", ip));
+            }
+        }
+        if let Some(gateway) = self.gateway.as_ref().filter(|gateway| gateway.parse::<IpAddr>().is_ok_and(|ip| ip.is_ipv6())) {
+            return Err(anyhow::anyhow!("Gateway {} is an IPv6 address; only IPv4 gateways can be set", gateway));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for NetworkChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if self.dhcp {
+            parts.push("DHCP".to_string());
+        }
+        if !self.addresses.is_empty() {
+            parts.push(self.addresses.join(", "));
+        }
+        if let Some(gateway) = &self.gateway {
+            parts.push(format!("gateway {}", gateway));
+        }
+        if !self.dns.is_empty() {
+            parts.push(format!("DNS {}", self.dns.join(", ")));
+        }
+        write!(f, "Configure {} with {}", self.interface, parts.join(", "))
+    }
+}
+
+/// A configuration file to install
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigFile {
+    pub path: String,
+    /// Current content, empty for a new file
+    pub before: String,
+    pub content: String,
+    /// Permission bits, e.g. 0o600 for files that may hold Wi-Fi passwords
+    pub mode: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum NetworkStep {
+    Write(ConfigFile),
+    /// Program and arguments
+    Run(Vec<String>),
+}
+
+impl fmt::Display for NetworkStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkStep::Write(file) => write!(f, "write {}", file.path),
+            NetworkStep::Run(command) => write!(f, "{}", command.iter().map(|word| shell_quote(word)).collect::<Vec<_>>().join(" ")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkPlan {
+    pub change: NetworkChange,
+    pub stack: Stack,
+    pub before: InterfaceConfig,
+    pub after: InterfaceConfig,
+    pub steps: Vec<NetworkStep>,
+    /// Command that restores the previous configuration
+    pub undo: String,
+    /// Caveat about how the change is made
+    pub note: Option<String>,
+}
+
+#[async_trait::async_trait]
+pub trait NetworkBackend: std::fmt::Debug + Send + Sync {
+    fn stack(&self) -> Stack;
+
+    /// Interfaces the stack manages right now; empty when its daemon is not running
+    async fn managed(&self) -> Vec<String>;
+
+    /// Every interface configuration, and the files that could not be read
+    async fn configs(&self) -> (Vec<InterfaceConfig>, Vec<String>);
+
+    /// Steps that turn the configuration of `interface` from `before` into `after`. A
+    /// `before` without a source is a new configuration.
+    async fn plan(&self, interface: &str, before: &InterfaceConfig, after: &InterfaceConfig) -> Result<Vec<NetworkStep>>;
+
+    /// Caveat shown with every change, if any
+    fn note(&self) -> Option<&'static str> {
+        None
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NetworkInspector {
+    transport: Arc<dyn Transport>,
+}
+
+impl NetworkInspector {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    fn backends(&self) -> Vec<Box<dyn NetworkBackend>> {
+        vec![
+            Box::new(netplan::Netplan::new(self.transport.clone())),
+            Box::new(networkmanager::NetworkManager::new(self.transport.clone())),
+            Box::new(networkd::Networkd::new(self.transport.clone())),
+            Box::new(ifupdown::Ifupdown::new(self.transport.clone())),
+        ]
+    }
+
+    /// Interfaces with their owner, configuration and runtime state, and the problems
+    /// found comparing them
+    pub async fn report(&self) -> NetworkReport {
+        let transport = self.transport.as_ref();
+        let mut report = NetworkReport::default();
+
+        let mut configs = Vec::new();
+        let mut managed = BTreeMap::new();
+        for backend in self.backends() {
+            let (found, unreadable) = backend.configs().await;
+            configs.extend(found);
+            report.unreadable.extend(unreadable);
+            managed.insert(backend.stack(), backend.managed().await);
+        }
+
+        let links = match transport.run("ip", &["-j", "addr", "show"]).await {
+            Ok(output) if output.success() => parse_ip_addr(&output.stdout),
+            _ => Vec::new(),
+        };
+        for family in ["-4", "-6"] {
+            if let Ok(output) = transport.run("ip", &["-j", family, "route", "show", "default"]).await {
+                report.default_routes.extend(parse_ip_route(&output.stdout));
+            }
+        }
+        if let Ok(resolv) = transport.read_file("/etc/resolv.conf").await {
+            (report.nameservers, report.search) = parse_resolv_conf(&resolv);
+        }
+
+        // Interfaces the kernel has, then configured ones that are missing. Units
+        // shipped in /usr/lib for interfaces that only exist in some setups, such as
+        // host0 in containers, are not worth reporting.
+        let mut names: Vec<String> = links.iter().map(|link| link.name.clone()).collect();
+        let vendor = |config: &InterfaceConfig| config.source.starts_with("/usr/") || config.source.starts_with("/lib/");
+        for config in configs.iter().filter(|config| config.is_concrete() && !vendor(config)) {
+            if !names.contains(&config.interface) {
+                names.push(config.interface.clone());
+            }
+        }
+
+        for name in names {
+            let configs: Vec<InterfaceConfig> = configs.iter().filter(|config| config.matches(&name)).cloned().collect();
+            let owner = owner(&name, &configs, &managed);
+            report.interfaces.push(Interface {
+                link: links.iter().find(|link| link.name == name).cloned(),
+                name,
+                owner,
+                configs,
+            });
+        }
+        report.diagnose();
+        report
+    }
+
+    /// Plan `change` for the stack that owns the interface. Interfaces nothing
+    /// configures yet get a configuration from the first stack that is running.
+    pub async fn plan(&self, change: &NetworkChange) -> Result<NetworkPlan> {
+        change.validate()?;
+        let report = self.report().await;
+        let interface = report.interface(&change.interface)
+            .ok_or_else(|| anyhow::anyhow!("No interface named {}", change.interface))?;
+
+        let backends = self.backends();
+        let mut chosen = None;
+        for backend in backends {
+            let running = match interface.owner {
+                Some(owner) => backend.stack() == owner,
+                None => !backend.managed().await.is_empty(),
+            };
+            if running {
+                chosen = Some(backend);
+                break;
+            }
+        }
+        let backend = chosen.ok_or_else(|| anyhow::anyhow!(
+            "No network stack manages {}; none of NetworkManager, systemd-networkd, netplan or ifupdown is in use", change.interface))?;
+
+        let before = interface.config().cloned()
+            .unwrap_or_else(|| InterfaceConfig::new(&change.interface, backend.stack(), ""));
+        if change.gateway.is_some() && change.addresses.is_empty() && before.method != Method::Static {
+            return Err(anyhow::anyhow!("{} has no static address to go with the gateway; give one with --address", change.interface));
+        }
+        let after = before.changed(change);
+        let steps = backend.plan(&change.interface, &before, &after).await?;
+
+        let undo = if before.source.is_empty() {
+            match backend.stack() {
+                Stack::NetworkManager => format!("nmcli connection delete {}", change.interface),
+                _ => format!("remove {} and apply the configuration again", steps.iter().find_map(|step| match step {
+                    NetworkStep::Write(file) => Some(file.path.clone()),
+                    NetworkStep::Run(_) => None,
+                }).unwrap_or_default()),
+            }
+        } else {
+            before.set_command(&change.interface)
+        };
+        Ok(NetworkPlan {
+            change: change.clone(),
+            stack: backend.stack(),
+            note: backend.note().map(String::from),
+            before,
+            after,
+            steps,
+            undo,
+        })
+    }
+}
+
+/// The stack responsible for `name`. netplan comes first because it generates the
+/// configuration of its renderer; then the daemons that manage it right now; then a
+/// stack whose configuration exists even though its daemon is not running.
+fn owner(name: &str, configs: &[InterfaceConfig], managed: &BTreeMap<Stack, Vec<String>>) -> Option<Stack> {
+    if configs.iter().any(|config| config.stack == Stack::Netplan) {
+        return Some(Stack::Netplan);
+    }
+    for stack in [Stack::NetworkManager, Stack::Networkd, Stack::Ifupdown] {
+        if managed.get(&stack).is_some_and(|names| names.iter().any(|managed| managed == name)) {
+            return Some(stack);
+        }
+    }
+    configs.first().map(|config| config.stack)
+}
+
+/// Command that applies a changed configuration of `interface`
+fn apply_hint(config: &InterfaceConfig, interface: &str) -> String {
+    match config.stack {
+        Stack::NetworkManager => format!("nmcli connection up {}", shell_quote(config.id.as_deref().unwrap_or(interface))),
+        Stack::Networkd => format!("networkctl reload && networkctl reconfigure {}", interface),
+        Stack::Netplan => "netplan apply".to_string(),
+        Stack::Ifupdown => format!("ifdown {} && ifup {}", interface, interface),
+    }
+}
+
+/// Whether a network management command line only reads state
+pub fn is_query(command: &str, args: &[String]) -> bool {
+    let subcommands: Vec<&str> = args.iter().map(String::as_str).filter(|arg| !arg.starts_with('-')).collect();
+    match command {
+        "nmcli" => {
+            let changes = ["modify", "add", "delete", "up", "down", "reload", "load", "connect", "disconnect", "set", "on", "off", "reapply"];
+            !subcommands.iter().any(|word| changes.contains(word))
+        }
+        "networkctl" => subcommands.first().is_none_or(|word| matches!(*word, "list" | "status" | "lldp" | "label" | "cat")),
+        "netplan" => subcommands.first().is_some_and(|word| matches!(*word, "get" | "info" | "status")),
+        _ => false,
+    }
+}
+
+/// Match a shell glob with `*`, `?` and `[...]`, as networkd's Name= and netplan's
+/// match rules do
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[char], name: &[char]) -> bool {
+        match pattern.first() {
+            None => name.is_empty(),
+            Some('*') => (0..=name.len()).any(|skip| matches(&pattern[1..], &name[skip..])),
+            Some('?') => !name.is_empty() && matches(&pattern[1..], &name[1..]),
+            Some('[') => {
+                let Some(end) = pattern.iter().position(|c| *c == ']') else {
+                    return name.first() == Some(&'[') && matches(&pattern[1..], &name[1..]);
+                };
+                let Some(c) = name.first() else {
+                    return false;
+                };
+                let class = &pattern[1..end];
+                let (negated, class) = match class.first() {
+                    Some('!') | Some('^') => (true, &class[1..]),
+                    _ => (false, class),
+                };
+                let mut found = false;
+                let mut i = 0;
+                while i < class.len() {
+                    if i + 2 < class.len() && class[i + 1] == '-' {
+                        found |= class[i] <= *c && *c <= class[i + 2];
+                        i += 3;
+                    } else {
+                        found |= class[i] == *c;
+                        i += 1;
+                    }
+                }
+                found != negated && matches(&pattern[end + 1..], &name[1..])
+            }
+            Some(c) => name.first() == Some(c) && matches(&pattern[1..], &name[1..]),
+        }
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches(&pattern, &name)
+}
+
+fn parse_cidr(cidr: &str) -> Option<(IpAddr, u8)> {
+    let (ip, prefix) = cidr.split_once('/')?;
+    let ip: IpAddr = ip.parse().ok()?;
+    let prefix: u8 = prefix.parse().ok()?;
+    let max = if ip.is_ipv4() { 32 } else { 128 };
+    (prefix <= max).then_some((ip, prefix))
+}
+
+/// Whether `ip` is in the subnet of the interface address `cidr`
+pub fn in_subnet(cidr: &str, ip: &str) -> bool {
+    let (Some((network, prefix)), Ok(ip)) = (parse_cidr(cidr), ip.parse::<IpAddr>()) else {
+        return false;
+    };
+    match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(network) & mask == u32::from(ip) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(network) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+    }
+}
+
+/// Prefix length of a dotted netmask such as 255.255.255.0, or of a plain number
+pub(crate) fn prefix_length(netmask: &str) -> Option<u8> {
+    if let Ok(prefix) = netmask.parse::<u8>() {
+        return Some(prefix);
+    }
+    let mask = u32::from(netmask.parse::<std::net::Ipv4Addr>().ok()?);
+    (mask.leading_ones() == mask.count_ones()).then(|| mask.count_ones() as u8)
+}
+
+/// Sections of an INI style file as written by NetworkManager and systemd, in order.
+/// Sections may repeat, as networkd's [Address] and [Route] do.
+pub(crate) fn parse_ini(text: &str) -> Vec<(String, Vec<(String, String)>)> {
+    let mut sections: Vec<(String, Vec<(String, String)>)> = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            sections.push((name.trim().to_string(), Vec::new()));
+            continue;
+        }
+        let (Some((key, value)), Some((_, entries))) = (line.split_once('='), sections.last_mut()) else {
+            continue;
+        };
+        entries.push((key.trim().to_string(), value.trim().to_string()));
+    }
+    sections
+}
+
+/// Files matching `pattern` in `dirs`, where a file masks files of the same name in
+/// later directories, sorted by file name
+pub(crate) async fn config_files(transport: &dyn Transport, dirs: &[&str], pattern: &str) -> Vec<String> {
+    let patterns: Vec<String> = dirs.iter().map(|dir| format!("{}/{}", dir, pattern)).collect();
+    let script = format!("ls -1d {} 2>/dev/null", patterns.join(" "));
+    let Ok(output) = transport.run("sh", &["-c", &script]).await else {
+        return Vec::new();
+    };
+
+    let rank = |path: &str| dirs.iter().position(|dir| path.starts_with(&format!("{}/", dir)));
+    let mut by_name: BTreeMap<String, String> = BTreeMap::new();
+    for path in output.stdout.lines() {
+        let Some(name) = path.rsplit('/').next() else {
+            continue;
+        };
+        match by_name.get(name) {
+            Some(existing) if rank(existing) <= rank(path) => {}
+            _ if rank(path).is_some() => {
+                by_name.insert(name.to_string(), path.to_string());
+            }
+            _ => {}
+        }
+    }
+    by_name.into_values().collect()
+}
+
+/// Read `paths`, returning their contents and the paths that could not be read
+pub(crate) async fn read_all(transport: &dyn Transport, paths: Vec<String>) -> (Vec<(String, String)>, Vec<String>) {
+    let mut files = Vec::new();
+    let mut unreadable = Vec::new();
+    for path in paths {
+        match transport.read_file(&path).await {
+            Ok(text) => files.push((path, text)),
+            Err(_) => unreadable.push(path),
+        }
+    }
+    (files, unreadable)
+}
+
+/// Where to write a changed file: files under /etc are edited in place, files
+/// shipped in /usr/lib, /lib or generated in /run are masked by a copy in `etc_dir`
+pub(crate) fn etc_path(source: &str, etc_dir: &str) -> String {
+    if source.starts_with("/etc/") {
+        return source.to_string();
+    }
+    format!("{}/{}", etc_dir, source.rsplit('/').next().unwrap_or(source))
+}
+
+/// Parse `ip -j addr show`, leaving out the loopback interface
+pub(crate) fn parse_ip_addr(output: &str) -> Vec<Link> {
+    let Ok(Value::Array(links)) = serde_json::from_str::<Value>(output) else {
+        return Vec::new();
+    };
+    links.iter()
+        .filter(|link| link["link_type"].as_str() != Some("loopback"))
+        .filter_map(|link| {
+            let flags: Vec<&str> = link["flags"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+            Some(Link {
+                name: link["ifname"].as_str()?.to_string(),
+                state: link["operstate"].as_str().unwrap_or("UNKNOWN").to_string(),
+                up: flags.contains(&"UP"),
+                carrier: flags.contains(&"LOWER_UP"),
+                addresses: link["addr_info"].as_array().into_iter().flatten()
+                    .filter_map(|address| Some(format!("{}/{}", address["local"].as_str()?, address["prefixlen"].as_u64()?)))
+                    .collect(),
+                parent: link["link"].as_str().map(String::from),
+            })
+        })
+        .collect()
+}
+
+/// Parse `ip -j route show`
+pub(crate) fn parse_ip_route(output: &str) -> Vec<RuntimeRoute> {
+    let Ok(Value::Array(routes)) = serde_json::from_str::<Value>(output) else {
+        return Vec::new();
+    };
+    routes.iter()
+        .filter_map(|route| Some(RuntimeRoute {
+            destination: route["dst"].as_str()?.to_string(),
+            gateway: route["gateway"].as_str().map(String::from),
+            device: route["dev"].as_str().map(String::from),
+            metric: route["metric"].as_u64(),
+            onlink: route["flags"].as_array().into_iter().flatten().any(|flag| flag.as_str() == Some("onlink")),
+        }))
+        .collect()
+}
+
+/// Nameservers and search domains of a resolv.conf
+pub(crate) fn parse_resolv_conf(text: &str) -> (Vec<String>, Vec<String>) {
+    let mut nameservers = Vec::new();
+    let mut search = Vec::new();
+    for line in text.lines() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("nameserver") => nameservers.extend(words.next().map(String::from)),
+            Some("search") | Some("domain") => search = words.map(String::from).collect(),
+            _ => {}
+        }
+    }
+    (nameservers, search)
+}
+
@@ -0,0 +1,223 @@
+use anyhow::{Context, Result};
+use serde_yaml::{Mapping, Value};
+use std::sync::Arc;
+
+use crate::remote::transport::Transport;
+use super::{config_files, etc_path, read_all, ConfigFile, InterfaceConfig, Method, NetworkBackend, NetworkStep, Route, Stack, Vlan};
+
+/// Configuration directories; a file in /run masks one of the same name in /etc,
+/// which masks one in /lib
+const CONFIG_DIRS: &[&str] = &["/run/netplan", "/etc/netplan", "/lib/netplan"];
+/// Device types that carry addresses
+const DEVICE_TYPES: &[&str] = &["ethernets", "wifis", "bonds", "bridges", "vlans"];
+
+#[derive(Debug, Clone)]
+pub struct Netplan {
+    transport: Arc<dyn Transport>,
+}
+
+impl Netplan {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+}
+
+fn renderer(value: &Value) -> Option<Stack> {
+    match value.as_str()? {
+        "NetworkManager" => Some(Stack::NetworkManager),
+        _ => Some(Stack::Networkd),
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    value.as_bool().unwrap_or_else(|| matches!(value.as_str(), Some("yes") | Some("true") | Some("on")))
+}
+
+fn strings(value: &Value) -> Vec<String> {
+    value.as_sequence().into_iter().flatten().filter_map(|item| match item {
+        Value::String(text) => Some(text.clone()),
+        // Addresses with options are written as "- 192.0.2.10/24: {lifetime: 0}"
+        Value::Mapping(mapping) => mapping.keys().next().and_then(Value::as_str).map(String::from),
+        _ => None,
+    }).collect()
+}
+
+/// Parse one netplan file into the definitions it contains. The definition ID is the
+/// interface name unless the definition matches on the name or renames the device.
+pub(crate) fn parse_netplan(path: &str, text: &str) -> Vec<InterfaceConfig> {
+    let Ok(document) = serde_yaml::from_str::<Value>(text) else {
+        return Vec::new();
+    };
+    let network = &document["network"];
+    let default_renderer = renderer(&network["renderer"]).unwrap_or(Stack::Networkd);
+
+    let mut configs = Vec::new();
+    for device_type in DEVICE_TYPES {
+        let Some(definitions) = network[*device_type].as_mapping() else {
+            continue;
+        };
+        for (id, definition) in definitions {
+            let Some(id) = id.as_str() else {
+                continue;
+            };
+            let name = definition["set-name"].as_str()
+                .or_else(|| definition["match"]["name"].as_str())
+                .unwrap_or(id);
+            let mut config = InterfaceConfig::new(name, Stack::Netplan, path);
+            config.id = Some(id.to_string());
+            config.renderer = Some(renderer(&definition["renderer"]).unwrap_or(default_renderer));
+            config.dhcp6 = truthy(&definition["dhcp6"]);
+            config.addresses = strings(&definition["addresses"]);
+            if let Some(gateway) = definition["gateway4"].as_str() {
+                config.gateway = Some(gateway.to_string());
+            }
+            if let Some(gateway) = definition["gateway6"].as_str() {
+                config.routes.push(Route { destination: "::/0".to_string(), gateway: Some(gateway.to_string()), metric: None });
+            }
+            for route in definition["routes"].as_sequence().into_iter().flatten() {
+                let destination = route["to"].as_str().unwrap_or_default();
+                let gateway = route["via"].as_str().map(String::from);
+                let metric = route["metric"].as_u64().and_then(|metric| u32::try_from(metric).ok());
+                match (destination, &gateway) {
+                    ("default" | "0.0.0.0/0", Some(via)) if via.contains('.') => config.gateway = gateway.clone(),
+                    ("default", _) => config.routes.push(Route { destination: "::/0".to_string(), gateway, metric }),
+                    _ => config.routes.push(Route { destination: destination.to_string(), gateway, metric }),
+                }
+            }
+            config.dns = strings(&definition["nameservers"]["addresses"]);
+            config.search = strings(&definition["nameservers"]["search"]);
+            if *device_type == "vlans" {
+                if let (Some(vlan_id), Some(link)) = (definition["id"].as_u64(), definition["link"].as_str()) {
+                    config.vlan = u16::try_from(vlan_id).ok().map(|vlan_id| Vlan { id: vlan_id, parent: link.to_string() });
+                }
+            }
+            config.method = if truthy(&definition["dhcp4"]) {
+                Method::Dhcp
+            } else if config.addresses.iter().any(|address| address.contains('.')) {
+                Method::Static
+            } else if definition["link-local"].as_sequence().is_some_and(|families| families.iter().any(|family| family.as_str() == Some("ipv4"))) {
+                Method::LinkLocal
+            } else {
+                Method::Unconfigured
+            };
+            configs.push(config);
+        }
+    }
+    configs
+}
+
+/// A netplan document with the addressing of definition `id` replaced by that of
+/// `config`. The definition is created under `ethernets` when the file has none.
+pub(crate) fn rewrite_netplan(text: &str, id: &str, config: &InterfaceConfig) -> Result<String> {
+    let mut document: Value = if text.trim().is_empty() {
+        serde_yaml::from_str("network:\n  version: 2\n")?
+    } else {
+        serde_yaml::from_str(text).context("Failed to parse the netplan file")?
+    };
+    let network = document.as_mapping_mut()
+        .and_then(|document| document.get_mut("network"))
+        .and_then(Value::as_mapping_mut)
+        .ok_or_else(|| anyhow::anyhow!("The netplan file has no network section"))?;
+
+    let device_type = DEVICE_TYPES.iter()
+        .find(|device_type| network.get(**device_type).and_then(Value::as_mapping).is_some_and(|definitions| definitions.contains_key(id)))
+        .copied()
+        .unwrap_or("ethernets");
+    let definitions = network.entry(Value::from(device_type)).or_insert_with(|| Value::Mapping(Mapping::new()));
+    let definition = definitions.as_mapping_mut()
+        .ok_or_else(|| anyhow::anyhow!("network.{} is not a mapping", device_type))?
+        .entry(Value::from(id))
+        .or_insert_with(|| Value::Mapping(Mapping::new()));
+    if definition.is_null() {
+        *definition = Value::Mapping(Mapping::new());
+    }
+    let definition = definition.as_mapping_mut()
+        .ok_or_else(|| anyhow::anyhow!("network.{}.{} is not a mapping", device_type, id))?;
+
+    definition.insert(Value::from("dhcp4"), Value::from(config.method == Method::Dhcp));
+    if config.addresses.is_empty() {
+        definition.remove("addresses");
+    } else {
+        definition.insert(Value::from("addresses"), Value::Sequence(config.addresses.iter().map(|address| Value::from(address.as_str())).collect()));
+    }
+
+    // gateway4 is deprecated in favour of a default route
+    definition.remove("gateway4");
+    let mut routes: Vec<Value> = definition.get("routes").and_then(Value::as_sequence).cloned().unwrap_or_default();
+    routes.retain(|route| {
+        let to = route["to"].as_str().unwrap_or_default();
+        !(matches!(to, "default" | "0.0.0.0/0") && route["via"].as_str().is_some_and(|via| via.contains('.')))
+    });
+    if let Some(gateway) = &config.gateway {
+        let mut route = Mapping::new();
+        route.insert(Value::from("to"), Value::from("default"));
+        route.insert(Value::from("via"), Value::from(gateway.as_str()));
+        routes.insert(0, Value::Mapping(route));
+    }
+    if routes.is_empty() {
+        definition.remove("routes");
+    } else {
+        definition.insert(Value::from("routes"), Value::Sequence(routes));
+    }
+
+    let nameservers = definition.entry(Value::from("nameservers")).or_insert_with(|| Value::Mapping(Mapping::new()));
+    if let Some(nameservers) = nameservers.as_mapping_mut() {
+        if config.dns.is_empty() {
+            nameservers.remove("addresses");
+        } else {
+            nameservers.insert(Value::from("addresses"), Value::Sequence(config.dns.iter().map(|server| Value::from(server.as_str())).collect()));
+        }
+    }
+    if definition.get("nameservers").and_then(Value::as_mapping).is_some_and(Mapping::is_empty) {
+        definition.remove("nameservers");
+    }
+    Ok(serde_yaml::to_string(&document)?)
+}
+
+#[async_trait::async_trait]
+impl NetworkBackend for Netplan {
+    fn stack(&self) -> Stack {
+        Stack::Netplan
+    }
+
+    /// netplan has no daemon; it hands its configuration to its renderers
+    async fn managed(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Definitions in later files override earlier ones of the same ID. netplan merges
+    /// them key by key; the last definition is the one a change is written to.
+    async fn configs(&self) -> (Vec<InterfaceConfig>, Vec<String>) {
+        let transport = self.transport.as_ref();
+        let paths = config_files(transport, CONFIG_DIRS, "*.yaml").await;
+        let (files, unreadable) = read_all(transport, paths).await;
+
+        let mut configs: Vec<InterfaceConfig> = Vec::new();
+        for (path, text) in &files {
+            for config in parse_netplan(path, text) {
+                configs.retain(|existing| existing.id != config.id);
+                configs.push(config);
+            }
+        }
+        (configs, unreadable)
+    }
+
+    async fn plan(&self, interface: &str, before: &InterfaceConfig, after: &InterfaceConfig) -> Result<Vec<NetworkStep>> {
+        let id = before.id.clone().unwrap_or_else(|| interface.to_string());
+        let (path, text) = if before.source.is_empty() {
+            (format!("/etc/netplan/90-tuxpilot-{}.yaml", interface), String::new())
+        } else {
+            (etc_path(&before.source, "/etc/netplan"), self.transport.read_file(&before.source).await?)
+        };
+        let current = self.transport.read_file(&path).await.unwrap_or_default();
+        Ok(vec![
+            // netplan warns about files other users can read, since they may hold Wi-Fi keys
+            NetworkStep::Write(ConfigFile { path, before: current, content: rewrite_netplan(&text, &id, after)?, mode: 0o600 }),
+            NetworkStep::Run(["netplan", "apply"].map(String::from).to_vec()),
+        ])
+    }
+
+    fn note(&self) -> Option<&'static str> {
+        Some("netplan files are rewritten without their comments")
+    }
+}
@@ -0,0 +1,266 @@
+use anyhow::Result;
+use std::sync::Arc;
+
+use crate::remote::transport::Transport;
+use super::{config_files, etc_path, parse_ini, read_all, ConfigFile, InterfaceConfig, Method, NetworkBackend, NetworkStep, Route, Stack, Vlan};
+
+/// Unit directories by precedence
+const UNIT_DIRS: &[&str] = &[
+    "/etc/systemd/network",
+    "/run/systemd/network",
+    "/usr/local/lib/systemd/network",
+    "/usr/lib/systemd/network",
+    "/lib/systemd/network",
+];
+
+#[derive(Debug, Clone)]
+pub struct Networkd {
+    transport: Arc<dyn Transport>,
+}
+
+impl Networkd {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+}
+
+/// Parse `networkctl list --no-legend`, e.g. "  2 eth0 ether routable configured",
+/// into the links networkd manages
+pub(crate) fn parse_networkctl_list(output: &str) -> Vec<String> {
+    output.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [_, link, _, _, setup, ..] = fields.as_slice() else {
+                return None;
+            };
+            (!matches!(*setup, "unmanaged" | "pending") && *link != "lo").then(|| link.to_string())
+        })
+        .collect()
+}
+
+/// Parse a `.network` unit. Units that match on something other than the name are
+/// left out, since they cannot be tied to an interface without asking networkd.
+pub(crate) fn parse_network(path: &str, text: &str) -> Option<(InterfaceConfig, Vec<String>)> {
+    let sections = parse_ini(text);
+    let values = |section: &str, key: &str| -> Vec<String> {
+        sections.iter()
+            .filter(|(name, _)| name == section)
+            .flat_map(|(_, entries)| entries)
+            .filter(|(name, _)| name == key)
+            .flat_map(|(_, value)| value.split_whitespace().map(String::from).collect::<Vec<_>>())
+            .collect()
+    };
+
+    let names = values("Match", "Name");
+    if names.is_empty() {
+        return None;
+    }
+    let mut config = InterfaceConfig::new(&names.join(" "), Stack::Networkd, path);
+    let dhcp = values("Network", "DHCP").first().cloned().unwrap_or_default();
+    config.dhcp6 = matches!(dhcp.as_str(), "yes" | "true" | "ipv6");
+    config.addresses = values("Network", "Address");
+    for (name, entries) in &sections {
+        let get = |key: &str| entries.iter().rev().find(|(name, _)| name == key).map(|(_, value)| value.clone());
+        match name.as_str() {
+            "Address" => config.addresses.extend(get("Address")),
+            "Route" => {
+                let route = Route {
+                    destination: get("Destination").unwrap_or_else(|| "default".to_string()),
+                    gateway: get("Gateway"),
+                    metric: get("Metric").and_then(|metric| metric.parse().ok()),
+                };
+                match (&route.gateway, route.destination.as_str()) {
+                    (Some(gateway), "default" | "0.0.0.0/0") if gateway.contains('.') => config.gateway = Some(gateway.clone()),
+                    (Some(gateway), "default") => config.routes.push(Route { destination: "::/0".to_string(), gateway: Some(gateway.clone()), metric: route.metric }),
+                    _ => config.routes.push(route),
+                }
+            }
+            _ => {}
+        }
+    }
+    for gateway in values("Network", "Gateway") {
+        if gateway.contains('.') {
+            config.gateway = Some(gateway);
+        } else {
+            config.routes.push(Route { destination: "::/0".to_string(), gateway: Some(gateway), metric: None });
+        }
+    }
+    config.method = if matches!(dhcp.as_str(), "yes" | "true" | "ipv4") {
+        Method::Dhcp
+    } else if config.addresses.iter().any(|address| address.contains('.')) {
+        Method::Static
+    } else if values("Network", "LinkLocalAddressing").iter().any(|value| matches!(value.as_str(), "yes" | "ipv4")) {
+        Method::LinkLocal
+    } else {
+        Method::Unconfigured
+    };
+    config.dns = values("Network", "DNS");
+    config.search = values("Network", "Domains");
+    Some((config, values("Network", "VLAN")))
+}
+
+/// Parse a `.netdev` unit into a VLAN's name and ID
+pub(crate) fn parse_netdev(text: &str) -> Option<(String, u16)> {
+    let sections = parse_ini(text);
+    let get = |section: &str, key: &str| sections.iter()
+        .filter(|(name, _)| name == section)
+        .flat_map(|(_, entries)| entries)
+        .find(|(name, _)| name == key)
+        .map(|(_, value)| value.clone());
+    if get("NetDev", "Kind").as_deref() != Some("vlan") {
+        return None;
+    }
+    Some((get("NetDev", "Name")?, get("VLAN", "Id")?.parse().ok()?))
+}
+
+/// A `.network` unit with its addressing replaced by that of `config`. Other settings
+/// and routes to other networks are kept.
+pub(crate) fn rewrite_network(text: &str, config: &InterfaceConfig) -> String {
+    let mut network = Vec::new();
+    match (config.method, config.dhcp6) {
+        (Method::Dhcp, true) => network.push("DHCP=yes".to_string()),
+        (Method::Dhcp, false) => network.push("DHCP=ipv4".to_string()),
+        (_, true) => network.push("DHCP=ipv6".to_string()),
+        _ => {}
+    }
+    network.extend(config.addresses.iter().map(|address| format!("Address={}", address)));
+    network.extend(config.gateway.iter().map(|gateway| format!("Gateway={}", gateway)));
+    network.extend(config.routes.iter()
+        .filter(|route| route.is_default())
+        .filter_map(|route| route.gateway.as_ref())
+        .map(|gateway| format!("Gateway={}", gateway)));
+    network.extend(config.dns.iter().map(|server| format!("DNS={}", server)));
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut section = String::new();
+    let mut skipping = false;
+    let mut inserted = false;
+    let blocks: Vec<&str> = text.lines().collect();
+    for (index, line) in blocks.iter().enumerate() {
+        let trimmed = line.trim();
+        if let Some(name) = trimmed.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            section = name.to_string();
+            // Route sections are dropped when they are default routes, which needs a
+            // look ahead to their Destination
+            let body: Vec<&str> = blocks[index + 1..].iter()
+                .take_while(|line| !line.trim().starts_with('['))
+                .copied()
+                .collect();
+            let destination = body.iter().find_map(|line| line.trim().strip_prefix("Destination=")).map(str::trim);
+            skipping = section == "Address"
+                || (section == "Route" && matches!(destination, None | Some("0.0.0.0/0") | Some("::/0")));
+            if skipping {
+                // Drop the blank line that separated the section from the previous one
+                if lines.last().is_some_and(|line| line.trim().is_empty()) {
+                    lines.pop();
+                }
+                continue;
+            }
+            lines.push(line.to_string());
+            if section == "Network" && !inserted {
+                lines.append(&mut network.clone());
+                inserted = true;
+            }
+            continue;
+        }
+        if skipping {
+            continue;
+        }
+        let key = trimmed.split_once('=').map(|(key, _)| key.trim());
+        if section == "Network" && matches!(key, Some("DHCP") | Some("Address") | Some("Gateway") | Some("DNS")) {
+            continue;
+        }
+        lines.push(line.to_string());
+    }
+    if !inserted {
+        if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+            lines.push(String::new());
+        }
+        lines.push("[Network]".to_string());
+        lines.extend(network);
+    }
+    lines.join("\n") + "\n"
+}
+
+#[async_trait::async_trait]
+impl NetworkBackend for Networkd {
+    fn stack(&self) -> Stack {
+        Stack::Networkd
+    }
+
+    async fn managed(&self) -> Vec<String> {
+        match self.transport.run("networkctl", &["list", "--no-legend", "--no-pager"]).await {
+            Ok(output) if output.success() => parse_networkctl_list(&output.stdout),
+            _ => Vec::new(),
+        }
+    }
+
+    async fn configs(&self) -> (Vec<InterfaceConfig>, Vec<String>) {
+        let transport = self.transport.as_ref();
+        // netplan's generated units are reported as netplan configuration
+        let mut paths = Vec::new();
+        for pattern in ["*.network", "*.netdev"] {
+            paths.extend(config_files(transport, UNIT_DIRS, pattern).await.into_iter()
+                .filter(|path| !path.rsplit('/').next().unwrap_or_default().starts_with("10-netplan-")));
+        }
+        let (files, unreadable) = read_all(transport, paths).await;
+
+        let mut configs = Vec::new();
+        let mut vlans = Vec::new();
+        let mut netdevs = Vec::new();
+        for (path, text) in &files {
+            if path.ends_with(".netdev") {
+                netdevs.extend(parse_netdev(text).map(|(name, id)| (name, id, path.clone())));
+            } else if let Some((config, vlan_names)) = parse_network(path, text) {
+                vlans.extend(vlan_names.into_iter().map(|name| (name, config.interface.clone())));
+                configs.push(config);
+            }
+        }
+
+        // A VLAN is a .netdev, attached to its parent by the parent's VLAN=
+        for (name, id, path) in netdevs {
+            let parent = vlans.iter().find(|(vlan, _)| *vlan == name).map(|(_, parent)| parent.clone()).unwrap_or_default();
+            let vlan = Vlan { id, parent };
+            match configs.iter_mut().find(|config| config.matches(&name)) {
+                Some(config) => config.vlan = Some(vlan),
+                None => {
+                    let mut config = InterfaceConfig::new(&name, Stack::Networkd, &path);
+                    config.vlan = Some(vlan);
+                    configs.push(config);
+                }
+            }
+        }
+        (configs, unreadable)
+    }
+
+    async fn plan(&self, interface: &str, before: &InterfaceConfig, after: &InterfaceConfig) -> Result<Vec<NetworkStep>> {
+        let match_only = |text: &str| {
+            let mut sections: Vec<(String, Vec<(String, String)>)> = parse_ini(text);
+            sections.retain(|(name, _)| name != "Match");
+            let mut unit = format!("[Match]\nName={}\n", interface);
+            for (name, entries) in sections {
+                unit.push_str(&format!("\n[{}]\n", name));
+                for (key, value) in entries {
+                    unit.push_str(&format!("{}={}\n", key, value));
+                }
+            }
+            unit
+        };
+        let (path, text) = if before.source.is_empty() || before.source.ends_with(".netdev") {
+            (format!("/etc/systemd/network/50-tuxpilot-{}.network", interface), format!("[Match]\nName={}\n", interface))
+        } else if !before.is_concrete() {
+            // The unit matches other interfaces too, so this one gets its own unit that
+            // sorts first and therefore wins
+            let text = self.transport.read_file(&before.source).await?;
+            (format!("/etc/systemd/network/00-tuxpilot-{}.network", interface), match_only(&text))
+        } else {
+            (etc_path(&before.source, UNIT_DIRS[0]), self.transport.read_file(&before.source).await?)
+        };
+        let current = self.transport.read_file(&path).await.unwrap_or_default();
+        Ok(vec![
+            NetworkStep::Write(ConfigFile { path, before: current, content: rewrite_network(&text, after), mode: 0o644 }),
+            NetworkStep::Run(["networkctl", "reload"].map(String::from).to_vec()),
+            NetworkStep::Run(["networkctl", "reconfigure", interface].map(String::from).to_vec()),
+        ])
+    }
+}
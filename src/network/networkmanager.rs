@@ -0,0 +1,260 @@
+use anyhow::Result;
+use std::sync::Arc;
+
+use crate::remote::transport::Transport;
+use super::{config_files, parse_ini, read_all, InterfaceConfig, Method, NetworkBackend, NetworkStep, Route, Stack, Vlan};
+
+/// Keyfile directories; /run holds connections made at runtime, including the ones
+/// netplan generates
+const KEYFILE_DIRS: &[&str] = &["/etc/NetworkManager/system-connections", "/run/NetworkManager/system-connections"];
+
+/// Connection properties read for connections without a readable keyfile
+const CONNECTION_FIELDS: &str = "connection.id,connection.type,connection.interface-name,ipv4.method,ipv4.addresses,\
+    ipv4.gateway,ipv4.dns,ipv4.dns-search,ipv6.method,ipv6.addresses,ipv6.gateway,ipv6.dns,vlan.id,vlan.parent";
+
+#[derive(Debug, Clone)]
+pub struct NetworkManager {
+    transport: Arc<dyn Transport>,
+}
+
+impl NetworkManager {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    /// Devices with their state and active connection, from `nmcli device`
+    async fn devices(&self) -> Vec<(String, String, String)> {
+        let Ok(output) = self.transport.run("nmcli", &["-t", "-f", "DEVICE,STATE,CONNECTION", "device"]).await else {
+            return Vec::new();
+        };
+        if !output.success() {
+            return Vec::new();
+        }
+        parse_devices(&output.stdout)
+    }
+}
+
+/// Parse `nmcli -t -f DEVICE,STATE,CONNECTION device`, where colons inside a field are
+/// escaped with a backslash
+pub(crate) fn parse_devices(output: &str) -> Vec<(String, String, String)> {
+    output.lines()
+        .filter_map(|line| {
+            let mut fields = Vec::new();
+            let mut field = String::new();
+            let mut chars = line.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => field.extend(chars.next()),
+                    ':' => fields.push(std::mem::take(&mut field)),
+                    _ => field.push(c),
+                }
+            }
+            fields.push(field);
+            let [device, state, connection] = <[String; 3]>::try_from(fields).ok()?;
+            Some((device, state, connection))
+        })
+        .collect()
+}
+
+/// Turn `nmcli -t connection show <id>` output, "section.key:value" lines with colons
+/// in values escaped, into keyfile text for `parse_keyfile`
+pub(crate) fn keyfile_from_nmcli(output: &str) -> String {
+    let mut sections: Vec<(String, Vec<String>)> = Vec::new();
+    for line in output.lines() {
+        let Some(colon) = line.char_indices().find(|(index, c)| *c == ':' && !line[..*index].ends_with('\\')).map(|(index, _)| index) else {
+            continue;
+        };
+        let (property, value) = (&line[..colon], line[colon + 1..].replace("\\:", ":"));
+        let Some((section, key)) = property.split_once('.') else {
+            continue;
+        };
+        if value.is_empty() || value == "--" {
+            continue;
+        }
+        // Lists are comma separated in nmcli and semicolon separated in keyfiles
+        let value = if matches!(key, "addresses" | "dns" | "dns-search") { value.replace(',', ";") } else { value };
+        let entry = format!("{}={}", key, value);
+        match sections.iter_mut().find(|(name, _)| name == section) {
+            Some((_, entries)) => entries.push(entry),
+            None => sections.push((section.to_string(), vec![entry])),
+        }
+    }
+    sections.iter().map(|(name, entries)| format!("[{}]\n{}\n", name, entries.join("\n"))).collect::<Vec<_>>().join("\n")
+}
+
+/// Parse a keyfile. Connections without an interface name apply to whichever device
+/// activates them, so they come back with an empty `interface`.
+pub(crate) fn parse_keyfile(path: &str, text: &str) -> Option<InterfaceConfig> {
+    let sections = parse_ini(text);
+    let get = |section: &str, key: &str| sections.iter()
+        .filter(|(name, _)| name == section)
+        .flat_map(|(_, entries)| entries)
+        .find(|(name, _)| name == key)
+        .map(|(_, value)| value.as_str());
+    let numbered = |section: &str, prefix: &str| -> Vec<String> {
+        let mut values: Vec<(u32, String)> = sections.iter()
+            .filter(|(name, _)| name == section)
+            .flat_map(|(_, entries)| entries)
+            .filter_map(|(key, value)| {
+                let number = key.strip_prefix(prefix)?;
+                let number = if number.is_empty() { 0 } else { number.parse().ok()? };
+                Some((number, value.clone()))
+            })
+            .collect();
+        values.sort_by_key(|(number, _)| *number);
+        values.into_iter().flat_map(|(_, value)| value.split(';').map(String::from).collect::<Vec<_>>())
+            .filter(|value| !value.is_empty())
+            .collect()
+    };
+    let list = |section: &str, key: &str| -> Vec<String> {
+        get(section, key).map(|value| value.split([';', ',']).map(str::trim).filter(|value| !value.is_empty()).map(String::from).collect())
+            .unwrap_or_default()
+    };
+
+    let kind = get("connection", "type").unwrap_or_default();
+    if kind == "loopback" {
+        return None;
+    }
+    let vlan = match (get("vlan", "id").and_then(|id| id.parse().ok()), get("vlan", "parent")) {
+        (Some(id), Some(parent)) => Some(Vlan { id, parent: parent.to_string() }),
+        _ => None,
+    };
+    let interface = get("connection", "interface-name")
+        .or_else(|| get("vlan", "interface-name"))
+        .map(String::from)
+        .or_else(|| vlan.as_ref().map(|vlan| format!("{}.{}", vlan.parent, vlan.id)))
+        .unwrap_or_default();
+
+    let mut config = InterfaceConfig::new(&interface, Stack::NetworkManager, path);
+    config.id = get("connection", "id").map(String::from);
+    config.vlan = vlan;
+    // NetworkManager defaults to DHCP when a connection has no [ipv4] method
+    config.method = match get("ipv4", "method").unwrap_or("auto") {
+        "auto" => Method::Dhcp,
+        "manual" | "shared" => Method::Static,
+        "link-local" => Method::LinkLocal,
+        "disabled" => Method::Disabled,
+        _ => Method::Unconfigured,
+    };
+    config.dhcp6 = matches!(get("ipv6", "method"), Some("auto") | Some("dhcp"));
+
+    for family in ["ipv4", "ipv6"] {
+        // Addresses are "address/prefix" with an optional ",gateway" in older keyfiles
+        for entry in numbered(family, "address").into_iter().chain(numbered(family, "addresses")) {
+            let mut parts = entry.split(',');
+            config.addresses.extend(parts.next().map(|address| address.trim().to_string()));
+            if let Some(gateway) = parts.next().map(str::trim).filter(|gateway| !gateway.is_empty()) {
+                set_gateway(&mut config, gateway);
+            }
+        }
+        if let Some(gateway) = get(family, "gateway") {
+            set_gateway(&mut config, gateway);
+        }
+        for entry in numbered(family, "route") {
+            let mut parts = entry.split(',').map(str::trim);
+            config.routes.push(Route {
+                destination: parts.next().unwrap_or_default().to_string(),
+                gateway: parts.next().filter(|gateway| !gateway.is_empty()).map(String::from),
+                metric: parts.next().and_then(|metric| metric.parse().ok()),
+            });
+        }
+        config.dns.extend(list(family, "dns"));
+        config.search.extend(list(family, "dns-search"));
+    }
+    Some(config)
+}
+
+fn set_gateway(config: &mut InterfaceConfig, gateway: &str) {
+    if gateway.contains(':') {
+        config.routes.push(Route { destination: "::/0".to_string(), gateway: Some(gateway.to_string()), metric: None });
+    } else {
+        config.gateway = Some(gateway.to_string());
+    }
+}
+
+/// `nmcli connection modify` or `add` arguments that give a connection the addressing
+/// of `config`
+fn connection_properties(config: &InterfaceConfig) -> Vec<String> {
+    let (ipv4, ipv6): (Vec<&String>, Vec<&String>) = config.addresses.iter().partition(|address| address.contains('.'));
+    let join = |values: &[&String]| values.iter().map(|value| value.as_str()).collect::<Vec<_>>().join(",");
+    let mut properties = vec!["ipv4.method".to_string()];
+    match config.method {
+        Method::Dhcp => properties.extend(["auto".to_string(), "ipv4.addresses".to_string(), String::new()]),
+        _ => properties.extend(["manual".to_string(), "ipv4.addresses".to_string(), join(&ipv4)]),
+    }
+    properties.extend(["ipv4.gateway".to_string(), config.gateway.clone().unwrap_or_default()]);
+    if !ipv6.is_empty() {
+        properties.extend(["ipv6.method".to_string(), "manual".to_string(), "ipv6.addresses".to_string(), join(&ipv6)]);
+    }
+    let (dns4, dns6): (Vec<&String>, Vec<&String>) = config.dns.iter().partition(|server| server.contains('.'));
+    properties.extend(["ipv4.dns".to_string(), join(&dns4)]);
+    if !dns6.is_empty() {
+        properties.extend(["ipv6.dns".to_string(), join(&dns6)]);
+    }
+    properties
+}
+
+#[async_trait::async_trait]
+impl NetworkBackend for NetworkManager {
+    fn stack(&self) -> Stack {
+        Stack::NetworkManager
+    }
+
+    async fn managed(&self) -> Vec<String> {
+        self.devices().await.into_iter()
+            .filter(|(_, state, _)| !state.starts_with("unmanaged"))
+            .map(|(device, _, _)| device)
+            .filter(|device| device != "lo")
+            .collect()
+    }
+
+    async fn configs(&self) -> (Vec<InterfaceConfig>, Vec<String>) {
+        let transport = self.transport.as_ref();
+        // netplan's generated connections are reported as netplan configuration
+        let paths = config_files(transport, KEYFILE_DIRS, "*").await.into_iter()
+            .filter(|path| !path.rsplit('/').next().unwrap_or_default().starts_with("netplan-"))
+            .collect();
+        let (files, unreadable) = read_all(transport, paths).await;
+        let mut configs: Vec<InterfaceConfig> = files.iter().filter_map(|(path, text)| parse_keyfile(path, text)).collect();
+
+        // Active connections that have no readable keyfile, because keyfiles are only
+        // readable by root or the connection only exists in memory, are read with nmcli
+        let devices = self.devices().await;
+        for (device, _, connection) in devices.iter().filter(|(_, _, connection)| !connection.is_empty()) {
+            if let Some(config) = configs.iter_mut().find(|config| config.id.as_ref() == Some(connection)) {
+                if config.interface.is_empty() {
+                    config.interface = device.clone();
+                }
+                continue;
+            }
+            let Ok(output) = self.transport.run("nmcli", &["-t", "-f", CONNECTION_FIELDS, "connection", "show", connection]).await else {
+                continue;
+            };
+            let source = format!("connection '{}'", connection);
+            if let Some(mut config) = output.success().then(|| parse_keyfile(&source, &keyfile_from_nmcli(&output.stdout))).flatten() {
+                config.interface = device.clone();
+                config.id = Some(connection.clone());
+                configs.push(config);
+            }
+        }
+        configs.retain(|config| !config.interface.is_empty());
+        // With nmcli at hand the active connections are all known
+        let unreadable = if devices.is_empty() { unreadable } else { Vec::new() };
+        (configs, unreadable)
+    }
+
+    async fn plan(&self, interface: &str, before: &InterfaceConfig, after: &InterfaceConfig) -> Result<Vec<NetworkStep>> {
+        let name = before.id.clone().unwrap_or_else(|| interface.to_string());
+        let mut change = vec!["nmcli".to_string(), "connection".to_string()];
+        if before.id.is_none() {
+            change.extend(["add", "type", "ethernet", "ifname", interface, "con-name", &name].map(String::from));
+        } else {
+            change.extend(["modify".to_string(), name.clone()]);
+        }
+        change.extend(connection_properties(after));
+        Ok(vec![
+            NetworkStep::Run(change),
+            NetworkStep::Run(["nmcli", "connection", "up", &name].map(String::from).to_vec()),
+        ])
+    }
+}
@@ -0,0 +1,267 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::config::PackageManager;
+use crate::remote::transport::Transport;
+use super::{dependency_name, operand_words, query, words};
+use super::{ChangeAction, InstallReason, PackageBackend, PackageChange, PackageInfo, PackageOperation, TransactionKind};
+
+/// Packages installed on request, one constraint such as "curl" or "vim@edge" per line
+const WORLD_FILE: &str = "/etc/apk/world";
+/// apk exits with 1 when a package or file owner is not found
+const NOT_FOUND: &[i32] = &[1];
+
+#[derive(Debug, Clone)]
+pub struct Apk {
+    transport: Arc<dyn Transport>,
+}
+
+impl Apk {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    async fn query(&self, args: &[&str]) -> Result<String> {
+        query(self.transport.as_ref(), "apk", args, NOT_FOUND).await
+    }
+
+    /// Installed versions by package name
+    async fn installed_versions(&self) -> Result<HashMap<String, String>> {
+        Ok(self.query(&["info", "-v"]).await?
+            .lines()
+            .filter_map(|line| {
+                let (name, version) = split_apk_version(line.trim());
+                Some((name, version?))
+            })
+            .collect())
+    }
+
+    /// Newer repository versions by package name, for all packages or just `names`
+    async fn upgrades(&self, names: &[&str]) -> Result<HashMap<String, (String, String)>> {
+        let mut args = vec!["version", "-l", "<"];
+        args.extend_from_slice(names);
+
+        // "Installed:   Available:" heading, then "busybox-1.36.1-r14  < 1.36.1-r15"
+        Ok(self.query(&args).await?
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let (name, current) = split_apk_version(fields.next()?);
+                if fields.next()? != "<" {
+                    return None;
+                }
+                Some((name, (current?, fields.next()?.to_string())))
+            })
+            .collect())
+    }
+}
+
+pub fn command(operation: &PackageOperation) -> Vec<String> {
+    match operation {
+        PackageOperation::Install(packages) => operand_words("apk", &["add"], packages),
+        PackageOperation::Remove(packages) => operand_words("apk", &["del"], packages),
+        PackageOperation::Upgrade(packages) if packages.is_empty() => words("apk", &["upgrade"], &[]),
+        PackageOperation::Upgrade(packages) => operand_words("apk", &["add", "--upgrade"], packages),
+        PackageOperation::Refresh => words("apk", &["update"], &[]),
+        PackageOperation::Search(term) => words("apk", &["search", "-v", "--", term], &[]),
+        PackageOperation::Info(name) => words("apk", &["info", "-a", "--", name], &[]),
+        PackageOperation::ListInstalled => words("apk", &["info", "-v"], &[]),
+        PackageOperation::ListUpgradable => words("apk", &["version", "-l", "<"], &[]),
+        PackageOperation::Clean => words("apk", &["cache", "clean"], &[]),
+    }
+}
+
+/// Split "font-misc-75dpi-1.0-r0" into the name and "1.0-r0". apk versions always end
+/// in a "-rN" release, and names may contain dashes followed by digits.
+fn split_apk_version(name_version: &str) -> (String, Option<String>) {
+    let mut parts = name_version.rsplitn(3, '-');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(release), Some(version), Some(name)) if release.starts_with('r') => {
+            (name.to_string(), Some(format!("{}-{}", version, release)))
+        }
+        _ => (name_version.to_string(), None),
+    }
+}
+
+/// Package name of a world file constraint such as "vim@edge" or "curl>=8"
+fn constraint_name(constraint: &str) -> &str {
+    constraint.split(|c: char| "<>=~@".contains(c)).next().unwrap_or(constraint)
+}
+
+/// Sections of `apk info -a`, each introduced by a "name-version field:" line
+fn info_sections(output: &str) -> (Option<String>, HashMap<String, Vec<String>>) {
+    let mut name_version = None;
+    let mut sections: HashMap<String, Vec<String>> = HashMap::new();
+    let mut current: Option<String> = None;
+
+    for line in output.lines() {
+        if let Some((nv, field)) = line.strip_suffix(':').and_then(|header| header.split_once(' ')) {
+            if split_apk_version(nv).1.is_some() {
+                name_version.get_or_insert_with(|| nv.to_string());
+                current = Some(field.to_string());
+                continue;
+            }
+        }
+        if let Some(field) = &current {
+            if !line.trim().is_empty() {
+                sections.entry(field.clone()).or_default().push(line.trim().to_string());
+            }
+        }
+    }
+    (name_version, sections)
+}
+
+#[async_trait::async_trait]
+impl PackageBackend for Apk {
+    fn manager(&self) -> PackageManager {
+        PackageManager::Apk
+    }
+
+    async fn search(&self, term: &str) -> Result<Vec<PackageInfo>> {
+        let installed = self.installed_versions().await?;
+        let output = self.query(&["search", "-v", "--", term]).await?;
+
+        // "name-1.0-r0 - description"
+        Ok(output.lines()
+            .filter_map(|line| line.split_once(" - "))
+            .map(|(name_version, description)| {
+                let (name, version) = split_apk_version(name_version);
+                PackageInfo {
+                    installed: installed.contains_key(&name),
+                    version: installed.get(&name).cloned().or(version),
+                    description: Some(description.to_string()),
+                    name,
+                    ..Default::default()
+                }
+            })
+            .collect())
+    }
+
+    async fn info(&self, name: &str) -> Result<Option<PackageInfo>> {
+        let (Some(name_version), sections) = info_sections(&self.query(&["info", "-a", "--", name]).await?) else {
+            return Ok(None);
+        };
+        let (package_name, repository_version) = split_apk_version(&name_version);
+        let installed = self.installed_versions().await?.remove(&package_name);
+        let available = if installed.is_some() {
+            self.upgrades(&[&package_name]).await?.remove(&package_name).map(|(_, available)| available)
+        } else {
+            None
+        };
+        let first = |field: &str| sections.get(field).and_then(|lines| lines.first().cloned());
+
+        Ok(Some(PackageInfo {
+            name: package_name,
+            version: installed.clone().or(repository_version),
+            available_version: available,
+            description: first("description"),
+            repository: None,
+            installed: installed.is_some(),
+            url: first("webpage"),
+            license: first("license"),
+            dependencies: sections.get("depends on")
+                .map(|deps| deps.iter().map(|d| dependency_name(d)).collect())
+                .unwrap_or_default(),
+            source: None,
+        }))
+    }
+
+    async fn list_installed(&self) -> Result<Vec<PackageInfo>> {
+        let mut packages: Vec<PackageInfo> = self.installed_versions().await?
+            .into_iter()
+            .map(|(name, version)| PackageInfo {
+                name,
+                version: Some(version),
+                installed: true,
+                ..Default::default()
+            })
+            .collect();
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(packages)
+    }
+
+    async fn list_upgradable(&self) -> Result<Vec<PackageInfo>> {
+        let mut packages: Vec<PackageInfo> = self.upgrades(&[]).await?
+            .into_iter()
+            .map(|(name, (current, available))| PackageInfo {
+                name,
+                version: Some(current),
+                available_version: Some(available),
+                installed: true,
+                ..Default::default()
+            })
+            .collect();
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(packages)
+    }
+
+    async fn owns_file(&self, path: &str) -> Result<Option<String>> {
+        // "/bin/busybox is owned by busybox-1.36.1-r15"
+        let output = self.query(&["info", "-W", "--", path]).await?;
+        Ok(output.lines()
+            .find_map(|line| line.split_once(" is owned by "))
+            .map(|(_, owner)| split_apk_version(owner.trim()).0))
+    }
+
+    async fn why_installed(&self, name: &str) -> Result<InstallReason> {
+        if !self.installed_versions().await?.contains_key(name) {
+            return Err(anyhow::anyhow!("Package '{}' is not installed", name));
+        }
+
+        let world = self.transport.read_file(WORLD_FILE).await.unwrap_or_default();
+        // "busybox-1.36.1-r15 is required by:" followed by one dependent per line
+        let dependents = self.query(&["info", "-r", "--", name]).await?;
+        let mut required_by: Vec<String> = dependents.lines()
+            .skip_while(|line| !line.ends_with("is required by:"))
+            .skip(1)
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| split_apk_version(line.trim()).0)
+            .collect();
+        required_by.sort();
+        required_by.dedup();
+
+        Ok(InstallReason {
+            package: name.to_string(),
+            explicit: world.lines().any(|line| constraint_name(line.trim()) == name),
+            required_by,
+        })
+    }
+
+    async fn preview(&self, kind: &TransactionKind, packages: &[String]) -> Result<Vec<PackageChange>> {
+        let mut args = match kind {
+            TransactionKind::Install => vec!["add"],
+            TransactionKind::Remove => vec!["del"],
+            TransactionKind::Upgrade if packages.is_empty() => vec!["upgrade"],
+            TransactionKind::Upgrade => vec!["add", "--upgrade"],
+        };
+        args.extend(["--simulate", "--"]);
+        args.extend(packages.iter().map(String::as_str));
+
+        // "(1/2) Installing name (1.0-r0)" and "(2/2) Upgrading name (1.0-r0 -> 1.1-r0)"
+        let output = query(self.transport.as_ref(), "apk", &args, &[]).await?;
+        Ok(output.lines()
+            .filter_map(|line| {
+                let rest = line.trim().strip_prefix('(')?.split_once(") ")?.1;
+                let (verb, rest) = rest.split_once(' ')?;
+                let (name, versions) = rest.split_once(" (")?;
+                let versions = versions.trim_end_matches(')');
+                let (from_version, to_version) = match versions.split_once(" -> ") {
+                    Some((from, to)) => (Some(from.to_string()), Some(to.to_string())),
+                    None if verb == "Purging" => (Some(versions.to_string()), None),
+                    None => (None, Some(versions.to_string())),
+                };
+
+                let action = match verb {
+                    "Installing" => ChangeAction::Install,
+                    "Upgrading" => ChangeAction::Upgrade,
+                    "Downgrading" => ChangeAction::Downgrade,
+                    "Replacing" | "Reinstalling" => ChangeAction::Reinstall,
+                    "Purging" => ChangeAction::Remove,
+                    _ => return None,
+                };
+                Some(PackageChange { name: name.to_string(), action, from_version, to_version })
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,218 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::config::PackageManager;
+use crate::remote::transport::Transport;
+use super::{dependency_name, key_values, non_empty, operand_words, query, words};
+use super::{ChangeAction, InstallReason, PackageBackend, PackageChange, PackageInfo, PackageOperation, TransactionKind};
+
+/// apt-cache and dpkg-query exit with 100 and 1 for unknown packages
+const NOT_FOUND: &[i32] = &[1, 100];
+
+#[derive(Debug, Clone)]
+pub struct Apt {
+    transport: Arc<dyn Transport>,
+}
+
+impl Apt {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    async fn query(&self, program: &str, args: &[&str]) -> Result<String> {
+        query(self.transport.as_ref(), program, args, NOT_FOUND).await
+    }
+
+    /// Installed versions by package name
+    async fn installed_versions(&self) -> Result<HashMap<String, String>> {
+        let output = self.query("dpkg-query", &["-W", "-f=${db:Status-Abbrev}\t${Package}\t${Version}\n"]).await?;
+        Ok(output.lines()
+            .filter(|line| line.starts_with("ii"))
+            .filter_map(|line| {
+                let mut fields = line.split('\t').skip(1);
+                Some((fields.next()?.to_string(), fields.next()?.to_string()))
+            })
+            .collect())
+    }
+}
+
+pub fn command(operation: &PackageOperation) -> Vec<String> {
+    match operation {
+        PackageOperation::Install(packages) => operand_words("apt-get", &["install", "-y"], packages),
+        PackageOperation::Remove(packages) => operand_words("apt-get", &["remove", "-y"], packages),
+        PackageOperation::Upgrade(packages) if packages.is_empty() => words("apt-get", &["upgrade", "-y"], &[]),
+        PackageOperation::Upgrade(packages) => operand_words("apt-get", &["install", "--only-upgrade", "-y"], packages),
+        PackageOperation::Refresh => words("apt-get", &["update"], &[]),
+        PackageOperation::Search(term) => words("apt-cache", &["search", "--", term], &[]),
+        PackageOperation::Info(name) => words("apt-cache", &["show", "--", name], &[]),
+        PackageOperation::ListInstalled => words("dpkg-query", &["-W"], &[]),
+        PackageOperation::ListUpgradable => words("apt", &["list", "--upgradable"], &[]),
+        PackageOperation::Clean => words("apt-get", &["autoclean"], &[]),
+    }
+}
+
+#[async_trait::async_trait]
+impl PackageBackend for Apt {
+    fn manager(&self) -> PackageManager {
+        PackageManager::Apt
+    }
+
+    async fn search(&self, term: &str) -> Result<Vec<PackageInfo>> {
+        let installed = self.installed_versions().await?;
+        let output = self.query("apt-cache", &["search", "--", term]).await?;
+
+        // "name - short description"
+        Ok(output.lines()
+            .filter_map(|line| line.split_once(" - "))
+            .map(|(name, description)| PackageInfo {
+                name: name.to_string(),
+                version: installed.get(name).cloned(),
+                description: Some(description.to_string()),
+                installed: installed.contains_key(name),
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    async fn info(&self, name: &str) -> Result<Option<PackageInfo>> {
+        let output = self.query("apt-cache", &["show", "--no-all-versions", "--", name]).await?;
+        let fields = key_values(&output);
+        let Some(package_name) = fields.get("Package") else {
+            return Ok(None);
+        };
+
+        let installed = self.installed_versions().await?.remove(package_name);
+        let candidate = non_empty(fields.get("Version"));
+        // Only the synopsis; the long description follows on indented lines
+        let description = output.lines()
+            .find_map(|line| line.strip_prefix("Description: ").or_else(|| line.strip_prefix("Description-en: ")))
+            .map(String::from);
+
+        Ok(Some(PackageInfo {
+            name: package_name.clone(),
+            available_version: candidate.clone().filter(|_| installed.is_some() && installed != candidate),
+            version: installed.clone().or(candidate),
+            description,
+            repository: None,
+            installed: installed.is_some(),
+            url: non_empty(fields.get("Homepage")),
+            license: None,
+            dependencies: non_empty(fields.get("Depends"))
+                .map(|deps| deps.split(',').map(|d| dependency_name(d.trim())).collect())
+                .unwrap_or_default(),
+            source: None,
+        }))
+    }
+
+    async fn list_installed(&self) -> Result<Vec<PackageInfo>> {
+        let mut packages: Vec<PackageInfo> = self.installed_versions().await?
+            .into_iter()
+            .map(|(name, version)| PackageInfo {
+                name,
+                version: Some(version),
+                installed: true,
+                ..Default::default()
+            })
+            .collect();
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(packages)
+    }
+
+    async fn list_upgradable(&self) -> Result<Vec<PackageInfo>> {
+        // "name/suite 2.0 amd64 [upgradable from: 1.0]"
+        let output = self.query("apt", &["list", "--upgradable"]).await?;
+        Ok(output.lines()
+            .filter(|line| line.contains("[upgradable from:"))
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let (name, suite) = fields.next()?.split_once('/')?;
+                let available = fields.next()?;
+                let current = line.rsplit_once("from: ")?.1.trim_end_matches(']');
+                Some(PackageInfo {
+                    name: name.to_string(),
+                    version: Some(current.to_string()),
+                    available_version: Some(available.to_string()),
+                    repository: Some(suite.to_string()),
+                    installed: true,
+                    ..Default::default()
+                })
+            })
+            .collect())
+    }
+
+    async fn owns_file(&self, path: &str) -> Result<Option<String>> {
+        // "coreutils: /usr/bin/ls", or "a, b: /path" when several packages ship it
+        let output = self.query("dpkg-query", &["-S", "--", path]).await?;
+        Ok(output.lines()
+            .filter_map(|line| line.rsplit_once(": "))
+            .find(|(_, file)| *file == path)
+            .and_then(|(owners, _)| owners.split(", ").next())
+            .map(|owner| owner.split(':').next().unwrap_or(owner).to_string()))
+    }
+
+    async fn why_installed(&self, name: &str) -> Result<InstallReason> {
+        if !self.installed_versions().await?.contains_key(name) {
+            return Err(anyhow::anyhow!("Package '{}' is not installed", name));
+        }
+
+        let automatic = self.query("apt-mark", &["showauto", "--", name]).await?;
+        let reverse = self.query("apt-cache", &["rdepends", "--installed", "--", name]).await?;
+
+        // "name\nReverse Depends:\n  dependent\n |alternative"
+        let mut required_by: Vec<String> = reverse.lines()
+            .skip_while(|line| !line.starts_with("Reverse Depends:"))
+            .skip(1)
+            .map(|line| line.trim().trim_start_matches('|').to_string())
+            .filter(|line| !line.is_empty())
+            .collect();
+        required_by.dedup();
+
+        Ok(InstallReason {
+            package: name.to_string(),
+            explicit: automatic.trim().is_empty(),
+            required_by,
+        })
+    }
+
+    async fn preview(&self, kind: &TransactionKind, packages: &[String]) -> Result<Vec<PackageChange>> {
+        let mut args = vec!["-s"];
+        match kind {
+            TransactionKind::Install => args.push("install"),
+            TransactionKind::Remove => args.push("remove"),
+            TransactionKind::Upgrade if packages.is_empty() => args.push("upgrade"),
+            TransactionKind::Upgrade => args.extend(["install", "--only-upgrade"]),
+        }
+        args.push("--");
+        args.extend(packages.iter().map(String::as_str));
+
+        // "Inst name [1.0] (2.0 Debian:12/stable [amd64])" and "Remv name [1.0]"
+        let output = query(self.transport.as_ref(), "apt-get", &args, &[]).await?;
+        Ok(output.lines()
+            .filter_map(|line| {
+                let (verb, rest) = line.split_once(' ')?;
+                let name = rest.split_whitespace().next()?.to_string();
+                // The installed version comes before the candidate's "(...)" group
+                let installed_part = rest.split('(').next().unwrap_or(rest);
+                let from_version = bracketed(installed_part, '[', ']');
+                let to_version = bracketed(rest, '(', ')')
+                    .and_then(|v| v.split_whitespace().next().map(String::from));
+
+                let action = match verb {
+                    "Inst" if from_version.is_some() => ChangeAction::Upgrade,
+                    "Inst" => ChangeAction::Install,
+                    "Remv" | "Purg" => ChangeAction::Remove,
+                    _ => return None,
+                };
+                Some(PackageChange { name, action, from_version, to_version })
+            })
+            .collect())
+    }
+}
+
+/// First text between `open` and `close`
+fn bracketed(text: &str, open: char, close: char) -> Option<String> {
+    let start = text.find(open)? + 1;
+    let end = text[start..].find(close)? + start;
+    Some(text[start..end].to_string())
+}
@@ -0,0 +1,161 @@
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::config::PackageManager;
+use crate::remote::transport::Transport;
+use super::{operand_words, query, rpm, words};
+use super::{InstallReason, PackageBackend, PackageInfo, PackageOperation};
+
+/// `dnf check-update` exits with 100 when updates are available
+const UPDATES_AVAILABLE: i32 = 100;
+
+#[derive(Debug, Clone)]
+pub struct Dnf {
+    transport: Arc<dyn Transport>,
+}
+
+impl Dnf {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    async fn query(&self, args: &[&str], accept: &[i32]) -> Result<String> {
+        query(self.transport.as_ref(), "dnf", args, accept).await
+    }
+
+    /// Newest version of a package in the enabled repositories
+    async fn latest(&self, name: &str) -> Result<Option<PackageInfo>> {
+        // dnf5 does not end --qf output with a newline, dnf4 does; blank lines are skipped
+        let format = "%{name}\t%{version}-%{release}\t%{summary}\t%{url}\t%{license}\t%{repoid}\n";
+        let output = self.query(&["-q", "repoquery", "--latest-limit", "1", "--qf", format, "--", name], &[]).await?;
+
+        Ok(output.lines()
+            .map(|line| line.split('\t').collect::<Vec<_>>())
+            .find(|fields| fields.len() == 6 && fields[0] == name)
+            .map(|fields| PackageInfo {
+                name: fields[0].to_string(),
+                version: Some(fields[1].to_string()),
+                description: Some(fields[2].to_string()),
+                repository: Some(fields[5].to_string()),
+                url: Some(fields[3].to_string()).filter(|url| url != "(none)"),
+                license: Some(fields[4].to_string()),
+                ..Default::default()
+            }))
+    }
+}
+
+pub fn command(operation: &PackageOperation) -> Vec<String> {
+    match operation {
+        PackageOperation::Install(packages) => operand_words("dnf", &["install", "-y"], packages),
+        PackageOperation::Remove(packages) => operand_words("dnf", &["remove", "-y"], packages),
+        PackageOperation::Upgrade(packages) => operand_words("dnf", &["upgrade", "-y"], packages),
+        PackageOperation::Refresh => words("dnf", &["makecache"], &[]),
+        PackageOperation::Search(term) => words("dnf", &["search", "--", term], &[]),
+        PackageOperation::Info(name) => words("dnf", &["info", "--", name], &[]),
+        PackageOperation::ListInstalled => words("dnf", &["list", "--installed"], &[]),
+        PackageOperation::ListUpgradable => words("dnf", &["check-update"], &[]),
+        PackageOperation::Clean => words("dnf", &["clean", "all"], &[]),
+    }
+}
+
+#[async_trait::async_trait]
+impl PackageBackend for Dnf {
+    fn manager(&self) -> PackageManager {
+        PackageManager::Dnf
+    }
+
+    async fn search(&self, term: &str) -> Result<Vec<PackageInfo>> {
+        let installed: HashSet<String> = rpm::installed(self.transport.as_ref()).await?
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        let output = self.query(&["-q", "search", "--", term], &[]).await?;
+
+        // dnf4 prints "name.arch : summary", dnf5 " name.arch\tsummary", both under
+        // "Matched fields" headings
+        let mut seen = HashSet::new();
+        Ok(output.lines()
+            .filter(|line| !line.starts_with('=') && !line.starts_with("Matched"))
+            .filter_map(|line| line.split_once(" : ").or_else(|| line.trim().split_once('\t')))
+            .filter_map(|(package, summary)| {
+                let package = package.trim();
+                let name = package.rsplit_once('.').map(|(name, _)| name).unwrap_or(package);
+                seen.insert(name.to_string()).then(|| PackageInfo {
+                    name: name.to_string(),
+                    description: Some(summary.trim().to_string()),
+                    installed: installed.contains(name),
+                    ..Default::default()
+                })
+            })
+            .collect())
+    }
+
+    async fn info(&self, name: &str) -> Result<Option<PackageInfo>> {
+        let latest = self.latest(name).await?;
+
+        match rpm::info(self.transport.as_ref(), name).await? {
+            Some(mut package) => {
+                if let Some(latest) = latest {
+                    package.repository = latest.repository;
+                    package.available_version = latest.version.filter(|v| Some(v) != package.version.as_ref());
+                }
+                Ok(Some(package))
+            }
+            None => Ok(latest),
+        }
+    }
+
+    async fn list_installed(&self) -> Result<Vec<PackageInfo>> {
+        rpm::installed(self.transport.as_ref()).await
+    }
+
+    async fn list_upgradable(&self) -> Result<Vec<PackageInfo>> {
+        let installed: HashMap<String, Option<String>> = rpm::installed(self.transport.as_ref()).await?
+            .into_iter()
+            .map(|p| (p.name, p.version))
+            .collect();
+        let output = self.query(&["-q", "check-update"], &[UPDATES_AVAILABLE]).await?;
+
+        // "name.arch  version-release  repo"; obsoleted packages follow their own heading
+        Ok(output.lines()
+            .take_while(|line| !line.starts_with("Obsoleting"))
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                let [package, available, repository] = fields.as_slice() else {
+                    return None;
+                };
+                let name = package.rsplit_once('.').map(|(name, _)| name).unwrap_or(package);
+                Some(PackageInfo {
+                    name: name.to_string(),
+                    version: installed.get(name).cloned().flatten(),
+                    available_version: Some(available.to_string()),
+                    repository: Some(repository.to_string()),
+                    installed: true,
+                    ..Default::default()
+                })
+            })
+            .collect())
+    }
+
+    async fn owns_file(&self, path: &str) -> Result<Option<String>> {
+        rpm::owner(self.transport.as_ref(), path).await
+    }
+
+    async fn why_installed(&self, name: &str) -> Result<InstallReason> {
+        if !rpm::is_installed(self.transport.as_ref(), name).await? {
+            return Err(anyhow::anyhow!("Package '{}' is not installed", name));
+        }
+
+        // "user" for packages installed on request, "dependency" or "weak-dependency"
+        // otherwise; dnf5 capitalises them
+        let reason = self.query(&["-q", "repoquery", "--installed", "--qf", "%{reason}\n", "--", name], &[]).await
+            .unwrap_or_default();
+
+        Ok(InstallReason {
+            package: name.to_string(),
+            explicit: !reason.to_lowercase().contains("dependency"),
+            required_by: rpm::required_by(self.transport.as_ref(), name).await?,
+        })
+    }
+}
@@ -0,0 +1,184 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::config::PackageManager;
+use crate::remote::transport::Transport;
+use super::{operand_words, query, words};
+use super::{InstallReason, PackageBackend, PackageInfo, PackageOperation};
+
+/// `flatpak info` exits with 1 for refs that are not installed
+const NOT_INSTALLED: &[i32] = &[1];
+
+#[derive(Debug, Clone)]
+pub struct Flatpak {
+    transport: Arc<dyn Transport>,
+}
+
+impl Flatpak {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    /// Tab separated rows of the requested columns; "No matches found" and other
+    /// messages have a single cell and are skipped. `args` starts with the subcommand.
+    async fn rows(&self, args: &[&str], columns: &str) -> Result<Vec<Vec<String>>> {
+        let columns = format!("--columns={}", columns);
+        // The columns go right after the subcommand, ahead of any "--"
+        let mut command = args.to_vec();
+        command.insert(1, &columns);
+
+        let width = columns.split(',').count();
+        let output = query(self.transport.as_ref(), "flatpak", &command, &[]).await?;
+        Ok(output.lines()
+            .map(|line| line.split('\t').map(|cell| cell.trim().to_string()).collect::<Vec<_>>())
+            .filter(|cells| cells.len() == width)
+            .collect())
+    }
+
+    /// Installed apps and runtimes by application ID, with their versions
+    async fn installed_versions(&self) -> Result<HashMap<String, String>> {
+        Ok(self.rows(&["list"], "application,version").await?
+            .into_iter()
+            .map(|cells| (cells[0].clone(), cells[1].clone()))
+            .collect())
+    }
+}
+
+pub fn command(operation: &PackageOperation) -> Vec<String> {
+    match operation {
+        PackageOperation::Install(packages) => operand_words("flatpak", &["install", "--noninteractive", "-y"], packages),
+        PackageOperation::Remove(packages) => operand_words("flatpak", &["uninstall", "--noninteractive", "-y"], packages),
+        PackageOperation::Upgrade(packages) => operand_words("flatpak", &["update", "--noninteractive", "-y"], packages),
+        PackageOperation::Refresh => words("flatpak", &["update", "--appstream"], &[]),
+        PackageOperation::Search(term) => words("flatpak", &["search", "--", term], &[]),
+        PackageOperation::Info(name) => words("flatpak", &["info", "--", name], &[]),
+        PackageOperation::ListInstalled => words("flatpak", &["list"], &[]),
+        PackageOperation::ListUpgradable => words("flatpak", &["remote-ls", "--updates"], &[]),
+        PackageOperation::Clean => words("flatpak", &["uninstall", "--unused", "--noninteractive", "-y"], &[]),
+    }
+}
+
+/// Application ID from a path inside a flatpak installation, such as
+/// "/var/lib/flatpak/app/org.gimp.GIMP/..." or ".../flatpak/exports/bin/org.gimp.GIMP"
+fn owner_from_path(path: &str) -> Option<String> {
+    let (_, inside) = path.split_once("/flatpak/")?;
+    let mut segments = inside.split('/');
+    match (segments.next()?, segments.next()?) {
+        ("app" | "runtime", id) => Some(id.to_string()),
+        ("exports", "bin") => segments.next().map(String::from),
+        _ => None,
+    }
+}
+
+#[async_trait::async_trait]
+impl PackageBackend for Flatpak {
+    fn manager(&self) -> PackageManager {
+        PackageManager::Flatpak
+    }
+
+    async fn search(&self, term: &str) -> Result<Vec<PackageInfo>> {
+        let installed = self.installed_versions().await?;
+        let rows = self.rows(&["search", "--", term], "application,name,description,version,remotes").await?;
+
+        Ok(rows.into_iter()
+            .map(|cells| PackageInfo {
+                installed: installed.contains_key(&cells[0]),
+                version: Some(cells[3].clone()).filter(|v| !v.is_empty()),
+                description: Some(format!("{} - {}", cells[1], cells[2])),
+                repository: Some(cells[4].clone()),
+                name: cells[0].clone(),
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    async fn info(&self, name: &str) -> Result<Option<PackageInfo>> {
+        let output = query(self.transport.as_ref(), "flatpak", &["info", "--", name], NOT_INSTALLED).await?;
+
+        // A "Name - summary" title, then right-aligned "Key: value" lines
+        let fields: HashMap<&str, &str> = output.lines()
+            .filter_map(|line| line.trim().split_once(": "))
+            .collect();
+        let Some(id) = fields.get("ID") else {
+            // Not installed; describe the search result with exactly this ID
+            return Ok(self.search(name).await?.into_iter().find(|p| p.name == name));
+        };
+        let title = output.lines().map(str::trim).find(|line| !line.is_empty() && !line.contains(": "));
+
+        let available = self.rows(&["remote-ls", "--updates"], "application,version").await?
+            .into_iter()
+            .find(|cells| cells[0] == *id)
+            .map(|cells| cells[1].clone())
+            .filter(|v| !v.is_empty());
+
+        Ok(Some(PackageInfo {
+            name: id.to_string(),
+            version: fields.get("Version").map(|v| v.to_string()),
+            available_version: available,
+            description: title.map(String::from),
+            repository: fields.get("Origin").map(|v| v.to_string()),
+            installed: true,
+            url: None,
+            license: fields.get("License").map(|v| v.to_string()),
+            dependencies: fields.get("Runtime").map(|runtime| vec![runtime.to_string()]).unwrap_or_default(),
+            source: None,
+        }))
+    }
+
+    async fn list_installed(&self) -> Result<Vec<PackageInfo>> {
+        let rows = self.rows(&["list"], "application,version,origin,name").await?;
+        Ok(rows.into_iter()
+            .map(|cells| PackageInfo {
+                name: cells[0].clone(),
+                version: Some(cells[1].clone()).filter(|v| !v.is_empty()),
+                repository: Some(cells[2].clone()),
+                description: Some(cells[3].clone()),
+                installed: true,
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    async fn list_upgradable(&self) -> Result<Vec<PackageInfo>> {
+        let installed = self.installed_versions().await?;
+        let rows = self.rows(&["remote-ls", "--updates"], "application,version,origin").await?;
+
+        Ok(rows.into_iter()
+            .map(|cells| PackageInfo {
+                version: installed.get(&cells[0]).cloned().filter(|v| !v.is_empty()),
+                available_version: Some(cells[1].clone()).filter(|v| !v.is_empty()),
+                repository: Some(cells[2].clone()),
+                name: cells[0].clone(),
+                installed: true,
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    async fn owns_file(&self, path: &str) -> Result<Option<String>> {
+        Ok(owner_from_path(path))
+    }
+
+    async fn why_installed(&self, name: &str) -> Result<InstallReason> {
+        let apps = self.rows(&["list", "--app"], "application,runtime").await?;
+        if apps.iter().any(|cells| cells[0] == name) {
+            return Ok(InstallReason { package: name.to_string(), explicit: true, required_by: Vec::new() });
+        }
+        if !self.installed_versions().await?.contains_key(name) {
+            return Err(anyhow::anyhow!("Flatpak '{}' is not installed", name));
+        }
+
+        // Runtimes come in with the apps built against them, e.g.
+        // "org.gnome.Platform/x86_64/45"
+        let prefix = format!("{}/", name);
+        Ok(InstallReason {
+            package: name.to_string(),
+            explicit: false,
+            required_by: apps.into_iter()
+                .filter(|cells| cells[1].starts_with(&prefix))
+                .map(|cells| cells[0].clone())
+                .collect(),
+        })
+    }
+}
@@ -0,0 +1,98 @@
+//! Package changes on image-based systems. rpm-ostree layers packages onto the image
+//! and transactional-update installs into a new snapshot; queries still go through the
+//! distribution's package manager, which reads the same rpm database.
+
+use anyhow::Result;
+
+use crate::config::PackageManager;
+use crate::system_image::SystemImage;
+use super::{operand_words, words};
+use super::{InstallReason, PackageBackend, PackageChange, PackageInfo, PackageOperation, Transaction, TransactionKind};
+
+#[derive(Debug)]
+pub struct Layered {
+    inner: Box<dyn PackageBackend>,
+    image: SystemImage,
+}
+
+impl Layered {
+    pub fn new(inner: Box<dyn PackageBackend>, image: SystemImage) -> Self {
+        Self { inner, image }
+    }
+}
+
+#[async_trait::async_trait]
+impl PackageBackend for Layered {
+    fn manager(&self) -> PackageManager {
+        self.inner.manager()
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<PackageInfo>> {
+        self.inner.search(query).await
+    }
+
+    async fn info(&self, name: &str) -> Result<Option<PackageInfo>> {
+        self.inner.info(name).await
+    }
+
+    async fn list_installed(&self) -> Result<Vec<PackageInfo>> {
+        self.inner.list_installed().await
+    }
+
+    async fn list_upgradable(&self) -> Result<Vec<PackageInfo>> {
+        self.inner.list_upgradable().await
+    }
+
+    async fn owns_file(&self, path: &str) -> Result<Option<String>> {
+        self.inner.owns_file(path).await
+    }
+
+    async fn why_installed(&self, name: &str) -> Result<InstallReason> {
+        self.inner.why_installed(name).await
+    }
+
+    async fn preview(&self, kind: &TransactionKind, packages: &[String]) -> Result<Vec<PackageChange>> {
+        self.inner.preview(kind, packages).await
+    }
+
+    fn command(&self, operation: &PackageOperation) -> Vec<String> {
+        match (&self.image, operation) {
+            (SystemImage::RpmOstree, PackageOperation::Install(packages)) => operand_words("rpm-ostree", &["install"], packages),
+            (SystemImage::RpmOstree, PackageOperation::Remove(packages)) => operand_words("rpm-ostree", &["uninstall"], packages),
+            // Deployments are upgraded as a whole
+            (SystemImage::RpmOstree, PackageOperation::Upgrade(_)) => words("rpm-ostree", &["upgrade"], &[]),
+            (SystemImage::RpmOstree, PackageOperation::Refresh) => words("rpm-ostree", &["refresh-md"], &[]),
+            (SystemImage::RpmOstree, PackageOperation::Clean) => words("rpm-ostree", &["cleanup", "-m"], &[]),
+            (SystemImage::TransactionalUpdate, PackageOperation::Install(packages)) => {
+                words("transactional-update", &["--non-interactive", "pkg", "install"], packages)
+            }
+            (SystemImage::TransactionalUpdate, PackageOperation::Remove(packages)) => {
+                words("transactional-update", &["--non-interactive", "pkg", "remove"], packages)
+            }
+            (SystemImage::TransactionalUpdate, PackageOperation::Upgrade(packages)) if packages.is_empty() => {
+                words("transactional-update", &["--non-interactive", "dup"], &[])
+            }
+            (SystemImage::TransactionalUpdate, PackageOperation::Upgrade(packages)) => {
+                words("transactional-update", &["--non-interactive", "pkg", "update"], packages)
+            }
+            (SystemImage::TransactionalUpdate, PackageOperation::Clean) => words("transactional-update", &["cleanup"], &[]),
+            _ => self.inner.command(operation),
+        }
+    }
+
+    fn needs_root(&self, operation: &PackageOperation) -> bool {
+        self.inner.needs_root(operation)
+    }
+
+    async fn transaction(&self, operation: &PackageOperation) -> Result<Transaction> {
+        if let Some((_, packages)) = operation.transaction_kind() {
+            if self.image == SystemImage::ReadOnly {
+                return Err(anyhow::anyhow!("{}", self.image.package_advice(&packages.join(" "))));
+            }
+        }
+
+        let mut transaction = self.inner.transaction(operation).await?;
+        transaction.command = self.command(operation);
+        Ok(transaction)
+    }
+}
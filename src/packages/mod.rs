@@ -0,0 +1,504 @@
+//! Package manager backends.
+//!
+//! Each backend wraps one package manager's command line tools and returns typed
+//! results, so agents, MCP tools and the web UI never parse package manager output.
+//! Queries run through a transport and work the same on this machine and on inventory
+//! hosts. Transactions only describe what would change and which command does it;
+//! running that command is left to the caller, which goes through the executor or sudo.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::config::PackageManager;
+use crate::remote::transport::{shell_quote, Transport};
+
+pub mod apk;
+pub mod apt;
+pub mod dnf;
+pub mod flatpak;
+pub mod layered;
+pub mod nix;
+pub mod pacman;
+pub mod portage;
+mod rpm;
+pub mod snap;
+pub mod xbps;
+pub mod zypper;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PackageInfo {
+    pub name: String,
+    /// Installed version, or the repository version for packages that are not installed
+    pub version: Option<String>,
+    /// Newer version the repositories offer for an installed package
+    pub available_version: Option<String>,
+    pub description: Option<String>,
+    pub repository: Option<String>,
+    pub installed: bool,
+    pub url: Option<String>,
+    pub license: Option<String>,
+    pub dependencies: Vec<String>,
+    /// Package manager the package belongs to, set when several were queried at once
+    pub source: Option<PackageManager>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum TransactionKind {
+    Install,
+    Remove,
+    Upgrade,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ChangeAction {
+    Install,
+    Upgrade,
+    Downgrade,
+    Reinstall,
+    Remove,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageChange {
+    pub name: String,
+    pub action: ChangeAction,
+    pub from_version: Option<String>,
+    pub to_version: Option<String>,
+}
+
+/// An install, removal or upgrade that has been planned but not run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transaction {
+    pub kind: TransactionKind,
+    pub manager: PackageManager,
+    /// Packages asked for; empty for a full system upgrade
+    pub packages: Vec<String>,
+    /// Command that performs the transaction. It needs root.
+    pub command: Vec<String>,
+    /// Every package the transaction would change, dependencies included, from a dry
+    /// run. Empty when the package manager cannot simulate without root.
+    pub changes: Vec<PackageChange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstallReason {
+    pub package: String,
+    /// Installed on request rather than pulled in as a dependency
+    pub explicit: bool,
+    /// Installed packages that depend on this one
+    pub required_by: Vec<String>,
+}
+
+/// Operations a backend can turn into a command line
+#[derive(Debug, Clone, PartialEq)]
+pub enum PackageOperation {
+    Install(Vec<String>),
+    Remove(Vec<String>),
+    /// Upgrade the given packages, or the whole system when empty
+    Upgrade(Vec<String>),
+    /// Refresh the repository metadata
+    Refresh,
+    Search(String),
+    Info(String),
+    ListInstalled,
+    ListUpgradable,
+    /// Clear the download cache
+    Clean,
+}
+
+impl PackageOperation {
+    /// Parse a CLI operation name such as "install" or "search"
+    pub fn parse(operation: &str, package: Option<&str>) -> Result<Self> {
+        if let Some(package) = package {
+            check_operands(package)?;
+        }
+        let packages = || package.map(|p| p.split_whitespace().map(String::from).collect()).unwrap_or_default();
+        let term = |what: &str| package
+            .map(String::from)
+            .ok_or_else(|| anyhow::anyhow!("'{}' needs a {}", operation, what));
+
+        Ok(match operation.to_lowercase().as_str() {
+            "install" | "add" => PackageOperation::Install(packages()),
+            "remove" | "uninstall" => PackageOperation::Remove(packages()),
+            "upgrade" => PackageOperation::Upgrade(packages()),
+            "update" | "refresh" => PackageOperation::Refresh,
+            "search" => PackageOperation::Search(term("search term")?),
+            "info" | "show" => PackageOperation::Info(term("package name")?),
+            "list" => PackageOperation::ListInstalled,
+            "upgradable" | "outdated" => PackageOperation::ListUpgradable,
+            "clean" => PackageOperation::Clean,
+            _ => return Err(anyhow::anyhow!(
+                "Unknown operation: {}. Try: install, remove, update, upgrade, search, info, list, upgradable, clean",
+                operation
+            )),
+        })
+    }
+
+    pub fn needs_root(&self) -> bool {
+        matches!(self,
+            PackageOperation::Install(_) | PackageOperation::Remove(_) | PackageOperation::Upgrade(_)
+            | PackageOperation::Refresh | PackageOperation::Clean)
+    }
+
+    /// Read-only operations whose results a backend returns as typed data
+    pub fn is_query(&self) -> bool {
+        matches!(self,
+            PackageOperation::Search(_) | PackageOperation::Info(_)
+            | PackageOperation::ListInstalled | PackageOperation::ListUpgradable)
+    }
+
+    pub fn transaction_kind(&self) -> Option<(TransactionKind, &[String])> {
+        match self {
+            PackageOperation::Install(packages) => Some((TransactionKind::Install, packages)),
+            PackageOperation::Remove(packages) => Some((TransactionKind::Remove, packages)),
+            PackageOperation::Upgrade(packages) => Some((TransactionKind::Upgrade, packages)),
+            _ => None,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+pub trait PackageBackend: std::fmt::Debug + Send + Sync {
+    fn manager(&self) -> PackageManager;
+
+    async fn search(&self, query: &str) -> Result<Vec<PackageInfo>>;
+
+    /// Details of one package, `None` when neither installed nor in the repositories
+    async fn info(&self, name: &str) -> Result<Option<PackageInfo>>;
+
+    async fn list_installed(&self) -> Result<Vec<PackageInfo>>;
+
+    /// Installed packages with a newer version available, set in `available_version`
+    async fn list_upgradable(&self) -> Result<Vec<PackageInfo>>;
+
+    /// Name of the installed package that owns `path`
+    async fn owns_file(&self, path: &str) -> Result<Option<String>>;
+
+    async fn why_installed(&self, name: &str) -> Result<InstallReason>;
+
+    /// Packages a transaction would change, from a dry run that does not need root
+    async fn preview(&self, _kind: &TransactionKind, _packages: &[String]) -> Result<Vec<PackageChange>> {
+        Ok(Vec::new())
+    }
+
+    fn command(&self, operation: &PackageOperation) -> Vec<String> {
+        command_line(&self.manager(), operation)
+    }
+
+    /// Whether the operation's command has to run as root. Per-user managers such as
+    /// nix install into the caller's own profile and must not go through sudo.
+    fn needs_root(&self, operation: &PackageOperation) -> bool {
+        operation.needs_root()
+    }
+
+    async fn install(&self, packages: &[String]) -> Result<Transaction> {
+        self.transaction(&PackageOperation::Install(packages.to_vec())).await
+    }
+
+    async fn remove(&self, packages: &[String]) -> Result<Transaction> {
+        self.transaction(&PackageOperation::Remove(packages.to_vec())).await
+    }
+
+    async fn upgrade(&self, packages: &[String]) -> Result<Transaction> {
+        self.transaction(&PackageOperation::Upgrade(packages.to_vec())).await
+    }
+
+    /// Plan an install, removal or upgrade
+    async fn transaction(&self, operation: &PackageOperation) -> Result<Transaction> {
+        let (kind, packages) = operation.transaction_kind()
+            .ok_or_else(|| anyhow::anyhow!("{:?} does not change packages", operation))?;
+        packages.iter().try_for_each(|name| check_operands(name))?;
+
+        let changes = match self.preview(&kind, packages).await {
+            Ok(changes) => changes,
+            Err(e) => {
+                log::warn!("Could not preview package transaction: {:#}", e);
+                Vec::new()
+            }
+        };
+
+        Ok(Transaction {
+            manager: self.manager(),
+            packages: packages.to_vec(),
+            command: self.command(operation),
+            changes,
+            kind,
+        })
+    }
+}
+
+/// Backend for `manager` that runs its queries through `transport`
+pub fn backend(manager: &PackageManager, transport: Arc<dyn Transport>) -> Box<dyn PackageBackend> {
+    match manager {
+        PackageManager::Pacman => Box::new(pacman::Pacman::new(transport)),
+        PackageManager::Apt => Box::new(apt::Apt::new(transport)),
+        PackageManager::Dnf => Box::new(dnf::Dnf::new(transport)),
+        PackageManager::Zypper => Box::new(zypper::Zypper::new(transport)),
+        PackageManager::Portage => Box::new(portage::Portage::new(transport)),
+        PackageManager::Apk => Box::new(apk::Apk::new(transport)),
+        PackageManager::Xbps => Box::new(xbps::Xbps::new(transport)),
+        PackageManager::Nix => Box::new(nix::Nix::new(transport, false)),
+        PackageManager::NixProfile => Box::new(nix::Nix::new(transport, true)),
+        PackageManager::Flatpak => Box::new(flatpak::Flatpak::new(transport)),
+        PackageManager::Snap => Box::new(snap::Snap::new(transport)),
+    }
+}
+
+/// Non-interactive command line for an operation, without a sudo prefix
+pub fn command_line(manager: &PackageManager, operation: &PackageOperation) -> Vec<String> {
+    match manager {
+        PackageManager::Pacman => pacman::command(operation),
+        PackageManager::Apt => apt::command(operation),
+        PackageManager::Dnf => dnf::command(operation),
+        PackageManager::Zypper => zypper::command(operation),
+        PackageManager::Portage => portage::command(operation),
+        PackageManager::Apk => apk::command(operation),
+        PackageManager::Xbps => xbps::command(operation),
+        PackageManager::Nix => nix::env_command(operation),
+        PackageManager::NixProfile => nix::profile_command(operation),
+        PackageManager::Flatpak => flatpak::command(operation),
+        PackageManager::Snap => snap::command(operation),
+    }
+}
+
+/// Several package managers queried together, e.g. the distribution's own next to
+/// flatpak and snap. A source that fails is logged and left out of the results.
+#[derive(Debug)]
+pub struct PackageSources {
+    backends: Vec<Box<dyn PackageBackend>>,
+}
+
+impl PackageSources {
+    pub fn new(managers: &[PackageManager], transport: Arc<dyn Transport>) -> Self {
+        Self {
+            backends: managers.iter().map(|manager| backend(manager, transport.clone())).collect(),
+        }
+    }
+
+    pub fn managers(&self) -> Vec<PackageManager> {
+        self.backends.iter().map(|backend| backend.manager()).collect()
+    }
+
+    pub fn backend(&self, manager: &PackageManager) -> Option<&dyn PackageBackend> {
+        self.backends.iter().find(|backend| backend.manager() == *manager).map(|backend| backend.as_ref())
+    }
+
+    pub async fn search(&self, term: &str) -> Result<Vec<PackageInfo>> {
+        self.collect(|backend| backend.search(term)).await
+    }
+
+    pub async fn list_installed(&self) -> Result<Vec<PackageInfo>> {
+        self.collect(|backend| backend.list_installed()).await
+    }
+
+    pub async fn list_upgradable(&self) -> Result<Vec<PackageInfo>> {
+        self.collect(|backend| backend.list_upgradable()).await
+    }
+
+    /// Details from the first source that knows the package
+    pub async fn info(&self, name: &str) -> Result<Option<PackageInfo>> {
+        for backend in &self.backends {
+            match backend.info(name).await {
+                Ok(Some(mut package)) => {
+                    package.source = Some(backend.manager());
+                    return Ok(Some(package));
+                }
+                Ok(None) => {}
+                Err(e) => log::warn!("{} lookup failed: {:#}", backend.manager(), e),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Run a query against every source at once and tag each result with its source.
+    /// Fails only when every source does.
+    async fn collect<'a, F, Fut>(&'a self, query: F) -> Result<Vec<PackageInfo>>
+    where
+        F: Fn(&'a dyn PackageBackend) -> Fut,
+        Fut: std::future::Future<Output = Result<Vec<PackageInfo>>>,
+    {
+        let results = futures::future::join_all(self.backends.iter().map(|backend| query(backend.as_ref()))).await;
+
+        let mut packages = Vec::new();
+        let mut errors = Vec::new();
+        for (backend, result) in self.backends.iter().zip(results) {
+            match result {
+                Ok(found) => packages.extend(found.into_iter().map(|mut package| {
+                    package.source = Some(backend.manager());
+                    package
+                })),
+                Err(e) => {
+                    log::warn!("{} query failed: {:#}", backend.manager(), e);
+                    errors.push(format!("{}: {:#}", backend.manager(), e));
+                }
+            }
+        }
+
+        if !errors.is_empty() && errors.len() == self.backends.len() {
+            return Err(anyhow::anyhow!("No package source answered: {}", errors.join("; ")));
+        }
+        Ok(packages)
+    }
+}
+
+impl Transaction {
+    /// The operation the transaction performs
+    pub fn operation(&self) -> PackageOperation {
+        let packages = self.packages.clone();
+        match self.kind {
+            TransactionKind::Install => PackageOperation::Install(packages),
+            TransactionKind::Remove => PackageOperation::Remove(packages),
+            TransactionKind::Upgrade => PackageOperation::Upgrade(packages),
+        }
+    }
+
+    pub fn command_line(&self) -> String {
+        self.command.iter().map(|word| shell_quote(word)).collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// `program` followed by `args` and then `extra`, as owned words
+fn words(program: &str, args: &[&str], extra: &[String]) -> Vec<String> {
+    std::iter::once(program)
+        .chain(args.iter().copied())
+        .map(String::from)
+        .chain(extra.iter().cloned())
+        .collect()
+}
+
+/// Like `words`, with `--` ahead of `operands` so that none of them is read as an option
+fn operand_words(program: &str, args: &[&str], operands: &[String]) -> Vec<String> {
+    let mut words = words(program, args, &[]);
+    if !operands.is_empty() {
+        words.push("--".to_string());
+        words.extend(operands.iter().cloned());
+    }
+    words
+}
+
+/// `value` as a package name or search term, refused when the package manager would
+/// read it as an option. Builders also put `--` before names where the tool takes it.
+fn operand(value: &str) -> Result<&str> {
+    if value.starts_with('-') {
+        return Err(anyhow::anyhow!("Invalid package name or search term '{}'", value));
+    }
+    Ok(value)
+}
+
+/// Refuse the whitespace-separated names or terms in `packages` that a package manager
+/// would read as options
+pub fn check_operands(packages: &str) -> Result<()> {
+    packages.split_whitespace().try_for_each(|name| operand(name).map(|_| ()))
+}
+
+/// Run a query in the C locale so its output can be parsed, returning stdout. Exit
+/// codes in `accept` count as success too, e.g. the "nothing found" status of a search.
+async fn query(transport: &dyn Transport, program: &str, args: &[&str], accept: &[i32]) -> Result<String> {
+    let mut command = vec!["LC_ALL=C", program];
+    command.extend_from_slice(args);
+
+    let output = transport.run("env", &command).await?;
+    match output.exit_code {
+        Some(0) => Ok(output.stdout),
+        Some(code) if accept.contains(&code) => Ok(output.stdout),
+        _ => Err(anyhow::anyhow!("{} {} failed: {}", program, args.join(" "), output.stderr.trim())),
+    }
+}
+
+/// Split "name-1.2.3" at the first dash that is followed by a digit, the convention
+/// portage and nix use for versioned names
+fn split_version(name_version: &str) -> (String, Option<String>) {
+    let bytes = name_version.as_bytes();
+    for (i, _) in name_version.match_indices('-') {
+        if bytes.get(i + 1).is_some_and(u8::is_ascii_digit) {
+            return (name_version[..i].to_string(), Some(name_version[i + 1..].to_string()));
+        }
+    }
+    (name_version.to_string(), None)
+}
+
+/// `Key : value` pairs as printed by pacman, apt, dnf and zypper. Indented lines
+/// continue the previous value; the first occurrence of a key wins.
+fn key_values(text: &str) -> HashMap<String, String> {
+    let mut pairs: HashMap<String, String> = HashMap::new();
+    let mut last_key: Option<String> = None;
+
+    for line in text.lines() {
+        if line.starts_with(char::is_whitespace) {
+            if let Some(value) = last_key.as_ref().and_then(|key| pairs.get_mut(key)) {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+
+        last_key = None;
+        if let Some((key, value)) = line.split_once(':') {
+            let key = key.trim().to_string();
+            if !pairs.contains_key(&key) {
+                pairs.insert(key.clone(), value.trim().to_string());
+                last_key = Some(key);
+            }
+        }
+    }
+    pairs
+}
+
+/// Package name from a dependency such as "glibc>=2.38" or "libc6 (>= 2.34)"
+fn dependency_name(dependency: &str) -> String {
+    dependency
+        .split(|c: char| c.is_whitespace() || "<>=(:".contains(c))
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// A value that is missing or one of the "none" placeholders package managers print
+fn non_empty(value: Option<&String>) -> Option<String> {
+    value.filter(|v| !v.is_empty() && v.as_str() != "None" && v.as_str() != "(none)").cloned()
+}
+
+impl std::fmt::Display for PackageInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(source) = &self.source {
+            write!(f, "{}: ", source)?;
+        }
+        write!(f, "{}", self.name)?;
+        if let Some(version) = &self.version {
+            write!(f, " {}", version)?;
+        }
+        if let Some(available) = &self.available_version {
+            write!(f, " → {}", available)?;
+        }
+        if let Some(repository) = &self.repository {
+            write!(f, " [{}]", repository)?;
+        }
+        if self.installed {
+            write!(f, " (installed)")?;
+        }
+        if let Some(description) = &self.description {
+            write!(f, " - {}", description)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for PackageChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let icon = match self.action {
+            ChangeAction::Install => "➕",
+            ChangeAction::Upgrade => "⬆️ ",
+            ChangeAction::Downgrade => "⬇️ ",
+            ChangeAction::Reinstall => "🔁",
+            ChangeAction::Remove => "➖",
+        };
+        write!(f, "{} {}", icon, self.name)?;
+        match (&self.from_version, &self.to_version) {
+            (Some(from), Some(to)) => write!(f, " {} → {}", from, to),
+            (None, Some(version)) | (Some(version), None) => write!(f, " {}", version),
+            (None, None) => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,367 @@
+//! Nix user profiles, managed either with the classic `nix-env` or with `nix profile`.
+//! A profile converted to `nix profile` can no longer be used with `nix-env`, so the
+//! two are separate package managers sharing this backend.
+
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::config::PackageManager;
+use crate::remote::transport::Transport;
+use super::{operand, operand_words, query, split_version, words};
+use super::{InstallReason, PackageBackend, PackageInfo, PackageOperation};
+
+/// nix and nix-env exit with 1 when nothing matches a selector or search term
+const NOT_FOUND: &[i32] = &[1];
+/// `nix` subcommands are still experimental on many installations
+const EXPERIMENTAL: &[&str] = &["--extra-experimental-features", "nix-command flakes"];
+const STORE_DIR: &str = "/nix/store/";
+
+/// An installed `nix profile` element
+struct ProfileElement {
+    name: String,
+    version: Option<String>,
+    /// e.g. "legacyPackages.x86_64-linux.hello"
+    attr_path: Option<String>,
+    /// Flake the element was installed from, e.g. "flake:nixpkgs"
+    flake: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Nix {
+    transport: Arc<dyn Transport>,
+    /// Use `nix profile` rather than `nix-env`
+    profile: bool,
+}
+
+impl Nix {
+    pub fn new(transport: Arc<dyn Transport>, profile: bool) -> Self {
+        Self { transport, profile }
+    }
+
+    async fn nix_env(&self, args: &[&str]) -> Result<String> {
+        query(self.transport.as_ref(), "nix-env", args, NOT_FOUND).await
+    }
+
+    async fn nix(&self, args: &[&str]) -> Result<String> {
+        let mut command = EXPERIMENTAL.to_vec();
+        command.extend_from_slice(args);
+        query(self.transport.as_ref(), "nix", &command, NOT_FOUND).await
+    }
+
+    /// Installed versions by package name, from `nix-env -q`
+    async fn env_installed(&self) -> Result<HashMap<String, Option<String>>> {
+        Ok(self.nix_env(&["-q"]).await?
+            .lines()
+            .map(|line| split_version(line.trim()))
+            .collect())
+    }
+
+    async fn profile_elements(&self) -> Result<Vec<ProfileElement>> {
+        let output = self.nix(&["profile", "list", "--json"]).await?;
+        let manifest: Value = serde_json::from_str(&output)?;
+
+        // Version 3 manifests key elements by name, older ones keep a list
+        let elements: Vec<(Option<String>, &Value)> = match &manifest["elements"] {
+            Value::Object(elements) => elements.iter().map(|(name, e)| (Some(name.clone()), e)).collect(),
+            Value::Array(elements) => elements.iter().map(|e| (None, e)).collect(),
+            _ => Vec::new(),
+        };
+
+        Ok(elements.into_iter()
+            .filter_map(|(name, element)| {
+                let attr_path = element["attrPath"].as_str().map(String::from);
+                let store_name = element["storePaths"][0].as_str().and_then(store_path_name);
+                let name = name
+                    .or_else(|| attr_path.as_ref().and_then(|path| path.rsplit('.').next().map(String::from)))
+                    .or_else(|| store_name.clone().map(|n| split_version(&n).0))?;
+                Some(ProfileElement {
+                    version: store_name.and_then(|n| split_version(&n).1),
+                    flake: element["originalUrl"].as_str().map(String::from),
+                    attr_path,
+                    name,
+                })
+            })
+            .collect())
+    }
+
+    /// Whether the package is part of the profile at all
+    async fn in_profile(&self, name: &str) -> Result<bool> {
+        Ok(if self.profile {
+            self.profile_elements().await?.iter().any(|e| e.name == name)
+        } else {
+            self.env_installed().await?.contains_key(name)
+        })
+    }
+}
+
+pub fn env_command(operation: &PackageOperation) -> Vec<String> {
+    match operation {
+        PackageOperation::Install(packages) => words("nix-env", &["-i"], packages),
+        PackageOperation::Remove(packages) => words("nix-env", &["-e"], packages),
+        PackageOperation::Upgrade(packages) => words("nix-env", &["-u"], packages),
+        PackageOperation::Refresh => words("nix-channel", &["--update"], &[]),
+        PackageOperation::Search(term) => words("nix-env", &["-qaP", "--description", &format!(".*{}.*", term)], &[]),
+        PackageOperation::Info(name) => words("nix-env", &["-qa", "--meta", "--json", name], &[]),
+        PackageOperation::ListInstalled => words("nix-env", &["-q"], &[]),
+        PackageOperation::ListUpgradable => words("nix-env", &["-qc"], &[]),
+        PackageOperation::Clean => words("nix-collect-garbage", &["-d"], &[]),
+    }
+}
+
+pub fn profile_command(operation: &PackageOperation) -> Vec<String> {
+    let flake_refs = |packages: &[String]| -> Vec<String> {
+        packages.iter().map(|p| format!("nixpkgs#{}", p)).collect()
+    };
+
+    match operation {
+        PackageOperation::Install(packages) => words("nix", &["profile", "install"], &flake_refs(packages)),
+        PackageOperation::Remove(packages) => operand_words("nix", &["profile", "remove"], packages),
+        PackageOperation::Upgrade(packages) if packages.is_empty() => words("nix", &["profile", "upgrade", ".*"], &[]),
+        PackageOperation::Upgrade(packages) => operand_words("nix", &["profile", "upgrade"], packages),
+        PackageOperation::Refresh => words("nix", &["flake", "metadata", "--refresh", "nixpkgs"], &[]),
+        PackageOperation::Search(term) => words("nix", &["search", "nixpkgs", term], &[]),
+        PackageOperation::Info(name) => words("nix", &["eval", "--json", &format!("nixpkgs#{}.meta", name)], &[]),
+        PackageOperation::ListInstalled | PackageOperation::ListUpgradable => words("nix", &["profile", "list"], &[]),
+        PackageOperation::Clean => words("nix-collect-garbage", &["-d"], &[]),
+    }
+}
+
+/// "hello-2.12.1" from "/nix/store/<hash>-hello-2.12.1/bin/hello"
+fn store_path_name(path: &str) -> Option<String> {
+    let entry = path.strip_prefix(STORE_DIR)?.split('/').next()?;
+    entry.split_once('-').map(|(_, name)| name.to_string())
+}
+
+/// `meta.license` is a single license or a list of them
+fn license(meta: &Value) -> Option<String> {
+    let name = |license: &Value| license["spdxId"].as_str()
+        .or_else(|| license["shortName"].as_str())
+        .or_else(|| license.as_str())
+        .map(String::from);
+
+    match &meta["license"] {
+        Value::Array(licenses) => Some(licenses.iter().filter_map(name).collect::<Vec<_>>().join(", "))
+            .filter(|joined| !joined.is_empty()),
+        license => name(license),
+    }
+}
+
+/// `meta.homepage` is a URL or a list of them
+fn homepage(meta: &Value) -> Option<String> {
+    meta["homepage"].as_str()
+        .or_else(|| meta["homepage"][0].as_str())
+        .map(String::from)
+}
+
+#[async_trait::async_trait]
+impl PackageBackend for Nix {
+    fn manager(&self) -> PackageManager {
+        if self.profile {
+            PackageManager::NixProfile
+        } else {
+            PackageManager::Nix
+        }
+    }
+
+    fn needs_root(&self, _operation: &PackageOperation) -> bool {
+        false
+    }
+
+    async fn search(&self, term: &str) -> Result<Vec<PackageInfo>> {
+        if self.profile {
+            let installed: Vec<String> = self.profile_elements().await?.into_iter().map(|e| e.name).collect();
+            // {"legacyPackages.x86_64-linux.hello": {"pname", "version", "description"}}
+            let output = self.nix(&["search", "nixpkgs", operand(term)?, "--json"]).await?;
+            let results: HashMap<String, Value> = if output.trim().is_empty() {
+                HashMap::new()
+            } else {
+                serde_json::from_str(&output)?
+            };
+
+            let mut packages: Vec<PackageInfo> = results.into_iter()
+                .map(|(attr_path, result)| {
+                    let name = attr_path.rsplit('.').next().unwrap_or(&attr_path).to_string();
+                    PackageInfo {
+                        installed: installed.contains(&name),
+                        version: result["version"].as_str().map(String::from),
+                        description: result["description"].as_str().map(String::from),
+                        repository: Some("nixpkgs".to_string()),
+                        name,
+                        ..Default::default()
+                    }
+                })
+                .collect();
+            packages.sort_by(|a, b| a.name.cmp(&b.name));
+            return Ok(packages);
+        }
+
+        let installed = self.env_installed().await?;
+        let output = self.nix_env(&["-qaP", "--description", &format!(".*{}.*", operand(term)?)]).await?;
+
+        // "nixpkgs.hello  hello-2.12.1  A program that produces a familiar, friendly greeting"
+        Ok(output.lines()
+            .filter_map(|line| {
+                let (attr_path, rest) = line.split_once(char::is_whitespace)?;
+                let rest = rest.trim_start();
+                let (name_version, description) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                let (name, version) = split_version(name_version);
+                Some(PackageInfo {
+                    installed: installed.contains_key(&name),
+                    version: installed.get(&name).cloned().flatten().or(version),
+                    description: Some(description.trim().to_string()).filter(|d| !d.is_empty()),
+                    repository: attr_path.split('.').next().map(String::from),
+                    name,
+                    ..Default::default()
+                })
+            })
+            .collect())
+    }
+
+    async fn info(&self, name: &str) -> Result<Option<PackageInfo>> {
+        let installed_version = if self.profile {
+            self.profile_elements().await?.into_iter().find(|e| e.name == name).map(|e| e.version)
+        } else {
+            self.env_installed().await?.remove(name)
+        };
+
+        let (version, meta) = if self.profile {
+            // Missing meta attributes would make the evaluation fail, so default them
+            let apply = "p: { version = p.version or null; meta = p.meta or {}; }";
+            let output = self.nix(&["eval", "--json", &format!("nixpkgs#{}", operand(name)?), "--apply", apply]).await?;
+            if output.trim().is_empty() {
+                return Ok(None);
+            }
+            let package: Value = serde_json::from_str(&output)?;
+            (package["version"].as_str().map(String::from), package["meta"].clone())
+        } else {
+            // {"nixpkgs.hello": {"name": "hello-2.12.1", "pname", "version", "meta": {...}}}
+            let output = self.nix_env(&["-qa", "--meta", "--json", operand(name)?]).await?;
+            if output.trim().is_empty() {
+                return Ok(None);
+            }
+            let results: HashMap<String, Value> = serde_json::from_str(&output)?;
+            let Some(package) = results.into_values().next() else {
+                return Ok(None);
+            };
+            let version = package["version"].as_str().map(String::from)
+                .or_else(|| package["name"].as_str().and_then(|n| split_version(n).1));
+            (version, package["meta"].clone())
+        };
+
+        let installed = installed_version.is_some();
+        let installed_version = installed_version.flatten();
+        Ok(Some(PackageInfo {
+            name: name.to_string(),
+            available_version: version.clone().filter(|v| installed && Some(v) != installed_version.as_ref()),
+            version: installed_version.or(version),
+            description: meta["description"].as_str().map(String::from),
+            repository: Some("nixpkgs".to_string()),
+            installed,
+            url: homepage(&meta),
+            license: license(&meta),
+            dependencies: Vec::new(),
+            source: None,
+        }))
+    }
+
+    async fn list_installed(&self) -> Result<Vec<PackageInfo>> {
+        let mut packages: Vec<PackageInfo> = if self.profile {
+            self.profile_elements().await?
+                .into_iter()
+                .map(|e| PackageInfo { name: e.name, version: e.version, installed: true, ..Default::default() })
+                .collect()
+        } else {
+            self.env_installed().await?
+                .into_iter()
+                .map(|(name, version)| PackageInfo { name, version, installed: true, ..Default::default() })
+                .collect()
+        };
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(packages)
+    }
+
+    async fn list_upgradable(&self) -> Result<Vec<PackageInfo>> {
+        if !self.profile {
+            // "hello-2.10  < 2.12.1"
+            let output = self.nix_env(&["-qc"]).await?;
+            return Ok(output.lines()
+                .filter_map(|line| {
+                    let fields: Vec<&str> = line.split_whitespace().collect();
+                    let [installed, "<", available] = fields.as_slice() else {
+                        return None;
+                    };
+                    let (name, version) = split_version(installed);
+                    Some(PackageInfo {
+                        name,
+                        version,
+                        available_version: Some(available.to_string()),
+                        installed: true,
+                        ..Default::default()
+                    })
+                })
+                .collect());
+        }
+
+        // nix profile has no dry-run upgrade, so evaluate each element's flake again
+        let mut packages = Vec::new();
+        for element in self.profile_elements().await? {
+            let (Some(flake), Some(attr_path)) = (&element.flake, &element.attr_path) else {
+                continue;
+            };
+            let latest = match self.nix(&["eval", "--raw", &format!("{}#{}.version", flake, attr_path)]).await {
+                Ok(version) => version.trim().to_string(),
+                Err(e) => {
+                    log::debug!("Could not evaluate {}: {:#}", element.name, e);
+                    continue;
+                }
+            };
+            if !latest.is_empty() && element.version.as_deref() != Some(latest.as_str()) {
+                packages.push(PackageInfo {
+                    name: element.name,
+                    version: element.version,
+                    available_version: Some(latest),
+                    repository: Some(flake.clone()),
+                    installed: true,
+                    ..Default::default()
+                });
+            }
+        }
+        Ok(packages)
+    }
+
+    async fn owns_file(&self, path: &str) -> Result<Option<String>> {
+        // Profile binaries are symlinks into the store path of their package
+        let resolved = self.transport.run("readlink", &["-f", "--", path]).await?;
+        if !resolved.success() {
+            return Ok(None);
+        }
+        Ok(store_path_name(resolved.stdout.trim()).map(|name| split_version(&name).0))
+    }
+
+    async fn why_installed(&self, name: &str) -> Result<InstallReason> {
+        if !self.in_profile(name).await? {
+            return Err(anyhow::anyhow!("Package '{}' is not in the Nix profile", name));
+        }
+
+        // Profile entries are always installed on request; their dependencies live in
+        // the store and never appear in the profile themselves
+        Ok(InstallReason {
+            package: name.to_string(),
+            explicit: true,
+            required_by: Vec::new(),
+        })
+    }
+}
+
+/// Package manager for the Nix profile of the user behind `transport`
+pub async fn detect(transport: &dyn Transport) -> PackageManager {
+    // `nix profile` writes a JSON manifest, `nix-env` a Nix expression
+    let manifest = transport.run("sh", &["-c", "test -e \"$HOME/.nix-profile/manifest.json\""]).await;
+    if manifest.map(|output| output.success()).unwrap_or(false) {
+        PackageManager::NixProfile
+    } else {
+        PackageManager::Nix
+    }
+}
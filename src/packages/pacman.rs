@@ -0,0 +1,203 @@
+use anyhow::Result;
+use std::sync::Arc;
+
+use crate::config::PackageManager;
+use crate::remote::transport::Transport;
+use super::{dependency_name, key_values, non_empty, operand_words, query, words};
+use super::{ChangeAction, InstallReason, PackageBackend, PackageChange, PackageInfo, PackageOperation, TransactionKind};
+
+/// pacman exits with 1 when a query matches nothing
+const NOT_FOUND: &[i32] = &[1];
+
+#[derive(Debug, Clone)]
+pub struct Pacman {
+    transport: Arc<dyn Transport>,
+}
+
+impl Pacman {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    async fn query(&self, args: &[&str]) -> Result<String> {
+        query(self.transport.as_ref(), "pacman", args, NOT_FOUND).await
+    }
+}
+
+pub fn command(operation: &PackageOperation) -> Vec<String> {
+    match operation {
+        PackageOperation::Install(packages) => operand_words("pacman", &["-S", "--needed", "--noconfirm"], packages),
+        PackageOperation::Remove(packages) => operand_words("pacman", &["-R", "--noconfirm"], packages),
+        // Arch does not support partial upgrades, so upgrading anything upgrades everything
+        PackageOperation::Upgrade(packages) => operand_words("pacman", &["-Syu", "--noconfirm"], packages),
+        // Syncing the databases without upgrading leads to partial upgrades as well
+        PackageOperation::Refresh => words("pacman", &["-Syu", "--noconfirm"], &[]),
+        PackageOperation::Search(term) => words("pacman", &["-Ss", "--", term], &[]),
+        PackageOperation::Info(name) => words("pacman", &["-Si", "--", name], &[]),
+        PackageOperation::ListInstalled => words("pacman", &["-Q"], &[]),
+        PackageOperation::ListUpgradable => words("pacman", &["-Qu"], &[]),
+        PackageOperation::Clean => words("pacman", &["-Sc", "--noconfirm"], &[]),
+    }
+}
+
+#[async_trait::async_trait]
+impl PackageBackend for Pacman {
+    fn manager(&self) -> PackageManager {
+        PackageManager::Pacman
+    }
+
+    async fn search(&self, term: &str) -> Result<Vec<PackageInfo>> {
+        let output = self.query(&["-Ss", "--", term]).await?;
+        let mut packages: Vec<PackageInfo> = Vec::new();
+
+        // "core/linux 6.6.1.arch1-1 (base) [installed]" followed by an indented description
+        for line in output.lines() {
+            if line.starts_with(char::is_whitespace) {
+                if let Some(package) = packages.last_mut() {
+                    package.description = Some(line.trim().to_string());
+                }
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            let Some((repository, name)) = fields.next().and_then(|f| f.split_once('/')) else {
+                continue;
+            };
+            packages.push(PackageInfo {
+                name: name.to_string(),
+                version: fields.next().map(String::from),
+                repository: Some(repository.to_string()),
+                installed: line.contains("[installed"),
+                ..Default::default()
+            });
+        }
+
+        Ok(packages)
+    }
+
+    async fn info(&self, name: &str) -> Result<Option<PackageInfo>> {
+        // The local database knows installed packages, the sync databases everything else
+        let installed = self.query(&["-Qi", "--", name]).await?;
+        let (output, is_installed) = if installed.trim().is_empty() {
+            (self.query(&["-Si", "--", name]).await?, false)
+        } else {
+            (installed, true)
+        };
+
+        let fields = key_values(&output);
+        let Some(package_name) = fields.get("Name") else {
+            return Ok(None);
+        };
+
+        let mut package = PackageInfo {
+            name: package_name.clone(),
+            version: non_empty(fields.get("Version")),
+            description: non_empty(fields.get("Description")),
+            repository: non_empty(fields.get("Repository")),
+            installed: is_installed,
+            url: non_empty(fields.get("URL")),
+            license: non_empty(fields.get("Licenses")),
+            dependencies: non_empty(fields.get("Depends On"))
+                .map(|deps| deps.split_whitespace().map(dependency_name).collect())
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+
+        if is_installed {
+            let sync = key_values(&self.query(&["-Si", "--", name]).await.unwrap_or_default());
+            package.repository = non_empty(sync.get("Repository"));
+            package.available_version = non_empty(sync.get("Version")).filter(|v| Some(v) != package.version.as_ref());
+        }
+
+        Ok(Some(package))
+    }
+
+    async fn list_installed(&self) -> Result<Vec<PackageInfo>> {
+        let output = self.query(&["-Q"]).await?;
+        Ok(output.lines()
+            .filter_map(|line| line.split_once(' '))
+            .map(|(name, version)| PackageInfo {
+                name: name.to_string(),
+                version: Some(version.trim().to_string()),
+                installed: true,
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    async fn list_upgradable(&self) -> Result<Vec<PackageInfo>> {
+        // "linux 6.6.1.arch1-1 -> 6.6.2.arch1-1", checked against the last database sync
+        let output = self.query(&["-Qu"]).await?;
+        Ok(output.lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                match fields.as_slice() {
+                    [name, current, "->", available, ..] => Some(PackageInfo {
+                        name: name.to_string(),
+                        version: Some(current.to_string()),
+                        available_version: Some(available.to_string()),
+                        installed: true,
+                        ..Default::default()
+                    }),
+                    _ => None,
+                }
+            })
+            .collect())
+    }
+
+    async fn owns_file(&self, path: &str) -> Result<Option<String>> {
+        let output = self.query(&["-Qqo", "--", path]).await?;
+        Ok(output.lines().next().map(|name| name.trim().to_string()))
+    }
+
+    async fn why_installed(&self, name: &str) -> Result<InstallReason> {
+        let fields = key_values(&self.query(&["-Qi", "--", name]).await?);
+        let reason = fields.get("Install Reason")
+            .ok_or_else(|| anyhow::anyhow!("Package '{}' is not installed", name))?;
+
+        Ok(InstallReason {
+            package: name.to_string(),
+            explicit: reason.starts_with("Explicitly"),
+            required_by: non_empty(fields.get("Required By"))
+                .map(|deps| deps.split_whitespace().map(String::from).collect())
+                .unwrap_or_default(),
+        })
+    }
+
+    async fn preview(&self, kind: &TransactionKind, packages: &[String]) -> Result<Vec<PackageChange>> {
+        if *kind == TransactionKind::Upgrade {
+            return Ok(self.list_upgradable().await?
+                .into_iter()
+                .map(|package| PackageChange {
+                    name: package.name,
+                    action: ChangeAction::Upgrade,
+                    from_version: package.version,
+                    to_version: package.available_version,
+                })
+                .collect());
+        }
+
+        // --print lists the resolved transaction without touching anything
+        let (operation, action) = match kind {
+            TransactionKind::Install => ("-Sp", ChangeAction::Install),
+            _ => ("-Rp", ChangeAction::Remove),
+        };
+        let mut args = vec![operation, "--print-format", "%n %v", "--"];
+        args.extend(packages.iter().map(String::as_str));
+
+        let output = query(self.transport.as_ref(), "pacman", &args, &[]).await?;
+        Ok(output.lines()
+            .filter_map(|line| line.split_once(' '))
+            .map(|(name, version)| {
+                let version = Some(version.to_string());
+                let removed = action == ChangeAction::Remove;
+                PackageChange {
+                    name: name.to_string(),
+                    action: action.clone(),
+                    from_version: if removed { version.clone() } else { None },
+                    to_version: if removed { None } else { version },
+                }
+            })
+            .collect())
+    }
+}
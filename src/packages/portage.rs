@@ -0,0 +1,215 @@
+use anyhow::Result;
+use std::sync::Arc;
+
+use crate::config::PackageManager;
+use crate::remote::transport::Transport;
+use super::{operand, query, split_version, words};
+use super::{ChangeAction, InstallReason, PackageBackend, PackageChange, PackageInfo, PackageOperation, TransactionKind};
+
+/// Packages installed on request ("category/name" atoms, one per line)
+const WORLD_FILE: &str = "/var/lib/portage/world";
+/// qfile and qdepends exit with 1 when nothing matches
+const NOT_FOUND: &[i32] = &[1];
+
+#[derive(Debug, Clone)]
+pub struct Portage {
+    transport: Arc<dyn Transport>,
+}
+
+impl Portage {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    async fn emerge(&self, args: &[&str]) -> Result<String> {
+        let mut command = vec!["--color=n", "--nospinner"];
+        command.extend_from_slice(args);
+        query(self.transport.as_ref(), "emerge", &command, &[]).await
+    }
+
+    /// Packages an `emerge --pretend` run would merge
+    async fn pretend(&self, args: &[&str]) -> Result<Vec<PackageChange>> {
+        let mut command = vec!["--pretend", "--quiet"];
+        command.extend_from_slice(args);
+        Ok(self.emerge(&command).await?.lines().filter_map(parse_merge_line).collect())
+    }
+}
+
+pub fn command(operation: &PackageOperation) -> Vec<String> {
+    match operation {
+        PackageOperation::Install(packages) => words("emerge", &["--ask=n", "--noreplace"], packages),
+        PackageOperation::Remove(packages) => words("emerge", &["--ask=n", "--unmerge"], packages),
+        PackageOperation::Upgrade(packages) if packages.is_empty() => {
+            words("emerge", &["--ask=n", "--update", "--deep", "--newuse", "@world"], &[])
+        }
+        PackageOperation::Upgrade(packages) => words("emerge", &["--ask=n", "--update"], packages),
+        PackageOperation::Refresh => words("emerge", &["--sync"], &[]),
+        PackageOperation::Search(term) => words("emerge", &["--search", term], &[]),
+        PackageOperation::Info(name) => words("emerge", &["--search", &format!("%^{}$", name)], &[]),
+        PackageOperation::ListInstalled => words("qlist", &["-Iv"], &[]),
+        PackageOperation::ListUpgradable => {
+            words("emerge", &["--pretend", "--update", "--deep", "--newuse", "@world"], &[])
+        }
+        PackageOperation::Clean => words("emerge", &["--ask=n", "--depclean"], &[]),
+    }
+}
+
+/// "[ebuild     U  ] sys-apps/foo-2.0 [1.0]" from `emerge --pretend --quiet`
+fn parse_merge_line(line: &str) -> Option<PackageChange> {
+    let rest = line.strip_prefix("[ebuild")?;
+    let (flags, rest) = rest.split_once(']')?;
+    let mut fields = rest.split_whitespace();
+    let (name, to_version) = split_version(fields.next()?);
+    let from_version = fields.next()
+        .and_then(|f| f.strip_prefix('['))
+        .map(|f| f.trim_end_matches(']').to_string());
+
+    let action = if flags.contains('U') {
+        ChangeAction::Upgrade
+    } else if flags.contains('D') {
+        ChangeAction::Downgrade
+    } else if flags.contains('R') {
+        ChangeAction::Reinstall
+    } else {
+        ChangeAction::Install
+    };
+    Some(PackageChange { name, action, from_version, to_version })
+}
+
+/// Result blocks of `emerge --search`, each starting with "*  category/name"
+fn parse_search(output: &str) -> Vec<PackageInfo> {
+    let mut packages: Vec<PackageInfo> = Vec::new();
+
+    for line in output.lines() {
+        if let Some(name) = line.strip_prefix("*  ") {
+            packages.push(PackageInfo {
+                name: name.trim().trim_end_matches(" [ Masked ]").to_string(),
+                ..Default::default()
+            });
+            continue;
+        }
+
+        let (Some(package), Some((key, value))) = (packages.last_mut(), line.trim().split_once(':')) else {
+            continue;
+        };
+        let value = value.trim().to_string();
+        match key {
+            "Latest version available" => package.available_version = Some(value),
+            "Latest version installed" if value != "[ Not Installed ]" => {
+                package.installed = true;
+                package.version = Some(value);
+            }
+            "Homepage" => package.url = Some(value),
+            "Description" => package.description = Some(value),
+            "License" => package.license = Some(value),
+            _ => {}
+        }
+    }
+
+    for package in &mut packages {
+        if !package.installed {
+            package.version = package.available_version.take();
+        } else if package.available_version == package.version {
+            package.available_version = None;
+        }
+    }
+    packages
+}
+
+#[async_trait::async_trait]
+impl PackageBackend for Portage {
+    fn manager(&self) -> PackageManager {
+        PackageManager::Portage
+    }
+
+    async fn search(&self, term: &str) -> Result<Vec<PackageInfo>> {
+        Ok(parse_search(&self.emerge(&["--search", operand(term)?]).await?))
+    }
+
+    async fn info(&self, name: &str) -> Result<Option<PackageInfo>> {
+        // "%" makes the term a regular expression, "@" matches it against category/name
+        let pattern = if name.contains('/') {
+            format!("%@^{}$", name)
+        } else {
+            format!("%^{}$", name)
+        };
+        Ok(parse_search(&self.emerge(&["--search", &pattern]).await?).into_iter().next())
+    }
+
+    async fn list_installed(&self) -> Result<Vec<PackageInfo>> {
+        let output = query(self.transport.as_ref(), "qlist", &["-IvC"], &[]).await?;
+        Ok(output.lines()
+            .map(|line| {
+                let (name, version) = split_version(line.trim());
+                PackageInfo { name, version, installed: true, ..Default::default() }
+            })
+            .collect())
+    }
+
+    async fn list_upgradable(&self) -> Result<Vec<PackageInfo>> {
+        Ok(self.pretend(&["--update", "--deep", "--newuse", "@world"]).await?
+            .into_iter()
+            .filter(|change| change.action == ChangeAction::Upgrade)
+            .map(|change| PackageInfo {
+                name: change.name,
+                version: change.from_version,
+                available_version: change.to_version,
+                installed: true,
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    async fn owns_file(&self, path: &str) -> Result<Option<String>> {
+        let output = query(self.transport.as_ref(), "qfile", &["-qC", "--", path], NOT_FOUND).await?;
+        Ok(output.lines().next().map(|atom| atom.trim().to_string()))
+    }
+
+    async fn why_installed(&self, name: &str) -> Result<InstallReason> {
+        let installed = self.list_installed().await?;
+        let atom = installed.iter()
+            .map(|p| p.name.as_str())
+            .find(|atom| *atom == name || atom.rsplit('/').next() == Some(name))
+            .ok_or_else(|| anyhow::anyhow!("Package '{}' is not installed", name))?
+            .to_string();
+
+        let world = self.transport.read_file(WORLD_FILE).await.unwrap_or_default();
+        let dependents = query(self.transport.as_ref(), "qdepends", &["-qQC", &atom], NOT_FOUND).await?;
+
+        // "category/name-version" per line, optionally followed by ":" and details
+        let mut required_by: Vec<String> = dependents.lines()
+            .filter_map(|line| line.split(|c: char| c == ':' || c.is_whitespace()).next())
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| split_version(entry).0)
+            .collect();
+        required_by.sort();
+        required_by.dedup();
+
+        Ok(InstallReason {
+            explicit: world.lines().any(|line| line.trim() == atom),
+            package: atom,
+            required_by,
+        })
+    }
+
+    async fn preview(&self, kind: &TransactionKind, packages: &[String]) -> Result<Vec<PackageChange>> {
+        let mut args: Vec<&str> = match kind {
+            // Unmerge output is not a merge list; report the requested atoms instead
+            TransactionKind::Remove => {
+                return Ok(packages.iter()
+                    .map(|name| PackageChange {
+                        name: name.clone(),
+                        action: ChangeAction::Remove,
+                        from_version: None,
+                        to_version: None,
+                    })
+                    .collect());
+            }
+            TransactionKind::Install => vec!["--noreplace"],
+            TransactionKind::Upgrade if packages.is_empty() => vec!["--update", "--deep", "--newuse", "@world"],
+            TransactionKind::Upgrade => vec!["--update"],
+        };
+        args.extend(packages.iter().map(String::as_str));
+        self.pretend(&args).await
+    }
+}
@@ -0,0 +1,74 @@
+//! Queries against the rpm database, shared by the dnf and zypper backends
+
+use anyhow::Result;
+
+use crate::remote::transport::Transport;
+use super::{query, PackageInfo};
+
+/// rpm exits with 1 when a package or file is not known
+const NOT_FOUND: &[i32] = &[1];
+
+pub(super) async fn installed(transport: &dyn Transport) -> Result<Vec<PackageInfo>> {
+    let output = query(transport, "rpm", &["-qa", "--qf", "%{NAME}\t%{VERSION}-%{RELEASE}\t%{SUMMARY}\n"], &[]).await?;
+
+    let mut packages: Vec<PackageInfo> = output.lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            Some((fields.next()?, fields.next()?, fields.next()))
+        })
+        // Imported signing keys show up as packages too
+        .filter(|(name, _, _)| *name != "gpg-pubkey")
+        .map(|(name, version, summary)| PackageInfo {
+            name: name.to_string(),
+            version: Some(version.to_string()),
+            description: summary.map(String::from),
+            installed: true,
+            ..Default::default()
+        })
+        .collect();
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(packages)
+}
+
+pub(super) async fn owner(transport: &dyn Transport, path: &str) -> Result<Option<String>> {
+    let output = query(transport, "rpm", &["-qf", "--qf", "%{NAME}\n", "--", path], NOT_FOUND).await?;
+    Ok(output.lines()
+        .next()
+        .filter(|line| !line.contains("not owned") && !line.contains("No such file"))
+        .map(|line| line.trim().to_string()))
+}
+
+pub(super) async fn required_by(transport: &dyn Transport, name: &str) -> Result<Vec<String>> {
+    let output = query(transport, "rpm", &["-q", "--whatrequires", "--qf", "%{NAME}\n", "--", name], NOT_FOUND).await?;
+    let mut names: Vec<String> = output.lines()
+        .filter(|line| !line.starts_with("no package"))
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect();
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+pub(super) async fn is_installed(transport: &dyn Transport, name: &str) -> Result<bool> {
+    let output = query(transport, "rpm", &["-q", "--qf", "%{NAME}\n", "--", name], NOT_FOUND).await?;
+    Ok(output.lines().any(|line| line.trim() == name))
+}
+
+pub(super) async fn info(transport: &dyn Transport, name: &str) -> Result<Option<PackageInfo>> {
+    let format = "%{NAME}\t%{VERSION}-%{RELEASE}\t%{SUMMARY}\t%{URL}\t%{LICENSE}\n";
+    let output = query(transport, "rpm", &["-q", "--qf", format, "--", name], NOT_FOUND).await?;
+
+    Ok(output.lines()
+        .map(|line| line.split('\t').collect::<Vec<_>>())
+        .find(|fields| fields.len() == 5 && fields[0] == name)
+        .map(|fields| PackageInfo {
+            name: fields[0].to_string(),
+            version: Some(fields[1].to_string()),
+            description: Some(fields[2].to_string()),
+            installed: true,
+            url: Some(fields[3].to_string()).filter(|url| url != "(none)"),
+            license: Some(fields[4].to_string()),
+            ..Default::default()
+        }))
+}
@@ -0,0 +1,205 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::config::PackageManager;
+use crate::remote::transport::Transport;
+use super::{key_values, non_empty, operand_words, query, words};
+use super::{InstallReason, PackageBackend, PackageInfo, PackageOperation};
+
+/// `snap info` exits with 1 for unknown snaps
+const NOT_FOUND: &[i32] = &[1];
+/// Notes `snap list` prints for snaps that other snaps are built on
+const SYSTEM_NOTES: &[&str] = &["base", "core", "snapd"];
+
+/// A row of `snap list`
+struct InstalledSnap {
+    name: String,
+    version: String,
+    publisher: String,
+    notes: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Snap {
+    transport: Arc<dyn Transport>,
+}
+
+impl Snap {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    /// Whitespace separated rows below the header line
+    async fn table(&self, args: &[&str]) -> Result<Vec<Vec<String>>> {
+        let output = query(self.transport.as_ref(), "snap", args, &[]).await?;
+        Ok(output.lines()
+            .skip(1)
+            .map(|line| line.split_whitespace().map(String::from).collect())
+            .collect())
+    }
+
+    async fn installed(&self) -> Result<Vec<InstalledSnap>> {
+        // "Name  Version  Rev  Tracking  Publisher  Notes"
+        Ok(self.table(&["list"]).await?
+            .into_iter()
+            .filter(|cells| cells.len() >= 6)
+            .map(|cells| InstalledSnap {
+                name: cells[0].clone(),
+                version: cells[1].clone(),
+                publisher: cells[4].clone(),
+                notes: cells[5].clone(),
+            })
+            .collect())
+    }
+
+    /// The `base:` a snap declares in its metadata
+    async fn base_of(&self, name: &str) -> Option<String> {
+        let metadata = self.transport.read_file(&format!("/snap/{}/current/meta/snap.yaml", name)).await.ok()?;
+        metadata.lines()
+            .find_map(|line| line.strip_prefix("base:"))
+            .map(|base| base.trim().to_string())
+    }
+}
+
+pub fn command(operation: &PackageOperation) -> Vec<String> {
+    match operation {
+        PackageOperation::Install(packages) => operand_words("snap", &["install"], packages),
+        PackageOperation::Remove(packages) => operand_words("snap", &["remove"], packages),
+        PackageOperation::Upgrade(packages) => operand_words("snap", &["refresh"], packages),
+        // snapd keeps its store metadata current by itself
+        PackageOperation::Refresh => words("snap", &["refresh", "--list"], &[]),
+        PackageOperation::Search(term) => words("snap", &["find", "--", term], &[]),
+        PackageOperation::Info(name) => words("snap", &["info", "--", name], &[]),
+        PackageOperation::ListInstalled => words("snap", &["list"], &[]),
+        PackageOperation::ListUpgradable => words("snap", &["refresh", "--list"], &[]),
+        // Old revisions are what snaps keep around; retain only two of them
+        PackageOperation::Clean => words("snap", &["set", "system", "refresh.retain=2"], &[]),
+    }
+}
+
+/// Snap name from "/snap/<name>/<revision>/..." or a "/snap/bin/<name>.<app>" wrapper
+fn owner_from_path(path: &str) -> Option<String> {
+    let inside = path.strip_prefix("/snap/")?;
+    let mut segments = inside.split('/');
+    match segments.next()? {
+        "bin" => segments.next().and_then(|app| app.split('.').next()).map(String::from),
+        name => Some(name.to_string()),
+    }
+}
+
+#[async_trait::async_trait]
+impl PackageBackend for Snap {
+    fn manager(&self) -> PackageManager {
+        PackageManager::Snap
+    }
+
+    async fn search(&self, term: &str) -> Result<Vec<PackageInfo>> {
+        let installed: HashMap<String, String> = self.installed().await?
+            .into_iter()
+            .map(|snap| (snap.name, snap.version))
+            .collect();
+
+        // "Name  Version  Publisher  Notes  Summary", the summary runs to the end of the line
+        Ok(self.table(&["find", "--", term]).await?
+            .into_iter()
+            .filter(|cells| cells.len() >= 4)
+            .map(|cells| PackageInfo {
+                installed: installed.contains_key(&cells[0]),
+                version: installed.get(&cells[0]).cloned().or_else(|| Some(cells[1].clone())),
+                description: Some(cells[4..].join(" ")).filter(|s| !s.is_empty()),
+                repository: Some(cells[2].clone()),
+                name: cells[0].clone(),
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    async fn info(&self, name: &str) -> Result<Option<PackageInfo>> {
+        let fields = key_values(&query(self.transport.as_ref(), "snap", &["info", "--", name], NOT_FOUND).await?);
+        let Some(snap_name) = fields.get("name") else {
+            return Ok(None);
+        };
+
+        // "installed: 120.0 (3358) 252MB -" and channel lines such as
+        // "latest/stable: 121.0 2023-12-01 (3600) 260MB -", joined onto "channels"
+        let installed = fields.get("installed").and_then(|v| v.split_whitespace().next()).map(String::from);
+        let tracking = non_empty(fields.get("tracking")).unwrap_or_else(|| "latest/stable".to_string());
+        let channel_version = fields.get("channels").and_then(|channels| {
+            let mut words = channels.split_whitespace();
+            words.by_ref().find(|word| *word == format!("{}:", tracking))?;
+            words.next().filter(|version| *version != "^" && *version != "--").map(String::from)
+        });
+
+        Ok(Some(PackageInfo {
+            name: snap_name.clone(),
+            available_version: channel_version.clone().filter(|v| installed.is_some() && installed.as_ref() != Some(v)),
+            version: installed.clone().or(channel_version),
+            description: non_empty(fields.get("summary")),
+            repository: non_empty(fields.get("publisher")),
+            installed: installed.is_some(),
+            url: non_empty(fields.get("store-url")).or_else(|| non_empty(fields.get("contact"))),
+            license: non_empty(fields.get("license")).filter(|license| license != "unset"),
+            dependencies: Vec::new(),
+            source: None,
+        }))
+    }
+
+    async fn list_installed(&self) -> Result<Vec<PackageInfo>> {
+        Ok(self.installed().await?
+            .into_iter()
+            .map(|snap| PackageInfo {
+                name: snap.name,
+                version: Some(snap.version),
+                repository: Some(snap.publisher),
+                installed: true,
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    async fn list_upgradable(&self) -> Result<Vec<PackageInfo>> {
+        let installed: HashMap<String, String> = self.installed().await?
+            .into_iter()
+            .map(|snap| (snap.name, snap.version))
+            .collect();
+
+        // "Name  Version  Rev  Size  Publisher  Notes" with the new version; "All snaps
+        // up to date." goes to stderr
+        Ok(self.table(&["refresh", "--list"]).await?
+            .into_iter()
+            .filter(|cells| cells.len() >= 5)
+            .map(|cells| PackageInfo {
+                version: installed.get(&cells[0]).cloned(),
+                available_version: Some(cells[1].clone()),
+                repository: Some(cells[4].clone()),
+                name: cells[0].clone(),
+                installed: true,
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    async fn owns_file(&self, path: &str) -> Result<Option<String>> {
+        Ok(owner_from_path(path))
+    }
+
+    async fn why_installed(&self, name: &str) -> Result<InstallReason> {
+        let installed = self.installed().await?;
+        let snap = installed.iter()
+            .find(|snap| snap.name == name)
+            .ok_or_else(|| anyhow::anyhow!("Snap '{}' is not installed", name))?;
+
+        let explicit = !snap.notes.split(',').any(|note| SYSTEM_NOTES.contains(&note));
+        let mut required_by = Vec::new();
+        if !explicit {
+            for other in installed.iter().filter(|other| other.name != name) {
+                if self.base_of(&other.name).await.as_deref() == Some(name) {
+                    required_by.push(other.name.clone());
+                }
+            }
+        }
+
+        Ok(InstallReason { package: name.to_string(), explicit, required_by })
+    }
+}
@@ -0,0 +1,240 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::config::PackageManager;
+use crate::remote::transport::Transport;
+use super::{dependency_name, key_values, non_empty, operand, operand_words, query, words};
+use super::{ChangeAction, InstallReason, PackageBackend, PackageChange, PackageInfo, PackageOperation, TransactionKind};
+
+/// xbps-query exits with 2 when a package or file is not known
+const NOT_FOUND: &[i32] = &[2];
+
+/// One line of an xbps-install or xbps-remove dry run
+struct Planned {
+    name: String,
+    version: String,
+    /// "install", "update", "downgrade", "reinstall", "remove" or "configure"
+    action: String,
+    repository: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Xbps {
+    transport: Arc<dyn Transport>,
+}
+
+impl Xbps {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    async fn query(&self, args: &[&str]) -> Result<String> {
+        query(self.transport.as_ref(), "xbps-query", args, NOT_FOUND).await
+    }
+
+    /// Installed versions by package name
+    async fn installed_versions(&self) -> Result<HashMap<String, String>> {
+        // "ii name-1.0_1  description"
+        Ok(self.query(&["-l"]).await?
+            .lines()
+            .filter_map(|line| {
+                let (name, version) = split_pkgver(line.split_whitespace().nth(1)?);
+                Some((name, version?))
+            })
+            .collect())
+    }
+
+    /// Dry run of xbps-install or xbps-remove, one "pkgver action arch repository ..."
+    /// line per package
+    async fn dry_run(&self, program: &str, args: &[&str]) -> Result<Vec<Planned>> {
+        let mut command = vec!["-n"];
+        command.extend_from_slice(args);
+        let output = query(self.transport.as_ref(), program, &command, &[]).await?;
+
+        Ok(output.lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                let (name, version) = split_pkgver(fields.first()?);
+                Some(Planned {
+                    name,
+                    version: version?,
+                    action: fields.get(1)?.to_string(),
+                    repository: fields.get(3).map(|r| r.to_string()),
+                })
+            })
+            .collect())
+    }
+}
+
+pub fn command(operation: &PackageOperation) -> Vec<String> {
+    match operation {
+        PackageOperation::Install(packages) => operand_words("xbps-install", &["-y"], packages),
+        PackageOperation::Remove(packages) => operand_words("xbps-remove", &["-y"], packages),
+        PackageOperation::Upgrade(packages) if packages.is_empty() => words("xbps-install", &["-Suy"], &[]),
+        PackageOperation::Upgrade(packages) => operand_words("xbps-install", &["-uy"], packages),
+        PackageOperation::Refresh => words("xbps-install", &["-S"], &[]),
+        PackageOperation::Search(term) => words("xbps-query", &["-Rs", term], &[]),
+        PackageOperation::Info(name) => words("xbps-query", &["-R", "--", name], &[]),
+        PackageOperation::ListInstalled => words("xbps-query", &["-l"], &[]),
+        PackageOperation::ListUpgradable => words("xbps-install", &["-nu"], &[]),
+        PackageOperation::Clean => words("xbps-remove", &["-Oy"], &[]),
+    }
+}
+
+/// Split a pkgver such as "gtk+3-3.24.38_1"; the version never contains a dash
+fn split_pkgver(pkgver: &str) -> (String, Option<String>) {
+    match pkgver.rsplit_once('-') {
+        Some((name, version)) if version.contains('_') => (name.to_string(), Some(version.to_string())),
+        _ => (pkgver.to_string(), None),
+    }
+}
+
+#[async_trait::async_trait]
+impl PackageBackend for Xbps {
+    fn manager(&self) -> PackageManager {
+        PackageManager::Xbps
+    }
+
+    async fn search(&self, term: &str) -> Result<Vec<PackageInfo>> {
+        // "[*] name-1.0_1  description", "[-]" for packages that are not installed. -s, like -o
+        // and -X, takes the operand as its argument, so there is no room for a "--"
+        let output = self.query(&["-Rs", operand(term)?]).await?;
+        Ok(output.lines()
+            .filter_map(|line| {
+                let (state, rest) = line.split_once(' ')?;
+                let (pkgver, description) = rest.trim_start().split_once(char::is_whitespace)?;
+                let (name, version) = split_pkgver(pkgver);
+                Some(PackageInfo {
+                    name,
+                    version,
+                    description: Some(description.trim().to_string()),
+                    installed: state == "[*]",
+                    ..Default::default()
+                })
+            })
+            .collect())
+    }
+
+    async fn info(&self, name: &str) -> Result<Option<PackageInfo>> {
+        let remote = key_values(&self.query(&["-R", "--", name]).await?);
+        let local = key_values(&self.query(&["--", name]).await?);
+        let fields = if local.contains_key("pkgver") { &local } else { &remote };
+        let Some(pkgver) = fields.get("pkgver") else {
+            return Ok(None);
+        };
+
+        let (package_name, version) = split_pkgver(pkgver);
+        let repository_version = remote.get("pkgver").and_then(|v| split_pkgver(v).1);
+        let installed = local.contains_key("pkgver");
+
+        Ok(Some(PackageInfo {
+            name: package_name,
+            available_version: repository_version.filter(|v| installed && Some(v) != version.as_ref()),
+            version,
+            description: non_empty(fields.get("short_desc")),
+            repository: non_empty(remote.get("repository")),
+            installed,
+            url: non_empty(fields.get("homepage")),
+            license: non_empty(fields.get("license")),
+            // Continuation lines are joined with spaces, one dependency per word
+            dependencies: fields.get("run_depends")
+                .map(|deps| deps.split_whitespace().map(dependency_name).collect())
+                .unwrap_or_default(),
+            source: None,
+        }))
+    }
+
+    async fn list_installed(&self) -> Result<Vec<PackageInfo>> {
+        let mut packages: Vec<PackageInfo> = self.installed_versions().await?
+            .into_iter()
+            .map(|(name, version)| PackageInfo {
+                name,
+                version: Some(version),
+                installed: true,
+                ..Default::default()
+            })
+            .collect();
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(packages)
+    }
+
+    async fn list_upgradable(&self) -> Result<Vec<PackageInfo>> {
+        let installed = self.installed_versions().await?;
+        Ok(self.dry_run("xbps-install", &["-u"]).await?
+            .into_iter()
+            .filter(|planned| planned.action == "update")
+            .map(|planned| PackageInfo {
+                version: installed.get(&planned.name).cloned(),
+                available_version: Some(planned.version),
+                repository: planned.repository,
+                installed: true,
+                name: planned.name,
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    async fn owns_file(&self, path: &str) -> Result<Option<String>> {
+        // "coreutils-9.4_1: /usr/bin/ls (regular file)"
+        let output = self.query(&["-o", operand(path)?]).await?;
+        Ok(output.lines()
+            .filter_map(|line| line.split_once(": "))
+            .find(|(_, file)| file.split(" (").next() == Some(path))
+            .map(|(pkgver, _)| split_pkgver(pkgver).0))
+    }
+
+    async fn why_installed(&self, name: &str) -> Result<InstallReason> {
+        if !self.installed_versions().await?.contains_key(name) {
+            return Err(anyhow::anyhow!("Package '{}' is not installed", name));
+        }
+
+        let automatic = self.query(&["-p", "automatic-install", "--", name]).await?;
+        let dependents = self.query(&["-X", operand(name)?]).await?;
+        let mut required_by: Vec<String> = dependents.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| split_pkgver(line.trim()).0)
+            .collect();
+        required_by.sort();
+        required_by.dedup();
+
+        Ok(InstallReason {
+            package: name.to_string(),
+            explicit: automatic.trim() != "yes",
+            required_by,
+        })
+    }
+
+    async fn preview(&self, kind: &TransactionKind, packages: &[String]) -> Result<Vec<PackageChange>> {
+        let installed = self.installed_versions().await?;
+        let packages: Vec<&str> = packages.iter().map(String::as_str).collect();
+        let plan = match kind {
+            TransactionKind::Install => self.dry_run("xbps-install", &packages).await?,
+            TransactionKind::Remove => self.dry_run("xbps-remove", &packages).await?,
+            TransactionKind::Upgrade => {
+                let mut args = vec!["-u"];
+                args.extend_from_slice(&packages);
+                self.dry_run("xbps-install", &args).await?
+            }
+        };
+
+        Ok(plan.into_iter()
+            .filter_map(|Planned { name, version, action, .. }| {
+                let action = match action.as_str() {
+                    "install" => ChangeAction::Install,
+                    "update" => ChangeAction::Upgrade,
+                    "downgrade" => ChangeAction::Downgrade,
+                    "reinstall" => ChangeAction::Reinstall,
+                    "remove" => ChangeAction::Remove,
+                    _ => return None,
+                };
+                let (from_version, to_version) = if action == ChangeAction::Remove {
+                    (Some(version), None)
+                } else {
+                    (installed.get(&name).cloned(), Some(version))
+                };
+                Some(PackageChange { name, action, from_version, to_version })
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,137 @@
+use anyhow::Result;
+use std::sync::Arc;
+
+use crate::config::PackageManager;
+use crate::remote::transport::Transport;
+use super::{key_values, non_empty, operand_words, query, rpm, words};
+use super::{InstallReason, PackageBackend, PackageInfo, PackageOperation};
+
+/// zypper exits with 104 when a search finds nothing
+const NOTHING_FOUND: &[i32] = &[104];
+/// Packages zypper pulled in as dependencies, one name per line
+const AUTO_INSTALLED: &str = "/var/lib/zypp/AutoInstalled";
+
+#[derive(Debug, Clone)]
+pub struct Zypper {
+    transport: Arc<dyn Transport>,
+}
+
+impl Zypper {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    /// Run zypper without refreshing repositories, which would need root
+    async fn query(&self, args: &[&str]) -> Result<String> {
+        let mut command = vec!["--quiet", "--non-interactive", "--no-refresh"];
+        command.extend_from_slice(args);
+        query(self.transport.as_ref(), "zypper", &command, NOTHING_FOUND).await
+    }
+}
+
+pub fn command(operation: &PackageOperation) -> Vec<String> {
+    match operation {
+        PackageOperation::Install(packages) => operand_words("zypper", &["--non-interactive", "install"], packages),
+        PackageOperation::Remove(packages) => operand_words("zypper", &["--non-interactive", "remove"], packages),
+        PackageOperation::Upgrade(packages) => operand_words("zypper", &["--non-interactive", "update"], packages),
+        PackageOperation::Refresh => words("zypper", &["refresh"], &[]),
+        PackageOperation::Search(term) => words("zypper", &["search", "--", term], &[]),
+        PackageOperation::Info(name) => words("zypper", &["info", "--", name], &[]),
+        PackageOperation::ListInstalled => words("zypper", &["search", "--installed-only"], &[]),
+        PackageOperation::ListUpgradable => words("zypper", &["list-updates"], &[]),
+        PackageOperation::Clean => words("zypper", &["clean"], &[]),
+    }
+}
+
+/// Rows of a zypper table, skipping the header and separator lines
+fn table_rows(output: &str, columns: usize) -> Vec<Vec<String>> {
+    output.lines()
+        .filter(|line| !line.starts_with("--"))
+        .map(|line| line.split('|').map(|cell| cell.trim().to_string()).collect::<Vec<_>>())
+        .filter(|cells| cells.len() == columns)
+        .skip(1)
+        .collect()
+}
+
+#[async_trait::async_trait]
+impl PackageBackend for Zypper {
+    fn manager(&self) -> PackageManager {
+        PackageManager::Zypper
+    }
+
+    async fn search(&self, term: &str) -> Result<Vec<PackageInfo>> {
+        // "S  | Name | Summary | Type", where S is "i" or "i+" for installed packages
+        let output = self.query(&["search", "--type", "package", "--", term]).await?;
+        Ok(table_rows(&output, 4).into_iter()
+            .map(|cells| PackageInfo {
+                installed: cells[0].starts_with('i'),
+                name: cells[1].clone(),
+                description: Some(cells[2].clone()),
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    async fn info(&self, name: &str) -> Result<Option<PackageInfo>> {
+        // Unknown packages get a "not found" message and exit status 0
+        let fields = key_values(&self.query(&["info", "--", name]).await?);
+        let Some(package_name) = fields.get("Name") else {
+            return Ok(None);
+        };
+
+        let installed = rpm::info(self.transport.as_ref(), name).await?;
+        let repository_version = non_empty(fields.get("Version"));
+        let installed_version = installed.as_ref().and_then(|p| p.version.clone());
+
+        Ok(Some(PackageInfo {
+            name: package_name.clone(),
+            available_version: repository_version.clone()
+                .filter(|v| installed_version.is_some() && Some(v) != installed_version.as_ref()),
+            version: installed_version.or(repository_version),
+            description: non_empty(fields.get("Summary")),
+            repository: non_empty(fields.get("Repository")),
+            installed: installed.is_some(),
+            url: installed.as_ref().and_then(|p| p.url.clone()),
+            license: installed.and_then(|p| p.license),
+            dependencies: Vec::new(),
+            source: None,
+        }))
+    }
+
+    async fn list_installed(&self) -> Result<Vec<PackageInfo>> {
+        rpm::installed(self.transport.as_ref()).await
+    }
+
+    async fn list_upgradable(&self) -> Result<Vec<PackageInfo>> {
+        // "S | Repository | Name | Current Version | Available Version | Arch"
+        let output = self.query(&["list-updates"]).await?;
+        Ok(table_rows(&output, 6).into_iter()
+            .map(|cells| PackageInfo {
+                repository: Some(cells[1].clone()),
+                name: cells[2].clone(),
+                version: Some(cells[3].clone()),
+                available_version: Some(cells[4].clone()),
+                installed: true,
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    async fn owns_file(&self, path: &str) -> Result<Option<String>> {
+        rpm::owner(self.transport.as_ref(), path).await
+    }
+
+    async fn why_installed(&self, name: &str) -> Result<InstallReason> {
+        if !rpm::is_installed(self.transport.as_ref(), name).await? {
+            return Err(anyhow::anyhow!("Package '{}' is not installed", name));
+        }
+
+        let automatic = self.transport.read_file(AUTO_INSTALLED).await.unwrap_or_default();
+
+        Ok(InstallReason {
+            package: name.to_string(),
+            explicit: !automatic.lines().any(|line| line.trim() == name),
+            required_by: rpm::required_by(self.transport.as_ref(), name).await?,
+        })
+    }
+}
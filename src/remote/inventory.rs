@@ -0,0 +1,153 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::Config;
+
+/// Group name that selects every host in the inventory
+pub const ALL_HOSTS: &str = "all";
+
+/// Hosts and groups that commands can be targeted at
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Inventory {
+    /// Connection settings used when neither the host nor its groups set them
+    pub defaults: SshSettings,
+    /// Per-group connection settings, keyed by group name
+    pub groups: HashMap<String, SshSettings>,
+    pub hosts: Vec<HostEntry>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SshSettings {
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    pub identity_file: Option<PathBuf>,
+    /// Host to tunnel through (`ssh -J`)
+    pub jump_host: Option<String>,
+    /// Extra `-o` options such as "StrictHostKeyChecking=accept-new"
+    pub ssh_options: Vec<String>,
+    /// Run commands that need root through `sudo -n` on the host
+    pub sudo: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostEntry {
+    pub name: String,
+    /// Hostname or IP address to connect to; defaults to the name
+    pub address: Option<String>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(flatten)]
+    pub settings: SshSettings,
+}
+
+/// A host with its connection settings merged from the host, its groups and the defaults
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteHost {
+    pub name: String,
+    pub address: String,
+    pub user: Option<String>,
+    pub port: u16,
+    pub identity_file: Option<PathBuf>,
+    pub jump_host: Option<String>,
+    pub ssh_options: Vec<String>,
+    pub sudo: bool,
+}
+
+impl Inventory {
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(Config::default_config_path()?.with_file_name("inventory.toml"))
+    }
+
+    /// Load the inventory configured in `config`. A missing file is an empty inventory.
+    pub fn load(config: &Config) -> Result<Self> {
+        let path = match &config.remote.inventory {
+            Some(path) => expand_home(path),
+            None => Self::default_path()?,
+        };
+
+        let mut inventory: Inventory = match fs::read_to_string(&path) {
+            Ok(content) => toml::from_str(&content)
+                .with_context(|| format!("Failed to parse inventory: {:?}", path))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Inventory::default(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read inventory: {:?}", path)),
+        };
+
+        let mut names = HashSet::new();
+        for host in &inventory.hosts {
+            if !names.insert(host.name.as_str()) {
+                return Err(anyhow::anyhow!("Host '{}' is listed twice in {:?}", host.name, path));
+            }
+        }
+
+        inventory.path = path;
+        Ok(inventory)
+    }
+
+    pub fn host(&self, name: &str) -> Result<RemoteHost> {
+        let entry = self.hosts.iter()
+            .find(|h| h.name == name)
+            .ok_or_else(|| anyhow::anyhow!("Host '{}' is not in the inventory {:?}", name, self.path))?;
+
+        Ok(self.merge(entry))
+    }
+
+    /// Hosts selected by `--host` and `--group`, in inventory order without duplicates
+    pub fn resolve(&self, hosts: &[String], groups: &[String]) -> Result<Vec<RemoteHost>> {
+        let mut selected = HashSet::new();
+
+        for name in hosts {
+            self.host(name)?;
+            selected.insert(name.as_str());
+        }
+
+        for group in groups {
+            let members: Vec<&str> = self.hosts.iter()
+                .filter(|h| group == ALL_HOSTS || h.groups.contains(group))
+                .map(|h| h.name.as_str())
+                .collect();
+
+            if members.is_empty() {
+                return Err(anyhow::anyhow!("Group '{}' has no hosts in the inventory {:?}", group, self.path));
+            }
+            selected.extend(members);
+        }
+
+        Ok(self.hosts.iter()
+            .filter(|h| selected.contains(h.name.as_str()))
+            .map(|h| self.merge(h))
+            .collect())
+    }
+
+    fn merge(&self, entry: &HostEntry) -> RemoteHost {
+        // Most specific first: the host, then its groups in order, then the defaults
+        let mut layers = vec![&entry.settings];
+        layers.extend(entry.groups.iter().filter_map(|g| self.groups.get(g)));
+        layers.push(&self.defaults);
+
+        RemoteHost {
+            name: entry.name.clone(),
+            address: entry.address.clone().unwrap_or_else(|| entry.name.clone()),
+            user: layers.iter().find_map(|s| s.user.clone()),
+            port: layers.iter().find_map(|s| s.port).unwrap_or(22),
+            identity_file: layers.iter().find_map(|s| s.identity_file.as_deref().map(expand_home)),
+            jump_host: layers.iter().find_map(|s| s.jump_host.clone()),
+            // ssh keeps the first value it sees for an option, so the host's own win
+            ssh_options: layers.iter().flat_map(|s| s.ssh_options.iter().cloned()).collect(),
+            sudo: layers.iter().find_map(|s| s.sudo).unwrap_or(false),
+        }
+    }
+}
+
+fn expand_home(path: &std::path::Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
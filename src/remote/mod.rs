@@ -0,0 +1,9 @@
+//! Remote execution over SSH.
+//!
+//! The inventory names the hosts and groups commands can be targeted at with
+//! `--host` and `--group`; transports run commands either on this machine or on an
+//! inventory host. Safety checks, approvals and auditing always stay on the
+//! controller, only the command itself travels over the transport.
+
+pub mod inventory;
+pub mod transport;
@@ -0,0 +1,323 @@
+use anyhow::{Context, Result};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+
+use crate::config::Config;
+use crate::session;
+use super::inventory::RemoteHost;
+
+/// Exit status ssh reports when it could not connect or authenticate. Remote commands
+/// can exit with it too, so it only counts as a connection failure together with one
+/// of ssh's own messages.
+const SSH_FAILURE: i32 = 255;
+
+/// How ssh's connection, authentication and multiplexing errors start
+const SSH_ERROR_PREFIXES: &[&str] = &[
+    "ssh: ",
+    "Host key verification failed",
+    "kex_exchange_identification:",
+    "Connection closed by ",
+    "Connection reset by ",
+    "Connection timed out during banner exchange",
+    "channel 0: open failed",
+    "stdio forwarding failed",
+    "mux_client_",
+    "Control socket connect",
+];
+/// Authentication errors, which ssh prefixes with the user and host
+const SSH_AUTH_ERRORS: &[&str] = &[": Permission denied (", ": Too many authentication failures"];
+
+#[derive(Debug, Clone)]
+pub struct CommandOutput {
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub duration: Duration,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// Where commands run: this machine, or an inventory host reached over SSH
+#[async_trait::async_trait]
+pub trait Transport: std::fmt::Debug + Send + Sync {
+    /// Name shown in output and recorded in audit entries
+    fn host_name(&self) -> &str;
+
+    fn is_remote(&self) -> bool;
+
+    async fn run(&self, command: &str, args: &[&str]) -> Result<CommandOutput>;
+
+    /// Run a command that needs root. Remote hosts use `sudo -n` when the inventory
+    /// allows it; locally elevation goes through the privileged helper instead.
+    async fn run_privileged(&self, command: &str, args: &[&str]) -> Result<CommandOutput> {
+        self.run(command, args).await
+    }
+
+    async fn read_file(&self, path: &str) -> Result<String> {
+        let output = self.run("cat", &[path]).await?;
+        if output.success() {
+            Ok(output.stdout)
+        } else {
+            Err(anyhow::anyhow!("Failed to read {} on {}: {}", path, self.host_name(), output.stderr.trim()))
+        }
+    }
+
+    async fn path_exists(&self, path: &str) -> bool {
+        self.run("test", &["-e", path]).await
+            .map(|output| output.success())
+            .unwrap_or(false)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LocalTransport;
+
+#[async_trait::async_trait]
+impl Transport for LocalTransport {
+    fn host_name(&self) -> &str {
+        "localhost"
+    }
+
+    fn is_remote(&self) -> bool {
+        false
+    }
+
+    async fn run(&self, command: &str, args: &[&str]) -> Result<CommandOutput> {
+        session::command(self.host_name(), command, args, async {
+            let start = Instant::now();
+            let output = Command::new(command)
+                .args(args)
+                .stdin(Stdio::null())
+                .output()
+                .await
+                .context(format!("Failed to execute command: {} {:?}", command, args))?;
+
+            Ok(CommandOutput {
+                exit_code: output.status.code(),
+                stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+                duration: start.elapsed(),
+            })
+        }).await
+    }
+
+    // File access is recorded as the equivalent `cat` and `test -e` commands so
+    // sessions replay the same way for local and remote hosts
+
+    async fn read_file(&self, path: &str) -> Result<String> {
+        let output = session::command(self.host_name(), "cat", &[path], async {
+            let start = Instant::now();
+            let read = tokio::fs::read_to_string(path).await;
+            Ok(CommandOutput {
+                exit_code: Some(if read.is_ok() { 0 } else { 1 }),
+                stderr: read.as_ref().err().map(|e| e.to_string()).unwrap_or_default(),
+                stdout: read.unwrap_or_default(),
+                duration: start.elapsed(),
+            })
+        }).await?;
+
+        if output.success() {
+            Ok(output.stdout)
+        } else {
+            Err(anyhow::anyhow!("Failed to read {}: {}", path, output.stderr.trim()))
+        }
+    }
+
+    async fn path_exists(&self, path: &str) -> bool {
+        session::command(self.host_name(), "test", &["-e", path], async {
+            let start = Instant::now();
+            let exists = tokio::fs::metadata(path).await.is_ok();
+            Ok(CommandOutput {
+                exit_code: Some(if exists { 0 } else { 1 }),
+                stdout: String::new(),
+                stderr: String::new(),
+                duration: start.elapsed(),
+            })
+        }).await
+            .map(|output| output.success())
+            .unwrap_or(false)
+    }
+}
+
+/// Runs commands through the system `ssh` client, so keys, agents, known_hosts and
+/// ~/.ssh/config work exactly as they do for the user's own ssh sessions.
+#[derive(Debug, Clone)]
+pub struct SshTransport {
+    host: RemoteHost,
+    connect_timeout_seconds: u64,
+    control_persist_seconds: u64,
+}
+
+impl SshTransport {
+    pub fn new(host: RemoteHost, config: &Config) -> Self {
+        Self {
+            host,
+            connect_timeout_seconds: config.remote.connect_timeout_seconds,
+            control_persist_seconds: config.remote.control_persist_seconds,
+        }
+    }
+
+    fn ssh_command(&self, remote_command: &str) -> Command {
+        let mut cmd = Command::new("ssh");
+
+        // Inventory options come first because ssh keeps the first value it sees
+        for option in &self.host.ssh_options {
+            cmd.arg("-o").arg(option);
+        }
+        cmd.arg("-o").arg("BatchMode=yes")
+            .arg("-o").arg(format!("ConnectTimeout={}", self.connect_timeout_seconds));
+
+        if self.control_persist_seconds > 0 {
+            let control_dir = dirs::runtime_dir().unwrap_or_else(std::env::temp_dir);
+            cmd.arg("-o").arg("ControlMaster=auto")
+                .arg("-o").arg(format!("ControlPath={}/tuxpilot-ssh-%C", control_dir.display()))
+                .arg("-o").arg(format!("ControlPersist={}", self.control_persist_seconds));
+        }
+
+        cmd.arg("-p").arg(self.host.port.to_string());
+        if let Some(identity) = &self.host.identity_file {
+            cmd.arg("-i").arg(identity).arg("-o").arg("IdentitiesOnly=yes");
+        }
+        if let Some(jump_host) = &self.host.jump_host {
+            cmd.arg("-J").arg(jump_host);
+        }
+        if let Some(user) = &self.host.user {
+            cmd.arg("-l").arg(user);
+        }
+
+        cmd.arg("--")
+            .arg(&self.host.address)
+            .arg(remote_command)
+            .stdin(Stdio::null())
+            .kill_on_drop(true);
+        cmd
+    }
+
+    async fn run_remote(&self, words: &[&str]) -> Result<CommandOutput> {
+        let remote_command = words.iter().map(|w| shell_quote(w)).collect::<Vec<_>>().join(" ");
+
+        session::command(&self.host.name, words[0], &words[1..], async {
+            let start = Instant::now();
+            let output = self.ssh_command(&remote_command)
+                .output()
+                .await
+                .context("Failed to start ssh")?;
+
+            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+            if output.status.code() == Some(SSH_FAILURE) && is_ssh_error(&stderr) {
+                return Err(anyhow::anyhow!("SSH connection to {} failed: {}", self.host.name, stderr.trim()));
+            }
+
+            Ok(CommandOutput {
+                exit_code: output.status.code(),
+                stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+                stderr,
+                duration: start.elapsed(),
+            })
+        }).await
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for SshTransport {
+    fn host_name(&self) -> &str {
+        &self.host.name
+    }
+
+    fn is_remote(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, command: &str, args: &[&str]) -> Result<CommandOutput> {
+        let mut words = vec![command];
+        words.extend_from_slice(args);
+        self.run_remote(&words).await
+    }
+
+    async fn run_privileged(&self, command: &str, args: &[&str]) -> Result<CommandOutput> {
+        if !self.host.sudo {
+            return self.run(command, args).await;
+        }
+
+        let mut words = vec!["sudo", "-n", "--", command];
+        words.extend_from_slice(args);
+        self.run_remote(&words).await
+    }
+}
+
+/// Whether ssh itself failed, judged by the last line it wrote to stderr
+fn is_ssh_error(stderr: &str) -> bool {
+    let Some(line) = stderr.lines().map(str::trim).rfind(|line| !line.is_empty()) else {
+        return false;
+    };
+
+    SSH_ERROR_PREFIXES.iter().any(|prefix| line.starts_with(prefix))
+        || SSH_AUTH_ERRORS.iter().any(|error| line.contains(error))
+}
+
+/// Quote a word for the remote POSIX shell that ssh hands the command line to
+pub fn shell_quote(word: &str) -> String {
+    let plain = !word.is_empty() && word.chars()
+        .all(|c| c.is_ascii_alphanumeric() || "_-./:=@%+,".contains(c));
+
+    if plain {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+/// Split a command line into words the way a POSIX shell would, honouring single and
+/// double quotes and backslashes but expanding nothing. Reverses `shell_quote`.
+pub fn shell_words(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let current = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => return Err(anyhow::anyhow!("Unterminated single quote in: {}", line)),
+                    }
+                }
+            }
+            '"' => {
+                let current = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => current.push(c),
+                            Some(c) => {
+                                current.push('\\');
+                                current.push(c);
+                            }
+                            None => return Err(anyhow::anyhow!("Unterminated double quote in: {}", line)),
+                        },
+                        Some(c) => current.push(c),
+                        None => return Err(anyhow::anyhow!("Unterminated double quote in: {}", line)),
+                    }
+                }
+            }
+            '\\' => {
+                let escaped = chars.next().context("Trailing backslash in command line")?;
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+
+    Ok(words)
+}
@@ -0,0 +1,211 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDate, TimeZone};
+
+/// How far ahead the next run is searched; far enough for February 29 on a given weekday
+const SEARCH_DAYS: i64 = 366 * 28;
+
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+/// The times a cron expression or a systemd calendar event matches, as the allowed
+/// values of each field
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calendar {
+    /// 0 is Monday
+    weekdays: Vec<u32>,
+    /// Every year when unset
+    years: Option<Vec<i32>>,
+    months: Vec<u32>,
+    days: Vec<u32>,
+    hours: Vec<u32>,
+    minutes: Vec<u32>,
+    seconds: Vec<u32>,
+    /// cron runs a job on days that match the day of month or the weekday when both
+    /// are restricted; systemd requires both
+    either_day: bool,
+    /// The expression ends in "UTC" rather than using the host's time zone
+    utc: bool,
+}
+
+impl Calendar {
+    /// Parse the five time fields of a crontab line, or one of the @ shortcuts other
+    /// than @reboot
+    pub fn cron(expression: &str) -> Result<Self> {
+        let expression = match expression.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            shortcut if shortcut.starts_with('@') => bail!("{} is not a time of day", shortcut),
+            expression => expression,
+        };
+        let [minute, hour, day, month, weekday] = <[&str; 5]>::try_from(expression.split_whitespace().collect::<Vec<_>>())
+            .map_err(|_| anyhow!("A cron schedule has five fields: {}", expression))?;
+
+        // Both 0 and 7 are Sunday
+        let mut weekdays: Vec<u32> = parse_field(weekday, 0, 7, "-", &|name| name_index(&WEEKDAYS, name).map(|index| (index + 1) % 7))?
+            .into_iter()
+            .map(|day| (day + 6) % 7)
+            .collect();
+        weekdays.sort_unstable();
+        weekdays.dedup();
+        Ok(Self {
+            weekdays,
+            years: None,
+            months: parse_field(month, 1, 12, "-", &|name| name_index(&MONTHS, name).map(|index| index + 1))?,
+            days: parse_field(day, 1, 31, "-", &|_| None)?,
+            hours: parse_field(hour, 0, 23, "-", &|_| None)?,
+            minutes: parse_field(minute, 0, 59, "-", &|_| None)?,
+            seconds: vec![0],
+            either_day: !day.starts_with('*') && !weekday.starts_with('*'),
+            utc: false,
+        })
+    }
+
+    /// Parse an OnCalendar expression, "[weekdays] [[year-]month-day] [hour:minute[:second]] [UTC]",
+    /// or one of its shorthands such as "daily". Days counted from the end of the month
+    /// and time zones other than UTC are not supported.
+    pub fn on_calendar(expression: &str) -> Result<Self> {
+        let expanded = match expression.trim().to_lowercase().as_str() {
+            "minutely" => "*-*-* *:*:00",
+            "hourly" => "*-*-* *:00:00",
+            "daily" => "*-*-* 00:00:00",
+            "weekly" => "Mon *-*-* 00:00:00",
+            "monthly" => "*-*-01 00:00:00",
+            "quarterly" => "*-01,04,07,10-01 00:00:00",
+            "semiannually" => "*-01,07-01 00:00:00",
+            "yearly" | "annually" => "*-01-01 00:00:00",
+            _ => expression,
+        };
+        let mut words: Vec<&str> = expanded.split_whitespace().collect();
+        if words.is_empty() {
+            bail!("Empty calendar expression");
+        }
+
+        let mut utc = false;
+        if words.len() > 1 {
+            let last = words[words.len() - 1];
+            if last.eq_ignore_ascii_case("UTC") {
+                utc = true;
+                words.pop();
+            } else if last.starts_with(|c: char| c.is_ascii_alphabetic()) && !last.contains([':', '-']) {
+                bail!("Time zone {} is not supported, only UTC", last);
+            }
+        }
+
+        let mut words = words.into_iter().peekable();
+        let weekdays = match words.next_if(|word| word.starts_with(|c: char| c.is_ascii_alphabetic())) {
+            Some(weekdays) => parse_field(&weekdays.replace('-', ".."), 0, 6, "..", &|name| name_index(&WEEKDAYS, name))?,
+            None => (0..7).collect(),
+        };
+        let (years, months, days) = match words.next_if(|word| word.contains('-') && !word.contains(':')) {
+            Some(date) => {
+                let parts: Vec<&str> = date.split('-').collect();
+                let (year, month, day) = match parts.as_slice() {
+                    [year, month, day] => (Some(*year), *month, *day),
+                    [month, day] => (None, *month, *day),
+                    _ => bail!("Invalid date {}", date),
+                };
+                if day.contains('~') {
+                    bail!("Days counted from the end of the month are not supported: {}", date);
+                }
+                let years = match year {
+                    Some(year) if year != "*" => Some(parse_field(year, 1970, 2200, "..", &|_| None)?.into_iter().map(|year| year as i32).collect()),
+                    _ => None,
+                };
+                (years, parse_field(month, 1, 12, "..", &|_| None)?, parse_field(day, 1, 31, "..", &|_| None)?)
+            }
+            None => (None, (1..=12).collect(), (1..=31).collect()),
+        };
+        let (hours, minutes, seconds) = match words.next_if(|word| word.contains(':')) {
+            Some(time) => {
+                let parts: Vec<&str> = time.split(':').collect();
+                let (hour, minute, second) = match parts.as_slice() {
+                    [hour, minute] => (*hour, *minute, "00"),
+                    [hour, minute, second] => (*hour, *minute, *second),
+                    _ => bail!("Invalid time {}", time),
+                };
+                (parse_field(hour, 0, 23, "..", &|_| None)?, parse_field(minute, 0, 59, "..", &|_| None)?, parse_field(second, 0, 59, "..", &|_| None)?)
+            }
+            None => (vec![0], vec![0], vec![0]),
+        };
+        if let Some(word) = words.next() {
+            bail!("Unexpected '{}' in calendar expression {}", word, expression);
+        }
+
+        Ok(Self { weekdays, years, months, days, hours, minutes, seconds, either_day: false, utc })
+    }
+
+    /// The first time after `after` that matches, in the offset of `after`. Offsets are
+    /// fixed, so runs on the far side of a daylight saving change are an hour off.
+    pub fn next_after(&self, after: DateTime<FixedOffset>) -> Option<DateTime<FixedOffset>> {
+        let offset = if self.utc { FixedOffset::east_opt(0)? } else { *after.offset() };
+        let start = after.with_timezone(&offset).date_naive();
+        for day in 0..SEARCH_DAYS {
+            let date = start + Duration::days(day);
+            if !self.matches_date(date) {
+                continue;
+            }
+            for &hour in &self.hours {
+                for &minute in &self.minutes {
+                    for &second in &self.seconds {
+                        let Some(time) = date.and_hms_opt(hour, minute, second) else {
+                            continue;
+                        };
+                        match offset.from_local_datetime(&time).single() {
+                            Some(time) if time > after => return Some(time.with_timezone(after.offset())),
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        let year = self.years.as_ref().is_none_or(|years| years.contains(&date.year()));
+        let day = self.days.contains(&date.day());
+        let weekday = self.weekdays.contains(&date.weekday().num_days_from_monday());
+        year && self.months.contains(&date.month()) && if self.either_day { day || weekday } else { day && weekday }
+    }
+}
+
+fn name_index(names: &[&str], name: &str) -> Option<u32> {
+    let name = name.to_lowercase();
+    names.iter().position(|candidate| name.starts_with(candidate)).map(|index| index as u32)
+}
+
+/// Parse a comma separated list of values, `range` separated ranges and "/" steps
+/// into the sorted values it allows. A step after a single value repeats it up to `max`.
+fn parse_field(text: &str, min: u32, max: u32, range: &str, name: &dyn Fn(&str) -> Option<u32>) -> Result<Vec<u32>> {
+    let value = |word: &str| -> Result<u32> {
+        let value = word.parse().ok().or_else(|| name(word)).ok_or_else(|| anyhow!("Invalid value '{}'", word))?;
+        if !(min..=max).contains(&value) {
+            bail!("{} is outside {}-{}", value, min, max);
+        }
+        Ok(value)
+    };
+
+    let mut values = Vec::new();
+    for item in text.split(',') {
+        let (base, step) = match item.split_once('/') {
+            Some((base, step)) => (base, step.parse::<u32>().ok().filter(|step| *step > 0).ok_or_else(|| anyhow!("Invalid step in '{}'", item))?),
+            None => (item, 1),
+        };
+        let (first, last) = match base.split_once(range) {
+            _ if base == "*" => (min, max),
+            Some((first, last)) => (value(first)?, value(last)?),
+            None if step > 1 => (value(base)?, max),
+            None => (value(base)?, value(base)?),
+        };
+        if first > last {
+            bail!("Range '{}' ends before it starts", item);
+        }
+        values.extend((first..=last).step_by(step as usize));
+    }
+    values.sort_unstable();
+    values.dedup();
+    Ok(values)
+}
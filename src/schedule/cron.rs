@@ -0,0 +1,366 @@
+use chrono::{DateTime, Duration, FixedOffset, Months, NaiveDate};
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::journal::{JournalQuery, JournalReader, LogRecord};
+use crate::remote::transport::Transport;
+use super::{Calendar, Job, JobKind, LastRun, HISTORY};
+
+const CRONTAB: &str = "/etc/crontab";
+const CRON_D: &str = "/etc/cron.d";
+const ANACRONTAB: &str = "/etc/anacrontab";
+/// Timestamps of anacron's last runs, one file per job ID
+const ANACRON_SPOOL: &str = "/var/spool/anacron";
+/// User crontabs on Debian, SUSE, and Red Hat and Arch
+const USER_CRONTAB_DIRS: &[&str] = &["/var/spool/cron/crontabs", "/var/spool/cron/tabs", "/var/spool/cron"];
+/// Directories of scripts that run-parts runs from crontab or anacrontab
+const PERIODIC_DIRS: &[&str] = &["/etc/cron.hourly", "/etc/cron.daily", "/etc/cron.weekly", "/etc/cron.monthly"];
+/// Syslog identifiers of the cron daemons and anacron
+const LOG_IDENTIFIERS: &[&str] = &["CRON", "CROND", "crond", "cron", "anacron"];
+
+/// A crontab line
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CronEntry {
+    /// Five time fields, or a shortcut such as "@daily"
+    pub schedule: String,
+    /// Only system crontabs name the user
+    pub user: Option<String>,
+    pub command: String,
+}
+
+/// An anacrontab line
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct AnacronEntry {
+    /// Period as written, such as "7" or "@monthly"
+    pub period: String,
+    /// Delay in minutes after anacron starts
+    pub delay: u32,
+    pub id: String,
+    pub command: String,
+}
+
+impl AnacronEntry {
+    /// The day the job is due again after a run on `last`
+    fn due(&self, last: NaiveDate) -> Option<NaiveDate> {
+        match self.period.as_str() {
+            "@daily" => last.checked_add_signed(Duration::days(1)),
+            "@weekly" => last.checked_add_signed(Duration::days(7)),
+            "@monthly" => last.checked_add_months(Months::new(1)),
+            period => last.checked_add_signed(Duration::days(period.parse().ok()?)),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self.period.as_str() {
+            "1" | "@daily" => "daily".to_string(),
+            "7" | "@weekly" => "weekly".to_string(),
+            "@monthly" => "monthly".to_string(),
+            period => format!("every {} days", period),
+        }
+    }
+}
+
+/// "NAME=value" lines set the environment of the jobs that follow
+fn is_assignment(line: &str) -> bool {
+    line.split_once('=').is_some_and(|(name, _)| {
+        let name = name.trim();
+        !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// The first `count` words of `line` and the rest of it
+fn split_words(line: &str, count: usize) -> Option<(Vec<&str>, &str)> {
+    let mut words = Vec::new();
+    let mut rest = line.trim_start();
+    for _ in 0..count {
+        let end = rest.find(char::is_whitespace)?;
+        words.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    (!rest.is_empty()).then_some((words, rest.trim_end()))
+}
+
+/// Parse a crontab. System crontabs, /etc/crontab and /etc/cron.d, have a user
+/// field between the schedule and the command.
+pub(crate) fn parse_crontab(text: &str, system: bool) -> Vec<CronEntry> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !is_assignment(line))
+        .filter_map(|line| {
+            let fields = if line.starts_with('@') { 1 } else { 5 };
+            let (words, rest) = split_words(line, fields + usize::from(system))?;
+            Some(CronEntry {
+                schedule: words[..fields].join(" "),
+                user: system.then(|| words[fields].to_string()),
+                command: rest.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Parse an anacrontab into its jobs and the hour anacron starts running them, from
+/// START_HOURS_RANGE
+pub(crate) fn parse_anacrontab(text: &str) -> (Vec<AnacronEntry>, Option<u32>) {
+    let mut start_hour = None;
+    let mut entries = Vec::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        if is_assignment(line) {
+            if let Some(range) = line.strip_prefix("START_HOURS_RANGE=") {
+                start_hour = range.split('-').next().and_then(|hour| hour.trim().parse().ok());
+            }
+            continue;
+        }
+        let Some((words, command)) = split_words(line, 3) else {
+            continue;
+        };
+        entries.push(AnacronEntry {
+            period: words[0].to_string(),
+            delay: words[1].parse().unwrap_or(0),
+            id: words[2].to_string(),
+            command: command.to_string(),
+        });
+    }
+    (entries, start_hour)
+}
+
+/// The periodic directory a command runs with run-parts, if any
+fn periodic_dir(command: &str) -> Option<&'static str> {
+    if !command.contains("run-parts") {
+        return None;
+    }
+    PERIODIC_DIRS.iter().copied().find(|dir| {
+        command.split(|c: char| c.is_whitespace() || matches!(c, ';' | ')' | '}' | '&' | '|'))
+            .any(|word| word.trim_end_matches('/') == *dir)
+    })
+}
+
+/// What a job is called in the timeline: the program of the last command in the line,
+/// since earlier ones are usually guards such as "test -x /usr/sbin/anacron ||"
+fn command_name(command: &str) -> String {
+    let last = command.split(['&', '|', ';']).map(str::trim).rfind(|part| !part.is_empty()).unwrap_or(command);
+    let program = last.split_whitespace()
+        .find(|word| !is_assignment(word) && !["(", "{", "exec", "nice", "ionice"].contains(word))
+        .unwrap_or_default();
+    program.rsplit('/').next().unwrap_or(program).to_string()
+}
+
+/// Last runs found in cron's and anacron's log messages
+#[derive(Debug, Default)]
+pub(crate) struct CronLog {
+    /// Start of the last run of each (user, command); cron does not log results
+    pub commands: HashMap<(String, String), DateTime<FixedOffset>>,
+    /// Last run of each anacron job ID
+    pub anacron: HashMap<String, LastRun>,
+}
+
+/// Parse log records such as "(root) CMD (command)" from cron and "Job `cron.daily'
+/// terminated (exit status: 1)" from anacron, oldest first
+pub(crate) fn parse_cron_log(records: &[LogRecord], offset: FixedOffset) -> CronLog {
+    let command = Regex::new(r"^\((?P<user>[^)]+)\) CMD \((?P<command>.*)\)$").expect("valid regex");
+    let anacron = Regex::new(r"^Job `(?P<id>[^']+)' (?P<event>started|terminated)(?P<rest>.*)$").expect("valid regex");
+    let status = Regex::new(r"exit status: (?P<status>\d+)").expect("valid regex");
+
+    let mut log = CronLog::default();
+    for record in records {
+        let at = record.timestamp.with_timezone(&offset);
+        let message = record.message.trim();
+        if let Some(captures) = command.captures(message) {
+            log.commands.insert((captures["user"].to_string(), captures["command"].to_string()), at);
+        } else if let Some(captures) = anacron.captures(message) {
+            let id = captures["id"].to_string();
+            if &captures["event"] == "started" {
+                log.anacron.insert(id, LastRun { at, success: None, detail: None });
+            } else if let Some(run) = log.anacron.get_mut(&id) {
+                let status = status.captures(&captures["rest"]).map(|captures| captures["status"].to_string());
+                run.success = Some(status.is_none());
+                run.detail = status.map(|status| format!("exit status {}", status));
+            }
+        }
+    }
+    log
+}
+
+/// How a periodic directory is run
+enum Runner {
+    Cron { schedule: String, calendars: Vec<Calendar>, user: String, command: String, source: String },
+    Anacron(AnacronEntry),
+}
+
+/// Read a file, noting it as unreadable when it exists but cannot be read
+async fn read(transport: &dyn Transport, path: &str, unreadable: &mut Vec<String>) -> Option<String> {
+    match transport.read_file(path).await {
+        Ok(text) => Some(text),
+        Err(_) => {
+            if transport.path_exists(path).await {
+                unreadable.push(path.to_string());
+            }
+            None
+        }
+    }
+}
+
+/// Regular files matching the shell patterns
+async fn list_files(transport: &dyn Transport, patterns: &[String]) -> Vec<String> {
+    let script = format!("for f in {}; do [ -f \"$f\" ] && echo \"$f\"; done; true", patterns.join(" "));
+    match transport.run("sh", &["-c", &script]).await {
+        Ok(output) => output.stdout.lines().map(String::from).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Jobs from the system and user crontabs, the periodic directories and anacrontab,
+/// and the files that could not be read
+pub(crate) async fn jobs(transport: &Arc<dyn Transport>, now: DateTime<FixedOffset>) -> (Vec<Job>, Vec<String>) {
+    let mut unreadable = Vec::new();
+    let mut crontabs: Vec<(String, Vec<CronEntry>)> = Vec::new();
+
+    let mut system = vec![CRONTAB.to_string()];
+    // cron skips editor backups and package manager leftovers
+    system.extend(list_files(transport.as_ref(), &[format!("{}/*", CRON_D)]).await.into_iter().filter(|path| {
+        let name = path.rsplit('/').next().unwrap_or_default();
+        !name.starts_with('.') && !name.ends_with('~') && !name.contains(".dpkg-") && !name.contains(".rpm")
+    }));
+    for path in system {
+        if let Some(text) = read(transport.as_ref(), &path, &mut unreadable).await {
+            crontabs.push((path, parse_crontab(&text, true)));
+        }
+    }
+
+    // The spool directories are only readable by root; other users can still read
+    // their own crontab
+    let user_files = list_files(transport.as_ref(), &USER_CRONTAB_DIRS.iter().map(|dir| format!("{}/*", dir)).collect::<Vec<_>>()).await;
+    let spool_readable = !user_files.is_empty();
+    for path in user_files {
+        let user = path.rsplit('/').next().unwrap_or_default().to_string();
+        if let Some(text) = read(transport.as_ref(), &path, &mut unreadable).await {
+            let entries = parse_crontab(&text, false).into_iter()
+                .map(|entry| CronEntry { user: Some(user.clone()), ..entry })
+                .collect();
+            crontabs.push((path, entries));
+        }
+    }
+    if !spool_readable {
+        if let Ok(output) = transport.run("sh", &["-c", "crontab -l 2>/dev/null && echo \"#user=$(id -un)\""]).await {
+            let user = output.stdout.lines().rev().find_map(|line| line.strip_prefix("#user=")).unwrap_or_default().to_string();
+            if output.success() && !user.is_empty() {
+                let entries = parse_crontab(&output.stdout, false).into_iter()
+                    .map(|entry| CronEntry { user: Some(user.clone()), ..entry })
+                    .collect();
+                crontabs.push((format!("crontab of {}", user), entries));
+            }
+        }
+    }
+
+    let (anacron_entries, start_hour) = match read(transport.as_ref(), ANACRONTAB, &mut unreadable).await {
+        Some(text) => parse_anacrontab(&text),
+        None => (Vec::new(), None),
+    };
+
+    let records = JournalReader::new(transport.clone())
+        .read(&JournalQuery {
+            identifiers: LOG_IDENTIFIERS.iter().map(|identifier| identifier.to_string()).collect(),
+            since: Some(HISTORY.to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap_or_default();
+    let log = parse_cron_log(&records, *now.offset());
+
+    let mut jobs = Vec::new();
+    let mut runners: HashMap<&str, Runner> = HashMap::new();
+    for (source, entries) in &crontabs {
+        for entry in entries {
+            let user = entry.user.clone().unwrap_or_default();
+            let calendars: Vec<Calendar> = Calendar::cron(&entry.schedule).into_iter().collect();
+            if let Some(dir) = periodic_dir(&entry.command) {
+                runners.insert(dir, Runner::Cron {
+                    schedule: entry.schedule.clone(),
+                    calendars,
+                    user,
+                    command: entry.command.clone(),
+                    source: source.clone(),
+                });
+                continue;
+            }
+            let mut job = Job::new(JobKind::Cron, &command_name(&entry.command), &entry.schedule, &entry.command, source);
+            job.user = entry.user.clone();
+            job.set_calendars(calendars, now);
+            job.last = log.commands.get(&(user, entry.command.clone()))
+                .map(|at| LastRun { at: *at, success: None, detail: None });
+            jobs.push(job);
+        }
+    }
+
+    // Anacron takes over a directory from cron; Debian's crontab only runs them
+    // itself when anacron is not installed
+    for entry in &anacron_entries {
+        match periodic_dir(&entry.command) {
+            Some(dir) => {
+                runners.insert(dir, Runner::Anacron(entry.clone()));
+            }
+            None => {
+                let mut job = Job::new(JobKind::Anacron, &entry.id, &entry.describe(), &entry.command, ANACRONTAB);
+                job.user = Some("root".to_string());
+                anacron_schedule(transport.as_ref(), &mut job, entry, start_hour, now, &log).await;
+                jobs.push(job);
+            }
+        }
+    }
+
+    let scripts = list_files(transport.as_ref(), &PERIODIC_DIRS.iter().map(|dir| format!("{}/*", dir)).collect::<Vec<_>>()).await;
+    for script in scripts {
+        let Some((dir, name)) = script.rsplit_once('/') else {
+            continue;
+        };
+        // run-parts only runs names made of letters, digits, '_' and '-'
+        if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            continue;
+        }
+        let label = format!("{}/{}", dir.trim_start_matches("/etc/"), name);
+        let job = match runners.get(dir) {
+            Some(Runner::Cron { schedule, calendars, user, command, source }) => {
+                let mut job = Job::new(JobKind::Cron, &label, schedule, &script, source);
+                job.user = Some(user.clone());
+                job.set_calendars(calendars.clone(), now);
+                job.last = log.commands.get(&(user.clone(), command.clone()))
+                    .map(|at| LastRun { at: *at, success: None, detail: None });
+                job
+            }
+            Some(Runner::Anacron(entry)) => {
+                let mut job = Job::new(JobKind::Anacron, &label, &entry.describe(), &script, ANACRONTAB);
+                job.user = Some("root".to_string());
+                anacron_schedule(transport.as_ref(), &mut job, entry, start_hour, now, &log).await;
+                job
+            }
+            None => {
+                let mut job = Job::new(JobKind::Cron, &label, "not run by crontab or anacrontab", &script, dir);
+                job.active = false;
+                job
+            }
+        };
+        jobs.push(job);
+    }
+    (jobs, unreadable)
+}
+
+/// Work out the next run of an anacron job from the day it last ran. A job that is
+/// already due runs whenever anacron next starts, which is not known here.
+async fn anacron_schedule(transport: &dyn Transport, job: &mut Job, entry: &AnacronEntry, start_hour: Option<u32>,
+    now: DateTime<FixedOffset>, log: &CronLog) {
+    job.last = log.anacron.get(&entry.id).cloned();
+    let stamp = transport.read_file(&format!("{}/{}", ANACRON_SPOOL, entry.id)).await.ok();
+    let last = stamp.and_then(|stamp| NaiveDate::parse_from_str(stamp.trim(), "%Y%m%d").ok());
+    let next = last
+        .and_then(|last| entry.due(last))
+        .and_then(|due| due.and_hms_opt(start_hour.unwrap_or(0), 0, 0))
+        .and_then(|due| due.and_local_timezone(*now.offset()).single())
+        .map(|due| due + Duration::minutes(entry.delay.into()));
+    match next {
+        Some(next) if next > now => job.next = Some(next),
+        _ => job.schedule.push_str(", due now"),
+    }
+}
+
@@ -0,0 +1,373 @@
+//! Scheduled jobs.
+//!
+//! cron, anacron and systemd timers each schedule jobs in their own way: crontab
+//! lines, scripts in `/etc/cron.daily` and friends, anacrontab periods and timer units
+//! with OnCalendar expressions. Each source is parsed into a common job model, and
+//! schedules are evaluated here rather than asked of the daemons, so every future run
+//! can be placed on one timeline. The last result of each job is taken from the
+//! journal. New timers and schedule changes are planned as unit files and commands for
+//! the executor to run, like the kernel drop-ins.
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Duration, FixedOffset, Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::remote::transport::{shell_quote, Transport};
+
+pub mod calendar;
+pub mod cron;
+pub mod timers;
+
+pub use calendar::Calendar;
+
+/// How far back the journal is searched for last runs, long enough for monthly jobs
+const HISTORY: &str = "-32d";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobKind {
+    Cron,
+    Anacron,
+    Timer,
+}
+
+impl fmt::Display for JobKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobKind::Cron => write!(f, "cron"),
+            JobKind::Anacron => write!(f, "anacron"),
+            JobKind::Timer => write!(f, "timer"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastRun {
+    pub at: DateTime<FixedOffset>,
+    /// Unknown when the log only records that the job started, as cron's does
+    pub success: Option<bool>,
+    /// Exit status or systemd's result when the job failed
+    pub detail: Option<String>,
+}
+
+impl fmt::Display for LastRun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.at.format("%a %Y-%m-%d %H:%M"))?;
+        match self.success {
+            Some(true) => write!(f, ", succeeded")?,
+            Some(false) => write!(f, ", failed")?,
+            None => write!(f, ", started")?,
+        }
+        if let Some(detail) = &self.detail {
+            write!(f, " ({})", detail)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub kind: JobKind,
+    /// Timer unit, anacron job ID, "cron.daily/logrotate" for periodic scripts, or the
+    /// program a crontab line runs
+    pub name: String,
+    pub user: Option<String>,
+    /// The schedule as written, such as "30 3 * * *" or "Mon..Fri 09:00"
+    pub schedule: String,
+    pub command: String,
+    /// File the job is defined in
+    pub source: String,
+    /// False for stopped timers and periodic scripts nothing runs
+    pub active: bool,
+    pub next: Option<DateTime<FixedOffset>>,
+    pub last: Option<LastRun>,
+    /// Parsed schedules, for runs after `next`
+    #[serde(skip)]
+    calendars: Vec<Calendar>,
+}
+
+impl Job {
+    pub(crate) fn new(kind: JobKind, name: &str, schedule: &str, command: &str, source: &str) -> Self {
+        Self {
+            kind,
+            name: name.to_string(),
+            user: None,
+            schedule: schedule.to_string(),
+            command: command.to_string(),
+            source: source.to_string(),
+            active: true,
+            next: None,
+            last: None,
+            calendars: Vec::new(),
+        }
+    }
+
+    /// Schedule the job by `calendars`, with the next run after `now`
+    pub(crate) fn set_calendars(&mut self, calendars: Vec<Calendar>, now: DateTime<FixedOffset>) {
+        self.next = calendars.iter().filter_map(|calendar| calendar.next_after(now)).min();
+        self.calendars = calendars;
+    }
+
+    /// The first run at or after `from` and before `to`. Jobs without a calendar, such
+    /// as anacron jobs, only know their next run.
+    pub fn next_between(&self, from: DateTime<FixedOffset>, to: DateTime<FixedOffset>) -> Option<DateTime<FixedOffset>> {
+        if !self.active {
+            return None;
+        }
+        let run = if self.calendars.is_empty() {
+            self.next.filter(|next| *next >= from)
+        } else {
+            let before = from - Duration::seconds(1);
+            self.calendars.iter().filter_map(|calendar| calendar.next_after(before)).min()
+        };
+        run.filter(|run| *run < to)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleReport {
+    /// The host's clock and UTC offset when the report was made
+    pub now: DateTime<FixedOffset>,
+    /// Jobs by next run; jobs without one come last
+    pub jobs: Vec<Job>,
+    /// Crontabs and units that could not be read
+    pub unreadable: Vec<String>,
+}
+
+impl ScheduleReport {
+    /// Jobs that run between `from` and `to`, each with its first run in that window,
+    /// in the order they run
+    pub fn timeline(&self, from: DateTime<FixedOffset>, to: DateTime<FixedOffset>) -> Vec<(DateTime<FixedOffset>, &Job)> {
+        let mut runs: Vec<(DateTime<FixedOffset>, &Job)> = self.jobs.iter()
+            .filter_map(|job| Some((job.next_between(from, to)?, job)))
+            .collect();
+        runs.sort_by_key(|(run, _)| *run);
+        runs
+    }
+
+    /// The timer called `name`, with or without its ".timer" suffix
+    pub fn timer(&self, name: &str) -> Option<&Job> {
+        let name = timer_name(name);
+        self.jobs.iter().find(|job| job.kind == JobKind::Timer && job.name == name)
+    }
+}
+
+/// A new timer and the service it starts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewTimer {
+    pub name: String,
+    pub on_calendar: String,
+    /// Command line; run through /bin/sh when it uses shell syntax
+    pub command: String,
+    /// Run as this user instead of root
+    pub user: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnitFile {
+    pub path: String,
+    /// Current content; empty for a new file
+    pub before: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ScheduleStep {
+    Write(UnitFile),
+    Run(Vec<String>),
+}
+
+impl fmt::Display for ScheduleStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleStep::Write(file) => write!(f, "write {}", file.path),
+            ScheduleStep::Run(command) => write!(f, "{}", command.iter().map(|word| shell_quote(word)).collect::<Vec<_>>().join(" ")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchedulePlan {
+    pub description: String,
+    /// The schedule being replaced, for edits
+    pub before: Option<String>,
+    pub schedule: String,
+    /// The next few runs under the new schedule
+    pub runs: Vec<DateTime<FixedOffset>>,
+    pub steps: Vec<ScheduleStep>,
+    /// Command that restores the previous state
+    pub undo: String,
+}
+
+/// How many upcoming runs a plan shows
+const PREVIEW_RUNS: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct ScheduleInspector {
+    transport: Arc<dyn Transport>,
+}
+
+impl ScheduleInspector {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    /// Every cron, anacron and timer job with its next and last run
+    pub async fn report(&self) -> ScheduleReport {
+        let now = self.now().await;
+        let (mut jobs, mut unreadable) = cron::jobs(&self.transport, now).await;
+        let (timer_jobs, timer_unreadable) = timers::jobs(&self.transport, now).await;
+        jobs.extend(timer_jobs);
+        unreadable.extend(timer_unreadable);
+        jobs.sort_by(|a, b| match (a.next, b.next) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => a.name.cmp(&b.name),
+        });
+        ScheduleReport { now, jobs, unreadable }
+    }
+
+    /// Create a timer and its service in /etc/systemd/system and start the timer
+    pub async fn plan_add(&self, timer: &NewTimer) -> Result<SchedulePlan> {
+        let name = timer_name(&timer.name);
+        let base = name.trim_end_matches(".timer");
+        if base.is_empty() || !base.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':')) {
+            bail!("Invalid timer name '{}'; use letters, digits, '-', '_', '.' and ':'", timer.name);
+        }
+        let report = self.report().await;
+        if report.timer(&name).is_some() {
+            bail!("{} already exists; change its schedule with `tuxpilot schedule edit {}`", name, name);
+        }
+        let timer_path = format!("{}/{}", timers::ETC_UNIT_DIR, name);
+        let service_path = format!("{}/{}.service", timers::ETC_UNIT_DIR, base);
+        for path in [&timer_path, &service_path] {
+            if self.transport.path_exists(path).await {
+                bail!("{} already exists", path);
+            }
+        }
+
+        let runs = self.runs(&timer.on_calendar, report.now).await?;
+        let program = timer.command.split_whitespace().next().ok_or_else(|| anyhow!("Empty command"))?;
+        let program = if program.starts_with('/') || timers::needs_shell(&timer.command) {
+            program.to_string()
+        } else {
+            // Older systemd versions want an absolute path in ExecStart
+            let output = self.transport.run("sh", &["-c", &format!("command -v {}", shell_quote(program))]).await?;
+            let path = output.stdout.trim().to_string();
+            if !output.success() || !path.starts_with('/') {
+                bail!("{} was not found on {}", program, self.transport.host_name());
+            }
+            path
+        };
+
+        let description = timer.description.clone().unwrap_or_else(|| timer.command.clone());
+        Ok(SchedulePlan {
+            description: format!("Run `{}` on {} with {}", timer.command, timer.on_calendar, name),
+            before: None,
+            schedule: timer.on_calendar.clone(),
+            runs,
+            steps: vec![
+                ScheduleStep::Write(UnitFile {
+                    path: service_path.clone(),
+                    before: String::new(),
+                    content: timers::service_unit(&description, &timer.command, &program, timer.user.as_deref()),
+                }),
+                ScheduleStep::Write(UnitFile {
+                    path: timer_path.clone(),
+                    before: String::new(),
+                    content: timers::timer_unit(&description, &timer.on_calendar),
+                }),
+                ScheduleStep::Run(["systemctl", "daemon-reload"].map(String::from).to_vec()),
+                ScheduleStep::Run(["systemctl", "enable", "--now", &name].map(String::from).to_vec()),
+            ],
+            undo: format!("systemctl disable --now {} && rm {} {} && systemctl daemon-reload", name, timer_path, service_path),
+        })
+    }
+
+    /// Replace the OnCalendar schedule of an existing timer with a drop-in
+    pub async fn plan_edit(&self, timer: &str, on_calendar: &str) -> Result<SchedulePlan> {
+        let report = self.report().await;
+        let job = report.timer(timer).ok_or_else(|| anyhow!("No timer called {} on {}", timer_name(timer), self.transport.host_name()))?;
+        let runs = self.runs(on_calendar, report.now).await?;
+        let path = format!("{}/{}.d/{}", timers::ETC_UNIT_DIR, job.name, timers::DROP_IN);
+        let before = self.transport.read_file(&path).await.unwrap_or_default();
+        let undo = match timers::parse_timer_unit(&before).calendars.first() {
+            // Restore the schedule tuxpilot set last time, not the unit's own
+            Some(previous) => format!("tuxpilot schedule edit {} --on-calendar {}", job.name, shell_quote(previous)),
+            None => format!("rm {} && systemctl daemon-reload && systemctl restart {}", path, job.name),
+        };
+        Ok(SchedulePlan {
+            description: format!("Run {} on {}", job.name, on_calendar),
+            before: Some(job.schedule.clone()),
+            schedule: on_calendar.to_string(),
+            runs,
+            steps: vec![
+                ScheduleStep::Write(UnitFile { path, before, content: timers::schedule_drop_in(on_calendar) }),
+                ScheduleStep::Run(["systemctl", "daemon-reload"].map(String::from).to_vec()),
+                // Restarting makes the timer work out its next elapse again
+                ScheduleStep::Run(["systemctl", "restart", &job.name].map(String::from).to_vec()),
+            ],
+            undo,
+        })
+    }
+
+    /// The next runs of an OnCalendar expression. Expressions this module cannot
+    /// evaluate are checked with systemd-analyze instead.
+    async fn runs(&self, on_calendar: &str, now: DateTime<FixedOffset>) -> Result<Vec<DateTime<FixedOffset>>> {
+        let calendar = match Calendar::on_calendar(on_calendar) {
+            Ok(calendar) => calendar,
+            Err(error) => {
+                let iterations = format!("--iterations={}", PREVIEW_RUNS);
+                let output = self.transport.run("systemd-analyze", &["calendar", &iterations, on_calendar]).await;
+                return match output {
+                    Ok(output) if output.success() => Ok(timers::parse_analyze_calendar(&output.stdout, *now.offset())),
+                    _ => Err(error.context(format!("Invalid OnCalendar expression '{}'", on_calendar))),
+                };
+            }
+        };
+        let mut runs = Vec::new();
+        let mut after = now;
+        while runs.len() < PREVIEW_RUNS {
+            let Some(run) = calendar.next_after(after) else {
+                break;
+            };
+            runs.push(run);
+            after = run;
+        }
+        if runs.is_empty() {
+            bail!("'{}' never elapses again", on_calendar);
+        }
+        Ok(runs)
+    }
+
+    /// The host's clock with its current UTC offset, which schedules are evaluated in
+    async fn now(&self) -> DateTime<FixedOffset> {
+        let output = self.transport.run("date", &["+%s %z"]).await;
+        output.ok()
+            .filter(|output| output.success())
+            .and_then(|output| parse_date(output.stdout.trim()))
+            .unwrap_or_else(|| Local::now().fixed_offset())
+    }
+}
+
+/// Parse `date '+%s %z'` output such as "1760832000 +0200"
+fn parse_date(text: &str) -> Option<DateTime<FixedOffset>> {
+    let (seconds, offset) = text.split_once(' ')?;
+    let sign = if offset.starts_with('-') { -1 } else { 1 };
+    let digits = offset.trim_start_matches(['+', '-']);
+    if digits.len() != 4 {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    let offset = FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))?;
+    offset.timestamp_opt(seconds.parse().ok()?, 0).single()
+}
+
+/// Unit name of a timer given with or without its suffix
+fn timer_name(name: &str) -> String {
+    if name.ends_with(".timer") { name.to_string() } else { format!("{}.timer", name) }
+}
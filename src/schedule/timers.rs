@@ -0,0 +1,409 @@
+use chrono::{DateTime, Duration, FixedOffset, NaiveDateTime, TimeZone};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::journal::{JournalQuery, JournalReader, LogRecord};
+use crate::linux_integration::boot::parse_span;
+use crate::network::{config_files, parse_ini};
+use crate::remote::transport::Transport;
+use super::{Calendar, Job, JobKind, LastRun, HISTORY};
+
+/// Where administrators' units and drop-ins go
+pub(crate) const ETC_UNIT_DIR: &str = "/etc/systemd/system";
+/// Unit directories by precedence
+const UNIT_DIRS: &[&str] = &[
+    "/etc/systemd/system",
+    "/run/systemd/system",
+    "/usr/local/lib/systemd/system",
+    "/usr/lib/systemd/system",
+    "/lib/systemd/system",
+];
+/// Drop-in that `tuxpilot schedule edit` writes; it sorts after systemctl edit's
+/// override.conf, so it wins over it
+pub(crate) const DROP_IN: &str = "tuxpilot.conf";
+/// Timer settings that count from an event rather than the wall clock
+const MONOTONIC: &[&str] = &["OnActiveSec", "OnBootSec", "OnStartupSec", "OnUnitActiveSec", "OnUnitInactiveSec"];
+const TIMER_PROPERTIES: &str = "Id,Unit,TimersCalendar,TimersMonotonic,NextElapseUSecRealtime,NextElapseUSecMonotonic,\
+    LastTriggerUSec,ActiveState,FragmentPath";
+
+/// The [Timer] settings of a timer unit and its drop-ins
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct TimerUnit {
+    pub calendars: Vec<String>,
+    /// Settings such as "OnBootSec=15min"
+    pub monotonic: Vec<String>,
+    /// Unit the timer starts; the service of the same name when unset
+    pub unit: Option<String>,
+}
+
+impl TimerUnit {
+    /// Apply a unit file or drop-in on top of what was read so far. Assigning the empty
+    /// string to any timer setting clears them all.
+    pub(crate) fn merge(&mut self, text: &str) {
+        for (section, entries) in parse_ini(text) {
+            if section != "Timer" {
+                continue;
+            }
+            for (key, value) in entries {
+                match key.as_str() {
+                    "OnCalendar" | "OnActiveSec" | "OnBootSec" | "OnStartupSec" | "OnUnitActiveSec" | "OnUnitInactiveSec" if value.is_empty() => {
+                        self.calendars.clear();
+                        self.monotonic.clear();
+                    }
+                    "OnCalendar" => self.calendars.push(value),
+                    "Unit" => self.unit = Some(value),
+                    key if MONOTONIC.contains(&key) => self.monotonic.push(format!("{}={}", key, value)),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn parse_timer_unit(text: &str) -> TimerUnit {
+    let mut unit = TimerUnit::default();
+    unit.merge(text);
+    unit
+}
+
+/// A timer as systemd or its unit files describe it
+#[derive(Debug, Clone)]
+struct Timer {
+    name: String,
+    unit: TimerUnit,
+    service: String,
+    source: String,
+    active: bool,
+    next: Option<DateTime<FixedOffset>>,
+    last_trigger: Option<DateTime<FixedOffset>>,
+}
+
+/// What a service runs, and how its last run ended according to systemd
+#[derive(Debug, Clone, Default)]
+struct Service {
+    command: Option<String>,
+    user: Option<String>,
+    result: Option<String>,
+}
+
+/// Parse `systemctl list-timers --all --no-legend` into timers and the units they
+/// start. The time columns hold spaces, so the timer is found by its suffix.
+pub(crate) fn parse_list_timers(output: &str) -> Vec<(String, String)> {
+    output.lines()
+        .filter_map(|line| {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let index = words.iter().rposition(|word| word.ends_with(".timer"))?;
+            let activates = words.get(index + 1).filter(|unit| **unit != "-").copied().unwrap_or_default();
+            Some((words[index].to_string(), activates.to_string()))
+        })
+        .collect()
+}
+
+/// Parse `systemctl show` output for several units into one property list per unit.
+/// Array properties such as TimersCalendar may repeat.
+pub(crate) fn parse_show(output: &str) -> Vec<Vec<(String, String)>> {
+    let mut units: Vec<Vec<(String, String)>> = vec![Vec::new()];
+    for line in output.lines() {
+        match line.split_once('=') {
+            Some((key, value)) => units.last_mut().expect("never empty").push((key.to_string(), value.to_string())),
+            None if line.trim().is_empty() => units.push(Vec::new()),
+            None => {}
+        }
+    }
+    units.retain(|properties| !properties.is_empty());
+    units
+}
+
+/// Parse a timestamp as systemctl prints it, "Mon 2026-10-19 03:00:00 CEST" or
+/// "@1760835600". Zone abbreviations other than UTC are taken to be the host's.
+pub(crate) fn parse_timestamp(value: &str, offset: FixedOffset) -> Option<DateTime<FixedOffset>> {
+    let value = value.trim();
+    if let Some(seconds) = value.strip_prefix('@') {
+        return offset.timestamp_opt(seconds.parse().ok()?, 0).single();
+    }
+    let words: Vec<&str> = value.split_whitespace().collect();
+    let [_, date, time, rest @ ..] = words.as_slice() else {
+        return None;
+    };
+    let local = NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y-%m-%d %H:%M:%S").ok()?;
+    match rest.first() {
+        Some(&"UTC") => Some(local.and_utc().with_timezone(&offset)),
+        _ => local.and_local_timezone(offset).single(),
+    }
+}
+
+/// The expressions in a TimersCalendar property, "{ OnCalendar=*-*-* 03:00:00 ; next_elapse=... }"
+fn calendar_expressions(value: &str) -> Vec<String> {
+    value.split("OnCalendar=").skip(1)
+        .filter_map(|rest| rest.split(" ;").next())
+        .map(|expression| expression.trim().to_string())
+        .collect()
+}
+
+/// The settings in a TimersMonotonic property, "{ OnUnitActiveSec=1d ; next_elapse=... }"
+fn monotonic_settings(value: &str) -> Vec<String> {
+    value.split('{').skip(1)
+        .filter_map(|rest| rest.split(" ;").next())
+        .map(|setting| setting.trim().to_string())
+        .filter(|setting| setting.contains('='))
+        .collect()
+}
+
+/// Parse the "Next elapse" and "Iter. #n" lines of `systemd-analyze calendar`
+pub(crate) fn parse_analyze_calendar(output: &str, offset: FixedOffset) -> Vec<DateTime<FixedOffset>> {
+    output.lines()
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(label, _)| matches!(label.trim(), "Next elapse") || label.trim().starts_with("Iter. #"))
+        .filter_map(|(_, value)| parse_timestamp(value, offset))
+        .collect()
+}
+
+/// The last run of `service` from systemd's messages about it, oldest first
+pub(crate) fn parse_service_log(records: &[LogRecord], service: &str, offset: FixedOffset) -> Option<LastRun> {
+    let mut last: Option<LastRun> = None;
+    for record in records.iter().filter(|record| record.identifier.as_deref() == Some("systemd") && record.concerns_unit(service)) {
+        let message = record.message.trim();
+        if message.starts_with("Starting ") {
+            last = Some(LastRun { at: record.timestamp.with_timezone(&offset), success: None, detail: None });
+            continue;
+        }
+        let Some(run) = last.as_mut() else {
+            continue;
+        };
+        if message.starts_with("Finished ") || message.ends_with("Deactivated successfully.") {
+            run.success = Some(true);
+        } else if let Some((_, status)) = message.split_once("Main process exited, ").filter(|(_, status)| !status.contains("status=0/")) {
+            run.detail = status.split(", ").find_map(|part| part.strip_prefix("status=")).map(|status| format!("status={}", status.trim_end_matches('.')));
+        } else if message.contains("Failed with result") || message.starts_with("Failed to start ") {
+            run.success = Some(false);
+            if run.detail.is_none() {
+                run.detail = message.split('\'').nth(1).map(String::from);
+            }
+        }
+    }
+    last
+}
+
+/// Timers as the running systemd sees them, with drop-ins applied; None when
+/// systemctl cannot talk to systemd, as in most containers
+async fn from_systemd(transport: &dyn Transport, now: DateTime<FixedOffset>) -> Option<Vec<Timer>> {
+    let output = transport.run("systemctl", &["list-timers", "--all", "--no-legend", "--no-pager"]).await.ok()?;
+    if !output.success() {
+        return None;
+    }
+    let listed = parse_list_timers(&output.stdout);
+    if listed.is_empty() {
+        return Some(Vec::new());
+    }
+
+    let mut args = vec!["show", "--no-pager", "-p", TIMER_PROPERTIES];
+    args.extend(listed.iter().map(|(timer, _)| timer.as_str()));
+    let output = transport.run("systemctl", &args).await.ok()?;
+    let uptime = transport.read_file("/proc/uptime").await.ok()
+        .and_then(|uptime| uptime.split_whitespace().next().and_then(|seconds| seconds.parse::<f64>().ok()));
+
+    let offset = *now.offset();
+    let mut timers = Vec::new();
+    for properties in parse_show(&output.stdout) {
+        let get = |key: &str| properties.iter().find(|(name, _)| name == key).map(|(_, value)| value.as_str()).unwrap_or_default();
+        let name = get("Id").to_string();
+        let unit = TimerUnit {
+            calendars: properties.iter().filter(|(key, _)| key == "TimersCalendar").flat_map(|(_, value)| calendar_expressions(value)).collect(),
+            monotonic: properties.iter().filter(|(key, _)| key == "TimersMonotonic").flat_map(|(_, value)| monotonic_settings(value)).collect(),
+            unit: Some(get("Unit").to_string()).filter(|unit| !unit.is_empty()),
+        };
+        // Monotonic timers elapse relative to boot, so the wall clock time is the boot
+        // time plus the span
+        let next = parse_timestamp(get("NextElapseUSecRealtime"), offset).or_else(|| {
+            let span = parse_span(get("NextElapseUSecMonotonic"))?;
+            Some(now - Duration::milliseconds((uptime? * 1000.0) as i64) + Duration::milliseconds(span as i64))
+        });
+        let listed_service = listed.iter().find(|(timer, _)| *timer == name).map(|(_, service)| service.clone()).unwrap_or_default();
+        timers.push(Timer {
+            service: unit.unit.clone().filter(|_| listed_service.is_empty()).unwrap_or(listed_service),
+            source: get("FragmentPath").to_string(),
+            active: get("ActiveState") == "active",
+            next,
+            last_trigger: parse_timestamp(get("LastTriggerUSec"), offset),
+            unit,
+            name,
+        });
+    }
+    Some(timers)
+}
+
+/// Timers read from their unit files and drop-ins, for hosts where systemd is not
+/// running; enabled timers are taken to be active
+async fn from_files(transport: &dyn Transport) -> (Vec<Timer>, Vec<String>) {
+    let mut unreadable = Vec::new();
+    let wants: Vec<String> = UNIT_DIRS.iter().map(|dir| format!("{}/*.wants/*.timer", dir)).collect();
+    let enabled: Vec<String> = match transport.run("sh", &["-c", &format!("ls -1d {} 2>/dev/null", wants.join(" "))]).await {
+        Ok(output) => output.stdout.lines().filter_map(|path| path.rsplit('/').next()).map(String::from).collect(),
+        Err(_) => Vec::new(),
+    };
+
+    let mut timers = Vec::new();
+    for path in config_files(transport, UNIT_DIRS, "*.timer").await {
+        let name = path.rsplit('/').next().unwrap_or_default().to_string();
+        // Templates only run through their instances
+        if name.ends_with("@.timer") {
+            continue;
+        }
+        let Ok(text) = transport.read_file(&path).await else {
+            unreadable.push(path);
+            continue;
+        };
+        let mut unit = parse_timer_unit(&text);
+        let drop_in_dirs: Vec<String> = UNIT_DIRS.iter().map(|dir| format!("{}/{}.d", dir, name)).collect();
+        let drop_in_dirs: Vec<&str> = drop_in_dirs.iter().map(String::as_str).collect();
+        for drop_in in config_files(transport, &drop_in_dirs, "*.conf").await {
+            match transport.read_file(&drop_in).await {
+                Ok(text) => unit.merge(&text),
+                Err(_) => unreadable.push(drop_in),
+            }
+        }
+        timers.push(Timer {
+            service: unit.unit.clone().unwrap_or_else(|| format!("{}.service", name.trim_end_matches(".timer"))),
+            source: path,
+            active: enabled.contains(&name),
+            next: None,
+            last_trigger: None,
+            unit,
+            name,
+        });
+    }
+    (timers, unreadable)
+}
+
+/// The command, user and last result of each service, from systemd or from the
+/// service files
+async fn services(transport: &dyn Transport, names: &[String], from_systemd: bool) -> HashMap<String, Service> {
+    let mut services = HashMap::new();
+    if from_systemd {
+        let mut args = vec!["show", "--no-pager", "-p", "Id,ExecStart,User,Result"];
+        args.extend(names.iter().map(String::as_str));
+        if let Ok(output) = transport.run("systemctl", &args).await {
+            for properties in parse_show(&output.stdout) {
+                let get = |key: &str| properties.iter().find(|(name, _)| name == key).map(|(_, value)| value.trim().to_string()).filter(|value| !value.is_empty());
+                // ExecStart is "{ path=/usr/bin/foo ; argv[]=/usr/bin/foo --bar ; ... }"
+                let command = get("ExecStart").and_then(|exec| exec.split("argv[]=").nth(1).and_then(|rest| rest.split(" ;").next()).map(|argv| argv.trim().to_string()));
+                if let Some(id) = get("Id") {
+                    services.insert(id, Service { command, user: get("User"), result: get("Result") });
+                }
+            }
+        }
+        return services;
+    }
+
+    for name in names {
+        for dir in UNIT_DIRS {
+            let Ok(text) = transport.read_file(&format!("{}/{}", dir, name)).await else {
+                continue;
+            };
+            let sections = parse_ini(&text);
+            let get = |key: &str| sections.iter()
+                .filter(|(section, _)| section == "Service")
+                .flat_map(|(_, entries)| entries)
+                .find(|(name, value)| name == key && !value.is_empty())
+                .map(|(_, value)| value.clone());
+            let command = get("ExecStart").map(|exec| exec.trim_start_matches(['-', '@', ':', '+', '!']).to_string());
+            services.insert(name.clone(), Service { command, user: get("User"), result: None });
+            break;
+        }
+    }
+    services
+}
+
+/// Every timer with what it runs, when, and how its last run went, and the unit files
+/// that could not be read
+pub(crate) async fn jobs(transport: &Arc<dyn Transport>, now: DateTime<FixedOffset>) -> (Vec<Job>, Vec<String>) {
+    let (timers, unreadable, running) = match from_systemd(transport.as_ref(), now).await {
+        Some(timers) => (timers, Vec::new(), true),
+        None => {
+            let (timers, unreadable) = from_files(transport.as_ref()).await;
+            (timers, unreadable, false)
+        }
+    };
+    let mut names: Vec<String> = timers.iter().map(|timer| timer.service.clone()).filter(|service| !service.is_empty()).collect();
+    names.sort();
+    names.dedup();
+    if names.is_empty() {
+        return (Vec::new(), unreadable);
+    }
+    let services = services(transport.as_ref(), &names, running).await;
+    let records = JournalReader::new(transport.clone())
+        .read(&JournalQuery { units: names, since: Some(HISTORY.to_string()), ..Default::default() })
+        .await
+        .unwrap_or_default();
+
+    let mut jobs = Vec::new();
+    for timer in timers {
+        let service = services.get(&timer.service).cloned().unwrap_or_default();
+        let mut schedule: Vec<String> = timer.unit.calendars.clone();
+        schedule.extend(timer.unit.monotonic.iter().cloned());
+        let schedule = if schedule.is_empty() { "no schedule".to_string() } else { schedule.join(", ") };
+        let command = service.command.clone().unwrap_or_else(|| timer.service.clone());
+
+        let mut job = Job::new(JobKind::Timer, &timer.name, &schedule, &command, &timer.source);
+        job.user = Some(service.user.clone().unwrap_or_else(|| "root".to_string()));
+        job.active = timer.active;
+        if timer.active {
+            // Later runs can only be worked out when every setting is a calendar this
+            // module understands
+            let calendars: Vec<Calendar> = timer.unit.calendars.iter().filter_map(|calendar| Calendar::on_calendar(calendar).ok()).collect();
+            if calendars.len() == timer.unit.calendars.len() && timer.unit.monotonic.is_empty() {
+                job.set_calendars(calendars, now);
+            }
+            // systemd's own next elapse includes randomized delays
+            if timer.next.is_some() {
+                job.next = timer.next;
+            }
+        }
+        job.last = parse_service_log(&records, &timer.service, *now.offset()).or_else(|| {
+            let at = timer.last_trigger?;
+            let result = service.result.clone();
+            Some(LastRun { at, success: result.as_deref().map(|result| result == "success"), detail: result.filter(|result| result != "success") })
+        });
+        jobs.push(job);
+    }
+    (jobs, unreadable)
+}
+
+/// Whether a command line needs a shell: pipes, redirections, variables and the like
+pub(crate) fn needs_shell(command: &str) -> bool {
+    command.contains(['|', '&', ';', '<', '>', '(', ')', '$', '`', '*', '?', '~'])
+}
+
+/// systemd expands "%" specifiers and "$" variables in ExecStart
+fn escape_exec(text: &str) -> String {
+    text.replace('%', "%%").replace('$', "$$")
+}
+
+/// A oneshot service that runs `command`, with its program resolved to `program`
+pub(crate) fn service_unit(description: &str, command: &str, program: &str, user: Option<&str>) -> String {
+    let exec = if needs_shell(command) {
+        format!("/bin/sh -c \"{}\"", escape_exec(&command.replace('\\', "\\\\").replace('"', "\\\"")))
+    } else {
+        let arguments = command.trim().split_once(char::is_whitespace).map(|(_, arguments)| arguments.trim()).unwrap_or_default();
+        escape_exec(format!("{} {}", program, arguments).trim_end())
+    };
+    let mut unit = format!("[Unit]\nDescription={}\n\n[Service]\nType=oneshot\n", description);
+    if let Some(user) = user {
+        unit.push_str(&format!("User={}\n", user));
+    }
+    unit.push_str(&format!("ExecStart={}\n", exec));
+    unit
+}
+
+/// A timer that starts the service of the same name
+pub(crate) fn timer_unit(description: &str, on_calendar: &str) -> String {
+    format!(
+        "[Unit]\nDescription=Timer for {}\n\n[Timer]\nOnCalendar={}\n# Run at the next boot when the machine was off at the scheduled time\nPersistent=true\n\n[Install]\nWantedBy=timers.target\n",
+        description, on_calendar,
+    )
+}
+
+/// A drop-in that replaces every schedule of a timer with `on_calendar`
+pub(crate) fn schedule_drop_in(on_calendar: &str) -> String {
+    format!("# Written by tuxpilot schedule edit\n[Timer]\nOnCalendar=\nOnCalendar={}\n", on_calendar)
+}
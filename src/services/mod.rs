@@ -0,0 +1,205 @@
+//! Service manager backends.
+//!
+//! Each backend wraps one init system's tools and returns typed service status, so
+//! the CLI, MCP tools and the state probe behave the same on systemd, OpenRC and
+//! SysVinit machines. Like the package backends, queries run through a transport and
+//! changes are only described as command lines for the executor to run.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::config::ServiceManager;
+use crate::remote::transport::Transport;
+
+pub mod openrc;
+pub mod systemd;
+pub mod sysvinit;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ActiveState {
+    Active,
+    Inactive,
+    Failed,
+    Activating,
+    Deactivating,
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EnabledState {
+    /// Started at boot
+    Enabled,
+    Disabled,
+    /// Only started as a dependency of other units
+    Static,
+    /// Cannot be started at all
+    Masked,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceStatus {
+    pub name: String,
+    pub description: Option<String>,
+    pub active: ActiveState,
+    /// The init system's own word for the state, such as "running", "exited" or "crashed"
+    pub sub_state: Option<String>,
+    pub enabled: EnabledState,
+    /// Main process of a running service
+    pub pid: Option<u32>,
+    /// Where the service starts: OpenRC runlevels, SysV runlevel numbers, or the
+    /// systemd targets that want it
+    pub runlevels: Vec<String>,
+}
+
+impl ServiceStatus {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            description: None,
+            active: ActiveState::Unknown,
+            sub_state: None,
+            enabled: EnabledState::Unknown,
+            pid: None,
+            runlevels: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for ActiveState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActiveState::Active => write!(f, "active"),
+            ActiveState::Inactive => write!(f, "inactive"),
+            ActiveState::Failed => write!(f, "failed"),
+            ActiveState::Activating => write!(f, "activating"),
+            ActiveState::Deactivating => write!(f, "deactivating"),
+            ActiveState::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+impl fmt::Display for EnabledState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnabledState::Enabled => write!(f, "enabled"),
+            EnabledState::Disabled => write!(f, "disabled"),
+            EnabledState::Static => write!(f, "static"),
+            EnabledState::Masked => write!(f, "masked"),
+            EnabledState::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// "nginx: active (running), enabled, PID 812, runlevels: default - A web server"
+impl fmt::Display for ServiceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.active)?;
+        if let Some(sub_state) = &self.sub_state {
+            write!(f, " ({})", sub_state)?;
+        }
+        write!(f, ", {}", self.enabled)?;
+        if let Some(pid) = self.pid {
+            write!(f, ", PID {}", pid)?;
+        }
+        if !self.runlevels.is_empty() {
+            write!(f, ", runlevels: {}", self.runlevels.join(" "))?;
+        }
+        if let Some(description) = &self.description {
+            write!(f, " - {}", description)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ServiceAction {
+    Status,
+    Start,
+    Stop,
+    Restart,
+    Reload,
+    Enable,
+    Disable,
+}
+
+impl ServiceAction {
+    pub fn needs_root(&self) -> bool {
+        *self != ServiceAction::Status
+    }
+}
+
+impl FromStr for ServiceAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "status" => Ok(ServiceAction::Status),
+            "start" => Ok(ServiceAction::Start),
+            "stop" => Ok(ServiceAction::Stop),
+            "restart" => Ok(ServiceAction::Restart),
+            "reload" => Ok(ServiceAction::Reload),
+            "enable" => Ok(ServiceAction::Enable),
+            "disable" => Ok(ServiceAction::Disable),
+            _ => Err(anyhow::anyhow!(
+                "Unknown service action '{}'. Use status, start, stop, restart, reload, enable or disable", s
+            )),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+pub trait ServiceBackend: std::fmt::Debug + Send + Sync {
+    fn manager(&self) -> ServiceManager;
+
+    /// Status of one service, `None` when there is no such service
+    async fn status(&self, name: &str) -> Result<Option<ServiceStatus>>;
+
+    /// Every service the init system knows about. PIDs are left out where finding
+    /// them would take a command per service.
+    async fn list(&self) -> Result<Vec<ServiceStatus>>;
+
+    /// Command line for an action, without a sudo prefix
+    fn command(&self, name: &str, action: &ServiceAction) -> Vec<String>;
+
+    /// The init system's own status report, for people and the AI to read
+    async fn describe(&self, name: &str) -> Result<String>;
+}
+
+/// Backend for `manager` that runs its queries through `transport`
+pub fn backend(manager: &ServiceManager, transport: Arc<dyn Transport>) -> Box<dyn ServiceBackend> {
+    match manager {
+        ServiceManager::Systemd => Box::new(systemd::Systemd::new(transport)),
+        ServiceManager::OpenRC => Box::new(openrc::OpenRc::new(transport)),
+        ServiceManager::SysVInit => Box::new(sysvinit::SysVinit::new(transport)),
+    }
+}
+
+/// `program` followed by `args`, as owned words
+fn words(program: &str, args: &[&str]) -> Vec<String> {
+    std::iter::once(program).chain(args.iter().copied()).map(String::from).collect()
+}
+
+/// Run a command in the C locale and return stdout whatever the exit code; status
+/// commands report a stopped service through their exit code
+async fn output(transport: &dyn Transport, program: &str, args: &[&str]) -> Result<(Option<i32>, String)> {
+    let mut command = vec!["LC_ALL=C", program];
+    command.extend_from_slice(args);
+    let output = transport.run("env", &command).await?;
+    Ok((output.exit_code, output.stdout))
+}
+
+/// PID from a daemon's pid file, the convention OpenRC and SysV scripts share
+async fn pid_from_file(transport: &dyn Transport, name: &str) -> Option<u32> {
+    for path in [format!("/run/{}.pid", name), format!("/var/run/{}.pid", name), format!("/run/{}/{}.pid", name, name)] {
+        if let Ok(content) = transport.read_file(&path).await {
+            if let Ok(pid) = content.trim().parse() {
+                return Some(pid);
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,155 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::config::ServiceManager;
+use crate::remote::transport::Transport;
+use super::{output, pid_from_file, words};
+use super::{ActiveState, EnabledState, ServiceAction, ServiceBackend, ServiceStatus};
+
+/// Runlevel new services are added to
+const DEFAULT_RUNLEVEL: &str = "default";
+
+#[derive(Debug, Clone)]
+pub struct OpenRc {
+    transport: Arc<dyn Transport>,
+}
+
+impl OpenRc {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    /// Runlevels by service for every init script, including ones in no runlevel
+    async fn runlevels(&self) -> Result<BTreeMap<String, Vec<String>>> {
+        let (exit_code, stdout) = output(self.transport.as_ref(), "rc-update", &["show", "-v"]).await?;
+        if exit_code != Some(0) {
+            return Err(anyhow::anyhow!("rc-update show failed on {}", self.transport.host_name()));
+        }
+
+        // "            sshd | default" and "          agetty |" for scripts in no runlevel
+        Ok(stdout.lines()
+            .filter_map(|line| {
+                let (name, runlevels) = line.split_once('|')?;
+                let name = name.trim();
+                (!name.is_empty()).then(|| (name.to_string(), runlevels.split_whitespace().map(String::from).collect()))
+            })
+            .collect())
+    }
+
+    /// The description line OpenRC scripts carry, `description="..."`
+    async fn description(&self, name: &str) -> Option<String> {
+        let script = self.transport.read_file(&format!("/etc/init.d/{}", name)).await.ok()?;
+        script.lines()
+            .find_map(|line| line.trim().strip_prefix("description="))
+            .map(|value| value.trim_matches(|c| c == '"' || c == '\'').to_string())
+            .filter(|value| !value.is_empty())
+    }
+}
+
+/// State words from `rc-service status` and `rc-status`
+fn active_state(state: &str) -> ActiveState {
+    match state {
+        "started" => ActiveState::Active,
+        "stopped" | "inactive" => ActiveState::Inactive,
+        "crashed" | "failed" => ActiveState::Failed,
+        "starting" | "scheduled" => ActiveState::Activating,
+        "stopping" => ActiveState::Deactivating,
+        _ => ActiveState::Unknown,
+    }
+}
+
+fn enabled_state(runlevels: &[String]) -> EnabledState {
+    if runlevels.is_empty() {
+        EnabledState::Disabled
+    } else {
+        EnabledState::Enabled
+    }
+}
+
+#[async_trait::async_trait]
+impl ServiceBackend for OpenRc {
+    fn manager(&self) -> ServiceManager {
+        ServiceManager::OpenRC
+    }
+
+    async fn status(&self, name: &str) -> Result<Option<ServiceStatus>> {
+        if !self.transport.path_exists(&format!("/etc/init.d/{}", name)).await {
+            return Ok(None);
+        }
+
+        // " * status: started", exiting with 3 when stopped and 32 when crashed
+        let (_, stdout) = output(self.transport.as_ref(), "rc-service", &[name, "status"]).await?;
+        let state = stdout.lines()
+            .find_map(|line| line.split_once("status:"))
+            .map(|(_, state)| state.trim().to_string());
+
+        let mut status = ServiceStatus::new(name);
+        status.description = self.description(name).await;
+        status.active = state.as_deref().map_or(ActiveState::Unknown, active_state);
+        status.sub_state = state;
+        status.runlevels = self.runlevels().await?.remove(name).unwrap_or_default();
+        status.enabled = enabled_state(&status.runlevels);
+        if status.active == ActiveState::Active {
+            status.pid = pid_from_file(self.transport.as_ref(), name).await;
+        }
+        Ok(Some(status))
+    }
+
+    async fn list(&self) -> Result<Vec<ServiceStatus>> {
+        let mut services: BTreeMap<String, ServiceStatus> = self.runlevels().await?
+            .into_iter()
+            .map(|(name, runlevels)| {
+                let mut status = ServiceStatus::new(&name);
+                status.enabled = enabled_state(&runlevels);
+                status.runlevels = runlevels;
+                (name, status)
+            })
+            .collect();
+
+        // "Runlevel: default" headers, then " sshd   [  started 01:02:03 (0) ]"
+        let (_, stdout) = output(self.transport.as_ref(), "rc-status", &["--all", "--nocolor"]).await?;
+        for line in stdout.lines() {
+            let Some((name, state)) = line.split_once('[') else {
+                continue;
+            };
+            let state = state.trim_end_matches(|c: char| c == ']' || c.is_whitespace());
+            let Some(word) = state.split_whitespace().next() else {
+                continue;
+            };
+            let name = name.trim();
+            let status = services.entry(name.to_string()).or_insert_with(|| ServiceStatus::new(name));
+            status.active = active_state(word);
+            status.sub_state = Some(word.to_string());
+        }
+
+        Ok(services.into_values()
+            .map(|mut status| {
+                // Scripts rc-status never mentioned have not been started
+                if status.active == ActiveState::Unknown {
+                    status.active = ActiveState::Inactive;
+                }
+                status
+            })
+            .collect())
+    }
+
+    fn command(&self, name: &str, action: &ServiceAction) -> Vec<String> {
+        match action {
+            ServiceAction::Status => words("rc-service", &[name, "status"]),
+            ServiceAction::Start => words("rc-service", &[name, "start"]),
+            ServiceAction::Stop => words("rc-service", &[name, "stop"]),
+            ServiceAction::Restart => words("rc-service", &[name, "restart"]),
+            ServiceAction::Reload => words("rc-service", &[name, "reload"]),
+            ServiceAction::Enable => words("rc-update", &["add", name, DEFAULT_RUNLEVEL]),
+            ServiceAction::Disable => words("rc-update", &["del", name, DEFAULT_RUNLEVEL]),
+        }
+    }
+
+    async fn describe(&self, name: &str) -> Result<String> {
+        let (_, stdout) = output(self.transport.as_ref(), "rc-service", &[name, "status"]).await?;
+        let runlevels = self.runlevels().await?.remove(name).unwrap_or_default();
+        Ok(format!("{}\nrunlevels: {}\n", stdout.trim_end(),
+            if runlevels.is_empty() { "none".to_string() } else { runlevels.join(" ") }))
+    }
+}
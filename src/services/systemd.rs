@@ -0,0 +1,162 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::config::ServiceManager;
+use crate::remote::transport::Transport;
+use super::{output, words};
+use super::{ActiveState, EnabledState, ServiceAction, ServiceBackend, ServiceStatus};
+
+const PROPERTIES: &str = "--property=Id,Description,LoadState,ActiveState,SubState,UnitFileState,MainPID,WantedBy";
+
+#[derive(Debug, Clone)]
+pub struct Systemd {
+    transport: Arc<dyn Transport>,
+}
+
+impl Systemd {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    async fn systemctl(&self, args: &[&str]) -> Result<String> {
+        let (exit_code, stdout) = output(self.transport.as_ref(), "systemctl", args).await?;
+        match exit_code {
+            Some(0) => Ok(stdout),
+            _ => Err(anyhow::anyhow!("systemctl {} failed on {}", args.join(" "), self.transport.host_name())),
+        }
+    }
+}
+
+/// "nginx" and "nginx.service" name the same unit
+fn unit_name(name: &str) -> String {
+    if name.contains('.') {
+        name.to_string()
+    } else {
+        format!("{}.service", name)
+    }
+}
+
+fn service_name(unit: &str) -> String {
+    unit.strip_suffix(".service").unwrap_or(unit).to_string()
+}
+
+fn active_state(state: &str) -> ActiveState {
+    match state {
+        "active" | "reloading" => ActiveState::Active,
+        "inactive" => ActiveState::Inactive,
+        "failed" => ActiveState::Failed,
+        "activating" => ActiveState::Activating,
+        "deactivating" => ActiveState::Deactivating,
+        _ => ActiveState::Unknown,
+    }
+}
+
+fn enabled_state(state: &str) -> EnabledState {
+    match state {
+        "enabled" | "enabled-runtime" | "alias" => EnabledState::Enabled,
+        "disabled" => EnabledState::Disabled,
+        "static" | "indirect" | "generated" => EnabledState::Static,
+        "masked" | "masked-runtime" => EnabledState::Masked,
+        _ => EnabledState::Unknown,
+    }
+}
+
+#[async_trait::async_trait]
+impl ServiceBackend for Systemd {
+    fn manager(&self) -> ServiceManager {
+        ServiceManager::Systemd
+    }
+
+    async fn status(&self, name: &str) -> Result<Option<ServiceStatus>> {
+        let unit = unit_name(name);
+        let properties: HashMap<String, String> = self.systemctl(&["show", &unit, PROPERTIES, "--no-pager"]).await?
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let property = |key: &str| properties.get(key).map(String::as_str).filter(|value| !value.is_empty());
+
+        // `systemctl show` succeeds for units that do not exist
+        if property("LoadState").is_none_or(|state| state == "not-found") {
+            return Ok(None);
+        }
+
+        let mut status = ServiceStatus::new(&service_name(property("Id").unwrap_or(&unit)));
+        status.description = property("Description").map(String::from);
+        status.active = property("ActiveState").map_or(ActiveState::Unknown, active_state);
+        status.sub_state = property("SubState").map(String::from);
+        status.enabled = property("UnitFileState").map_or(EnabledState::Unknown, enabled_state);
+        status.pid = property("MainPID").and_then(|pid| pid.parse().ok()).filter(|pid| *pid != 0);
+        status.runlevels = property("WantedBy")
+            .map(|targets| targets.split_whitespace().map(String::from).collect())
+            .unwrap_or_default();
+        Ok(Some(status))
+    }
+
+    async fn list(&self) -> Result<Vec<ServiceStatus>> {
+        // "UNIT LOAD ACTIVE SUB DESCRIPTION" for loaded units
+        let mut services: Vec<ServiceStatus> = self.systemctl(&["list-units", "--type=service", "--all", "--no-legend", "--no-pager", "--plain"]).await?
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let unit = fields.next()?;
+                let load = fields.next()?;
+                if load == "not-found" {
+                    return None;
+                }
+                let mut status = ServiceStatus::new(&service_name(unit));
+                status.active = active_state(fields.next()?);
+                status.sub_state = fields.next().map(String::from);
+                let description = fields.collect::<Vec<_>>().join(" ");
+                status.description = Some(description).filter(|d| !d.is_empty());
+                Some(status)
+            })
+            .collect();
+
+        // "UNIT STATE [PRESET]" for every installed unit file, loaded or not
+        let unit_files = self.systemctl(&["list-unit-files", "--type=service", "--no-legend", "--no-pager"]).await?;
+        for line in unit_files.lines() {
+            let mut fields = line.split_whitespace();
+            let (Some(unit), Some(state)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            let name = service_name(unit);
+            // Template units such as "getty@.service" are not services by themselves
+            if name.ends_with('@') {
+                continue;
+            }
+            match services.iter_mut().find(|status| status.name == name) {
+                Some(status) => status.enabled = enabled_state(state),
+                None => {
+                    let mut status = ServiceStatus::new(&name);
+                    status.active = ActiveState::Inactive;
+                    status.enabled = enabled_state(state);
+                    services.push(status);
+                }
+            }
+        }
+
+        services.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(services)
+    }
+
+    fn command(&self, name: &str, action: &ServiceAction) -> Vec<String> {
+        let verb = match action {
+            ServiceAction::Status => "status",
+            ServiceAction::Start => "start",
+            ServiceAction::Stop => "stop",
+            ServiceAction::Restart => "restart",
+            ServiceAction::Reload => "reload",
+            ServiceAction::Enable => "enable",
+            ServiceAction::Disable => "disable",
+        };
+        words("systemctl", &[verb, name])
+    }
+
+    async fn describe(&self, name: &str) -> Result<String> {
+        // Exits with 3 for stopped units, which still have a useful report
+        let (_, stdout) = output(self.transport.as_ref(), "systemctl", &["status", name, "--no-pager", "-n", "20"]).await?;
+        Ok(stdout)
+    }
+}
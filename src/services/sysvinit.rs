@@ -0,0 +1,169 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::config::ServiceManager;
+use crate::remote::transport::Transport;
+use super::{output, pid_from_file, words};
+use super::{ActiveState, EnabledState, ServiceAction, ServiceBackend, ServiceStatus};
+
+const INIT_DIR: &str = "/etc/init.d";
+/// Files in the init script directory that are not services
+const NOT_SERVICES: &[&str] = &["README", "skeleton", "functions", "rc", "rcS", "rc.local", "halt", "reboot", "single"];
+
+#[derive(Debug, Clone)]
+pub struct SysVinit {
+    transport: Arc<dyn Transport>,
+    /// Red Hat and SUSE style systems manage start links with `chkconfig`, Debian
+    /// style systems with `update-rc.d`
+    chkconfig: bool,
+}
+
+impl SysVinit {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport, chkconfig: false }
+    }
+
+    pub fn with_chkconfig(mut self, chkconfig: bool) -> Self {
+        self.chkconfig = chkconfig;
+        self
+    }
+
+    /// Runlevels by service, from the start links in the rc directories
+    async fn start_links(&self) -> Result<BTreeMap<String, Vec<String>>> {
+        let (_, stdout) = output(self.transport.as_ref(), "sh", &["-c", "ls -1d /etc/rc?.d/S* /etc/rc.d/rc?.d/S* 2>/dev/null"]).await?;
+
+        // "/etc/rc2.d/S01ssh"
+        let mut links: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for path in stdout.lines() {
+            let mut components = path.rsplit('/');
+            let (Some(link), Some(directory)) = (components.next(), components.next()) else {
+                continue;
+            };
+            let Some(runlevel) = directory.strip_prefix("rc").and_then(|d| d.strip_suffix(".d")) else {
+                continue;
+            };
+            let name = link.trim_start_matches('S').trim_start_matches(|c: char| c.is_ascii_digit());
+            if name.is_empty() {
+                continue;
+            }
+            let runlevels = links.entry(name.to_string()).or_default();
+            if !runlevels.iter().any(|known| known == runlevel) {
+                runlevels.push(runlevel.to_string());
+            }
+        }
+        Ok(links)
+    }
+}
+
+/// Names end up in a shell command line and in paths, so only allow what init scripts use
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.@+".contains(c))
+}
+
+fn enabled_state(runlevels: &[String]) -> EnabledState {
+    if runlevels.is_empty() {
+        EnabledState::Disabled
+    } else {
+        EnabledState::Enabled
+    }
+}
+
+#[async_trait::async_trait]
+impl ServiceBackend for SysVinit {
+    fn manager(&self) -> ServiceManager {
+        ServiceManager::SysVInit
+    }
+
+    async fn status(&self, name: &str) -> Result<Option<ServiceStatus>> {
+        if !valid_name(name) || !self.transport.path_exists(&format!("{}/{}", INIT_DIR, name)).await {
+            return Ok(None);
+        }
+
+        // LSB exit codes: 0 running, 1 dead with a pid file, 2 dead with a lock file,
+        // 3 not running, 4 unknown
+        let (exit_code, _) = output(self.transport.as_ref(), "service", &[name, "status"]).await?;
+        let mut status = ServiceStatus::new(name);
+        (status.active, status.sub_state) = match exit_code {
+            Some(0) => (ActiveState::Active, Some("running".to_string())),
+            Some(1) | Some(2) => (ActiveState::Failed, Some("dead".to_string())),
+            Some(3) => (ActiveState::Inactive, Some("stopped".to_string())),
+            _ => (ActiveState::Unknown, None),
+        };
+        status.runlevels = self.start_links().await?.remove(name).unwrap_or_default();
+        status.enabled = enabled_state(&status.runlevels);
+
+        if status.active == ActiveState::Active {
+            status.pid = match pid_from_file(self.transport.as_ref(), name).await {
+                Some(pid) => Some(pid),
+                None => {
+                    let (_, stdout) = output(self.transport.as_ref(), "pidof", &["-s", name]).await?;
+                    stdout.trim().parse().ok()
+                }
+            };
+        }
+        Ok(Some(status))
+    }
+
+    async fn list(&self) -> Result<Vec<ServiceStatus>> {
+        let links = self.start_links().await?;
+        let mut services: BTreeMap<String, ServiceStatus> = BTreeMap::new();
+
+        // Debian's service prints " [ + ]  ssh", with "-" for stopped and "?" when the
+        // script has no status action
+        let (_, stdout) = output(self.transport.as_ref(), "service", &["--status-all"]).await?;
+        for line in stdout.lines() {
+            let Some(rest) = line.trim().strip_prefix('[') else {
+                continue;
+            };
+            let Some((state, name)) = rest.split_once(']') else {
+                continue;
+            };
+            let mut status = ServiceStatus::new(name.trim());
+            status.active = match state.trim() {
+                "+" => ActiveState::Active,
+                "-" => ActiveState::Inactive,
+                _ => ActiveState::Unknown,
+            };
+            services.insert(status.name.clone(), status);
+        }
+
+        // Other implementations run every script's status action and print free text,
+        // so fall back to the scripts themselves
+        if services.is_empty() {
+            let (_, stdout) = output(self.transport.as_ref(), "ls", &["-1", INIT_DIR]).await?;
+            for name in stdout.lines().map(str::trim).filter(|name| valid_name(name) && !NOT_SERVICES.contains(name)) {
+                services.insert(name.to_string(), ServiceStatus::new(name));
+            }
+        }
+
+        Ok(services.into_values()
+            .map(|mut status| {
+                status.runlevels = links.get(&status.name).cloned().unwrap_or_default();
+                status.enabled = enabled_state(&status.runlevels);
+                status
+            })
+            .collect())
+    }
+
+    fn command(&self, name: &str, action: &ServiceAction) -> Vec<String> {
+        match action {
+            ServiceAction::Status => words("service", &[name, "status"]),
+            ServiceAction::Start => words("service", &[name, "start"]),
+            ServiceAction::Stop => words("service", &[name, "stop"]),
+            ServiceAction::Restart => words("service", &[name, "restart"]),
+            ServiceAction::Reload => words("service", &[name, "reload"]),
+            ServiceAction::Enable if self.chkconfig => words("chkconfig", &[name, "on"]),
+            ServiceAction::Disable if self.chkconfig => words("chkconfig", &[name, "off"]),
+            ServiceAction::Enable => words("update-rc.d", &[name, "enable"]),
+            ServiceAction::Disable => words("update-rc.d", &[name, "disable"]),
+        }
+    }
+
+    async fn describe(&self, name: &str) -> Result<String> {
+        let (_, stdout) = output(self.transport.as_ref(), "service", &[name, "status"]).await?;
+        let runlevels = self.start_links().await?.remove(name).unwrap_or_default();
+        Ok(format!("{}\nstarted in runlevels: {}\n", stdout.trim_end(),
+            if runlevels.is_empty() { "none".to_string() } else { runlevels.join(" ") }))
+    }
+}
@@ -0,0 +1,324 @@
+//! Session recording and replay.
+//!
+//! With `--record <archive>` every external command run through a transport, the
+//! executor or the privileged helper is written to a session archive together with its
+//! output and exit code, along with every AI exchange and every answer given to an
+//! approval prompt. `--replay <archive>` substitutes those recordings for real
+//! execution, provider calls and prompts, so a user's run can be reproduced exactly on
+//! another machine.
+//!
+//! The archive is JSON Lines: a header followed by one event per line, so a recording
+//! cut short by a crash is still usable.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::os::unix::fs::OpenOptionsExt;
+use std::future::Future;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::remote::transport::CommandOutput;
+
+const ARCHIVE_VERSION: u32 = 1;
+
+static SESSION: OnceLock<Session> = OnceLock::new();
+
+/// Host, program and arguments of a recorded command
+type CommandKey = (String, String, Vec<String>);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionHeader {
+    pub version: u32,
+    pub recorded_at: DateTime<Utc>,
+    pub tuxpilot_version: String,
+    pub hostname: String,
+    /// Command line of the recorded run
+    pub arguments: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum SessionEvent {
+    Command {
+        host: String,
+        program: String,
+        args: Vec<String>,
+        exit_code: Option<i32>,
+        stdout: String,
+        stderr: String,
+        duration_ms: u64,
+        /// Set when the command could not be run at all
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    AiExchange {
+        provider: String,
+        system_prompt: String,
+        user_prompt: String,
+        response: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    Decision {
+        prompt: String,
+        choice: usize,
+    },
+}
+
+/// A loaded session archive
+#[derive(Debug, Clone)]
+pub struct SessionArchive {
+    pub header: SessionHeader,
+    pub events: Vec<SessionEvent>,
+}
+
+#[derive(Debug)]
+enum Session {
+    Record(Mutex<File>),
+    Replay(Player),
+}
+
+/// Recorded events waiting to be handed out during replay. Commands are matched by
+/// host and command line, so concurrent probes may run in any order; AI exchanges and
+/// prompt answers are handed out in recording order.
+#[derive(Debug, Default)]
+struct Player {
+    commands: Mutex<HashMap<CommandKey, VecDeque<SessionEvent>>>,
+    ai_exchanges: Mutex<VecDeque<SessionEvent>>,
+    decisions: Mutex<VecDeque<SessionEvent>>,
+}
+
+impl SessionArchive {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read session archive: {:?}", path))?;
+        let mut lines = content.lines().filter(|line| !line.trim().is_empty());
+
+        let header: SessionHeader = serde_json::from_str(lines.next().context("Session archive is empty")?)
+            .context("Failed to parse session archive header")?;
+        if header.version > ARCHIVE_VERSION {
+            return Err(anyhow::anyhow!(
+                "Session archive version {} is newer than this TuxPilot supports ({})",
+                header.version, ARCHIVE_VERSION
+            ));
+        }
+
+        let events = lines.enumerate()
+            .map(|(i, line)| serde_json::from_str(line)
+                .with_context(|| format!("Failed to parse session event {}", i + 1)))
+            .collect::<Result<Vec<SessionEvent>>>()?;
+
+        Ok(Self { header, events })
+    }
+}
+
+/// Start recording this run into `path`. Must be called once, before any command runs.
+pub fn start_recording(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).context("Failed to create session archive directory")?;
+    }
+    // Archives hold command output and AI exchanges, so only the owner may read them
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .with_context(|| format!("Failed to create session archive: {:?}", path))?;
+
+    let header = SessionHeader {
+        version: ARCHIVE_VERSION,
+        recorded_at: Utc::now(),
+        tuxpilot_version: env!("CARGO_PKG_VERSION").to_string(),
+        hostname: gethostname::gethostname().to_string_lossy().to_string(),
+        arguments: std::env::args().collect(),
+    };
+    writeln!(file, "{}", serde_json::to_string(&header)?).context("Failed to write session archive")?;
+
+    install(Session::Record(Mutex::new(file)))
+}
+
+/// Replay the recordings in `path` instead of running commands and calling providers
+pub fn start_replay(path: &Path) -> Result<()> {
+    let archive = SessionArchive::load(path)?;
+    let player = Player::default();
+
+    for event in archive.events {
+        match &event {
+            SessionEvent::Command { host, program, args, .. } => {
+                let key = (host.clone(), program.clone(), args.clone());
+                player.commands.lock().unwrap().entry(key).or_default().push_back(event);
+            }
+            SessionEvent::AiExchange { .. } => player.ai_exchanges.lock().unwrap().push_back(event),
+            SessionEvent::Decision { .. } => player.decisions.lock().unwrap().push_back(event),
+        }
+    }
+
+    install(Session::Replay(player))
+}
+
+fn install(session: Session) -> Result<()> {
+    SESSION.set(session).map_err(|_| anyhow::anyhow!("A session is already being recorded or replayed"))
+}
+
+pub fn is_replaying() -> bool {
+    matches!(SESSION.get(), Some(Session::Replay(_)))
+}
+
+/// Run an external command through the active session: recorded when recording,
+/// answered from the archive when replaying, and simply run otherwise.
+pub async fn command<F>(host: &str, program: &str, args: &[&str], run: F) -> Result<CommandOutput>
+where
+    F: Future<Output = Result<CommandOutput>>,
+{
+    let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+
+    match SESSION.get() {
+        None => run.await,
+        Some(Session::Record(file)) => {
+            let result = run.await;
+            let event = match &result {
+                Ok(output) => SessionEvent::Command {
+                    host: host.to_string(),
+                    program: program.to_string(),
+                    args,
+                    exit_code: output.exit_code,
+                    stdout: output.stdout.clone(),
+                    stderr: output.stderr.clone(),
+                    duration_ms: output.duration.as_millis() as u64,
+                    error: None,
+                },
+                Err(e) => SessionEvent::Command {
+                    host: host.to_string(),
+                    program: program.to_string(),
+                    args,
+                    exit_code: None,
+                    stdout: String::new(),
+                    stderr: String::new(),
+                    duration_ms: 0,
+                    error: Some(format!("{:#}", e)),
+                },
+            };
+            append(file, &event)?;
+            result
+        }
+        Some(Session::Replay(player)) => {
+            let key = (host.to_string(), program.to_string(), args);
+            let event = player.commands.lock().unwrap()
+                .get_mut(&key)
+                .and_then(VecDeque::pop_front)
+                .ok_or_else(|| anyhow::anyhow!(
+                    "Session replay has no recording of `{} {}` on {}",
+                    program, key.2.join(" "), host
+                ))?;
+
+            match event {
+                SessionEvent::Command { error: Some(error), .. } => Err(anyhow::anyhow!(error)),
+                SessionEvent::Command { exit_code, stdout, stderr, duration_ms, .. } => Ok(CommandOutput {
+                    exit_code,
+                    stdout,
+                    stderr,
+                    duration: Duration::from_millis(duration_ms),
+                }),
+                _ => unreachable!("only command events are queued by command line"),
+            }
+        }
+    }
+}
+
+/// Send a prompt to an AI provider through the active session
+pub async fn ai_exchange<F>(provider: &str, system_prompt: &str, user_prompt: &str, send: F) -> Result<String>
+where
+    F: Future<Output = Result<String>>,
+{
+    match SESSION.get() {
+        None => send.await,
+        Some(Session::Record(file)) => {
+            let result = send.await;
+            append(file, &SessionEvent::AiExchange {
+                provider: provider.to_string(),
+                system_prompt: system_prompt.to_string(),
+                user_prompt: user_prompt.to_string(),
+                response: result.as_ref().ok().cloned(),
+                error: result.as_ref().err().map(|e| format!("{:#}", e)),
+            })?;
+            result
+        }
+        Some(Session::Replay(player)) => {
+            let event = player.ai_exchanges.lock().unwrap()
+                .pop_front()
+                .context("Session replay has no more recorded AI exchanges")?;
+
+            match event {
+                SessionEvent::AiExchange { user_prompt: recorded, response, error, .. } => {
+                    if recorded != user_prompt {
+                        log::warn!("Replayed AI exchange was recorded for a different prompt");
+                    }
+                    match (response, error) {
+                        (Some(response), _) => Ok(response),
+                        (None, error) => Err(anyhow::anyhow!(error.unwrap_or_else(|| "AI request failed".to_string()))),
+                    }
+                }
+                _ => unreachable!("only AI exchanges are queued as AI exchanges"),
+            }
+        }
+    }
+}
+
+/// Ask the user to choose through the active session. `ask` resolves to the index of
+/// the chosen option; during replay the recorded answer is used without asking.
+pub async fn decision<F>(prompt: &str, ask: F) -> Result<usize>
+where
+    F: Future<Output = Result<usize>>,
+{
+    match SESSION.get() {
+        None => ask.await,
+        Some(Session::Record(file)) => {
+            let choice = ask.await?;
+            append(file, &SessionEvent::Decision { prompt: prompt.to_string(), choice })?;
+            Ok(choice)
+        }
+        Some(Session::Replay(player)) => {
+            match player.decisions.lock().unwrap().pop_front() {
+                Some(SessionEvent::Decision { choice, .. }) => Ok(choice),
+                _ => Err(anyhow::anyhow!("Session replay has no recorded answer for: {}", prompt)),
+            }
+        }
+    }
+}
+
+fn append(file: &Mutex<File>, event: &SessionEvent) -> Result<()> {
+    let line = serde_json::to_string(event)?;
+    let mut file = file.lock().unwrap();
+    writeln!(file, "{}", line).context("Failed to write session archive")?;
+    file.flush().context("Failed to write session archive")
+}
+
+impl std::fmt::Display for SessionEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionEvent::Command { host, program, args, exit_code, error, duration_ms, .. } => {
+                let status = match (error, exit_code) {
+                    (Some(error), _) => format!("error: {}", error),
+                    (None, Some(code)) => format!("exit {}", code),
+                    (None, None) => "killed".to_string(),
+                };
+                let command_line: Vec<&str> = std::iter::once(program).chain(args).map(String::as_str).collect();
+                write!(f, "🔧 [{}] {} ({}, {}ms)", host, command_line.join(" "), status, duration_ms)
+            }
+            SessionEvent::AiExchange { provider, user_prompt, error, .. } => {
+                let prompt: String = user_prompt.chars().take(60).collect();
+                let status = if error.is_some() { "failed" } else { "answered" };
+                write!(f, "🤖 [{}] {} ({})", provider, prompt.replace('\n', " "), status)
+            }
+            SessionEvent::Decision { prompt, choice } => {
+                write!(f, "🙋 {} → option {}", prompt, choice)
+            }
+        }
+    }
+}
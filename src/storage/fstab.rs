@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::remote::transport::Transport;
+use super::{Area, Finding, Severity};
+
+pub const FSTAB: &str = "/etc/fstab";
+
+/// Option pairs that contradict each other
+const CONFLICTING_OPTIONS: &[(&str, &str)] = &[
+    ("ro", "rw"),
+    ("auto", "noauto"),
+    ("exec", "noexec"),
+    ("suid", "nosuid"),
+    ("dev", "nodev"),
+    ("sync", "async"),
+    ("atime", "noatime"),
+    ("user", "nouser"),
+];
+/// Options a filesystem refuses to mount with
+const UNSUPPORTED_OPTIONS: &[(&str, &[&str])] = &[
+    ("xfs", &["errors=", "data=", "barrier", "nobarrier", "commit="]),
+    ("btrfs", &["errors=", "data=", "barrier=", "nobarrier=", "journal_checksum"]),
+    ("vfat", &["data=", "commit=", "acl", "user_xattr"]),
+];
+/// `tag=` device references and the /dev/disk directory that resolves them
+const TAGS: &[(&str, &str)] = &[
+    ("UUID", "/dev/disk/by-uuid"),
+    ("LABEL", "/dev/disk/by-label"),
+    ("PARTUUID", "/dev/disk/by-partuuid"),
+    ("PARTLABEL", "/dev/disk/by-partlabel"),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FstabEntry {
+    pub line: usize,
+    pub source: String,
+    pub mount_point: String,
+    pub fs_type: String,
+    pub options: Vec<String>,
+    pub dump: String,
+    pub pass: String,
+}
+
+impl FstabEntry {
+    fn has_option(&self, option: &str) -> bool {
+        self.options.iter().any(|existing| existing == option)
+    }
+
+    /// Whether boot goes on when the filesystem cannot be mounted
+    pub fn optional(&self) -> bool {
+        self.has_option("nofail") || self.has_option("noauto")
+    }
+
+    fn subject(&self) -> String {
+        format!("{} line {} ({})", FSTAB, self.line, self.mount_point)
+    }
+}
+
+/// Entries of /etc/fstab, and the line numbers of lines that are not valid entries
+pub async fn read(transport: &dyn Transport) -> (Vec<FstabEntry>, Vec<usize>) {
+    transport.read_file(FSTAB).await.map(|fstab| parse(&fstab)).unwrap_or_default()
+}
+
+pub(crate) fn parse(fstab: &str) -> (Vec<FstabEntry>, Vec<usize>) {
+    let mut entries = Vec::new();
+    let mut invalid = Vec::new();
+    for (index, line) in fstab.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // Spaces in fields are written as \040
+        let fields: Vec<String> = line.split_whitespace().map(|field| field.replace("\\040", " ")).collect();
+        if fields.len() < 3 || fields.len() > 6 {
+            invalid.push(index + 1);
+            continue;
+        }
+        let field = |position: usize, default: &str| fields.get(position).cloned().unwrap_or_else(|| default.to_string());
+        entries.push(FstabEntry {
+            line: index + 1,
+            source: fields[0].clone(),
+            mount_point: fields[1].clone(),
+            fs_type: fields[2].clone(),
+            options: field(3, "defaults").split(',').map(String::from).collect(),
+            dump: field(4, "0"),
+            pass: field(5, "0"),
+        });
+    }
+    (entries, invalid)
+}
+
+/// Device node an fstab source refers to, `None` for network, pseudo and other
+/// sources that are not block devices
+fn device_path(source: &str) -> Option<String> {
+    if let Some((tag, value)) = source.split_once('=') {
+        let value = value.trim_matches('"');
+        return TAGS.iter().find(|(name, _)| *name == tag).map(|(_, dir)| format!("{}/{}", dir, value.replace('/', "\\x2f")));
+    }
+    source.starts_with("/dev/").then(|| source.to_string())
+}
+
+/// Problems with the entries in /etc/fstab: devices that no longer exist, options that
+/// contradict each other or that the filesystem does not accept, and fields mount does
+/// not understand
+pub async fn check(transport: &dyn Transport, entries: &[FstabEntry], invalid_lines: &[usize]) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = invalid_lines.iter()
+        .map(|line| Finding::new(Area::Fstab, Severity::Critical, format!("{} line {}", FSTAB, line),
+            "is not a valid entry; it needs a source, mount point, type and up to three more fields"))
+        .collect();
+
+    let mut mount_points: HashMap<&str, usize> = HashMap::new();
+    for entry in entries {
+        let finding = |severity, message: String| Finding::new(Area::Fstab, severity, entry.subject(), message);
+
+        if let Some(device) = device_path(&entry.source) {
+            if !transport.path_exists(&device).await {
+                let (severity, consequence) = if entry.optional() {
+                    (Severity::Warning, "it is skipped at boot")
+                } else {
+                    (Severity::Critical, "boot stops in emergency mode")
+                };
+                findings.push(finding(severity, format!("{} does not exist; {}", entry.source, consequence))
+                    .advice("update the entry with the UUID `blkid` shows, remove it, or add nofail"));
+            }
+        }
+
+        for (option, opposite) in CONFLICTING_OPTIONS {
+            if entry.has_option(option) && entry.has_option(opposite) {
+                findings.push(finding(Severity::Warning, format!("options {} and {} contradict each other; the last one wins", option, opposite)));
+            }
+        }
+        if let Some((_, unsupported)) = UNSUPPORTED_OPTIONS.iter().find(|(fs_type, _)| *fs_type == entry.fs_type) {
+            for option in entry.options.iter().filter(|option| unsupported.iter().any(|bad| if bad.ends_with('=') { option.starts_with(bad) } else { option == bad })) {
+                let severity = if entry.optional() { Severity::Warning } else { Severity::Critical };
+                findings.push(finding(severity, format!("{} does not support the option {}; the mount fails", entry.fs_type, option)));
+            }
+        }
+
+        if entry.dump.parse::<u32>().is_err() || entry.pass.parse::<u32>().is_err() {
+            findings.push(finding(Severity::Critical, format!("dump and pass must be numbers, not {} and {}", entry.dump, entry.pass)));
+        } else if entry.fs_type == "swap" && entry.pass != "0" {
+            findings.push(finding(Severity::Warning, "swap is never checked; set pass to 0".to_string()));
+        } else if entry.mount_point == "/" && entry.pass == "2" {
+            findings.push(finding(Severity::Info, "the root filesystem should be checked first; set pass to 1".to_string()));
+        }
+
+        if entry.fs_type != "swap" && entry.mount_point != "none" {
+            if !entry.mount_point.starts_with('/') {
+                findings.push(finding(Severity::Critical, "mount point is not an absolute path".to_string()));
+            }
+            if let Some(first) = mount_points.insert(&entry.mount_point, entry.line) {
+                findings.push(finding(Severity::Warning, format!("{} is also mounted by line {}; the later mount hides the earlier one", entry.mount_point, first)));
+            }
+        }
+    }
+    findings
+}
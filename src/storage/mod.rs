@@ -0,0 +1,235 @@
+//! Storage health.
+//!
+//! Drive health comes from `smartctl -j`, which reads the ATA attribute table and the
+//! NVMe health log alike; without smartmontools, NVMe drives are read with nvme-cli.
+//! Volumes are inventoried from LVM, `/proc/mdstat`, btrfs and ZFS, `/etc/fstab` is
+//! checked against the devices that actually exist, and the kernel's filesystem error
+//! counters are read from `/sys/fs`. Everything is turned into findings, and the ones
+//! that predict a failing drive become predictive alerts.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::monitoring::{AlertSeverity, AlertType, PredictiveAlert};
+use crate::remote::transport::Transport;
+
+pub mod fstab;
+pub mod smart;
+pub mod volumes;
+
+use fstab::FstabEntry;
+use smart::DiskHealth;
+use volumes::Volume;
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Critical => write!(f, "critical"),
+        }
+    }
+}
+
+/// Which part of the storage stack a finding is about
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Area {
+    Disk,
+    Volume,
+    Fstab,
+    Filesystem,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Finding {
+    pub area: Area,
+    pub severity: Severity,
+    /// Device, volume, mount point or fstab line the finding is about
+    pub subject: String,
+    pub message: String,
+    /// What to do about it
+    pub advice: Option<String>,
+}
+
+impl Finding {
+    pub fn new(area: Area, severity: Severity, subject: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { area, severity, subject: subject.into(), message: message.into(), advice: None }
+    }
+
+    pub fn advice(mut self, advice: impl Into<String>) -> Self {
+        self.advice = Some(advice.into());
+        self
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.subject, self.message)?;
+        if let Some(advice) = &self.advice {
+            write!(f, " ({})", advice)?;
+        }
+        Ok(())
+    }
+}
+
+/// Error counters the kernel keeps for a mounted filesystem
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilesystemErrors {
+    pub fs_type: String,
+    /// Block device for ext4, "<uuid> device <id>" for btrfs
+    pub device: String,
+    pub counters: BTreeMap<String, u64>,
+}
+
+impl FilesystemErrors {
+    pub fn total(&self) -> u64 {
+        self.counters.values().sum()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StorageReport {
+    pub disks: Vec<DiskHealth>,
+    pub volumes: Vec<Volume>,
+    pub fstab: Vec<FstabEntry>,
+    pub filesystem_errors: Vec<FilesystemErrors>,
+    pub findings: Vec<Finding>,
+}
+
+impl StorageReport {
+    /// Findings, most severe first
+    pub fn sorted_findings(&self) -> Vec<&Finding> {
+        let mut findings: Vec<&Finding> = self.findings.iter().collect();
+        findings.sort_by(|a, b| b.severity.partial_cmp(&a.severity).unwrap_or(std::cmp::Ordering::Equal));
+        findings
+    }
+
+    /// Drive findings that point to a failure ahead, as monitoring alerts
+    pub fn predictive_alerts(&self) -> Vec<PredictiveAlert> {
+        let now = chrono::Utc::now();
+        self.findings.iter()
+            .filter(|finding| finding.area == Area::Disk && finding.severity > Severity::Info)
+            .map(|finding| {
+                let critical = finding.severity == Severity::Critical;
+                let disk = self.disks.iter().find(|disk| disk.device == finding.subject);
+                let mut preventive_actions = vec![
+                    format!("Back up the data on {}", finding.subject),
+                    format!("Run a long self-test: smartctl -t long {}", finding.subject),
+                ];
+                if critical {
+                    preventive_actions.push("Plan to replace the drive".to_string());
+                }
+                PredictiveAlert {
+                    alert_id: uuid::Uuid::new_v4().to_string(),
+                    alert_type: AlertType::MaintenanceRequired,
+                    severity: if critical { AlertSeverity::Critical } else { AlertSeverity::Warning },
+                    title: format!("Disk failure predicted for {}", finding.subject),
+                    description: finding.message.clone(),
+                    predicted_time: now + chrono::Duration::days(if critical { 7 } else { 30 }),
+                    confidence: if critical { 0.85 } else { 0.6 },
+                    affected_components: std::iter::once(finding.subject.clone())
+                        .chain(disk.and_then(|disk| disk.model.clone()))
+                        .collect(),
+                    preventive_actions,
+                    created_at: now,
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StorageInspector {
+    transport: Arc<dyn Transport>,
+}
+
+impl StorageInspector {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+
+    /// Inspect drives, volumes, fstab and filesystem error counters. Tools that are
+    /// missing or not allowed to run leave their part of the report empty.
+    pub async fn inspect(&self) -> StorageReport {
+        let transport = self.transport.as_ref();
+        let (fstab, invalid_lines) = fstab::read(transport).await;
+        let mut report = StorageReport {
+            disks: smart::disks(transport).await,
+            volumes: volumes::inventory(transport).await,
+            fstab,
+            filesystem_errors: filesystem_errors(transport).await,
+            findings: Vec::new(),
+        };
+
+        let mut findings: Vec<Finding> = report.disks.iter().flat_map(DiskHealth::findings).collect();
+        findings.extend(report.volumes.iter().flat_map(Volume::findings));
+        findings.extend(fstab::check(transport, &report.fstab, &invalid_lines).await);
+        for errors in report.filesystem_errors.iter().filter(|errors| errors.total() > 0) {
+            let counters: Vec<String> = errors.counters.iter()
+                .filter(|(_, count)| **count > 0)
+                .map(|(name, count)| format!("{} {}", count, name.replace('_', " ")))
+                .collect();
+            let advice = match errors.fs_type.as_str() {
+                "btrfs" => "run `btrfs scrub start` on the mount point",
+                _ => "check the journal for the first error and fsck the filesystem when it is unmounted",
+            };
+            findings.push(Finding::new(Area::Filesystem, Severity::Warning, errors.device.clone(),
+                format!("{} recorded {}", errors.fs_type, counters.join(", "))).advice(advice));
+        }
+        report.findings = findings;
+        report
+    }
+}
+
+/// ext4's `errors_count` and btrfs' per-device `error_stats` from /sys/fs
+async fn filesystem_errors(transport: &dyn Transport) -> Vec<FilesystemErrors> {
+    let script = "grep -H . /sys/fs/ext4/*/errors_count /sys/fs/btrfs/*/devinfo/*/error_stats 2>/dev/null";
+    let Ok(output) = transport.run("sh", &["-c", script]).await else {
+        return Vec::new();
+    };
+    parse_sysfs_errors(&output.stdout)
+}
+
+/// `grep -H` output: "path:value" for ext4, "path:name value" per line for btrfs
+pub(crate) fn parse_sysfs_errors(output: &str) -> Vec<FilesystemErrors> {
+    let mut all: Vec<FilesystemErrors> = Vec::new();
+    for line in output.lines() {
+        let Some((path, value)) = line.split_once(':') else {
+            continue;
+        };
+        let parts: Vec<&str> = path.trim_start_matches("/sys/fs/").split('/').collect();
+        let (fs_type, device, name, value) = match parts.as_slice() {
+            ["ext4", device, "errors_count"] => ("ext4", format!("/dev/{}", device), "errors", value.trim()),
+            ["btrfs", uuid, "devinfo", id, "error_stats"] => {
+                let Some((name, value)) = value.split_once(' ') else {
+                    continue;
+                };
+                ("btrfs", format!("{} device {}", uuid, id), name, value.trim())
+            }
+            _ => continue,
+        };
+        let Ok(value) = value.parse::<u64>() else {
+            continue;
+        };
+        match all.iter_mut().find(|errors| errors.fs_type == fs_type && errors.device == device) {
+            Some(errors) => {
+                errors.counters.insert(name.to_string(), value);
+            }
+            None => all.push(FilesystemErrors {
+                fs_type: fs_type.to_string(),
+                device,
+                counters: BTreeMap::from([(name.to_string(), value)]),
+            }),
+        }
+    }
+    all
+}
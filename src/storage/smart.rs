@@ -0,0 +1,262 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::remote::transport::Transport;
+use super::{Area, Finding, Severity};
+
+/// ATA attributes whose raw value predicts failure as soon as it is not zero, and how
+/// bad a non-zero value is
+const ATA_COUNTERS: &[(u64, &str, Severity)] = &[
+    (5, "reallocated sectors", Severity::Warning),
+    (187, "reported uncorrectable errors", Severity::Critical),
+    (188, "command timeouts", Severity::Warning),
+    (197, "pending sectors", Severity::Critical),
+    (198, "offline uncorrectable sectors", Severity::Critical),
+];
+/// Drives this hot age faster
+const HOT_CELSIUS: i64 = 60;
+/// NVMe wear, in percent of the rated endurance, worth planning a replacement at
+const WORN_PERCENT: u64 = 90;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiskHealth {
+    pub device: String,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    /// "ATA", "NVMe" or "SCSI"
+    pub protocol: String,
+    /// The drive's overall self-assessment
+    pub passed: Option<bool>,
+    pub temperature: Option<i64>,
+    pub power_on_hours: Option<u64>,
+    /// Failure-predicting counters by name, such as "pending sectors" or "media errors"
+    pub counters: BTreeMap<String, u64>,
+    /// Attributes at or below their threshold, with "now" or "in the past"
+    pub failing_attributes: Vec<(String, String)>,
+    /// NVMe percentage used
+    pub wear: Option<u64>,
+    /// NVMe available spare and the threshold below which the drive warns
+    pub spare: Option<(u64, u64)>,
+    /// NVMe critical warning bits
+    pub critical_warning: Option<u64>,
+    /// Why the drive could not be read, e.g. missing permissions
+    pub error: Option<String>,
+}
+
+impl DiskHealth {
+    pub fn findings(&self) -> Vec<Finding> {
+        let finding = |severity, message: String| Finding::new(Area::Disk, severity, self.device.clone(), message);
+        let mut findings = Vec::new();
+
+        if let Some(error) = &self.error {
+            findings.push(finding(Severity::Info, format!("health not read: {}", error))
+                .advice("run as root to read SMART data"));
+            return findings;
+        }
+        if self.passed == Some(false) {
+            findings.push(finding(Severity::Critical, "the drive reports that its overall health check FAILED".to_string())
+                .advice("back up now and replace the drive"));
+        }
+        for (name, when) in &self.failing_attributes {
+            let severity = if when == "now" { Severity::Critical } else { Severity::Warning };
+            findings.push(finding(severity, format!("attribute {} is at or below its failure threshold ({})", name, when)));
+        }
+        for (name, count) in self.counters.iter().filter(|(_, count)| **count > 0) {
+            let severity = ATA_COUNTERS.iter()
+                .find(|(_, counter, _)| counter == name)
+                .map(|(_, _, severity)| *severity)
+                .unwrap_or(Severity::Critical);
+            findings.push(finding(severity, format!("{} {}", count, name))
+                .advice("back up and run a long self-test"));
+        }
+        if let Some(bits) = self.critical_warning.filter(|bits| *bits != 0) {
+            findings.push(finding(Severity::Critical, format!("NVMe critical warning {:#04x}: {}", bits, critical_warnings(bits).join(", "))));
+        }
+        if let Some((spare, threshold)) = self.spare.filter(|(spare, threshold)| spare < threshold) {
+            findings.push(finding(Severity::Critical, format!("available spare {}% is below the {}% threshold", spare, threshold)));
+        }
+        if let Some(wear) = self.wear.filter(|wear| *wear >= WORN_PERCENT) {
+            let severity = if wear >= 100 { Severity::Critical } else { Severity::Warning };
+            findings.push(finding(severity, format!("{}% of the rated write endurance is used", wear)));
+        }
+        if let Some(temperature) = self.temperature.filter(|temperature| *temperature >= HOT_CELSIUS) {
+            findings.push(finding(Severity::Warning, format!("running at {} °C", temperature))
+                .advice("check the airflow around the drive"));
+        }
+        findings
+    }
+
+    /// One line summary, e.g. "/dev/sda Samsung SSD 860: PASSED, 34 °C, 12034 h"
+    pub fn summary(&self) -> String {
+        let mut summary = self.device.clone();
+        if let Some(model) = &self.model {
+            summary.push_str(&format!(" {}", model));
+        }
+        let mut facts = Vec::new();
+        match (&self.error, self.passed) {
+            (Some(_), _) => facts.push("unreadable".to_string()),
+            (None, Some(true)) => facts.push("PASSED".to_string()),
+            (None, Some(false)) => facts.push("FAILED".to_string()),
+            (None, None) => facts.push("no self-assessment".to_string()),
+        }
+        facts.extend(self.temperature.map(|temperature| format!("{} °C", temperature)));
+        facts.extend(self.power_on_hours.map(|hours| format!("{} h", hours)));
+        facts.extend(self.wear.map(|wear| format!("{}% worn", wear)));
+        format!("{}: {}", summary, facts.join(", "))
+    }
+}
+
+/// Meaning of the NVMe critical warning bits
+fn critical_warnings(bits: u64) -> Vec<&'static str> {
+    const BITS: &[&str] = &[
+        "spare below threshold",
+        "temperature out of range",
+        "reliability degraded",
+        "read-only mode",
+        "volatile memory backup failed",
+        "persistent memory region read-only",
+    ];
+    BITS.iter().enumerate().filter(|(bit, _)| bits & (1 << bit) != 0).map(|(_, meaning)| *meaning).collect()
+}
+
+/// Health of every drive smartctl finds, or of NVMe drives through nvme-cli when
+/// smartctl is not installed
+pub async fn disks(transport: &dyn Transport) -> Vec<DiskHealth> {
+    let scan = match transport.run("smartctl", &["-j", "--scan"]).await {
+        Ok(scan) if scan.success() => scan,
+        _ => return nvme_disks(transport).await,
+    };
+    let devices = serde_json::from_str::<Value>(&scan.stdout)
+        .ok()
+        .and_then(|scan| scan["devices"].as_array().cloned())
+        .unwrap_or_default();
+
+    let mut disks = Vec::new();
+    for device in devices {
+        let (Some(name), Some(kind)) = (device["name"].as_str(), device["type"].as_str()) else {
+            continue;
+        };
+        // smartctl's exit status is a bit mask that is also set for failing drives, so
+        // the JSON is parsed whatever it is
+        let Ok(output) = transport.run("smartctl", &["-j", "-a", "-d", kind, name]).await else {
+            continue;
+        };
+        match serde_json::from_str::<Value>(&output.stdout) {
+            Ok(json) => disks.push(parse_smartctl(name, &json)),
+            Err(_) => disks.push(DiskHealth {
+                device: name.to_string(),
+                error: Some(output.stderr.trim().to_string()),
+                ..Default::default()
+            }),
+        }
+    }
+    disks
+}
+
+/// Parse `smartctl -j -a` output for an ATA, NVMe or SCSI drive
+pub(crate) fn parse_smartctl(device: &str, json: &Value) -> DiskHealth {
+    let text = |value: &Value| value.as_str().map(|text| text.trim().to_string()).filter(|text| !text.is_empty());
+    let mut disk = DiskHealth {
+        device: device.to_string(),
+        model: text(&json["model_name"]).or_else(|| text(&json["scsi_model_name"])),
+        serial: text(&json["serial_number"]),
+        protocol: text(&json["device"]["protocol"]).unwrap_or_else(|| "unknown".to_string()),
+        passed: json["smart_status"]["passed"].as_bool(),
+        temperature: json["temperature"]["current"].as_i64(),
+        power_on_hours: json["power_on_time"]["hours"].as_u64(),
+        ..Default::default()
+    };
+
+    // Errors such as "Permission denied" when smartctl could not open the device
+    let exit_status = json["smartctl"]["exit_status"].as_u64().unwrap_or_default();
+    if exit_status & 0b11 != 0 {
+        let messages: Vec<String> = json["smartctl"]["messages"].as_array()
+            .map(|messages| messages.iter().filter_map(|message| text(&message["string"])).collect())
+            .unwrap_or_default();
+        disk.error = Some(if messages.is_empty() { "smartctl could not open the device".to_string() } else { messages.join("; ") });
+        return disk;
+    }
+
+    for attribute in json["ata_smart_attributes"]["table"].as_array().into_iter().flatten() {
+        let name = attribute["name"].as_str().unwrap_or_default().to_string();
+        if let Some(when) = text(&attribute["when_failed"]) {
+            let when = if when == "now" { "now" } else { "in the past" };
+            disk.failing_attributes.push((name.clone(), when.to_string()));
+        }
+        let id = attribute["id"].as_u64().unwrap_or_default();
+        if let Some((_, counter, _)) = ATA_COUNTERS.iter().find(|(counter_id, _, _)| *counter_id == id) {
+            // Some vendors pack more than the count into the upper bytes of the raw value
+            let raw = attribute["raw"]["value"].as_u64().unwrap_or_default() & 0xffff_ffff;
+            disk.counters.insert(counter.to_string(), raw);
+        }
+    }
+
+    let nvme = &json["nvme_smart_health_information_log"];
+    if nvme.is_object() {
+        disk.critical_warning = nvme["critical_warning"].as_u64();
+        disk.wear = nvme["percentage_used"].as_u64();
+        if let (Some(spare), Some(threshold)) = (nvme["available_spare"].as_u64(), nvme["available_spare_threshold"].as_u64()) {
+            disk.spare = Some((spare, threshold));
+        }
+        disk.counters.insert("media errors".to_string(), nvme["media_errors"].as_u64().unwrap_or_default());
+    }
+
+    if let Some(defects) = json["scsi_grown_defect_list"].as_u64() {
+        disk.counters.insert("grown defects".to_string(), defects);
+    }
+    disk
+}
+
+/// NVMe drives read with `nvme smart-log`
+async fn nvme_disks(transport: &dyn Transport) -> Vec<DiskHealth> {
+    let Ok(list) = transport.run("nvme", &["list", "-o", "json"]).await else {
+        return Vec::new();
+    };
+    let devices = serde_json::from_str::<Value>(&list.stdout)
+        .ok()
+        .and_then(|list| list["Devices"].as_array().cloned())
+        .unwrap_or_default();
+
+    let mut disks = Vec::new();
+    for device in devices {
+        let Some(path) = device["DevicePath"].as_str() else {
+            continue;
+        };
+        let Ok(output) = transport.run("nvme", &["smart-log", "-o", "json", path]).await else {
+            continue;
+        };
+        let mut disk = match serde_json::from_str::<Value>(&output.stdout) {
+            Ok(log) if output.success() => parse_nvme_smart_log(path, &log),
+            _ => DiskHealth {
+                device: path.to_string(),
+                protocol: "NVMe".to_string(),
+                error: Some(output.stderr.trim().to_string()),
+                ..Default::default()
+            },
+        };
+        disk.model = device["ModelNumber"].as_str().map(|model| model.trim().to_string());
+        disk.serial = device["SerialNumber"].as_str().map(|serial| serial.trim().to_string());
+        disks.push(disk);
+    }
+    disks
+}
+
+/// Parse `nvme smart-log -o json`, whose temperature is in Kelvin
+pub(crate) fn parse_nvme_smart_log(device: &str, log: &Value) -> DiskHealth {
+    let critical_warning = log["critical_warning"].as_u64()
+        // nvme-cli 2 reports the bits as an object with a "value" field
+        .or_else(|| log["critical_warning"]["value"].as_u64());
+    DiskHealth {
+        device: device.to_string(),
+        protocol: "NVMe".to_string(),
+        passed: critical_warning.map(|bits| bits == 0),
+        temperature: log["temperature"].as_i64().map(|kelvin| kelvin - 273),
+        power_on_hours: log["power_on_hours"].as_u64(),
+        counters: BTreeMap::from([("media errors".to_string(), log["media_errors"].as_u64().unwrap_or_default())]),
+        wear: log["percent_used"].as_u64(),
+        spare: log["avail_spare"].as_u64().zip(log["spare_thresh"].as_u64()),
+        critical_warning,
+        ..Default::default()
+    }
+}
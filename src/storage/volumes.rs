@@ -0,0 +1,298 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+
+use crate::remote::transport::Transport;
+use super::{Area, Finding, Severity};
+
+/// Thin pools stop accepting writes when they are full
+const THIN_POOL_FULL_PERCENT: f64 = 90.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum VolumeKind {
+    LvmVolumeGroup,
+    LvmLogicalVolume,
+    Mdraid,
+    Btrfs,
+    Zfs,
+}
+
+impl fmt::Display for VolumeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VolumeKind::LvmVolumeGroup => write!(f, "LVM volume group"),
+            VolumeKind::LvmLogicalVolume => write!(f, "LVM logical volume"),
+            VolumeKind::Mdraid => write!(f, "md RAID"),
+            VolumeKind::Btrfs => write!(f, "btrfs"),
+            VolumeKind::Zfs => write!(f, "ZFS pool"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Volume {
+    pub kind: VolumeKind,
+    pub name: String,
+    pub size_bytes: Option<u64>,
+    /// State as the tool reports it, e.g. "active raid1", "ONLINE" or "partial"
+    pub state: String,
+    pub healthy: bool,
+    /// Member devices
+    pub devices: Vec<String>,
+    /// Why the volume is not healthy, or what it is busy with
+    pub detail: Option<String>,
+}
+
+impl Volume {
+    fn new(kind: VolumeKind, name: &str, state: &str) -> Self {
+        Self {
+            kind,
+            name: name.to_string(),
+            size_bytes: None,
+            state: state.to_string(),
+            healthy: true,
+            devices: Vec::new(),
+            detail: None,
+        }
+    }
+
+    pub fn findings(&self) -> Vec<Finding> {
+        if self.healthy {
+            return Vec::new();
+        }
+        let message = format!("{} is {}{}", self.kind, self.state,
+            self.detail.as_ref().map(|detail| format!(": {}", detail)).unwrap_or_default());
+        let advice = match self.kind {
+            VolumeKind::Mdraid => "replace the failed member and add it back with mdadm --manage --add",
+            VolumeKind::Zfs => "see `zpool status -v` for the affected devices",
+            VolumeKind::Btrfs => "replace the missing device with `btrfs replace`",
+            VolumeKind::LvmVolumeGroup | VolumeKind::LvmLogicalVolume => "see `lvs -a -o +devices` for the affected devices",
+        };
+        vec![Finding::new(Area::Volume, Severity::Critical, self.name.clone(), message).advice(advice)]
+    }
+}
+
+impl fmt::Display for Volume {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: {}", self.kind, self.name, self.state)?;
+        if let Some(size) = self.size_bytes {
+            write!(f, ", {:.1} GiB", size as f64 / (1u64 << 30) as f64)?;
+        }
+        if !self.devices.is_empty() {
+            write!(f, " on {}", self.devices.join(", "))?;
+        }
+        if let Some(detail) = &self.detail {
+            write!(f, " ({})", detail)?;
+        }
+        Ok(())
+    }
+}
+
+/// LVM, md RAID, btrfs and ZFS volumes; tools that are not installed are skipped
+pub async fn inventory(transport: &dyn Transport) -> Vec<Volume> {
+    let mut volumes = Vec::new();
+    let output = |command: &'static str, args: &'static [&'static str]| async move {
+        transport.run(command, args).await.ok().filter(|output| output.success()).map(|output| output.stdout)
+    };
+
+    if let Some(stdout) = output("vgs", &["--reportformat", "json", "--units", "b", "--nosuffix",
+        "-o", "vg_name,vg_size,vg_free,pv_count,vg_missing_pv_count"]).await {
+        volumes.extend(parse_vgs(&stdout));
+    }
+    if let Some(stdout) = output("lvs", &["--reportformat", "json", "--units", "b", "--nosuffix",
+        "-o", "lv_name,vg_name,lv_size,lv_attr,lv_health_status,data_percent"]).await {
+        volumes.extend(parse_lvs(&stdout));
+    }
+    if let Ok(mdstat) = transport.read_file("/proc/mdstat").await {
+        volumes.extend(parse_mdstat(&mdstat));
+    }
+    if let Some(stdout) = output("btrfs", &["filesystem", "show", "--raw"]).await {
+        volumes.extend(parse_btrfs_show(&stdout));
+    }
+    if let Some(stdout) = output("zpool", &["list", "-H", "-p", "-o", "name,size,health"]).await {
+        volumes.extend(parse_zpool_list(&stdout));
+    }
+    volumes
+}
+
+/// Rows of an LVM JSON report
+fn lvm_rows<'a>(report: &'a Value, kind: &'a str) -> impl Iterator<Item = &'a Value> {
+    report["report"].as_array().into_iter().flatten()
+        .flat_map(move |section| section[kind].as_array().into_iter().flatten())
+}
+
+fn lvm_number(value: &Value) -> Option<u64> {
+    value.as_str().and_then(|number| number.trim().parse::<f64>().ok()).map(|number| number as u64)
+}
+
+pub(crate) fn parse_vgs(output: &str) -> Vec<Volume> {
+    let Ok(report) = serde_json::from_str::<Value>(output) else {
+        return Vec::new();
+    };
+    lvm_rows(&report, "vg")
+        .map(|row| {
+            let name = row["vg_name"].as_str().unwrap_or_default();
+            let missing = lvm_number(&row["vg_missing_pv_count"]).unwrap_or_default();
+            let mut volume = Volume::new(VolumeKind::LvmVolumeGroup, name, if missing > 0 { "partial" } else { "complete" });
+            volume.size_bytes = lvm_number(&row["vg_size"]);
+            if missing > 0 {
+                volume.healthy = false;
+                volume.detail = Some(format!("{} of {} physical volumes missing", missing,
+                    lvm_number(&row["pv_count"]).unwrap_or_default()));
+            }
+            volume
+        })
+        .collect()
+}
+
+pub(crate) fn parse_lvs(output: &str) -> Vec<Volume> {
+    let Ok(report) = serde_json::from_str::<Value>(output) else {
+        return Vec::new();
+    };
+    lvm_rows(&report, "lv")
+        .map(|row| {
+            let name = format!("{}/{}", row["vg_name"].as_str().unwrap_or_default(), row["lv_name"].as_str().unwrap_or_default());
+            let health = row["lv_health_status"].as_str().unwrap_or_default().trim();
+            let attr = row["lv_attr"].as_str().unwrap_or_default();
+            let mut volume = Volume::new(VolumeKind::LvmLogicalVolume, &name, if health.is_empty() { "ok" } else { health });
+            volume.size_bytes = lvm_number(&row["lv_size"]);
+            volume.healthy = health.is_empty();
+
+            // Thin pools carry "t" as their volume type
+            let data_percent = row["data_percent"].as_str().and_then(|percent| percent.trim().parse::<f64>().ok());
+            if let Some(percent) = data_percent.filter(|percent| attr.starts_with('t') && *percent >= THIN_POOL_FULL_PERCENT) {
+                volume.healthy = false;
+                volume.state = "nearly full".to_string();
+                volume.detail = Some(format!("thin pool data {:.1}% used; writes fail once it is full", percent));
+            }
+            volume
+        })
+        .collect()
+}
+
+/// Parse /proc/mdstat, e.g.
+///
+/// ```text
+/// md0 : active raid1 sdb1[1](F) sda1[0]
+///       1046528 blocks super 1.2 [2/1] [U_]
+///       [==>..................]  recovery = 12.6% (132480/1046528) finish=0.4min
+/// ```
+pub(crate) fn parse_mdstat(mdstat: &str) -> Vec<Volume> {
+    let mut volumes: Vec<Volume> = Vec::new();
+    for line in mdstat.lines() {
+        if let Some((name, rest)) = line.split_once(" : ") {
+            if !name.starts_with("md") {
+                continue;
+            }
+            let mut words = rest.split_whitespace().peekable();
+            let mut state = words.next().unwrap_or_default().to_string();
+            if words.peek().is_some_and(|word| word.starts_with('(')) {
+                state.push_str(&format!(" {}", words.next().unwrap_or_default()));
+            }
+            let mut devices = Vec::new();
+            let mut failed = Vec::new();
+            for word in words {
+                let Some((device, role)) = word.split_once('[') else {
+                    state.push_str(&format!(" {}", word));
+                    continue;
+                };
+                devices.push(device.to_string());
+                if role.ends_with("(F)") {
+                    failed.push(device.to_string());
+                }
+            }
+            let mut volume = Volume::new(VolumeKind::Mdraid, &format!("/dev/{}", name.trim()), &state);
+            volume.devices = devices;
+            if !failed.is_empty() {
+                volume.healthy = false;
+                volume.detail = Some(format!("failed: {}", failed.join(", ")));
+            }
+            if state.starts_with("inactive") {
+                volume.healthy = false;
+            }
+            volumes.push(volume);
+            continue;
+        }
+
+        let Some(volume) = volumes.last_mut() else {
+            continue;
+        };
+        let line = line.trim();
+        if line.contains(" blocks") {
+            // "[2/1] [U_]": an underscore is a missing member
+            if let Some(status) = line.rsplit(' ').next().filter(|status| status.starts_with('[') && status.contains('_')) {
+                volume.healthy = false;
+                volume.state = format!("{} degraded", volume.state);
+                if volume.detail.is_none() {
+                    volume.detail = Some(format!("members {}", status));
+                }
+            }
+            if let Some(blocks) = line.split_whitespace().next().and_then(|blocks| blocks.parse::<u64>().ok()) {
+                volume.size_bytes = Some(blocks * 1024);
+            }
+        } else if let Some(progress) = ["recovery", "resync", "reshape", "check"].iter().find(|operation| line.contains(&format!("{} =", operation))) {
+            let percent = line.split('=').nth(1).and_then(|rest| rest.split_whitespace().next()).unwrap_or_default();
+            let busy = format!("{} {}", progress, percent);
+            volume.detail = Some(match &volume.detail {
+                Some(detail) => format!("{}, {}", detail, busy),
+                None => busy,
+            });
+        }
+    }
+    volumes
+}
+
+/// Parse `btrfs filesystem show --raw`, e.g.
+///
+/// ```text
+/// Label: 'data'  uuid: 6f7a...
+///         Total devices 2 FS bytes used 1073741824
+///         devid    1 size 500107862016 used 2155872256 path /dev/sdb
+///         *** Some devices missing
+/// ```
+pub(crate) fn parse_btrfs_show(output: &str) -> Vec<Volume> {
+    let mut volumes: Vec<Volume> = Vec::new();
+    for line in output.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("Label:") {
+            let (label, uuid) = rest.split_once("uuid:").unwrap_or((rest, ""));
+            let label = label.trim().trim_matches('\'');
+            let name = if label.is_empty() || label == "none" { uuid.trim() } else { label };
+            volumes.push(Volume::new(VolumeKind::Btrfs, name, "complete"));
+            continue;
+        }
+        let Some(volume) = volumes.last_mut() else {
+            continue;
+        };
+        if line.starts_with("devid") {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if let Some(position) = words.iter().position(|word| *word == "path") {
+                volume.devices.extend(words.get(position + 1).map(|path| path.to_string()));
+            }
+            if let Some(position) = words.iter().position(|word| *word == "size") {
+                let size = words.get(position + 1).and_then(|size| size.parse::<u64>().ok()).unwrap_or_default();
+                volume.size_bytes = Some(volume.size_bytes.unwrap_or_default() + size);
+            }
+        } else if line.contains("devices missing") {
+            volume.healthy = false;
+            volume.state = "degraded".to_string();
+            volume.detail = Some("some devices are missing".to_string());
+        }
+    }
+    volumes
+}
+
+/// Parse `zpool list -H -p -o name,size,health`
+pub(crate) fn parse_zpool_list(output: &str) -> Vec<Volume> {
+    output.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [name, size, health] = fields.as_slice() else {
+                return None;
+            };
+            let mut volume = Volume::new(VolumeKind::Zfs, name, health);
+            volume.size_bytes = size.parse().ok();
+            volume.healthy = *health == "ONLINE";
+            Some(volume)
+        })
+        .collect()
+}
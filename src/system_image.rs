@@ -0,0 +1,205 @@
+//! Image-based and immutable distributions.
+//!
+//! Fedora Silverblue/Kinoite (rpm-ostree), openSUSE MicroOS/Aeon (transactional-update)
+//! and SteamOS ship `/usr` read-only. Package changes there are layered onto the image
+//! or written into a new snapshot and only take effect after a reboot, and files under
+//! `/usr` are overridden from `/etc` rather than edited. This module detects those
+//! systems and turns commands that would fail or be lost into advice for the right
+//! mechanism.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::remote::transport::Transport;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum SystemImage {
+    /// Packages and files are changed in place
+    #[default]
+    Mutable,
+    /// OSTree deployment with rpm-ostree package layering
+    RpmOstree,
+    /// Read-only root where changes go into a new btrfs snapshot
+    TransactionalUpdate,
+    /// Read-only `/usr` without a layering tool, such as SteamOS
+    ReadOnly,
+}
+
+impl fmt::Display for SystemImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SystemImage::Mutable => write!(f, "Mutable"),
+            SystemImage::RpmOstree => write!(f, "Image based (rpm-ostree)"),
+            SystemImage::TransactionalUpdate => write!(f, "Transactional (transactional-update)"),
+            SystemImage::ReadOnly => write!(f, "Read-only /usr"),
+        }
+    }
+}
+
+/// A change that is staged and not active yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingActivation {
+    pub reason: String,
+    /// Makes the change active; a reboot always works
+    pub apply_command: String,
+}
+
+/// Detect the image type of the machine behind `transport`
+pub async fn detect(transport: &dyn Transport) -> SystemImage {
+    let has = |program: &'static str| async move {
+        transport.run("sh", &["-c", &format!("command -v {}", program)]).await
+            .map(|output| output.success())
+            .unwrap_or(false)
+    };
+
+    if transport.path_exists("/run/ostree-booted").await {
+        return if has("rpm-ostree").await { SystemImage::RpmOstree } else { SystemImage::ReadOnly };
+    }
+
+    let root_read_only = mount_is_read_only(transport, "/").await;
+    if root_read_only && has("transactional-update").await {
+        return SystemImage::TransactionalUpdate;
+    }
+    if root_read_only || mount_is_read_only(transport, "/usr").await {
+        return SystemImage::ReadOnly;
+    }
+
+    SystemImage::Mutable
+}
+
+async fn mount_is_read_only(transport: &dyn Transport, path: &str) -> bool {
+    match transport.run("findmnt", &["-n", "-o", "OPTIONS", "--target", path]).await {
+        Ok(output) if output.success() => output.stdout.trim().split(',').any(|option| option == "ro"),
+        _ => false,
+    }
+}
+
+impl SystemImage {
+    pub fn is_immutable(&self) -> bool {
+        *self != SystemImage::Mutable
+    }
+
+    /// Why `program args` should not run as is on this system, and what to do instead.
+    /// `None` when the command is fine.
+    pub fn reroute(&self, program: &str, args: &[String]) -> Option<String> {
+        if !self.is_immutable() {
+            return None;
+        }
+
+        if let Some(packages) = package_change(program, args) {
+            return Some(self.package_advice(&packages));
+        }
+
+        let target = args.iter()
+            .filter(|arg| !arg.starts_with('-'))
+            .find(|arg| arg.starts_with("/usr/") && !arg.starts_with("/usr/local/"))?;
+        if !WRITING_COMMANDS.contains(&program) || (program == "sed" && !args.iter().any(|a| a.starts_with("-i"))) {
+            return None;
+        }
+
+        Some(match etc_override(target) {
+            Some(overlay) => format!(
+                "{} is read-only on this system. Copy it to {} and change the copy there; files in /etc take precedence over the vendor defaults in /usr",
+                target, overlay
+            ),
+            None => format!(
+                "{} is read-only on this system. Put local programs and data under /usr/local or /opt instead",
+                target
+            ),
+        })
+    }
+
+    /// How to install or remove packages on this system instead of with the base
+    /// package manager
+    pub fn package_advice(&self, packages: &str) -> String {
+        match self {
+            SystemImage::Mutable => String::new(),
+            SystemImage::RpmOstree => format!(
+                "This system is image based (rpm-ostree). Layer packages with `rpm-ostree install {}` and reboot to use them, \
+                 or install development tools inside a container with `toolbox run sudo dnf install {}`. \
+                 Desktop apps are best installed with flatpak",
+                packages, packages
+            ),
+            SystemImage::TransactionalUpdate => format!(
+                "The root filesystem is read-only (transactional-update). Install into a new snapshot with \
+                 `transactional-update pkg install {}` and reboot to use it, or use a distrobox container",
+                packages
+            ),
+            SystemImage::ReadOnly => format!(
+                "/usr is read-only and there is no layering tool. Install desktop apps with flatpak, and tools such as {} \
+                 inside a container created with `distrobox create` or `toolbox create`",
+                packages
+            ),
+        }
+    }
+}
+
+/// Whether a command that already ran left its change staged rather than active
+pub fn pending_activation(program: &str, args: &[String]) -> Option<PendingActivation> {
+    // transactional-update without a subcommand runs "up"
+    let subcommand = args.iter().find(|arg| !arg.starts_with('-')).map(String::as_str).unwrap_or("up");
+    let dry_run = args.iter().any(|arg| arg == "--dry-run" || (program == "rpm-ostree" && arg == "-n"));
+    if dry_run {
+        return None;
+    }
+
+    match (program, subcommand) {
+        ("rpm-ostree", "install" | "uninstall") if args.iter().any(|a| a == "--apply-live" || a == "-A") => None,
+        ("rpm-ostree", "install" | "uninstall") => Some(PendingActivation {
+            reason: "Package layering is staged in a new deployment".to_string(),
+            apply_command: "rpm-ostree apply-live".to_string(),
+        }),
+        ("rpm-ostree", "upgrade" | "update" | "rebase" | "override" | "kargs" | "initramfs" | "deploy") => {
+            Some(PendingActivation {
+                reason: "A new deployment is staged for the next boot".to_string(),
+                apply_command: "systemctl reboot".to_string(),
+            })
+        }
+        ("transactional-update", "apply") => None,
+        ("transactional-update", _) => Some(PendingActivation {
+            reason: "Changes were written to a new snapshot".to_string(),
+            apply_command: "systemctl reboot".to_string(),
+        }),
+        _ => None,
+    }
+}
+
+/// Commands that write to the paths they are given
+const WRITING_COMMANDS: &[&str] = &[
+    "cp", "mv", "rm", "ln", "touch", "mkdir", "rmdir", "tee", "install", "chmod", "chown", "sed", "truncate",
+];
+
+/// The packages an install or removal with a distribution package manager would
+/// change, or `None` for queries and other commands
+fn package_change(program: &str, args: &[String]) -> Option<String> {
+    let words: Vec<&str> = args.iter().map(String::as_str).collect();
+    let changes = match program {
+        "dnf" | "yum" | "apt" | "apt-get" | "zypper" => words.iter().any(|word| matches!(*word,
+            "install" | "in" | "remove" | "rm" | "erase" | "upgrade" | "up" | "update" | "dup" | "dist-upgrade"
+            | "patch" | "reinstall" | "autoremove")),
+        // -Ss, -Si, -Sl, -Sg and -Sp only query
+        "pacman" => words.iter().any(|word| (word.starts_with("-S") && !word[2..].contains(['s', 'i', 'l', 'g', 'p']))
+            || word.starts_with("-R") || word.starts_with("-U")),
+        "rpm" => words.iter().any(|word| matches!(*word, "-i" | "-U" | "-e" | "--install" | "--upgrade" | "--erase")
+            || word.starts_with("-iv") || word.starts_with("-Uv")),
+        _ => false,
+    };
+    if !changes {
+        return None;
+    }
+
+    let packages: Vec<&str> = words.into_iter()
+        .filter(|word| !word.starts_with('-'))
+        .skip(if program == "pacman" || program == "rpm" { 0 } else { 1 })
+        .collect();
+    Some(if packages.is_empty() { "<package>".to_string() } else { packages.join(" ") })
+}
+
+/// Where an administrator overrides a vendor file shipped under /usr, following the
+/// systemd convention of vendor files in /usr/lib and local ones in /etc
+fn etc_override(path: &str) -> Option<String> {
+    let relative = path.strip_prefix("/usr/lib/")
+        .or_else(|| path.strip_prefix("/usr/share/"))
+        .or_else(|| path.strip_prefix("/usr/etc/"))?;
+    Some(format!("/etc/{}", relative))
+}
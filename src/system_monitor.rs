@@ -1,9 +1,15 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
 use sysinfo::{System, Disks, Networks};
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::time;
 
 use crate::config::Config;
+use crate::linux_integration::LinuxIntegration;
+use crate::network::NetworkInspector;
+use crate::remote::transport::LocalTransport;
+use crate::services;
+use crate::storage;
 
 pub struct SystemMonitor {
     system: System,
@@ -124,6 +130,102 @@ impl SystemMonitor {
         Ok(status.join("\n"))
     }
 
+    /// Status of the machine behind `linux_integration`, read from /proc and standard
+    /// tools so it works over SSH where sysinfo cannot be used
+    pub async fn get_remote_status(linux_integration: &LinuxIntegration) -> Result<String> {
+        let mut status = Vec::new();
+
+        // CPU usage from two /proc/stat samples
+        let first = linux_integration.read_file("/proc/stat").await?;
+        time::sleep(Duration::from_millis(500)).await;
+        let second = linux_integration.read_file("/proc/stat").await?;
+        if let (Some((idle_a, total_a)), Some((idle_b, total_b))) = (cpu_times(&first), cpu_times(&second)) {
+            let total = total_b.saturating_sub(total_a).max(1) as f64;
+            let busy = total - idle_b.saturating_sub(idle_a) as f64;
+            status.push(format!("CPU Usage: {:.1}%", busy / total * 100.0));
+        }
+
+        // Memory and swap from /proc/meminfo (values in kB)
+        if let Ok(meminfo) = linux_integration.read_file("/proc/meminfo").await {
+            let value = |key: &str| -> u64 {
+                meminfo.lines()
+                    .find(|line| line.starts_with(key))
+                    .and_then(|line| line.split_whitespace().nth(1))
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0)
+            };
+
+            let total_memory = value("MemTotal:");
+            let used_memory = total_memory.saturating_sub(value("MemAvailable:"));
+            if total_memory > 0 {
+                status.push(format!(
+                    "Memory: {:.1}% ({} MB / {} MB)",
+                    used_memory as f64 / total_memory as f64 * 100.0,
+                    used_memory / 1024,
+                    total_memory / 1024
+                ));
+            }
+
+            let total_swap = value("SwapTotal:");
+            let used_swap = total_swap.saturating_sub(value("SwapFree:"));
+            if total_swap > 0 {
+                status.push(format!(
+                    "Swap: {:.1}% ({} MB / {} MB)",
+                    used_swap as f64 / total_swap as f64 * 100.0,
+                    used_swap / 1024,
+                    total_swap / 1024
+                ));
+            }
+        }
+
+        // Disk usage of real filesystems
+        if let Ok(df) = linux_integration.execute_command("df", &["-P", "-B1", "-x", "tmpfs", "-x", "devtmpfs", "-x", "squashfs", "-x", "overlay"]).await {
+            for line in df.lines().skip(1) {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                if let (Some(total), Some(used), Some(mount)) = (fields.get(1), fields.get(2), fields.last()) {
+                    let total_space: u64 = total.parse().unwrap_or(0);
+                    let used_space: u64 = used.parse().unwrap_or(0);
+                    if total_space > 0 {
+                        status.push(format!(
+                            "Disk {}: {:.1}% ({} GB / {} GB)",
+                            mount,
+                            used_space as f64 / total_space as f64 * 100.0,
+                            used_space / 1024 / 1024 / 1024,
+                            total_space / 1024 / 1024 / 1024
+                        ));
+                    }
+                }
+            }
+        }
+
+        if let Ok(loadavg) = linux_integration.read_file("/proc/loadavg").await {
+            let load_parts: Vec<&str> = loadavg.split_whitespace().collect();
+            if load_parts.len() >= 3 {
+                status.push(format!(
+                    "Load Average: {} {} {}",
+                    load_parts[0], load_parts[1], load_parts[2]
+                ));
+            }
+        }
+
+        if let Ok(ps) = linux_integration.execute_command("ps", &["-eo", "comm,pcpu", "--sort=-pcpu", "--no-headers"]).await {
+            let top_processes: Vec<String> = ps.lines()
+                .take(5)
+                .filter_map(|line| {
+                    let (name, cpu) = line.trim().rsplit_once(char::is_whitespace)?;
+                    let cpu: f32 = cpu.parse().ok()?;
+                    (cpu > 0.1).then(|| format!("{} ({:.1}%)", name.trim(), cpu))
+                })
+                .collect();
+
+            if !top_processes.is_empty() {
+                status.push(format!("Top CPU: {}", top_processes.join(", ")));
+            }
+        }
+
+        Ok(status.join("\n"))
+    }
+
     pub async fn start_continuous_monitoring(&mut self) -> Result<()> {
         let mut interval = time::interval(Duration::from_secs(5));
         
@@ -214,17 +316,10 @@ impl SystemMonitor {
     }
 
     pub async fn get_service_status(&self, service_name: &str) -> Result<String> {
-        // This would integrate with systemctl or other service managers
-        let output = tokio::process::Command::new("systemctl")
-            .args(&["status", service_name])
-            .output()
-            .await
-            .context("Failed to check service status")?;
-
-        if output.status.success() {
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
-        } else {
-            Ok(format!("Service '{}' not found or not accessible", service_name))
+        let backend = services::backend(&self.config.system.service_manager, Arc::new(LocalTransport));
+        match backend.status(service_name).await? {
+            Some(status) => Ok(status.to_string()),
+            None => Ok(format!("Service '{}' not found ({})", service_name, backend.manager())),
         }
     }
 
@@ -244,16 +339,21 @@ impl SystemMonitor {
             ));
         }
         
-        // Add IP information
-        if let Ok(ip_output) = tokio::process::Command::new("ip")
-            .args(&["addr", "show"])
-            .output()
-            .await
-        {
-            if ip_output.status.success() {
-                let ip_info = String::from_utf8_lossy(&ip_output.stdout);
-                network_status.push(format!("\nIP Configuration:\n{}", ip_info));
-            }
+        // Who configures each interface, and what keeps it off the network
+        let report = NetworkInspector::new(Arc::new(LocalTransport)).report().await;
+        network_status.push(String::new());
+        for interface in &report.interfaces {
+            let owner = interface.owner.map(|owner| owner.to_string()).unwrap_or_else(|| "not configured".to_string());
+            let addresses = interface.link.as_ref().map(|link| link.addresses.join(" ")).unwrap_or_else(|| "missing".to_string());
+            let method = interface.config().map(|config| config.method.to_string()).unwrap_or_else(|| "-".to_string());
+            network_status.push(format!("Interface Config: {} | Managed By: {} | IPv4: {} | Addresses: {}", interface.name, owner, method, addresses));
+        }
+        for route in &report.default_routes {
+            network_status.push(format!("Default Route: {}", route));
+        }
+        network_status.push(format!("DNS Servers: {}", report.nameservers.join(" ")));
+        for problem in &report.problems {
+            network_status.push(format!("Network Problem: {}", problem));
         }
         
         Ok(network_status.join("\n"))
@@ -287,7 +387,20 @@ impl SystemMonitor {
                 disk.file_system()
             ));
         }
+
+        // SMART health of the drives behind those filesystems
+        for disk in storage::smart::disks(&LocalTransport).await {
+            hardware_info.push(format!("Disk Health: {}", disk.summary()));
+        }
         
         Ok(hardware_info.join("\n"))
     }
 }
+
+/// Idle and total jiffies from the aggregate "cpu" line of /proc/stat
+fn cpu_times(stat: &str) -> Option<(u64, u64)> {
+    let line = stat.lines().find(|line| line.starts_with("cpu "))?;
+    let values: Vec<u64> = line.split_whitespace().skip(1).filter_map(|v| v.parse().ok()).collect();
+    let idle = values.get(3)? + values.get(4).unwrap_or(&0);
+    Some((idle, values.iter().sum()))
+}
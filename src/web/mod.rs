@@ -10,7 +10,9 @@ pub mod auth;
 pub mod websocket;
 
 use crate::config::Config;
+use crate::journal::{JournalQuery, JournalReader, LogRecord};
 use crate::linux_integration::LinuxIntegration;
+use crate::execution::approval::{ApprovalBroker, PendingApproval};
 use crate::execution::CommandExecutor;
 use crate::agents::AgentSystem;
 
@@ -20,9 +22,11 @@ pub struct WebServer {
     config: Config,
     linux_integration: LinuxIntegration,
     command_executor: Arc<RwLock<CommandExecutor>>,
+    /// Shared separately so approvals can be decided while an execution waits on the executor
+    approval_broker: ApprovalBroker,
     agent_system: Arc<RwLock<AgentSystem>>,
     active_sessions: Arc<RwLock<HashMap<String, WebSession>>>,
-    auth_manager: auth::AuthManager,
+    auth_manager: Arc<RwLock<auth::AuthManager>>,
     chat_sessions: Arc<RwLock<HashMap<String, ChatSession>>>,
     chat_history: Arc<RwLock<HashMap<String, Vec<ChatMessage>>>>,
     config_backups: Arc<RwLock<HashMap<String, ConfigBackup>>>,
@@ -106,6 +110,12 @@ pub struct ChatRequest {
     pub context: Option<serde_json::Value>,
 }
 
+/// Body of an approve/deny call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovalDecisionRequest {
+    pub comment: Option<String>,
+}
+
 /// Configuration management structures
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConfigurationSchema {
@@ -229,6 +239,13 @@ pub enum WebSocketMessage {
         sender: ChatSender,
         is_typing: bool,
     },
+    ApprovalUpdate {
+        approval: Box<PendingApproval>,
+    },
+    /// A journal entry on the followed log stream
+    JournalRecord {
+        record: Box<LogRecord>,
+    },
     Error {
         error_code: String,
         message: String,
@@ -329,14 +346,16 @@ impl WebServer {
         agent_system: AgentSystem,
     ) -> Result<Self> {
         let auth_manager = auth::AuthManager::new(&config).await?;
+        let approval_broker = command_executor.approval_broker().clone();
 
         Ok(Self {
             config,
             linux_integration,
             command_executor: Arc::new(RwLock::new(command_executor)),
+            approval_broker,
             agent_system: Arc::new(RwLock::new(agent_system)),
             active_sessions: Arc::new(RwLock::new(HashMap::new())),
-            auth_manager,
+            auth_manager: Arc::new(RwLock::new(auth_manager)),
             chat_sessions: Arc::new(RwLock::new(HashMap::new())),
             chat_history: Arc::new(RwLock::new(HashMap::new())),
             config_backups: Arc::new(RwLock::new(HashMap::new())),
@@ -355,7 +374,7 @@ impl WebServer {
 
     pub async fn create_session(&self, user_id: String, ip_address: String, user_agent: String) -> Result<WebSession> {
         let session_id = uuid::Uuid::new_v4().to_string();
-        let permissions = self.auth_manager.get_user_permissions(&user_id).await?;
+        let permissions = self.auth_manager.read().await.get_user_permissions(&user_id).await?;
 
         let session = WebSession {
             session_id: session_id.clone(),
@@ -375,6 +394,18 @@ impl WebServer {
         self.active_sessions.read().await.get(session_id).cloned()
     }
 
+    /// The user a session ID or API key belongs to; `None` for anything else. Decisions
+    /// are recorded under this user, so two keys of the same user are one approver.
+    pub async fn authenticate(&self, token: &str) -> Option<String> {
+        if let Some(session) = self.get_session(token).await {
+            return Some(session.user_id);
+        }
+        match self.auth_manager.write().await.authenticate_api_key(token).await {
+            Ok(auth::AuthResult::Success(user_id)) => Some(user_id),
+            _ => None,
+        }
+    }
+
     pub async fn update_session_activity(&self, session_id: &str) -> Result<()> {
         if let Some(session) = self.active_sessions.write().await.get_mut(session_id) {
             session.last_activity = chrono::Utc::now();
@@ -409,18 +440,12 @@ impl WebServer {
             return Err(anyhow::anyhow!("Insufficient permissions"));
         }
 
-        // Get system logs
-        let output = tokio::process::Command::new("journalctl")
-            .args(&["-n", &lines.to_string(), "--no-pager"])
-            .output()
-            .await?;
-
-        let logs = String::from_utf8_lossy(&output.stdout)
-            .lines()
-            .map(|line| line.to_string())
-            .collect();
-
-        Ok(logs)
+        let reader = JournalReader::new(self.linux_integration.transport().clone());
+        let query = JournalQuery { lines: Some(lines), ..Default::default() };
+        Ok(reader.read(&query).await?
+            .iter()
+            .map(|record| record.to_string())
+            .collect())
     }
 
     // Chat functionality
@@ -1,8 +1,8 @@
 use anyhow::Result;
 use axum::{
-    extract::{Path, Query, State},
+    extract::{ws::WebSocketUpgrade, Path, Query, State},
     http::{HeaderMap, StatusCode},
-    response::{Html, Json},
+    response::{Html, Json, Response},
     routing::{get, post},
     Router,
 };
@@ -13,7 +13,9 @@ use tower::ServiceBuilder;
 use tower_http::{cors::CorsLayer, services::ServeDir};
 // ServeFile removed - no longer serving static CSS
 
-use super::{WebServer, ChatRequest, ConfigUpdateRequest};
+use super::{WebServer, ApprovalDecisionRequest, ChatRequest, ConfigUpdateRequest};
+use crate::journal::{JournalQuery, JournalReader};
+use crate::remote::transport::{LocalTransport, Transport};
 
 /// HTTP server implementation
 pub struct HttpServer {
@@ -37,6 +39,17 @@ impl HttpServer {
             .route("/api/system/status", get(system_status))
             .route("/api/commands/execute", post(execute_command))
             .route("/api/logs", get(get_logs))
+            .route("/api/logs/ws", get(logs_websocket))
+            .route("/api/audit", get(get_audit_log))
+            // Packages
+            .route("/api/packages", get(list_packages))
+            .route("/api/packages/:name", get(get_package))
+            // Approval queue
+            .route("/api/approvals", get(list_approvals))
+            .route("/api/approvals/ws", get(approvals_websocket))
+            .route("/api/approvals/:approval_id", get(get_approval))
+            .route("/api/approvals/:approval_id/approve", post(approve_request))
+            .route("/api/approvals/:approval_id/deny", post(deny_request))
             // Chat endpoints
             .route("/api/chat", post(send_chat_message))
             .route("/api/chat/sessions", get(get_chat_sessions))
@@ -152,7 +165,13 @@ async fn api_info() -> Json<Value> {
         "endpoints": {
             "GET /api/system/status": "System status information",
             "POST /api/commands/execute": "Execute commands (requires auth)",
-            "GET /api/logs": "System logs (requires auth)",
+            "GET /api/logs": "Journal entries filtered by ?unit=, priority, boot, pid, since, until, lines and after (cursor) (requires auth)",
+            "GET /api/logs/ws": "WebSocket stream of new journal entries, same filters as /api/logs (requires auth)",
+            "GET /api/audit": "Filtered, paginated audit log (requires auth)",
+            "GET /api/approvals": "Queued execution requests, ?status=pending (requires auth)",
+            "GET /api/approvals/ws": "WebSocket stream of approval updates; accepts approve/deny messages (requires auth)",
+            "POST /api/approvals/:approval_id/approve": "Approve a queued request with an optional comment (requires auth)",
+            "POST /api/approvals/:approval_id/deny": "Deny a queued request with an optional comment (requires auth)",
             "POST /api/chat": "Send chat message to AI agents (requires auth)",
             "POST /api/chat/session": "Create new chat session (requires auth)",
             "GET /api/chat/sessions": "Get user's chat sessions (requires auth)",
@@ -223,37 +242,330 @@ async fn execute_command(
     }
 }
 
+/// Journal filters from query parameters; `unit`, `identifier` and `transport` may list
+/// several values separated by commas
+fn journal_query(params: &HashMap<String, String>) -> Result<JournalQuery, StatusCode> {
+    Ok(JournalQuery {
+        units: params.get("unit")
+            .map(|units| units.split(',').map(|unit| unit.trim().to_string()).filter(|unit| !unit.is_empty()).collect())
+            .unwrap_or_default(),
+        identifiers: params.get("identifier")
+            .map(|identifiers| identifiers.split(',').map(|identifier| identifier.trim().to_string()).filter(|identifier| !identifier.is_empty()).collect())
+            .unwrap_or_default(),
+        transports: params.get("transport")
+            .map(|transports| transports.split(',').map(|transport| transport.trim().to_string()).filter(|transport| !transport.is_empty()).collect())
+            .unwrap_or_default(),
+        priority: params.get("priority")
+            .map(|p| crate::journal::parse_priority(p))
+            .transpose()
+            .map_err(|_| StatusCode::BAD_REQUEST)?,
+        boot: params.get("boot").cloned(),
+        pid: params.get("pid")
+            .map(|pid| pid.parse())
+            .transpose()
+            .map_err(|_| StatusCode::BAD_REQUEST)?,
+        since: params.get("since").cloned(),
+        until: params.get("until").cloned(),
+        lines: Some(params.get("lines")
+            .map(|lines| lines.parse())
+            .transpose()
+            .map_err(|_| StatusCode::BAD_REQUEST)?
+            .unwrap_or(100)),
+        after_cursor: params.get("after").cloned(),
+    })
+}
+
 async fn get_logs(
-    State(_web_server): State<WebServer>,
+    State(web_server): State<WebServer>,
     headers: HeaderMap,
     Query(params): Query<HashMap<String, String>>,
 ) -> Result<Json<Value>, StatusCode> {
-    // Check authorization
-    if headers.get("authorization").is_none() {
-        return Err(StatusCode::UNAUTHORIZED);
+    authenticated_user(&web_server, &headers).await?;
+
+    let query = journal_query(&params)?;
+    let reader = JournalReader::new(web_server.linux_integration.transport().clone());
+    match reader.read(&query).await {
+        // The last cursor lets the viewer ask for what came after via ?after=
+        Ok(records) => Ok(Json(json!({
+            "cursor": records.last().map(|record| record.cursor.clone()).or(query.after_cursor),
+            "total": records.len(),
+            "logs": records,
+            "timestamp": chrono::Utc::now().to_rfc3339()
+        }))),
+        Err(e) => {
+            eprintln!("Journal query error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
     }
+}
 
-    let lines = params.get("lines")
-        .and_then(|l| l.parse::<usize>().ok())
-        .unwrap_or(10);
+async fn logs_websocket(
+    ws: WebSocketUpgrade,
+    State(web_server): State<WebServer>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response, StatusCode> {
+    // Browsers cannot set headers on WebSocket requests, so accept ?token= as well
+    let token = bearer_token(&headers)
+        .map(str::to_string)
+        .or_else(|| params.get("token").cloned())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    web_server.authenticate(&token).await.ok_or(StatusCode::UNAUTHORIZED)?;
+    let query = journal_query(&params)?;
+
+    Ok(ws.on_upgrade(move |socket| async move {
+        web_server.handle_log_socket(socket, query).await
+    }))
+}
 
-    // Mock log data
-    let logs = (0..lines).map(|i| {
-        json!({
-            "timestamp": chrono::Utc::now().to_rfc3339(),
-            "level": "INFO",
-            "source": "tuxpilot",
-            "message": format!("Sample log entry {}", i + 1)
-        })
-    }).collect::<Vec<_>>();
+fn parse_audit_query(params: &HashMap<String, String>) -> anyhow::Result<crate::execution::audit::query::AuditQuery> {
+    use crate::execution::audit::query;
+
+    let mut audit_query = query::AuditQuery::new();
+    audit_query.since = params.get("since").map(|v| query::parse_time_bound(v)).transpose()?;
+    audit_query.until = params.get("until").map(|v| query::parse_time_bound(v)).transpose()?;
+    audit_query.user = params.get("user").cloned();
+    audit_query.session_id = params.get("session").cloned();
+    audit_query.min_risk = params.get("risk").map(|v| query::parse_risk_level(v)).transpose()?;
+    if let Some(types) = params.get("type") {
+        audit_query.entry_types = types.split(',')
+            .map(query::parse_entry_type)
+            .collect::<anyhow::Result<_>>()?;
+    }
+    audit_query.command_contains = params.get("grep").cloned();
+    audit_query.success = params.get("success").map(|v| v.parse::<bool>()).transpose()?;
+    audit_query.page = params.get("page")
+        .and_then(|p| p.parse::<usize>().ok())
+        .unwrap_or(1);
+    audit_query.per_page = params.get("per_page")
+        .and_then(|p| p.parse::<usize>().ok())
+        .unwrap_or(50)
+        .min(500);
+
+    Ok(audit_query)
+}
+
+async fn get_audit_log(
+    State(web_server): State<WebServer>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Value>, StatusCode> {
+    authenticated_user(&web_server, &headers).await?;
+
+    let audit_query = parse_audit_query(&params).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let executor = web_server.command_executor.read().await;
+    match executor.audit_logger().query(audit_query).await {
+        Ok(page) => Ok(Json(json!({
+            "entries": page.hits,
+            "total": page.total,
+            "page": page.page,
+            "per_page": page.per_page,
+            "total_pages": page.total_pages,
+            "timestamp": chrono::Utc::now().to_rfc3339()
+        }))),
+        Err(e) => {
+            eprintln!("Audit query error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// Package endpoints
+
+/// Installed packages from every detected source; `?upgradable=true` lists available
+/// upgrades, `?q=` searches and `?source=flatpak` narrows to one package manager
+async fn list_packages(
+    State(web_server): State<WebServer>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Value>, StatusCode> {
+    authenticated_user(&web_server, &headers).await?;
+
+    let source = params.get("source")
+        .map(|s| s.parse::<crate::config::PackageManager>())
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let sources = web_server.linux_integration.package_sources(source.as_ref());
+    let packages = if let Some(query) = params.get("q") {
+        sources.search(query).await
+    } else if params.get("upgradable").map(|v| v == "true").unwrap_or(false) {
+        sources.list_upgradable().await
+    } else {
+        sources.list_installed().await
+    };
+
+    match packages {
+        Ok(packages) => Ok(Json(json!({
+            "sources": sources.managers(),
+            "packages": packages,
+            "total": packages.len(),
+            "timestamp": chrono::Utc::now().to_rfc3339()
+        }))),
+        Err(e) => {
+            eprintln!("Package query error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_package(
+    State(web_server): State<WebServer>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    authenticated_user(&web_server, &headers).await?;
+
+    let sources = web_server.linux_integration.package_sources(None);
+    let package = match sources.info(&name).await {
+        Ok(Some(package)) => package,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            eprintln!("Package query error: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let backend = package.source.as_ref().and_then(|source| sources.backend(source));
+    let reason = match backend {
+        Some(backend) if package.installed => backend.why_installed(&name).await.ok(),
+        _ => None,
+    };
 
     Ok(Json(json!({
-        "logs": logs,
-        "total": lines,
+        "package": package,
+        "install_reason": reason,
         "timestamp": chrono::Utc::now().to_rfc3339()
     })))
 }
 
+// Approval endpoints
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get("authorization")?.to_str().ok()?;
+    Some(value.strip_prefix("Bearer ").unwrap_or(value))
+}
+
+/// The user whose session ID or API key the request carries
+async fn authenticated_user(web_server: &WebServer, headers: &HeaderMap) -> Result<String, StatusCode> {
+    let token = bearer_token(headers).ok_or(StatusCode::UNAUTHORIZED)?;
+    web_server.authenticate(token).await.ok_or(StatusCode::UNAUTHORIZED)
+}
+
+async fn list_approvals(
+    State(web_server): State<WebServer>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Value>, StatusCode> {
+    use crate::execution::approval::ApprovalStatus;
+
+    authenticated_user(&web_server, &headers).await?;
+
+    let status = params.get("status")
+        .map(|s| s.parse::<ApprovalStatus>())
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    match web_server.approval_broker.list(status).await {
+        Ok(approvals) => Ok(Json(json!({
+            "total": approvals.len(),
+            "approvals": approvals,
+            "timestamp": chrono::Utc::now().to_rfc3339()
+        }))),
+        Err(e) => {
+            eprintln!("Approval queue error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_approval(
+    State(web_server): State<WebServer>,
+    headers: HeaderMap,
+    Path(approval_id): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    authenticated_user(&web_server, &headers).await?;
+
+    let approval_id = uuid::Uuid::parse_str(&approval_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    match web_server.approval_broker.get(approval_id).await {
+        Ok(Some(approval)) => Ok(Json(json!({
+            "approval": approval,
+            "timestamp": chrono::Utc::now().to_rfc3339()
+        }))),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            eprintln!("Approval queue error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn approve_request(
+    State(web_server): State<WebServer>,
+    headers: HeaderMap,
+    Path(approval_id): Path<String>,
+    body: Option<Json<ApprovalDecisionRequest>>,
+) -> Result<Json<Value>, StatusCode> {
+    let comment = body.and_then(|Json(b)| b.comment);
+    decide_approval(&web_server, &headers, &approval_id, true, comment).await
+}
+
+async fn deny_request(
+    State(web_server): State<WebServer>,
+    headers: HeaderMap,
+    Path(approval_id): Path<String>,
+    body: Option<Json<ApprovalDecisionRequest>>,
+) -> Result<Json<Value>, StatusCode> {
+    let comment = body.and_then(|Json(b)| b.comment);
+    decide_approval(&web_server, &headers, &approval_id, false, comment).await
+}
+
+async fn decide_approval(
+    web_server: &WebServer,
+    headers: &HeaderMap,
+    approval_id: &str,
+    approved: bool,
+    comment: Option<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let approver = authenticated_user(web_server, headers).await?;
+    let approval_id = uuid::Uuid::parse_str(approval_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    match web_server.approval_broker.get(approval_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+
+    match web_server.approval_broker.decide(approval_id, &approver, approved, comment).await {
+        Ok(approval) => Ok(Json(json!({
+            "approval": approval,
+            "timestamp": chrono::Utc::now().to_rfc3339()
+        }))),
+        Err(e) => {
+            // Already decided, expired, or the same approver twice
+            eprintln!("Approval decision rejected: {}", e);
+            Err(StatusCode::CONFLICT)
+        }
+    }
+}
+
+async fn approvals_websocket(
+    ws: WebSocketUpgrade,
+    State(web_server): State<WebServer>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response, StatusCode> {
+    // Browsers cannot set headers on WebSocket requests, so accept ?token= as well
+    let token = bearer_token(&headers)
+        .map(str::to_string)
+        .or_else(|| params.get("token").cloned())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let approver = web_server.authenticate(&token).await.ok_or(StatusCode::UNAUTHORIZED)?;
+
+    Ok(ws.on_upgrade(move |socket| async move {
+        web_server.handle_approval_socket(socket, approver).await
+    }))
+}
+
 // Chat endpoints
 async fn send_chat_message(
     State(web_server): State<WebServer>,
@@ -814,9 +1126,9 @@ async fn get_available_ai_providers(
     let mut providers = json!({});
 
     // Check for Ollama
-    if let Ok(output) = std::process::Command::new("ollama").arg("list").output() {
-        if output.status.success() {
-            let models_output = String::from_utf8_lossy(&output.stdout);
+    if let Ok(output) = LocalTransport.run("ollama", &["list"]).await {
+        if output.success() {
+            let models_output = &output.stdout;
             let models: Vec<String> = models_output
                 .lines()
                 .skip(1) // Skip header
@@ -1,8 +1,24 @@
 // WebSocket implementation for real-time updates
 
 use anyhow::Result;
+use axum::extract::ws::{Message, WebSocket};
+use futures::StreamExt;
+use serde::Deserialize;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::execution::approval::ApprovalStatus;
+use crate::journal::{JournalQuery, JournalReader};
 use super::{WebSocketMessage, WebServer};
 
+/// Decisions a client can send on the approvals socket
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+enum ApprovalCommand {
+    Approve { id: Uuid, comment: Option<String> },
+    Deny { id: Uuid, comment: Option<String> },
+}
+
 impl WebServer {
     pub async fn handle_websocket_connection(&self, _session_id: String) -> Result<()> {
         println!("🔌 WebSocket connection established");
@@ -33,4 +49,93 @@ impl WebServer {
         println!("📤 Sending command output: {:?}", message);
         Ok(())
     }
+
+    /// Stream approval updates to one client and apply the approve/deny messages it sends
+    pub async fn handle_approval_socket(&self, mut socket: WebSocket, approver: String) {
+        let mut updates = self.approval_broker.subscribe();
+
+        // Start with the current queue so the client does not miss older requests
+        if let Ok(pending) = self.approval_broker.list(Some(ApprovalStatus::Pending)).await {
+            for approval in pending {
+                if send_message(&mut socket, &WebSocketMessage::ApprovalUpdate { approval: Box::new(approval) }).await.is_err() {
+                    return;
+                }
+            }
+        }
+
+        loop {
+            tokio::select! {
+                update = updates.recv() => match update {
+                    Ok(approval) => {
+                        if send_message(&mut socket, &WebSocketMessage::ApprovalUpdate { approval: Box::new(approval) }).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                incoming = socket.recv() => match incoming {
+                    Some(Ok(Message::Text(text))) => {
+                        // The resulting update reaches every client through the broadcast
+                        if let Err(e) = self.apply_approval_command(&text, &approver).await {
+                            let error = WebSocketMessage::Error {
+                                error_code: "approval_failed".to_string(),
+                                message: format!("{:#}", e),
+                            };
+                            if send_message(&mut socket, &error).await.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+    }
+
+    /// Send journal entries matching `query` as they are written, until the client
+    /// goes away
+    pub async fn handle_log_socket(&self, mut socket: WebSocket, query: JournalQuery) {
+        let reader = JournalReader::new(self.linux_integration.transport().clone());
+        let records = reader.follow(query, std::time::Duration::from_secs(2));
+        futures::pin_mut!(records);
+
+        loop {
+            tokio::select! {
+                record = records.next() => {
+                    let message = match record {
+                        Some(Ok(record)) => WebSocketMessage::JournalRecord { record: Box::new(record) },
+                        Some(Err(e)) => WebSocketMessage::Error {
+                            error_code: "journal_failed".to_string(),
+                            message: format!("{:#}", e),
+                        },
+                        None => break,
+                    };
+                    if send_message(&mut socket, &message).await.is_err() {
+                        break;
+                    }
+                }
+                incoming = socket.recv() => match incoming {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+    }
+
+    async fn apply_approval_command(&self, text: &str, approver: &str) -> Result<()> {
+        let (id, approved, comment) = match serde_json::from_str::<ApprovalCommand>(text)? {
+            ApprovalCommand::Approve { id, comment } => (id, true, comment),
+            ApprovalCommand::Deny { id, comment } => (id, false, comment),
+        };
+
+        self.approval_broker.decide(id, approver, approved, comment).await?;
+        Ok(())
+    }
+}
+
+async fn send_message(socket: &mut WebSocket, message: &WebSocketMessage) -> Result<()> {
+    socket.send(Message::Text(serde_json::to_string(message)?)).await?;
+    Ok(())
 }
@@ -3,6 +3,52 @@ use predicates::prelude::*;
 use tempfile::TempDir;
 use std::fs;
 
+/// A scratch directory with `stubs` installed as executable scripts in its `bin`
+/// directory, and a tuxpilot command that runs them in place of the real tools
+fn with_stubs(stubs: &[(&str, &str)]) -> (TempDir, Command) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = TempDir::new().unwrap();
+    let bin_dir = temp_dir.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    for (name, script) in stubs {
+        let stub = bin_dir.join(name);
+        fs::write(&stub, script).unwrap();
+        fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    let cmd = tuxpilot(&temp_dir);
+    (temp_dir, cmd)
+}
+
+/// tuxpilot with its config and data in `temp_dir`, finding the stubs in
+/// `temp_dir/bin` ahead of the real tools
+fn tuxpilot(temp_dir: &TempDir) -> Command {
+    let path = format!("{}:{}", temp_dir.path().join("bin").display(), std::env::var("PATH").unwrap_or_default());
+    let mut cmd = Command::cargo_bin("tuxpilot").unwrap();
+    cmd.env("XDG_DATA_HOME", temp_dir.path())
+        .env("PATH", path)
+        .arg("--config")
+        .arg(temp_dir.path().join("config.toml"));
+    cmd
+}
+
+/// Create the default config the way a first run does, and return it
+fn default_config(temp_dir: &TempDir) -> String {
+    tuxpilot(temp_dir).args(["audit", "--limit", "1"]).assert().success();
+    fs::read_to_string(temp_dir.path().join("config.toml")).unwrap()
+}
+
+/// Create the default config, pointed at an inventory listing `hosts`
+fn use_inventory(temp_dir: &TempDir, hosts: &str) {
+    let inventory_path = temp_dir.path().join("inventory.toml");
+    fs::write(&inventory_path, hosts).unwrap();
+
+    let config = default_config(temp_dir)
+        .replace("[remote]\n", &format!("[remote]\ninventory = {:?}\n", inventory_path));
+    fs::write(temp_dir.path().join("config.toml"), config).unwrap();
+}
+
 #[test]
 fn test_help_command() {
     let mut cmd = Command::cargo_bin("tuxpilot").unwrap();
@@ -25,7 +71,7 @@ fn test_version_command() {
 fn test_config_show() {
     let temp_dir = TempDir::new().unwrap();
     let config_path = temp_dir.path().join("config.toml");
-    
+
     // Create a test config file
     let config_content = r#"
 [ai]
@@ -45,15 +91,15 @@ theme = "default"
 show_tips = true
 auto_suggest = true
 "#;
-    
+
     fs::write(&config_path, config_content).unwrap();
-    
+
     let mut cmd = Command::cargo_bin("tuxpilot").unwrap();
     cmd.arg("--config")
         .arg(&config_path)
         .arg("config")
         .arg("--show");
-    
+
     cmd.assert()
         .success()
         .stdout(predicate::str::contains("Current Configuration"));
@@ -85,10 +131,1369 @@ fn test_diagnose_without_ai() {
     cmd.arg("diagnose")
         .arg("--input")
         .arg("test error message");
-    
+
     // This might fail without AI configuration, but should handle gracefully
     let output = cmd.output().unwrap();
-    
+
     // Check that it doesn't panic
     assert!(output.status.code().is_some());
 }
+
+#[test]
+fn test_audit_verify_detects_tampering() {
+    let temp_dir = TempDir::new().unwrap();
+    let audit_dir = temp_dir.path().join("tuxpilot").join("audit");
+
+    // An empty audit log is trivially intact
+    tuxpilot(&temp_dir).args(["audit", "verify"]).assert()
+        .success()
+        .stdout(predicate::str::contains("Audit log is intact"));
+
+    // An entry whose content no longer matches its hash must be reported
+    let forged_entry = r#"{"id":"6f9619ff-8b86-d011-b42d-00c04fc964ff","timestamp":"2025-01-01T00:00:00Z","entry_type":"Error","user":"test","session_id":"1","data":{"error":"edited"},"sequence":1,"prev_hash":"0000000000000000000000000000000000000000000000000000000000000000","hash":"deadbeef"}"#;
+    fs::write(audit_dir.join("audit.jsonl"), format!("{}\n", forged_entry)).unwrap();
+
+    tuxpilot(&temp_dir).args(["audit", "verify"]).assert()
+        .failure()
+        .stdout(predicate::str::contains("ModifiedEntry"));
+}
+
+#[test]
+fn test_audit_signatures_verify_only_against_pinned_key() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("config.toml");
+    let audit_dir = temp_dir.path().join("tuxpilot").join("audit");
+    let queue_dir = temp_dir.path().join("tuxpilot").join("approvals");
+    fs::create_dir_all(&queue_dir).unwrap();
+
+    let run = |args: &[&str]| {
+        let mut cmd = tuxpilot(&temp_dir);
+        cmd.env("XDG_CONFIG_HOME", temp_dir.path()).args(args);
+        cmd
+    };
+
+    let config = default_config(&temp_dir).replace("sign_entries = false", "sign_entries = true");
+    fs::write(&config_path, &config).unwrap();
+
+    // Deciding an approval writes a signed audit entry
+    let queue = r#"[{"id":"6f9619ff-8b86-d011-b42d-00c04fc96401","request":{"id":"6f9619ff-8b86-d011-b42d-00c04fc964ff","command":"systemctl","args":["restart","nginx"],"description":"Restart nginx","risk_level":"Medium","required_permissions":["ServiceManagement"],"context":{"user_request":"","ai_reasoning":"","expected_outcome":"","rollback_plan":null}},"requested_by":"web","created_at":"2099-01-01T00:00:00Z","expires_at":"2099-01-02T00:00:00Z","required_approvals":1,"decisions":[],"status":"Pending"}]"#;
+    fs::write(queue_dir.join("queue.json"), queue).unwrap();
+    run(&["approvals", "deny", "6f9619ff-8b86-d011-b42d-00c04fc96401"]).assert().success();
+
+    // The signing key lives next to the config, not in the audit directory
+    let key_path = temp_dir.path().join("tuxpilot").join("audit.key");
+    assert_eq!(fs::metadata(&key_path).unwrap().permissions().mode() & 0o777, 0o600);
+    assert!(fs::read_dir(&audit_dir).unwrap().all(|entry| !entry.unwrap().file_name().to_string_lossy().ends_with(".key")));
+
+    // Without a pinned key the signatures cannot be trusted
+    run(&["audit", "verify"]).assert()
+        .failure()
+        .stdout(predicate::str::contains("no trusted host public key"));
+
+    let pinned = temp_dir.path().join("audit.pub");
+    fs::copy(key_path.with_extension("pub"), &pinned).unwrap();
+    fs::write(&config_path, config.replace("sign_entries = true", &format!("sign_entries = true\nverifying_key_path = {:?}", pinned))).unwrap();
+    run(&["audit", "verify"]).assert()
+        .success()
+        .stdout(predicate::str::contains("Audit log is intact"));
+
+    // Stripping every signature leaves the hash chain intact, but not an unsigned log
+    let log_path = audit_dir.join("audit.jsonl");
+    let signed = fs::read_to_string(&log_path).unwrap();
+    let stripped: String = signed.lines()
+        .map(|line| {
+            let mut entry: serde_json::Value = serde_json::from_str(line).unwrap();
+            entry.as_object_mut().unwrap().remove("signature");
+            format!("{}\n", entry)
+        })
+        .collect();
+    fs::write(&log_path, stripped).unwrap();
+    run(&["audit", "verify"]).assert()
+        .failure()
+        .stdout(predicate::str::contains("MissingSignature"));
+    fs::write(&log_path, signed).unwrap();
+
+    // A key in the directory whoever writes the log can also write is not accepted
+    let inside = audit_dir.join("audit.pub");
+    fs::copy(&pinned, &inside).unwrap();
+    fs::write(&config_path, config.replace("sign_entries = true", &format!("sign_entries = true\nverifying_key_path = {:?}", inside))).unwrap();
+    run(&["audit", "verify"]).assert()
+        .failure()
+        .stderr(predicate::str::contains("outside the audit directory"));
+}
+
+#[test]
+fn test_audit_query_filters() {
+    let temp_dir = TempDir::new().unwrap();
+    let audit_dir = temp_dir.path().join("tuxpilot").join("audit");
+    fs::create_dir_all(&audit_dir).unwrap();
+
+    let entries = [
+        r#"{"id":"6f9619ff-8b86-d011-b42d-00c04fc964f1","timestamp":"2025-01-01T00:00:00Z","entry_type":"ExecutionRequest","user":"alice","session_id":"1","data":{"execution_id":"6f9619ff-8b86-d011-b42d-00c04fc964aa","command":"systemctl restart nginx","risk_level":"High"},"sequence":1}"#,
+        r#"{"id":"6f9619ff-8b86-d011-b42d-00c04fc964f2","timestamp":"2025-01-02T00:00:00Z","entry_type":"Error","user":"bob","session_id":"2","data":{"error":"disk full"},"sequence":2}"#,
+    ];
+    fs::write(audit_dir.join("audit.jsonl"), format!("{}\n", entries.join("\n"))).unwrap();
+
+    tuxpilot(&temp_dir).args(["audit", "--risk", "high", "--grep", "systemctl"]).assert()
+        .success()
+        .stdout(predicate::str::contains("Showing 1 of 1 matching entries"))
+        .stdout(predicate::str::contains("systemctl restart nginx"));
+
+    tuxpilot(&temp_dir).args(["audit", "--user", "bob", "--since", "2025-01-02"]).assert()
+        .success()
+        .stdout(predicate::str::contains("Showing 1 of 1 matching entries"))
+        .stdout(predicate::str::contains("bob"));
+
+    // A non-ASCII unit is an error, not a panic
+    for since in ["7ä", "ä"] {
+        tuxpilot(&temp_dir).args(["audit", "--since", since]).assert()
+            .failure()
+            .stderr(predicate::str::contains("Invalid time value").or(predicate::str::contains("Unknown time unit")))
+            .stderr(predicate::str::contains("panicked").not());
+    }
+}
+
+#[test]
+fn test_audit_sinks_deliver_to_local_syslog() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("config.toml");
+
+    let listener = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    listener.set_read_timeout(Some(std::time::Duration::from_secs(5))).unwrap();
+
+    // Create the default config, then add a sink pointing at the listener
+    tuxpilot(&temp_dir).args(["audit", "test-sinks"]).assert()
+        .success()
+        .stdout(predicate::str::contains("No audit sinks configured"));
+
+    let mut config = fs::read_to_string(&config_path).unwrap();
+    config.push_str(&format!(
+        "\n[[audit.sinks]]\nname = \"soc\"\nkind = \"Syslog\"\nformat = \"Cef\"\ntransport = \"Udp\"\naddress = \"{}\"\n",
+        listener.local_addr().unwrap()
+    ));
+    fs::write(&config_path, config).unwrap();
+
+    tuxpilot(&temp_dir).args(["audit", "test-sinks"]).assert()
+        .success()
+        .stdout(predicate::str::contains("soc: delivered"));
+
+    let mut buffer = [0u8; 4096];
+    let len = listener.recv(&mut buffer).unwrap();
+    let message = String::from_utf8_lossy(&buffer[..len]);
+    assert!(message.starts_with("<85>1 "), "unexpected syslog header: {}", message);
+    assert!(message.contains("CEF:0|TuxPilot|TuxPilot|"));
+}
+
+#[test]
+fn test_audit_sinks_spool_entries_until_the_target_is_back() {
+    use std::io::Read;
+
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("config.toml");
+    let spool = temp_dir.path().join("tuxpilot").join("audit").join("spool").join("soc.spool");
+
+    // Nothing listens on a port that was just released
+    let address = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+    let test_sinks = || {
+        let mut cmd = tuxpilot(&temp_dir);
+        cmd.args(["audit", "test-sinks"]);
+        cmd
+    };
+
+    test_sinks().assert().success();
+    let mut config = fs::read_to_string(&config_path).unwrap();
+    config.push_str(&format!(
+        "\n[[audit.sinks]]\nname = \"soc\"\nkind = \"Syslog\"\nformat = \"Rfc5424\"\ntransport = \"Tcp\"\naddress = \"{}\"\n",
+        address
+    ));
+    fs::write(&config_path, config).unwrap();
+
+    test_sinks().assert()
+        .failure()
+        .stdout(predicate::str::contains("soc: "));
+    assert_eq!(fs::read_to_string(&spool).unwrap().lines().count(), 1);
+
+    // The next run sends the spooled entry before its own
+    let listener = std::net::TcpListener::bind(address).unwrap();
+    test_sinks().assert()
+        .success()
+        .stdout(predicate::str::contains("soc: delivered"));
+
+    let (mut stream, _) = listener.accept().unwrap();
+    stream.set_read_timeout(Some(std::time::Duration::from_secs(5))).unwrap();
+    let mut received = String::new();
+    let _ = stream.read_to_string(&mut received);
+    assert_eq!(received.matches("TuxPilot audit sink test").count(), 2, "unexpected stream: {}", received);
+    assert!(fs::read_to_string(&spool).unwrap().is_empty());
+}
+
+#[test]
+fn test_privileged_helper_rejects_unapproved_requests() {
+    let request = r#"{"Execute":{"request":{"id":"6f9619ff-8b86-d011-b42d-00c04fc964ff","command":"true","args":[],"description":"test","risk_level":"Safe","required_permissions":["ReadSystem"],"context":{"user_request":"","ai_reasoning":"","expected_outcome":"","rollback_plan":null}},"grant":null}}"#;
+
+    // Without root the helper refuses outright; as root it still needs an approval
+    let mut cmd = Command::cargo_bin("tuxpilot").unwrap();
+    cmd.arg("privileged-helper").write_stdin(request);
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(r#""result":null"#))
+        .stdout(predicate::str::contains("sudo or pkexec").or(predicate::str::contains("No active approval")));
+
+    // The scope comes from the program, not from the permissions the request claims, and
+    // a shell is only run under an approval for that one request
+    let claimed = |command: &str, args: &str| format!(r#"{{"Execute":{{"request":{{"id":"6f9619ff-8b86-d011-b42d-00c04fc964ff","command":"{}","args":{},"description":"test","risk_level":"Low","required_permissions":["PackageManagement","WriteSystem"],"context":{{"user_request":"","ai_reasoning":"","expected_outcome":"","rollback_plan":null}}}},"grant":null}}}}"#, command, args);
+    let mut cmd = Command::cargo_bin("tuxpilot").unwrap();
+    cmd.arg("privileged-helper").write_stdin(claimed("true", "[]"));
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("sudo or pkexec").or(predicate::str::contains("No active approval for ReadSystem")));
+
+    let mut cmd = Command::cargo_bin("tuxpilot").unwrap();
+    cmd.arg("privileged-helper").write_stdin(claimed("bash", r#"["-c","id"]"#));
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("sudo or pkexec").or(predicate::str::contains("bash runs any command it is given and needs a fresh approval")));
+
+    // Grants are only taken by privileged-grant, which sudo -k or pkexec authenticates afresh
+    let mut cmd = Command::cargo_bin("tuxpilot").unwrap();
+    cmd.arg("privileged-helper").write_stdin(r#"{"Grant":{"scope":"PackageManagement","minutes":10}}"#);
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("sudo or pkexec").or(predicate::str::contains("need fresh authentication")));
+}
+
+#[test]
+fn test_approval_queue_decisions() {
+    let temp_dir = TempDir::new().unwrap();
+    let queue_dir = temp_dir.path().join("tuxpilot").join("approvals");
+    fs::create_dir_all(&queue_dir).unwrap();
+
+    // The approver is taken from the password database, not from $USER
+    let output = std::process::Command::new("id").arg("-un").output().unwrap();
+    let me = String::from_utf8(output.stdout).unwrap().trim().to_string();
+
+    let pending = |id: &str, requested_by: &str| format!(r#"{{"id":"{}","request":{{"id":"6f9619ff-8b86-d011-b42d-00c04fc964ff","command":"systemctl","args":["restart","nginx"],"description":"Restart nginx","risk_level":"Medium","required_permissions":["ServiceManagement"],"context":{{"user_request":"","ai_reasoning":"","expected_outcome":"","rollback_plan":null}}}},"requested_by":"{}","created_at":"2099-01-01T00:00:00Z","expires_at":"2099-01-02T00:00:00Z","required_approvals":1,"decisions":[],"status":"Pending"}}"#, id, requested_by);
+    let queue = format!("[{},{}]",
+        pending("6f9619ff-8b86-d011-b42d-00c04fc96401", "web"),
+        pending("6f9619ff-8b86-d011-b42d-00c04fc96402", &me));
+    fs::write(queue_dir.join("queue.json"), queue).unwrap();
+
+    let approve = |id: &str| {
+        let mut cmd = tuxpilot(&temp_dir);
+        cmd.env("USER", "alice")
+            .args(["approvals", "approve", id, "--comment", "looks fine"]);
+        cmd
+    };
+
+    approve("6f9619ff-8b86-d011-b42d-00c04fc96401").assert()
+        .success()
+        .stdout(predicate::str::contains("Approved"))
+        .stdout(predicate::str::contains("Approvals: 1/1"));
+
+    let queue: serde_json::Value = serde_json::from_str(&fs::read_to_string(queue_dir.join("queue.json")).unwrap()).unwrap();
+    let approval = queue.as_array().unwrap().iter()
+        .find(|approval| approval["id"] == "6f9619ff-8b86-d011-b42d-00c04fc96401")
+        .unwrap();
+    assert_eq!(approval["decisions"][0]["approver"], me.as_str());
+
+    // A settled request cannot be decided again
+    approve("6f9619ff-8b86-d011-b42d-00c04fc96401").assert()
+        .failure()
+        .stderr(predicate::str::contains("already Approved"));
+
+    // Nobody approves their own request, whatever $USER says
+    approve("6f9619ff-8b86-d011-b42d-00c04fc96402").assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot approve their own request"));
+}
+
+#[test]
+fn test_execute_reports_side_effects_from_state_diff() {
+    // Probes read the state files; the "download" changes them, as an installer would
+    let (temp_dir, _) = with_stubs(&[
+        ("systemctl", "#!/bin/sh\ncase \"$1\" in\n  list-unit-files) cat \"$STATE/enabled\" ;;\n  list-units) cat \"$STATE/active\" ;;\nesac\n"),
+        ("ss", "#!/bin/sh\ncat \"$STATE/sockets\"\n"),
+        ("dpkg-query", "#!/bin/sh\ncat \"$STATE/packages\"\n"),
+        ("curl", "#!/bin/sh\nprintf 'install ok installed\\tmy-app\\n' >> \"$STATE/packages\"\necho 'my-app.service enabled' > \"$STATE/enabled\"\necho 'my-app.service loaded active running My App' > \"$STATE/active\"\necho 'tcp LISTEN 0 128 0.0.0.0:8080 0.0.0.0:*' > \"$STATE/sockets\"\necho 'port = 8080' >> \"$APP_CONFIG\"\necho 'listen 8080' > \"$CONF_DIR/site one.conf\"\n"),
+    ]);
+    let config_path = temp_dir.path().join("config.toml");
+    let state_dir = temp_dir.path().join("state");
+    let conf_dir = temp_dir.path().join("conf.d");
+    let app_config = temp_dir.path().join("app config.conf");
+    for dir in [&state_dir, &conf_dir] {
+        fs::create_dir_all(dir).unwrap();
+    }
+    fs::write(&app_config, "port = 80\n").unwrap();
+    fs::write(state_dir.join("packages"), "install ok installed\tbash\n").unwrap();
+    for probe in ["enabled", "active", "sockets"] {
+        fs::write(state_dir.join(probe), "").unwrap();
+    }
+
+    // Create the default config, then pin the package manager and watch the app's files
+    let mut config = String::new();
+    let mut in_watched_paths = false;
+    for line in default_config(&temp_dir).lines() {
+        if line.starts_with("package_manager = ") {
+            config.push_str("package_manager = \"Apt\"\n");
+        } else if line.starts_with("watched_paths = [") {
+            in_watched_paths = true;
+            config.push_str(&format!("watched_paths = [{:?}, {:?}]\n", app_config, conf_dir));
+        } else if in_watched_paths {
+            in_watched_paths = line != "]";
+        } else {
+            config.push_str(line);
+            config.push('\n');
+        }
+    }
+    fs::write(&config_path, config).unwrap();
+
+    tuxpilot(&temp_dir)
+        .env("STATE", &state_dir)
+        .env("APP_CONFIG", &app_config)
+        .env("CONF_DIR", &conf_dir)
+        .args(["execute", "install the app", "--command", "curl http://deploy.invalid/my-app", "--mode", "autonomous"])
+        .assert()
+        .success();
+
+    let audit_log = fs::read_to_string(temp_dir.path().join("tuxpilot").join("audit").join("audit.jsonl")).unwrap();
+    let result: serde_json::Value = audit_log.lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .find(|entry| entry["entry_type"] == "ExecutionResult")
+        .unwrap();
+    let effects: Vec<(String, String)> = result["data"]["side_effects"].as_array().unwrap().iter()
+        .map(|effect| (effect["description"].as_str().unwrap().to_string(),
+                       effect["rollback_command"].as_str().unwrap_or_default().to_string()))
+        .collect();
+    let rollback = |description: &str| effects.iter()
+        .find(|(found, _)| found == description)
+        .map(|(_, rollback)| rollback.clone())
+        .unwrap_or_else(|| panic!("no side effect {:?} in {:?}", description, effects));
+
+    assert_eq!(rollback("Package installed: my-app"), "apt-get remove -y -- my-app");
+    assert_eq!(rollback("Unit enabled: my-app.service"), "systemctl disable my-app.service");
+    assert_eq!(rollback("Unit started: my-app.service"), "systemctl stop my-app.service");
+    assert_eq!(rollback("Listening socket opened: tcp 0.0.0.0:8080"), "");
+
+    // Paths are quoted, so a rollback cannot be split at the space
+    let site = conf_dir.join("site one.conf");
+    assert_eq!(rollback(&format!("File created: {}", site.display())), format!("rm -f '{}'", site.display()));
+    let restore = rollback(&format!("File modified: {}", app_config.display()));
+    assert!(restore.starts_with("cp -a ") && restore.ends_with(&format!(" '{}'", app_config.display())), "{}", restore);
+}
+
+#[test]
+fn test_execute_on_inventory_group() {
+    // Stand-in for ssh that runs the remote command locally, so no sshd is needed
+    let (temp_dir, _) = with_stubs(&[("ssh", r#"#!/bin/sh
+while [ "$1" != "--" ]; do shift; done
+shift
+if [ "$1" = "unreachable" ]; then echo "ssh: connect to host unreachable: Connection refused" >&2; exit 255; fi
+shift
+exec sh -c "$*"
+"#)]);
+    use_inventory(&temp_dir, r#"
+[[hosts]]
+name = "web01"
+address = "127.0.0.1"
+groups = ["web"]
+
+[[hosts]]
+name = "web02"
+address = "unreachable"
+groups = ["web"]
+"#);
+
+    tuxpilot(&temp_dir)
+        .args(["execute", "kernel name", "--command", "uname -s", "--group", "web", "--mode", "semi-auto"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Linux"))
+        .stdout(predicate::str::contains("Results: 1 succeeded, 1 failed"))
+        .stdout(predicate::str::contains("SSH connection to web02 failed"));
+
+    // A remote command may exit 255 itself; that is its status, not a failed
+    // connection, and quoted words reach the remote shell intact
+    tuxpilot(&temp_dir)
+        .args(["execute", "remote exit", "--command", r#"sh -c 'echo "two  words"; exit 255'"#, "--host", "web01", "--mode", "semi-auto"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("two  words"))
+        .stdout(predicate::str::contains("web01: exit 255"))
+        .stdout(predicate::str::contains("SSH connection").not());
+}
+
+#[test]
+fn test_session_record_and_replay() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = TempDir::new().unwrap();
+    let archive = temp_dir.path().join("session.jsonl");
+
+    tuxpilot(&temp_dir)
+        .args(["execute", "greet", "--command", "echo recorded", "--mode", "semi-auto", "--record"])
+        .arg(&archive)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("recorded"));
+
+    // The archive holds command output, so only the owner may read it, and the
+    // provider detection probe is recorded alongside the executed command
+    assert_eq!(fs::metadata(&archive).unwrap().permissions().mode() & 0o777, 0o600);
+    assert!(fs::read_to_string(&archive).unwrap().contains(r#""program":"which""#));
+
+    // Replay must use the archive, not the live command
+    let edited = fs::read_to_string(&archive).unwrap()
+        .replace(r#""stdout":"recorded\n""#, r#""stdout":"from the archive\n""#);
+    fs::write(&archive, edited).unwrap();
+
+    tuxpilot(&temp_dir)
+        .args(["execute", "greet", "--command", "echo recorded", "--mode", "semi-auto", "--replay"])
+        .arg(&archive)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("from the archive"));
+
+    tuxpilot(&temp_dir)
+        .args(["execute", "greet", "--command", "echo something else", "--mode", "semi-auto", "--replay"])
+        .arg(&archive)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no recording of `echo something else`"));
+}
+
+#[test]
+fn test_package_search_shows_each_source() {
+    // Stand-ins answering the queries the apk and flatpak backends make
+    let (temp_dir, mut cmd) = with_stubs(&[
+        ("apk", r#"#!/bin/sh
+case "$1 $2" in
+  "info -v") echo "vim-9.0.2127-r0" ;;
+  "version -l") echo "Installed:    Available:"; echo "vim-9.0.2127-r0    < 9.1.0-r0" ;;
+  *) exit 1 ;;
+esac
+"#),
+        ("flatpak", r#"#!/bin/sh
+case "$1 $3" in
+  "search --") printf 'org.vim.Vim\tVim\tThe ubiquitous text editor\t9.1\tflathub\n' ;;
+esac
+"#),
+    ]);
+
+    cmd.env("TUXPILOT_TEST_MODE", "1")
+        .args(["package", "search", "vim"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Flatpak: org.vim.Vim 9.1 [flathub] - Vim - The ubiquitous text editor"));
+
+    tuxpilot(&temp_dir)
+        .env("TUXPILOT_TEST_MODE", "1")
+        .args(["package", "upgradable", "--source", "apk"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Apk: vim 9.0.2127-r0 → 9.1.0-r0 (installed)"));
+}
+
+#[test]
+fn test_package_install_previews_the_planned_transaction() {
+    // apt-get's simulation of installing vim, which pulls in an upgraded vim-common
+    let (temp_dir, _) = with_stubs(&[("apt-get", r#"#!/bin/sh
+[ "$1" = "-s" ] || exit 1
+echo "Inst vim-common [2:9.0.1378-1] (2:9.0.1378-2 Debian:12/stable [all])"
+echo "Inst vim (2:9.0.1378-2 Debian:12/stable [amd64])"
+"#)]);
+
+    let config: String = default_config(&temp_dir).lines()
+        .map(|line| if line.starts_with("package_manager = ") { "package_manager = \"Apt\"" } else { line })
+        .map(|line| format!("{}\n", line))
+        .collect();
+    fs::write(temp_dir.path().join("config.toml"), config).unwrap();
+
+    tuxpilot(&temp_dir)
+        .env("TUXPILOT_TEST_MODE", "1")
+        .args(["package", "install", "vim"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("⬆️  vim-common 2:9.0.1378-1 → 2:9.0.1378-2"))
+        .stdout(predicate::str::contains("➕ vim 2:9.0.1378-2"))
+        .stdout(predicate::str::contains("Command: apt-get install -y -- vim"));
+
+    // A name that apt-get would read as an option never reaches it
+    tuxpilot(&temp_dir)
+        .env("TUXPILOT_TEST_MODE", "1")
+        .args(["package", "install", "--", "-oAPT::Get::Simulate=0"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid package name or search term '-oAPT::Get::Simulate=0'"));
+}
+
+#[test]
+fn test_execute_reroutes_package_installs_on_transactional_systems() {
+    // A read-only root with transactional-update, as on openSUSE MicroOS
+    let (_temp_dir, mut cmd) = with_stubs(&[
+        ("findmnt", "#!/bin/sh\necho ro,relatime,subvol=/@/.snapshots/1/snapshot\n"),
+        ("transactional-update", "#!/bin/sh\nexit 0\n"),
+    ]);
+
+    cmd.args(["execute", "install vim", "--command", "zypper install vim", "--mode", "semi-auto"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("transactional-update pkg install vim"));
+}
+
+#[test]
+fn test_logs_reads_structured_journal_entries() {
+    // Records the arguments and prints one entry the way `journalctl -o json` does
+    let (temp_dir, mut cmd) = with_stubs(&[("journalctl", r#"#!/bin/sh
+echo "$@" > "$XDG_DATA_HOME/journalctl-args"
+echo '{"__CURSOR":"s=1;i=2a","__REALTIME_TIMESTAMP":"1714557600000000","PRIORITY":"3","_SYSTEMD_UNIT":"nginx.service","_PID":"812","_BOOT_ID":"4b1d","SYSLOG_IDENTIFIER":"nginx","MESSAGE":"bind() to 0.0.0.0:80 failed"}'
+"#)]);
+
+    cmd.args(["logs", "--unit", "nginx.service", "--priority", "err", "--boot", "-1", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""unit":"nginx.service","pid":812,"boot_id":"4b1d""#))
+        .stdout(predicate::str::contains(r#""message":"bind() to 0.0.0.0:80 failed""#));
+
+    let args = fs::read_to_string(temp_dir.path().join("journalctl-args")).unwrap();
+    assert!(args.contains("-o json -u nginx.service -p 3 -b -1"), "{}", args);
+}
+
+#[test]
+fn test_diagnose_parses_log_from_stdin() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let xorg_log = "\
+[    23.456] X.Org X Server 1.21.1
+[    23.457] (==) Log file: \"/var/log/Xorg.0.log\"
+[    23.500] (II) Loading /usr/lib/xorg/modules/drivers/nvidia_drv.so
+[    23.600] (EE) NVIDIA: Failed to initialize the NVIDIA kernel module.
+[    23.601] (EE) No devices detected.
+[    23.602] (WW) Falling back to old probe method for modesetting
+";
+
+    // The AI is not reachable here; the parsed clusters are printed before it is asked
+    tuxpilot(&temp_dir)
+        .args(["diagnose", "--input", "-"])
+        .write_stdin(xorg_log)
+        .assert()
+        .stdout(predicate::str::contains("stdin: Xorg log, 6 lines, 1 error clusters"))
+        .stdout(predicate::str::contains("lines 4-5: NVIDIA: Failed to initialize the NVIDIA kernel module."));
+}
+
+#[test]
+fn test_service_reports_typed_status_and_command() {
+    // Answers `systemctl show` with the properties of a running, enabled unit
+    let (_temp_dir, mut cmd) = with_stubs(&[("systemctl", r#"#!/bin/sh
+if [ "$1" = "show" ]; then
+    printf 'Id=nginx.service\nDescription=A high performance web server\nLoadState=loaded\nActiveState=active\nSubState=running\nUnitFileState=enabled\nMainPID=812\nWantedBy=multi-user.target\n'
+fi
+"#)]);
+
+    // The AI is not reachable here; status and command are printed before it is asked
+    cmd.args(["service", "nginx", "restart"])
+        .assert()
+        .stdout(predicate::str::contains(
+            "nginx: active (running), enabled, PID 812, runlevels: multi-user.target - A high performance web server"))
+        .stdout(predicate::str::contains("sudo systemctl restart nginx"));
+}
+
+#[test]
+fn test_execute_rejects_boot_loader_changes_on_wsl_hosts() {
+    // Runs remote commands locally, except that the kernel release is WSL2's
+    let (temp_dir, _) = with_stubs(&[("ssh", r#"#!/bin/sh
+while [ "$1" != "--" ]; do shift; done
+shift 2
+case "$*" in
+    *osrelease*) echo 5.15.153.1-microsoft-standard-WSL2 ;;
+    *) exec sh -c "$*" ;;
+esac
+"#)]);
+    use_inventory(&temp_dir, "[[hosts]]\nname = \"devbox\"\naddress = \"127.0.0.1\"\n");
+
+    tuxpilot(&temp_dir)
+        .args(["execute", "rebuild grub config", "--command", "update-grub", "--host", "devbox", "--mode", "semi-auto"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("WSL boots Microsoft's kernel without a boot loader"));
+}
+
+#[test]
+fn test_kernel_sysctl_shows_runtime_value_and_refuses_unmanaged_reset() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // kernel.ostype is read-only and never persisted
+    tuxpilot(&temp_dir).args(["kernel", "sysctl", "kernel/ostype"]).assert()
+        .success()
+        .stdout(predicate::str::contains("kernel.ostype = Linux (not persisted)"));
+
+    // Only settings in tuxpilot's own drop-in can be reset
+    tuxpilot(&temp_dir).args(["kernel", "sysctl", "kernel.ostype", "--reset"]).assert()
+        .failure()
+        .stderr(predicate::str::contains("kernel.ostype is not set in /etc/sysctl.d/99-tuxpilot.conf"));
+}
+
+#[test]
+fn test_firewall_explains_ufw_rules_and_previews_open_port() {
+    // An active ufw that denies incoming traffic except SSH
+    let (temp_dir, mut cmd) = with_stubs(&[("ufw", r#"#!/bin/sh
+if [ "$1" = "status" ]; then
+    printf 'Status: active\nDefault: deny (incoming), allow (outgoing), disabled (routed)\n\n'
+    printf 'To                         Action      From\n--                         ------      ----\n'
+    printf '22/tcp                     ALLOW IN    Anywhere\n'
+fi
+"#)]);
+
+    cmd.arg("firewall")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Firewall: ufw"))
+        .stdout(predicate::str::contains("22/tcp (ssh) from everyone"));
+
+    // A dry run previews the rule and its revert without running ufw
+    tuxpilot(&temp_dir)
+        .args(["firewall", "open", "443/tcp", "--comment", "web", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("+   443/tcp  ALLOW IN  Anywhere  # web"))
+        .stdout(predicate::str::contains("ufw allow 443/tcp comment web"))
+        .stdout(predicate::str::contains("ufw delete allow 443/tcp"));
+}
+
+#[test]
+fn test_firewall_reads_malformed_rules_without_widening_them() {
+    // nft JSON with rules for unknown chains, expressions that are not lists and port
+    // values that are no ports; only the real ports count as open
+    let (_temp_dir, mut cmd) = with_stubs(&[("nft", r#"#!/bin/sh
+case "$*" in
+    "list tables") echo "table inet filter" ;;
+    *) cat <<'JSON'
+{"nftables": [{"metainfo": {}}, 7, {"chain": null},
+ {"chain": {"family": "inet", "table": "filter", "name": "input", "hook": "input", "policy": "drop"}},
+ {"rule": {"family": "inet", "table": "filter", "chain": "input", "expr": [{"match": {"op": "==", "left": {"payload": {"protocol": "tcp", "field": "dport"}}, "right": 22}}, {"accept": null}]}},
+ {"rule": {"chain": "input", "expr": "garbage"}},
+ {"rule": {"family": "inet", "table": "filter", "chain": "input", "expr": [{"match": {"op": "==", "left": {"payload": {"protocol": "tcp", "field": "dport"}}, "right": {"range": [9, 1]}}}, {"accept": null}]}},
+ {"rule": {"family": "inet", "table": "filter", "chain": "input", "expr": [{"match": {"op": "==", "left": {"payload": {"protocol": "udp", "field": "dport"}}, "right": {"set": [53, "x", 70000]}}}, {"accept": null}]}},
+ {"rule": {"family": "inet", "table": "filter", "chain": "nowhere", "expr": [{"accept": null}]}}]}
+JSON
+    ;;
+esac
+"#)]);
+
+    cmd.arg("firewall")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("22/tcp (ssh) from everyone"))
+        .stdout(predicate::str::contains("53/udp from everyone"))
+        .stdout(predicate::str::contains("Allow tcp traffic when tcp dport 9-1"))
+        .stdout(predicate::str::contains("9-1/tcp").not());
+
+    // Truncated JSON is an error rather than an empty ruleset
+    let (_temp_dir, mut cmd) = with_stubs(&[("nft", r#"#!/bin/sh
+case "$*" in
+    "list tables") echo "table inet filter" ;;
+    *) echo '{"nftables": [{"chain": {"family": "inet"' ;;
+esac
+"#)]);
+    cmd.arg("firewall")
+        .assert()
+        .stderr(predicate::str::contains("Failed to parse nft JSON output"));
+
+    // iptables-save output with rules outside a table, for undeclared chains, cut
+    // short, or with ports that do not parse
+    let (_temp_dir, mut cmd) = with_stubs(&[("iptables-save", r#"#!/bin/sh
+cat <<'RULES'
+-A INPUT -p tcp --dport 23 -j ACCEPT
+*filter
+:INPUT DROP [0:0]
+:
+-A
+-A NOWHERE -p tcp --dport 24 -j ACCEPT
+-A INPUT -p tcp --dport 9:1 -j ACCEPT
+-A INPUT -p ünïcode --dport 25 -j ACCEPT
+-A INPUT -p tcp --dport 80 -m comment --comment "unterminated -j ACCEPT
+-A INPUT -p tcp -m tcp --dport 443 -j ACCEPT
+-A INPUT ! -s
+-A INPUT -j
+COMMIT
+RULES
+"#)]);
+
+    cmd.arg("firewall")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Firewall: iptables"))
+        .stdout(predicate::str::contains("443/tcp (https) from everyone"))
+        .stdout(predicate::str::contains("Allow tcp traffic when --dport 9:1"))
+        .stdout(predicate::str::contains("Allow ünïcode traffic when --dport 25"))
+        .stdout(predicate::str::contains("23/tcp").not())
+        .stdout(predicate::str::contains("24/tcp").not())
+        .stdout(predicate::str::contains("80/tcp").not());
+}
+
+#[test]
+fn test_firewall_revert_lines_keep_rule_text_in_one_word() {
+    // Revert lines are run by `sh -c`, so quotes and semicolons in comments, chain
+    // names and sources must reach the firewall tool as part of one argument. Each
+    // stub lists its ruleset, or records its arguments when RECORD is set.
+    const HOSTILE: &str = "it's; touch pwned";
+    let record = |tool: &str, listing: &str| format!(
+        "#!/bin/sh\nif [ -n \"$RECORD\" ]; then printf '%s\\n' {tool} \"$@\" >> \"$RECORD\"; exit 0; fi\n{listing}");
+
+    // Run the "Revert with:" lines of a dry run and return what the tools received
+    let revert = |temp_dir: &TempDir, args: &[&str]| -> String {
+        let output = tuxpilot(temp_dir).args(args).arg("--dry-run").assert().success().get_output().stdout.clone();
+        let stdout = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = stdout.lines()
+            .skip_while(|line| *line != "Revert with:")
+            .skip(1)
+            .take_while(|line| line.starts_with("  "))
+            .collect();
+        assert!(!lines.is_empty(), "no revert lines in {}", stdout);
+
+        let recorded = temp_dir.path().join("recorded");
+        let path = format!("{}:{}", temp_dir.path().join("bin").display(), std::env::var("PATH").unwrap_or_default());
+        for line in lines {
+            let status = std::process::Command::new("sh")
+                .args(["-c", line.trim()])
+                .current_dir(temp_dir.path())
+                .env("PATH", &path)
+                .env("RECORD", &recorded)
+                .status()
+                .unwrap();
+            assert!(status.success(), "`{}` failed", line);
+        }
+        assert!(!temp_dir.path().join("pwned").exists(), "a revert line ran text of the rule");
+        fs::read_to_string(recorded).unwrap()
+    };
+
+    // ufw puts the comment of a closed rule back
+    let ufw_status = format!("printf 'Status: active\\n\\nTo                         Action      From\\n\
+--                         ------      ----\\n'\nprintf '%s\\n' '80/tcp                     ALLOW IN    Anywhere                   # {}'\n",
+        HOSTILE.replace('\'', r"'\''"));
+    let (temp_dir, _) = with_stubs(&[("ufw", &record("ufw", &ufw_status))]);
+    let recorded = revert(&temp_dir, &["firewall", "close", "80/tcp"]);
+    assert!(recorded.contains(&format!("comment\n{}\n", HOSTILE)), "{}", recorded);
+
+    // iptables reinserts the rule with its comment
+    let rules = format!("#!/bin/sh\ncat <<'RULES'\n*filter\n:INPUT DROP [0:0]\n\
+-A INPUT -p tcp -m tcp --dport 80 -m comment --comment \"{}\" -j ACCEPT\nCOMMIT\nRULES\n", HOSTILE);
+    let (temp_dir, _) = with_stubs(&[
+        ("iptables-save", &rules),
+        ("iptables", &record("iptables", "")),
+    ]);
+    let recorded = revert(&temp_dir, &["firewall", "close", "80/tcp"]);
+    assert!(recorded.contains(&format!("--comment\n{}\n", HOSTILE)), "{}", recorded);
+
+    // nftables names a chain that needs quoting, both when deleting an added rule and
+    // when putting a closed one back
+    let ruleset = format!(r#"case "$*" in
+    "list tables") echo "table inet filter" ;;
+    *) cat <<'JSON'
+{{"nftables": [{{"chain": {{"family": "inet", "table": "filter", "name": "{0}", "hook": "input", "policy": "drop"}}}},
+ {{"rule": {{"family": "inet", "table": "filter", "chain": "{0}", "handle": 4, "expr": [{{"match": {{"op": "==", "left": {{"payload": {{"protocol": "tcp", "field": "dport"}}}}, "right": 80}}}}, {{"accept": null}}]}}}}]}}
+JSON
+    ;;
+esac
+"#, HOSTILE);
+    let (temp_dir, _) = with_stubs(&[("nft", &record("nft", &ruleset))]);
+    let recorded = revert(&temp_dir, &["firewall", "open", "443/tcp"]);
+    assert!(recorded.contains(&format!("list\nchain\ninet\nfilter\n{}\n", HOSTILE)), "{}", recorded);
+    fs::remove_file(temp_dir.path().join("recorded")).unwrap();
+    let recorded = revert(&temp_dir, &["firewall", "close", "80/tcp"]);
+    assert!(recorded.contains(&format!("rule\ninet\nfilter\n{}\n", HOSTILE)), "{}", recorded);
+
+    // firewalld removes a rich rule whose source was given on the command line
+    let zones = "case \"$1\" in\n    --state) echo running ;;\n    --list-all-zones) printf 'public (default, active)\\n  ports: 22/tcp\\n' ;;\nesac\n";
+    let (temp_dir, _) = with_stubs(&[("firewall-cmd", &record("firewall-cmd", zones))]);
+    let source = format!("10.0.0.1\"; {}", HOSTILE);
+    let recorded = revert(&temp_dir, &["firewall", "open", "443/tcp", "--from", &source]);
+    assert!(recorded.contains(&format!("--remove-rich-rule=rule family=\"ipv4\" source address=\"{}\"", source)), "{}", recorded);
+}
+
+#[test]
+fn test_storage_reports_failing_drive_as_predictive_alert() {
+    // One ATA drive that passes its self-assessment but has pending sectors
+    let (_temp_dir, mut cmd) = with_stubs(&[("smartctl", r#"#!/bin/sh
+if [ "$2" = "--scan" ]; then
+    echo '{"devices":[{"name":"/dev/sda","type":"sat","protocol":"ATA"}]}'
+    exit 0
+fi
+echo '{"smartctl":{"exit_status":4},"device":{"protocol":"ATA"},"model_name":"WDC WD40EFRX","smart_status":{"passed":true},"temperature":{"current":38},"power_on_time":{"hours":40211},"ata_smart_attributes":{"table":[{"id":5,"name":"Reallocated_Sector_Ct","when_failed":"","raw":{"value":0}},{"id":197,"name":"Current_Pending_Sector","when_failed":"","raw":{"value":8}}]}}'
+exit 4
+"#)]);
+
+    cmd.arg("storage")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("/dev/sda WDC WD40EFRX: PASSED, 38 °C, 40211 h"))
+        .stdout(predicate::str::contains("/dev/sda: 8 pending sectors"))
+        .stdout(predicate::str::contains("Disk failure predicted for /dev/sda"));
+}
+
+#[test]
+fn test_boot_records_history_and_reports_unit_regressions() {
+    let (temp_dir, mut cmd) = with_stubs(&[("systemd-analyze", r#"#!/bin/sh
+case "$1" in
+time) printf 'Startup finished in 6.004s (loader) + 1.201s (kernel) + 12.480s (userspace) = 19.685s\n' ;;
+blame) printf '1min 2.500s docker.service\n      812ms systemd-udev-settle.service\n' ;;
+critical-chain) printf 'multi-user.target @12.469s\n\342\224\224\342\224\200docker.service @1.2s +1min 2.500s\n' ;;
+esac
+"#)]);
+
+    // An earlier boot in which docker started in four seconds
+    let history_dir = temp_dir.path().join("tuxpilot");
+    fs::create_dir_all(&history_dir).unwrap();
+    fs::write(history_dir.join("boot-history.json"), r#"{"boots":[{"boot_id":"0123456789abcdef0123456789abcdef",
+        "host":"localhost","booted_at":"2020-01-01T08:00:00Z","times":{"userspace":9000,"total":14000},
+        "blame":[{"unit":"docker.service","ms":4000}],"critical_chain":[]}]}"#).unwrap();
+
+    cmd.arg("boot")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Startup: 6.004s loader + 1.201s kernel + 12.480s userspace = 19.685s"))
+        .stdout(predicate::str::contains("  docker.service @1.200s +1min 2.500s"))
+        .stdout(predicate::str::contains("docker.service: 1min 2.500s, usually 4.000s"))
+        .stdout(predicate::str::contains("Shorten the boot loader menu timeout"));
+
+    // The current boot is now part of the history
+    tuxpilot(&temp_dir)
+        .args(["boot", "--history"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("localhost 0123456789abcdef0123456789abcdef  14.000s"))
+        .stdout(predicate::str::contains("19.685s"));
+}
+
+#[test]
+fn test_network_explains_missing_gateway_and_plans_nmcli_change() {
+    let (temp_dir, mut cmd) = with_stubs(&[
+        // eth0 has its static address but no default route
+        ("ip", r#"#!/bin/sh
+if [ "$2" = "addr" ]; then
+    echo '[{"ifname":"eth0","flags":["BROADCAST","UP","LOWER_UP"],"operstate":"UP","link_type":"ether","addr_info":[{"family":"inet","local":"192.0.2.10","prefixlen":24}]}]'
+else
+    echo '[]'
+fi
+"#),
+        // NetworkManager manages eth0 with a connection whose keyfile is not readable
+        ("nmcli", r#"#!/bin/sh
+case "$*" in
+    *" device") printf 'eth0:connected:Wired connection 1\nlo:unmanaged:\n' ;;
+    *"connection show Wired connection 1")
+        printf 'connection.id:Wired connection 1\nconnection.type:802-3-ethernet\nipv4.method:manual\n'
+        printf 'ipv4.addresses:192.0.2.10/24\nipv4.gateway:192.0.2.1\nipv4.dns:192.0.2.53\nipv6.method:ignore\n' ;;
+esac
+"#),
+    ]);
+
+    cmd.arg("network")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("eth0 [NetworkManager] UP, 192.0.2.10/24"))
+        .stdout(predicate::str::contains("there is no default IPv4 route"))
+        .stdout(predicate::str::contains("the gateway 192.0.2.1 configured for eth0 is not applied"));
+
+    // Switching to DHCP is planned with nmcli, and undone by restoring the static setup
+    tuxpilot(&temp_dir)
+        .args(["network", "set", "eth0", "--dhcp", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("-   ipv4: static"))
+        .stdout(predicate::str::contains("+   ipv4: DHCP"))
+        .stdout(predicate::str::contains("nmcli connection modify 'Wired connection 1' ipv4.method auto ipv4.addresses ''"))
+        .stdout(predicate::str::contains("tuxpilot network set eth0 --address 192.0.2.10/24 --gateway 192.0.2.1 --dns 192.0.2.53"));
+}
+
+#[test]
+fn test_network_skips_malformed_netplan_and_interfaces_stanzas() {
+    // A host whose netplan files and interfaces file are partly broken: YAML that does
+    // not parse, definitions of the wrong shape, and stanzas missing their fields
+    let (temp_dir, _) = with_stubs(&[("ssh", r#"#!/bin/sh
+while [ "$1" != "--" ]; do shift; done
+shift 2
+case "$*" in
+    *"/netplan/*.yaml"*) printf '/etc/netplan/01-broken.yaml\n/etc/netplan/02-odd.yaml\n/etc/netplan/03-lan.yaml\n' ;;
+    "cat /etc/netplan/01-broken.yaml") printf 'network:\n  ethernets:\n    eth0: [unclosed\n' ;;
+    "cat /etc/netplan/02-odd.yaml") cat <<'YAML'
+network:
+  wifis: "not a mapping"
+  ethernets:
+    7: {dhcp4: true}
+    eth9:
+      addresses: 10.0.0.1/8
+      routes: {to: default}
+      dhcp4: maybe
+YAML
+    ;;
+    "cat /etc/netplan/03-lan.yaml") printf 'network:\n  ethernets:\n    eth1:\n      addresses: [192.0.2.20/24]\n      gateway4: 192.0.2.1\n' ;;
+    "cat /etc/network/interfaces") cat <<'INTERFACES'
+    address 10.1.1.1
+iface
+iface eth2
+iface eth3 inet
+iface eth4 inet static
+    address
+    netmask 255.255.255.999
+    gateway
+    up ip route add
+iface ëth6 inet static
+    address 198.51.100.7/24
+source
+INTERFACES
+    ;;
+    "ip "*) echo '[]' ;;
+    *) exec sh -c "$*" ;;
+esac
+"#)]);
+    use_inventory(&temp_dir, "[[hosts]]\nname = \"box\"\naddress = \"127.0.0.1\"\n");
+
+    tuxpilot(&temp_dir)
+        .args(["network", "--host", "box"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("eth1 [netplan] missing"))
+        .stdout(predicate::str::contains("gateway: 192.0.2.1"))
+        .stdout(predicate::str::contains("eth9 [netplan] missing"))
+        .stdout(predicate::str::contains("eth4 [ifupdown] missing"))
+        .stdout(predicate::str::contains("address: 198.51.100.7/24"))
+        .stdout(predicate::str::contains("eth0 [").not())
+        .stdout(predicate::str::contains("eth2 [").not())
+        .stdout(predicate::str::contains("eth3 [").not())
+        .stdout(predicate::str::contains("10.0.0.1").not())
+        .stdout(predicate::str::contains("10.1.1.1").not());
+}
+
+#[test]
+fn test_schedule_answers_what_runs_at_3am_and_plans_timer_edit() {
+    let (temp_dir, mut cmd) = with_stubs(&[
+        // It is Mon 2026-10-19 01:00 UTC on the host
+        ("date", "#!/bin/sh\necho '1792371600 +0000'\n"),
+        // backup.timer runs at 03:00 and its service failed last night
+        ("systemctl", r#"#!/bin/sh
+case "$*" in
+    list-timers*) echo 'Mon 2026-10-19 03:00:00 UTC 2h left Sun 2026-10-18 03:00:00 UTC 22h ago backup.timer backup.service' ;;
+    *backup.timer*) cat <<'UNIT'
+Id=backup.timer
+Unit=backup.service
+TimersCalendar={ OnCalendar=*-*-* 03:00:00 ; next_elapse=Mon 2026-10-19 03:00:00 UTC }
+NextElapseUSecRealtime=Mon 2026-10-19 03:00:00 UTC
+NextElapseUSecMonotonic=0
+LastTriggerUSec=Sun 2026-10-18 03:00:00 UTC
+ActiveState=active
+FragmentPath=/etc/systemd/system/backup.timer
+UNIT
+    ;;
+    *backup.service*) cat <<'UNIT'
+Id=backup.service
+ExecStart={ path=/usr/local/bin/backup ; argv[]=/usr/local/bin/backup --all ; ignore_errors=no }
+User=
+Result=exit-code
+UNIT
+    ;;
+esac
+"#),
+        ("journalctl", r#"#!/bin/sh
+case "$*" in
+    *" -u "*) cat <<'LOG'
+{"__CURSOR":"s1","__REALTIME_TIMESTAMP":"1792292400000000","SYSLOG_IDENTIFIER":"systemd","UNIT":"backup.service","MESSAGE":"Starting backup.service - Nightly backup..."}
+{"__CURSOR":"s2","__REALTIME_TIMESTAMP":"1792292405000000","SYSLOG_IDENTIFIER":"systemd","UNIT":"backup.service","MESSAGE":"backup.service: Main process exited, code=exited, status=2/INVALIDARGUMENT"}
+{"__CURSOR":"s3","__REALTIME_TIMESTAMP":"1792292405000000","SYSLOG_IDENTIFIER":"systemd","UNIT":"backup.service","MESSAGE":"backup.service: Failed with result 'exit-code'."}
+LOG
+    ;;
+    *" -t "*) echo '{"__CURSOR":"c1","__REALTIME_TIMESTAMP":"1792294200000000","SYSLOG_IDENTIFIER":"CRON","MESSAGE":"(root) CMD (/usr/bin/report)"}' ;;
+esac
+"#),
+        ("crontab", "#!/bin/sh\nprintf '30 3 * * * /usr/bin/report\\n0 12 * * * /usr/bin/lunch\\n'\n"),
+        ("id", "#!/bin/sh\necho root\n"),
+    ]);
+
+    cmd.args(["schedule", "--at", "3am"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Runs between 03:00 and 04:00 on Mon 2026-10-19"))
+        .stdout(predicate::str::contains("03:00  timer backup.timer (*-*-* 03:00:00)"))
+        .stdout(predicate::str::contains("root: /usr/local/bin/backup --all; last run Sun 2026-10-18 03:00, failed (status=2/INVALIDARGUMENT)"))
+        .stdout(predicate::str::contains("03:30  cron report (30 3 * * *)"))
+        .stdout(predicate::str::contains("root: /usr/bin/report; last run Sun 2026-10-18 03:30, started"))
+        .stdout(predicate::str::contains("lunch").not());
+
+    // Moving the timer to weekdays is a drop-in that resets the old schedule
+    tuxpilot(&temp_dir)
+        .args(["schedule", "edit", "backup", "--on-calendar", "Mon..Fri 04:00", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Schedule: *-*-* 03:00:00 -> Mon..Fri 04:00"))
+        .stdout(predicate::str::contains("Next runs: Mon 2026-10-19 04:00, Tue 2026-10-20 04:00, Wed 2026-10-21 04:00"))
+        .stdout(predicate::str::contains("Write /etc/systemd/system/backup.timer.d/tuxpilot.conf"))
+        .stdout(predicate::str::contains("OnCalendar=Mon..Fri 04:00"))
+        .stdout(predicate::str::contains("Run: systemctl restart backup.timer"))
+        .stdout(predicate::str::contains("Undo with: rm /etc/systemd/system/backup.timer.d/tuxpilot.conf"));
+}
+
+#[test]
+fn test_schedule_lists_malformed_cron_lines_and_rejects_bad_calendars() {
+    let (temp_dir, mut cmd) = with_stubs(&[
+        ("date", "#!/bin/sh\necho '1792371600 +0000'\n"),
+        // A timer whose calendar systemd would not accept
+        ("systemctl", r#"#!/bin/sh
+case "$*" in
+    list-timers*) echo 'n/a n/a n/a - - broken.timer broken.service' ;;
+    *broken.timer*) printf 'Id=broken.timer\nUnit=broken.service\nTimersCalendar={ OnCalendar=Fri 31-02 25:61 ; next_elapse=n/a }\nActiveState=active\n' ;;
+    *broken.service*) printf 'Id=broken.service\nExecStart=garbage\n' ;;
+esac
+"#),
+        ("journalctl", "#!/bin/sh\nexit 0\n"),
+        // One good line among fields out of range, bad steps and ranges, and names
+        // cron does not know
+        ("crontab", r#"#!/bin/sh
+cat <<'CRON'
+30 3 * * * /usr/bin/report
+61 3 * * * /usr/bin/bad-minute
+*/0 3 * * * /usr/bin/zero-step
+5-1 3 * * * /usr/bin/reversed
+0 3 ä * * /usr/bin/unicode
+@fortnightly /usr/bin/unknown-macro
+0 3 * * Mon-Sun,jan /usr/bin/bad-name
+MAILTO
+CRON
+"#),
+        ("id", "#!/bin/sh\necho root\n"),
+    ]);
+
+    cmd.arg("schedule")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Mon 03:30  cron report (30 3 * * *)"))
+        .stdout(predicate::str::contains("Not scheduled:"))
+        .stdout(predicate::str::contains("timer broken.timer (Fri 31-02 25:61)"))
+        .stdout(predicate::str::contains("cron bad-minute (61 3 * * *)"))
+        .stdout(predicate::str::contains("cron zero-step (*/0 3 * * *)"))
+        .stdout(predicate::str::contains("cron reversed (5-1 3 * * *)"))
+        .stdout(predicate::str::contains("cron unicode (0 3 ä * *)"))
+        .stdout(predicate::str::contains("cron unknown-macro (@fortnightly)"))
+        .stdout(predicate::str::contains("cron bad-name (0 3 * * Mon-Sun,jan)"));
+
+    // A new calendar is checked before anything is planned
+    for (calendar, reason) in [
+        ("25:61", "25 is outside 0-23"),
+        ("Mon..Fri 04:00 extra junk", "Time zone junk is not supported"),
+        ("ä", "Unexpected 'ä'"),
+        ("*-*-* 03:00:00/0", "Invalid step in '00/0'"),
+        ("", "Empty calendar expression"),
+    ] {
+        tuxpilot(&temp_dir)
+            .args(["schedule", "edit", "broken", "--on-calendar", calendar, "--dry-run"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(format!("Invalid OnCalendar expression '{}'", calendar)))
+            .stderr(predicate::str::contains(reason))
+            .stderr(predicate::str::contains("panicked").not());
+    }
+}
+
+#[test]
+fn test_users_reports_root_and_plans_checked_sudo_rules() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // Every system has root, and it can run anything; password hashes never show up
+    tuxpilot(&temp_dir).args(["users", "--user", "root", "--json"]).assert()
+        .success()
+        .stdout(predicate::str::contains("\"uid\": 0"))
+        .stdout(predicate::str::contains("\"root\": true"))
+        .stdout(predicate::str::contains("$6$").not());
+
+    // A sudo rule is a sudoers.d file with its arguments escaped, undone by removing it
+    tuxpilot(&temp_dir)
+        .args(["users", "grant", "root", "--command", "/usr/bin/systemctl restart a,b", "--nopasswd", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Write /etc/sudoers.d/tuxpilot-root (checked with visudo):"))
+        .stdout(predicate::str::contains("+root ALL=(root) NOPASSWD: /usr/bin/systemctl restart a\\,b"))
+        .stdout(predicate::str::contains("Undo with: rm /etc/sudoers.d/tuxpilot-root"));
+
+    // sudo needs full paths, and root cannot be locked out
+    tuxpilot(&temp_dir).args(["users", "grant", "root", "--command", "systemctl", "--dry-run"]).assert()
+        .failure()
+        .stderr(predicate::str::contains("sudo needs the full path of systemctl"));
+
+    tuxpilot(&temp_dir).args(["users", "lock", "root", "--dry-run"]).assert()
+        .failure()
+        .stderr(predicate::str::contains("Locking root"));
+}
+
+#[test]
+fn test_users_reports_malformed_sudoers_lines_and_keeps_the_rest() {
+    // A host whose sudoers has one good rule among lines sudo would reject
+    let (temp_dir, _) = with_stubs(&[("ssh", r#"#!/bin/sh
+while [ "$1" != "--" ]; do shift; done
+shift 2
+case "$*" in
+    "cat /etc/sudoers") cat <<'SUDOERS'
+root ALL=(ALL:ALL) ALL
+Defaults@ env_reset
+User_Alias admins = alice
+alice ALL = (root
+%wheel ALL=(ALL) ALL :
+bob ALL=(ALL) NOPASSWD: /usr/bin/ä, \
+ünïcode ALL=ALL
+"unterminated ALL=ALL
+SUDOERS
+    ;;
+    *) exec sh -c "$*" ;;
+esac
+"#)]);
+    use_inventory(&temp_dir, "[[hosts]]\nname = \"box\"\naddress = \"127.0.0.1\"\n");
+
+    tuxpilot(&temp_dir)
+        .args(["users", "--host", "box", "--user", "root"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("sudo: ALL as ALL:ALL via root (/etc/sudoers:1)"))
+        .stdout(predicate::str::contains("/etc/sudoers:2: Defaults@ without a list"))
+        .stdout(predicate::str::contains("/etc/sudoers:3: Alias names are upper case letters, digits and '_': admins"))
+        .stdout(predicate::str::contains("/etc/sudoers:4: Expected ')' at the end of the line"))
+        .stdout(predicate::str::contains("/etc/sudoers:5: Expected a name at the end of the line"))
+        // The continued line is reported where it starts
+        .stdout(predicate::str::contains("/etc/sudoers:6: Unexpected '='"))
+        .stdout(predicate::str::contains("/etc/sudoers:8: Unterminated quote"));
+}
+
+#[test]
+fn test_mac_groups_denials_by_service_and_proposes_fixes() {
+    let (temp_dir, mut cmd) = with_stubs(&[
+        // An enforcing SELinux host without auditd: an AVC denial for nginx reading a file
+        // moved from a home directory, and an AppArmor denial for mysqld
+        ("journalctl", r##"#!/bin/sh
+cat <<'LOG'
+{"__CURSOR":"a1","__REALTIME_TIMESTAMP":"1792292400000000","_TRANSPORT":"audit","MESSAGE":"AVC avc:  denied  { read } for  pid=999999 comm=\"nginx\" path=\"/srv/www/index.html\" dev=\"dm-0\" ino=1234 scontext=system_u:system_r:httpd_t:s0 tcontext=unconfined_u:object_r:user_home_t:s0 tclass=file permissive=0"}
+{"__CURSOR":"a2","__REALTIME_TIMESTAMP":"1792292401000000","_TRANSPORT":"audit","MESSAGE":"AVC avc:  denied  { open } for  pid=999999 comm=\"nginx\" path=\"/srv/www/index.html\" dev=\"dm-0\" ino=1234 scontext=system_u:system_r:httpd_t:s0 tcontext=unconfined_u:object_r:user_home_t:s0 tclass=file permissive=0"}
+{"__CURSOR":"k1","__REALTIME_TIMESTAMP":"1792292402000000","_TRANSPORT":"kernel","MESSAGE":"audit: type=1400 audit(1792292402.123:457): apparmor=\"DENIED\" operation=\"open\" class=\"file\" profile=\"/usr/sbin/mysqld\" name=\"/data/mysql/ibdata1\" pid=999998 comm=\"mysqld\" requested_mask=\"r\" denied_mask=\"r\" fsuid=111 ouid=111"}
+LOG
+"##),
+        ("getenforce", "#!/bin/sh\necho Enforcing\n"),
+        ("sestatus", "#!/bin/sh\necho 'Loaded policy name:             targeted'\n"),
+        ("matchpathcon", "#!/bin/sh\necho system_u:object_r:httpd_sys_content_t:s0\n"),
+        ("audit2why", r#"#!/bin/sh
+grep -q 'comm="nginx"' || exit 1
+cat <<'WHY'
+	Was caused by:
+	The boolean httpd_read_user_content was set incorrectly.
+	Description:
+	Allow httpd to read user content
+
+	Allow access by executing:
+	# setsebool -P httpd_read_user_content 1
+WHY
+"#),
+        ("systemctl", r#"#!/bin/sh
+case "$*" in
+    *--failed*) echo "nginx.service loaded failed failed The nginx HTTP and reverse proxy server" ;;
+    *nginx.service*) echo loaded ;;
+    *) echo not-found ;;
+esac
+"#),
+    ]);
+
+    cmd.arg("mac")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("SELinux enforcing (targeted policy)"))
+        .stdout(predicate::str::contains("[nginx.service (failed)]: nginx (httpd_t) was denied read, open on a file labelled user_home_t (/srv/www/index.html), 2 times"))
+        .stdout(predicate::str::contains("1. Relabel /srv/www/index.html: it is labelled user_home_t but the policy expects httpd_sys_content_t there"))
+        .stdout(predicate::str::contains("restorecon -v /srv/www/index.html"))
+        .stdout(predicate::str::contains("2. Turn on httpd_read_user_content"))
+        .stdout(predicate::str::contains("(Allow httpd to read user content)"))
+        .stdout(predicate::str::contains("3. Make httpd_t permissive"))
+        .stdout(predicate::str::contains("mysqld (/usr/sbin/mysqld) was denied r access to /data/mysql/ibdata1"))
+        .stdout(predicate::str::contains("4. Allow /data/mysql/ibdata1 in /usr/sbin/mysqld: add `/data/mysql/ibdata1 r,`"))
+        .stdout(predicate::str::contains("aa-complain /usr/sbin/mysqld"));
+
+    // Profile rules are written by hand
+    tuxpilot(&temp_dir)
+        .args(["mac", "--apply", "4"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("has to be done by hand"));
+}
+
+#[test]
+fn test_mac_ignores_malformed_audit_records() {
+    // Denials cut short, without contexts or a profile, with a timestamp that is no
+    // number, and a line that is not JSON, around one complete AVC record
+    let stamp = chrono::Utc::now().timestamp_micros() - 600_000_000;
+    let journalctl = format!(r##"#!/bin/sh
+cat <<'LOG'
+{{"__CURSOR":"a0","__REALTIME_TIMESTAMP":"{0}","_TRANSPORT":"audit","MESSAGE":"AVC avc:  denied  {{ read }} for  pid=1 comm=\"nginx\""}}
+{{"__CURSOR":"a1","__REALTIME_TIMESTAMP":"{0}","_TRANSPORT":"audit","MESSAGE":"AVC avc:  denied  {{ read  for  pid=999999 comm=\"nginx scontext=system_u tclass=file"}}
+{{"__CURSOR":"a2","__REALTIME_TIMESTAMP":"{0}","_TRANSPORT":"audit","MESSAGE":"AVC avc:  denied  {{ }} for scontext=:::: tcontext=ä tclass="}}
+{{"__CURSOR":"k1","__REALTIME_TIMESTAMP":"{0}","_TRANSPORT":"kernel","MESSAGE":"audit: type=1400 audit(garbage): apparmor=\"DENIED\" operation=\"open\" profile=\"/usr/sbin/mysqld name=\"/data"}}
+{{"__CURSOR":"k2","__REALTIME_TIMESTAMP":"{0}","_TRANSPORT":"kernel","MESSAGE":"audit: type=1400 apparmor=\"DENIED\""}}
+{{"__CURSOR":"k3","__REALTIME_TIMESTAMP":"not a number","_TRANSPORT":"kernel","MESSAGE":"audit: apparmor=\"DENIED\" profile=\"/x\" name=\"/y\" requested_mask=\"r\" comm=\"x\""}}
+not json at all
+{{"__CURSOR":"a3","__REALTIME_TIMESTAMP":"{0}","_TRANSPORT":"audit","MESSAGE":"AVC avc:  denied  {{ write }} for  pid=999997 comm=\"httpd\" name=\"log\" scontext=system_u:system_r:httpd_t:s0 tcontext=system_u:object_r:var_t:s0 tclass=dir permissive=0"}}
+LOG
+"##, stamp);
+    let (_temp_dir, mut cmd) = with_stubs(&[
+        ("journalctl", &journalctl),
+        ("getenforce", "#!/bin/sh\necho Enforcing\n"),
+        ("matchpathcon", "#!/bin/sh\nexit 1\n"),
+        ("audit2why", "#!/bin/sh\nexit 1\n"),
+        ("systemctl", "#!/bin/sh\necho not-found\n"),
+    ]);
+
+    cmd.arg("mac")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("httpd (httpd_t) was denied write on a dir labelled var_t"))
+        .stdout(predicate::str::contains("1. Make httpd_t permissive"))
+        .stdout(predicate::str::contains("nginx").not())
+        .stdout(predicate::str::contains("mysqld").not())
+        .stdout(predicate::str::contains("2.").not());
+}
+
+#[test]
+fn test_crashes_groups_core_dumps_by_signature_with_backtraces() {
+    // demo crashed twice in the same place and systemd-coredump symbolised it; worker's
+    // trace has to be read from its core with eu-stack
+    let now = chrono::Utc::now().timestamp_micros();
+    let coredumpctl = format!(r#"#!/bin/sh
+case "$*" in
+    *--json*) echo '[{{"time":{},"pid":100,"uid":0,"gid":0,"sig":11,"corefile":"present","exe":"/usr/bin/demo","size":4096}},{{"time":{},"pid":200,"uid":0,"gid":0,"sig":11,"corefile":"missing","exe":"/usr/bin/demo","size":4096}},{{"time":{},"pid":300,"uid":0,"gid":0,"sig":6,"corefile":"present","exe":"/usr/bin/worker","size":4096}}]' ;;
+    *"info -1 --no-pager 300"*) cat <<'INFO'
+           PID: 300 (worker)
+        Signal: 6 (ABRT)
+  Command Line: /usr/bin/worker --queue mail
+    Executable: /usr/bin/worker
+          Unit: worker.service
+INFO
+    ;;
+    *"info -1 --no-pager"*) cat <<'INFO'
+           PID: 100 (demo)
+        Signal: 11 (SEGV)
+    Executable: /usr/bin/demo
+          Unit: demo.service
+       Package: demo/1.0-1
+       Message: Process 100 (demo) of user 0 dumped core.
+
+                Stack trace of thread 100:
+                #0  0x0000555500001139 parse_config (/usr/bin/demo + 0x1139)
+                #1  0x0000555500001200 main (/usr/bin/demo + 0x1200)
+                #2  0x00007f0000029d90 __libc_start_main (libc.so.6 + 0x29d90)
+
+                Stack trace of thread 101:
+                #0  0x00007f00000a1000 poll (libc.so.6 + 0xa1000)
+INFO
+    ;;
+    *dump*) for last; do :; done; : > "$last" ;;
+esac
+"#, now - 60_000_000, now - 3_600_000_000, now - 120_000_000);
+    let (temp_dir, mut cmd) = with_stubs(&[
+        ("coredumpctl", &coredumpctl),
+        ("eu-stack", r#"#!/bin/sh
+cat <<'STACK'
+PID 300 - core
+TID 300:
+#0  0x00007f0000001000 raise - /usr/lib64/libc.so.6
+#1  0x00007f0000002000 abort - /usr/lib64/libc.so.6
+#2  0x0000555500001000 flush_queue - /usr/bin/worker
+    /src/worker/queue.c:88
+#3  0x0000555500002000 main - /usr/bin/worker
+STACK
+"#),
+    ]);
+
+    cmd.arg("crashes")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("3 crashes in the last 24 hours (coredumpctl), 2 different:"))
+        .stdout(predicate::str::contains("/usr/bin/demo (demo 1.0-1) killed by SIGSEGV, 2 times since"))
+        .stdout(predicate::str::contains("in demo.service"))
+        .stdout(predicate::str::contains("#0  parse_config (/usr/bin/demo)"))
+        .stdout(predicate::str::contains("poll").not())
+        .stdout(predicate::str::contains("/usr/bin/worker killed by SIGABRT"))
+        .stdout(predicate::str::contains("#2  flush_queue (/usr/bin/worker) at /src/worker/queue.c:88"));
+
+    tuxpilot(&temp_dir)
+        .args(["crashes", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"signature\": \"worker SIGABRT in flush_queue < main\""))
+        .stdout(predicate::str::contains("\"backtrace_source\": \"eu-stack\""));
+}
+
+#[test]
+fn test_kernel_log_decodes_oopses_taint_and_hardware_errors() {
+    // An oops in the proprietary nvidia module, logged one line per journal entry, then
+    // a link that keeps correcting errors and an NVMe command timing out
+    let stamp = chrono::Utc::now().timestamp_micros() - 600_000_000;
+    let messages = [
+        "BUG: kernel NULL pointer dereference, address: 0000000000000008",
+        "#PF: supervisor read access in kernel mode",
+        "Oops: 0000 [#1] PREEMPT SMP NOPTI",
+        "CPU: 3 PID: 4321 Comm: Xorg Tainted: P           OE      6.1.0-13-amd64 #1  Debian 6.1.55-1",
+        "RIP: 0010:nv_dma_map_pages+0x2a/0x190 [nvidia]",
+        "Call Trace:",
+        " <TASK>",
+        " ? __die+0x23/0x70",
+        " nv_dma_map_pages+0x2a/0x190 [nvidia]",
+        " nvidia_ioctl+0x5f1/0x8f0 [nvidia]",
+        " __x64_sys_ioctl+0x90/0xd0",
+        " </TASK>",
+        "Modules linked in: nvidia_drm(POE) nvidia(POE) snd_hda_intel ext4",
+        "---[ end trace 0000000000000000 ]---",
+        "nvme 0000:03:00.0: PCIe Bus Error: severity=Corrected, type=Physical Layer, (Receiver ID)",
+        "nvme 0000:03:00.0: PCIe Bus Error: severity=Corrected, type=Physical Layer, (Receiver ID)",
+        "nvme nvme0: I/O 123 QID 4 timeout, aborting",
+    ];
+    let records: Vec<String> = messages.iter().enumerate()
+        .map(|(index, message)| serde_json::json!({
+            "__CURSOR": format!("k{}", index),
+            "__REALTIME_TIMESTAMP": (stamp + index as i64 * 1000).to_string(),
+            "_TRANSPORT": "kernel",
+            "MESSAGE": message,
+        }).to_string())
+        .collect();
+    let journalctl = format!("#!/bin/sh\ncat <<'LOG'\n{}\nLOG\n", records.join("\n"));
+    let (_temp_dir, mut cmd) = with_stubs(&[
+        ("journalctl", &journalctl),
+        ("modinfo", r#"#!/bin/sh
+case "$3" in
+    nvidia) echo /lib/modules/6.1.0-13-amd64/updates/dkms/nvidia.ko ;;
+    nvme) echo /lib/modules/6.1.0-13-amd64/kernel/drivers/nvme/host/nvme.ko ;;
+    *) exit 1 ;;
+esac
+"#),
+        ("dpkg-query", r#"#!/bin/sh
+case "$3" in
+    *nvidia.ko) echo "nvidia-kernel-dkms: $3" ;;
+    *nvme.ko) echo "linux-image-6.1.0-13-amd64: $3" ;;
+esac
+"#),
+        ("pacman", r#"#!/bin/sh
+case "$3" in
+    *nvidia.ko) echo nvidia-kernel-dkms ;;
+    *nvme.ko) echo linux-image-6.1.0-13-amd64 ;;
+esac
+"#),
+    ]);
+
+    cmd.args(["kernel", "log"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("3 problems in boot 0 (journal):"))
+        .stdout(predicate::str::contains("Oops in nvidia (nvidia-kernel-dkms): BUG: kernel NULL pointer dereference, address: 0000000000000008"))
+        .stdout(predicate::str::contains("process: Xorg[4321]"))
+        .stdout(predicate::str::contains("at: nv_dma_map_pages+0x2a/0x190"))
+        .stdout(predicate::str::contains("tainted: POE (12289): proprietary module loaded, out-of-tree module loaded, unsigned module loaded"))
+        .stdout(predicate::str::contains("#0  nv_dma_map_pages [nvidia]"))
+        .stdout(predicate::str::contains("#2  __x64_sys_ioctl"))
+        .stdout(predicate::str::contains("__die").not())
+        .stdout(predicate::str::contains("Reproduce it without nvidia_drm(POE), nvidia(POE)"))
+        .stdout(predicate::str::contains("PCIe error (corrected) on 0000:03:00.0 [nvme] (linux-image-6.1.0-13-amd64): nvme 0000:03:00.0: PCIe Bus Error: severity=Corrected, type=Physical Layer, (Receiver ID), 2 times"))
+        .stdout(predicate::str::contains("NVMe timeout on nvme0 [nvme] (linux-image-6.1.0-13-amd64)"))
+        .stdout(predicate::str::contains("smartctl -a /dev/nvme0"))
+        .stdout(predicate::str::contains("🔮 NVMe timeout on nvme0"));
+}